@@ -0,0 +1,100 @@
+//! Times CSI indexing of a large synthetic GFF3 and reports the peak heap
+//! it needs, for comparing index data structures, then the throughput of
+//! serializing the resulting index on its own.
+//!
+//! Usage:
+//!   cargo run --release --example bench_index -- [features_per_contig] [contigs]
+//!
+//! Two layouts are measured: dense (a 1 kb gene every 1.2 kb, as in a
+//! bacterial genome) and sparse (a 50 bp feature every 20 kb, one finest
+//! bin each).  The GFF3 is compressed once up front; only indexing is
+//! timed, best of three runs.  Serialization (`CsiIndex::write_bgzf`, the
+//! writer `csi_index` uses) is timed on the index parsed back, in MB/s of
+//! uncompressed index.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::{Cursor, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use mgnify_wasm::htslib::{bgzf_compress, csi_index_gff, BgzfReader, CsiIndex};
+
+/// System allocator that tracks the current and peak bytes allocated.
+struct Counting;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let now = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(now, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn synthetic_gff(contigs: u64, features: u64, step: u64, length: u64) -> Vec<u8> {
+    let mut gff = String::from("##gff-version 3\n");
+    for c in 0..contigs {
+        for i in 0..features {
+            let start = i * step + 1;
+            gff.push_str(&format!("contig_{}\tbench\tgene\t{}\t{}\t.\t+\t.\tID=c{}g{}\n", c, start, start + length - 1, c, i));
+        }
+    }
+    let mut bgzf = Vec::new();
+    bgzf_compress(Cursor::new(gff.as_bytes()), &mut bgzf).expect("bgzf_compress failed");
+    bgzf
+}
+
+fn main() {
+    let args: Vec<u64> = std::env::args().skip(1).map(|a| a.parse().expect("arguments are numbers")).collect();
+    let features = args.first().copied().unwrap_or(200_000);
+    let contigs = args.get(1).copied().unwrap_or(10);
+
+    for (label, step, length) in [("dense", 1_200, 1_000), ("sparse", 20_000, 50)] {
+        let bgzf = synthetic_gff(contigs, features, step, length);
+        let records = contigs * features;
+        let mut best = f64::INFINITY;
+        let mut peak = 0;
+        let mut csi = Vec::new();
+        for _ in 0..3 {
+            let baseline = CURRENT.load(Ordering::Relaxed);
+            PEAK.store(baseline, Ordering::Relaxed);
+            let started = Instant::now();
+            csi.clear();
+            csi_index_gff(Cursor::new(&bgzf), &mut csi).expect("csi_index_gff failed");
+            best = best.min(started.elapsed().as_secs_f64());
+            peak = PEAK.load(Ordering::Relaxed) - baseline;
+        }
+        println!(
+            "{:<6} {:>9} records  {:>8.1} ms  {:>6.0} ns/record  peak heap {:>6.1} MiB ({:>5.1} B/record)  .csi {} B",
+            label,
+            records,
+            best * 1e3,
+            best * 1e9 / records as f64,
+            peak as f64 / (1 << 20) as f64,
+            peak as f64 / records as f64,
+            csi.len(),
+        );
+
+        let index = CsiIndex::from_bgzf(&csi).expect("parsing our .csi failed");
+        let mut raw = Vec::new();
+        BgzfReader::new(Cursor::new(&csi)).read_to_end(&mut raw).expect("decompressing our .csi failed");
+        let mut best = f64::INFINITY;
+        for _ in 0..3 {
+            let started = Instant::now();
+            let written = index.write_bgzf(Vec::new()).expect("write_bgzf failed");
+            best = best.min(started.elapsed().as_secs_f64());
+            assert_eq!(written, csi, "serializing the parsed index changed it");
+        }
+        println!("{:<6} serialize {:>8.1} ms  {:>6.1} MB/s", label, best * 1e3, raw.len() as f64 / best / 1e6);
+    }
+}
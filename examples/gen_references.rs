@@ -1,20 +1,22 @@
-/// Produces the BGZF-compressed files that samtools/tabix need to generate
-/// the committed reference index files used by integration tests.
-///
-/// Usage:
-///   cargo run --example gen_references -- <fasta_in> <fasta_bgz_out> <gff_in> <gff_bgz_out>
-///
-/// Input files may be plain or gzip-compressed (detected by magic bytes).
-///
-/// After running this, use tests/generate_references.sh to invoke samtools/tabix
-/// on the outputs and commit the resulting .fai, .gzi, and .csi files.
+//! Produces the BGZF-compressed files that samtools/tabix need to generate
+//! the committed reference index files used by integration tests.
+//!
+//! Usage:
+//!   cargo run --features reference --example gen_references -- <fasta_in> <fasta_bgz_out> <gff_in> <gff_bgz_out>
+//!   cargo run --features reference --example gen_references -- --check <fasta_in> <gff_in>
+//!
+//! Input files may be plain or gzip-compressed (detected by magic bytes).
+//!
+//! After running this, use tests/generate_references.sh to invoke samtools/tabix
+//! on the outputs and commit the resulting .fai, .gzi, .csi and .tbi files.
+//! `--check` instead runs samtools/tabix itself and prints the divergence
+//! report of `mgnify_wasm::reference` (exit status 1 on divergence).
 
 use std::fs;
-use std::io::{Cursor, Read};
+use std::io::Read;
 
 use flate2::read::MultiGzDecoder;
-use mgnify_wasm::htslib::bgzf_compress;
-use mgnify_wasm::gff_preprocess;
+use mgnify_wasm::reference::{bgzf_pair, htslib_artifacts, our_artifacts, Report};
 
 /// Read a file, transparently decompressing if it begins with the gzip magic bytes.
 fn read_maybe_gz(path: &str) -> Vec<u8> {
@@ -38,29 +40,43 @@ fn read_text_maybe_gz(path: &str) -> String {
         .unwrap_or_else(|e| panic!("non-UTF8 content in {}: {}", path, e))
 }
 
+/// Index both files with our code and with samtools/tabix, print the report.
+fn check(fasta_in: &str, gff_in: &str) {
+    let (fasta_bgz, gff_bgz) = bgzf_pair(&read_maybe_gz(fasta_in), &read_text_maybe_gz(gff_in))
+        .expect("bgzf_compress failed");
+    let workdir = std::env::temp_dir().join(format!("gen_references-{}", std::process::id()));
+    fs::create_dir_all(&workdir).expect("cannot create work directory");
+    let theirs = htslib_artifacts(&fasta_bgz, &gff_bgz, &workdir);
+    let _ = fs::remove_dir_all(&workdir);
+
+    let mut report = Report::default();
+    report.compare(fasta_in, &our_artifacts(&fasta_bgz, &gff_bgz).expect("indexing failed"), &theirs.expect("samtools/tabix failed"));
+    print!("{}", report);
+    if !report.is_compatible() {
+        std::process::exit(1);
+    }
+}
+
 fn main() {
     let args: Vec<String> = std::env::args().collect();
+    if args.len() == 4 && args[1] == "--check" {
+        return check(&args[2], &args[3]);
+    }
     if args.len() != 5 {
         eprintln!("Usage: gen_references <fasta_in> <fasta_bgz_out> <gff_in> <gff_bgz_out>");
+        eprintln!("       gen_references --check <fasta_in> <gff_in>");
         std::process::exit(1);
     }
 
     let (fasta_in, fasta_out, gff_in, gff_out) = (&args[1], &args[2], &args[3], &args[4]);
 
-    // FASTA: read (decompressing if needed) and BGZF-compress
-    let fa_bytes = read_maybe_gz(fasta_in);
-    let mut fasta_bgz = Vec::new();
-    bgzf_compress(Cursor::new(&fa_bytes), &mut fasta_bgz).expect("FASTA bgzf_compress failed");
+    // FASTA is BGZF-compressed as is; the GFF3 is preprocessed (sort + strip
+    // ##FASTA) first, exactly as the pipeline does.
+    let (fasta_bgz, gff_bgz) = bgzf_pair(&read_maybe_gz(fasta_in), &read_text_maybe_gz(gff_in))
+        .expect("bgzf_compress failed");
     fs::write(fasta_out, &fasta_bgz)
         .unwrap_or_else(|e| panic!("cannot write {}: {}", fasta_out, e));
     eprintln!("Wrote {} bytes → {}", fasta_bgz.len(), fasta_out);
-
-    // GFF3: read (decompressing if needed), preprocess (sort + strip ##FASTA), then BGZF-compress
-    let gff_raw = read_text_maybe_gz(gff_in);
-    let gff_preprocessed = gff_preprocess(&gff_raw);
-    let mut gff_bgz = Vec::new();
-    bgzf_compress(Cursor::new(gff_preprocessed.as_bytes()), &mut gff_bgz)
-        .expect("GFF bgzf_compress failed");
     fs::write(gff_out, &gff_bgz)
         .unwrap_or_else(|e| panic!("cannot write {}: {}", gff_out, e));
     eprintln!("Wrote {} bytes → {}", gff_bgz.len(), gff_out);
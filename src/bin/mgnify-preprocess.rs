@@ -0,0 +1,459 @@
+//! File-path/stdio front-end to the preprocessing pipeline, for native use
+//! and for WASI runtimes (wasmtime, wasmer, …) where there is no browser
+//! `File` or console:
+//!
+//!   cargo build --release --target wasm32-wasip1 --bin mgnify-preprocess
+//!   wasmtime run --dir . target/wasm32-wasip1/release/mgnify-preprocess.wasm \
+//!       genome.fa annotations.gff3 out/genome
+//!
+//! Runs exactly the code behind `IndexGen`; only input and output differ.
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+use mgnify_wasm::error::error_code;
+use mgnify_wasm::gfa::Gfa;
+use mgnify_wasm::htslib::{set_crc32_impl, Crc32Impl};
+use mgnify_wasm::input::{read_pair, InputSource};
+use mgnify_wasm::limits::{input_budget, read_to_end_within, Measure};
+use mgnify_wasm::manifest::MANIFEST_NAME;
+use mgnify_wasm::preview::PreviewOptions;
+use mgnify_wasm::{BinRollup, CircularPolicy, Collation, ControlCharPolicy, FaiOffsets, IndexFormat, IndexGen, IgvOptions, IndexGenOptions, JBrowseOptions, MalformedRecordPolicy, OutputNames, TrackFormat};
+
+const USAGE: &str = "\
+Usage: mgnify-preprocess [OPTIONS] <FASTA> <GFF3> <OUT_PREFIX>
+
+Inputs may be plain or gzip-compressed; `-` reads one of them from stdin.
+Writes <OUT_PREFIX>.fasta.bgz/.fasta.fai/.fasta.gzi/.gff.bgz plus the GFF
+index(es) (or the names --naming and --name give) and
+<OUT_PREFIX>.manifest.json, and prints the warnings JSON on stdout.  With
+--fasta-transform split_at_gaps it also writes <OUT_PREFIX>.contigs.json,
+where each contig came from.
+
+Options:
+  --compression-level <0-9>             BGZF deflate level (default 6)
+  --index-format <csi|tbi|both>         GFF index format(s) (default csi)
+  --search-index                        also write <OUT_PREFIX>.gff.search
+  --gff-sort-keys <type,strand>         order records with equal coordinates by these
+  --gff-type-priority <gene,mRNA,...>   feature types the `type` sort key puts first
+  --gff-collation <bytewise|dictionary|natural>
+                                        seqid order of the sorted GFF3, independent of the locale
+                                        (default bytewise, as LC_ALL=C sort)
+  --repair-coordinates                  swap start/end of records with start > end
+  --control-chars <error|strip|replace> control character policy (default error)
+  --malformed-records <error|drop>      GFF3 records without seqid..end or numeric coordinates
+                                        (default error)
+  --circular-features <split|clamp|error>
+                                        features crossing the origin of a circular sequence
+                                        (default split)
+  --fasta-transform <SPEC>              FASTA preprocessing step, e.g. rename_seqids:1=chr1; repeat
+                                        for several, in order (replaces the default steps)
+  --gff-transform <SPEC>                likewise for the GFF3, e.g. filter_types:drop=region
+  --replicon-metadata <FILE>            contig<TAB>replicon type<TAB>linear|circular table, tagged
+                                        onto FASTA headers and GFF3 region records
+  --rewrap-irregular                    rewrap FASTA with irregular line lengths
+  --strip-cr                            convert FASTA CRLF line endings to LF
+  --gap-track <none|bed|gff>            also write the assembly gaps as <OUT_PREFIX>.gaps.bed.gz
+                                        (or .gaps.gff3.gz) with its .csi (default none)
+  --gap-min-length <N>                  shortest run of Ns that is a gap (default 10)
+  --repeat-track <none|bed|gff>         also write homopolymers and tandem repeats as
+                                        <OUT_PREFIX>.repeats.bed.gz (or .gff3.gz) with its .csi
+                                        (default none)
+  --homopolymer-min-length <N>          shortest homopolymer of the repeat track (default 8)
+  --tandem-min-length <N>               shortest tandem repeat of the repeat track, in bases
+                                        (default 12)
+  --orf-track <none|bed|gff>            also write six-frame ORFs (table 11) as a provisional
+                                        <OUT_PREFIX>.orfs.gff3.gz (or .bed.gz) with its .csi
+                                        (default none)
+  --orf-min-length <N>                  shortest ORF, in bases with the stop codon (default 300)
+  --genetic-code <N>                    NCBI genetic code table of the ORF track (default 11)
+  --rna-track <none|bed|gff>            also write a low-confidence scan for 16S/23S rRNA and tRNA
+                                        as <OUT_PREFIX>.rna.gff3.gz (or .bed.gz) with its .csi
+                                        (default none)
+  --fai-offsets <uncompressed|virtual>  .fai offset convention (default uncompressed)
+  --bin-rollup <htslib|aggressive>      how sparse index bins are merged; aggressive gives
+                                        smaller .csi/.tbi files (default htslib)
+  --skip-written-crc                    don't re-check CRC32s of the outputs while indexing
+  --crc32 <fast|scalar>                 CRC32 implementation (default fast)
+  --sequential                          with --features threads, still write the FASTA and GFF3
+                                        outputs one after the other (less memory)
+  --seed <N>                            seed of sampling features, recorded in the manifest (default 0)
+  --dry-run                             validate only; print the dry-run JSON
+  --indexes-only                        inputs are BGZF (GFF3 sorted); write only the indexes
+  --preview-bytes <N>                   preview: process only the first N bytes of each input
+                                        (default 16777216 with --preview-sequences)
+  --preview-sequences <K>               preview: process only the first K sequences of each input
+  --depth <FILE>                        read depth (samtools depth or jgi_summarize_bam_contig_depths
+                                        output) into <OUT_PREFIX>.coverage.bedgraph.gz with its .csi;
+                                        with --dry-run, into stats.coverage
+  --bins <FILE>                         read a contig<TAB>bin table into <OUT_PREFIX>.bins.bed.gz,
+                                        coloured by bin, with its .csi and per-bin size, N50 and GC
+  --gfa <FILE>                          check a GFA1 assembly graph against the FASTA, writing
+                                        <OUT_PREFIX>.gfa.json (links, components, name/length matches)
+  --gfa-segments                        with --gfa, also write the segment sequences to
+                                        <OUT_PREFIX>.segments.fa
+  --keep-going                          when the FASTA or GFF3 stage fails, still write the other's
+                                        outputs (exit status 1)
+  --jbrowse-url <URL>                   also write <OUT_PREFIX>.jbrowse.json for files served at URL
+  --text-index                          with --jbrowse-url, also write the search box index of the
+                                        track next to the GFF3 (<GFF3 file>.ix and .ixx)
+  --track-styles                        also write <OUT_PREFIX>.styles.json with default colours
+                                        per feature type and source
+  --igv-url <TEMPLATE>                  also write <OUT_PREFIX>.igv.json; {prefix}, {artifact} and
+                                        {file} in TEMPLATE become each file's URL
+  --naming <prefix|samtools>            output file names: <OUT_PREFIX>.fasta.bgz and so on, or
+                                        after the inputs as bgzip/faidx/tabix name them
+                                        (genome.fa.gz, genome.fa.gz.fai, ...) in the directory of
+                                        <OUT_PREFIX> (default prefix)
+  --name <ARTIFACT=FILE>                file name of one output (fasta.bgz, gff.csi, ...) in the
+                                        directory of <OUT_PREFIX>; repeat for several
+";
+
+struct Args {
+    options: IndexGenOptions,
+    dry_run: bool,
+    indexes_only: bool,
+    /// Process only the start of each input, if set.
+    preview: Option<PreviewOptions>,
+    /// Keep the outputs of the stage that succeeded when the other fails.
+    keep_going: bool,
+    /// Base URL for `<OUT_PREFIX>.jbrowse.json`, if one is wanted.
+    jbrowse_url: Option<String>,
+    /// Write the trix text index the JBrowse config points at.
+    text_index: bool,
+    /// URL template for `<OUT_PREFIX>.igv.json`, if one is wanted.
+    igv_url: Option<String>,
+    /// Write `<OUT_PREFIX>.styles.json`.
+    track_styles: bool,
+    /// Name the outputs after the inputs (`--naming samtools`).
+    samtools_names: bool,
+    /// `--name` overrides: artifact and file name.
+    renames: Vec<(String, String)>,
+    /// Depth file to read into the coverage track.
+    depth: Option<String>,
+    /// Contig-to-bin table to read into the bins track.
+    bins: Option<String>,
+    /// GFA1 graph to check against the FASTA.
+    gfa: Option<String>,
+    /// Write the segments of the graph as `<OUT_PREFIX>.segments.fa`.
+    gfa_segments: bool,
+    fasta: String,
+    gff: String,
+    prefix: String,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut options = IndexGenOptions::default();
+    let (mut dry_run, mut indexes_only, mut keep_going, mut track_styles, mut positional) = (false, false, false, false, Vec::new());
+    let (mut jbrowse_url, mut igv_url, mut preview) = (None, None, None::<PreviewOptions>);
+    let (mut samtools_names, mut renames, mut depth, mut bins) = (false, Vec::new(), None, None);
+    let (mut gfa, mut gfa_segments, mut text_index) = (None, false, false);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--compression-level" => {
+                options.compression_level = value()?.parse().map_err(|_| "--compression-level must be a number".to_owned())?;
+            }
+            "--index-format" => {
+                options.index_format = match value()?.as_str() {
+                    "csi" => IndexFormat::Csi,
+                    "tbi" => IndexFormat::Tbi,
+                    "both" => IndexFormat::Both,
+                    v => return Err(format!("unknown index format {:?}", v)),
+                };
+            }
+            "--control-chars" => {
+                options.control_chars = match value()?.as_str() {
+                    "error" => ControlCharPolicy::Error,
+                    "strip" => ControlCharPolicy::Strip,
+                    "replace" => ControlCharPolicy::Replace,
+                    v => return Err(format!("unknown control character policy {:?}", v)),
+                };
+            }
+            "--malformed-records" => {
+                options.malformed_records = match value()?.as_str() {
+                    "error" => MalformedRecordPolicy::Error,
+                    "drop" => MalformedRecordPolicy::Drop,
+                    v => return Err(format!("unknown malformed record policy {:?}", v)),
+                };
+            }
+            "--circular-features" => {
+                options.circular_features = match value()?.as_str() {
+                    "split" => CircularPolicy::Split,
+                    "clamp" => CircularPolicy::Clamp,
+                    "error" => CircularPolicy::Error,
+                    v => return Err(format!("unknown circular feature policy {:?}", v)),
+                };
+            }
+            "--gff-collation" => {
+                options.gff_collation = match value()?.as_str() {
+                    "bytewise" => Collation::Bytewise,
+                    "dictionary" => Collation::Dictionary,
+                    "natural" => Collation::Natural,
+                    v => return Err(format!("unknown collation {:?}", v)),
+                };
+            }
+            "--fai-offsets" => {
+                options.fai_offsets = match value()?.as_str() {
+                    "uncompressed" => FaiOffsets::Uncompressed,
+                    "virtual" => FaiOffsets::Virtual,
+                    v => return Err(format!("unknown .fai offset convention {:?}", v)),
+                };
+            }
+            "--bin-rollup" => {
+                options.bin_rollup = match value()?.as_str() {
+                    "htslib" => BinRollup::Htslib,
+                    "aggressive" => BinRollup::Aggressive,
+                    v => return Err(format!("unknown bin rollup {:?}", v)),
+                };
+            }
+            "--gap-track" => {
+                options.gap_track = match value()?.as_str() {
+                    "none" => TrackFormat::Off,
+                    "bed" => TrackFormat::Bed,
+                    "gff" => TrackFormat::Gff,
+                    v => return Err(format!("unknown gap track format {:?}", v)),
+                };
+            }
+            "--gap-min-length" => {
+                options.gap_min_length = value()?.parse().map_err(|_| "--gap-min-length must be a number below 2^32".to_owned())?;
+            }
+            "--repeat-track" => {
+                options.repeat_track = match value()?.as_str() {
+                    "none" => TrackFormat::Off,
+                    "bed" => TrackFormat::Bed,
+                    "gff" => TrackFormat::Gff,
+                    v => return Err(format!("unknown repeat track format {:?}", v)),
+                };
+            }
+            "--homopolymer-min-length" => {
+                options.homopolymer_min_length =
+                    value()?.parse().map_err(|_| "--homopolymer-min-length must be a number below 2^32".to_owned())?;
+            }
+            "--orf-track" => {
+                options.orf_track = match value()?.as_str() {
+                    "none" => TrackFormat::Off,
+                    "bed" => TrackFormat::Bed,
+                    "gff" => TrackFormat::Gff,
+                    v => return Err(format!("unknown ORF track format {:?}", v)),
+                };
+            }
+            "--genetic-code" => {
+                options.genetic_code = value()?.parse().map_err(|_| "--genetic-code must be a number".to_owned())?;
+            }
+            "--orf-min-length" => {
+                options.orf_min_length = value()?.parse().map_err(|_| "--orf-min-length must be a number below 2^32".to_owned())?;
+            }
+            "--rna-track" => {
+                options.rna_track = match value()?.as_str() {
+                    "none" => TrackFormat::Off,
+                    "bed" => TrackFormat::Bed,
+                    "gff" => TrackFormat::Gff,
+                    v => return Err(format!("unknown RNA track format {:?}", v)),
+                };
+            }
+            "--tandem-min-length" => {
+                options.tandem_min_length = value()?.parse().map_err(|_| "--tandem-min-length must be a number below 2^32".to_owned())?;
+            }
+            "--seed" => {
+                options.seed = value()?.parse().map_err(|_| "--seed must be a number below 2^32".to_owned())?;
+            }
+            "--crc32" => {
+                set_crc32_impl(match value()?.as_str() {
+                    "fast" => Crc32Impl::Fast,
+                    "scalar" => Crc32Impl::Scalar,
+                    v => return Err(format!("unknown CRC32 implementation {:?}", v)),
+                });
+            }
+            "--preview-bytes" => {
+                preview.get_or_insert_with(PreviewOptions::new).max_bytes =
+                    value()?.parse().map_err(|_| "--preview-bytes must be a number below 2^32".to_owned())?;
+            }
+            "--preview-sequences" => {
+                preview.get_or_insert_with(PreviewOptions::new).max_sequences =
+                    value()?.parse().map_err(|_| "--preview-sequences must be a number".to_owned())?;
+            }
+            "--search-index" => options = options.with_default_search_keys(),
+            "--gff-sort-keys" => options.gff_sort_keys = value()?.split(',').map(str::to_owned).collect(),
+            "--gff-type-priority" => options.gff_type_priority = value()?.split(',').map(str::to_owned).collect(),
+            "--fasta-transform" => options.fasta_transforms.push(value()?),
+            "--gff-transform" => options.gff_transforms.push(value()?),
+            "--replicon-metadata" => {
+                let path = value()?;
+                options.replicon_metadata = std::fs::read_to_string(&path).map_err(|e| format!("--replicon-metadata {}: {}", path, e))?;
+            }
+            "--repair-coordinates" => options.repair_coordinates = true,
+            "--rewrap-irregular" => options.rewrap_irregular = true,
+            "--strip-cr" => options.strip_cr = true,
+            "--skip-written-crc" => options.verify_written_crc = false,
+            "--sequential" => options.parallel_branches = false,
+            "--dry-run" => dry_run = true,
+            "--indexes-only" => indexes_only = true,
+            "--keep-going" => keep_going = true,
+            "--jbrowse-url" => jbrowse_url = Some(value()?),
+            "--text-index" => text_index = true,
+            "--igv-url" => igv_url = Some(value()?),
+            "--track-styles" => track_styles = true,
+            "--depth" => depth = Some(value()?),
+            "--bins" => bins = Some(value()?),
+            "--gfa" => gfa = Some(value()?),
+            "--gfa-segments" => gfa_segments = true,
+            "--naming" => {
+                samtools_names = match value()?.as_str() {
+                    "prefix" => false,
+                    "samtools" => true,
+                    other => return Err(format!("--naming must be prefix or samtools, not {}", other)),
+                };
+            }
+            "--name" => {
+                let value = value()?;
+                let (artifact, file) = value.split_once('=').ok_or_else(|| format!("--name needs ARTIFACT=FILE, not {}", value))?;
+                renames.push((artifact.to_owned(), file.to_owned()));
+            }
+            "-h" | "--help" => return Err(String::new()),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => positional.push(arg),
+        }
+    }
+    let [fasta, gff, prefix]: [String; 3] = positional.try_into().map_err(|_| "expected <FASTA> <GFF3> <OUT_PREFIX>".to_owned())?;
+    if fasta == "-" && gff == "-" {
+        return Err("only one input can be read from stdin".into());
+    }
+    if dry_run && indexes_only {
+        return Err("--dry-run and --indexes-only cannot be combined".into());
+    }
+    if keep_going && (dry_run || indexes_only) {
+        return Err("--keep-going only applies to the full pipeline".into());
+    }
+    if preview.is_some() && (dry_run || indexes_only || keep_going) {
+        return Err("--preview-bytes and --preview-sequences only apply to the full pipeline".into());
+    }
+    if [Some(fasta.as_str()), Some(gff.as_str()), depth.as_deref(), bins.as_deref(), gfa.as_deref()].iter().filter(|p| **p == Some("-")).count() > 1 {
+        return Err("only one input can be read from stdin".into());
+    }
+    if dry_run && (bins.is_some() || gfa.is_some()) {
+        return Err("--bins and --gfa only apply to runs that write files".into());
+    }
+    if gfa_segments && gfa.is_none() {
+        return Err("--gfa-segments needs --gfa".into());
+    }
+    if text_index && jbrowse_url.is_none() {
+        return Err("--text-index needs --jbrowse-url".into());
+    }
+    if dry_run && (jbrowse_url.is_some() || igv_url.is_some() || track_styles) {
+        return Err("--dry-run writes no files for --jbrowse-url, --igv-url or --track-styles to describe".into());
+    }
+    Ok(Args { options, dry_run, indexes_only, preview, keep_going, jbrowse_url, text_index, igv_url, track_styles, samtools_names, renames, depth, bins, gfa, gfa_segments, fasta, gff, prefix })
+}
+
+fn source(path: &str) -> io::Result<InputSource> {
+    if path != "-" {
+        return Ok(InputSource::Path(path.into()));
+    }
+    let mut bytes = Vec::new();
+    read_to_end_within(io::stdin().lock(), &mut bytes, input_budget(), Measure::Stored, "standard input")?;
+    Ok(InputSource::Bytes(bytes))
+}
+
+/// Returns whether every stage succeeded (always, without `--keep-going`).
+fn run(args: Args) -> io::Result<bool> {
+    let (fasta, gff) = (source(&args.fasta)?, source(&args.gff)?);
+    let mut stdout = io::stdout().lock();
+    if args.dry_run {
+        let (fa_bytes, gff_bytes) = read_pair(fasta, gff)?;
+        let depth = args.depth.as_deref().map(|path| source(path)?.read_all()).transpose()?;
+        let report = IndexGen::dry_run_with_depth(&fa_bytes, &gff_bytes, depth.as_deref(), &args.options);
+        writeln!(stdout, "{}", report.dump())?;
+        return Ok(true);
+    }
+    let mut out = if args.indexes_only {
+        IndexGen::indexes_from_sources(fasta, gff, &args.options)?
+    } else if let Some(preview) = &args.preview {
+        IndexGen::preview_from_sources(fasta, gff, &args.options, preview)?
+    } else if args.keep_going {
+        IndexGen::from_sources_partial(fasta, gff, &args.options)?
+    } else {
+        IndexGen::from_sources(fasta, gff, &args.options)?
+    };
+    // The depth is checked against the .fai, which a failed FASTA stage
+    // of a --keep-going run does not leave.
+    if let Some(path) = args.depth.as_deref().filter(|_| !out.artifacts()[1].1.is_empty()) {
+        out.add_coverage_bytes(&source(path)?.read_all()?, &args.options)?;
+    }
+    if let Some(path) = args.bins.as_deref().filter(|_| !out.artifacts()[0].1.is_empty()) {
+        out.add_bins_bytes(&source(path)?.read_all()?, &args.options)?;
+    }
+    // Outputs go next to <OUT_PREFIX>, named by the chosen convention.
+    let dir = Path::new(&args.prefix).parent().unwrap_or(Path::new(""));
+    let name = Path::new(&args.prefix).file_name().map_or(args.prefix.clone(), |n| n.to_string_lossy().into_owned());
+    let mut names = if args.samtools_names { OutputNames::from_inputs(&args.fasta, &args.gff)? } else { OutputNames::new(&name) };
+    for (artifact, file) in &args.renames {
+        names.rename(artifact, file.clone())?;
+    }
+    for (artifact, data) in out.artifacts().into_iter().filter(|(_, data)| !data.is_empty()) {
+        std::fs::write(dir.join(names.get(artifact)), data)?;
+    }
+    std::fs::write(format!("{}.{}", args.prefix, MANIFEST_NAME), out.manifest_named(&names).pretty(2))?;
+    let complete = out.failures().is_empty();
+    // Browser configs sit next to the outputs and need all of them, so a
+    // partial run writes none.
+    if let Some(url) = args.jbrowse_url.filter(|_| complete) {
+        let options = JBrowseOptions { base_url: url, names: Some(names.clone()), text_index: args.text_index, ..JBrowseOptions::new(name.clone()) };
+        std::fs::write(format!("{}.jbrowse.json", args.prefix), out.jbrowse_config_json(&options)?.pretty(2))?;
+        if args.text_index {
+            let mut trix = out.trix_index_files(&options)?;
+            std::fs::write(dir.join(options.trix_file_name("ix")), trix.ix())?;
+            std::fs::write(dir.join(options.trix_file_name("ixx")), trix.ixx())?;
+        }
+    }
+    if let Some(template) = args.igv_url.filter(|_| complete) {
+        let options = IgvOptions { url_template: template, names: Some(names), ..IgvOptions::new(name) };
+        std::fs::write(format!("{}.igv.json", args.prefix), out.igv_session_json(&options)?.pretty(2))?;
+    }
+    for track in out.track_names().iter().filter_map(|name| out.track(name)) {
+        let path = format!("{}.{}.{}.gz", args.prefix, track.name(), track.extension());
+        std::fs::write(&path, track.bgz_bytes())?;
+        std::fs::write(format!("{}.csi", path), track.csi_bytes())?;
+    }
+    if out.contig_map().min_gap.is_some() {
+        std::fs::write(format!("{}.contigs.json", args.prefix), out.contig_map().to_json().pretty(2))?;
+    }
+    if args.track_styles && !out.artifacts()[3].1.is_empty() {
+        std::fs::write(format!("{}.styles.json", args.prefix), out.track_styles_json()?.pretty(2))?;
+    }
+    // Like the depth, the graph is checked against the .fai.
+    if let Some(path) = args.gfa.as_deref().filter(|_| !out.artifacts()[1].1.is_empty()) {
+        let gfa = source(path)?.read_all()?;
+        std::fs::write(format!("{}.gfa.json", args.prefix), out.check_gfa_bytes(&gfa)?.pretty(2))?;
+        if args.gfa_segments {
+            std::fs::write(format!("{}.segments.fa", args.prefix), Gfa::parse(&gfa, true)?.segments_fasta())?;
+        }
+    }
+    writeln!(stdout, "{}", out.warnings().to_json().dump())?;
+    for failure in out.failures() {
+        eprintln!("mgnify-preprocess: {} stage failed: {}: {}", failure.stage, failure.error.code, failure.error);
+    }
+    Ok(complete)
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(msg) => {
+            if !msg.is_empty() {
+                eprintln!("mgnify-preprocess: {}\n", msg);
+            }
+            eprint!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match run(args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("mgnify-preprocess: {}: {}", error_code(&e), e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Metagenome bin membership, as a colour-coded BED track.
+//!
+//! Before MAGs are submitted, a look at which contigs went into which bin
+//! (and how big, contiguous and GC-consistent each bin is) catches the
+//! worst binning mistakes.  The bins file is the `contig  bin` table that
+//! DAS Tool and most binners write: tab-separated, one contig per line,
+//! extra columns ignored.  Blank lines and `#` comments are skipped, as is
+//! a first line whose contig is not in the FASTA (a `contig  bin` header).
+//!
+//! Contigs must be sequences of the FASTA (`E_UNKNOWN_SEQUENCE`); a contig
+//! listed twice or a line without a bin is `E_BINS_PARSE`.  Every binned
+//! contig becomes one BED9 line over its whole length, named after its bin
+//! and coloured (`itemRgb`) by bin, in the `bins` track (see
+//! `fasta/track.rs`) as `<OUT_PREFIX>.bins.bed.gz`; size, N50 and GC of
+//! every bin go into its stats.  Colours are the source palette of
+//! `export/styles.rs`, given to the bins in name order and reused
+//! cyclically.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read};
+
+use crate::error::{io_error, ErrorCode};
+use crate::export::styles::SOURCE_PALETTE;
+use crate::fasta::track::SequenceTrack;
+use crate::fasta::{FastaLine, FastaParser};
+use crate::htslib::{BgzfReader, TabixConfig};
+use crate::scan::{self, BaseCounts};
+use crate::stats::n50;
+
+/// A sequence of the FASTA: `(name, length, base counts)`.
+pub type SequenceComposition = (String, u64, BaseCounts);
+
+/// One bin and its contigs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bin {
+    pub name: String,
+    /// `#rrggbb`.
+    pub color: &'static str,
+    /// Indices into the sequences, in FASTA order.
+    pub contigs: Vec<usize>,
+}
+
+/// The bins of an assembly.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bins {
+    /// In name order.
+    pub bins: Vec<Bin>,
+    /// The sequences the bins were read against, in FASTA order.
+    pub sequences: Vec<SequenceComposition>,
+}
+
+fn bins_error(line: usize, message: impl std::fmt::Display) -> io::Error {
+    io_error(ErrorCode::BinsParse, format!("bins file line {}: {}", line, message))
+}
+
+/// `#rrggbb` as BED `itemRgb`, `r,g,b`.
+fn item_rgb(color: &str) -> String {
+    let channel = |i: usize| u8::from_str_radix(&color[i..i + 2], 16).unwrap_or(0);
+    format!("{},{},{}", channel(1), channel(3), channel(5))
+}
+
+impl Bins {
+    /// Read the bins file `tsv` of the FASTA whose sequences are
+    /// `sequences`, in FASTA order.
+    pub fn parse(tsv: &[u8], sequences: Vec<SequenceComposition>) -> io::Result<Self> {
+        let index: HashMap<&str, usize> = sequences.iter().enumerate().map(|(i, (name, _, _))| (name.as_str(), i)).collect();
+        let mut bin_of: Vec<Option<&str>> = vec![None; sequences.len()];
+        let mut first = true;
+        let text = std::str::from_utf8(tsv).map_err(|_| io_error(ErrorCode::BinsParse, "bins file is not UTF-8"))?;
+        for (n, line) in text.split('\n').enumerate().map(|(n, line)| (n + 1, line.trim_end_matches('\r'))) {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+            let header = std::mem::replace(&mut first, false);
+            let Some(&i) = index.get(fields[0]) else {
+                if header {
+                    continue;
+                }
+                return Err(io_error(ErrorCode::UnknownSequence, format!("bins file line {}: unknown sequence {:?}", n, fields[0])));
+            };
+            let bin = fields.get(1).copied().filter(|b| !b.is_empty()).ok_or_else(|| bins_error(n, "expected `contig<TAB>bin`"))?;
+            if let Some(other) = bin_of[i].replace(bin) {
+                return Err(bins_error(n, format!("sequence {:?} is already in bin {:?}", fields[0], other)));
+            }
+        }
+        let mut by_name: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for (i, bin) in bin_of.iter().enumerate() {
+            if let Some(bin) = bin {
+                by_name.entry(bin).or_default().push(i);
+            }
+        }
+        let bins = by_name
+            .into_iter()
+            .enumerate()
+            .map(|(k, (name, contigs))| Bin { name: name.to_string(), color: SOURCE_PALETTE[k % SOURCE_PALETTE.len()], contigs })
+            .collect();
+        Ok(Bins { bins, sequences })
+    }
+
+    /// The figures for the stats: `{bins: [{name, color, contigs, size,
+    /// n50, gc_fraction}], unbinned: {contigs, size}}`, GC over A+C+G+T to
+    /// 4 decimals.
+    pub fn to_json(&self) -> json::JsonValue {
+        let mut bins = json::JsonValue::new_array();
+        let mut binned = vec![false; self.sequences.len()];
+        for bin in &self.bins {
+            let lengths: Vec<u64> = bin.contigs.iter().map(|&i| self.sequences[i].1).collect();
+            let mut bases = BaseCounts::default();
+            for &i in &bin.contigs {
+                bases += self.sequences[i].2;
+                binned[i] = true;
+            }
+            let gc = if bases.gc + bases.at == 0 { 0.0 } else { bases.gc as f64 / (bases.gc + bases.at) as f64 };
+            let _ = bins.push(json::object! {
+                name: bin.name.as_str(),
+                color: bin.color,
+                contigs: bin.contigs.len(),
+                size: lengths.iter().sum::<u64>(),
+                n50: n50(&lengths),
+                gc_fraction: (gc * 10000.0).round() / 10000.0,
+            });
+        }
+        let unbinned: Vec<u64> = self.sequences.iter().zip(&binned).filter(|(_, b)| !**b).map(|(s, _)| s.1).collect();
+        json::object! {
+            bins: bins,
+            unbinned: json::object! { contigs: unbinned.len(), size: unbinned.iter().sum::<u64>() },
+        }
+    }
+
+    /// The binned contigs as BED9, in FASTA order: `seqid  0  length  bin
+    /// 0  .  0  length  r,g,b`.
+    pub fn to_bed(&self) -> String {
+        let mut bin_of: Vec<Option<&Bin>> = vec![None; self.sequences.len()];
+        for bin in &self.bins {
+            for &i in &bin.contigs {
+                bin_of[i] = Some(bin);
+            }
+        }
+        let mut out = String::new();
+        for ((name, length, _), bin) in self.sequences.iter().zip(bin_of) {
+            if let Some(bin) = bin {
+                out.push_str(&format!("{}\t0\t{}\t{}\t0\t.\t0\t{}\t{}\n", name, length, bin.name, length, item_rgb(bin.color)));
+            }
+        }
+        out
+    }
+
+    /// The `bins` track, compressed at `level`, with [`Bins::to_json`] as
+    /// its figures.
+    pub fn track(&self, level: u32) -> io::Result<SequenceTrack> {
+        SequenceTrack::from_text("bins", "bed", &TabixConfig::BED, &self.to_bed(), self.to_json(), level)
+    }
+}
+
+/// `(name, length, base counts)` of every sequence of a BGZF-compressed
+/// FASTA, in order, read a line at a time.
+pub fn sequence_composition<R: Read>(fasta_bgz: R) -> io::Result<Vec<SequenceComposition>> {
+    let mut reader = BgzfReader::new(fasta_bgz);
+    let mut out = Vec::new();
+    let mut parser = FastaParser::new();
+    let mut bases = BaseCounts::default();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)?.0 == 0 {
+            break;
+        }
+        let line = line.strip_suffix(b"\n").unwrap_or(&line);
+        match parser.push(line) {
+            (FastaLine::Header { .. }, Some(record)) => out.push((record.name, record.length, std::mem::take(&mut bases))),
+            (FastaLine::Sequence(line), _) if parser.current().is_some() => bases += scan::count_bases(line),
+            _ => {}
+        }
+    }
+    out.extend(parser.finish().map(|record| (record.name, record.length, bases)));
+    Ok(out)
+}
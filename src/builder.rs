@@ -0,0 +1,243 @@
+//! Chained construction of [`IndexGen`] runs.
+//!
+//! Every option gets a setter here, so JS callers only name the options they
+//! change and new ones never shift a positional argument:
+//!
+//! ```js
+//! const out = new IndexGenBuilder().compression_level(9).index_format("both").run(faFile, gffFile);
+//! ```
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::js_error;
+use crate::health;
+use crate::htslib::{BinRollup, FaiOffsets};
+use crate::input::InputSource;
+use crate::options::{CircularPolicy, Collation, ControlCharPolicy, IndexFormat, IndexGenOptions, MalformedRecordPolicy, OutputContainer, TrackFormat};
+use crate::IndexGen;
+
+/// Builder for [`IndexGen`].  Setters take and return the builder, so in JS
+/// the previous handle is consumed by each call; keep the returned one.
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default)]
+pub struct IndexGenBuilder {
+    options: IndexGenOptions,
+}
+
+#[wasm_bindgen]
+impl IndexGenBuilder {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start from an existing options object.
+    pub fn from_options(options: &IndexGenOptions) -> Self {
+        IndexGenBuilder { options: options.clone() }
+    }
+
+    /// A copy of the options set so far.
+    pub fn options(&self) -> IndexGenOptions {
+        self.options.clone()
+    }
+
+    pub fn compression_level(mut self, level: u32) -> Self {
+        self.options.compression_level = level;
+        self
+    }
+
+    pub fn search_keys(mut self, keys: Vec<String>) -> Self {
+        self.options.search_keys = keys;
+        self
+    }
+
+    /// Build the search index over `DEFAULT_SEARCH_KEYS`.
+    pub fn default_search_keys(mut self) -> Self {
+        self.options = self.options.with_default_search_keys();
+        self
+    }
+
+    pub fn attribute_whitelist(mut self, keys: Vec<String>) -> Self {
+        self.options.attribute_whitelist = keys;
+        self
+    }
+
+    pub fn attribute_blacklist(mut self, keys: Vec<String>) -> Self {
+        self.options.attribute_blacklist = keys;
+        self
+    }
+
+    pub fn index_format(mut self, format: IndexFormat) -> Self {
+        self.options.index_format = format;
+        self
+    }
+
+    pub fn fasta_output(mut self, container: OutputContainer) -> Self {
+        self.options.fasta_output = container;
+        self
+    }
+
+    pub fn gff_output(mut self, container: OutputContainer) -> Self {
+        self.options.gff_output = container;
+        self
+    }
+
+    pub fn gff_sort_keys(mut self, keys: Vec<String>) -> Self {
+        self.options.gff_sort_keys = keys;
+        self
+    }
+
+    pub fn gff_type_priority(mut self, types: Vec<String>) -> Self {
+        self.options.gff_type_priority = types;
+        self
+    }
+
+    pub fn gff_collation(mut self, collation: Collation) -> Self {
+        self.options.gff_collation = collation;
+        self
+    }
+
+    pub fn repair_coordinates(mut self, repair: bool) -> Self {
+        self.options.repair_coordinates = repair;
+        self
+    }
+
+    pub fn control_chars(mut self, policy: ControlCharPolicy) -> Self {
+        self.options.control_chars = policy;
+        self
+    }
+
+    pub fn malformed_records(mut self, policy: MalformedRecordPolicy) -> Self {
+        self.options.malformed_records = policy;
+        self
+    }
+
+    pub fn circular_features(mut self, policy: CircularPolicy) -> Self {
+        self.options.circular_features = policy;
+        self
+    }
+
+    pub fn rewrap_irregular(mut self, rewrap: bool) -> Self {
+        self.options.rewrap_irregular = rewrap;
+        self
+    }
+
+    pub fn strip_cr(mut self, strip: bool) -> Self {
+        self.options.strip_cr = strip;
+        self
+    }
+
+    pub fn fai_offsets(mut self, offsets: FaiOffsets) -> Self {
+        self.options.fai_offsets = offsets;
+        self
+    }
+
+    pub fn bin_rollup(mut self, rollup: BinRollup) -> Self {
+        self.options.bin_rollup = rollup;
+        self
+    }
+
+    pub fn gap_track(mut self, format: TrackFormat) -> Self {
+        self.options.gap_track = format;
+        self
+    }
+
+    pub fn gap_min_length(mut self, min_length: u32) -> Self {
+        self.options.gap_min_length = min_length;
+        self
+    }
+
+    pub fn repeat_track(mut self, format: TrackFormat) -> Self {
+        self.options.repeat_track = format;
+        self
+    }
+
+    pub fn homopolymer_min_length(mut self, min_length: u32) -> Self {
+        self.options.homopolymer_min_length = min_length;
+        self
+    }
+
+    pub fn tandem_min_length(mut self, min_length: u32) -> Self {
+        self.options.tandem_min_length = min_length;
+        self
+    }
+
+    pub fn orf_track(mut self, format: TrackFormat) -> Self {
+        self.options.orf_track = format;
+        self
+    }
+
+    pub fn orf_min_length(mut self, min_length: u32) -> Self {
+        self.options.orf_min_length = min_length;
+        self
+    }
+
+    pub fn rna_track(mut self, format: TrackFormat) -> Self {
+        self.options.rna_track = format;
+        self
+    }
+
+    pub fn genetic_code(mut self, table: u32) -> Self {
+        self.options.genetic_code = table;
+        self
+    }
+
+    pub fn fasta_transforms(mut self, specs: Vec<String>) -> Self {
+        self.options.fasta_transforms = specs;
+        self
+    }
+
+    pub fn gff_transforms(mut self, specs: Vec<String>) -> Self {
+        self.options.gff_transforms = specs;
+        self
+    }
+
+    pub fn replicon_metadata(mut self, tsv: String) -> Self {
+        self.options.replicon_metadata = tsv;
+        self
+    }
+
+    pub fn verify_written_crc(mut self, verify: bool) -> Self {
+        self.options.verify_written_crc = verify;
+        self
+    }
+
+    pub fn webcrypto_hashing(mut self, offload: bool) -> Self {
+        self.options.webcrypto_hashing = offload;
+        self
+    }
+
+    pub fn parallel_branches(mut self, parallel: bool) -> Self {
+        self.options.parallel_branches = parallel;
+        self
+    }
+
+    pub fn seed(mut self, seed: u32) -> Self {
+        self.options.seed = seed;
+        self
+    }
+
+    /// Preprocess two browser `File`s with the options set.
+    pub fn run(&self, fa_file: web_sys::File, gff_file: web_sys::File) -> Result<IndexGen, JsValue> {
+        health::contain(|| IndexGen::from_sources(InputSource::File(fa_file), InputSource::File(gff_file), &self.options))
+            .map_err(js_error)
+    }
+
+    /// Like [`IndexGenBuilder::run`], keeping the outputs of the stage that
+    /// succeeded when the other fails; see `IndexGen.partial`.
+    pub fn run_partial(&self, fa_file: web_sys::File, gff_file: web_sys::File) -> Result<IndexGen, JsValue> {
+        IndexGen::partial(fa_file, gff_file, Some(self.options.clone()))
+    }
+
+    /// Preprocess two `Buffer`s / `Uint8Array`s (plain or gzip) with the
+    /// options set.
+    pub fn run_buffers(&self, fa: Vec<u8>, gff: Vec<u8>) -> Result<IndexGen, JsValue> {
+        IndexGen::from_buffers(fa, gff, Some(self.options.clone()))
+    }
+
+    /// Index two files that are already BGZF (the GFF3 sorted) with the
+    /// options set; see `IndexGen.indexes_only`.
+    pub fn run_indexes_only(&self, fa_bgz_file: web_sys::File, gff_bgz_file: web_sys::File) -> Result<IndexGen, JsValue> {
+        IndexGen::indexes_only(fa_bgz_file, gff_bgz_file, Some(self.options.clone()))
+    }
+}
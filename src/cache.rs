@@ -0,0 +1,408 @@
+//! Content-addressed cache of pipeline outputs in the Origin Private File
+//! System (OPFS).
+//!
+//! An entry is keyed on the SHA-256 of both (decompressed) inputs plus the
+//! options digest, so re-processing the same files with the same options can
+//! short-circuit to the stored artifacts.  Layout:
+//!
+//! ```text
+//! <OPFS root>/mgnify-wasm-cache/<key>/meta.json
+//!                                    /<artifact name>   (one file per output)
+//! ```
+//!
+//! The same helpers back [`spill`], which moves a result's outputs out of
+//! wasm memory into `<OPFS root>/mgnify-wasm-spill/<id>/`.
+//!
+//! With an AES-GCM key set by [`cache_set_key`], entries are stored
+//! encrypted through WebCrypto, `meta.json` included.  An encrypted file is
+//! [`SEALED_MAGIC`] followed by one record per [`WRITE_CHUNK`] of
+//! plaintext: a random 12-byte IV and the ciphertext with its 16-byte tag.
+//! Each record is authenticated with `<key>/<file>/<index>/<more|last>`, so
+//! records cannot be moved between files or entries, reordered, or cut off
+//! at a record boundary.  Reading decrypts transparently; an entry that
+//! cannot be decrypted with the current key (or without one) is a cache
+//! miss.  Spilled outputs are handed out as plain `File`s and never
+//! encrypted.
+//!
+//! The cache lives in OPFS only; there is no IndexedDB fallback, so where
+//! OPFS is missing the cached entry points fail with `E_CACHE`.
+
+use std::cell::RefCell;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+use crate::error::{js_error_code, ErrorCode};
+use crate::hash::{sha256_hex, subtle_crypto, web_crypto};
+
+const CACHE_DIR: &str = "mgnify-wasm-cache";
+const SPILL_DIR: &str = "mgnify-wasm-spill";
+const META_FILE: &str = "meta.json";
+/// Bytes copied to JS per write, so no output is ever copied whole.
+const WRITE_CHUNK: usize = 8 << 20;
+/// First bytes of an encrypted cache file.
+const SEALED_MAGIC: &[u8] = b"MGWCAES1";
+const IV_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+/// Bytes of a full encrypted record.
+const SEALED_RECORD: usize = IV_LEN + WRITE_CHUNK + TAG_LEN;
+
+thread_local! {
+    static CACHE_KEY: RefCell<Option<web_sys::CryptoKey>> = const { RefCell::new(None) };
+}
+
+/// Cache key for a pair of inputs processed with the given options digest.
+pub fn cache_key(fasta_sha256: &str, gff_sha256: &str, options_digest: &str) -> String {
+    sha256_hex(format!("fasta={}\ngff={}\noptions={}\n", fasta_sha256, gff_sha256, options_digest).as_bytes())
+}
+
+// ---------------------------------------------------------------------------
+// OPFS helpers
+// ---------------------------------------------------------------------------
+
+fn storage_manager() -> Result<web_sys::StorageManager, JsValue> {
+    let global = js_sys::global();
+    if let Some(scope) = global.dyn_ref::<web_sys::WorkerGlobalScope>() {
+        Ok(scope.navigator().storage())
+    } else if let Some(window) = global.dyn_ref::<web_sys::Window>() {
+        Ok(window.navigator().storage())
+    } else {
+        Err(js_error_code(ErrorCode::Cache, "OPFS is not available in this context"))
+    }
+}
+
+async fn opfs_dir(name: &str) -> Result<web_sys::FileSystemDirectoryHandle, JsValue> {
+    let root: web_sys::FileSystemDirectoryHandle = JsFuture::from(storage_manager()?.get_directory()).await?.dyn_into()?;
+    subdir(&root, name, true).await
+}
+
+async fn cache_root() -> Result<web_sys::FileSystemDirectoryHandle, JsValue> {
+    opfs_dir(CACHE_DIR).await
+}
+
+async fn subdir(
+    parent: &web_sys::FileSystemDirectoryHandle,
+    name: &str,
+    create: bool,
+) -> Result<web_sys::FileSystemDirectoryHandle, JsValue> {
+    let opts = web_sys::FileSystemGetDirectoryOptions::new();
+    opts.set_create(create);
+    JsFuture::from(parent.get_directory_handle_with_options(name, &opts)).await?.dyn_into()
+}
+
+/// Write `data` to the file `name` of `dir`, [`WRITE_CHUNK`] bytes at a
+/// time, encrypted for the entry `sealed.1` when `sealed` is given;
+/// returns the file handle.
+async fn write_file(
+    dir: &web_sys::FileSystemDirectoryHandle,
+    name: &str,
+    data: &[u8],
+    sealed: Option<(&Sealer, &str)>,
+) -> Result<web_sys::FileSystemFileHandle, JsValue> {
+    let opts = web_sys::FileSystemGetFileOptions::new();
+    opts.set_create(true);
+    let handle: web_sys::FileSystemFileHandle =
+        JsFuture::from(dir.get_file_handle_with_options(name, &opts)).await?.dyn_into()?;
+    let stream: web_sys::FileSystemWritableFileStream = JsFuture::from(handle.create_writable()).await?.dyn_into()?;
+    let write = |bytes: &[u8]| stream.write_with_js_u8_array(&js_sys::Uint8Array::from(bytes));
+    match sealed {
+        None => {
+            for chunk in data.chunks(WRITE_CHUNK) {
+                JsFuture::from(write(chunk)?).await?;
+            }
+        }
+        Some((sealer, entry)) => {
+            JsFuture::from(write(SEALED_MAGIC)?).await?;
+            let records = data.len().div_ceil(WRITE_CHUNK).max(1);
+            for index in 0..records {
+                let chunk = &data[index * WRITE_CHUNK..data.len().min((index + 1) * WRITE_CHUNK)];
+                let record = sealer.seal(&record_aad(entry, name, index, index + 1 == records), chunk).await?;
+                JsFuture::from(write(&record)?).await?;
+            }
+        }
+    }
+    JsFuture::from(stream.close()).await?;
+    Ok(handle)
+}
+
+async fn get_file(dir: &web_sys::FileSystemDirectoryHandle, name: &str) -> Result<web_sys::File, JsValue> {
+    let handle: web_sys::FileSystemFileHandle = JsFuture::from(dir.get_file_handle(name)).await?.dyn_into()?;
+    JsFuture::from(handle.get_file()).await?.dyn_into()
+}
+
+/// Bytes `[start, end)` of `blob`.
+async fn blob_range(blob: &web_sys::Blob, start: usize, end: usize) -> Result<Vec<u8>, JsValue> {
+    let buf = JsFuture::from(blob.slice_with_f64_and_f64(start as f64, end as f64)?.array_buffer()).await?;
+    Ok(js_sys::Uint8Array::new(&buf).to_vec())
+}
+
+async fn is_sealed(file: &web_sys::File) -> Result<bool, JsValue> {
+    Ok(blob_range(file, 0, SEALED_MAGIC.len()).await? == SEALED_MAGIC)
+}
+
+/// The contents of the file `name` of the entry `entry` in `dir`,
+/// decrypted record by record if it is encrypted.  Fails for an encrypted
+/// file without a key, or one the key does not open.
+async fn read_file(dir: &web_sys::FileSystemDirectoryHandle, entry: &str, name: &str) -> Result<Vec<u8>, JsValue> {
+    let file = get_file(dir, name).await?;
+    if !is_sealed(&file).await? {
+        return blob_range(&file, 0, file.size() as usize).await;
+    }
+    let sealer = Sealer::current()?.ok_or_else(|| js_error_code(ErrorCode::Cache, &format!("cache entry {} is encrypted; set its key with cache_set_key", entry)))?;
+    let size = file.size() as usize;
+    let mut out = Vec::new();
+    let mut start = SEALED_MAGIC.len();
+    for index in 0.. {
+        let end = size.min(start + SEALED_RECORD);
+        let last = end == size;
+        let record = blob_range(&file, start, end).await?;
+        out.extend(sealer.open(&record_aad(entry, name, index, last), &record).await?);
+        if last {
+            break;
+        }
+        start = end;
+    }
+    Ok(out)
+}
+
+/// Names of the entries (sub-directories) of `dir`.
+async fn dir_names(dir: &web_sys::FileSystemDirectoryHandle) -> Result<Vec<String>, JsValue> {
+    let iter = dir.keys();
+    let mut names = Vec::new();
+    loop {
+        let next: js_sys::IteratorNext = JsFuture::from(iter.next()?).await?.unchecked_into();
+        if next.done() {
+            break;
+        }
+        if let Some(name) = next.value().as_string() {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+// ---------------------------------------------------------------------------
+// Encryption at rest
+// ---------------------------------------------------------------------------
+
+/// Associated data of record `index` of the file `name` of the entry `entry`.
+fn record_aad(entry: &str, name: &str, index: usize, last: bool) -> String {
+    format!("{}/{}/{}/{}", entry, name, index, if last { "last" } else { "more" })
+}
+
+/// AES-GCM through WebCrypto under the key set by [`cache_set_key`].
+struct Sealer {
+    crypto: web_sys::Crypto,
+    subtle: web_sys::SubtleCrypto,
+    key: web_sys::CryptoKey,
+}
+
+impl Sealer {
+    /// The sealer for the current key, `None` when there is none.
+    fn current() -> Result<Option<Sealer>, JsValue> {
+        let Some(key) = CACHE_KEY.with(|k| k.borrow().clone()) else {
+            return Ok(None);
+        };
+        let unavailable = || js_error_code(ErrorCode::Cache, "encrypting the cache needs WebCrypto (a secure context)");
+        Ok(Some(Sealer { crypto: web_crypto().ok_or_else(unavailable)?, subtle: subtle_crypto().ok_or_else(unavailable)?, key }))
+    }
+
+    fn params(iv: &[u8], aad: &str) -> Result<js_sys::Object, JsValue> {
+        let params = js_sys::Object::new();
+        js_sys::Reflect::set(&params, &"name".into(), &"AES-GCM".into())?;
+        js_sys::Reflect::set(&params, &"iv".into(), &js_sys::Uint8Array::from(iv))?;
+        js_sys::Reflect::set(&params, &"additionalData".into(), &js_sys::Uint8Array::from(aad.as_bytes()))?;
+        Ok(params)
+    }
+
+    /// A random IV followed by `chunk` encrypted under it.
+    async fn seal(&self, aad: &str, chunk: &[u8]) -> Result<Vec<u8>, JsValue> {
+        let mut iv = [0u8; IV_LEN];
+        self.crypto.get_random_values_with_u8_array(&mut iv)?;
+        let sealed = JsFuture::from(self.subtle.encrypt_with_object_and_u8_array(&Self::params(&iv, aad)?, &self.key, chunk)?).await?;
+        let mut record = iv.to_vec();
+        record.extend(js_sys::Uint8Array::new(&sealed).to_vec());
+        Ok(record)
+    }
+
+    /// The plaintext of a record [`Sealer::seal`] wrote.
+    async fn open(&self, aad: &str, record: &[u8]) -> Result<Vec<u8>, JsValue> {
+        if record.len() < IV_LEN + TAG_LEN {
+            return Err(js_error_code(ErrorCode::Cache, "truncated encrypted cache file"));
+        }
+        let (iv, sealed) = record.split_at(IV_LEN);
+        let plain = JsFuture::from(self.subtle.decrypt_with_object_and_u8_array(&Self::params(iv, aad)?, &self.key, sealed)?)
+            .await
+            .map_err(|_| js_error_code(ErrorCode::Cache, "cache file does not decrypt with the current key"))?;
+        Ok(js_sys::Uint8Array::new(&plain).to_vec())
+    }
+}
+
+/// Encrypt cache entries stored from now on with `key`, an AES-GCM
+/// `CryptoKey` allowed to `encrypt` and `decrypt` (from
+/// `crypto.subtle.generateKey` or `importKey`), and decrypt entries with it
+/// on read-back.  `undefined` goes back to storing plain entries.  Throws
+/// `E_INVALID_OPTION` for any other key.
+#[wasm_bindgen]
+pub fn cache_set_key(key: Option<web_sys::CryptoKey>) -> Result<(), JsValue> {
+    if let Some(key) = &key {
+        let algorithm = key.algorithm().ok().and_then(|a| js_sys::Reflect::get(&a, &"name".into()).ok()).and_then(|n| n.as_string());
+        let usable = || {
+            let usages: Vec<String> = key.usages().iter().filter_map(|u| u.as_string()).collect();
+            ["encrypt", "decrypt"].iter().all(|u| usages.iter().any(|v| v == u))
+        };
+        if algorithm.as_deref() != Some("AES-GCM") || !usable() {
+            return Err(js_error_code(ErrorCode::InvalidOption, "the cache key must be an AES-GCM CryptoKey usable to encrypt and decrypt"));
+        }
+    }
+    CACHE_KEY.with(|k| *k.borrow_mut() = key);
+    Ok(())
+}
+
+/// Whether entries stored now are encrypted.
+#[wasm_bindgen]
+pub fn cache_encrypted() -> bool {
+    CACHE_KEY.with(|k| k.borrow().is_some())
+}
+
+// ---------------------------------------------------------------------------
+// Entry storage
+// ---------------------------------------------------------------------------
+
+/// Store `artifacts` under `key`, replacing any previous entry.
+pub async fn store(key: &str, artifacts: &[(&str, &[u8])], mut meta: json::JsonValue) -> Result<(), JsValue> {
+    let sealer = Sealer::current()?;
+    let sealed = sealer.as_ref().map(|s| (s, key));
+    let root = cache_root().await?;
+    let dir = subdir(&root, key, true).await?;
+    let mut size = 0u64;
+    for (name, data) in artifacts {
+        write_file(&dir, name, data, sealed).await?;
+        size += data.len() as u64;
+    }
+    meta["key"] = key.into();
+    meta["created_ms"] = js_sys::Date::now().into();
+    meta["size"] = size.into();
+    meta["artifacts"] = artifacts.iter().map(|(name, _)| *name).collect::<Vec<_>>().into();
+    meta["encrypted"] = sealer.is_some().into();
+    // meta.json is written last: an entry without it is incomplete and ignored.
+    write_file(&dir, META_FILE, meta.dump().as_bytes(), sealed).await?;
+    Ok(())
+}
+
+/// The `meta.json` of the entry `key`, or `None` on a cache miss.
+pub async fn load_meta(key: &str) -> Result<Option<json::JsonValue>, JsValue> {
+    let root = cache_root().await?;
+    let Ok(dir) = subdir(&root, key, false).await else {
+        return Ok(None);
+    };
+    let Ok(meta) = read_file(&dir, key, META_FILE).await else {
+        return Ok(None);
+    };
+    Ok(json::parse(&String::from_utf8_lossy(&meta)).ok())
+}
+
+/// Load the artifacts `names` stored under `key`, or `None` on a cache miss.
+pub async fn load(key: &str, names: &[&str]) -> Result<Option<Vec<Vec<u8>>>, JsValue> {
+    let root = cache_root().await?;
+    let Ok(dir) = subdir(&root, key, false).await else {
+        return Ok(None);
+    };
+    if read_file(&dir, key, META_FILE).await.is_err() {
+        return Ok(None);
+    }
+    let mut out = Vec::with_capacity(names.len());
+    for name in names {
+        match read_file(&dir, key, name).await {
+            Ok(data) => out.push(data),
+            Err(_) => return Ok(None),
+        }
+    }
+    Ok(Some(out))
+}
+
+// ---------------------------------------------------------------------------
+// WASM-bindgen exports: inspection and eviction
+// ---------------------------------------------------------------------------
+
+/// List cache entries as a JSON array of their `meta.json` objects
+/// (`key`, `created_ms`, `size`, `artifacts`, `encrypted`, input hashes,
+/// options digest).  An encrypted entry the current key does not open is
+/// listed as `{key, encrypted: true}`.  Anything in the cache directory
+/// that is not a directory is no entry and is skipped.
+#[wasm_bindgen]
+pub async fn cache_list() -> Result<String, JsValue> {
+    let root = cache_root().await?;
+    let mut entries = json::JsonValue::new_array();
+    for name in dir_names(&root).await? {
+        let Ok(dir) = subdir(&root, &name, false).await else {
+            continue;
+        };
+        match read_file(&dir, &name, META_FILE).await {
+            Ok(meta) => {
+                if let Ok(meta) = json::parse(&String::from_utf8_lossy(&meta)) {
+                    let _ = entries.push(meta);
+                }
+            }
+            Err(_) => {
+                if let Ok(file) = get_file(&dir, META_FILE).await {
+                    if is_sealed(&file).await? {
+                        let _ = entries.push(json::object! { key: name.as_str(), encrypted: true });
+                    }
+                }
+            }
+        }
+    }
+    Ok(entries.dump())
+}
+
+/// Remove one cache entry. Resolves to `true` if it existed.
+#[wasm_bindgen]
+pub async fn cache_evict(key: String) -> Result<bool, JsValue> {
+    let root = cache_root().await?;
+    let opts = web_sys::FileSystemRemoveOptions::new();
+    opts.set_recursive(true);
+    Ok(JsFuture::from(root.remove_entry_with_options(&key, &opts)).await.is_ok())
+}
+
+/// Remove every cache entry.
+#[wasm_bindgen]
+pub async fn cache_clear() -> Result<(), JsValue> {
+    let root = cache_root().await?;
+    let opts = web_sys::FileSystemRemoveOptions::new();
+    opts.set_recursive(true);
+    for name in dir_names(&root).await? {
+        JsFuture::from(root.remove_entry_with_options(&name, &opts)).await?;
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Spilled outputs
+// ---------------------------------------------------------------------------
+
+/// Write `artifacts` to OPFS files under `mgnify-wasm-spill/<id>` and return
+/// them as `File`s, which browsers read from disk rather than memory.
+pub async fn spill(id: &str, artifacts: &[(&str, &[u8])]) -> Result<Vec<web_sys::File>, JsValue> {
+    let dir = subdir(&opfs_dir(SPILL_DIR).await?, id, true).await?;
+    let mut files = Vec::with_capacity(artifacts.len());
+    for (name, data) in artifacts {
+        let handle = write_file(&dir, name, data, None).await?;
+        files.push(JsFuture::from(handle.get_file()).await?.dyn_into()?);
+    }
+    Ok(files)
+}
+
+/// Remove every file written by `IndexGen.spill_to_opfs()`.  `File`s handed
+/// out for them cannot be read afterwards.
+#[wasm_bindgen]
+pub async fn spill_clear() -> Result<(), JsValue> {
+    let root = opfs_dir(SPILL_DIR).await?;
+    let opts = web_sys::FileSystemRemoveOptions::new();
+    opts.set_recursive(true);
+    for name in dir_names(&root).await? {
+        JsFuture::from(root.remove_entry_with_options(&name, &opts)).await?;
+    }
+    Ok(())
+}
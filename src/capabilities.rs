@@ -0,0 +1,83 @@
+//! What this build can do, for front ends to feature-detect instead of
+//! assuming: `capabilities()` returns
+//!
+//! ```text
+//! {"name":"mgnify-wasm","version":"0.1.0","options_schema_version":1,
+//!  "features":{"threads":false,"reference":false},"simd":"simd128","pointer_width":32,
+//!  "input_budget":3221225472,"formats":{…},"options":{"compression_level":"0-9",…}}
+//! ```
+//!
+//! `options` has one entry per `IndexGenOptions` field with its accepted
+//! values; `input_budget` is `null` when the build has none.
+//! `shared_input` is whether the calling context can take
+//! `SharedArrayBuffer` input (see `input/shared.rs`).
+
+use wasm_bindgen::prelude::*;
+
+use crate::input::shared_memory_available;
+use crate::limits::input_budget;
+use crate::options::{IndexGenOptions, OPTIONS_SCHEMA_VERSION};
+use crate::scan;
+use crate::transform::{FASTA_TRANSFORMS, GFF_TRANSFORMS};
+use crate::translate::GeneticCode;
+
+/// Accepted values of the option `name`: a list, or a description.
+fn option_values(name: &str) -> json::JsonValue {
+    match name {
+        "compression_level" => "0-9".into(),
+        "index_format" => json::array!["csi", "tbi", "both"],
+        "fasta_output" | "gff_output" => json::array!["bgzf", "gzip"],
+        "control_chars" => json::array!["error", "strip", "replace"],
+        "malformed_records" => json::array!["error", "drop"],
+        "circular_features" => json::array!["split", "clamp", "error"],
+        "fai_offsets" => json::array!["uncompressed", "virtual"],
+        "bin_rollup" => json::array!["htslib", "aggressive"],
+        "gap_track" | "repeat_track" | "orf_track" | "rna_track" => json::array!["none", "bed", "gff"],
+        "gap_min_length" | "homopolymer_min_length" | "tandem_min_length" | "orf_min_length" => "1-4294967295".into(),
+        "gff_sort_keys" => json::array!["type", "strand"],
+        "gff_collation" => json::array!["bytewise", "dictionary", "natural"],
+        "repair_coordinates" | "rewrap_irregular" | "strip_cr" | "verify_written_crc" | "webcrypto_hashing" | "parallel_branches" => "boolean".into(),
+        "seed" => "0-4294967295".into(),
+        "genetic_code" => json::JsonValue::from(GeneticCode::ids().collect::<Vec<_>>()),
+        "fasta_transforms" => json::JsonValue::from(FASTA_TRANSFORMS.to_vec()),
+        "gff_transforms" => json::JsonValue::from(GFF_TRANSFORMS.to_vec()),
+        "replicon_metadata" => "TSV: contig, replicon type, linear|circular".into(),
+        _ => "list of strings".into(),
+    }
+}
+
+/// [`capabilities`] as a JSON value.
+pub fn capabilities_json() -> json::JsonValue {
+    let mut options = json::JsonValue::new_object();
+    for name in IndexGenOptions::default().field_names() {
+        options[name.as_str()] = option_values(&name);
+    }
+    let budget = input_budget();
+    json::object! {
+        name: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        options_schema_version: OPTIONS_SCHEMA_VERSION,
+        features: {
+            threads: cfg!(feature = "threads"),
+            reference: cfg!(feature = "reference"),
+        },
+        simd: scan::backend(),
+        pointer_width: usize::BITS,
+        input_budget: if budget == u64::MAX { json::JsonValue::Null } else { budget.into() },
+        shared_input: shared_memory_available(),
+        formats: {
+            input: ["fasta", "gff3", "gtf", "gzip", "bgzf"],
+            output: ["bgzf", "gzip"],
+            indexes: ["fai", "gzi", "csi", "tbi", "gsi"],
+            exports: ["embl", "ndjson", "jbrowse2", "igv", "styles", "manifest"],
+        },
+        options: options,
+    }
+}
+
+/// Version, cargo features, formats and option schema of this build, as
+/// JSON (see the module docs).
+#[wasm_bindgen]
+pub fn capabilities() -> String {
+    capabilities_json().dump()
+}
@@ -0,0 +1,374 @@
+//! Cooperative runs, for hosts that call the wasm on the main thread.
+//!
+//! [`CooperativeRun`] is the pipeline of [`IndexGen::from_buffers`] as a
+//! sequence of small units of work: 64 KiB of compression, or
+//! [`LINES_PER_UNIT`] lines of `.fai`/`.csi`/`.tbi` indexing.  Each
+//! [`CooperativeRun::step`] runs units until its time slice is used up and
+//! returns, so the caller can yield to the event loop in between:
+//!
+//! ```js
+//! const run = IndexGen.start_cooperative(fa, gff, options, 15);
+//! while (!run.step()) await new Promise((r) => setTimeout(r));
+//! const out = run.finish();
+//! ```
+//!
+//! A unit that has started runs to its end, so a slice overruns by at most
+//! one unit.  Steps with nothing to split them at run whole, in one slice
+//! each: decompressing a gzip input, the control character scrub and the
+//! transform pipeline, sorting the GFF3, checking the `.fai` against the
+//! `.gzi`, and the search index.  The outputs and warnings are those of
+//! [`IndexGen::from_buffers`]; the branches run one after the other.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::{self, Cursor};
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{io_error, js_error, ErrorCode};
+use crate::fasta::split::ContigMap;
+use crate::fasta::track::SequenceTrack;
+use crate::htslib::{verify_fasta_index, BgzfReader, BgzfWriter, FaiOffsets, FaidxIndexer, GzipWriter, TabixConfig, TabixIndexer};
+use crate::input::{read_pair, InputSource};
+use crate::options::{IndexFormat, IndexGenOptions, OutputContainer};
+use crate::progress::{now_ms, ContigProgress, Extent};
+use crate::transform::unique_ids::IdRenames;
+use crate::transform::Pipelines;
+use crate::warnings::Warnings;
+use crate::{gff, health, logw, progress, search, GffBranch, IndexGen};
+
+/// Time slice of a step when none is given.
+pub const DEFAULT_SLICE_MS: f64 = 15.0;
+
+/// Lines indexed per unit of work.
+pub const LINES_PER_UNIT: usize = 1024;
+
+/// Bytes compressed per unit of work.
+const BYTES_PER_UNIT: usize = 0x10000;
+
+/// Compressor of one output, as `fasta_output`/`gff_output` select.
+enum Compressor {
+    Bgzf(BgzfWriter<Vec<u8>>),
+    Gzip(GzipWriter<Vec<u8>>),
+}
+
+impl Compressor {
+    fn new(container: OutputContainer, level: u32) -> Self {
+        match container {
+            OutputContainer::Gzip => Compressor::Gzip(GzipWriter::with_level(Vec::new(), level)),
+            _ => Compressor::Bgzf(BgzfWriter::with_level(Vec::new(), level)),
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        match self {
+            Compressor::Bgzf(w) => io::Write::write_all(w, data),
+            Compressor::Gzip(w) => io::Write::write_all(w, data),
+        }
+    }
+
+    fn finish(self) -> io::Result<Vec<u8>> {
+        match self {
+            Compressor::Bgzf(w) => w.finish(),
+            Compressor::Gzip(w) => w.finish(),
+        }
+    }
+}
+
+/// Where a run is, with the data that step works on.
+enum Phase {
+    Read { fa: Vec<u8>, gff: Vec<u8> },
+    PrepareFasta { fa: Vec<u8>, gff: Vec<u8> },
+    CompressFasta { fa: Vec<u8>, gff: Vec<u8>, writer: Compressor, at: usize },
+    IndexFasta { gff: Vec<u8>, indexer: FaidxIndexer<Cursor<Vec<u8>>, Vec<u8>> },
+    PrepareGff { gff: Vec<u8> },
+    SortGff { gff: String },
+    CompressGff { text: Vec<u8>, writer: Compressor, at: usize, contigs: u64 },
+    Csi(TabixIndexer<Cursor<Vec<u8>>>),
+    Tbi(TabixIndexer<Cursor<Vec<u8>>>),
+    Search(Vec<u8>),
+    Done(Box<IndexGen>),
+    /// A step failed, or the result was taken.
+    Over,
+}
+
+impl Phase {
+    fn name(&self) -> &'static str {
+        match self {
+            Phase::Read { .. } => "read",
+            Phase::PrepareFasta { .. } => "fasta_prepare",
+            Phase::CompressFasta { .. } => "fasta_compress",
+            Phase::IndexFasta { .. } => "faidx",
+            Phase::PrepareGff { .. } => "gff_prepare",
+            Phase::SortGff { .. } => "gff_sort",
+            Phase::CompressGff { .. } => "gff_compress",
+            Phase::Csi(_) => "csi",
+            Phase::Tbi(_) => "tbi",
+            Phase::Search(_) => "search",
+            Phase::Done(_) => "done",
+            Phase::Over => "over",
+        }
+    }
+}
+
+/// A pipeline run a time slice at a time (see the module docs).
+#[wasm_bindgen]
+pub struct CooperativeRun {
+    options: IndexGenOptions,
+    pipelines: Pipelines,
+    slice_ms: f64,
+    phase: Phase,
+    warnings: Warnings,
+    /// Where `split_at_gaps` put each contig, for the GFF3 pipeline.
+    contigs: ContigMap,
+    /// What `unique_ids` renamed.
+    id_renames: IdRenames,
+    tracks: BTreeMap<&'static str, SequenceTrack>,
+    fasta: (Vec<u8>, Vec<u8>, Vec<u8>),
+    gff: GffBranch,
+    /// What is known of the sorted GFF3, for index progress.
+    gff_extent: Extent,
+}
+
+#[wasm_bindgen]
+impl CooperativeRun {
+    /// Run units of work for up to the time slice; true once the run is
+    /// complete.  Throws the error of a failing step, and
+    /// `E_INVALID_OPTION` when called again after one.
+    pub fn step(&mut self) -> Result<bool, JsValue> {
+        health::contain(|| self.run_slice().map_err(js_error))
+    }
+
+    /// The step the run is at: `read`, `fasta_prepare`, `fasta_compress`,
+    /// `faidx`, `gff_prepare`, `gff_sort`, `gff_compress`, `csi`, `tbi`,
+    /// `search`, then `done`.
+    pub fn stage(&self) -> String {
+        self.phase.name().to_owned()
+    }
+
+    /// The outputs of a complete run.  Throws `E_INVALID_OPTION` before
+    /// [`CooperativeRun::step`] returned true, or if they were taken.
+    pub fn finish(&mut self) -> Result<IndexGen, JsValue> {
+        self.take_result().map_err(js_error)
+    }
+}
+
+impl CooperativeRun {
+    /// A run over `fa` and `gff` (plain or gzip, as
+    /// [`IndexGen::from_buffers`] takes them), stepping for `slice_ms` at a
+    /// time.  Nothing is read until the first step.
+    pub fn start(fa: Vec<u8>, gff: Vec<u8>, options: &IndexGenOptions, slice_ms: f64) -> io::Result<Self> {
+        if !(slice_ms.is_finite() && slice_ms > 0.0) {
+            return Err(io_error(ErrorCode::InvalidOption, format!("time slice {} ms is not a positive duration", slice_ms)));
+        }
+        let mut warnings = Warnings::default();
+        crate::check_options(options, &mut warnings);
+        Ok(CooperativeRun {
+            options: options.clone(),
+            pipelines: Pipelines::from_options(options)?,
+            slice_ms,
+            phase: Phase::Read { fa, gff },
+            warnings,
+            contigs: ContigMap::default(),
+            id_renames: IdRenames::default(),
+            tracks: BTreeMap::new(),
+            fasta: Default::default(),
+            gff: GffBranch::default(),
+            gff_extent: Extent::default(),
+        })
+    }
+
+    /// [`CooperativeRun::step`] natively.
+    pub fn run_slice(&mut self) -> io::Result<bool> {
+        let deadline = now_ms() + self.slice_ms;
+        loop {
+            match self.phase {
+                Phase::Done(_) => return Ok(true),
+                Phase::Over => return Err(io_error(ErrorCode::InvalidOption, "the run is over: a step failed or its outputs were taken")),
+                _ => {}
+            }
+            let phase = std::mem::replace(&mut self.phase, Phase::Over);
+            self.phase = self.unit(phase)?;
+            if now_ms() >= deadline {
+                return Ok(matches!(self.phase, Phase::Done(_)));
+            }
+        }
+    }
+
+    /// [`CooperativeRun::finish`] natively.
+    pub fn take_result(&mut self) -> io::Result<IndexGen> {
+        match std::mem::replace(&mut self.phase, Phase::Over) {
+            Phase::Done(out) => Ok(*out),
+            phase => {
+                let message = format!("the run is at {}, not done", phase.name());
+                self.phase = phase;
+                Err(io_error(ErrorCode::InvalidOption, message))
+            }
+        }
+    }
+
+    /// Run one unit of work of `phase`; returns where the run is next.
+    fn unit(&mut self, phase: Phase) -> io::Result<Phase> {
+        let options = &self.options;
+        Ok(match phase {
+            Phase::Read { fa, gff } => {
+                logw("Reading fasta and gff into memory", None);
+                let (fa, gff) = read_pair(InputSource::Bytes(fa), InputSource::Bytes(gff))?;
+                Phase::PrepareFasta { fa, gff }
+            }
+            Phase::PrepareFasta { mut fa, gff } => {
+                if let Some(prepared) = owned(crate::prepare_fasta(&fa, options, &self.pipelines.fasta, &mut self.warnings, &mut self.contigs)?, fa.len()) {
+                    fa = prepared;
+                }
+                self.tracks = crate::fasta_tracks(&fa, options)?;
+                let writer = match options.fasta_output {
+                    OutputContainer::Gzip => {
+                        logw("Compressing fasta as plain gzip (no index)", None);
+                        Compressor::new(OutputContainer::Gzip, options.compression_level)
+                    }
+                    _ => {
+                        logw("Compressing and indexing fasta", None);
+                        Compressor::new(OutputContainer::Bgzf, options.compression_level)
+                    }
+                };
+                Phase::CompressFasta { fa, gff, writer, at: 0 }
+            }
+            Phase::CompressFasta { fa, gff, mut writer, at } if at < fa.len() => {
+                let end = fa.len().min(at + BYTES_PER_UNIT);
+                writer.write(&fa[at..end])?;
+                Phase::CompressFasta { fa, gff, writer, at: end }
+            }
+            Phase::CompressFasta { fa, gff, writer, .. } => {
+                let gzip = matches!(writer, Compressor::Gzip(_));
+                let bgz = writer.finish()?;
+                if gzip {
+                    self.fasta.0 = bgz;
+                    return Ok(Phase::PrepareGff { gff });
+                }
+                let extent = Extent { bytes: Some(fa.len() as u64), contigs: Some(crate::contig_count(&fa)) };
+                let reader = written(bgz, options).track(ContigProgress::new("faidx", extent));
+                Phase::IndexFasta { gff, indexer: FaidxIndexer::new(reader, Vec::new(), options.fai_offsets) }
+            }
+            Phase::IndexFasta { gff, mut indexer } => {
+                if !indexer.step(LINES_PER_UNIT)? {
+                    return Ok(Phase::IndexFasta { gff, indexer });
+                }
+                let mut gzi = Vec::new();
+                let (fai, reader) = indexer.finish(&mut gzi)?;
+                let bgz = reader.into_inner().into_inner();
+                if options.fai_offsets == FaiOffsets::Uncompressed {
+                    verify_fasta_index(&bgz, &fai, &gzi)
+                        .map_err(|e| io_error(ErrorCode::Internal, format!("internal .fai/.gzi cross-check failed: {}", e)))?;
+                }
+                self.fasta = (bgz, fai, gzi);
+                Phase::PrepareGff { gff }
+            }
+            Phase::PrepareGff { gff } => {
+                let prepared = crate::prepare_gff(&gff, options, &self.pipelines.gff, &mut self.warnings, &mut self.contigs, &mut self.id_renames)?;
+                let gff = match prepared {
+                    Cow::Borrowed(text) if text.len() == gff.len() => String::from_utf8(gff).map_err(|_| io_error(ErrorCode::GffEncoding, "GFF is not valid UTF-8"))?,
+                    prepared => prepared.into_owned(),
+                };
+                Phase::SortGff { gff }
+            }
+            Phase::SortGff { gff } => {
+                let sorted = gff::SortedGff::with_ties(&gff, options.repair_coordinates, &crate::gff_tie_break(options)?, &mut self.warnings);
+                if sorted.was_in_order() {
+                    logw("GFF already in index order; streaming it without sorting", None);
+                }
+                progress::report("gff", 0, None);
+                let writer = match options.gff_output {
+                    OutputContainer::Gzip => {
+                        logw("Compressing gff as plain gzip (no index)", None);
+                        Compressor::new(OutputContainer::Gzip, options.compression_level)
+                    }
+                    _ => {
+                        logw("Compressing and indexing gff", None);
+                        Compressor::new(OutputContainer::Bgzf, options.compression_level)
+                    }
+                };
+                let mut text = Vec::with_capacity(gff.len());
+                self.gff.attribute_bytes_saved = crate::write_sorted_gff(&sorted, options, &mut text)?;
+                Phase::CompressGff { text, writer, at: 0, contigs: sorted.seqid_count() }
+            }
+            Phase::CompressGff { text, mut writer, at, contigs } if at < text.len() => {
+                let end = text.len().min(at + BYTES_PER_UNIT);
+                writer.write(&text[at..end])?;
+                Phase::CompressGff { text, writer, at: end, contigs }
+            }
+            Phase::CompressGff { text, writer, contigs, .. } => {
+                let gzip = matches!(writer, Compressor::Gzip(_));
+                let bgz = writer.finish()?;
+                if gzip {
+                    self.gff.bgz = bgz;
+                    return self.done();
+                }
+                self.gff_extent = Extent { bytes: Some(text.len() as u64), contigs: Some(contigs) };
+                self.index_from(bgz, IndexFormat::Csi)?
+            }
+            Phase::Csi(mut indexer) => {
+                if !indexer.step(LINES_PER_UNIT)? {
+                    return Ok(Phase::Csi(indexer));
+                }
+                let (n_lvls, reader) = indexer.finish(&mut self.gff.csi)?;
+                crate::check_csi(n_lvls, &self.gff.csi, &mut self.warnings)?;
+                self.index_from(reader.into_inner().into_inner(), IndexFormat::Tbi)?
+            }
+            Phase::Tbi(mut indexer) => {
+                if !indexer.step(LINES_PER_UNIT)? {
+                    return Ok(Phase::Tbi(indexer));
+                }
+                let (_, reader) = indexer.finish(&mut self.gff.tbi)?;
+                Phase::Search(reader.into_inner().into_inner())
+            }
+            Phase::Search(bgz) => {
+                if !options.search_keys.is_empty() {
+                    logw("Building attribute search index", None);
+                    self.gff.search = search::build_search_index_reader(written(bgz.as_slice(), options), &options.search_keys)?;
+                }
+                self.gff.bgz = bgz;
+                return self.done();
+            }
+            phase @ (Phase::Done(_) | Phase::Over) => phase,
+        })
+    }
+
+    /// The first of the GFF3 indexes from `from` on (CSI, then TBI, then the
+    /// search index) that `index_format` asks for, over `bgz`.
+    fn index_from(&self, bgz: Vec<u8>, from: IndexFormat) -> io::Result<Phase> {
+        let options = &self.options;
+        let (csi, tbi) = (options.index_format != IndexFormat::Tbi, options.index_format != IndexFormat::Csi);
+        let (config, rollup) = (&TabixConfig::GFF, options.bin_rollup);
+        Ok(match from {
+            IndexFormat::Csi if csi => Phase::Csi(TabixIndexer::csi(written(bgz, options).track(ContigProgress::new("csi", self.gff_extent)), config, rollup)?),
+            _ if tbi => Phase::Tbi(TabixIndexer::tbi(written(bgz, options).track(ContigProgress::new("tbi", self.gff_extent)), config, rollup)?),
+            _ => Phase::Search(bgz),
+        })
+    }
+
+    /// The outputs, once every step ran.
+    fn done(&mut self) -> io::Result<Phase> {
+        let (fasta_bgz, fasta_fai, fasta_gzi) = std::mem::take(&mut self.fasta);
+        let gff = std::mem::take(&mut self.gff);
+        let warnings = std::mem::take(&mut self.warnings);
+        let mut out = IndexGen::from_parts(fasta_bgz, fasta_fai, fasta_gzi, gff, warnings, &self.options);
+        out.contigs = std::mem::take(&mut self.contigs);
+        out.id_renames = std::mem::take(&mut self.id_renames);
+        out.tracks = std::mem::take(&mut self.tracks);
+        Ok(Phase::Done(Box::new(out)))
+    }
+}
+
+/// Reader over BGZF output this run just wrote, as `written` in `lib.rs`.
+fn written<R: AsRef<[u8]>>(bgz: R, options: &IndexGenOptions) -> BgzfReader<Cursor<R>> {
+    BgzfReader::new(Cursor::new(bgz)).verify_crc(options.verify_written_crc)
+}
+
+/// The prepared input, unless it is the whole of the input, `len` bytes,
+/// unchanged.
+fn owned(prepared: Cow<[u8]>, len: usize) -> Option<Vec<u8>> {
+    match prepared {
+        Cow::Borrowed(bytes) if bytes.len() == len => None,
+        prepared => Some(prepared.into_owned()),
+    }
+}
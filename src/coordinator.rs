@@ -0,0 +1,35 @@
+//! Runs the FASTA and GFF3 branches of a run side by side.
+//!
+//! Each branch reads its input and runs its transform pipeline on the
+//! calling thread (transform hooks may be JS functions, which cannot leave
+//! it); the write phases, which take most of the time (sorting,
+//! compression, indexing), then run at once: the FASTA one on a scoped
+//! thread and the GFF3 one on the calling thread, so wall-clock time
+//! approaches that of the slower branch rather than the sum of both.
+//! Where no thread can be spawned (wasm without thread support) they run
+//! one after the other.  Without the `threads` feature or with
+//! `parallel_branches` off, the caller does not come here at all: it writes
+//! the FASTA before reading the GFF3 (see `run_branches` in `lib.rs`).
+//! The outputs are the same either way, and so are the warnings: the GFF3
+//! write phase collects its own, merged afterwards.
+
+/// `(a(), b())`, with `a` on a scoped thread when `parallel` and the
+/// `threads` feature allow it.  A panic in `a` is resumed on the caller.
+pub fn join<A: Send, B>(parallel: bool, a: impl FnOnce() -> A + Send, b: impl FnOnce() -> B) -> (A, B) {
+    if !(parallel && cfg!(feature = "threads")) {
+        return (a(), b());
+    }
+    // `spawn_scoped` consumes its closure even when it fails, so `a` runs
+    // from an option that a copy of `run_a` on this thread can still take.
+    let a = std::sync::Mutex::new(Some(a));
+    let run_a = || a.lock().ok().and_then(|mut a| a.take()).map(|a| a());
+    std::thread::scope(|scope| {
+        let handle = std::thread::Builder::new().spawn_scoped(scope, run_a);
+        let b = b();
+        let a = match handle {
+            Ok(handle) => handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)),
+            Err(_) => run_a(),
+        };
+        (a.expect("the first branch runs exactly once"), b)
+    })
+}
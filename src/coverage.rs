@@ -0,0 +1,195 @@
+//! Read depth from metagenome binning, as a bedGraph track.
+//!
+//! Two tab-separated layouts are read, told apart by the first line:
+//!
+//! - per base, as `samtools depth` writes it: `contig  pos  depth…`, 1-based,
+//!   one depth column per sample (summed), positions increasing within a
+//!   contig and each contig in one block.  Runs of equal depth become one
+//!   interval; positions not listed have no interval and count as 0.
+//! - per contig, as `jgi_summarize_bam_contig_depths` writes it: a
+//!   `contigName  contigLen  totalAvgDepth  …` header, then one line per
+//!   contig, which becomes one interval over the whole contig.
+//!
+//! Contigs must be sequences of the FASTA (`E_UNKNOWN_SEQUENCE`), and
+//! `contigLen` their length; anything else malformed is `E_DEPTH_PARSE`.
+//! Blank lines and `#` comments (`samtools depth -H`) are skipped.  The
+//! intervals, in FASTA order, become the `coverage` track (see
+//! `fasta/track.rs`) as `<OUT_PREFIX>.coverage.bedgraph.gz`, and the mean
+//! depth of every sequence goes into the stats.
+
+use std::collections::HashMap;
+use std::io;
+
+use crate::error::{io_error, ErrorCode};
+use crate::fasta::track::SequenceTrack;
+use crate::fasta::{FastaLine, FastaParser};
+use crate::htslib::TabixConfig;
+use crate::scan;
+
+/// Layout of a depth file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepthFormat {
+    /// `samtools depth`.
+    PerBase,
+    /// `jgi_summarize_bam_contig_depths`.
+    PerContig,
+}
+
+impl DepthFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DepthFormat::PerBase => "per_base",
+            DepthFormat::PerContig => "per_contig",
+        }
+    }
+}
+
+/// A bedGraph interval: `(start, end, depth)`, 0-based half-open.
+pub type DepthInterval = (u64, u64, f64);
+
+/// Depth of the sequences of an assembly.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Coverage {
+    pub format: DepthFormat,
+    /// Intervals of the sequences the file covers, in FASTA order.
+    pub intervals: Vec<(String, Vec<DepthInterval>)>,
+    /// Mean depth of every FASTA sequence, in FASTA order, and its length.
+    pub means: Vec<(String, u64, f64)>,
+}
+
+/// Depth read so far for one sequence.
+#[derive(Default)]
+struct Depths {
+    intervals: Vec<DepthInterval>,
+    /// Depth summed over the bases.
+    sum: f64,
+}
+
+fn depth_error(line: usize, message: impl std::fmt::Display) -> io::Error {
+    io_error(ErrorCode::DepthParse, format!("depth file line {}: {}", line, message))
+}
+
+fn parse_depth(value: &str, line: usize) -> io::Result<f64> {
+    value.trim().parse::<f64>().ok().filter(|d| d.is_finite() && *d >= 0.0).ok_or_else(|| depth_error(line, format!("bad depth {:?}", value)))
+}
+
+impl Coverage {
+    /// Read the depth file `depth` of the FASTA whose sequences are
+    /// `lengths`, `(name, length)` in FASTA order.
+    pub fn parse(depth: &[u8], lengths: &[(String, u64)]) -> io::Result<Self> {
+        let index: HashMap<&str, usize> = lengths.iter().enumerate().map(|(i, (name, _))| (name.as_str(), i)).collect();
+        let mut depths: Vec<Option<Depths>> = lengths.iter().map(|_| None).collect();
+        let mut format = None;
+        let mut current = None;
+        for (n, line) in scan::split_lines(depth).enumerate().map(|(n, line)| (n + 1, line)) {
+            let line = std::str::from_utf8(line).map_err(|_| depth_error(n, "not UTF-8"))?.trim_end_matches(['\r', '\n']);
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let format = *format.get_or_insert(if line.starts_with("contigName\t") { DepthFormat::PerContig } else { DepthFormat::PerBase });
+            if format == DepthFormat::PerContig && line.starts_with("contigName\t") {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 3 {
+                return Err(depth_error(n, "expected at least 3 tab-separated columns"));
+            }
+            let i = *index.get(fields[0]).ok_or_else(|| io_error(ErrorCode::UnknownSequence, format!("depth file line {}: unknown sequence {:?}", n, fields[0])))?;
+            let length = lengths[i].1;
+            if current != Some(i) {
+                if depths[i].is_some() {
+                    return Err(depth_error(n, format!("sequence {:?} is listed in more than one block", fields[0])));
+                }
+                depths[i] = Some(Depths::default());
+                current = Some(i);
+            }
+            let seq = depths[i].get_or_insert_with(Depths::default);
+            match format {
+                DepthFormat::PerBase => {
+                    let pos: u64 = fields[1].parse().map_err(|_| depth_error(n, format!("bad position {:?}", fields[1])))?;
+                    if pos == 0 || pos > length {
+                        return Err(depth_error(n, format!("position {} outside {:?} (1-{})", pos, fields[0], length)));
+                    }
+                    let depth = fields[2..].iter().map(|v| parse_depth(v, n)).sum::<io::Result<f64>>()?;
+                    match seq.intervals.last_mut() {
+                        Some(last) if last.1 >= pos => return Err(depth_error(n, format!("positions of {:?} are not increasing", fields[0]))),
+                        Some(last) if last.1 == pos - 1 && last.2 == depth => last.1 = pos,
+                        _ => seq.intervals.push((pos - 1, pos, depth)),
+                    }
+                    seq.sum += depth;
+                }
+                DepthFormat::PerContig => {
+                    if fields[1].parse::<u64>().ok() != Some(length) {
+                        return Err(depth_error(n, format!("{:?} has length {} here but {} in the FASTA", fields[0], fields[1], length)));
+                    }
+                    let depth = parse_depth(fields[2], n)?;
+                    if length > 0 {
+                        seq.intervals.push((0, length, depth));
+                    }
+                    seq.sum = depth * length as f64;
+                    current = None;
+                }
+            }
+        }
+        let format = format.unwrap_or(DepthFormat::PerBase);
+        let mut intervals = Vec::new();
+        let mut means = Vec::new();
+        for ((name, length), seq) in lengths.iter().zip(depths) {
+            let sum = seq.as_ref().map_or(0.0, |s| s.sum);
+            means.push((name.clone(), *length, if *length == 0 { 0.0 } else { sum / *length as f64 }));
+            if let Some(seq) = seq {
+                intervals.push((name.clone(), seq.intervals));
+            }
+        }
+        Ok(Coverage { format, intervals, means })
+    }
+
+    /// The figures for the stats: `{format, mean_depth, sequences_covered,
+    /// mean_depth_per_sequence: {name: depth}}`, depths to 2 decimals and
+    /// `mean_depth` over all bases.
+    pub fn to_json(&self) -> json::JsonValue {
+        let round = |d: f64| (d * 100.0).round() / 100.0;
+        let total: u64 = self.means.iter().map(|m| m.1).sum();
+        let sum: f64 = self.means.iter().map(|m| m.2 * m.1 as f64).sum();
+        let mut per_sequence = json::JsonValue::new_object();
+        for (name, _, mean) in &self.means {
+            per_sequence[name.as_str()] = round(*mean).into();
+        }
+        json::object! {
+            format: self.format.as_str(),
+            mean_depth: round(if total == 0 { 0.0 } else { sum / total as f64 }),
+            sequences_covered: self.intervals.len(),
+            mean_depth_per_sequence: per_sequence,
+        }
+    }
+
+    /// The intervals as bedGraph: `seqid  start  end  depth`.
+    pub fn to_bedgraph(&self) -> String {
+        let mut out = String::new();
+        for (name, intervals) in &self.intervals {
+            for (start, end, depth) in intervals {
+                out.push_str(&format!("{}\t{}\t{}\t{}\n", name, start, end, depth));
+            }
+        }
+        out
+    }
+
+    /// The `coverage` track, compressed at `level`, with
+    /// [`Coverage::to_json`] as its figures.
+    pub fn track(&self, level: u32) -> io::Result<SequenceTrack> {
+        SequenceTrack::from_text("coverage", "bedgraph", &TabixConfig::BED, &self.to_bedgraph(), self.to_json(), level)
+    }
+}
+
+/// `(name, length)` of every sequence of an uncompressed FASTA, in order.
+pub fn sequence_lengths(fa: &[u8]) -> Vec<(String, u64)> {
+    let mut out = Vec::new();
+    let mut parser = FastaParser::new();
+    for line in scan::split_lines(fa) {
+        if let (FastaLine::Header { .. }, Some(record)) = parser.push(line) {
+            out.push((record.name, record.length));
+        }
+    }
+    out.extend(parser.finish().map(|record| (record.name, record.length)));
+    out
+}
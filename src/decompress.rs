@@ -4,7 +4,9 @@
 use flate2::read::MultiGzDecoder;
 use std::io::{self, Chain, Cursor, Read};
 
-const GZ_MAGIC: [u8; 2] = [0x1F, 0x8B];
+use crate::error::{io_error, ErrorCode};
+
+pub(crate) const GZ_MAGIC: [u8; 2] = [0x1F, 0x8B];
 
 
 /// Enum that allows for alternating between uncompressed and compressed files
@@ -27,22 +29,26 @@ impl<'a, F: Read + 'a> Read for ReaderEnum<'a, F> {
 }
 
 
-/// Returns a reader from a fasta file
-pub fn open_file_maybe_gz<'a, F>(file_in: &'a mut F) -> ReaderEnum<'a, F>
+/// Returns a reader from a fasta file, or `E_INPUT_EMPTY` for a file
+/// shorter than the two bytes of the gzip magic.
+pub fn open_file_maybe_gz<'a, F>(file_in: &'a mut F) -> io::Result<ReaderEnum<'a, F>>
 where
     F: Read + 'a,
 {
     let mut first_two_bytes = [0; 2];
     file_in
         .read_exact(&mut first_two_bytes)
-        .expect("Empty input file");
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => io_error(ErrorCode::InputEmpty, "empty input file"),
+            _ => e,
+        })?;
     let first_two_cursor = Cursor::new(first_two_bytes);
     let new_reader = first_two_cursor.chain(file_in);
-    match first_two_bytes {
+    Ok(match first_two_bytes {
         GZ_MAGIC => {
             let gz_reader = MultiGzDecoder::new(new_reader);
             ReaderEnum::Gzipped(gz_reader)
         }
         _ => ReaderEnum::Plain(new_reader),
-    }
+    })
 }
@@ -0,0 +1,236 @@
+//! Machine-readable failure codes.
+//!
+//! Failures travel through the crate as `io::Error`s (so `?` keeps working
+//! across readers, writers and parsers) wrapping a [`PreprocessError`] that
+//! carries a stable [`ErrorCode`].  At the wasm boundary [`js_error`] turns
+//! them into a JS `Error` with a `code` property, which front ends branch on
+//! instead of matching the English message.
+
+use std::{error, fmt, io};
+
+use wasm_bindgen::prelude::*;
+
+/// Stable identifier of a failure; see [`ErrorCode::as_str`] for the string
+/// form exposed to JS.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// Filesystem or other I/O failure without a more specific code.
+    Io,
+    /// A gzip-compressed input could not be decompressed.
+    InputDecompress,
+    /// An input too short to hold any data (fewer than two bytes).
+    InputEmpty,
+    /// A JS input value of an unsupported type.
+    InputType,
+    /// An input too large to hold in this build's memory.
+    InputTooLarge,
+    /// Downloading an input failed.
+    Fetch,
+    /// The OPFS output cache is unavailable or an entry is damaged.
+    Cache,
+    /// Malformed FASTA, e.g. a non-UTF-8 sequence name.
+    FastaParse,
+    /// Two FASTA sequences share a name.
+    FastaDupName,
+    /// Sequence lines of irregular length, which `.fai` cannot describe.
+    FastaLineLength,
+    /// A control character under `ControlCharPolicy::Error`.
+    ControlCharacter,
+    /// The GFF3 is not valid UTF-8.
+    GffEncoding,
+    /// Malformed GFF3 record (missing column, non-numeric coordinate, …).
+    GffParse,
+    /// Records not grouped by sequence and sorted by start, as indexing needs.
+    GffUnsorted,
+    /// Malformed depth (coverage) file.
+    DepthParse,
+    /// Malformed contig-to-bin table.
+    BinsParse,
+    /// Malformed GFA1 assembly graph.
+    GfaParse,
+    /// A coordinate beyond what the index format can address.
+    CoordinateLimit,
+    /// Not a gzip/BGZF stream, or an unsupported header.
+    BgzfHeader,
+    /// A BGZF/gzip block that fails to decode (CRC, size, deflate data).
+    BgzfCorrupt,
+    /// A BGZF/gzip stream or a virtual offset past its end.
+    BgzfTruncated,
+    /// An unreadable `.fai`, `.gzi`, `.csi`, `.tbi` or search index.
+    IndexParse,
+    /// Indexes that do not match their data or each other.
+    IndexMismatch,
+    /// A region query, or a line of a depth file, on a sequence the index
+    /// or FASTA does not know.
+    UnknownSequence,
+    /// An option value that cannot be honoured.
+    InvalidOption,
+    /// A registered transform hook threw or returned something other than
+    /// a string, `null` or `undefined`.
+    TransformHook,
+    /// A bug: an internal consistency check failed.
+    Internal,
+    /// A bug: the call panicked.
+    Panic,
+    /// An earlier call panicked; `reset()` before calling again.
+    InstancePoisoned,
+}
+
+impl ErrorCode {
+    /// Every code, in declaration order.
+    pub const ALL: [ErrorCode; 29] = [
+        ErrorCode::Io,
+        ErrorCode::InputDecompress,
+        ErrorCode::InputEmpty,
+        ErrorCode::InputType,
+        ErrorCode::InputTooLarge,
+        ErrorCode::Fetch,
+        ErrorCode::Cache,
+        ErrorCode::FastaParse,
+        ErrorCode::FastaDupName,
+        ErrorCode::FastaLineLength,
+        ErrorCode::ControlCharacter,
+        ErrorCode::GffEncoding,
+        ErrorCode::GffParse,
+        ErrorCode::GffUnsorted,
+        ErrorCode::DepthParse,
+        ErrorCode::BinsParse,
+        ErrorCode::GfaParse,
+        ErrorCode::CoordinateLimit,
+        ErrorCode::BgzfHeader,
+        ErrorCode::BgzfCorrupt,
+        ErrorCode::BgzfTruncated,
+        ErrorCode::IndexParse,
+        ErrorCode::IndexMismatch,
+        ErrorCode::UnknownSequence,
+        ErrorCode::InvalidOption,
+        ErrorCode::TransformHook,
+        ErrorCode::Internal,
+        ErrorCode::Panic,
+        ErrorCode::InstancePoisoned,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::Io => "E_IO",
+            ErrorCode::InputDecompress => "E_INPUT_DECOMPRESS",
+            ErrorCode::InputEmpty => "E_INPUT_EMPTY",
+            ErrorCode::InputType => "E_INPUT_TYPE",
+            ErrorCode::InputTooLarge => "E_INPUT_TOO_LARGE",
+            ErrorCode::Fetch => "E_FETCH",
+            ErrorCode::Cache => "E_CACHE",
+            ErrorCode::FastaParse => "E_FASTA_PARSE",
+            ErrorCode::FastaDupName => "E_FASTA_DUPNAME",
+            ErrorCode::FastaLineLength => "E_FASTA_LINE_LENGTH",
+            ErrorCode::ControlCharacter => "E_CONTROL_CHARACTER",
+            ErrorCode::GffEncoding => "E_GFF_ENCODING",
+            ErrorCode::GffParse => "E_GFF_PARSE",
+            ErrorCode::GffUnsorted => "E_GFF_UNSORTED",
+            ErrorCode::DepthParse => "E_DEPTH_PARSE",
+            ErrorCode::BinsParse => "E_BINS_PARSE",
+            ErrorCode::GfaParse => "E_GFA_PARSE",
+            ErrorCode::CoordinateLimit => "E_COORDINATE_LIMIT",
+            ErrorCode::BgzfHeader => "E_BGZF_HEADER",
+            ErrorCode::BgzfCorrupt => "E_BGZF_CORRUPT",
+            ErrorCode::BgzfTruncated => "E_BGZF_TRUNCATED",
+            ErrorCode::IndexParse => "E_INDEX_PARSE",
+            ErrorCode::IndexMismatch => "E_INDEX_MISMATCH",
+            ErrorCode::UnknownSequence => "E_UNKNOWN_SEQUENCE",
+            ErrorCode::InvalidOption => "E_INVALID_OPTION",
+            ErrorCode::TransformHook => "E_TRANSFORM_HOOK",
+            ErrorCode::Internal => "E_INTERNAL",
+            ErrorCode::Panic => "E_PANIC",
+            ErrorCode::InstancePoisoned => "E_INSTANCE_POISONED",
+        }
+    }
+
+    /// `io::ErrorKind` used when the code travels inside an `io::Error`.
+    fn kind(self) -> io::ErrorKind {
+        match self {
+            ErrorCode::Io => io::ErrorKind::Other,
+            ErrorCode::BgzfTruncated => io::ErrorKind::UnexpectedEof,
+            ErrorCode::InputTooLarge => io::ErrorKind::OutOfMemory,
+            ErrorCode::InputType | ErrorCode::InvalidOption | ErrorCode::UnknownSequence => io::ErrorKind::InvalidInput,
+            _ => io::ErrorKind::InvalidData,
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A failure with its code.  The message is the English description shown
+/// when the front end has no translation for the code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PreprocessError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl PreprocessError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        PreprocessError { code, message: message.into() }
+    }
+
+    /// The code and message of any `io::Error`: the wrapped
+    /// `PreprocessError` if there is one, otherwise [`ErrorCode::Io`].
+    pub fn from_io(e: &io::Error) -> Self {
+        match e.get_ref().and_then(|inner| inner.downcast_ref::<PreprocessError>()) {
+            Some(inner) => inner.clone(),
+            None => PreprocessError::new(ErrorCode::Io, e.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl error::Error for PreprocessError {}
+
+impl From<PreprocessError> for io::Error {
+    fn from(e: PreprocessError) -> Self {
+        io::Error::new(e.code.kind(), e)
+    }
+}
+
+impl From<PreprocessError> for JsValue {
+    fn from(e: PreprocessError) -> Self {
+        js_error_code(e.code, &e.message)
+    }
+}
+
+/// An `io::Error` carrying `code`.
+pub fn io_error(code: ErrorCode, message: impl Into<String>) -> io::Error {
+    PreprocessError::new(code, message).into()
+}
+
+/// Code of an `io::Error`; [`ErrorCode::Io`] when it carries none.
+pub fn error_code(e: &io::Error) -> ErrorCode {
+    PreprocessError::from_io(e).code
+}
+
+/// Give `e` the code `code` unless it already has one, keeping its message.
+pub fn with_code(e: io::Error, code: ErrorCode) -> io::Error {
+    match error_code(&e) {
+        ErrorCode::Io => io_error(code, e.to_string()),
+        _ => e,
+    }
+}
+
+/// A JS `Error` with the message of `e` and its code as `code`.
+pub fn js_error(e: io::Error) -> JsValue {
+    PreprocessError::from_io(&e).into()
+}
+
+/// A JS `Error` with `message` and `code`.
+pub fn js_error_code(code: ErrorCode, message: &str) -> JsValue {
+    let err: JsValue = JsError::new(message).into();
+    let _ = js_sys::Reflect::set(&err, &JsValue::from_str("code"), &JsValue::from_str(code.as_str()));
+    err
+}
@@ -0,0 +1,192 @@
+//! Output size and run time preview.
+//!
+//! A few blocks of each input are pushed through the real pipeline stages
+//! (sort, compression, indexing) and the measured ratios and throughputs are
+//! extrapolated to the full input size.  Plain inputs are sampled once in
+//! each of 16 equal stretches, at offsets drawn from `options.seed`, so the
+//! same seed gives the same estimate; gzipped inputs can only be sampled
+//! from the start, and their decompressed size is extrapolated from the
+//! prefix.
+
+use std::io::{self, Read};
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::js_error;
+use crate::gff::{self, gff_preprocess};
+use crate::health;
+use crate::htslib::{bgzf_compress_level, csi_index_gff, gzip_compress_level, tbi_index_gff, BgzfReader};
+use crate::input::{InputSample, InputSource};
+use crate::options::{IndexFormat, IndexGenOptions, OutputContainer};
+use crate::progress::now_ms;
+use crate::search;
+use crate::seed::Seedable;
+
+/// Number of slices sampled per input.
+const SAMPLE_COUNT: usize = 16;
+/// Size of one slice (one BGZF block's worth).
+const SAMPLE_SIZE: usize = 0xff00;
+
+/// Run `f`, returning its result and elapsed milliseconds.
+fn timed<T>(f: impl FnOnce() -> io::Result<T>) -> io::Result<(T, f64)> {
+    let start = now_ms();
+    let out = f()?;
+    Ok((out, now_ms() - start))
+}
+
+/// Per-stage time estimates, in pipeline order.
+struct Stages(Vec<(&'static str, f64)>);
+
+impl Stages {
+    fn add(&mut self, stage: &'static str, sample_ms: f64, scale: f64) {
+        self.0.push((stage, sample_ms * scale));
+    }
+}
+
+fn compress(data: &[u8], container: OutputContainer, level: u32) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    if container == OutputContainer::Gzip {
+        gzip_compress_level(data, &mut out, level)?;
+    } else {
+        bgzf_compress_level(data, &mut out, level)?;
+    }
+    Ok(out)
+}
+
+/// Input summary plus the extrapolated size of its compressed output.
+fn input_json(sample: &InputSample, sampled: u64, output_bytes: u64) -> json::JsonValue {
+    json::object! {
+        input_bytes: sample.raw_len,
+        gzipped: sample.gzipped,
+        uncompressed_bytes: sample.len,
+        exact: sample.exact,
+        sampled_bytes: sampled,
+        output_bytes: output_bytes,
+    }
+}
+
+/// Compressed size of the full input from the `sampled` → `compressed` ratio.
+fn extrapolate(total: u64, sampled: u64, compressed: u64) -> u64 {
+    if sampled == 0 {
+        return 0;
+    }
+    (total as f64 * compressed as f64 / sampled as f64).round() as u64
+}
+
+fn estimate_fasta(sample: &InputSample, options: &IndexGenOptions, stages: &mut Stages) -> io::Result<json::JsonValue> {
+    let sampled = sample.sampled_len();
+    let scale = sample.len as f64 / sampled.max(1) as f64;
+    let (compressed, ms) = timed(|| {
+        sample.chunks.iter().map(|c| compress(c, options.fasta_output, options.compression_level)).collect::<io::Result<Vec<_>>>()
+    })?;
+    stages.add("fasta_compress", ms, scale);
+    if options.fasta_output == OutputContainer::Bgzf {
+        // faidx is a single decompressing pass over the BGZF.
+        let ((), ms) = timed(|| {
+            for block in &compressed {
+                BgzfReader::new(block.as_slice()).read_to_end(&mut Vec::new())?;
+            }
+            Ok(())
+        })?;
+        stages.add("fasta_index", ms, scale);
+    }
+    let compressed_len = compressed.iter().map(|c| c.len() as u64).sum();
+    Ok(input_json(sample, sampled, extrapolate(sample.len, sampled, compressed_len)))
+}
+
+fn estimate_gff(sample: &InputSample, options: &IndexGenOptions, stages: &mut Stages) -> io::Result<json::JsonValue> {
+    // Whole lines only: drop the partial lines at both ends of each slice.
+    // The first slice always starts at the start of the file.  Slices past
+    // an embedded `##FASTA` still count as sampled, but like the real
+    // pipeline produce no output.
+    let (mut text, mut sampled, mut in_fasta) = (String::new(), 0u64, false);
+    for (i, chunk) in sample.chunks.iter().enumerate() {
+        let chunk = String::from_utf8_lossy(chunk);
+        let from = if i == 0 { 0 } else { chunk.find('\n').map_or(chunk.len(), |p| p + 1) };
+        let to = if sample.exact { chunk.len() } else { chunk.rfind('\n').map_or(0, |p| p + 1).max(from) };
+        sampled += (to - from) as u64;
+        for line in chunk[from..to].split_inclusive('\n') {
+            in_fasta |= line.starts_with("##FASTA");
+            // Sequence lines of a FASTA section cut mid-way are not records.
+            if !in_fasta && (line.starts_with('#') || line.split('\t').count() >= 5) {
+                text.push_str(line);
+            }
+        }
+    }
+    let scale = sample.len as f64 / sampled.max(1) as f64;
+
+    let (processed, ms) = timed(|| {
+        let sorted = gff_preprocess(&text);
+        Ok(if options.attribute_whitelist.is_empty() && options.attribute_blacklist.is_empty() {
+            sorted
+        } else {
+            gff::project_attributes(&sorted, &options.attribute_whitelist, &options.attribute_blacklist)
+        })
+    })?;
+    stages.add("gff_sort", ms, scale);
+
+    let (compressed, ms) = timed(|| compress(processed.as_bytes(), options.gff_output, options.compression_level))?;
+    stages.add("gff_compress", ms, scale);
+
+    if options.gff_output == OutputContainer::Bgzf {
+        let ((), ms) = timed(|| {
+            if options.index_format != IndexFormat::Tbi {
+                csi_index_gff(compressed.as_slice(), &mut Vec::new())?;
+            }
+            if options.index_format != IndexFormat::Csi {
+                tbi_index_gff(compressed.as_slice(), &mut Vec::new())?;
+            }
+            Ok(())
+        })?;
+        stages.add("gff_index", ms, scale);
+        if !options.search_keys.is_empty() {
+            let (_, ms) = timed(|| search::build_search_index(&compressed, &options.search_keys))?;
+            stages.add("gff_search", ms, scale);
+        }
+    }
+    Ok(input_json(sample, sampled, extrapolate(sample.len, sampled, compressed.len() as u64)))
+}
+
+/// Estimate output sizes and per-stage times for running the pipeline over
+/// `fa` and `gff` with `options`.  Returns
+/// `{fasta, gff, stages: [{stage, ms}], output_bytes, total_ms}`, where
+/// `fasta` / `gff` are `{input_bytes, gzipped, uncompressed_bytes, exact,
+/// sampled_bytes, output_bytes}`.
+pub fn estimate(fa: InputSource, gff: InputSource, options: &IndexGenOptions) -> io::Result<json::JsonValue> {
+    let mut stages = Stages(Vec::new());
+
+    let mut rng = options.rng("estimate");
+    let (fa_sample, fa_ms) = timed(|| fa.sample(SAMPLE_COUNT, SAMPLE_SIZE, &mut rng))?;
+    let (gff_sample, gff_ms) = timed(|| gff.sample(SAMPLE_COUNT, SAMPLE_SIZE, &mut rng))?;
+    // Reading plain inputs is cheap next to everything else; gzip costs a
+    // decompressing pass, which the sampled prefix measures.
+    let read_ms = [(&fa_sample, fa_ms), (&gff_sample, gff_ms)]
+        .iter()
+        .filter(|(s, _)| s.gzipped)
+        .map(|(s, ms)| ms * s.len as f64 / s.sampled_len().max(1) as f64)
+        .sum();
+    stages.add("read", read_ms, 1.0);
+
+    let fasta = estimate_fasta(&fa_sample, options, &mut stages)?;
+    let gff = estimate_gff(&gff_sample, options, &mut stages)?;
+
+    let output_bytes = fasta["output_bytes"].as_u64().unwrap_or(0) + gff["output_bytes"].as_u64().unwrap_or(0);
+    let total_ms: f64 = stages.0.iter().map(|(_, ms)| ms).sum();
+    Ok(json::object! {
+        fasta: fasta,
+        gff: gff,
+        stages: stages.0.iter().map(|(stage, ms)| json::object! { stage: *stage, ms: ms.round() }).collect::<Vec<_>>(),
+        output_bytes: output_bytes,
+        total_ms: total_ms.round(),
+    })
+}
+
+/// Quick size/time preview for a pair of input files, as the JSON described
+/// in [`estimate`].  Only samples the inputs, so it returns in a fraction of
+/// the time a full run takes.
+#[wasm_bindgen]
+pub fn estimate_outputs(fa_file: web_sys::File, gff_file: web_sys::File, options: Option<IndexGenOptions>) -> Result<String, JsValue> {
+    health::contain(|| estimate(InputSource::File(fa_file), InputSource::File(gff_file), &options.unwrap_or_default()))
+        .map(|e| e.dump())
+        .map_err(js_error)
+}
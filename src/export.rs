@@ -0,0 +1,15 @@
+//! Conversion of annotations into submission and visualisation formats.
+
+pub mod embl;
+pub mod igv;
+pub mod jbrowse;
+pub mod ndjson;
+pub mod styles;
+pub mod trix;
+
+pub use embl::{embl_feature_table, embl_feature_table_with_code, gff_to_embl};
+pub use igv::{igv_session, IgvOptions};
+pub use jbrowse::{jbrowse_config, JBrowseOptions};
+pub use ndjson::{gff_to_ndjson, gff_to_ndjson_bgzf, ndjson_bgzf, ndjson_records, NdjsonIndexed, NDJSON_TABIX};
+pub use styles::{gff_track_styles, track_styles};
+pub use trix::{gff_trix_index, TrixIndex};
@@ -0,0 +1,226 @@
+//! EMBL feature-table export for ENA flat-file submissions.
+//!
+//! Each sequence becomes a minimal entry (`ID`, `FH` header, `FT` lines, `//`).
+//! GFF lines sharing `seqid`, type and `ID` are merged into one feature whose
+//! location is a `join(...)`, wrapped in `complement(...)` on the minus strand
+//! (or with each part complemented on its own for trans-spliced features).
+//! CDS translations are recomputed from the FASTA when the sequence is known,
+//! with the genetic code of their `transl_table`, else a default table.
+
+use std::io;
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{io_error, js_error, ErrorCode};
+use crate::fasta::Sequences;
+use crate::gff::multipart::{group_parts, Feature};
+use crate::gff::{attribute_values, data_lines, GffFields};
+use crate::health;
+use crate::translate::{GeneticCode, DEFAULT_TABLE};
+
+const LINE_WIDTH: usize = 80;
+const FT_PREFIX: &str = "FT   ";
+const FT_CONTINUATION: &str = "FT                   ";
+
+/// Feature keys written as-is; any other GFF type becomes `misc_feature`.
+const INSDC_KEYS: &[&str] = &[
+    "CDS", "gene", "mRNA", "tRNA", "rRNA", "tmRNA", "ncRNA", "misc_RNA", "precursor_RNA",
+    "exon", "intron", "5'UTR", "3'UTR", "misc_feature", "repeat_region", "mobile_element",
+    "regulatory", "operon", "rep_origin", "oriT", "stem_loop", "protein_bind", "misc_binding",
+    "gap", "assembly_gap", "mat_peptide", "sig_peptide", "transit_peptide", "propeptide",
+];
+
+/// Qualifiers whose values are written without quotes.
+const UNQUOTED: &[&str] = &["transl_table", "codon_start"];
+
+/// GFF3 reserved attributes that have an INSDC counterpart.
+fn qualifier_name(key: &str) -> Option<&str> {
+    match key {
+        "Dbxref" => Some("db_xref"),
+        "Note" => Some("note"),
+        "EC_number" => Some(key),
+        // Remaining capitalised keys are GFF3 structure (ID, Parent, Name, ...).
+        _ if key.starts_with(|c: char| c.is_ascii_uppercase()) => None,
+        _ => Some(key),
+    }
+}
+
+fn feature_key(ftype: &str) -> (&str, Option<&str>) {
+    match ftype {
+        "databank_entry" | "region" | "source" => ("source", None),
+        "sequence_feature" => ("misc_feature", None),
+        "transcript" => ("misc_RNA", None),
+        "signal_peptide" => ("sig_peptide", None),
+        _ if INSDC_KEYS.contains(&ftype) => (ftype, None),
+        _ => ("misc_feature", Some(ftype)),
+    }
+}
+
+/// EMBL rendering of a (possibly multi-part) feature.
+impl Feature<'_> {
+    fn location(&self) -> String {
+        let span = |p: &GffFields| if p.start == p.end { p.start.to_string() } else { format!("{}..{}", p.start, p.end) };
+        if self.is_trans_spliced() {
+            let spans: Vec<String> =
+                self.parts.iter().map(|p| if p.strand == "-" { format!("complement({})", span(p)) } else { span(p) }).collect();
+            return format!("join({})", spans.join(","));
+        }
+        let spans: Vec<String> = self.parts.iter().map(span).collect();
+        let loc = if spans.len() > 1 { format!("join({})", spans.join(",")) } else { spans.concat() };
+        if self.first().strand == "-" { format!("complement({})", loc) } else { loc }
+    }
+
+    /// Phase of the 5'-most part.
+    fn phase(&self) -> usize {
+        self.five_prime().phase.parse().ok().unwrap_or(0)
+    }
+
+    /// `(codon_start, translation)` from the FASTA, for CDS features, with
+    /// genetic code `default_table` unless the feature has `transl_table`.
+    fn translation(&self, seqs: &Sequences, default_table: u32) -> io::Result<Option<(usize, String)>> {
+        if self.first().ftype != "CDS" || self.is_pseudo() {
+            return Ok(None);
+        }
+        let Some(nt) = self.spliced(seqs) else {
+            return Ok(None);
+        };
+        let table = match self.first().attribute("transl_table") {
+            Some(t) => t.trim_matches('"').parse().map_err(|_| {
+                io_error(ErrorCode::GffParse, format!("invalid transl_table {:?}", t))
+            })?,
+            None => default_table,
+        };
+        let phase = self.phase().min(nt.len());
+        let mut aa = GeneticCode::table(table)?.translate(&nt[phase..], phase == 0);
+        if aa.ends_with('*') {
+            aa.pop();
+        }
+        Ok(Some((phase + 1, aa)))
+    }
+}
+
+/// Group GFF lines into features, per sequence, in input order.
+fn collect_features(gff: &str) -> Vec<(&str, Vec<Feature<'_>>)> {
+    let mut seqs: Vec<(&str, Vec<Feature>)> = Vec::new();
+    for feature in group_parts(data_lines(gff).filter_map(GffFields::parse)) {
+        let seqid = feature.first().seqid;
+        match seqs.iter_mut().find(|(name, _)| *name == seqid) {
+            Some((_, features)) => features.push(feature),
+            None => seqs.push((seqid, vec![feature])),
+        }
+    }
+    for (_, features) in &mut seqs {
+        features.sort_by_key(|f| f.extent().0);
+    }
+    seqs
+}
+
+/// Split `text` into lines of at most `width` bytes, preferring to break after
+/// one of `breaks`.
+fn wrap<'t>(mut text: &'t str, width: usize, breaks: &[char]) -> Vec<&'t str> {
+    let mut lines = Vec::new();
+    while text.len() > width {
+        let mut cut = width;
+        while !text.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        let at = text[..cut].rfind(breaks).map(|i| i + 1).unwrap_or(cut);
+        lines.push(text[..at].trim_end());
+        text = &text[at..];
+    }
+    lines.push(text);
+    lines
+}
+
+fn push_qualifier(out: &mut String, name: &str, value: &str) {
+    let text = if UNQUOTED.contains(&name) {
+        format!("/{}={}", name, value)
+    } else {
+        format!("/{}=\"{}\"", name, value.replace('"', "\"\""))
+    };
+    for line in wrap(&text, LINE_WIDTH - FT_CONTINUATION.len(), &[' ']) {
+        out.push_str(FT_CONTINUATION);
+        out.push_str(line);
+        out.push('\n');
+    }
+}
+
+fn push_feature(out: &mut String, feature: &Feature, seqs: &Sequences, default_table: u32) -> io::Result<()> {
+    let (key, note) = feature_key(feature.first().ftype);
+    for (i, line) in wrap(&feature.location(), LINE_WIDTH - FT_CONTINUATION.len(), &[',']).into_iter().enumerate() {
+        if i == 0 {
+            out.push_str(&format!("{}{:<16}{}\n", FT_PREFIX, key, line));
+        } else {
+            out.push_str(&format!("{}{}\n", FT_CONTINUATION, line));
+        }
+    }
+    if let Some(ftype) = note {
+        push_qualifier(out, "note", ftype);
+    }
+
+    let translation = feature.translation(seqs, default_table)?;
+    for kv in feature.first().attributes.split(';') {
+        let (k, v) = match kv.trim().split_once('=') {
+            Some((k, v)) => (k, Some(v)),
+            None => (kv.trim(), None),
+        };
+        let Some(name) = qualifier_name(k).filter(|n| !n.is_empty()) else {
+            continue;
+        };
+        let Some(v) = v else {
+            // Flag qualifiers such as `/pseudo`.
+            out.push_str(&format!("{}/{}\n", FT_CONTINUATION, name));
+            continue;
+        };
+        if translation.is_some() && (name == "translation" || name == "codon_start") {
+            continue;
+        }
+        for value in attribute_values(v) {
+            push_qualifier(out, name, &value);
+        }
+    }
+    if let Some((codon_start, aa)) = translation {
+        if codon_start > 1 {
+            push_qualifier(out, "codon_start", &codon_start.to_string());
+        }
+        push_qualifier(out, "translation", &aa);
+    }
+    Ok(())
+}
+
+/// Build an EMBL feature table from GFF3 text, translating CDS features
+/// against `seqs` with genetic code table 11 where they give no
+/// `transl_table`.  Sequences without a `source`-like feature get a minimal
+/// `source` spanning the whole sequence.
+pub fn embl_feature_table(gff: &str, seqs: &Sequences) -> io::Result<String> {
+    embl_feature_table_with_code(gff, seqs, DEFAULT_TABLE)
+}
+
+/// [`embl_feature_table`], translating CDS features without `transl_table`
+/// with genetic code `default_table`, which is checked even when no CDS
+/// needs it.
+pub fn embl_feature_table_with_code(gff: &str, seqs: &Sequences, default_table: u32) -> io::Result<String> {
+    GeneticCode::table(default_table)?;
+    let mut out = String::new();
+    for (seqid, features) in collect_features(gff) {
+        out.push_str(&format!("ID   {}\nFH   Key             Location/Qualifiers\nFH\n", seqid));
+        let has_source = features.iter().any(|f| feature_key(f.first().ftype).0 == "source");
+        if let (false, Some(len)) = (has_source, seqs.seq_len(seqid)) {
+            out.push_str(&format!("{}{:<16}1..{}\n", FT_PREFIX, "source", len));
+            push_qualifier(&mut out, "mol_type", "genomic DNA");
+        }
+        for feature in &features {
+            push_feature(&mut out, feature, seqs, default_table)?;
+        }
+        out.push_str("//\n");
+    }
+    Ok(out)
+}
+
+/// Convert GFF3 text plus the (uncompressed) FASTA into an EMBL feature
+/// table, translating CDS features without `transl_table` with genetic
+/// code `transl_table` (default 11).  See [`embl_feature_table`].
+#[wasm_bindgen]
+pub fn gff_to_embl(gff: &str, fasta: &[u8], transl_table: Option<u32>) -> Result<String, JsValue> {
+    health::contain(|| embl_feature_table_with_code(gff, &Sequences::parse(fasta)?, transl_table.unwrap_or(DEFAULT_TABLE))).map_err(js_error)
+}
@@ -0,0 +1,104 @@
+//! igv.js session for a run's outputs.
+//!
+//! The session holds the reference (the BGZF FASTA with its `.fai` and
+//! `.gzi`, which igv.js calls `compressedIndexURL`) and one annotation
+//! track (the GFF3 with its CSI or TBI index), opened on the first
+//! sequence, so a page can pass it to `igv.createBrowser` as it is.  Every
+//! URL comes from a template, so the files can be served under any layout,
+//! e.g. `https://example.org/{prefix}/{artifact}` or
+//! `/api/files?name={prefix}.{artifact}`.
+
+use std::io;
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{io_error, ErrorCode};
+use crate::htslib::parse_fai;
+use crate::naming::OutputNames;
+use crate::options::IndexFormat;
+
+/// Where the outputs will be served, for [`igv_session`].
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct IgvOptions {
+    /// Reference id and name shown by igv.js.
+    #[wasm_bindgen(getter_with_clone)]
+    pub genome_name: String,
+    /// URL of each output: `{prefix}` is replaced by `file_prefix`,
+    /// `{artifact}` by the output's name (`fasta.bgz`, `fasta.fai`,
+    /// `fasta.gzi`, `gff.bgz`, `gff.csi` or `gff.tbi`) and `{file}` by its
+    /// file name, `<file_prefix>.<artifact>` unless `names` is set.
+    #[wasm_bindgen(getter_with_clone)]
+    pub url_template: String,
+    /// What `{prefix}` stands for; the CLI's `<OUT_PREFIX>` file name.
+    #[wasm_bindgen(getter_with_clone)]
+    pub file_prefix: String,
+    /// File names `{file}` stands for (see `naming.rs`); set from JS with
+    /// `set_names`.
+    #[wasm_bindgen(skip)]
+    pub names: Option<OutputNames>,
+}
+
+#[wasm_bindgen]
+impl IgvOptions {
+    /// Files named after the genome, relative to the page:
+    /// `<genome_name>.fasta.bgz` and so on.
+    #[wasm_bindgen(constructor)]
+    pub fn new(genome_name: String) -> Self {
+        IgvOptions { file_prefix: genome_name.clone(), genome_name, url_template: "{file}".to_owned(), names: None }
+    }
+
+    /// Let `{file}` stand for the file names `names` gives.
+    pub fn set_names(&mut self, names: &OutputNames) {
+        self.names = Some(names.clone());
+    }
+}
+
+impl IgvOptions {
+    fn url(&self, artifact: &str) -> String {
+        let file = match &self.names {
+            Some(names) => names.get(artifact).to_owned(),
+            None => format!("{}.{}", self.file_prefix, artifact),
+        };
+        self.url_template.replace("{prefix}", &self.file_prefix).replace("{artifact}", artifact).replace("{file}", &file)
+    }
+}
+
+/// The igv.js session for a FASTA indexed by `fai` and its GFF3, indexed
+/// by `gff_index` (a GFF3 without one is loaded whole).
+pub fn igv_session(options: &IgvOptions, fai: &[u8], gff_index: Option<IndexFormat>) -> io::Result<json::JsonValue> {
+    if !options.url_template.contains("{artifact}") && !options.url_template.contains("{file}") {
+        return Err(io_error(ErrorCode::InvalidOption, "the igv.js URL template has no {artifact} or {file} placeholder, so every file would get the same URL"));
+    }
+    let records = parse_fai(fai)?;
+    let first = records
+        .first()
+        .ok_or_else(|| io_error(ErrorCode::InvalidOption, "igv.js needs the BGZF FASTA and its .fai, which this run did not produce"))?;
+    let name = options.genome_name.as_str();
+
+    let mut track = json::object! {
+        name: "Annotations",
+        type: "annotation",
+        format: "gff3",
+        url: options.url("gff.bgz"),
+    };
+    match gff_index {
+        Some(format) => {
+            track["indexURL"] = options.url(if format == IndexFormat::Tbi { "gff.tbi" } else { "gff.csi" }).into();
+            track["indexed"] = true.into();
+        }
+        None => track["indexed"] = false.into(),
+    }
+
+    Ok(json::object! {
+        reference: {
+            id: name,
+            name: name,
+            fastaURL: options.url("fasta.bgz"),
+            indexURL: options.url("fasta.fai"),
+            compressedIndexURL: options.url("fasta.gzi"),
+        },
+        locus: first.name.as_str(),
+        tracks: [track],
+    })
+}
@@ -0,0 +1,220 @@
+//! JBrowse 2 configuration for a run's outputs.
+//!
+//! The config holds one assembly (the BGZF FASTA with its `.fai`/`.gzi`)
+//! and one feature track (the GFF3 with its CSI or TBI index), with the
+//! file locations built from where the page will serve them, so a preview
+//! page can hand it to `createViewState` as it is:
+//!
+//! ```text
+//! {"assemblies":[{"name":"genome","sequence":{…BgzipFastaAdapter…},"refNameAliases":{…}}],
+//!  "tracks":[{"type":"FeatureTrack","trackId":"genome-annotations",…,"metadata":{…}}]}
+//! ```
+//!
+//! With `text_index` the track also gets `textSearching`, pointing at the
+//! trix files of `trix.rs`.
+//!
+//! `refNameAliases` maps each FASTA sequence to the GFF3 seqids that name
+//! it differently (`chr1`/`1`, `NC_000913.3`/`NC_000913`), so features on
+//! either spelling are drawn.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{io_error, ErrorCode};
+use crate::gff::{data_lines, GffFields};
+use crate::htslib::parse_fai;
+use crate::naming::OutputNames;
+use crate::options::IndexFormat;
+use crate::stats::GffStats;
+
+/// Where the outputs will be served, for [`jbrowse_config`].
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct JBrowseOptions {
+    /// Assembly name; the track refers to it.
+    #[wasm_bindgen(getter_with_clone)]
+    pub assembly_name: String,
+    /// Prepended to every file name as it is: a directory URL ending in
+    /// `/`, or empty for files next to the config.
+    #[wasm_bindgen(getter_with_clone)]
+    pub base_url: String,
+    /// File names are `<file_prefix>.fasta.bgz`, `.fasta.fai`,
+    /// `.fasta.gzi`, `.gff.bgz` and `.gff.csi`/`.gff.tbi`, as the CLI
+    /// writes them for `<OUT_PREFIX>`, unless `names` is set.
+    #[wasm_bindgen(getter_with_clone)]
+    pub file_prefix: String,
+    /// File names of the outputs, replacing `file_prefix` (see
+    /// `naming.rs`); set from JS with `set_names`.
+    #[wasm_bindgen(skip)]
+    pub names: Option<OutputNames>,
+    /// Give the track a `TrixTextSearchAdapter` over the `.ix`/`.ixx` of
+    /// `export/trix.rs`, served next to the GFF3 and named after it
+    /// (`<GFF3 file>.ix`, `.ixx`).
+    pub text_index: bool,
+}
+
+#[wasm_bindgen]
+impl JBrowseOptions {
+    /// Files named after the assembly, next to the config.
+    #[wasm_bindgen(constructor)]
+    pub fn new(assembly_name: String) -> Self {
+        JBrowseOptions { file_prefix: assembly_name.clone(), assembly_name, base_url: String::new(), names: None, text_index: false }
+    }
+
+    /// Point at the files as `names` names them.
+    pub fn set_names(&mut self, names: &OutputNames) {
+        self.names = Some(names.clone());
+    }
+}
+
+impl JBrowseOptions {
+    fn file_name(&self, artifact: &str) -> String {
+        match &self.names {
+            Some(names) => names.get(artifact).to_owned(),
+            None => format!("{}.{}", self.file_prefix, artifact),
+        }
+    }
+
+    fn location(&self, artifact: &str) -> json::JsonValue {
+        self.uri(self.file_name(artifact))
+    }
+
+    fn uri(&self, file: String) -> json::JsonValue {
+        json::object! {
+            uri: format!("{}{}", self.base_url, file),
+            locationType: "UriLocation",
+        }
+    }
+
+    /// Id of the annotation track.
+    pub fn track_id(&self) -> String {
+        format!("{}-annotations", self.assembly_name)
+    }
+
+    /// File name of the trix `ext` (`ix` or `ixx`) of the GFF3 output.
+    pub fn trix_file_name(&self, ext: &str) -> String {
+        format!("{}.{}", self.file_name("gff.bgz"), ext)
+    }
+}
+
+/// Name under which spellings of one sequence name compare equal: without
+/// a `chr` prefix or a `.N` version suffix.
+fn alias_key(name: &str) -> &str {
+    let name = ["chr", "Chr", "CHR"].iter().find_map(|p| name.strip_prefix(p)).filter(|n| !n.is_empty()).unwrap_or(name);
+    match name.rsplit_once('.') {
+        Some((base, version)) if !base.is_empty() && !version.is_empty() && version.bytes().all(|b| b.is_ascii_digit()) => base,
+        _ => name,
+    }
+}
+
+/// `FromConfigAdapter` features giving each FASTA name the GFF3 seqids
+/// that spell it differently and its unversioned form; `None` when no
+/// name has any.
+fn ref_name_aliases(names: &[String], gff: Option<&str>) -> Option<json::JsonValue> {
+    let seqids: BTreeSet<&str> = gff.map(|gff| data_lines(gff).filter_map(GffFields::parse).map(|f| f.seqid).collect()).unwrap_or_default();
+    let mut by_key: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for seqid in &seqids {
+        by_key.entry(alias_key(seqid)).or_default().insert(seqid);
+    }
+    let features: Vec<json::JsonValue> = names
+        .iter()
+        .filter_map(|name| {
+            let key = alias_key(name);
+            let mut aliases: BTreeSet<&str> = by_key.get(key).cloned().unwrap_or_default();
+            if key != name {
+                aliases.insert(key);
+            }
+            aliases.remove(name.as_str());
+            (!aliases.is_empty()).then(|| {
+                json::object! {
+                    refName: name.as_str(),
+                    uniqueId: format!("alias-{}", name),
+                    aliases: aliases.into_iter().collect::<Vec<_>>(),
+                }
+            })
+        })
+        .collect();
+    (!features.is_empty()).then(|| {
+        json::object! {
+            adapter: {
+                type: "FromConfigAdapter",
+                features: features,
+            }
+        }
+    })
+}
+
+/// The JBrowse 2 config for a FASTA indexed by `fai` and its GFF3.  `gff`
+/// is the GFF3 text as compressed (for the track metadata and the aliases;
+/// without it the config has neither) and `gff_index` the index served with
+/// it; a GFF3 without one is read whole by `Gff3Adapter`.
+pub fn jbrowse_config(options: &JBrowseOptions, fai: &[u8], gff: Option<&str>, gff_index: Option<IndexFormat>) -> io::Result<json::JsonValue> {
+    if options.assembly_name.is_empty() {
+        return Err(io_error(ErrorCode::InvalidOption, "the JBrowse assembly name is empty"));
+    }
+    let names: Vec<String> = parse_fai(fai)?.into_iter().map(|r| r.name).collect();
+    if names.is_empty() {
+        return Err(io_error(ErrorCode::InvalidOption, "JBrowse needs the BGZF FASTA and its .fai, which this run did not produce"));
+    }
+    let name = options.assembly_name.as_str();
+
+    let mut assembly = json::object! {
+        name: name,
+        sequence: {
+            type: "ReferenceSequenceTrack",
+            trackId: format!("{}-ReferenceSequenceTrack", name),
+            adapter: {
+                type: "BgzipFastaAdapter",
+                fastaLocation: options.location("fasta.bgz"),
+                faiLocation: options.location("fasta.fai"),
+                gziLocation: options.location("fasta.gzi"),
+            },
+        },
+    };
+    if let Some(aliases) = ref_name_aliases(&names, gff) {
+        assembly["refNameAliases"] = aliases;
+    }
+
+    let adapter = match gff_index {
+        Some(format) => {
+            let (suffix, index_type) = if format == IndexFormat::Tbi { ("gff.tbi", "TBI") } else { ("gff.csi", "CSI") };
+            json::object! {
+                type: "Gff3TabixAdapter",
+                gffGzLocation: options.location("gff.bgz"),
+                index: { location: options.location(suffix), indexType: index_type },
+            }
+        }
+        None => json::object! { type: "Gff3Adapter", gffLocation: options.location("gff.bgz") },
+    };
+    let mut track = json::object! {
+        type: "FeatureTrack",
+        trackId: options.track_id(),
+        name: "Annotations",
+        assemblyNames: [name],
+        category: ["Annotation"],
+        adapter: adapter,
+    };
+    if let Some(gff) = gff {
+        let stats = GffStats::from_gff(gff);
+        track["description"] = format!("{} features on {} sequences", stats.records, stats.seqids).into();
+        track["metadata"] = stats.to_json();
+    }
+    if options.text_index {
+        track["textSearching"] = json::object! {
+            textSearchAdapter: {
+                type: "TrixTextSearchAdapter",
+                textSearchAdapterId: format!("{}-index", options.track_id()),
+                ixFilePath: options.uri(options.trix_file_name("ix")),
+                ixxFilePath: options.uri(options.trix_file_name("ixx")),
+                assemblyNames: [name],
+            },
+        };
+    }
+
+    Ok(json::object! {
+        assemblies: [assembly],
+        tracks: [track],
+    })
+}
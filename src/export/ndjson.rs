@@ -0,0 +1,133 @@
+//! Newline-delimited JSON export of GFF features for custom front-end tracks.
+//!
+//! Every feature becomes one JSON object:
+//!
+//! ```text
+//! {"seqid":"chr1","type":"CDS","start":100,"end":200,"strand":"+","attributes":{"ID":["cds1"],"Dbxref":["a","b"]}}
+//! ```
+//!
+//! Attribute values are always arrays (comma-separated and repeated keys are
+//! collected, `%XX` escapes decoded, flags without a value are `[]`).  For
+//! the indexed form each object is prefixed with `seqid<TAB>start<TAB>end<TAB>`
+//! so the generic tabix indexer ([`NDJSON_TABIX`]) can bin it.
+//!
+//! With `collapse_multipart` the records of a feature in several parts
+//! (sharing seqid, type and `ID`) become one object spanning them all, for
+//! tracks that draw a feature once, with the parts listed in transcription
+//! order: `"parts":[[100,200],[300,350]]`.  The GFF3 output and its index
+//! keep one record per part.
+
+use std::io::{self, Cursor};
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::js_error;
+use crate::gff::{attribute_values, data_lines, gff_preprocess, group_parts, GffFields};
+use crate::health;
+use crate::htslib::{bgzf_compress_level, csi_index, TabixConfig};
+
+/// Column layout of the indexed NDJSON form.
+pub const NDJSON_TABIX: TabixConfig = TabixConfig { col_seq: 1, col_beg: 2, col_end: 3, ..TabixConfig::GFF };
+
+/// JSON record of one GFF feature.
+pub fn feature_json(f: &GffFields) -> json::JsonValue {
+    let mut attributes = json::JsonValue::new_object();
+    for kv in f.attributes.split(';').map(str::trim).filter(|kv| !kv.is_empty()) {
+        let (k, values) = match kv.split_once('=') {
+            Some((k, v)) => (k, attribute_values(v)),
+            None => (kv, Vec::new()),
+        };
+        if !attributes.has_key(k) {
+            attributes[k] = json::JsonValue::new_array();
+        }
+        for v in values {
+            let _ = attributes[k].push(v);
+        }
+    }
+    json::object! {
+        seqid: f.seqid,
+        type: f.ftype,
+        start: f.start,
+        end: f.end,
+        strand: f.strand,
+        attributes: attributes,
+    }
+}
+
+/// NDJSON records for the (sorted) features of `gff`.  With `coords` each
+/// line is prefixed with its tab-separated seqid/start/end columns; with
+/// `collapse_multipart` each multi-part feature is one record.
+pub fn ndjson_records(gff: &str, coords: bool, collapse_multipart: bool) -> String {
+    let mut out = String::new();
+    let mut push = |f: &GffFields, (start, end): (u64, u64), record: json::JsonValue| {
+        if coords {
+            out.push_str(&format!("{}\t{}\t{}\t", f.seqid, start, end));
+        }
+        out.push_str(&record.dump());
+        out.push('\n');
+    };
+    if !collapse_multipart {
+        let sorted = gff_preprocess(gff);
+        for f in data_lines(&sorted).filter_map(GffFields::parse) {
+            push(&f, (f.start, f.end), feature_json(&f));
+        }
+        return out;
+    }
+    // Grouped before sorting, so trans-spliced parts keep their order.
+    let mut features = group_parts(data_lines(gff).filter_map(GffFields::parse));
+    features.sort_by(|a, b| a.first().seqid.cmp(b.first().seqid).then(a.extent().cmp(&b.extent())));
+    for feature in features {
+        let (start, end) = feature.extent();
+        let mut record = feature_json(feature.first());
+        record["start"] = start.into();
+        record["end"] = end.into();
+        record["parts"] = feature.parts.iter().map(|p| json::array![p.start, p.end]).collect::<Vec<_>>().into();
+        push(feature.first(), (start, end), record);
+    }
+    out
+}
+
+/// BGZF-compressed, coordinate-prefixed NDJSON plus its `.csi` index.
+pub fn ndjson_bgzf(gff: &str, level: u32, collapse_multipart: bool) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    let records = ndjson_records(gff, true, collapse_multipart);
+    let mut bgz = Vec::new();
+    bgzf_compress_level(Cursor::new(records.as_bytes()), &mut bgz, level)?;
+    let mut csi = Vec::new();
+    csi_index(Cursor::new(&bgz), &mut csi, &NDJSON_TABIX)?;
+    Ok((bgz, csi))
+}
+
+/// Convert GFF3 text into plain NDJSON (one feature object per line), with
+/// multi-part features as one object when `collapse_multipart`.
+#[wasm_bindgen]
+pub fn gff_to_ndjson(gff: &str, collapse_multipart: Option<bool>) -> String {
+    ndjson_records(gff, false, collapse_multipart.unwrap_or(false))
+}
+
+/// Indexed NDJSON produced by [`gff_to_ndjson_bgzf`].
+#[wasm_bindgen]
+pub struct NdjsonIndexed {
+    bgz: Vec<u8>,
+    csi: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl NdjsonIndexed {
+    /// Moves the BGZF-compressed records out. May only be called once meaningfully.
+    pub fn bgz(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.bgz)
+    }
+    /// Moves the `.csi` index bytes out. May only be called once meaningfully.
+    pub fn csi(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.csi)
+    }
+}
+
+/// Convert GFF3 text into coordinate-prefixed NDJSON, BGZF-compress it at
+/// `level` (default 6) and index it with a CSI over columns 1-3.
+/// `collapse_multipart` as for [`gff_to_ndjson`].
+#[wasm_bindgen]
+pub fn gff_to_ndjson_bgzf(gff: &str, level: Option<u32>, collapse_multipart: Option<bool>) -> Result<NdjsonIndexed, JsValue> {
+    let (bgz, csi) = health::contain(|| ndjson_bgzf(gff, level.unwrap_or(6), collapse_multipart.unwrap_or(false))).map_err(js_error)?;
+    Ok(NdjsonIndexed { bgz, csi })
+}
@@ -0,0 +1,88 @@
+//! Default track styling: a sidecar JSON suggesting a display colour and
+//! priority per feature type and a colour per source, so a preview browser
+//! draws CDS, RNAs, CRISPR arrays and mobile elements distinguishably
+//! without any configuration.  Built from the counts of [`GffStats`]:
+//!
+//! ```text
+//! {"styles_version":1,
+//!  "types":{"CDS":{"category":"coding","color":"#1f77b4","priority":1,"count":4211},…},
+//!  "sources":{"Prodigal":{"color":"#4e79a7","count":4211},…},
+//!  "default":{"category":"other","color":"#8c564b","priority":7}}
+//! ```
+//!
+//! Lower priorities are drawn first / on top.  Only types and sources in
+//! the file are listed; anything else gets `default`.
+
+use wasm_bindgen::prelude::*;
+
+use crate::stats::GffStats;
+
+/// Version of the sidecar layout.
+pub const STYLES_VERSION: u32 = 1;
+
+/// Categories: name, colour, priority and the feature types (compared
+/// ignoring ASCII case) that fall in it.
+const CATEGORIES: &[(&str, &str, u32, &[&str])] = &[
+    ("coding", "#1f77b4", 1, &["CDS"]),
+    ("trna", "#ff7f0e", 2, &["tRNA", "tmRNA"]),
+    ("rrna", "#d62728", 2, &["rRNA"]),
+    ("crispr", "#9467bd", 2, &["CRISPR", "CRISPR_array", "direct_repeat", "repeat_unit", "spacer"]),
+    (
+        "mobile_element",
+        "#e377c2",
+        2,
+        &[
+            "mobile_genetic_element", "mobile_element", "transposable_element", "transposon", "insertion_sequence",
+            "integron", "prophage", "integrative_element", "conjugative_transposon",
+        ],
+    ),
+    ("gene", "#2ca02c", 3, &["gene", "pseudogene"]),
+    ("transcript", "#17becf", 4, &["mRNA", "transcript", "exon"]),
+    ("ncrna", "#bcbd22", 5, &["ncRNA", "misc_RNA", "antisense_RNA", "RNase_P_RNA", "SRP_RNA", "riboswitch"]),
+    ("repeat", "#7f7f7f", 6, &["repeat_region", "inverted_repeat", "tandem_repeat"]),
+    ("region", "#c7c7c7", 9, &["region", "databank_entry", "source", "chromosome", "contig", "plasmid"]),
+];
+
+/// Colour, priority and category of types in no category.
+const DEFAULT_STYLE: (&str, &str, u32) = ("other", "#8c564b", 7);
+
+/// Colours of sources, the most frequent first; reused cyclically (and
+/// for the bins of `bins.rs`).
+pub(crate) const SOURCE_PALETTE: &[&str] = &["#4e79a7", "#f28e2b", "#59a14f", "#e15759", "#76b7b2", "#edc948", "#b07aa1", "#ff9da7", "#9c755f", "#bab0ac"];
+
+/// `(category, colour, priority)` of a feature type.
+pub fn type_style(ftype: &str) -> (&'static str, &'static str, u32) {
+    CATEGORIES
+        .iter()
+        .find(|(_, _, _, types)| types.iter().any(|t| t.eq_ignore_ascii_case(ftype)))
+        .map_or(DEFAULT_STYLE, |&(category, color, priority, _)| (category, color, priority))
+}
+
+/// The styles sidecar for a GFF3 with statistics `stats`.
+pub fn track_styles(stats: &GffStats) -> json::JsonValue {
+    let mut types = json::JsonValue::new_object();
+    for (ftype, &count) in &stats.types {
+        let (category, color, priority) = type_style(ftype);
+        types[ftype.as_str()] = json::object! { category: category, color: color, priority: priority, count: count };
+    }
+    let mut by_count: Vec<(&String, &usize)> = stats.sources.iter().collect();
+    by_count.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    let mut sources = json::JsonValue::new_object();
+    for (i, (source, &count)) in by_count.into_iter().enumerate() {
+        sources[source.as_str()] = json::object! { color: SOURCE_PALETTE[i % SOURCE_PALETTE.len()], count: count };
+    }
+    let (category, color, priority) = DEFAULT_STYLE;
+    json::object! {
+        styles_version: STYLES_VERSION,
+        types: types,
+        sources: sources,
+        default: { category: category, color: color, priority: priority },
+    }
+}
+
+/// Suggested display styles (JSON, see the module docs) for the feature
+/// types and sources of GFF3 text.
+#[wasm_bindgen]
+pub fn gff_track_styles(gff: &str) -> String {
+    track_styles(&GffStats::from_gff(gff)).dump()
+}
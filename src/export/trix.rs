@@ -0,0 +1,132 @@
+//! JBrowse 2 text search index (trix) for the annotation track.
+//!
+//! JBrowse's search box looks names up in a pair of static files,
+//! `TrixTextSearchAdapter`'s `.ix` and `.ixx`, as `jbrowse text-index`
+//! writes them with UCSC's `ixIxx`:
+//!
+//! - `.ix`: one line per lower-cased word, `word id,pos id,pos …`, sorted
+//!   by word; `pos` is the 1-based position of the word in the text of that
+//!   id.  The id of a feature is the JSON array `[location, track id,
+//!   values…]`, every element URI-encoded and commas replaced by `|`, so
+//!   JBrowse can show and open hits without fetching anything else.
+//! - `.ixx`: where to start reading the `.ix` for a word: the first
+//!   [`PREFIX_SIZE`] characters of a word and the `.ix` byte offset of its
+//!   line as 10 hexadecimal digits, at the first line and then every
+//!   [`BIN_SIZE`] bytes or more, where the prefix changes.
+//!
+//! The words are the whitespace-separated values of [`TRIX_KEYS`] of every
+//! feature but [`EXCLUDED_TYPES`]; a feature in several parts is one hit
+//! over its whole extent.
+
+use std::collections::BTreeMap;
+
+use wasm_bindgen::prelude::*;
+
+use crate::gff::multipart::group_parts;
+use crate::gff::{attribute_values, data_lines, GffFields};
+
+/// Attributes whose values are searchable.
+pub const TRIX_KEYS: [&str; 3] = ["Name", "ID", "product"];
+
+/// Feature types left out, as `jbrowse text-index` does by default for
+/// exons: their names repeat their transcript's.
+pub const EXCLUDED_TYPES: [&str; 1] = ["exon"];
+
+/// Characters of a word prefix in the `.ixx`.
+pub const PREFIX_SIZE: usize = 5;
+
+/// `.ix` bytes between `.ixx` entries, at least.
+pub const BIN_SIZE: usize = 64 * 1024;
+
+/// JavaScript's `encodeURIComponent`.
+fn encode_uri_component(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.!~*'()".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+/// `.ix` and `.ixx` of a trix index.
+#[wasm_bindgen]
+pub struct TrixIndex {
+    ix: Vec<u8>,
+    ixx: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl TrixIndex {
+    /// Moves the `.ix` bytes out. May only be called once meaningfully.
+    pub fn ix(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.ix)
+    }
+    /// Moves the `.ixx` bytes out. May only be called once meaningfully.
+    pub fn ixx(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.ixx)
+    }
+}
+
+impl TrixIndex {
+    /// The index of the features of `gff` for the JBrowse track `track_id`.
+    pub fn build(gff: &str, track_id: &str) -> Self {
+        // Word -> (id, position) in feature order.
+        let mut words: BTreeMap<String, Vec<(usize, usize)>> = BTreeMap::new();
+        let mut ids: Vec<String> = Vec::new();
+        let records = data_lines(gff).filter_map(GffFields::parse).filter(|f| !EXCLUDED_TYPES.contains(&f.ftype));
+        for feature in group_parts(records) {
+            let first = feature.first();
+            let mut values: Vec<String> = Vec::new();
+            for key in TRIX_KEYS {
+                for value in first.attribute(key).map(attribute_values).unwrap_or_default() {
+                    if !value.trim().is_empty() && !values.contains(&value) {
+                        values.push(value);
+                    }
+                }
+            }
+            if values.is_empty() {
+                continue;
+            }
+            let (start, end) = feature.extent();
+            let location = format!("{}:{}..{}", first.seqid, start, end);
+            let fields: Vec<String> =
+                [location.as_str(), track_id].into_iter().chain(values.iter().map(String::as_str)).map(|v| format!("\"{}\"", encode_uri_component(v))).collect();
+            let id = ids.len();
+            ids.push(format!("[{}]", fields.join("|")));
+            for (pos, word) in values.iter().flat_map(|v| v.split_whitespace()).enumerate() {
+                let hits = words.entry(word.to_lowercase()).or_default();
+                if !hits.iter().any(|&(i, _)| i == id) {
+                    hits.push((id, pos + 1));
+                }
+            }
+        }
+
+        let mut ix = String::new();
+        let mut ixx = String::new();
+        let mut last: Option<(usize, &str)> = None;
+        for (word, hits) in &words {
+            let prefix: String = word.chars().take(PREFIX_SIZE).collect();
+            let due = last.is_none_or(|(at, previous)| ix.len() - at >= BIN_SIZE && previous != prefix);
+            if due {
+                ixx.push_str(&format!("{}{:010X}\n", prefix, ix.len()));
+                last = Some((ix.len(), &word[..prefix.len()]));
+            }
+            ix.push_str(word);
+            for &(id, pos) in hits {
+                ix.push_str(&format!(" {},{}", ids[id], pos));
+            }
+            ix.push('\n');
+        }
+        TrixIndex { ix: ix.into_bytes(), ixx: ixx.into_bytes() }
+    }
+}
+
+/// The trix index of GFF3 text for the JBrowse track `track_id`.  See
+/// [`TrixIndex::build`].
+#[wasm_bindgen]
+pub fn gff_trix_index(gff: &str, track_id: &str) -> TrixIndex {
+    TrixIndex::build(gff, track_id)
+}
@@ -0,0 +1,172 @@
+//! In-memory FASTA access for features that need sequence data
+//! (translation, region extraction).
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io;
+
+use crate::error::{io_error, ErrorCode};
+use crate::htslib::LineLayout;
+use crate::scan;
+
+pub mod gaps;
+pub mod orfs;
+mod packed;
+pub mod record;
+pub mod repeats;
+pub mod rna;
+pub mod split;
+pub mod track;
+
+use packed::PackedSeq;
+pub use record::{utf8_name, FastaLine, FastaParser, FastaReader, FastaRecord, FastaRecords, FastaVisitor};
+
+/// Line width used by [`rewrap`] when the file has no data line to copy.
+pub const DEFAULT_LINE_WIDTH: usize = 60;
+
+/// Sequences of a FASTA file keyed by name (header text up to the first
+/// whitespace), with line breaks removed.  They are held packed (about two
+/// bits per base, see `fasta/packed.rs`) and decoded on access.
+#[derive(Default)]
+pub struct Sequences {
+    names: Vec<String>,
+    seqs: HashMap<String, PackedSeq>,
+}
+
+impl Sequences {
+    /// Parse an uncompressed FASTA file.
+    pub fn parse(fa: &[u8]) -> io::Result<Self> {
+        let mut out = Sequences::default();
+        let mut parser = FastaParser::new();
+        let mut seq = Vec::new();
+        for line in fa.split(|&b| b == b'\n') {
+            match parser.push(line) {
+                (FastaLine::Header { name, .. }, ended) => {
+                    utf8_name(name)?;
+                    if let Some(record) = ended {
+                        out.insert(record.name, std::mem::take(&mut seq))?;
+                    }
+                }
+                (FastaLine::Sequence(line), _) if parser.current().is_some() => seq.extend(line.iter().filter(|b| b.is_ascii_graphic())),
+                _ => {}
+            }
+        }
+        if let Some(record) = parser.finish() {
+            out.insert(record.name, seq)?;
+        }
+        Ok(out)
+    }
+
+    fn insert(&mut self, name: String, seq: Vec<u8>) -> io::Result<()> {
+        if self.seqs.contains_key(&name) {
+            return Err(io_error(ErrorCode::FastaDupName, format!("duplicate sequence name {:?}", name)));
+        }
+        self.names.push(name.clone());
+        self.seqs.insert(name, PackedSeq::new(&seq));
+        Ok(())
+    }
+
+    /// Sequence names in file order.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Length of `name` in bases.
+    pub fn seq_len(&self, name: &str) -> Option<usize> {
+        self.seqs.get(name).map(PackedSeq::len)
+    }
+
+    /// Full sequence of `name`.
+    pub fn get(&self, name: &str) -> Option<Vec<u8>> {
+        let seq = self.seqs.get(name)?;
+        Some(seq.slice(0..seq.len()))
+    }
+
+    /// Bases `start..=end` (1-based, inclusive) of `name`, if in range.
+    pub fn region(&self, name: &str, start: u64, end: u64) -> Option<Vec<u8>> {
+        let seq = self.seqs.get(name)?;
+        if start == 0 || start > end || end as usize > seq.len() {
+            return None;
+        }
+        Some(seq.slice(start as usize - 1..end as usize))
+    }
+
+    /// Heap bytes held for the sequence data (names excluded).
+    pub fn resident_bytes(&self) -> usize {
+        self.seqs.values().map(PackedSeq::resident_bytes).sum()
+    }
+}
+
+/// 1-based number of the first line that breaks the `.fai` line layout
+/// rule (a data line after a line of a different length), if any.
+pub fn first_irregular_line(fa: &[u8]) -> Option<usize> {
+    let mut layout = LineLayout::default();
+    for (i, line) in fa.split_inclusive(|&b| b == b'\n').enumerate() {
+        if line.starts_with(b">") {
+            layout = LineLayout::default();
+        } else if line[0] == b'\n' || line[0] == b'\r' {
+            layout.blank();
+        } else if !layout.data(line.len(), scan::count_graphic(line)) {
+            return Some(i + 1);
+        }
+    }
+    None
+}
+
+/// Rewrite every sequence with `width` bases per line (`\n`-terminated).
+/// Header lines are kept as they are; blank lines are dropped.
+pub fn rewrap(fa: &[u8], width: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(fa.len() + fa.len() / width.max(1) + 1);
+    let mut col = 0;
+    for line in fa.split_inclusive(|&b| b == b'\n') {
+        if line.starts_with(b">") {
+            if col > 0 {
+                out.push(b'\n');
+                col = 0;
+            }
+            out.extend_from_slice(line);
+            if !line.ends_with(b"\n") {
+                out.push(b'\n');
+            }
+            continue;
+        }
+        for &b in line.iter().filter(|b| b.is_ascii_graphic()) {
+            out.push(b);
+            col += 1;
+            if col == width {
+                out.push(b'\n');
+                col = 0;
+            }
+        }
+    }
+    if col > 0 {
+        out.push(b'\n');
+    }
+    out
+}
+
+/// `fa` with every `\r\n` turned into `\n`; borrowed if there is none.
+pub fn strip_cr(fa: &[u8]) -> Cow<'_, [u8]> {
+    if !fa.windows(2).any(|w| w == b"\r\n") {
+        return Cow::Borrowed(fa);
+    }
+    let mut out = Vec::with_capacity(fa.len());
+    for line in fa.split_inclusive(|&b| b == b'\n') {
+        match line.strip_suffix(b"\r\n") {
+            Some(body) => {
+                out.extend_from_slice(body);
+                out.push(b'\n');
+            }
+            None => out.extend_from_slice(line),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Bases on the first data line, the width [`rewrap`] keeps by default.
+pub fn first_line_width(fa: &[u8]) -> Option<usize> {
+    fa.split(|&b| b == b'\n')
+        .filter(|l| !l.starts_with(b">"))
+        .map(|l| l.iter().filter(|b| b.is_ascii_graphic()).count())
+        .find(|&n| n > 0)
+}
@@ -0,0 +1,167 @@
+//! Assembly gaps: runs of `N` in a sequence, and the gap track.
+//!
+//! With `IndexGenOptions::gap_track` set, the FASTA pass lists every run of
+//! at least `gap_min_length` `N`s (or `n`s) of the prepared FASTA as the
+//! `gaps` track (see `track.rs`), which browsers can show next to the
+//! annotation (columns tab-separated):
+//!
+//! ```text
+//! scaf1  10  22  gap_1                                            (BED, 0-based)
+//! scaf1  .  gap  11  22  .  .  .  ID=gap_1;estimated_length=12   (GFF3)
+//! ```
+//!
+//! The same scan gives the gap figures of
+//! [`FastaStats`](crate::stats::FastaStats).
+
+use std::io;
+use std::ops::Range;
+
+use super::track::{SequenceTrack, TrackFeature};
+use super::{FastaLine, FastaParser};
+use crate::options::TrackFormat;
+use crate::scan;
+
+/// Run length from which an `N` run is a gap unless a caller says
+/// otherwise, as NCBI and ENA count them.
+pub const DEFAULT_MIN_GAP: u64 = 10;
+
+/// 0-based half-open ranges of the runs of `N` or `n` at least `min_len`
+/// long in `seq` (bases only, without line breaks).
+pub fn n_runs(seq: &[u8], min_len: u64) -> Vec<Range<u64>> {
+    let mut finder = GapFinder::new(min_len);
+    finder.push(seq);
+    finder.finish()
+}
+
+/// Finds gaps in a sequence fed a line at a time.
+#[derive(Clone, Debug)]
+pub struct GapFinder {
+    min_len: u64,
+    /// Bases seen so far.
+    pos: u64,
+    /// Start of the `N` run the sequence currently ends in.
+    run: Option<u64>,
+    runs: Vec<Range<u64>>,
+}
+
+impl GapFinder {
+    pub fn new(min_len: u64) -> Self {
+        GapFinder { min_len, pos: 0, run: None, runs: Vec::new() }
+    }
+
+    /// Feed the next sequence line; bytes other than bases (line endings,
+    /// spaces) are skipped.
+    pub fn push(&mut self, line: &[u8]) {
+        for &b in line.iter().filter(|b| b.is_ascii_graphic()) {
+            match (b == b'N' || b == b'n', self.run) {
+                (true, None) => self.run = Some(self.pos),
+                (false, Some(start)) => self.close(start),
+                _ => {}
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn close(&mut self, start: u64) {
+        if self.pos - start >= self.min_len {
+            self.runs.push(start..self.pos);
+        }
+        self.run = None;
+    }
+
+    /// The gaps of the sequence, in order; the finder starts over.
+    pub fn finish(&mut self) -> Vec<Range<u64>> {
+        if let Some(start) = self.run {
+            self.close(start);
+        }
+        self.pos = 0;
+        std::mem::take(&mut self.runs)
+    }
+}
+
+/// Gap figures of an assembly.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GapStats {
+    /// Shortest run of `N`s counted.
+    pub min_gap: u64,
+    pub count: u64,
+    /// Bases in gaps.
+    pub total_length: u64,
+    pub max_length: u64,
+    /// Sequences with at least one gap.
+    pub sequences: u64,
+}
+
+impl GapStats {
+    /// Count the gaps of one sequence.
+    pub fn add(&mut self, gaps: &[Range<u64>]) {
+        self.count += gaps.len() as u64;
+        self.sequences += u64::from(!gaps.is_empty());
+        for gap in gaps {
+            self.total_length += gap.end - gap.start;
+            self.max_length = self.max_length.max(gap.end - gap.start);
+        }
+    }
+
+    pub fn to_json(&self) -> json::JsonValue {
+        json::object! {
+            min_gap: self.min_gap,
+            count: self.count,
+            total_length: self.total_length,
+            max_length: self.max_length,
+            sequences: self.sequences,
+        }
+    }
+
+    /// The figures [`GapStats::to_json`] wrote.
+    pub fn from_json(value: &json::JsonValue) -> Self {
+        let field = |name: &str| value[name].as_u64().unwrap_or(0);
+        GapStats {
+            min_gap: field("min_gap"),
+            count: field("count"),
+            total_length: field("total_length"),
+            max_length: field("max_length"),
+            sequences: field("sequences"),
+        }
+    }
+}
+
+/// The gaps of every sequence of an uncompressed FASTA, by name, in file
+/// order (sequences without gaps included).
+pub fn fasta_gaps(fa: &[u8], min_len: u64) -> Vec<(String, Vec<Range<u64>>)> {
+    let mut out = Vec::new();
+    let mut finder = GapFinder::new(min_len);
+    let mut parser = FastaParser::new();
+    let mut end = |record: Option<super::FastaRecord>, finder: &mut GapFinder| {
+        if let Some(record) = record {
+            out.push((record.name, finder.finish()));
+        }
+    };
+    for line in scan::split_lines(fa) {
+        match parser.push(line) {
+            (FastaLine::Header { .. }, ended) => end(ended, &mut finder),
+            (FastaLine::Sequence(line), _) if parser.current().is_some() => finder.push(line),
+            _ => {}
+        }
+    }
+    end(parser.finish(), &mut finder);
+    out
+}
+
+/// The `gaps` track of `fa`: its runs of at least `min_len` `N`s as `gap`
+/// features, compressed at `level`, with [`GapStats`] as its figures.
+pub fn gap_track(fa: &[u8], min_len: u64, format: TrackFormat, level: u32) -> io::Result<SequenceTrack> {
+    let mut stats = GapStats { min_gap: min_len, ..Default::default() };
+    let sequences = fasta_gaps(fa, min_len)
+        .into_iter()
+        .map(|(name, gaps)| {
+            stats.add(&gaps);
+            let features = gaps
+                .into_iter()
+                .map(|gap| TrackFeature { start: gap.start, end: gap.end, strand: None, kind: "gap", attributes: format!("estimated_length={}", gap.end - gap.start) })
+                .collect();
+            (name, features)
+        })
+        .collect();
+    SequenceTrack::write("gaps", "gap", format, sequences, stats.to_json(), level)
+}
@@ -0,0 +1,174 @@
+//! Six-frame open reading frames, and the ORF track.
+//!
+//! For assemblies uploaded without annotation, a provisional track gives
+//! the browser something to show before a real gene caller has run.  Each
+//! sequence is read in its three forward and three reverse frames with
+//! the genetic code table of `IndexGenOptions::genetic_code` (default 11);
+//! an ORF runs from the first start codon after a stop (any start of the
+//! table: `ATG`, `GTG`, `TTG`, … for table 11) through the next
+//! stop, and is kept when it spans at least `orf_min_length` bases (default
+//! 300, stop included).  A codon with a base other than `ACGT` (a gap, an
+//! IUPAC code) closes the frame without an ORF, as does the end of the
+//! sequence, so ORFs never run into gaps or off an edge.
+//!
+//! With `IndexGenOptions::orf_track` set they become the `orfs` track (see
+//! `track.rs`), `ORF` features with a strand and
+//! `start_codon=<codon>;aa_length=<n>`, sorted and indexed like the other
+//! tracks.  Nested and overlapping ORFs in other frames are all reported;
+//! this is not a gene prediction.
+
+use std::io;
+
+use super::track::{SequenceTrack, TrackFeature};
+use super::{FastaLine, FastaParser};
+use crate::options::TrackFormat;
+use crate::scan;
+use crate::translate::{reverse_complement, GeneticCode};
+
+/// Default shortest ORF, in bases (100 codons).
+pub const DEFAULT_MIN_ORF: u64 = 300;
+
+/// An open reading frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Orf {
+    /// 0-based half-open range on the sequence, stop codon included.
+    pub start: u64,
+    pub end: u64,
+    /// `+` or `-`.
+    pub strand: char,
+    /// Upper case.
+    pub start_codon: [u8; 3],
+}
+
+impl Orf {
+    /// Amino acids of the translation, the stop not counted.
+    pub fn aa_length(&self) -> u64 {
+        (self.end - self.start) / 3 - 1
+    }
+}
+
+/// The ORFs of `seq` on its forward strand at least `min_len` bases long,
+/// as `(start, end, start codon)`, frame by frame.
+fn forward_orfs(seq: &[u8], code: &GeneticCode, min_len: u64) -> Vec<(usize, usize, [u8; 3])> {
+    let mut out = Vec::new();
+    for frame in 0..3 {
+        let mut open: Option<(usize, [u8; 3])> = None;
+        for (k, codon) in seq.get(frame..).unwrap_or_default().chunks_exact(3).enumerate() {
+            let at = frame + 3 * k;
+            if code.is_stop(codon) {
+                if let Some((start, start_codon)) = open.take().filter(|(start, _)| (at + 3 - start) as u64 >= min_len) {
+                    out.push((start, at + 3, start_codon));
+                }
+            } else if !codon.iter().all(|b| matches!(b, b'A' | b'C' | b'G' | b'T')) {
+                open = None;
+            } else if open.is_none() && code.is_start(codon) {
+                open = Some((at, [codon[0], codon[1], codon[2]]));
+            }
+        }
+    }
+    out
+}
+
+/// The ORFs of `seq` (bases only, any case) on both strands at least
+/// `min_len` bases long, forward strand first.
+pub fn find_orfs(seq: &[u8], code: &GeneticCode, min_len: u64) -> Vec<Orf> {
+    let seq = seq.to_ascii_uppercase();
+    let forward = forward_orfs(&seq, code, min_len).into_iter().map(|(start, end, start_codon)| Orf {
+        start: start as u64,
+        end: end as u64,
+        strand: '+',
+        start_codon,
+    });
+    let len = seq.len();
+    let reverse = forward_orfs(&reverse_complement(&seq), code, min_len).into_iter().map(|(start, end, start_codon)| Orf {
+        start: (len - end) as u64,
+        end: (len - start) as u64,
+        strand: '-',
+        start_codon,
+    });
+    forward.chain(reverse).collect()
+}
+
+/// ORF figures of an assembly.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OrfStats {
+    pub min_length: u64,
+    /// NCBI genetic code table read with.
+    pub genetic_code: u32,
+    pub count: u64,
+    pub forward: u64,
+    pub reverse: u64,
+    /// Longest ORF in bases.
+    pub max_length: u64,
+}
+
+impl OrfStats {
+    /// Count the ORFs of one sequence.
+    pub fn add(&mut self, orfs: &[Orf]) {
+        for orf in orfs {
+            self.count += 1;
+            if orf.strand == '+' {
+                self.forward += 1;
+            } else {
+                self.reverse += 1;
+            }
+            self.max_length = self.max_length.max(orf.end - orf.start);
+        }
+    }
+
+    pub fn to_json(&self) -> json::JsonValue {
+        json::object! {
+            min_length: self.min_length,
+            genetic_code: self.genetic_code,
+            count: self.count,
+            forward: self.forward,
+            reverse: self.reverse,
+            max_length: self.max_length,
+        }
+    }
+}
+
+/// The ORFs of every sequence of an uncompressed FASTA under genetic code
+/// `table`, by name, in file order (sequences without ORFs included).
+/// Sequences are read whole, one at a time, for their reverse strand.
+pub fn fasta_orfs(fa: &[u8], min_len: u64, table: u32) -> io::Result<Vec<(String, Vec<Orf>)>> {
+    let code = GeneticCode::table(table)?;
+    let mut out = Vec::new();
+    let mut seq = Vec::new();
+    let mut parser = FastaParser::new();
+    for line in scan::split_lines(fa) {
+        match parser.push(line) {
+            (FastaLine::Header { .. }, Some(record)) => {
+                out.push((record.name, find_orfs(&seq, &code, min_len)));
+                seq.clear();
+            }
+            (FastaLine::Sequence(line), _) if parser.current().is_some() => seq.extend(line.iter().filter(|b| b.is_ascii_graphic())),
+            _ => {}
+        }
+    }
+    if let Some(record) = parser.finish() {
+        out.push((record.name, find_orfs(&seq, &code, min_len)));
+    }
+    Ok(out)
+}
+
+/// The `orfs` track of `fa` under genetic code `table`, compressed at
+/// `level`, with [`OrfStats`] as its figures.
+pub fn orf_track(fa: &[u8], min_len: u64, table: u32, format: TrackFormat, level: u32) -> io::Result<SequenceTrack> {
+    let mut stats = OrfStats { min_length: min_len, genetic_code: table, ..Default::default() };
+    let sequences = fasta_orfs(fa, min_len, table)?
+        .into_iter()
+        .map(|(name, orfs)| {
+            stats.add(&orfs);
+            let features = orfs
+                .iter()
+                .map(|orf| {
+                    let attributes = format!("start_codon={};aa_length={}", String::from_utf8_lossy(&orf.start_codon), orf.aa_length());
+                    TrackFeature { start: orf.start, end: orf.end, strand: Some(orf.strand), kind: "ORF", attributes }
+                })
+                .collect();
+            (name, features)
+        })
+        .collect();
+    SequenceTrack::write("orfs", "orf", format, sequences, stats.to_json(), level)
+}
@@ -0,0 +1,142 @@
+//! Packed storage for resident sequences.
+//!
+//! Nucleotide FASTA is almost all `ACGT`, so each sequence is held at two
+//! bits per base.  Bytes outside that alphabet (`N` runs, IUPAC codes,
+//! anything else) go in a run-length exception list, and soft-masked
+//! (lowercase) stretches in a list of ranges.  A sequence rich in ambiguity
+//! codes is held at four bits per base instead (the BAM `=ACMGRSVTWYHKDBN`
+//! alphabet), and one that neither suits (protein) stays as raw bytes;
+//! each sequence gets whichever of the three is smallest.
+
+use std::mem::size_of;
+use std::ops::Range;
+
+const ALPHABET_2: &[u8; 4] = b"ACGT";
+const ALPHABET_4: &[u8; 16] = b"=ACMGRSVTWYHKDBN";
+
+/// `len` copies of `byte` from `start`, which the packed codes cannot hold.
+#[derive(Clone, Copy, Debug)]
+struct Run {
+    start: usize,
+    len: usize,
+    byte: u8,
+}
+
+impl Run {
+    fn end(&self) -> usize {
+        self.start + self.len
+    }
+}
+
+/// One sequence, packed.
+#[derive(Debug)]
+pub(crate) struct PackedSeq {
+    len: usize,
+    /// Bits per base: 2, 4 or 8 (raw bytes, no exceptions or case ranges).
+    width: u8,
+    data: Vec<u8>,
+    /// Sorted, non-overlapping; stored upper case.
+    exceptions: Vec<Run>,
+    /// Sorted, non-overlapping lowercase ranges.
+    lower: Vec<Range<usize>>,
+}
+
+impl PackedSeq {
+    pub(crate) fn new(seq: &[u8]) -> Self {
+        let lower = lower_ranges(seq);
+        let runs2 = exception_runs(seq, ALPHABET_2);
+        let runs4 = exception_runs(seq, ALPHABET_4);
+        let cost = |width: usize, runs: usize| {
+            seq.len().div_ceil(8 / width) + runs * size_of::<Run>() + lower.len() * size_of::<Range<usize>>()
+        };
+        let (cost2, cost4) = (cost(2, runs2.len()), cost(4, runs4.len()));
+        if cost2.min(cost4) >= seq.len() {
+            return PackedSeq { len: seq.len(), width: 8, data: seq.to_vec(), exceptions: Vec::new(), lower: Vec::new() };
+        }
+        let (width, alphabet, mut exceptions) =
+            if cost2 <= cost4 { (2, &ALPHABET_2[..], runs2) } else { (4, &ALPHABET_4[..], runs4) };
+        let per_byte = 8 / width;
+        let mut data = vec![0u8; seq.len().div_ceil(per_byte)];
+        for (i, &b) in seq.iter().enumerate() {
+            let code = alphabet.iter().position(|&a| a == b.to_ascii_uppercase()).unwrap_or(0) as u8;
+            data[i / per_byte] |= code << ((i % per_byte) * width);
+        }
+        exceptions.shrink_to_fit();
+        let mut lower = lower;
+        lower.shrink_to_fit();
+        PackedSeq { len: seq.len(), width: width as u8, data, exceptions, lower }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Heap bytes held for this sequence.
+    pub(crate) fn resident_bytes(&self) -> usize {
+        self.data.capacity()
+            + self.exceptions.capacity() * size_of::<Run>()
+            + self.lower.capacity() * size_of::<Range<usize>>()
+    }
+
+    /// Bases `range` (0-based, half-open) as the original bytes.
+    pub(crate) fn slice(&self, range: Range<usize>) -> Vec<u8> {
+        let range = range.start.min(self.len)..range.end.min(self.len);
+        if self.width == 8 {
+            return self.data[range].to_vec();
+        }
+        let (width, per_byte) = (self.width as usize, 8 / self.width as usize);
+        let alphabet = if width == 2 { &ALPHABET_2[..] } else { &ALPHABET_4[..] };
+        let mask = (1u8 << width) - 1;
+        let mut out: Vec<u8> = range
+            .clone()
+            .map(|i| alphabet[((self.data[i / per_byte] >> ((i % per_byte) * width)) & mask) as usize])
+            .collect();
+        let first = self.exceptions.partition_point(|run| run.end() <= range.start);
+        for run in self.exceptions[first..].iter().take_while(|run| run.start < range.end) {
+            let (from, to) = (run.start.max(range.start), run.end().min(range.end));
+            out[from - range.start..to - range.start].fill(run.byte);
+        }
+        let first = self.lower.partition_point(|r| r.end <= range.start);
+        for r in self.lower[first..].iter().take_while(|r| r.start < range.end) {
+            let (from, to) = (r.start.max(range.start), r.end.min(range.end));
+            out[from - range.start..to - range.start].make_ascii_lowercase();
+        }
+        out
+    }
+}
+
+/// Runs of (upper-cased) bytes outside `alphabet`.
+fn exception_runs(seq: &[u8], alphabet: &[u8]) -> Vec<Run> {
+    let mut runs: Vec<Run> = Vec::new();
+    for (i, &b) in seq.iter().enumerate() {
+        let b = b.to_ascii_uppercase();
+        if alphabet.contains(&b) {
+            continue;
+        }
+        match runs.last_mut() {
+            Some(run) if run.end() == i && run.byte == b => run.len += 1,
+            _ => runs.push(Run { start: i, len: 1, byte: b }),
+        }
+    }
+    runs
+}
+
+/// Lowercase stretches of `seq`.  Non-letters keep whatever case surrounds
+/// them (lowercasing leaves them unchanged), so `acg-t` is one range.
+fn lower_ranges(seq: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    let mut in_lower = false;
+    for (i, &b) in seq.iter().enumerate() {
+        if b.is_ascii_alphabetic() {
+            in_lower = b.is_ascii_lowercase();
+        }
+        if !in_lower {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(r) if r.end == i => r.end += 1,
+            _ => ranges.push(i..i + 1),
+        }
+    }
+    ranges
+}
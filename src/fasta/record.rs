@@ -0,0 +1,267 @@
+//! Record-level FASTA parsing shared by faidx, statistics, translation and
+//! validation.
+//!
+//! [`FastaParser`] is the core: fed one line at a time, from memory or from
+//! a BGZF reader that also tracks offsets, it classifies each line and
+//! keeps the current [`FastaRecord`] (name, description, bases so far).
+//! [`FastaReader`] drives it over any [`BufRead`], either record by record
+//! or with per-record callbacks ([`FastaVisitor`]) that also see the
+//! sequence chunks, and [`FastaRecords`] is the iterator-style JS form, for
+//! listing contigs as they are found:
+//!
+//! ```js
+//! const records = new FastaRecords(bytes);
+//! for (let r; (r = records.next()) !== undefined; ) {
+//!   const { name, description, length } = JSON.parse(r);
+//! }
+//! ```
+//!
+//! A name is the header text up to the first space or tab, the description
+//! the rest; the length counts the printable bytes of the sequence lines.
+
+use std::io::{self, BufRead, Cursor};
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{io_error, js_error, ErrorCode};
+use crate::health;
+use crate::scan;
+
+/// One line of a FASTA file, without its line ending.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FastaLine<'a> {
+    /// A `>` line, split into name and description.
+    Header { name: &'a [u8], description: &'a [u8] },
+    /// A sequence line; before the first header, stray text.
+    Sequence(&'a [u8]),
+    /// An empty line.
+    Blank,
+}
+
+/// A FASTA record as far as it has been read.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FastaRecord {
+    pub name: String,
+    pub description: String,
+    /// Bases (printable bytes of the sequence lines).
+    pub length: u64,
+    /// 1-based line number of the header.
+    pub line: usize,
+}
+
+impl FastaRecord {
+    /// `{name, description, length, line}`.
+    pub fn to_json(&self) -> json::JsonValue {
+        json::object! { name: self.name.as_str(), description: self.description.as_str(), length: self.length, line: self.line }
+    }
+}
+
+/// `name` as UTF-8, the way indexes need it.
+pub fn utf8_name(name: &[u8]) -> io::Result<&str> {
+    std::str::from_utf8(name).map_err(|_| io_error(ErrorCode::FastaParse, "non-UTF8 sequence name"))
+}
+
+/// Line-at-a-time FASTA state: the current record and the line count.
+#[derive(Debug, Default)]
+pub struct FastaParser {
+    current: Option<FastaRecord>,
+    line: usize,
+}
+
+impl FastaParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the next line (with or without its line ending).  Returns the
+    /// line and, when it is a header, the record it ends.
+    pub fn push<'a>(&mut self, line: &'a [u8]) -> (FastaLine<'a>, Option<FastaRecord>) {
+        self.line += 1;
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if let Some(header) = line.strip_prefix(b">") {
+            let name_end = header.iter().position(|&b| b == b' ' || b == b'\t').unwrap_or(header.len());
+            let (name, description) = (&header[..name_end], header[name_end..].trim_ascii());
+            let next = FastaRecord {
+                name: String::from_utf8_lossy(name).into_owned(),
+                description: String::from_utf8_lossy(description).into_owned(),
+                length: 0,
+                line: self.line,
+            };
+            return (FastaLine::Header { name, description }, self.current.replace(next));
+        }
+        if line.is_empty() {
+            return (FastaLine::Blank, None);
+        }
+        if let Some(record) = self.current.as_mut() {
+            record.length += scan::count_graphic(line) as u64;
+        }
+        (FastaLine::Sequence(line), None)
+    }
+
+    /// The record being read.
+    pub fn current(&self) -> Option<&FastaRecord> {
+        self.current.as_ref()
+    }
+
+    /// Lines read so far.
+    pub fn lines_read(&self) -> usize {
+        self.line
+    }
+
+    /// End of input: the last record.
+    pub fn finish(&mut self) -> Option<FastaRecord> {
+        self.current.take()
+    }
+}
+
+/// Callbacks of [`FastaReader::visit`], all defaulting to nothing.
+pub trait FastaVisitor {
+    /// A header was read.
+    fn record_start(&mut self, _record: &FastaRecord) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// A sequence line of `record` (its length already counted), without
+    /// its line ending.
+    fn sequence(&mut self, _record: &FastaRecord, _chunk: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// `record` is complete.
+    fn record_end(&mut self, _record: FastaRecord) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Streams the records of a FASTA file from a reader, a line at a time.
+/// Names must be UTF-8 (`E_FASTA_PARSE`, after the record before has been
+/// returned); text before the first header is skipped.
+pub struct FastaReader<R> {
+    reader: R,
+    parser: FastaParser,
+    buf: Vec<u8>,
+    /// A bad header, reported once the record it ends is out.
+    pending: Option<io::Error>,
+    done: bool,
+}
+
+impl<R: BufRead> FastaReader<R> {
+    pub fn new(reader: R) -> Self {
+        FastaReader { reader, parser: FastaParser::new(), buf: Vec::new(), pending: None, done: false }
+    }
+
+    /// Lines read so far.
+    pub fn lines_read(&self) -> usize {
+        self.parser.lines_read()
+    }
+
+    /// Fail with a pending error, ending the input.
+    fn check(&mut self) -> io::Result<()> {
+        match self.pending.take() {
+            Some(e) => {
+                self.done = true;
+                self.parser.finish();
+                Err(e)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Read a line; `None` at the end of the input.  Borrows the fields
+    /// one by one, so the parser stays readable while the line is in use.
+    fn step<'b>(
+        reader: &mut R,
+        parser: &mut FastaParser,
+        buf: &'b mut Vec<u8>,
+        pending: &mut Option<io::Error>,
+        done: &mut bool,
+    ) -> io::Result<Option<(FastaLine<'b>, Option<FastaRecord>)>> {
+        buf.clear();
+        if *done || reader.read_until(b'\n', buf)? == 0 {
+            *done = true;
+            return Ok(None);
+        }
+        let (line, ended) = parser.push(buf);
+        if let FastaLine::Header { name, .. } = line {
+            *pending = utf8_name(name).err().map(|e| io_error(ErrorCode::FastaParse, format!("line {}: {}", parser.lines_read(), e)));
+        }
+        Ok(Some((line, ended)))
+    }
+
+    /// The next complete record, or `None` after the last.
+    pub fn next_record(&mut self) -> io::Result<Option<FastaRecord>> {
+        self.check()?;
+        while let Some((_, ended)) = Self::step(&mut self.reader, &mut self.parser, &mut self.buf, &mut self.pending, &mut self.done)? {
+            if ended.is_some() {
+                return Ok(ended);
+            }
+            if self.pending.is_some() {
+                self.check()?;
+            }
+        }
+        Ok(self.parser.finish())
+    }
+
+    /// Read the rest of the input, calling `visitor` for each record and
+    /// sequence line.
+    pub fn visit(&mut self, visitor: &mut impl FastaVisitor) -> io::Result<()> {
+        self.check()?;
+        while let Some((line, ended)) = Self::step(&mut self.reader, &mut self.parser, &mut self.buf, &mut self.pending, &mut self.done)? {
+            if let Some(record) = ended {
+                visitor.record_end(record)?;
+            }
+            if self.pending.is_some() {
+                return self.check();
+            }
+            let Some(current) = self.parser.current() else { continue };
+            match line {
+                FastaLine::Header { .. } => visitor.record_start(current)?,
+                FastaLine::Sequence(chunk) => visitor.sequence(current, chunk)?,
+                FastaLine::Blank => {}
+            }
+        }
+        match self.parser.finish() {
+            Some(record) => visitor.record_end(record),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for FastaReader<R> {
+    type Item = io::Result<FastaRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+/// The records of an uncompressed FASTA file, one at a time (see the
+/// module docs).
+#[wasm_bindgen]
+pub struct FastaRecords {
+    reader: FastaReader<Cursor<Vec<u8>>>,
+}
+
+#[wasm_bindgen]
+impl FastaRecords {
+    #[wasm_bindgen(constructor)]
+    pub fn new(fa: Vec<u8>) -> FastaRecords {
+        FastaRecords { reader: FastaReader::new(Cursor::new(fa)) }
+    }
+
+    /// The next record as JSON, `{name, description, length, line}`, or
+    /// `undefined` after the last.  A non-UTF-8 name throws
+    /// `E_FASTA_PARSE`, after which iteration ends.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<String>, JsValue> {
+        health::contain(|| self.reader.next().transpose().map(|r| r.map(|r| r.to_json().dump()))).map_err(js_error)
+    }
+
+    /// Lines read so far.
+    #[wasm_bindgen(getter)]
+    pub fn lines_read(&self) -> usize {
+        self.reader.lines_read()
+    }
+}
+
@@ -0,0 +1,213 @@
+//! Homopolymers and simple tandem repeats, and the repeat track.
+//!
+//! A quick look at why an annotation looks odd (a frameshift in a poly-A
+//! run, a gene broken at a microsatellite) without a server-side masker.
+//! [`RepeatFinder`] takes a sequence a line at a time and, for every
+//! period from 1 to 6, follows how far each base repeats the one a period
+//! back.  A stretch of
+//!
+//! - one base repeated `homopolymer_min_length` (default 8) times or more
+//!   is a homopolymer;
+//! - a unit of 2 to 6 bases, not itself a repeat of a shorter one, spanning
+//!   `tandem_min_length` (default 12) bases or more and at least 3 copies
+//!   is a tandem repeat.
+//!
+//! Only exact copies count, case is ignored, and `N`s never repeat (gaps
+//! are `gaps.rs`'s).  With `IndexGenOptions::repeat_track` set they become
+//! the `repeats` track (see `track.rs`), `tandem_repeat` features with
+//! `rpt_type=tandem;rpt_unit_seq=<unit>;copies=<n>`.
+
+use std::io;
+
+use super::track::{SequenceTrack, TrackFeature};
+use super::{FastaLine, FastaParser};
+use crate::options::TrackFormat;
+use crate::scan;
+
+/// Default shortest homopolymer.
+pub const DEFAULT_MIN_HOMOPOLYMER: u64 = 8;
+
+/// Default shortest tandem repeat, in bases.
+pub const DEFAULT_MIN_TANDEM: u64 = 12;
+
+/// Longest repeat unit followed.
+pub const MAX_PERIOD: usize = 6;
+
+/// Fewest copies of a unit of 2 or more bases.
+const MIN_COPIES: u64 = 3;
+
+/// A homopolymer (unit of one base) or tandem repeat.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Repeat {
+    /// 0-based half-open range on the sequence.
+    pub start: u64,
+    pub end: u64,
+    /// Upper case.
+    pub unit: Vec<u8>,
+}
+
+impl Repeat {
+    /// Copies of the unit, the last one possibly partial.
+    pub fn copies(&self) -> f64 {
+        (self.end - self.start) as f64 / self.unit.len() as f64
+    }
+}
+
+/// Finds repeats in a sequence fed a line at a time.
+#[derive(Clone, Debug)]
+pub struct RepeatFinder {
+    min_homopolymer: u64,
+    min_tandem: u64,
+    /// Bases seen so far.
+    pos: u64,
+    /// The last [`MAX_PERIOD`] bases, base `i` at `i % MAX_PERIOD`.
+    recent: [u8; MAX_PERIOD],
+    /// Per period (index 0 for period 1): bases in a row equal to the one
+    /// a period back, and the unit that run started with.
+    runs: [(u64, [u8; MAX_PERIOD]); MAX_PERIOD],
+    found: Vec<Repeat>,
+}
+
+impl RepeatFinder {
+    pub fn new(min_homopolymer: u64, min_tandem: u64) -> Self {
+        RepeatFinder { min_homopolymer, min_tandem, pos: 0, recent: [0; MAX_PERIOD], runs: [(0, [0; MAX_PERIOD]); MAX_PERIOD], found: Vec::new() }
+    }
+
+    /// Feed the next sequence line; bytes other than bases (line endings,
+    /// spaces) are skipped.
+    pub fn push(&mut self, line: &[u8]) {
+        for &b in line.iter().filter(|b| b.is_ascii_graphic()) {
+            let b = b.to_ascii_uppercase();
+            for period in 1..=MAX_PERIOD {
+                let back = self.pos.checked_sub(period as u64).map(|i| self.recent[i as usize % MAX_PERIOD]);
+                if b != b'N' && back == Some(b) {
+                    if self.runs[period - 1].0 == 0 {
+                        let mut unit = [0; MAX_PERIOD];
+                        for (k, u) in unit[..period].iter_mut().enumerate() {
+                            *u = self.recent[(self.pos as usize - period + k) % MAX_PERIOD];
+                        }
+                        self.runs[period - 1].1 = unit;
+                    }
+                    self.runs[period - 1].0 += 1;
+                } else {
+                    self.close(period);
+                }
+            }
+            self.recent[self.pos as usize % MAX_PERIOD] = b;
+            self.pos += 1;
+        }
+    }
+
+    /// End the run of `period` at the current position, keeping it if it
+    /// is long enough.
+    fn close(&mut self, period: usize) {
+        let (matched, unit) = std::mem::take(&mut self.runs[period - 1]);
+        if matched == 0 {
+            return;
+        }
+        let len = matched + period as u64;
+        let unit = &unit[..period];
+        let keep = if period == 1 {
+            len >= self.min_homopolymer
+        } else {
+            len >= self.min_tandem && len >= MIN_COPIES * period as u64 && primitive(unit) && !unit.contains(&b'N')
+        };
+        if keep {
+            self.found.push(Repeat { start: self.pos - len, end: self.pos, unit: unit.to_vec() });
+        }
+    }
+
+    /// The repeats of the sequence, by end position; the finder starts over.
+    pub fn finish(&mut self) -> Vec<Repeat> {
+        for period in 1..=MAX_PERIOD {
+            self.close(period);
+        }
+        self.pos = 0;
+        std::mem::take(&mut self.found)
+    }
+}
+
+/// Whether `unit` is not a shorter unit repeated (`ATAT` is `AT` twice).
+fn primitive(unit: &[u8]) -> bool {
+    (1..unit.len()).filter(|d| unit.len().is_multiple_of(*d)).all(|d| unit[d..] != unit[..unit.len() - d])
+}
+
+/// Repeat figures of an assembly.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RepeatStats {
+    pub min_homopolymer: u64,
+    pub min_tandem: u64,
+    pub homopolymers: u64,
+    pub tandem_repeats: u64,
+    /// Lengths of the repeats summed, so bases in overlapping ones count
+    /// more than once.
+    pub total_length: u64,
+    pub max_length: u64,
+}
+
+impl RepeatStats {
+    /// Count the repeats of one sequence.
+    pub fn add(&mut self, repeats: &[Repeat]) {
+        for r in repeats {
+            if r.unit.len() == 1 {
+                self.homopolymers += 1;
+            } else {
+                self.tandem_repeats += 1;
+            }
+            self.total_length += r.end - r.start;
+            self.max_length = self.max_length.max(r.end - r.start);
+        }
+    }
+
+    pub fn to_json(&self) -> json::JsonValue {
+        json::object! {
+            min_homopolymer: self.min_homopolymer,
+            min_tandem: self.min_tandem,
+            homopolymers: self.homopolymers,
+            tandem_repeats: self.tandem_repeats,
+            total_length: self.total_length,
+            max_length: self.max_length,
+        }
+    }
+}
+
+/// The repeats of every sequence of an uncompressed FASTA, by name, in
+/// file order (sequences without repeats included).
+pub fn fasta_repeats(fa: &[u8], min_homopolymer: u64, min_tandem: u64) -> Vec<(String, Vec<Repeat>)> {
+    let mut out = Vec::new();
+    let mut finder = RepeatFinder::new(min_homopolymer, min_tandem);
+    let mut parser = FastaParser::new();
+    for line in scan::split_lines(fa) {
+        match parser.push(line) {
+            (FastaLine::Header { .. }, Some(record)) => out.push((record.name, finder.finish())),
+            (FastaLine::Sequence(line), _) if parser.current().is_some() => finder.push(line),
+            _ => {}
+        }
+    }
+    if let Some(record) = parser.finish() {
+        out.push((record.name, finder.finish()));
+    }
+    out
+}
+
+/// The `repeats` track of `fa`, compressed at `level`, with
+/// [`RepeatStats`] as its figures.
+pub fn repeat_track(fa: &[u8], min_homopolymer: u64, min_tandem: u64, format: TrackFormat, level: u32) -> io::Result<SequenceTrack> {
+    let mut stats = RepeatStats { min_homopolymer, min_tandem, ..Default::default() };
+    let sequences = fasta_repeats(fa, min_homopolymer, min_tandem)
+        .into_iter()
+        .map(|(name, repeats)| {
+            stats.add(&repeats);
+            let features = repeats
+                .iter()
+                .map(|r| {
+                    let unit = String::from_utf8_lossy(&r.unit).to_ascii_lowercase();
+                    let attributes = format!("rpt_type=tandem;rpt_unit_seq={};copies={}", unit, (r.copies() * 10.0).round() / 10.0);
+                    TrackFeature { start: r.start, end: r.end, strand: None, kind: "tandem_repeat", attributes }
+                })
+                .collect();
+            (name, features)
+        })
+        .collect();
+    SequenceTrack::write("repeats", "repeat", format, sequences, stats.to_json(), level)
+}
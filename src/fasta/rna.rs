@@ -0,0 +1,324 @@
+//! A quick scan for rRNA- and tRNA-like regions, and the RNA track.
+//!
+//! MGnify wants rRNAs in a submitted assembly; when no annotation comes
+//! with it this gives a first, low-confidence answer in the browser.  There
+//! are no profiles or alignments, only:
+//!
+//! - **rRNA**: k-mer matches against [`ANCHORS`], stretches of 16S and 23S
+//!   rRNA conserved across bacteria and archaea (the sites of the universal
+//!   primers), ambiguity codes expanded, on both strands.  Anchors of one
+//!   gene on one strand whose implied gene start agrees within a tenth of
+//!   the gene length are one hit; a hit needs two different anchors, and
+//!   spans the gene as long as the reference (*E. coli*), clipped to the
+//!   sequence.
+//! - **tRNA**: a T-arm (a 5 bp stem round a `TTCRANY` loop) followed by a
+//!   7 bp acceptor stem whose other half lies 44 to 70 bases upstream, as
+//!   in the cloverleaf; G·U pairs count.  The feature runs from the acceptor
+//!   stem through the discriminator base.
+//!
+//! Both miss diverged genes and may flag look-alikes.  With
+//! `IndexGenOptions::rna_track` set the hits become the `rna` track (see
+//! `track.rs`), `rRNA` and `tRNA` features with a strand and
+//! `Name=<gene>;Note=low-confidence quick scan`, rRNAs also with
+//! `anchors=<found>/<of gene>`.
+
+use std::collections::HashMap;
+use std::io;
+
+use super::track::{SequenceTrack, TrackFeature};
+use super::{FastaLine, FastaParser};
+use crate::options::TrackFormat;
+use crate::scan;
+use crate::translate::reverse_complement;
+
+/// An rRNA gene the scan looks for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RrnaGene {
+    pub name: &'static str,
+    /// Length of the *E. coli* gene.
+    pub length: u64,
+}
+
+pub const RRNA_16S: RrnaGene = RrnaGene { name: "16S_rRNA", length: 1542 };
+pub const RRNA_23S: RrnaGene = RrnaGene { name: "23S_rRNA", length: 2904 };
+
+/// A conserved rRNA stretch: the gene, its 0-based start on the *E. coli*
+/// gene, and its sequence on the gene's strand, with IUPAC codes.
+pub const ANCHORS: [(RrnaGene, u64, &str); 11] = [
+    (RRNA_16S, 7, "AGAGTTTGATCMTGGCTCAG"),      // 27F
+    (RRNA_16S, 340, "CCTACGGGNGGCWGCAG"),       // 341F
+    (RRNA_16S, 514, "GTGYCAGCMGCCGCGGTAA"),     // 515F
+    (RRNA_16S, 786, "ATTAGAWACCCBNGTAGTCC"),    // 806R
+    (RRNA_16S, 906, "AAACTYAAAKGAATTGACGG"),    // 926F
+    (RRNA_16S, 1098, "GCAACGAGCGCAACCC"),       // 1114F
+    (RRNA_16S, 1377, "GYACACACCGCCCGT"),        // 1392R
+    (RRNA_16S, 1473, "AAGTCGTAACAAGGTAACC"),    // 1492R
+    (RRNA_23S, 22, "ACGGTGGATGCCTTG"),          // L1
+    (RRNA_23S, 114, "CYGAATGGGRVAACC"),         // 129F
+    (RRNA_23S, 2224, "AGTTTDACTGGGGCGGT"),      // 2241R
+];
+
+/// What a hit is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RnaKind {
+    Rrna { gene: RrnaGene, anchors: usize, of: usize },
+    Trna,
+}
+
+/// An rRNA- or tRNA-like region.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RnaHit {
+    /// 0-based half-open range on the sequence.
+    pub start: u64,
+    pub end: u64,
+    /// `+` or `-`.
+    pub strand: char,
+    pub kind: RnaKind,
+}
+
+/// Bases of the IUPAC code `b`.
+fn iupac(b: u8) -> &'static [u8] {
+    match b {
+        b'A' => b"A",
+        b'C' => b"C",
+        b'G' => b"G",
+        b'T' => b"T",
+        b'R' => b"AG",
+        b'Y' => b"CT",
+        b'S' => b"CG",
+        b'W' => b"AT",
+        b'K' => b"GT",
+        b'M' => b"AC",
+        b'B' => b"CGT",
+        b'D' => b"AGT",
+        b'H' => b"ACT",
+        b'V' => b"ACG",
+        _ => b"ACGT",
+    }
+}
+
+fn base_code(b: u8) -> Option<u64> {
+    match b {
+        b'A' => Some(0),
+        b'C' => Some(1),
+        b'G' => Some(2),
+        b'T' => Some(3),
+        _ => None,
+    }
+}
+
+/// The k-mers of [`ANCHORS`], ambiguity codes expanded, on both strands:
+/// `(length, 2-bit packed word)` to `(anchor, strand)`.
+struct AnchorIndex {
+    words: HashMap<(usize, u64), (usize, char)>,
+    lengths: Vec<usize>,
+}
+
+impl AnchorIndex {
+    fn new() -> Self {
+        let mut words = HashMap::new();
+        for (i, (_, _, anchor)) in ANCHORS.iter().enumerate() {
+            for (strand, word) in [('+', anchor.as_bytes().to_vec()), ('-', reverse_complement(anchor.as_bytes()))] {
+                let mut packed = vec![0u64];
+                for &b in &word {
+                    packed = packed.iter().flat_map(|p| iupac(b).iter().map(move |&c| p << 2 | base_code(c).unwrap_or(0))).collect();
+                }
+                for p in packed {
+                    words.insert((word.len(), p), (i, strand));
+                }
+            }
+        }
+        let mut lengths: Vec<usize> = ANCHORS.iter().map(|(_, _, a)| a.len()).collect();
+        lengths.sort_unstable();
+        lengths.dedup();
+        AnchorIndex { words, lengths }
+    }
+
+    /// Anchor matches in `seq` (upper case): `(anchor, strand, start)`.
+    fn matches(&self, seq: &[u8]) -> Vec<(usize, char, u64)> {
+        let mut out = Vec::new();
+        let (mut word, mut valid) = (0u64, 0usize);
+        for (i, &b) in seq.iter().enumerate() {
+            match base_code(b) {
+                Some(c) => {
+                    word = word << 2 | c;
+                    valid += 1;
+                }
+                None => valid = 0,
+            }
+            for &len in self.lengths.iter().filter(|&&len| len <= valid) {
+                if let Some(&(anchor, strand)) = self.words.get(&(len, word & ((1 << (2 * len)) - 1))) {
+                    out.push((anchor, strand, (i + 1 - len) as u64));
+                }
+            }
+        }
+        out
+    }
+}
+
+/// rRNA hits of `seq` from its anchor matches.
+fn rrna_hits(seq: &[u8], index: &AnchorIndex) -> Vec<RnaHit> {
+    let len = seq.len() as i64;
+    // Implied start of the gene's low end on the sequence, per match.
+    let mut implied: Vec<(&'static str, char, i64, usize)> = index
+        .matches(seq)
+        .into_iter()
+        .map(|(anchor, strand, at)| {
+            let (gene, offset, word) = ANCHORS[anchor];
+            let start = match strand {
+                '+' => at as i64 - offset as i64,
+                _ => at as i64 + word.len() as i64 + offset as i64 - gene.length as i64,
+            };
+            (gene.name, strand, start, anchor)
+        })
+        .collect();
+    implied.sort_unstable();
+    let mut out = Vec::new();
+    let mut rest = &implied[..];
+    while let Some(&(name, strand, start, anchor)) = rest.first() {
+        let gene = ANCHORS[anchor].0;
+        let tolerance = gene.length as i64 / 10;
+        let n = rest.iter().take_while(|m| m.0 == name && m.1 == strand && m.2 - start <= tolerance).count();
+        let mut anchors: Vec<usize> = rest[..n].iter().map(|m| m.3).collect();
+        anchors.sort_unstable();
+        anchors.dedup();
+        if anchors.len() >= 2 {
+            let of = ANCHORS.iter().filter(|a| a.0 == gene).count();
+            out.push(RnaHit {
+                start: start.clamp(0, len) as u64,
+                end: (start + gene.length as i64).clamp(0, len) as u64,
+                strand,
+                kind: RnaKind::Rrna { gene, anchors: anchors.len(), of },
+            });
+        }
+        rest = &rest[n..];
+    }
+    out
+}
+
+/// Whether bases `a` and `b` pair (Watson-Crick or G·U).
+fn pairs(a: u8, b: u8) -> bool {
+    matches!((a, b), (b'A', b'T') | (b'T', b'A') | (b'C', b'G') | (b'G', b'C') | (b'G', b'T') | (b'T', b'G'))
+}
+
+/// Whether `x` and `y` form a stem, `x[k]` pairing with `y[len - 1 - k]`.
+fn stem(x: &[u8], y: &[u8]) -> bool {
+    x.len() == y.len() && x.iter().zip(y.iter().rev()).all(|(&a, &b)| pairs(a, b))
+}
+
+/// Whether `loop7` is a T-loop, `TTCRANY`.
+fn t_loop(loop7: &[u8]) -> bool {
+    loop7[..3] == *b"TTC" && matches!(loop7[3], b'A' | b'G') && loop7[4] == b'A' && matches!(loop7[6], b'C' | b'T')
+}
+
+/// tRNA-like regions of `seq` (upper case) on its forward strand, as
+/// 0-based half-open ranges.
+fn forward_trnas(seq: &[u8]) -> Vec<(u64, u64)> {
+    let mut out = Vec::new();
+    // `s0` is the start of the T-stem's 5' half.
+    for s0 in 44..seq.len().saturating_sub(24) {
+        if !t_loop(&seq[s0 + 5..s0 + 12]) || !stem(&seq[s0..s0 + 5], &seq[s0 + 12..s0 + 17]) {
+            continue;
+        }
+        let acceptor = &seq[s0 + 17..s0 + 24];
+        // The stem nearest the usual 48 bases upstream.
+        let found = (44..=s0.min(70)).filter(|&d| stem(&seq[s0 - d..s0 - d + 7], acceptor)).min_by_key(|&d| d.abs_diff(48));
+        if let Some(t) = found.map(|d| s0 - d) {
+            out.push((t as u64, (s0 + 25) as u64));
+        }
+    }
+    out
+}
+
+/// The rRNA- and tRNA-like regions of `seq` (bases only, any case), rRNAs
+/// first.
+pub fn find_rnas(seq: &[u8]) -> Vec<RnaHit> {
+    thread_local! {
+        static INDEX: AnchorIndex = AnchorIndex::new();
+    }
+    let seq = seq.to_ascii_uppercase();
+    let len = seq.len() as u64;
+    let mut out = INDEX.with(|index| rrna_hits(&seq, index));
+    let trna = |start, end, strand| RnaHit { start, end, strand, kind: RnaKind::Trna };
+    out.extend(forward_trnas(&seq).into_iter().map(|(start, end)| trna(start, end, '+')));
+    out.extend(forward_trnas(&reverse_complement(&seq)).into_iter().map(|(start, end)| trna(len - end, len - start, '-')));
+    out
+}
+
+/// RNA scan figures of an assembly.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RnaStats {
+    pub rrna_16s: u64,
+    pub rrna_23s: u64,
+    pub trna: u64,
+}
+
+impl RnaStats {
+    /// Count the hits of one sequence.
+    pub fn add(&mut self, hits: &[RnaHit]) {
+        for hit in hits {
+            match hit.kind {
+                RnaKind::Rrna { gene, .. } if gene == RRNA_16S => self.rrna_16s += 1,
+                RnaKind::Rrna { .. } => self.rrna_23s += 1,
+                RnaKind::Trna => self.trna += 1,
+            }
+        }
+    }
+
+    pub fn to_json(&self) -> json::JsonValue {
+        json::object! {
+            rrna_16s: self.rrna_16s,
+            rrna_23s: self.rrna_23s,
+            trna: self.trna,
+        }
+    }
+}
+
+/// The hits of every sequence of an uncompressed FASTA, by name, in file
+/// order (sequences without hits included).  Sequences are read whole, one
+/// at a time, for their reverse strand.
+pub fn fasta_rnas(fa: &[u8]) -> Vec<(String, Vec<RnaHit>)> {
+    let mut out = Vec::new();
+    let mut seq = Vec::new();
+    let mut parser = FastaParser::new();
+    for line in scan::split_lines(fa) {
+        match parser.push(line) {
+            (FastaLine::Header { .. }, Some(record)) => {
+                out.push((record.name, find_rnas(&seq)));
+                seq.clear();
+            }
+            (FastaLine::Sequence(line), _) if parser.current().is_some() => seq.extend(line.iter().filter(|b| b.is_ascii_graphic())),
+            _ => {}
+        }
+    }
+    if let Some(record) = parser.finish() {
+        out.push((record.name, find_rnas(&seq)));
+    }
+    out
+}
+
+/// The `rna` track of `fa`, compressed at `level`, with [`RnaStats`] as
+/// its figures.
+pub fn rna_track(fa: &[u8], format: TrackFormat, level: u32) -> io::Result<SequenceTrack> {
+    let mut stats = RnaStats::default();
+    let sequences = fasta_rnas(fa)
+        .into_iter()
+        .map(|(name, hits)| {
+            stats.add(&hits);
+            let features = hits
+                .iter()
+                .map(|hit| {
+                    let (kind, attributes) = match hit.kind {
+                        RnaKind::Rrna { gene, anchors, of } => {
+                            ("rRNA", format!("Name={};anchors={}/{};Note=low-confidence quick scan", gene.name, anchors, of))
+                        }
+                        RnaKind::Trna => ("tRNA", "Name=tRNA;Note=low-confidence quick scan".to_owned()),
+                    };
+                    TrackFeature { start: hit.start, end: hit.end, strand: Some(hit.strand), kind, attributes }
+                })
+                .collect();
+            (name, features)
+        })
+        .collect();
+    SequenceTrack::write("rna", "rna", format, sequences, stats.to_json(), level)
+}
@@ -0,0 +1,224 @@
+//! Splitting scaffolds into contigs at assembly gaps (the `split_at_gaps`
+//! FASTA transform).
+//!
+//! Every run of at least `min_gap` `N`s is cut out of its sequence, and
+//! what is left either side becomes a contig; runs at either end are
+//! trimmed.  A scaffold left in one piece keeps its name, otherwise piece
+//! `k` (1-based, in scaffold order) is named `<scaffold>_<k>`, keeping the
+//! header's description.  The GFF3 of the same run is moved onto the
+//! contigs: features are renamed and shifted, `##sequence-region` lines are
+//! replaced by one per contig, and a feature that spans a gap (or lies in
+//! one) is dropped with a `feature_spans_gap` warning.  [`ContigMap`]
+//! records where each contig came from.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io;
+
+use super::gaps::n_runs;
+use super::DEFAULT_LINE_WIDTH;
+use crate::error::{io_error, ErrorCode};
+use crate::gff::rewrite_lines;
+use crate::warnings::Warnings;
+
+/// A contig cut from a scaffold.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Contig {
+    pub name: String,
+    /// 0-based half-open range on the scaffold.
+    pub start: u64,
+    pub end: u64,
+}
+
+/// A scaffold that was split or trimmed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Scaffold {
+    pub name: String,
+    pub length: u64,
+    /// In scaffold order; empty for a scaffold of gaps only, which is
+    /// dropped.
+    pub contigs: Vec<Contig>,
+}
+
+/// The scaffolds `split_at_gaps` changed.  `min_gap` is `None` until a
+/// FASTA has been split, so the GFF3 step can tell "nothing to move" from
+/// "not split at all".
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ContigMap {
+    pub min_gap: Option<u64>,
+    pub scaffolds: Vec<Scaffold>,
+}
+
+impl ContigMap {
+    /// Split the sequences of `fa` at runs of at least `min_gap` `N`s,
+    /// with a `scaffold_split` warning per scaffold changed; borrowed when
+    /// there is none.  Fails with `E_FASTA_DUPNAME` when a contig name is
+    /// already taken.
+    pub fn split<'a>(fa: &'a [u8], min_gap: u64, warnings: &mut Warnings) -> io::Result<(Cow<'a, [u8]>, ContigMap)> {
+        let mut map = ContigMap { min_gap: Some(min_gap), scaffolds: Vec::new() };
+        let records = records(fa);
+        let mut taken: HashSet<&[u8]> = records.iter().map(|r| header_name(r.header)).collect();
+        let mut new_names = HashSet::new();
+        let mut out: Option<Vec<u8>> = None;
+        for record in &records {
+            let seq: Vec<u8> = record.body.iter().copied().filter(u8::is_ascii_graphic).collect();
+            let gaps = n_runs(&seq, min_gap);
+            if gaps.is_empty() {
+                if let Some(out) = &mut out {
+                    out.extend_from_slice(record.bytes);
+                }
+                continue;
+            }
+            let out = out.get_or_insert_with(|| fa[..record.offset].to_vec());
+            let name = header_name(record.header);
+            let scaffold = String::from_utf8_lossy(name).into_owned();
+            let mut pieces = Vec::new();
+            let mut start = 0;
+            for gap in gaps.iter().chain([&(seq.len() as u64..seq.len() as u64)]) {
+                if gap.start > start {
+                    pieces.push(start..gap.start);
+                }
+                start = gap.end;
+            }
+            taken.remove(name);
+            let mut contigs = Vec::with_capacity(pieces.len());
+            for (k, piece) in pieces.iter().enumerate() {
+                let contig = if pieces.len() == 1 { scaffold.clone() } else { format!("{}_{}", scaffold, k + 1) };
+                if taken.contains(contig.as_bytes()) || !new_names.insert(contig.clone()) {
+                    return Err(io_error(ErrorCode::FastaDupName, format!("splitting {} at gaps gives contig {}, a name already in use", scaffold, contig)));
+                }
+                let description = record.header[1 + name.len()..].trim_ascii_end();
+                out.extend_from_slice(b">");
+                out.extend_from_slice(contig.as_bytes());
+                out.extend_from_slice(description);
+                out.push(b'\n');
+                let width = record.width.unwrap_or(DEFAULT_LINE_WIDTH);
+                for line in seq[piece.start as usize..piece.end as usize].chunks(width) {
+                    out.extend_from_slice(line);
+                    out.push(b'\n');
+                }
+                contigs.push(Contig { name: contig, start: piece.start, end: piece.end });
+            }
+            let message = match contigs.len() {
+                0 => format!("{} is gaps only; dropped", scaffold),
+                n => format!("{} split at {} runs of {} or more Ns into {} contigs", scaffold, gaps.len(), min_gap, n),
+            };
+            warnings.push("fasta", "scaffold_split", Some(record.line), message);
+            map.scaffolds.push(Scaffold { name: scaffold, length: seq.len() as u64, contigs });
+        }
+        Ok((out.map_or(Cow::Borrowed(fa), Cow::Owned), map))
+    }
+
+    /// `gff` moved onto the contigs; borrowed when no feature is on a
+    /// scaffold that changed.
+    pub fn remap_gff<'a>(&self, gff: &'a str, warnings: &mut Warnings) -> io::Result<Cow<'a, str>> {
+        let scaffolds: HashMap<&str, &Scaffold> = self.scaffolds.iter().map(|s| (s.name.as_str(), s)).collect();
+        if scaffolds.is_empty() {
+            return Ok(Cow::Borrowed(gff));
+        }
+        rewrite_lines(gff, |line_no, line| {
+            if let Some(rest) = line.strip_prefix("##sequence-region") {
+                let Some(scaffold) = rest.split_whitespace().next().and_then(|s| scaffolds.get(s)) else {
+                    return Ok(None);
+                };
+                let regions = scaffold.contigs.iter().map(|c| format!("##sequence-region {} 1 {}\n", c.name, c.end - c.start));
+                return Ok(Some(Cow::Owned(regions.collect())));
+            }
+            if line.starts_with('#') {
+                return Ok(None);
+            }
+            let cols: Vec<&str> = line.splitn(6, '\t').collect();
+            let Some(scaffold) = cols.first().and_then(|s| scaffolds.get(s)) else {
+                return Ok(None);
+            };
+            // Malformed coordinates are left for validation to report.
+            let (Some(Ok(start)), Some(Ok(end)), Some(rest)) = (cols.get(3).map(|s| s.parse::<u64>()), cols.get(4).map(|s| s.parse::<u64>()), cols.get(5)) else {
+                return Ok(None);
+            };
+            // A record with start > end moves as it is, for sorting to report
+            // or repair.
+            let (lo, hi) = (start.min(end), start.max(end));
+            let at = scaffold.contigs.partition_point(|c| c.end < hi);
+            match scaffold.contigs.get(at).filter(|c| lo > c.start) {
+                Some(c) => Ok(Some(Cow::Owned(format!("{}\t{}\t{}\t{}\t{}\t{}", c.name, cols[1], cols[2], start - c.start, end - c.start, rest)))),
+                None => {
+                    let message = format!("{} {}:{}-{} spans a gap of the scaffold (or lies in one); dropped", cols[2], scaffold.name, start, end);
+                    warnings.push("gff", "feature_spans_gap", Some(line_no), message);
+                    Ok(Some(Cow::Borrowed("")))
+                }
+            }
+        })
+    }
+
+    /// `{min_gap, scaffolds: [{name, length, contigs: [{name, start, end}]}]}`
+    /// with 1-based inclusive contig coordinates on the scaffold.
+    pub fn to_json(&self) -> json::JsonValue {
+        let scaffolds: Vec<json::JsonValue> = self
+            .scaffolds
+            .iter()
+            .map(|s| {
+                let contigs: Vec<json::JsonValue> = s.contigs.iter().map(|c| json::object! { name: c.name.as_str(), start: c.start + 1, end: c.end }).collect();
+                json::object! { name: s.name.as_str(), length: s.length, contigs: contigs }
+            })
+            .collect();
+        json::object! { min_gap: self.min_gap, scaffolds: scaffolds }
+    }
+
+    /// The map [`ContigMap::to_json`] wrote.
+    pub fn from_json(value: &json::JsonValue) -> Self {
+        let scaffolds = value["scaffolds"]
+            .members()
+            .map(|s| Scaffold {
+                name: s["name"].as_str().unwrap_or_default().to_owned(),
+                length: s["length"].as_u64().unwrap_or(0),
+                contigs: s["contigs"]
+                    .members()
+                    .map(|c| Contig {
+                        name: c["name"].as_str().unwrap_or_default().to_owned(),
+                        start: c["start"].as_u64().unwrap_or(1).saturating_sub(1),
+                        end: c["end"].as_u64().unwrap_or(0),
+                    })
+                    .collect(),
+            })
+            .collect();
+        ContigMap { min_gap: value["min_gap"].as_u64(), scaffolds }
+    }
+}
+
+/// A FASTA record: where it starts, its header line, its sequence lines
+/// and all of its bytes.
+struct Record<'a> {
+    offset: usize,
+    /// 1-based line number of the header.
+    line: usize,
+    header: &'a [u8],
+    body: &'a [u8],
+    bytes: &'a [u8],
+    /// Bases on the first sequence line.
+    width: Option<usize>,
+}
+
+fn records(fa: &[u8]) -> Vec<Record<'_>> {
+    let mut out: Vec<Record> = Vec::new();
+    let mut offset = 0;
+    for (i, line) in fa.split_inclusive(|&b| b == b'\n').enumerate() {
+        if line.starts_with(b">") {
+            out.push(Record { offset, line: i + 1, header: line, body: &fa[offset + line.len()..offset + line.len()], bytes: line, width: None });
+        } else if let Some(record) = out.last_mut() {
+            let end = offset + line.len();
+            record.body = &fa[record.offset + record.header.len()..end];
+            record.bytes = &fa[record.offset..end];
+            let bases = line.iter().filter(|b| b.is_ascii_graphic()).count();
+            if record.width.is_none() && bases > 0 {
+                record.width = Some(bases);
+            }
+        }
+        offset += line.len();
+    }
+    out
+}
+
+/// First word of a FASTA header line, without the `>`.
+fn header_name(line: &[u8]) -> &[u8] {
+    line[1..].split(|b| b.is_ascii_whitespace()).next().unwrap_or_default()
+}
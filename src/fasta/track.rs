@@ -0,0 +1,146 @@
+//! Feature tracks found in the FASTA pass (assembly gaps, repeats, …),
+//! written as BED or GFF3, bgzipped, with a `.csi`.
+//!
+//! A detector lists [`TrackFeature`]s per sequence, in FASTA order;
+//! [`SequenceTrack::write`] sorts each sequence's features by position and
+//! numbers them in that order.  BED lines are `seqid start end name`,
+//! 0-based, with `score strand` added for tracks whose features have a
+//! strand; GFF3 lines carry `ID=<name>` before the detector's attributes.
+//! A FASTA without such features gives an empty track.  Tracks read from
+//! other inputs (depth as bedGraph, see `coverage.rs`) come as text.
+
+use std::io::{self, Cursor};
+
+use wasm_bindgen::prelude::*;
+
+use crate::htslib::{bgzf_compress_level, csi_index, TabixConfig};
+use crate::options::TrackFormat;
+
+/// One feature of a track.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrackFeature {
+    /// 0-based half-open range on the sequence.
+    pub start: u64,
+    pub end: u64,
+    pub strand: Option<char>,
+    /// GFF3 type.
+    pub kind: &'static str,
+    /// GFF3 attributes after `ID`, `key=value;…`; may be empty.
+    pub attributes: String,
+}
+
+/// A track: features as BED or GFF3, bgzipped, with its `.csi`, and the
+/// detector's figures.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct SequenceTrack {
+    name: &'static str,
+    extension: &'static str,
+    bgz: Vec<u8>,
+    csi: Vec<u8>,
+    stats: json::JsonValue,
+}
+
+#[wasm_bindgen]
+impl SequenceTrack {
+    /// What the track holds: `gaps`, `repeats`, ….
+    pub fn name(&self) -> String {
+        self.name.to_owned()
+    }
+
+    /// Moves the bgzipped track out. May only be called once meaningfully.
+    pub fn bgz(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.bgz)
+    }
+
+    /// Moves the `.csi` index bytes out. May only be called once meaningfully.
+    pub fn csi(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.csi)
+    }
+
+    /// File name extension of the track before `.gz`: `bed`, `gff3` or
+    /// `bedgraph`.
+    pub fn extension(&self) -> String {
+        self.extension.to_owned()
+    }
+
+    /// The detector's figures as JSON.
+    pub fn stats_json(&self) -> String {
+        self.stats.dump()
+    }
+}
+
+impl SequenceTrack {
+    /// The track `name` of `sequences`, `(seqid, features)` in FASTA order,
+    /// compressed at `level`.  Features are named `<prefix>_<k>`.
+    pub fn write(
+        name: &'static str,
+        prefix: &str,
+        format: TrackFormat,
+        sequences: Vec<(String, Vec<TrackFeature>)>,
+        stats: json::JsonValue,
+        level: u32,
+    ) -> io::Result<Self> {
+        let stranded = sequences.iter().flat_map(|(_, f)| f).any(|f| f.strand.is_some());
+        let mut text = String::new();
+        if format == TrackFormat::Gff {
+            text.push_str("##gff-version 3\n");
+        }
+        let mut k = 0;
+        for (seqid, mut features) in sequences {
+            features.sort_by_key(|f| (f.start, f.end));
+            for f in features {
+                k += 1;
+                let strand = f.strand.unwrap_or('.');
+                let line = match format {
+                    TrackFormat::Gff => {
+                        let sep = if f.attributes.is_empty() { "" } else { ";" };
+                        format!("{}\t.\t{}\t{}\t{}\t.\t{}\t.\tID={}_{}{}{}\n", seqid, f.kind, f.start + 1, f.end, strand, prefix, k, sep, f.attributes)
+                    }
+                    _ if stranded => format!("{}\t{}\t{}\t{}_{}\t0\t{}\n", seqid, f.start, f.end, prefix, k, strand),
+                    _ => format!("{}\t{}\t{}\t{}_{}\n", seqid, f.start, f.end, prefix, k),
+                };
+                text.push_str(&line);
+            }
+        }
+        let config = if format == TrackFormat::Gff { TabixConfig::GFF } else { TabixConfig::BED };
+        Self::from_text(name, format.extension(), &config, &text, stats, level)
+    }
+
+    /// The track `name` of `text`, already grouped by sequence and sorted
+    /// by start, indexed with `config` and compressed at `level`.
+    pub fn from_text(
+        name: &'static str,
+        extension: &'static str,
+        config: &TabixConfig,
+        text: &str,
+        stats: json::JsonValue,
+        level: u32,
+    ) -> io::Result<Self> {
+        let mut bgz = Vec::new();
+        bgzf_compress_level(Cursor::new(text.as_bytes()), &mut bgz, level)?;
+        let mut csi = Vec::new();
+        csi_index(Cursor::new(&bgz), &mut csi, config)?;
+        Ok(SequenceTrack { name, extension, bgz, csi, stats })
+    }
+
+    /// A track read back from the cache.
+    pub(crate) fn from_parts(name: &'static str, format: TrackFormat, bgz: Vec<u8>, csi: Vec<u8>, stats: json::JsonValue) -> Self {
+        SequenceTrack { name, extension: format.extension(), bgz, csi, stats }
+    }
+
+    /// The bgzipped track, without moving it out.
+    pub fn bgz_bytes(&self) -> &[u8] {
+        &self.bgz
+    }
+
+    /// The `.csi`, without moving it out.
+    pub fn csi_bytes(&self) -> &[u8] {
+        &self.csi
+    }
+
+    /// The detector's figures.
+    pub fn stats(&self) -> &json::JsonValue {
+        &self.stats
+    }
+}
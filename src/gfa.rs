@@ -0,0 +1,222 @@
+//! GFA1 assembly graphs, checked against the FASTA.
+//!
+//! Assemblers (SPAdes, metaSPAdes, Flye, MEGAHIT via `contig2fastg`) write
+//! the graph next to the contigs, and users uploading both should learn
+//! early when the two do not belong together.  Only what that check needs
+//! is read:
+//!
+//! - `S name sequence [tags]`: a segment; with `*` for the sequence its
+//!   length comes from the `LN:i:` tag, which must otherwise agree with the
+//!   sequence;
+//! - `L from orient to orient overlap`: a link between segment ends;
+//! - `P` and `W` lines are counted; `H`, `C`, `J` and `#` lines and
+//!   unknown record types are skipped, as the spec asks.
+//!
+//! A duplicate segment, a link to a segment the file does not have, a bad
+//! orientation or length, and a `VN:Z:2.0` header (GFA2) are
+//! `E_GFA_PARSE`.  [`Gfa::report`] sums up the graph (links, dead ends,
+//! connected components) and matches segments to FASTA sequences by name
+//! and length; [`Gfa::segments_fasta`] writes the segment sequences out.
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{io_error, js_error, ErrorCode};
+use crate::fasta::DEFAULT_LINE_WIDTH;
+use crate::health;
+use crate::scan;
+
+/// A segment of the graph.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub name: String,
+    pub length: u64,
+    /// `None` for `*`, or when the graph was read without sequences.
+    pub sequence: Option<Vec<u8>>,
+}
+
+/// A link from one segment end to another: segment indices and whether
+/// each is taken reverse-complemented (`-`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Link {
+    pub from: usize,
+    pub from_reverse: bool,
+    pub to: usize,
+    pub to_reverse: bool,
+}
+
+/// A GFA1 graph.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Gfa {
+    /// `VN:Z:` of the header, if any.
+    pub version: Option<String>,
+    /// In file order.
+    pub segments: Vec<Segment>,
+    pub links: Vec<Link>,
+    /// `P` and `W` lines.
+    pub paths: u64,
+}
+
+fn gfa_error(line: usize, message: impl std::fmt::Display) -> io::Error {
+    io_error(ErrorCode::GfaParse, format!("GFA line {}: {}", line, message))
+}
+
+fn orientation(value: &str, line: usize) -> io::Result<bool> {
+    match value {
+        "+" => Ok(false),
+        "-" => Ok(true),
+        _ => Err(gfa_error(line, format!("bad orientation {:?}", value))),
+    }
+}
+
+/// Union-find root of `i`, halving the path on the way.
+fn root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+impl Gfa {
+    /// Read GFA1 text, keeping the segment sequences when `keep_sequences`
+    /// is set (for [`Gfa::segments_fasta`]).
+    pub fn parse(gfa: &[u8], keep_sequences: bool) -> io::Result<Self> {
+        let mut out = Gfa::default();
+        let mut index: HashMap<String, usize> = HashMap::new();
+        // Links name segments that may come later in the file.
+        let mut pending: Vec<(usize, &str, bool, &str, bool)> = Vec::new();
+        for (n, line) in scan::split_lines(gfa).enumerate().map(|(n, line)| (n + 1, line)) {
+            let line = std::str::from_utf8(line).map_err(|_| gfa_error(n, "not UTF-8"))?.trim_end_matches('\r');
+            let fields: Vec<&str> = line.split('\t').collect();
+            match fields[0] {
+                "H" => {
+                    if let Some(vn) = fields[1..].iter().find_map(|t| t.strip_prefix("VN:Z:")) {
+                        if !vn.starts_with('1') {
+                            return Err(gfa_error(n, format!("GFA version {} is not supported, only GFA1", vn)));
+                        }
+                        out.version = Some(vn.to_owned());
+                    }
+                }
+                "S" => {
+                    let [_, name, sequence, ..] = fields[..] else {
+                        return Err(gfa_error(n, "expected `S<TAB>name<TAB>sequence`"));
+                    };
+                    let ln = fields[3..].iter().find_map(|t| t.strip_prefix("LN:i:"));
+                    let ln = ln.map(|v| v.parse::<u64>().map_err(|_| gfa_error(n, format!("bad LN:i:{}", v)))).transpose()?;
+                    let length = match (sequence, ln) {
+                        ("*", Some(ln)) => ln,
+                        ("*", None) => return Err(gfa_error(n, format!("segment {:?} has neither a sequence nor LN:i:", name))),
+                        (seq, Some(ln)) if seq.len() as u64 != ln => return Err(gfa_error(n, format!("segment {:?} is {} bases but LN:i:{}", name, seq.len(), ln))),
+                        (seq, _) => seq.len() as u64,
+                    };
+                    if index.insert(name.to_owned(), out.segments.len()).is_some() {
+                        return Err(gfa_error(n, format!("segment {:?} is defined twice", name)));
+                    }
+                    let sequence = Some(sequence).filter(|s| keep_sequences && *s != "*").map(|s| s.as_bytes().to_vec());
+                    out.segments.push(Segment { name: name.to_owned(), length, sequence });
+                }
+                "L" => {
+                    let [_, from, from_orient, to, to_orient, ..] = fields[..] else {
+                        return Err(gfa_error(n, "expected `L<TAB>from<TAB>orient<TAB>to<TAB>orient<TAB>overlap`"));
+                    };
+                    pending.push((n, from, orientation(from_orient, n)?, to, orientation(to_orient, n)?));
+                }
+                "P" | "W" => out.paths += 1,
+                _ => {}
+            }
+        }
+        for (n, from, from_reverse, to, to_reverse) in pending {
+            let segment = |name: &str| index.get(name).copied().ok_or_else(|| gfa_error(n, format!("link to unknown segment {:?}", name)));
+            out.links.push(Link { from: segment(from)?, from_reverse, to: segment(to)?, to_reverse });
+        }
+        Ok(out)
+    }
+
+    /// The figures of the graph and how it matches the FASTA whose
+    /// sequences are `lengths`, `(name, length)`: `{version, segments,
+    /// total_length, links, self_loops, dead_ends, isolated_segments,
+    /// components, paths, fasta: {consistent, matched, missing_from_fasta,
+    /// missing_from_graph, length_mismatches: [{name, gfa, fasta}]}}`.  A
+    /// dead end is a segment end no link touches.
+    pub fn report(&self, lengths: &[(String, u64)]) -> json::JsonValue {
+        let count = self.segments.len();
+        // Ends 2i (start) and 2i+1 (end) of segment i.
+        let mut linked = vec![false; 2 * count];
+        let mut parent: Vec<usize> = (0..count).collect();
+        let mut self_loops = 0;
+        for link in &self.links {
+            // Leaving `from` forward is leaving its end; entering `to`
+            // forward is entering its start.
+            linked[2 * link.from + usize::from(!link.from_reverse)] = true;
+            linked[2 * link.to + usize::from(link.to_reverse)] = true;
+            if link.from == link.to {
+                self_loops += 1;
+            }
+            let (a, b) = (root(&mut parent, link.from), root(&mut parent, link.to));
+            parent[a] = b;
+        }
+        let components = (0..count).filter(|&i| root(&mut parent, i) == i).count();
+        let isolated = linked.chunks(2).filter(|ends| !ends[0] && !ends[1]).count();
+
+        let fasta: HashMap<&str, u64> = lengths.iter().map(|(name, length)| (name.as_str(), *length)).collect();
+        let mut matched = 0;
+        let mut missing_from_fasta = json::JsonValue::new_array();
+        let mut length_mismatches = json::JsonValue::new_array();
+        for segment in &self.segments {
+            match fasta.get(segment.name.as_str()) {
+                Some(&length) if length == segment.length => matched += 1,
+                Some(&length) => {
+                    let _ = length_mismatches.push(json::object! { name: segment.name.as_str(), gfa: segment.length, fasta: length });
+                }
+                None => {
+                    let _ = missing_from_fasta.push(segment.name.as_str());
+                }
+            }
+        }
+        let segments: HashSet<&str> = self.segments.iter().map(|s| s.name.as_str()).collect();
+        let missing_from_graph: Vec<&str> = lengths.iter().map(|(name, _)| name.as_str()).filter(|name| !segments.contains(name)).collect();
+        json::object! {
+            version: self.version.clone(),
+            segments: count,
+            total_length: self.segments.iter().map(|s| s.length).sum::<u64>(),
+            links: self.links.len(),
+            self_loops: self_loops,
+            dead_ends: linked.iter().filter(|l| !**l).count(),
+            isolated_segments: isolated,
+            components: components,
+            paths: self.paths,
+            fasta: json::object! {
+                consistent: missing_from_fasta.is_empty() && length_mismatches.is_empty() && missing_from_graph.is_empty(),
+                matched: matched,
+                missing_from_fasta: missing_from_fasta,
+                missing_from_graph: missing_from_graph,
+                length_mismatches: length_mismatches,
+            },
+        }
+    }
+
+    /// The segments with a sequence as FASTA, in file order, wrapped at
+    /// [`DEFAULT_LINE_WIDTH`].
+    pub fn segments_fasta(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for segment in &self.segments {
+            let Some(sequence) = &segment.sequence else { continue };
+            out.extend_from_slice(format!(">{}\n", segment.name).as_bytes());
+            for line in sequence.chunks(DEFAULT_LINE_WIDTH) {
+                out.extend_from_slice(line);
+                out.push(b'\n');
+            }
+        }
+        out
+    }
+}
+
+/// The segments of GFA1 text with a sequence, as FASTA.  See
+/// [`Gfa::segments_fasta`].
+#[wasm_bindgen]
+pub fn gfa_segments_fasta(gfa: &[u8]) -> Result<Vec<u8>, JsValue> {
+    health::contain(|| Ok(Gfa::parse(gfa, true)?.segments_fasta())).map_err(js_error)
+}
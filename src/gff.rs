@@ -0,0 +1,636 @@
+//! GFF3 line handling shared by preprocessing, diffing, querying and export.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use crate::error::{io_error, ErrorCode};
+use crate::options::Collation;
+use crate::warnings::Warnings;
+
+pub mod circular;
+pub mod columns;
+pub mod conflicts;
+pub mod diff;
+pub mod gtf;
+pub mod multipart;
+pub mod record;
+pub mod tree;
+
+pub use circular::{circular_lengths, normalize_circular};
+pub use columns::{normalize_columns, pad_columns};
+pub use conflicts::{conflict_report, gff_conflicts};
+pub use diff::{diff_gff, gff_diff, GffDiff};
+pub use gtf::{detect_dialect, gtf_to_gff3, GffDialect};
+pub use multipart::{group_parts, part_key};
+pub use record::{columns, GffReader, GffRecord, GffRecords};
+pub use tree::FeatureTree;
+
+/// The nine columns of a GFF3 data line, borrowed from the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GffFields<'a> {
+    pub seqid: &'a str,
+    pub source: &'a str,
+    pub ftype: &'a str,
+    pub start: u64,
+    pub end: u64,
+    pub score: &'a str,
+    pub strand: &'a str,
+    pub phase: &'a str,
+    pub attributes: &'a str,
+}
+
+impl<'a> GffFields<'a> {
+    /// Parse a data line. Returns `None` for comments, blank lines and lines
+    /// without nine tab-separated columns or with non-numeric coordinates.
+    pub fn parse(line: &'a str) -> Option<Self> {
+        let line = line.trim_end_matches(['\n', '\r']);
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let f: Vec<&str> = columns(line, 9).collect();
+        if f.len() < 9 {
+            return None;
+        }
+        Some(GffFields {
+            seqid: f[0],
+            source: f[1],
+            ftype: f[2],
+            start: f[3].trim().parse().ok()?,
+            end: f[4].trim().parse().ok()?,
+            score: f[5],
+            strand: f[6],
+            phase: f[7],
+            attributes: f[8],
+        })
+    }
+
+    /// Value of attribute `key` in column 9, if present.
+    pub fn attribute(&self, key: &str) -> Option<&'a str> {
+        attribute(self.attributes, key)
+    }
+}
+
+/// Value of `key` in a GFF3 column-9 string (`key=value;key=value`).
+pub fn attribute<'a>(attributes: &'a str, key: &str) -> Option<&'a str> {
+    attributes
+        .split(';')
+        .filter_map(|kv| kv.trim().split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v)
+}
+
+/// Decode one column-9 value into its (possibly several) values.
+/// Quoted values (as written by EMBOSS) are kept whole; otherwise commas
+/// separate values and `%XX` escapes are decoded.
+pub fn attribute_values(value: &str) -> Vec<String> {
+    if let Some(inner) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        return vec![inner.to_owned()];
+    }
+    value.split(',').map(percent_decode).collect()
+}
+
+/// Decode GFF3 `%XX` escapes.
+pub fn percent_decode(s: &str) -> String {
+    let b = s.as_bytes();
+    let mut out = Vec::with_capacity(b.len());
+    let mut i = 0;
+    while i < b.len() {
+        let hex = b.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok()).and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (b[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (c, _) => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Data lines of a GFF3 file: comments and blank lines are skipped and
+/// everything from an embedded `##FASTA` section on is ignored.
+pub fn data_lines(gff: &str) -> impl Iterator<Item = &str> {
+    gff.split('\n')
+        .take_while(|l| !l.starts_with("##FASTA"))
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+}
+
+/// `gff` with lines before any `##FASTA` section replaced by what `f`
+/// returns for them, given their 1-based number and text (line ending
+/// included); `None` keeps a line.  Borrows `gff` when nothing is
+/// replaced.
+pub(crate) fn rewrite_lines<'a>(gff: &'a str, mut f: impl FnMut(usize, &'a str) -> io::Result<Option<Cow<'a, str>>>) -> io::Result<Cow<'a, str>> {
+    let mut out: Option<String> = None;
+    let mut offset = 0;
+    for (i, line) in gff.split_inclusive('\n').enumerate() {
+        if line.starts_with("##FASTA") {
+            break;
+        }
+        match (f(i + 1, line)?, &mut out) {
+            (Some(new), out) => out.get_or_insert_with(|| gff[..offset].to_owned()).push_str(&new),
+            (None, Some(out)) => out.push_str(line),
+            (None, None) => {}
+        }
+        offset += line.len();
+    }
+    Ok(match out {
+        Some(mut out) => {
+            out.push_str(&gff[offset..]);
+            Cow::Owned(out)
+        }
+        None => Cow::Borrowed(gff),
+    })
+}
+
+/// Order of seqids under `collation`.  Seqids equal under `Dictionary` or
+/// `Natural` are then compared bytewise, so only identical seqids compare
+/// equal and the records of a sequence stay together.
+pub fn seqid_cmp(a: &str, b: &str, collation: Collation) -> Ordering {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let keep = |c: &&u8| c.is_ascii_alphanumeric() || **c == b' ' || **c == b'\t';
+    match collation {
+        Collation::Dictionary => a.iter().filter(keep).cmp(b.iter().filter(keep)),
+        Collation::Natural => natural_cmp(a, b),
+        _ => Ordering::Equal,
+    }
+    .then_with(|| a.cmp(b))
+}
+
+/// Byte order, except that runs of ASCII digits compare as the numbers
+/// they spell (leading zeros ignored).
+fn natural_cmp(mut a: &[u8], mut b: &[u8]) -> Ordering {
+    let digits = |s: &[u8]| s.iter().take_while(|c| c.is_ascii_digit()).count();
+    let number = |s: &[u8]| -> usize { s.iter().take_while(|&&c| c == b'0').count() };
+    loop {
+        match (a.first(), b.first()) {
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (n, m) = (digits(a), digits(b));
+                let (x, y) = (&a[number(&a[..n])..n], &b[number(&b[..m])..m]);
+                let order = x.len().cmp(&y.len()).then_with(|| x.cmp(y));
+                if order != Ordering::Equal {
+                    return order;
+                }
+                (a, b) = (&a[n..], &b[m..]);
+            }
+            (Some(x), Some(y)) if x == y => (a, b) = (&a[1..], &b[1..]),
+            (x, y) => return x.cmp(&y),
+        }
+    }
+}
+
+/// Record order used for indexing, emulating `LC_ALL=C sort -k1,1 -k4,4n
+/// -k5,5n`: seqids bytewise, whatever the locale.
+pub fn record_cmp(a: &str, b: &str) -> Ordering {
+    let a_fields: Vec<&str> = columns(a, 6).collect();
+    let b_fields: Vec<&str> = columns(b, 6).collect();
+
+    // k1,1 - bytewise on field 1 (index 0)
+    a_fields[0].cmp(b_fields[0])
+        // k4,4n - numeric on field 4 (index 3)
+        .then_with(|| {
+            let a4: i64 = a_fields[3].parse().unwrap_or(0);
+            let b4: i64 = b_fields[3].parse().unwrap_or(0);
+            a4.cmp(&b4)
+        })
+        // k5,5n - numeric on field 5 (index 4)
+        .then_with(|| {
+            let a5: i64 = a_fields[4].parse().unwrap_or(0);
+            let b5: i64 = b_fields[4].parse().unwrap_or(0);
+            a5.cmp(&b5)
+        })
+}
+
+/// Column-9 attributes kept by the attribute projection: those selected by
+/// `whitelist` (empty = all) and not listed in `blacklist`.  The whitelist
+/// always selects `Is_circular`, which marks a sequence as circular for
+/// later runs and for readers.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AttributeFilter<'a> {
+    pub whitelist: &'a [String],
+    pub blacklist: &'a [String],
+}
+
+impl AttributeFilter<'_> {
+    /// True when every attribute is kept.
+    pub fn keeps_all(&self) -> bool {
+        self.whitelist.is_empty() && self.blacklist.is_empty()
+    }
+
+    fn keep(&self, kv: &str) -> bool {
+        let key = kv.trim().split('=').next().unwrap_or_default();
+        let whitelisted = self.whitelist.is_empty() || key == circular::IS_CIRCULAR || self.whitelist.iter().any(|k| k == key);
+        whitelisted && !self.blacklist.iter().any(|k| k == key)
+    }
+
+    /// Append `line` to `out` with the dropped attributes removed.  Comment
+    /// lines and lines with fewer than nine columns pass through; a record
+    /// left without attributes gets `.`.
+    pub fn project_line(&self, line: &str, out: &mut String) {
+        let body = line.trim_end_matches(['\n', '\r']);
+        let attributes = columns(body, 9).nth(8).filter(|_| !body.starts_with('#'));
+        let Some(attributes) = attributes else {
+            out.push_str(line);
+            return;
+        };
+        out.push_str(&body[..body.len() - attributes.len()]);
+        let mut kept = attributes.split(';').filter(|kv| !kv.trim().is_empty() && self.keep(kv));
+        match kept.next() {
+            Some(first) => {
+                out.push_str(first);
+                for kv in kept {
+                    out.push(';');
+                    out.push_str(kv);
+                }
+            }
+            None => out.push('.'),
+        }
+        out.push_str(&line[body.len()..]);
+    }
+}
+
+/// Drop column-9 attributes not selected by `whitelist` (empty = keep all)
+/// or listed in `blacklist` (see [`AttributeFilter::project_line`]).
+pub fn project_attributes(gff: &str, whitelist: &[String], blacklist: &[String]) -> String {
+    let filter = AttributeFilter { whitelist, blacklist };
+    let mut out = String::with_capacity(gff.len());
+    for line in gff.split_inclusive('\n') {
+        filter.project_line(line, &mut out);
+    }
+    out
+}
+
+// Reorders start for indexing and removes sequence if present
+pub fn gff_preprocess(gff_string: &str) -> String {
+    gff_preprocess_with_warnings(gff_string, false, &mut Warnings::default())
+}
+
+/// Key under which a `##` pragma may appear only once: the directive name,
+/// plus the seqid for `##sequence-region`.  `###` (forward-reference
+/// barrier) may repeat.
+fn pragma_key(line: &str) -> Option<String> {
+    if line.starts_with("###") {
+        return None;
+    }
+    let mut words = line.split_whitespace();
+    let name = words.next()?;
+    Some(match name {
+        "##sequence-region" => format!("{} {}", name, words.next().unwrap_or_default()),
+        _ => name.to_owned(),
+    })
+}
+
+/// `line` with columns 4 and 5 swapped, if it is a record with start > end.
+fn swap_reversed(line: &str) -> Option<String> {
+    GffFields::parse(line).filter(|f| f.start > f.end)?;
+    let mut cols: Vec<&str> = columns(line, 9).collect();
+    cols.swap(3, 4);
+    Some(cols.join("\t"))
+}
+
+/// [`gff_preprocess`], recording duplicate pragmas, whether the records
+/// needed sorting and records with start > end in `warnings`.  With
+/// `repair_coordinates` such records get their start and end swapped (some
+/// tools write minus-strand features that way), otherwise they are kept as
+/// they are.
+pub fn gff_preprocess_with_warnings(gff_string: &str, repair_coordinates: bool, warnings: &mut Warnings) -> String {
+    let mut out = Vec::with_capacity(gff_string.len());
+    SortedGff::new(gff_string, repair_coordinates, warnings)
+        .write_to(&mut out, AttributeFilter::default())
+        .expect("writing to a Vec cannot fail");
+    String::from_utf8(out).expect("lines of a str are UTF-8")
+}
+
+/// A column that orders records with the same seqid, start and end.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TieKey {
+    /// Column 3, by [`TieBreak`]'s type priority, then by name.
+    Type,
+    /// Column 7: `+`, `-`, `.`, `?`, anything else.
+    Strand,
+}
+
+/// How [`SortedGff`] orders records with identical coordinates
+/// (`IndexGenOptions::gff_sort_keys`), and seqids
+/// (`IndexGenOptions::gff_collation`).  Indexing only needs the records
+/// grouped by seqid and sorted by start, so this is purely for the readers
+/// that expect e.g. a gene before its mRNA, or for matching a server-side
+/// sort byte for byte; the default keeps such records in input order and
+/// compares seqids bytewise.
+#[derive(Clone, Debug)]
+pub struct TieBreak<'a> {
+    keys: Vec<TieKey>,
+    type_priority: &'a [String],
+    collation: Collation,
+}
+
+impl Default for TieBreak<'_> {
+    fn default() -> Self {
+        TieBreak { keys: Vec::new(), type_priority: &[], collation: Collation::Bytewise }
+    }
+}
+
+impl<'a> TieBreak<'a> {
+    /// Sort keys `"type"` and `"strand"`, compared in the order given;
+    /// `type_priority` lists the feature types that sort first, in order.
+    pub fn parse(keys: &[String], type_priority: &'a [String]) -> io::Result<Self> {
+        let keys = keys
+            .iter()
+            .map(|key| match key.as_str() {
+                "type" => Ok(TieKey::Type),
+                "strand" => Ok(TieKey::Strand),
+                _ => Err(io_error(ErrorCode::InvalidOption, format!("unknown GFF sort key {:?}; expected \"type\" or \"strand\"", key))),
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(TieBreak { keys, type_priority, ..Default::default() })
+    }
+
+    /// Compare seqids under `collation` instead of bytewise.
+    pub fn with_collation(self, collation: Collation) -> Self {
+        TieBreak { collation, ..self }
+    }
+
+    /// The order of two records by seqid, start and end, then the keys.
+    fn order(&self, a: &Record, b: &Record) -> Ordering {
+        a.cmp(b, self.collation).then_with(|| self.cmp(a.line, b.line))
+    }
+
+    fn cmp(&self, a: &str, b: &str) -> Ordering {
+        self.keys.iter().fold(Ordering::Equal, |order, key| {
+            order.then_with(|| match key {
+                TieKey::Type => {
+                    let (a, b) = (column(a, 2), column(b, 2));
+                    self.type_rank(a).cmp(&self.type_rank(b)).then_with(|| a.cmp(b))
+                }
+                TieKey::Strand => strand_rank(column(a, 6)).cmp(&strand_rank(column(b, 6))),
+            })
+        })
+    }
+
+    /// Position of `kind` in the type priority; unlisted types come after.
+    fn type_rank(&self, kind: &str) -> usize {
+        self.type_priority.iter().position(|t| t == kind).unwrap_or(self.type_priority.len())
+    }
+}
+
+/// Column `i` (0-based) of `line`, empty if it has fewer.
+fn column(line: &str, i: usize) -> &str {
+    columns(line, i + 2).nth(i).unwrap_or_default()
+}
+
+fn strand_rank(strand: &str) -> u8 {
+    match strand {
+        "+" => 0,
+        "-" => 1,
+        "." => 2,
+        "?" => 3,
+        _ => 4,
+    }
+}
+
+/// A record line and its sort key (see [`record_cmp`]).
+#[derive(Clone, Copy)]
+struct Record<'a> {
+    line: &'a str,
+    start: i64,
+    end: i64,
+    /// Written with columns 4 and 5 swapped (`repair_coordinates`).
+    swapped: bool,
+}
+
+impl<'a> Record<'a> {
+    fn new(line: &'a str, swapped: bool) -> Self {
+        let mut cols = columns(line, 6).skip(3).map(|c| c.parse().unwrap_or(0));
+        let (start, end) = (cols.next().unwrap_or(0), cols.next().unwrap_or(0));
+        let (start, end) = if swapped { (end, start) } else { (start, end) };
+        Record { line, start, end, swapped }
+    }
+
+    /// The record of `line` and whether its start is after its end.
+    fn parse(line: &'a str, repair_coordinates: bool) -> (Self, bool) {
+        let reversed = GffFields::parse(line).is_some_and(|f| f.start > f.end);
+        (Record::new(line, reversed && repair_coordinates), reversed)
+    }
+
+    fn seqid(&self) -> &'a str {
+        columns(self.line, 2).next().unwrap_or_default()
+    }
+
+    fn cmp(&self, other: &Self, collation: Collation) -> Ordering {
+        seqid_cmp(self.seqid(), other.seqid(), collation).then(self.start.cmp(&other.start)).then(self.end.cmp(&other.end))
+    }
+}
+
+/// The non-empty lines of `gff` before any `##FASTA` section, with their
+/// 1-based line numbers.
+fn gff_lines(gff: &str) -> impl Iterator<Item = (usize, &str)> {
+    gff.split('\n')
+        .enumerate()
+        .take_while(|(_, line)| !line.starts_with("##FASTA"))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(i, line)| (i + 1, line))
+}
+
+/// Whether `gff` is already in the order [`SortedGff`] writes: every `#`
+/// line before the first record and the records non-decreasing, `ties`
+/// included.  Stops at
+/// the first line out of place and allocates nothing.
+fn in_index_order(gff: &str, repair_coordinates: bool, ties: &TieBreak) -> bool {
+    let mut prev: Option<Record> = None;
+    for (_, line) in gff_lines(gff) {
+        if line.starts_with('#') {
+            if prev.is_some() {
+                return false;
+            }
+            continue;
+        }
+        let (record, _) = Record::parse(line, repair_coordinates);
+        if prev.is_some_and(|prev| ties.order(&prev, &record) == Ordering::Greater) {
+            return false;
+        }
+        prev = Some(record);
+    }
+    true
+}
+
+/// A GFF3 file in index order, borrowing its lines from the text: the `#`
+/// lines first, then the records sorted by [`record_cmp`], anything from an
+/// embedded `##FASTA` section on dropped.  The lines are written out one by
+/// one, so the sorted file never exists as a second copy in memory.  Input
+/// that is already in that order (typical of tool output) is not buffered
+/// at all: its lines are streamed from the text as they are.
+pub struct SortedGff<'a> {
+    /// The input, when it is already in index order.
+    in_order: Option<&'a str>,
+    repair_coordinates: bool,
+    comments: Vec<&'a str>,
+    records: Vec<Record<'a>>,
+}
+
+impl<'a> SortedGff<'a> {
+    /// Sort `gff`, with the warnings and `repair_coordinates` handling of
+    /// [`gff_preprocess_with_warnings`].
+    pub fn new(gff: &'a str, repair_coordinates: bool, warnings: &mut Warnings) -> Self {
+        Self::with_ties(gff, repair_coordinates, &TieBreak::default(), warnings)
+    }
+
+    /// [`SortedGff::new`], ordering records with identical coordinates by
+    /// `ties`.  Only records out of coordinate order raise `unsorted_input`.
+    pub fn with_ties(gff: &'a str, repair_coordinates: bool, ties: &TieBreak, warnings: &mut Warnings) -> Self {
+        let in_order = in_index_order(gff, repair_coordinates, ties);
+        let mut comments = Vec::new();
+        let mut records: Vec<Record> = Vec::new();
+        let mut pragmas: HashSet<String> = HashSet::new();
+        let mut prev: Option<Record> = None;
+        let mut first_unsorted: Option<usize> = None;
+
+        for (n, line) in gff_lines(gff) {
+            if line.starts_with('#') {
+                let key = if line.starts_with("##") { pragma_key(line.trim_end_matches('\r')) } else { None };
+                if let Some(key) = key.filter(|k| !pragmas.insert(k.clone())) {
+                    warnings.push("gff", "duplicate_pragma", Some(n), format!("duplicate {} pragma", key));
+                }
+                if !in_order {
+                    comments.push(line);
+                }
+            } else {
+                let (record, reversed) = Record::parse(line, repair_coordinates);
+                if reversed && repair_coordinates {
+                    warnings.push("gff", "coordinates_swapped", Some(n), "start was after end; swapped".into());
+                } else if reversed {
+                    warnings.push("gff", "start_after_end", Some(n), "start is after end; bins for this record are meaningless".into());
+                }
+                if first_unsorted.is_none() && prev.is_some_and(|prev| prev.cmp(&record, ties.collation) == Ordering::Greater) {
+                    first_unsorted = Some(n);
+                }
+                prev = Some(record);
+                if !in_order {
+                    records.push(record);
+                }
+            }
+        }
+
+        if let Some(line) = first_unsorted {
+            warnings.push("gff", "unsorted_input", Some(line), "records are not sorted by seqid, start, end; sorted for indexing".into());
+        }
+        records.sort_by(|a, b| ties.order(a, b));
+        SortedGff { in_order: in_order.then_some(gff), repair_coordinates, comments, records }
+    }
+
+    /// Whether the input was already in index order, so nothing was sorted
+    /// or buffered.
+    pub fn was_in_order(&self) -> bool {
+        self.in_order.is_some()
+    }
+
+    /// Write the sorted file (`\n`-terminated lines) with `filter` applied
+    /// to the records; returns the bytes the filter removed.
+    pub fn write_to<W: Write>(&self, out: &mut W, filter: AttributeFilter) -> io::Result<u64> {
+        let mut removed = 0;
+        let mut projected = String::new();
+        if let Some(gff) = self.in_order {
+            for (_, line) in gff_lines(gff) {
+                if line.starts_with('#') {
+                    write_line(out, line)?;
+                } else {
+                    let (record, _) = Record::parse(line, self.repair_coordinates);
+                    removed += write_record(out, &record, filter, &mut projected)?;
+                }
+            }
+            return Ok(removed);
+        }
+        for line in &self.comments {
+            write_line(out, line)?;
+        }
+        for record in &self.records {
+            removed += write_record(out, record, filter, &mut projected)?;
+        }
+        Ok(removed)
+    }
+
+    /// Number of sequences the records are on.
+    pub fn seqid_count(&self) -> u64 {
+        let seqids: Box<dyn Iterator<Item = &str>> = match self.in_order {
+            Some(gff) => Box::new(gff_lines(gff).map(|(_, l)| l).filter(|l| !l.starts_with('#')).map(|l| l.split('\t').next().unwrap_or(l))),
+            None => Box::new(self.records.iter().map(Record::seqid)),
+        };
+        let mut last = None;
+        seqids.filter(|&seqid| last.replace(seqid) != Some(seqid)).count() as u64
+    }
+
+    /// Length of the output of [`SortedGff::write_to`] with `filter`.
+    pub fn output_len(&self, filter: AttributeFilter) -> u64 {
+        let mut counter = ByteCounter(0);
+        let _ = self.write_to(&mut counter, filter);
+        counter.0
+    }
+
+    /// Whether [`SortedGff::write_to`] with `filter` writes the input back
+    /// byte for byte: in order, nothing projected away, no `##FASTA`
+    /// section, blank lines or `\r`, and a final newline.  Stops at the
+    /// first difference.
+    pub fn writes_input(&self, filter: AttributeFilter) -> bool {
+        let Some(gff) = self.in_order else {
+            return false;
+        };
+        let mut rest = SameBytes(gff.as_bytes());
+        self.write_to(&mut rest, filter).is_ok() && rest.0.is_empty()
+    }
+}
+
+fn write_line<W: Write>(out: &mut W, line: &str) -> io::Result<()> {
+    out.write_all(line.as_bytes())?;
+    out.write_all(b"\n")
+}
+
+/// Write `record` (swapped if needed) with `filter` applied, using
+/// `projected` as scratch; returns the bytes the filter removed.
+fn write_record<W: Write>(out: &mut W, record: &Record, filter: AttributeFilter, projected: &mut String) -> io::Result<u64> {
+    let line = match record.swapped {
+        true => Cow::Owned(swap_reversed(record.line).unwrap_or_else(|| record.line.to_owned())),
+        false => Cow::Borrowed(record.line),
+    };
+    if filter.keeps_all() {
+        return write_line(out, &line).map(|()| 0);
+    }
+    projected.clear();
+    filter.project_line(&line, projected);
+    write_line(out, projected)?;
+    Ok(line.len().saturating_sub(projected.len()) as u64)
+}
+
+/// A writer that only counts.
+struct ByteCounter(u64);
+
+/// Checks what is written against the bytes it holds, failing at the first
+/// difference; what is left is what was not written.
+struct SameBytes<'a>(&'a [u8]);
+
+impl Write for SameBytes<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.0.strip_prefix(buf) {
+            Some(rest) => self.0 = rest,
+            None => return Err(io::Error::other("differs")),
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+//! Features crossing the origin of a circular sequence (plasmids, most
+//! bacterial chromosomes).  A sequence is circular when a record on it,
+//! normally its `region`, has `Is_circular=true`; its length is the end of
+//! its `##sequence-region` pragma, else of that record.  GFF3 writes a
+//! feature that crosses the origin with an end past the sequence length,
+//! and some annotators write it with end < start instead.  Neither is an
+//! interval an index can place, so [`normalize_circular`] splits or clamps
+//! such features before sorting.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io;
+
+use crate::error::{io_error, ErrorCode};
+use crate::gff::{rewrite_lines, GffFields};
+use crate::options::CircularPolicy;
+use crate::warnings::Warnings;
+
+/// The attribute marking a sequence as circular.
+pub const IS_CIRCULAR: &str = "Is_circular";
+
+/// Length of every circular sequence of `gff`, by seqid.
+pub fn circular_lengths(gff: &str) -> HashMap<&str, u64> {
+    let mut regions: HashMap<&str, u64> = HashMap::new();
+    let mut circular: HashMap<&str, u64> = HashMap::new();
+    for line in gff.split('\n').take_while(|l| !l.starts_with("##FASTA")) {
+        if let Some(pragma) = line.strip_prefix("##sequence-region") {
+            if let [seqid, _, end] = pragma.split_whitespace().collect::<Vec<_>>()[..] {
+                if let Ok(end) = end.parse() {
+                    regions.insert(seqid, end);
+                }
+            }
+        } else if let Some(f) = GffFields::parse(line).filter(|f| f.attribute(IS_CIRCULAR) == Some("true")) {
+            circular.entry(f.seqid).or_insert(f.end);
+        }
+    }
+    for (seqid, len) in circular.iter_mut() {
+        if let Some(&region) = regions.get(seqid) {
+            *len = region;
+        }
+    }
+    circular
+}
+
+/// For a feature at `start..=end` on a circular sequence of `len` bases,
+/// the end of its part after the origin (which runs from base 1), if it
+/// crosses the origin.
+pub fn origin_overhang(start: u64, end: u64, len: u64) -> Option<u64> {
+    if start == 0 || start > len {
+        return None;
+    }
+    match end {
+        end if end < start => Some(end),
+        end if end > len => Some(end - len),
+        _ => None,
+    }
+}
+
+/// `line` (without its line ending) with columns 4 and 5 set to `start`
+/// and `end`.
+fn with_coordinates(line: &str, start: u64, end: u64) -> String {
+    let (start, end) = (start.to_string(), end.to_string());
+    let mut cols: Vec<&str> = line.splitn(9, '\t').collect();
+    cols[3] = &start;
+    cols[4] = &end;
+    cols.join("\t")
+}
+
+/// `gff` with every feature that crosses the origin of a circular sequence
+/// handled by `policy`: split into a record ending at the sequence end and
+/// one starting at base 1 (`origin_split` warning), ended at the sequence
+/// end (`origin_clamped` warning), or failed with `E_GFF_PARSE`.  Both
+/// parts of a split feature keep its columns, ID included, which GFF3
+/// reads as one feature in two pieces.  Borrows `gff` when nothing
+/// changes.
+pub fn normalize_circular<'a>(gff: &'a str, policy: CircularPolicy, warnings: &mut Warnings) -> io::Result<Cow<'a, str>> {
+    let lengths = circular_lengths(gff);
+    if lengths.is_empty() {
+        return Ok(Cow::Borrowed(gff));
+    }
+    rewrite_lines(gff, |n, line| {
+        let Some(f) = GffFields::parse(line) else { return Ok(None) };
+        let Some(&len) = lengths.get(f.seqid) else { return Ok(None) };
+        let Some(overhang) = origin_overhang(f.start, f.end, len) else { return Ok(None) };
+        let body = line.trim_end_matches(['\n', '\r']);
+        let eol = &line[body.len()..];
+        if policy == CircularPolicy::Error {
+            return Err(io_error(
+                ErrorCode::GffParse,
+                format!("line {}: {}..{} crosses the origin of circular {:?} ({} bp); split or clamp it with circular_features", n, f.start, f.end, f.seqid, len),
+            ));
+        }
+        let head = with_coordinates(body, f.start, len);
+        if policy == CircularPolicy::Clamp {
+            warnings.push("gff", "origin_clamped", Some(n), format!("{}..{} crosses the origin of {:?}; ended at base {}", f.start, f.end, f.seqid, len));
+            return Ok(Some(Cow::Owned(format!("{}{}", head, eol))));
+        }
+        warnings.push("gff", "origin_split", Some(n), format!("{}..{} crosses the origin of {:?}; split at base {}", f.start, f.end, f.seqid, len));
+        let tail = with_coordinates(body, 1, overhang);
+        Ok(Some(Cow::Owned(format!("{}{}{}{}", head, if eol.is_empty() { "\n" } else { eol }, tail, eol))))
+    })
+}
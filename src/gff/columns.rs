@@ -0,0 +1,80 @@
+//! Column counts of GFF3 records.  Each stage needs a different minimum:
+//! sorting and indexing place a record by its first
+//! [`MIN_RECORD_COLUMNS`] columns (seqid to end), while the exports and
+//! the search index read all [`GFF3_COLUMNS`].  [`normalize_columns`] runs
+//! before sorting so every record that reaches the later stages has all
+//! nine.
+
+use std::borrow::Cow;
+use std::io;
+
+use crate::error::{io_error, ErrorCode};
+use crate::gff::rewrite_lines;
+use crate::options::MalformedRecordPolicy;
+use crate::warnings::Warnings;
+
+/// Columns a record needs to be sorted and indexed: seqid, source, type,
+/// start and end.
+pub const MIN_RECORD_COLUMNS: usize = 5;
+
+/// Columns of a complete GFF3 record.
+pub const GFF3_COLUMNS: usize = 9;
+
+/// `line` (without its line ending) with missing trailing columns added
+/// and empty score/strand/phase/attribute columns set to `.`; `None` when
+/// it already has nine non-empty ones.  Lines with fewer than
+/// [`MIN_RECORD_COLUMNS`] columns are left to the caller.
+pub fn pad_columns(line: &str) -> Option<String> {
+    let cols: Vec<&str> = line.splitn(GFF3_COLUMNS, '\t').collect();
+    let complete = cols.len() == GFF3_COLUMNS && cols[MIN_RECORD_COLUMNS..].iter().all(|c| !c.trim().is_empty());
+    if complete || cols.len() < MIN_RECORD_COLUMNS {
+        return None;
+    }
+    let padded: Vec<&str> = (0..GFF3_COLUMNS)
+        .map(|i| match cols.get(i) {
+            Some(col) if i < MIN_RECORD_COLUMNS || !col.trim().is_empty() => col,
+            _ => ".",
+        })
+        .collect();
+    Some(padded.join("\t"))
+}
+
+/// Why `line` (a data line) cannot be placed in the index, if it cannot.
+fn unplaceable(line: &str) -> Option<String> {
+    let cols: Vec<&str> = line.splitn(GFF3_COLUMNS, '\t').collect();
+    if cols.len() < MIN_RECORD_COLUMNS {
+        return Some(format!("only {} column(s); a record needs at least seqid, source, type, start and end", cols.len()));
+    }
+    let (start, end) = (cols[3].trim(), cols[4].trim());
+    (start.parse::<u64>().is_err() || end.parse::<u64>().is_err()).then(|| format!("start/end not integers: {:?}, {:?}", start, end))
+}
+
+/// `gff` with every record given nine columns: short records are padded
+/// with `.` and empty score, strand, phase or attribute columns set to `.`,
+/// each with a `columns_padded` warning.  Records that cannot be placed
+/// (fewer than five columns, or a start or end that is not a number) fail
+/// with `E_GFF_PARSE` or, with [`MalformedRecordPolicy::Drop`], are removed
+/// with a `record_dropped` warning.  Whitespace-only lines are removed and
+/// anything from an embedded `##FASTA` section on is kept as it is.
+/// Borrows `gff` when nothing changes.
+pub fn normalize_columns<'a>(gff: &'a str, policy: MalformedRecordPolicy, warnings: &mut Warnings) -> io::Result<Cow<'a, str>> {
+    rewrite_lines(gff, |n, line| {
+        let body = line.trim_end_matches(['\n', '\r']);
+        Ok(if body.trim().is_empty() {
+            (!line.trim_end_matches('\n').is_empty()).then_some(Cow::Borrowed(""))
+        } else if body.starts_with('#') {
+            None
+        } else if let Some(reason) = unplaceable(body) {
+            if policy == MalformedRecordPolicy::Error {
+                return Err(io_error(ErrorCode::GffParse, format!("line {}: {}", n, reason)));
+            }
+            warnings.push("gff", "record_dropped", Some(n), format!("{}; record dropped", reason));
+            Some(Cow::Borrowed(""))
+        } else if let Some(padded) = pad_columns(body) {
+            warnings.push("gff", "columns_padded", Some(n), "missing or empty columns set to '.'".to_owned());
+            Some(Cow::Owned(format!("{}{}", padded, &line[body.len()..])))
+        } else {
+            None
+        })
+    })
+}
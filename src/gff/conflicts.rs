@@ -0,0 +1,109 @@
+//! Suspicious overlaps in an annotation, typically left by merging the
+//! GFF3 of several annotation sources:
+//!
+//! - CDS records on the same sequence and strand overlapping by more than
+//!   [`CDS_OVERLAP_FRACTION`] of the shorter one (parts of one multi-part
+//!   CDS, which share an `ID`, are not compared);
+//! - genes (`gene`, `pseudogene`, `ncRNA_gene`, … any type ending in
+//!   `gene`) with the same type, span and strand as another.
+//!
+//! Overlaps are found with the interval tree of `tree.rs`.  Every case is
+//! counted; the first [`MAX_EXAMPLES`] of each kind are listed.
+
+use std::collections::BTreeMap;
+
+use wasm_bindgen::prelude::*;
+
+use super::tree::SeqTree;
+use super::{data_lines, GffFields};
+
+/// Share of the shorter CDS two same-strand CDSs must overlap by.
+pub const CDS_OVERLAP_FRACTION: f64 = 0.5;
+
+/// Examples listed per kind of conflict.
+pub const MAX_EXAMPLES: usize = 20;
+
+/// `{id, source, start, end}` of a record, `id` `null` without an `ID`.
+fn describe(f: &GffFields) -> json::JsonValue {
+    json::object! { id: f.attribute("ID"), source: f.source, start: f.start, end: f.end }
+}
+
+/// The conflicts of `gff`: `{cds_overlaps: {count, examples: [{seqid,
+/// strand, overlap, fraction, a: {id, source, start, end}, b}]},
+/// duplicate_genes: {count, examples: [{seqid, type, start, end, strand,
+/// features: [{id, source}]}]}}`.  `fraction` is the overlap over the
+/// length of the shorter CDS, to 4 decimals; a duplicate gene counts once
+/// per extra copy.
+pub fn conflict_report(gff: &str) -> json::JsonValue {
+    let records: Vec<GffFields> = data_lines(gff).filter_map(GffFields::parse).collect();
+
+    // By sequence and strand, in name order for stable examples.
+    let mut cds = BTreeMap::new();
+    for (i, f) in records.iter().enumerate().filter(|(_, f)| f.ftype == "CDS" && f.start <= f.end) {
+        cds.entry((f.seqid, f.strand)).or_insert_with(Vec::new).push((f.start, f.end, i));
+    }
+    let mut cds_count = 0;
+    let mut cds_examples = json::JsonValue::new_array();
+    for ((seqid, strand), items) in cds {
+        let tree = SeqTree::new(items.clone());
+        let mut hits = Vec::new();
+        for (start, end, i) in items {
+            hits.clear();
+            tree.overlaps(start, end, &mut hits);
+            let a = &records[i];
+            for &j in hits.iter().filter(|&&j| j > i) {
+                let b = &records[j];
+                if a.attribute("ID").is_some() && a.attribute("ID") == b.attribute("ID") {
+                    continue;
+                }
+                let overlap = a.end.min(b.end) - a.start.max(b.start) + 1;
+                let fraction = overlap as f64 / (a.end - a.start + 1).min(b.end - b.start + 1) as f64;
+                if fraction <= CDS_OVERLAP_FRACTION {
+                    continue;
+                }
+                cds_count += 1;
+                if cds_examples.len() < MAX_EXAMPLES {
+                    let _ = cds_examples.push(json::object! {
+                        seqid: seqid,
+                        strand: strand,
+                        overlap: overlap,
+                        fraction: (fraction * 10000.0).round() / 10000.0,
+                        a: describe(a),
+                        b: describe(b),
+                    });
+                }
+            }
+        }
+    }
+
+    let mut genes: BTreeMap<(&str, u64, u64, &str, &str), Vec<&GffFields>> = BTreeMap::new();
+    for f in records.iter().filter(|f| f.ftype.ends_with("gene")) {
+        genes.entry((f.seqid, f.start, f.end, f.strand, f.ftype)).or_default().push(f);
+    }
+    let mut gene_count = 0;
+    let mut gene_examples = json::JsonValue::new_array();
+    for ((seqid, start, end, strand, ftype), copies) in genes.into_iter().filter(|(_, copies)| copies.len() > 1) {
+        gene_count += copies.len() - 1;
+        if gene_examples.len() < MAX_EXAMPLES {
+            let _ = gene_examples.push(json::object! {
+                seqid: seqid,
+                type: ftype,
+                start: start,
+                end: end,
+                strand: strand,
+                features: copies.iter().map(|f| json::object! { id: f.attribute("ID"), source: f.source }).collect::<Vec<_>>(),
+            });
+        }
+    }
+
+    json::object! {
+        cds_overlaps: json::object! { count: cds_count, examples: cds_examples },
+        duplicate_genes: json::object! { count: gene_count, examples: gene_examples },
+    }
+}
+
+/// JSON conflict report of GFF3 text.  See [`conflict_report`].
+#[wasm_bindgen]
+pub fn gff_conflicts(gff: &str) -> String {
+    conflict_report(gff).dump()
+}
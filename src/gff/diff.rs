@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use wasm_bindgen::prelude::*;
+
+use super::{data_lines, record_cmp, GffFields};
+
+/// Features added, removed and changed between two GFF3 versions.
+/// Lists are in index (sorted) order.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct GffDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// `(old line, new line, names of the columns that differ)`.
+    pub changed: Vec<(String, String, Vec<&'static str>)>,
+    pub unchanged: usize,
+}
+
+const COLUMNS: [&str; 9] = ["seqid", "source", "type", "start", "end", "score", "strand", "phase", "attributes"];
+
+/// Identity of a feature across versions: its `ID` when that is unique in the
+/// file, otherwise its coordinates (multi-part features share an `ID`).
+fn feature_keys<'a>(lines: &[&'a str]) -> Vec<String> {
+    let fields: Vec<Option<GffFields<'a>>> = lines.iter().map(|l| GffFields::parse(l)).collect();
+    let mut id_count: HashMap<&str, usize> = HashMap::new();
+    for f in fields.iter().flatten() {
+        if let Some(id) = f.attribute("ID") {
+            *id_count.entry(id).or_default() += 1;
+        }
+    }
+    lines
+        .iter()
+        .zip(&fields)
+        .map(|(line, f)| match f {
+            Some(f) => match f.attribute("ID") {
+                Some(id) if id_count[id] == 1 => format!("ID={}", id),
+                id => format!("{}\t{}\t{}\t{}\t{}\t{}", id.unwrap_or(""), f.seqid, f.ftype, f.start, f.end, f.strand),
+            },
+            // Unparseable lines can only match themselves.
+            None => format!("line\t{}", line),
+        })
+        .collect()
+}
+
+fn changed_columns(old: &str, new: &str) -> Vec<&'static str> {
+    let (o, n): (Vec<&str>, Vec<&str>) = (old.splitn(9, '\t').collect(), new.splitn(9, '\t').collect());
+    COLUMNS
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| o.get(*i) != n.get(*i))
+        .map(|(_, c)| *c)
+        .collect()
+}
+
+/// Compare two GFF3 files feature by feature.
+pub fn diff_gff(old: &str, new: &str) -> GffDiff {
+    let old_lines: Vec<&str> = data_lines(old).map(|l| l.trim_end_matches('\r')).collect();
+    let new_lines: Vec<&str> = data_lines(new).map(|l| l.trim_end_matches('\r')).collect();
+
+    let mut old_by_key: HashMap<String, Vec<&str>> = HashMap::new();
+    for (key, line) in feature_keys(&old_lines).into_iter().zip(&old_lines) {
+        old_by_key.entry(key).or_default().push(line);
+    }
+
+    let mut out = GffDiff::default();
+    for (key, line) in feature_keys(&new_lines).into_iter().zip(&new_lines) {
+        match old_by_key.get_mut(&key).and_then(|v| v.pop()) {
+            Some(prev) if prev == *line => out.unchanged += 1,
+            Some(prev) => out.changed.push((prev.to_owned(), line.to_string(), changed_columns(prev, line))),
+            None => out.added.push(line.to_string()),
+        }
+    }
+    out.removed = old_by_key.into_values().flatten().map(str::to_owned).collect();
+
+    out.added.sort_by(|a, b| record_cmp(a, b));
+    out.removed.sort_by(|a, b| record_cmp(a, b));
+    out.changed.sort_by(|a, b| record_cmp(&a.1, &b.1));
+    out
+}
+
+impl GffDiff {
+    /// JSON report: `{summary, added: [line], removed: [line],
+    /// changed: [{old, new, columns}]}`.
+    pub fn to_json(&self) -> json::JsonValue {
+        json::object! {
+            summary: {
+                added: self.added.len(),
+                removed: self.removed.len(),
+                changed: self.changed.len(),
+                unchanged: self.unchanged,
+            },
+            added: self.added.clone(),
+            removed: self.removed.clone(),
+            changed: self.changed.iter().map(|(old, new, cols)| json::object! {
+                old: old.as_str(),
+                new: new.as_str(),
+                columns: cols.clone(),
+            }).collect::<Vec<_>>(),
+        }
+    }
+}
+
+/// Diff two GFF3 texts; returns the JSON report described in [`GffDiff::to_json`].
+#[wasm_bindgen]
+pub fn gff_diff(old: &str, new: &str) -> String {
+    diff_gff(old, new).to_json().dump()
+}
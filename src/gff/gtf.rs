@@ -0,0 +1,227 @@
+//! GTF / GFF2 input: telling its column 9 (`key "value";`) from GFF3's
+//! (`key=value;`) and converting it to GFF3, so the index is never built
+//! over a file GFF3 readers reject.
+
+use std::collections::HashMap;
+
+use super::{data_lines, GffFields};
+
+/// Column-9 syntax of an annotation file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GffDialect {
+    /// `key=value;key=value`.
+    Gff3,
+    /// `key "value"; key "value";` (GTF and GFF2).
+    Gtf,
+}
+
+impl GffDialect {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GffDialect::Gff3 => "gff3",
+            GffDialect::Gtf => "gtf",
+        }
+    }
+}
+
+/// Data lines [`detect_dialect`] looks at.
+const SAMPLE_LINES: usize = 1000;
+
+/// The dialect most of the first [`SAMPLE_LINES`] records are written in;
+/// GFF3 when none has attributes.
+pub fn detect_dialect(gff: &str) -> GffDialect {
+    let (mut gff3, mut gtf) = (0usize, 0usize);
+    for line in data_lines(gff).take(SAMPLE_LINES) {
+        match line.splitn(9, '\t').nth(8).and_then(attribute_syntax) {
+            Some(GffDialect::Gff3) => gff3 += 1,
+            Some(GffDialect::Gtf) => gtf += 1,
+            None => {}
+        }
+    }
+    if gtf > gff3 { GffDialect::Gtf } else { GffDialect::Gff3 }
+}
+
+/// Syntax of one column 9, from whether its first key ends at `=` or at
+/// whitespace.
+fn attribute_syntax(attributes: &str) -> Option<GffDialect> {
+    let first = attributes.split(';').map(str::trim).find(|a| !a.is_empty() && *a != ".")?;
+    match first.bytes().find(|&b| b == b'=' || b.is_ascii_whitespace())? {
+        b'=' => Some(GffDialect::Gff3),
+        _ => Some(GffDialect::Gtf),
+    }
+}
+
+/// `tag value` pairs of a GTF/GFF2 column 9, with the quotes removed.
+/// Quoted values may contain `;`.
+fn gtf_attributes(attributes: &str) -> Vec<(&str, &str)> {
+    let mut pairs = Vec::new();
+    let mut rest = attributes.trim_end_matches(['\r', '\n']);
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == ';' || c.is_ascii_whitespace());
+        if rest.is_empty() || rest == "." {
+            return pairs;
+        }
+        let key_end = rest.find(|c: char| c.is_ascii_whitespace() || c == ';').unwrap_or(rest.len());
+        let key = &rest[..key_end];
+        rest = rest[key_end..].trim_start_matches([' ', '\t']);
+        let value = if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            rest = quoted.get(end + 1..).unwrap_or_default();
+            &quoted[..end]
+        } else {
+            let end = rest.find(';').unwrap_or(rest.len());
+            let value = rest[..end].trim();
+            rest = &rest[end..];
+            value
+        };
+        pairs.push((key, value));
+    }
+}
+
+/// Append `s` to `out` with the characters GFF3 reserves in column 9
+/// `%XX`-escaped.
+fn escape_into(s: &str, out: &mut String) {
+    for c in s.chars() {
+        match c {
+            ';' | '=' | '&' | ',' | '%' => out.push_str(&format!("%{:02X}", c as u32)),
+            c if c.is_ascii_control() => out.push_str(&format!("%{:02X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+}
+
+/// GFF3 column 9 for `pairs`, after `ID` and `Parent` if given.  Repeated
+/// tags (GENCODE's `tag "basic"; tag "CCDS";`) become one multi-valued
+/// attribute.
+fn gff3_attributes(id: Option<&str>, parent: Option<&str>, pairs: &[(&str, &str)]) -> String {
+    let mut merged: Vec<(&str, Vec<&str>)> = Vec::new();
+    for &(key, value) in pairs {
+        match merged.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, values)) => values.push(value),
+            None => merged.push((key, vec![value])),
+        }
+    }
+    let mut out = String::new();
+    let reserved = [("ID", id), ("Parent", parent)];
+    for (key, value) in reserved.iter().filter_map(|(k, v)| Some((*k, (*v)?))) {
+        out.push_str(key);
+        out.push('=');
+        escape_into(value, &mut out);
+        out.push(';');
+    }
+    for (key, values) in merged {
+        escape_into(key, &mut out);
+        out.push('=');
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            escape_into(value, &mut out);
+        }
+        out.push(';');
+    }
+    out.pop();
+    if out.is_empty() {
+        out.push('.');
+    }
+    out
+}
+
+/// Extent of the features of one gene or transcript.
+struct Span<'a> {
+    seqid: &'a str,
+    source: &'a str,
+    strand: &'a str,
+    start: u64,
+    end: u64,
+    gene_id: Option<&'a str>,
+    /// A `gene`/`transcript` line of its own exists.
+    declared: bool,
+}
+
+/// Spans by ID, in order of first appearance.
+#[derive(Default)]
+struct Spans<'a> {
+    order: Vec<&'a str>,
+    by_id: HashMap<&'a str, Span<'a>>,
+}
+
+impl<'a> Spans<'a> {
+    fn add(&mut self, id: &'a str, f: &GffFields<'a>, gene_id: Option<&'a str>, declared: bool) {
+        let span = self.by_id.entry(id).or_insert_with(|| {
+            self.order.push(id);
+            Span { seqid: f.seqid, source: f.source, strand: f.strand, start: f.start, end: f.end, gene_id, declared: false }
+        });
+        span.start = span.start.min(f.start);
+        span.end = span.end.max(f.end);
+        span.declared |= declared;
+    }
+
+    /// Lines for the spans without a line of their own.
+    fn undeclared(&self, ftype: &str, out: &mut String) -> usize {
+        let mut added = 0;
+        for id in &self.order {
+            let span = &self.by_id[id];
+            if span.declared {
+                continue;
+            }
+            let pairs: Vec<(&str, &str)> = match span.gene_id {
+                Some(gene_id) => vec![("gene_id", gene_id), ("transcript_id", id)],
+                None => vec![("gene_id", id)],
+            };
+            let attributes = gff3_attributes(Some(id), span.gene_id, &pairs);
+            out.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\t.\t{}\t.\t{}\n",
+                span.seqid, span.source, ftype, span.start, span.end, span.strand, attributes
+            ));
+            added += 1;
+        }
+        added
+    }
+}
+
+/// Convert GTF/GFF2 text to GFF3: column 9 rewritten as `key=value`,
+/// `gene` records given `ID=<gene_id>`, `transcript`/`mRNA` records
+/// `ID=<transcript_id>;Parent=<gene_id>`, everything else
+/// `Parent=<transcript_id>` (or its gene), and a gene or transcript record
+/// added, spanning its features, for each one GTF only implies.  The
+/// version pragma is replaced and an embedded `##FASTA` section dropped;
+/// other comments and lines that are not records are kept.  Returns the
+/// text and the number of records added.
+pub fn gtf_to_gff3(gtf: &str) -> (String, usize) {
+    let mut out = String::with_capacity(gtf.len() + gtf.len() / 4);
+    out.push_str("##gff-version 3\n");
+    let (mut genes, mut transcripts) = (Spans::default(), Spans::default());
+    for line in gtf.split('\n').take_while(|l| !l.starts_with("##FASTA")) {
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() || line.starts_with("##gff-version") {
+            continue;
+        }
+        let Some(f) = GffFields::parse(line) else {
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        };
+        let pairs = gtf_attributes(f.attributes);
+        let get = |key: &str| pairs.iter().find(|(k, _)| *k == key).map(|&(_, v)| v).filter(|v| !v.is_empty());
+        let (gene_id, transcript_id) = (get("gene_id"), get("transcript_id"));
+        let (id, parent) = match f.ftype {
+            "gene" => (gene_id, None),
+            "transcript" | "mRNA" => (transcript_id, gene_id),
+            _ => (None, transcript_id.or(gene_id)),
+        };
+        if let Some(gene_id) = gene_id {
+            genes.add(gene_id, &f, None, f.ftype == "gene");
+        }
+        if let Some(transcript_id) = transcript_id.filter(|_| f.ftype != "gene") {
+            transcripts.add(transcript_id, &f, gene_id, matches!(f.ftype, "transcript" | "mRNA"));
+        }
+        let cols: Vec<&str> = line.splitn(9, '\t').take(8).collect();
+        out.push_str(&cols.join("\t"));
+        out.push('\t');
+        out.push_str(&gff3_attributes(id, parent, &pairs));
+        out.push('\n');
+    }
+    let added = genes.undeclared("gene", &mut out) + transcripts.undeclared("transcript", &mut out);
+    (out, added)
+}
@@ -0,0 +1,100 @@
+//! Features in several parts.  GFF3 writes a discontinuous feature, such as
+//! a spliced CDS or a trans-spliced gene, as one record per part, the parts
+//! sharing seqid, type and `ID`.  Sorting and indexing handle the records
+//! one by one; the stages that count, search, translate or draw features
+//! group the parts first with [`group_parts`].
+
+use std::collections::HashMap;
+
+use crate::fasta::Sequences;
+use crate::gff::GffFields;
+use crate::translate::reverse_complement;
+
+/// The feature `f` is a part of: its seqid, type and `ID`.  `None` for a
+/// record without an `ID`, which is a feature of its own.
+pub fn part_key<'a>(f: &GffFields<'a>) -> Option<(&'a str, &'a str, &'a str)> {
+    f.attribute("ID").map(|id| (f.seqid, f.ftype, id))
+}
+
+/// One feature and its parts.
+#[derive(Clone, Debug)]
+pub struct Feature<'a> {
+    /// In transcription order for trans-spliced features, otherwise by
+    /// coordinate.
+    pub parts: Vec<GffFields<'a>>,
+}
+
+impl<'a> Feature<'a> {
+    pub fn first(&self) -> &GffFields<'a> {
+        &self.parts[0]
+    }
+
+    /// Smallest start and largest end of the parts.
+    pub fn extent(&self) -> (u64, u64) {
+        let start = self.parts.iter().map(|p| p.start).min().unwrap_or(0);
+        let end = self.parts.iter().map(|p| p.end).max().unwrap_or(0);
+        (start, end)
+    }
+
+    /// Whether the parts are joined in input order rather than coordinate
+    /// order: they lie on different strands, or the feature has
+    /// `exception=trans-splicing` (as NCBI writes).
+    pub fn is_trans_spliced(&self) -> bool {
+        self.parts.iter().any(|p| p.strand != self.first().strand)
+            || self.parts.iter().any(|p| p.attribute("exception").is_some_and(|e| e.contains("trans-splicing")))
+    }
+
+    /// The part where transcription starts.
+    pub fn five_prime(&self) -> &GffFields<'a> {
+        match (self.is_trans_spliced(), self.first().strand) {
+            (false, "-") => self.parts.last().unwrap_or(&self.parts[0]),
+            _ => self.first(),
+        }
+    }
+
+    /// Whether a part has `pseudo` or `pseudogene`, which may be a bare
+    /// flag (no `=value`), as written by EMBOSS.
+    pub fn is_pseudo(&self) -> bool {
+        self.parts.iter().flat_map(|p| p.attributes.split(';')).any(|kv| {
+            let key = kv.trim().split('=').next().unwrap_or_default();
+            key == "pseudo" || key == "pseudogene"
+        })
+    }
+
+    /// Nucleotide sequence of the parts joined in transcription order,
+    /// each read on its own strand; `None` if a part is outside `seqs`.
+    pub fn spliced(&self, seqs: &Sequences) -> Option<Vec<u8>> {
+        let trans = self.is_trans_spliced();
+        let mut nt = Vec::new();
+        for p in &self.parts {
+            let part = seqs.region(p.seqid, p.start, p.end)?;
+            nt.extend(if trans && p.strand == "-" { reverse_complement(&part) } else { part });
+        }
+        Some(if !trans && self.first().strand == "-" { reverse_complement(&nt) } else { nt })
+    }
+}
+
+/// Group `records` into features, in the order of their first part.
+/// Parts of trans-spliced features keep their input order, the others are
+/// sorted by coordinate.
+pub fn group_parts<'a>(records: impl IntoIterator<Item = GffFields<'a>>) -> Vec<Feature<'a>> {
+    let mut features: Vec<Feature> = Vec::new();
+    let mut by_key: HashMap<(&str, &str, &str), usize> = HashMap::new();
+    for f in records {
+        match part_key(&f) {
+            Some(key) if by_key.contains_key(&key) => features[by_key[&key]].parts.push(f),
+            key => {
+                if let Some(key) = key {
+                    by_key.insert(key, features.len());
+                }
+                features.push(Feature { parts: vec![f] });
+            }
+        }
+    }
+    for feature in &mut features {
+        if !feature.is_trans_spliced() {
+            feature.parts.sort_by_key(|p| (p.start, p.end));
+        }
+    }
+    features
+}
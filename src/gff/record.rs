@@ -0,0 +1,238 @@
+//! Record-level GFF3 parsing: the column splitting every reader of GFF3
+//! lines shares, an owned [`GffRecord`] with typed columns and decoded
+//! attributes, and [`GffReader`], which streams records from any
+//! [`BufRead`] without holding the file in memory.  [`GffRecords`] is the
+//! iterator-style JS form, for inspecting records in a worker:
+//!
+//! ```js
+//! const records = new GffRecords(text);
+//! for (let r; (r = records.next()) !== undefined; ) {
+//!   const { seqid, type, start, end, attributes } = JSON.parse(r);
+//! }
+//! ```
+//!
+//! Comments, directives and blank lines are skipped, and reading stops at
+//! an embedded `##FASTA` section.  Unlike [`GffFields::parse`], which
+//! passes over lines it cannot read, a malformed record is an
+//! `E_GFF_PARSE` error naming its line.
+//!
+//! [`GffFields::parse`]: super::GffFields::parse
+
+use std::io::{self, BufRead, Cursor};
+use std::ops::Range;
+
+use wasm_bindgen::prelude::*;
+
+use super::attribute_values;
+use crate::error::{io_error, js_error, ErrorCode};
+use crate::health;
+
+/// The first `n` tab-separated columns of `line`, the last running to the
+/// end of the line.
+pub fn columns(line: &str, n: usize) -> impl Iterator<Item = &str> {
+    column_ranges(line.as_bytes(), n).map(move |r| &line[r])
+}
+
+/// Byte ranges of the first `n` tab-separated columns of `line`, as for
+/// [`columns`].
+pub fn column_ranges(line: &[u8], n: usize) -> impl Iterator<Item = Range<usize>> + '_ {
+    let mut start = Some(0);
+    (0..n).map_while(move |i| {
+        let from = start?;
+        let to = match line[from..].iter().position(|&b| b == b'\t') {
+            Some(tab) if i + 1 < n => from + tab,
+            _ => line.len(),
+        };
+        start = (to < line.len()).then_some(to + 1);
+        Some(from..to)
+    })
+}
+
+/// A GFF3 data record with typed columns.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GffRecord {
+    /// 1-based line number in the input.
+    pub line: usize,
+    pub seqid: String,
+    pub source: String,
+    pub ftype: String,
+    /// 1-based, inclusive.
+    pub start: u64,
+    pub end: u64,
+    /// `None` for `.`.
+    pub score: Option<f64>,
+    /// `+`, `-`, `.` or `?`.
+    pub strand: char,
+    /// `None` for `.`.
+    pub phase: Option<u8>,
+    /// Column 9 in order, each value list decoded as by
+    /// [`attribute_values`]; a flag without `=` has no values.
+    pub attributes: Vec<(String, Vec<String>)>,
+}
+
+fn malformed(line: usize, what: impl std::fmt::Display) -> io::Error {
+    io_error(ErrorCode::GffParse, format!("line {}: {}", line, what))
+}
+
+impl GffRecord {
+    /// Parse line `n` (1-based) of a GFF3: `None` for comments, directives
+    /// and blank lines, an error for a record without nine columns or with
+    /// an unreadable coordinate, score, strand or phase.
+    pub fn parse(text: &str, n: usize) -> io::Result<Option<Self>> {
+        let text = text.trim_end_matches(['\n', '\r']);
+        if text.trim().is_empty() || text.starts_with('#') {
+            return Ok(None);
+        }
+        let f: Vec<&str> = columns(text, 9).collect();
+        if f.len() < 9 {
+            return Err(malformed(n, format_args!("{} column(s), expected 9", f.len())));
+        }
+        let coordinate = |col: &str| col.trim().parse::<u64>().map_err(|_| malformed(n, format_args!("non-numeric coordinate {:?}", col)));
+        let score = match f[5].trim() {
+            "." => None,
+            s => Some(s.parse().map_err(|_| malformed(n, format_args!("non-numeric score {:?}", s)))?),
+        };
+        let strand = match f[6].trim() {
+            s @ ("+" | "-" | "." | "?") => s.chars().next().unwrap_or('.'),
+            s => return Err(malformed(n, format_args!("strand {:?} is not one of + - . ?", s))),
+        };
+        let phase = match f[7].trim() {
+            "." => None,
+            p @ ("0" | "1" | "2") => p.parse().ok(),
+            p => return Err(malformed(n, format_args!("phase {:?} is not one of 0 1 2 .", p))),
+        };
+        let attributes = f[8]
+            .split(';')
+            .map(str::trim)
+            .filter(|kv| !kv.is_empty() && *kv != ".")
+            .map(|kv| match kv.split_once('=') {
+                Some((k, v)) => (k.to_owned(), attribute_values(v)),
+                None => (kv.to_owned(), Vec::new()),
+            })
+            .collect();
+        Ok(Some(GffRecord {
+            line: n,
+            seqid: f[0].to_owned(),
+            source: f[1].to_owned(),
+            ftype: f[2].to_owned(),
+            start: coordinate(f[3])?,
+            end: coordinate(f[4])?,
+            score,
+            strand,
+            phase,
+            attributes,
+        }))
+    }
+
+    /// First value of attribute `key`.
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes.iter().find(|(k, _)| k == key).and_then(|(_, v)| v.first()).map(String::as_str)
+    }
+
+    /// `{line, seqid, source, type, start, end, score, strand, phase,
+    /// attributes: {key: [values]}}`, with `null` for `.` scores and
+    /// phases; repeated keys are merged.
+    pub fn to_json(&self) -> json::JsonValue {
+        let mut attributes = json::JsonValue::new_object();
+        for (k, values) in &self.attributes {
+            if !attributes.has_key(k) {
+                attributes[k.as_str()] = json::JsonValue::new_array();
+            }
+            for v in values {
+                let _ = attributes[k.as_str()].push(v.as_str());
+            }
+        }
+        json::object! {
+            line: self.line,
+            seqid: self.seqid.as_str(),
+            source: self.source.as_str(),
+            type: self.ftype.as_str(),
+            start: self.start,
+            end: self.end,
+            score: self.score,
+            strand: self.strand.to_string(),
+            phase: self.phase,
+            attributes: attributes,
+        }
+    }
+}
+
+/// Streams the records of a GFF3 from a reader, a line at a time.
+pub struct GffReader<R> {
+    reader: R,
+    buf: Vec<u8>,
+    line: usize,
+    done: bool,
+}
+
+impl<R: BufRead> GffReader<R> {
+    pub fn new(reader: R) -> Self {
+        GffReader { reader, buf: Vec::new(), line: 0, done: false }
+    }
+
+    /// Lines read so far.
+    pub fn lines_read(&self) -> usize {
+        self.line
+    }
+
+    /// The next record, or `None` at the end of the records.
+    pub fn next_record(&mut self) -> io::Result<Option<GffRecord>> {
+        while !self.done {
+            self.buf.clear();
+            if self.reader.read_until(b'\n', &mut self.buf)? == 0 {
+                break;
+            }
+            self.line += 1;
+            let text = std::str::from_utf8(&self.buf)
+                .map_err(|_| io_error(ErrorCode::GffEncoding, format!("line {}: not valid UTF-8", self.line)))?;
+            if text.starts_with("##FASTA") {
+                break;
+            }
+            if let Some(record) = GffRecord::parse(text, self.line)? {
+                return Ok(Some(record));
+            }
+        }
+        self.done = true;
+        Ok(None)
+    }
+}
+
+impl<R: BufRead> Iterator for GffReader<R> {
+    type Item = io::Result<GffRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.next_record().transpose();
+        if matches!(next, Some(Err(_))) {
+            self.done = true;
+        }
+        next
+    }
+}
+
+/// The records of GFF3 text, one at a time (see the module docs).
+#[wasm_bindgen]
+pub struct GffRecords {
+    reader: GffReader<Cursor<Vec<u8>>>,
+}
+
+#[wasm_bindgen]
+impl GffRecords {
+    #[wasm_bindgen(constructor)]
+    pub fn new(gff: String) -> GffRecords {
+        GffRecords { reader: GffReader::new(Cursor::new(gff.into_bytes())) }
+    }
+
+    /// The next record as JSON (see [`GffRecord::to_json`]), or
+    /// `undefined` after the last.  A malformed record throws
+    /// `E_GFF_PARSE`, after which iteration ends.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<String>, JsValue> {
+        health::contain(|| self.reader.next().transpose().map(|r| r.map(|r| r.to_json().dump()))).map_err(js_error)
+    }
+
+    /// Lines read so far.
+    #[wasm_bindgen(getter)]
+    pub fn lines_read(&self) -> usize {
+        self.reader.lines_read()
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use wasm_bindgen::prelude::*;
+
+use super::{data_lines, GffFields};
+
+/// Features of one sequence sorted by start, with an implicit augmented
+/// binary tree (`max_end`) over the sorted order.
+pub(super) struct SeqTree {
+    /// `(start, end, record index)`, 1-based inclusive, sorted by start.
+    items: Vec<(u64, u64, usize)>,
+    /// Heap-ordered max end of each subtree; node 1 covers `0..items.len()`.
+    max_end: Vec<u64>,
+    /// `prefix_max_end[i]` = max end of `items[..=i]`.
+    prefix_max_end: Vec<u64>,
+}
+
+impl SeqTree {
+    pub(super) fn new(mut items: Vec<(u64, u64, usize)>) -> Self {
+        items.sort_unstable();
+        let mut tree = SeqTree { max_end: vec![0; 4 * items.len().max(1)], prefix_max_end: Vec::new(), items };
+        if !tree.items.is_empty() {
+            tree.build(1, 0, tree.items.len());
+        }
+        tree.prefix_max_end = tree
+            .items
+            .iter()
+            .scan(0, |m, &(_, e, _)| {
+                *m = e.max(*m);
+                Some(*m)
+            })
+            .collect();
+        tree
+    }
+
+    fn build(&mut self, node: usize, lo: usize, hi: usize) -> u64 {
+        let m = if hi - lo == 1 {
+            self.items[lo].1
+        } else {
+            let mid = (lo + hi) / 2;
+            self.build(2 * node, lo, mid).max(self.build(2 * node + 1, mid, hi))
+        };
+        self.max_end[node] = m;
+        m
+    }
+
+    /// Record indices of items overlapping `[start, end]`, in start order.
+    pub(super) fn overlaps(&self, start: u64, end: u64, out: &mut Vec<usize>) {
+        let mut hits = Vec::new();
+        self.overlapping_items(start, end, &mut hits);
+        out.extend(hits.into_iter().map(|i| self.items[i].2));
+    }
+
+    /// Positions in `items` overlapping `[start, end]`.  Only the prefix of
+    /// items starting at or before `end` is searched, and subtrees ending
+    /// before `start` are pruned.
+    fn overlapping_items(&self, start: u64, end: u64, out: &mut Vec<usize>) {
+        let limit = self.items.partition_point(|&(s, _, _)| s <= end);
+        if limit > 0 {
+            self.collect(1, 0, self.items.len(), limit, start, out);
+        }
+    }
+
+    fn collect(&self, node: usize, lo: usize, hi: usize, limit: usize, start: u64, out: &mut Vec<usize>) {
+        if lo >= limit || self.max_end[node] < start {
+            return;
+        }
+        if hi - lo == 1 {
+            out.push(lo);
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        self.collect(2 * node, lo, mid, limit, start, out);
+        self.collect(2 * node + 1, mid, hi, limit, start, out);
+    }
+
+    /// Record indices of the items closest to `pos`: those containing it, or
+    /// else every item at the minimal distance on either side.
+    fn nearest(&self, pos: u64, out: &mut Vec<usize>) {
+        self.overlaps(pos, pos, out);
+        if !out.is_empty() {
+            return;
+        }
+        let split = self.items.partition_point(|&(s, _, _)| s <= pos);
+        // Everything starting at or before `pos` ends before it here.
+        let left = split.checked_sub(1).map(|i| self.prefix_max_end[i]);
+        let right = self.items.get(split).map(|&(s, _, _)| s);
+        let Some(best) = left.map(|e| pos - e).into_iter().chain(right.map(|s| s - pos)).min() else {
+            return;
+        };
+        if let Some(e) = left.filter(|e| pos - e == best) {
+            let mut touching = Vec::new();
+            self.overlapping_items(e, e, &mut touching);
+            out.extend(touching.into_iter().filter(|&i| self.items[i].1 == e).map(|i| self.items[i].2));
+        }
+        if let Some(s) = right.filter(|s| s - pos == best) {
+            out.extend(self.items[split..].iter().take_while(|it| it.0 == s).map(|it| it.2));
+        }
+    }
+}
+
+/// In-memory interval tree over parsed GFF features, for interactive queries
+/// while the annotation is still resident (the `.csi` serves on-disk ones).
+/// Coordinates are 1-based and inclusive, like GFF3.
+#[wasm_bindgen]
+pub struct FeatureTree {
+    records: Vec<String>,
+    seqs: HashMap<String, SeqTree>,
+}
+
+impl FeatureTree {
+    /// Build the tree from GFF3 text; comments, the `##FASTA` section and
+    /// unparseable lines are skipped.
+    pub fn build(gff: &str) -> Self {
+        let mut records = Vec::new();
+        let mut items: HashMap<String, Vec<(u64, u64, usize)>> = HashMap::new();
+        for line in data_lines(gff) {
+            let line = line.trim_end_matches('\r');
+            if let Some(f) = GffFields::parse(line) {
+                items.entry(f.seqid.to_owned()).or_default().push((f.start, f.end, records.len()));
+                records.push(line.to_owned());
+            }
+        }
+        let seqs = items.into_iter().map(|(seq, items)| (seq, SeqTree::new(items))).collect();
+        FeatureTree { records, seqs }
+    }
+
+    fn lines(&self, indices: Vec<usize>) -> Vec<String> {
+        indices.into_iter().map(|i| self.records[i].clone()).collect()
+    }
+}
+
+#[wasm_bindgen]
+impl FeatureTree {
+    #[wasm_bindgen(constructor)]
+    pub fn new(gff: &str) -> FeatureTree {
+        Self::build(gff)
+    }
+
+    /// GFF lines of the features overlapping `start..=end` on `seqid`.
+    pub fn overlaps(&self, seqid: &str, start: u64, end: u64) -> Vec<String> {
+        let mut out = Vec::new();
+        if let Some(tree) = self.seqs.get(seqid) {
+            tree.overlaps(start, end, &mut out);
+        }
+        self.lines(out)
+    }
+
+    /// GFF lines of the features nearest to `pos` on `seqid`: those containing
+    /// it, or else all features at the smallest distance.
+    pub fn nearest(&self, seqid: &str, pos: u64) -> Vec<String> {
+        let mut out = Vec::new();
+        if let Some(tree) = self.seqs.get(seqid) {
+            tree.nearest(pos, &mut out);
+        }
+        self.lines(out)
+    }
+
+    /// Number of features in the tree.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
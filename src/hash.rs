@@ -0,0 +1,66 @@
+//! Content hashing used for cache keys and output checksums.
+//!
+//! [`sha256_hex`] hashes in Rust.  [`sha256_hex_all`] can hand the work to
+//! the browser's WebCrypto (`crypto.subtle.digest`) instead, which hashes
+//! natively and off the wasm thread, leaving it free for compression; the
+//! Rust path is the fallback wherever WebCrypto is missing or refuses.
+
+use sha2::{Digest, Sha256};
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+/// Inputs larger than this are hashed in Rust even when offloading:
+/// `crypto.subtle.digest` has no incremental form, so the browser holds a
+/// copy of each input it hashes.
+pub const WEBCRYPTO_MAX_BYTES: usize = 1 << 30;
+
+/// Lower-case hex SHA-256 of `data`.
+pub fn sha256_hex(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+/// Lower-case hex encoding.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// `crypto` of the window or worker, if there is one.
+pub(crate) fn web_crypto() -> Option<web_sys::Crypto> {
+    if !cfg!(all(target_arch = "wasm32", not(target_os = "wasi"))) {
+        return None;
+    }
+    let global = js_sys::global();
+    if let Some(scope) = global.dyn_ref::<web_sys::WorkerGlobalScope>() {
+        scope.crypto().ok()
+    } else {
+        global.dyn_ref::<web_sys::Window>()?.crypto().ok()
+    }
+}
+
+/// `crypto.subtle` of the window or worker, if there is one (secure
+/// contexts only).
+pub(crate) fn subtle_crypto() -> Option<web_sys::SubtleCrypto> {
+    let subtle = web_crypto()?.subtle();
+    (!subtle.is_undefined()).then_some(subtle)
+}
+
+/// [`sha256_hex`] of each of `inputs`.  With `offload`, every input up to
+/// [`WEBCRYPTO_MAX_BYTES`] is handed to WebCrypto before any is awaited, so
+/// the browser may hash them in parallel; the rest, and any WebCrypto
+/// rejects, are hashed in Rust, as are all of them outside a browser.
+pub async fn sha256_hex_all<const N: usize>(inputs: [&[u8]; N], offload: bool) -> [String; N] {
+    let subtle = if offload { subtle_crypto() } else { None };
+    let pending = inputs.map(|data| {
+        let subtle = subtle.as_ref().filter(|_| data.len() <= WEBCRYPTO_MAX_BYTES)?;
+        subtle.digest_with_str_and_u8_array("SHA-256", data).ok()
+    });
+    let mut out: [String; N] = std::array::from_fn(|_| String::new());
+    for ((hex, data), promise) in out.iter_mut().zip(inputs).zip(pending) {
+        let digest = match promise {
+            Some(promise) => JsFuture::from(promise).await.ok().map(|buf| js_sys::Uint8Array::new(&buf).to_vec()),
+            None => None,
+        };
+        *hex = digest.map_or_else(|| sha256_hex(data), |d| to_hex(&d));
+    }
+    out
+}
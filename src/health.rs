@@ -0,0 +1,96 @@
+//! Panic containment at the wasm boundary.
+//!
+//! A panic leaves the instance in an unknown state (half-written buffers,
+//! leaked shadow stack), and on `wasm32-unknown-unknown` it aborts as a
+//! `RuntimeError` trap that `catch_unwind` cannot stop.  The panic hook
+//! installed here therefore marks the instance unhealthy before anything
+//! unwinds or traps; every exported entry point then refuses to run with
+//! `E_INSTANCE_POISONED` until the worker calls [`reset`] (or reloads the
+//! module).  Where unwinding is available (native, WASI with `panic =
+//! "unwind"`) [`contain`] also turns the panic itself into an `E_PANIC`
+//! error instead of letting it escape.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, Once};
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{ErrorCode, PreprocessError};
+
+static POISONED: AtomicBool = AtomicBool::new(false);
+static LAST_PANIC: Mutex<Option<String>> = Mutex::new(None);
+static HOOK: Once = Once::new();
+
+/// Install the panic hook that records panics (idempotent).  In the browser
+/// it also forwards them to `console.error` (`console_error_panic_hook`).
+pub fn install_panic_hook() {
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            POISONED.store(true, Ordering::SeqCst);
+            if let Ok(mut last) = LAST_PANIC.lock() {
+                *last = Some(info.to_string());
+            }
+            if cfg!(all(target_arch = "wasm32", not(target_os = "wasi"))) {
+                console_error_panic_hook::hook(info);
+            } else {
+                previous(info);
+            }
+        }));
+    });
+}
+
+/// Fails with `E_INSTANCE_POISONED` if an earlier call panicked.  Async entry
+/// points call this first; [`contain`] does it for the synchronous ones.
+pub fn check() -> Result<(), PreprocessError> {
+    install_panic_hook();
+    if !POISONED.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    let cause = last_panic().unwrap_or_else(|| "unknown panic".to_owned());
+    Err(PreprocessError::new(
+        ErrorCode::InstancePoisoned,
+        format!("an earlier call panicked ({}); call reset() or reload the module", cause),
+    ))
+}
+
+/// Runs one entry point: refuses if the instance is poisoned, and returns a
+/// panic in `f` as an `E_PANIC` error where the target can unwind.
+pub fn contain<T, E: From<PreprocessError>>(f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+    check()?;
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = last_panic()
+            .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_owned());
+        Err(PreprocessError::new(ErrorCode::Panic, message).into())
+    })
+}
+
+/// False once a call has panicked, until [`reset`].
+#[wasm_bindgen]
+pub fn is_healthy() -> bool {
+    !POISONED.load(Ordering::SeqCst)
+}
+
+/// Message and location of the last panic, if any since [`reset`].
+#[wasm_bindgen]
+pub fn last_panic() -> Option<String> {
+    LAST_PANIC.lock().ok().and_then(|last| last.clone())
+}
+
+/// Clear the unhealthy flag after a panic so the instance takes calls again.
+/// Results (`IndexGen`, `SearchIndex`, …) are owned by their JS handles and
+/// there is no other state kept between calls; free the handles involved in
+/// the failed call, since they may be half-built.  A trap leaks the stack
+/// frames it interrupted, so a worker that keeps hitting panics should
+/// reload the module instead.
+#[wasm_bindgen]
+pub fn reset() {
+    install_panic_hook();
+    POISONED.store(false, Ordering::SeqCst);
+    if let Ok(mut last) = LAST_PANIC.lock() {
+        *last = None;
+    }
+}
@@ -1,13 +1,48 @@
 use wasm_bindgen::prelude::*;
-use std::io::Cursor;
+use std::io::{self, Cursor, Write};
+
+use crate::error::{io_error, js_error, ErrorCode};
+use crate::health;
+use crate::input::visit_js_chunks;
+use crate::limits;
 
 mod bgzf;
+mod crc;
 mod tabix;
 mod faidx;
+mod query;
+mod compare;
+mod members;
+mod speculative;
+mod pool;
+mod reheader;
+mod append;
+mod voffset;
 
-pub use bgzf::{BgzfWriter, BgzfReader, bgzf_compress};
-pub use tabix::csi_index_gff;
-pub use faidx::faidx_index_fasta;
+pub use bgzf::{
+    bgzf_cat, bgzf_compress, bgzf_compress_chunks, bgzf_compress_level, bgzf_compress_split, gzip_compress_level, plausible_block_header, recover_bgzf, BgzfReader, BgzfRecovery,
+    BgzfWriter, DamagedRange, GzipWriter, EOF_BLOCK,
+};
+pub use crc::{crc32, crc32_impl, set_crc32_impl, Crc32Impl};
+pub use tabix::{
+    csi_index, csi_index_gff, csi_index_reader, csi_index_reader_with, tbi_index, tbi_index_gff, tbi_index_reader, tbi_index_reader_with,
+    BinRollup, TabixConfig, TabixIndexer, N_LVLS as CSI_N_LVLS,
+};
+pub use faidx::{faidx_index_fasta, faidx_index_fasta_with, faidx_index_reader, gzi_index, FaiOffsets, FaidxIndexer};
+pub(crate) use faidx::LineLayout;
+pub use compare::{compare_index, IndexDiff, IndexDifference};
+pub use pool::{pool_stats, pooled_bytes, PoolStats};
+pub(crate) use pool::PooledBuf;
+pub(crate) use crc::Crc32;
+pub use members::{gzip_members, inflate_member, inflate_members, inflate_parallel, GzipMember};
+pub use speculative::inflate_speculative;
+pub use reheader::{gff_header, reheader_gff};
+pub use append::append_gff;
+pub use voffset::{compose_voffset, region_bin, region_bins, split_voffset, uncompressed_to_voffset, voffset_to_uncompressed};
+pub use query::{
+    chunk_block_range, extract_fasta_region, extract_gff_chunk, fai_byte_range, gzi_block_range,
+    parse_fai, parse_gzi, record_at, verify_fasta_index, BlockRange, CsiIndex, FaiRecord, IndexDensity, DENSE_BIN_CHUNKS,
+};
 
 // ---------------------------------------------------------------------------
 // WASM-bindgen exports
@@ -15,20 +50,118 @@ pub use faidx::faidx_index_fasta;
 
 /// Compress raw bytes into BGZF format.
 #[wasm_bindgen]
-pub fn compress_bgzf(input: &[u8]) -> Vec<u8> {
-    let mut output = Vec::new();
-    bgzf_compress(Cursor::new(input), &mut output)
-        .expect("bgzf_compress failed");
-    output
+pub fn compress_bgzf(input: &[u8]) -> Result<Vec<u8>, JsValue> {
+    health::contain(|| {
+        let mut output = Vec::new();
+        bgzf_compress(Cursor::new(input), &mut output)?;
+        Ok(output)
+    })
+    .map_err(js_error)
+}
+
+/// The deflate `level` a standalone compressor was given, 6 by default.
+/// These have no warnings to report a clamp in, so past 9 throws
+/// `E_INVALID_OPTION`.
+fn deflate_level(level: Option<u32>) -> io::Result<u32> {
+    match level.unwrap_or(6) {
+        level @ 0..=9 => Ok(level),
+        level => Err(io_error(ErrorCode::InvalidOption, format!("compression level {} is out of range 0-9", level))),
+    }
+}
+
+/// Compress chunks into BGZF without joining them first: an array of
+/// `Uint8Array`s, or an async iterable of them (a Node `Readable`, a web
+/// `ReadableStream`).  Each chunk goes through one reused buffer, so memory
+/// stays at the output plus the largest chunk; the output is byte-identical
+/// to `compress_bgzf` on the concatenation.  `level` is 0-9, 6 by default.
+#[wasm_bindgen]
+pub async fn compress_bgzf_chunks(chunks: JsValue, level: Option<u32>) -> Result<Vec<u8>, JsValue> {
+    let mut writer = BgzfWriter::with_level(Vec::new(), deflate_level(level).map_err(js_error)?);
+    let mut buf = Vec::new();
+    visit_js_chunks(&chunks, |chunk| {
+        buf.resize(chunk.length() as usize, 0);
+        chunk.copy_to(&mut buf);
+        writer.write_all(&buf).map_err(js_error)
+    })
+    .await?;
+    writer.finish().map_err(js_error)
+}
+
+/// Concatenate BGZF files without recompressing them, like `samtools
+/// cat`, e.g. to assemble a file uploaded in separately compressed parts:
+/// an array of `Uint8Array`s or an async iterable of them, one whole BGZF
+/// file each.  The EOF block of every part is dropped and one ends the
+/// result.  A part not starting with a BGZF block throws `E_BGZF_HEADER`.
+/// Each part is copied straight into the result, sized up front when the
+/// parts come as an array.
+#[wasm_bindgen]
+pub async fn concat_bgzf(parts: JsValue) -> Result<Vec<u8>, JsValue> {
+    let mut out = Vec::new();
+    if let Some(array) = parts.dyn_ref::<js_sys::Array>() {
+        let total: u64 = array.iter().filter_map(|part| part.dyn_into::<js_sys::Uint8Array>().ok()).map(|part| part.length() as u64).sum();
+        limits::try_reserve(&mut out, total + EOF_BLOCK.len() as u64, "the concatenated file").map_err(js_error)?;
+    }
+    let mut count = 0;
+    visit_js_chunks(&parts, |part| {
+        count += 1;
+        let len = part.length();
+        if len > 0 && !plausible_block_header(&part.subarray(0, len.min(18)).to_vec()) {
+            return Err(js_error(io_error(ErrorCode::BgzfHeader, format!("part {} is not BGZF", count))));
+        }
+        let eof = EOF_BLOCK.len() as u32;
+        let ends_with_eof = len >= eof && part.subarray(len - eof, len).to_vec() == EOF_BLOCK;
+        let keep = if ends_with_eof { len - eof } else { len };
+        let start = out.len();
+        limits::try_reserve(&mut out, keep as u64, "the concatenated file").map_err(js_error)?;
+        out.resize(start + keep as usize, 0);
+        part.subarray(0, keep).copy_to(&mut out[start..]);
+        Ok(())
+    })
+    .await?;
+    out.extend_from_slice(&EOF_BLOCK);
+    Ok(out)
+}
+
+/// Compress raw bytes into a single plain gzip member (not BGZF), at
+/// `level` 0-9, 6 by default.
+#[wasm_bindgen]
+pub fn compress_gzip(input: &[u8], level: Option<u32>) -> Result<Vec<u8>, JsValue> {
+    health::contain(|| {
+        let mut output = Vec::new();
+        gzip_compress_level(Cursor::new(input), &mut output, deflate_level(level)?)?;
+        Ok(output)
+    })
+    .map_err(js_error)
 }
 
 /// Build a tabix `.csi` index from a BGZF-compressed GFF3 byte slice.
 #[wasm_bindgen]
-pub fn index_gff_csi(bgzf_input: &[u8]) -> Vec<u8> {
-    let mut csi = Vec::new();
-    csi_index_gff(Cursor::new(bgzf_input), &mut csi)
-        .expect("csi_index_gff failed");
-    csi
+pub fn index_gff_csi(bgzf_input: &[u8]) -> Result<Vec<u8>, JsValue> {
+    health::contain(|| {
+        let mut csi = Vec::new();
+        csi_index_gff(Cursor::new(bgzf_input), &mut csi)?;
+        Ok(csi)
+    })
+    .map_err(js_error)
+}
+
+/// Build a tabix `.tbi` index from a BGZF-compressed GFF3 byte slice.
+/// Fails if a feature ends beyond 2^29, the limit of the TBI format.
+#[wasm_bindgen]
+pub fn index_gff_tbi(bgzf_input: &[u8]) -> Result<Vec<u8>, JsValue> {
+    health::contain(|| {
+        let mut tbi = Vec::new();
+        tbi_index_gff(Cursor::new(bgzf_input), &mut tbi)?;
+        Ok(tbi)
+    })
+    .map_err(js_error)
+}
+
+/// Bin and chunk counts of a BGZF-compressed `.csi`, as JSON (see
+/// [`IndexDensity::to_json`]).
+#[wasm_bindgen]
+pub fn index_density(csi_bgzf: &[u8]) -> Result<String, JsValue> {
+    health::contain(|| CsiIndex::from_bgzf(csi_bgzf)).map(|idx| idx.density().to_json().dump()).map_err(js_error)
 }
 
 /// Result of indexing a BGZF-compressed FASTA file.
@@ -51,11 +184,189 @@ impl FaidxResult {
 }
 
 /// Build `.fai` and `.gzi` indexes from a BGZF-compressed FASTA byte slice.
+/// `offsets` picks the `.fai` offset convention (default `"uncompressed"`).
+#[wasm_bindgen]
+pub fn index_fasta_fai(bgzf_input: &[u8], offsets: Option<FaiOffsets>) -> Result<FaidxResult, JsValue> {
+    health::contain(|| {
+        let (mut fai, mut gzi) = (Vec::new(), Vec::new());
+        faidx_index_fasta_with(Cursor::new(bgzf_input), &mut fai, &mut gzi, offsets.unwrap_or(FaiOffsets::Uncompressed))?;
+        Ok(FaidxResult { fai, gzi })
+    })
+    .map_err(js_error)
+}
+
+/// Build the `.gzi` block index of any BGZF file, e.g. a bgzipped VCF or
+/// TSV, for readers that seek by uncompressed offset.
+#[wasm_bindgen]
+pub fn index_bgzf_gzi(bgzf_input: &[u8]) -> Result<Vec<u8>, JsValue> {
+    health::contain(|| {
+        let mut gzi = Vec::new();
+        gzi_index(Cursor::new(bgzf_input), &mut gzi)?;
+        Ok(gzi)
+    })
+    .map_err(js_error)
+}
+
+/// Result of [`recover_bgzf_file`]: the salvaged bytes and what was lost.
 #[wasm_bindgen]
-pub fn index_fasta_fai(bgzf_input: &[u8]) -> FaidxResult {
-    let mut fai = Vec::new();
-    let mut gzi = Vec::new();
-    faidx_index_fasta(Cursor::new(bgzf_input), &mut fai, &mut gzi)
-        .expect("faidx_index_fasta failed");
-    FaidxResult { fai, gzi }
+pub struct RecoveredBgzf {
+    pub(crate) recovery: BgzfRecovery,
+}
+
+#[wasm_bindgen]
+impl RecoveredBgzf {
+    /// Moves the decompressed bytes out. May only be called once meaningfully.
+    pub fn data(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.recovery.data)
+    }
+
+    /// JSON array of skipped ranges: `[{start, end, recovered_offset,
+    /// reason}]`, with compressed `start`/`end` and the offset in `data()`
+    /// where the gap is.
+    pub fn damaged(&self) -> String {
+        let ranges = self.recovery.damaged.iter().map(|d| json::object! {
+            start: d.start,
+            end: d.end,
+            recovered_offset: d.recovered_offset,
+            reason: d.reason.as_str(),
+        });
+        json::JsonValue::Array(ranges.collect()).dump()
+    }
+}
+
+/// Decompress a partially corrupted BGZF file, skipping damaged blocks.
+#[wasm_bindgen]
+pub fn recover_bgzf_file(bgzf_input: &[u8]) -> RecoveredBgzf {
+    RecoveredBgzf { recovery: recover_bgzf(bgzf_input) }
+}
+
+/// Check that every `.fai` offset, translated through the `.gzi`, lands
+/// just after its header line in the BGZF FASTA.  Throws describing the
+/// first mismatch.
+#[wasm_bindgen]
+pub fn verify_fasta_fai(bgzf_input: &[u8], fai: &[u8], gzi: &[u8]) -> Result<(), JsValue> {
+    health::contain(|| verify_fasta_index(bgzf_input, fai, gzi)).map_err(js_error)
+}
+
+/// Structurally compare two `.csi`, `.tbi` or `.fai` indexes of the same
+/// file (e.g. ours and one built with samtools/tabix).  Returns JSON
+/// `{format, equivalent, total, differences: [{seq, bin, field, ours,
+/// theirs}]}`; throws if the two are not indexes of the same format.
+#[wasm_bindgen]
+pub fn compare_index_files(ours: &[u8], theirs: &[u8]) -> Result<String, JsValue> {
+    health::contain(|| compare_index(ours, theirs)).map(|diff| diff.to_json().dump()).map_err(js_error)
+}
+
+/// A bgzipped GFF3 and its index after [`reheader_gff_bgzf`] or
+/// [`append_gff_bgzf`].
+#[wasm_bindgen]
+pub struct IndexedGff {
+    pub(crate) bgz: Vec<u8>,
+    pub(crate) index: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl IndexedGff {
+    /// Moves the BGZF-compressed GFF3 out. May only be called once meaningfully.
+    pub fn bgz(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.bgz)
+    }
+    /// Moves the updated `.csi`/`.tbi` out. May only be called once meaningfully.
+    pub fn index(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.index)
+    }
+}
+
+/// Replace the leading `#` lines of a bgzipped GFF3 with `header`, like
+/// `tabix -r`: only the blocks holding the header are recompressed and the
+/// `.csi` or `.tbi` is patched to match.  Throws `E_INVALID_OPTION` for a
+/// header line not starting with `#`.
+#[wasm_bindgen]
+pub fn reheader_gff_bgzf(bgzf_input: &[u8], index: &[u8], header: &str) -> Result<IndexedGff, JsValue> {
+    health::contain(|| reheader_gff(bgzf_input, index, header)).map(|(bgz, index)| IndexedGff { bgz, index }).map_err(js_error)
+}
+
+/// Append GFF3 `records` to a bgzipped, sorted GFF3 and extend its `.csi`
+/// to cover them, without recompressing or reindexing the existing
+/// records.  Throws `E_GFF_UNSORTED` when the records would leave the file
+/// unsorted.
+#[wasm_bindgen]
+pub fn append_gff_bgzf(bgzf_input: &[u8], csi: &[u8], records: &str) -> Result<IndexedGff, JsValue> {
+    health::contain(|| append_gff(bgzf_input, csi, records)).map(|(bgz, index)| IndexedGff { bgz, index }).map_err(js_error)
+}
+
+/// The leading `#` lines of a bgzipped GFF3, for editing before
+/// [`reheader_gff_bgzf`].
+#[wasm_bindgen]
+pub fn gff_bgzf_header(bgzf_input: &[u8]) -> Result<String, JsValue> {
+    health::contain(|| gff_header(bgzf_input)).map_err(js_error)
+}
+
+/// The line starting at virtual offset `voffset` of a BGZF-compressed file,
+/// e.g. a hit of `SearchIndex.lookup` in the `.gff3.bgz`.
+#[wasm_bindgen]
+pub fn gff_record_at(bgzf_input: &[u8], voffset: u64) -> Result<String, JsValue> {
+    health::contain(|| record_at(bgzf_input, voffset)).map_err(js_error)
+}
+
+/// Member boundaries of a (multi-member) gzip file as a JSON array of
+/// `{compressed_offset, compressed_len, uncompressed_offset,
+/// uncompressed_len}`.  Each member can be inflated on its own, e.g. by a
+/// pool of workers each calling `inflate_gzip_member(data.subarray(offset,
+/// offset + len))`; concatenated in order they give the whole file.
+#[wasm_bindgen]
+pub fn gzip_member_boundaries(data: &[u8]) -> Result<String, JsValue> {
+    health::contain(|| gzip_members(data))
+        .map(|members| json::JsonValue::Array(members.iter().map(GzipMember::to_json).collect()).dump())
+        .map_err(js_error)
+}
+
+/// Inflate one gzip member (the bytes one entry of `gzip_member_boundaries`
+/// points at), checking its CRC and length.
+#[wasm_bindgen]
+pub fn inflate_gzip_member(member: &[u8]) -> Result<Vec<u8>, JsValue> {
+    health::contain(|| inflate_member(member)).map_err(js_error)
+}
+
+/// Virtual offset helpers for JS (see `voffset.rs`).  Binning defaults
+/// to tabix's `min_shift` 14 and 5 levels.
+#[wasm_bindgen(js_name = voffset)]
+pub struct VirtualOffsets;
+
+#[wasm_bindgen(js_class = voffset)]
+impl VirtualOffsets {
+    /// `coffset << 16 | uoffset`.
+    pub fn compose(coffset: u64, uoffset: u64) -> Result<u64, JsValue> {
+        compose_voffset(coffset, uoffset).map_err(js_error)
+    }
+
+    /// The compressed offset of the block `voffset` points into.
+    pub fn coffset(voffset: u64) -> u64 {
+        split_voffset(voffset).0
+    }
+
+    /// The offset within its block's uncompressed data.
+    pub fn uoffset(voffset: u64) -> u64 {
+        split_voffset(voffset).1
+    }
+
+    /// The virtual offset of an uncompressed offset, through a `.gzi`.
+    pub fn from_uncompressed(gzi: &[u8], offset: u64) -> Result<u64, JsValue> {
+        health::contain(|| uncompressed_to_voffset(&parse_gzi(gzi)?, offset)).map_err(js_error)
+    }
+
+    /// The uncompressed offset of a virtual offset, through a `.gzi`.
+    pub fn to_uncompressed(gzi: &[u8], voffset: u64) -> Result<u64, JsValue> {
+        health::contain(|| voffset_to_uncompressed(&parse_gzi(gzi)?, voffset)).map_err(js_error)
+    }
+
+    /// htslib `hts_reg2bin` of the 0-based half-open `[beg, end)`.
+    pub fn reg2bin(beg: u64, end: u64, min_shift: Option<u32>, n_lvls: Option<u32>) -> Result<u32, JsValue> {
+        region_bin(beg, end, min_shift.unwrap_or(14), n_lvls.unwrap_or(5)).map_err(js_error)
+    }
+
+    /// htslib `hts_reg2bins` of the 0-based half-open `[beg, end)`.
+    pub fn reg2bins(beg: u64, end: u64, min_shift: Option<u32>, n_lvls: Option<u32>) -> Result<Vec<u32>, JsValue> {
+        region_bins(beg, end, min_shift.unwrap_or(14), n_lvls.unwrap_or(5)).map_err(js_error)
+    }
 }
@@ -0,0 +1,184 @@
+//! Appending records to a bgzipped, indexed GFF3 without rebuilding it.
+//!
+//! The file keeps every block but the EOF marker; the new records follow
+//! in blocks of their own, then a new EOF marker.  Only the new records are
+//! indexed, with their offsets moved past the old blocks, and their bins
+//! merged into the old `.csi`: a sequence the file ends with gets the new
+//! chunks added to its bins, a new sequence is added whole.  Bins the new
+//! records alone were too small for are rolled into their parents as
+//! usual, so the bins may differ from a rebuild's; queries still find the
+//! same records.
+//!
+//! The records must keep the file sorted: they continue its last sequence
+//! from its last start, or start sequences it does not have.  A bin the
+//! last sequence did not have takes the smaller of its own `loff` and that
+//! of its nearest existing ancestor, which is never past the first record
+//! overlapping it, as the old records' linear index is not in the `.csi`.
+
+use std::collections::HashMap;
+use std::io::{self, Cursor, Write};
+
+use super::bgzf::BgzfWriter;
+use super::members::{gzip_members, inflate_member, GzipMember};
+use super::query::{bin_first, CsiBins, CsiIndex};
+use super::tabix::{csi_index_reader, deepen_bin, TabixConfig, HTS_MIN_MARKER_DIST};
+use super::BgzfReader;
+use crate::error::{io_error, ErrorCode};
+use crate::gff::record::column_ranges;
+
+/// Sequence and start of the last record of `bgzf`, read from its last
+/// blocks, and whether the file ends with a newline.
+fn last_record(bgzf: &[u8], members: &[GzipMember], config: &TabixConfig) -> io::Result<(Option<(String, u64)>, bool)> {
+    let mut tail: Vec<u8> = Vec::new();
+    let mut ends_with_newline = true;
+    for (i, member) in members.iter().enumerate().rev() {
+        let mut data = inflate_member(member.slice(bgzf))?;
+        if tail.is_empty() {
+            ends_with_newline = data.ends_with(b"\n");
+        }
+        data.extend_from_slice(&tail);
+        tail = data;
+        // Before the first block, the first line of the tail may be partial.
+        let mut lines = tail.split(|&b| b == b'\n').rev().peekable();
+        while let Some(line) = lines.next() {
+            if lines.peek().is_none() && i > 0 {
+                break;
+            }
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if line.iter().all(u8::is_ascii_whitespace) || line[0] == config.meta_char {
+                continue;
+            }
+            return Ok((Some(parse_position(line, config)?), ends_with_newline));
+        }
+    }
+    Ok((None, ends_with_newline))
+}
+
+/// Sequence and 0-based start of a record.
+fn parse_position(line: &[u8], config: &TabixConfig) -> io::Result<(String, u64)> {
+    let n_cols = config.col_seq.max(config.col_beg) as usize;
+    let fields: Vec<&[u8]> = column_ranges(line, n_cols + 1).map(|r| &line[r]).collect();
+    let field = |col: u32| fields.get(col as usize - 1).copied().filter(|_| col > 0);
+    let unreadable = || io_error(ErrorCode::GffParse, format!("cannot place record {:?}", String::from_utf8_lossy(line)));
+    let seq = field(config.col_seq).and_then(|f| std::str::from_utf8(f).ok()).ok_or_else(unreadable)?;
+    let start: u64 = field(config.col_beg).and_then(|f| std::str::from_utf8(f).ok()?.trim().parse().ok()).ok_or_else(unreadable)?;
+    Ok((seq.to_owned(), if config.zero_based { start } else { start.saturating_sub(1) }))
+}
+
+/// Renumber every bin of `index` for `to` levels.
+fn deepen(index: &mut CsiIndex, to: u32) {
+    let (from, old_meta, new_meta) = (index.n_lvls, bin_first(index.n_lvls + 1) + 1, bin_first(to + 1) + 1);
+    for bins in &mut index.seqs {
+        *bins = std::mem::take(bins)
+            .into_iter()
+            .map(|(bin, entry)| (if bin == old_meta { new_meta } else { deepen_bin(bin, from, to) }, entry))
+            .collect();
+    }
+    index.n_lvls = to;
+}
+
+/// Add `chunk`, which starts after every chunk of `chunks`, merging it
+/// into the last when block-adjacent as the indexer does.
+fn push_chunk(chunks: &mut Vec<(u64, u64)>, chunk: (u64, u64)) {
+    match chunks.last_mut() {
+        Some(last) if chunk.0 <= last.1.saturating_add(HTS_MIN_MARKER_DIST) => last.1 = last.1.max(chunk.1),
+        _ => chunks.push(chunk),
+    }
+}
+
+/// Merge the bins of the appended records of a sequence into its old ones.
+fn merge_bins(old: &mut CsiBins, new: CsiBins, meta_bin: u32) {
+    let seq_start = old.get(&meta_bin).and_then(|(_, c)| c.first()).map_or(0, |c| c.0);
+    let mut new: Vec<_> = new.into_iter().collect();
+    new.sort_unstable_by_key(|e| e.0);
+    for (bin, (loff, chunks)) in new {
+        if bin == meta_bin {
+            let pseudo = &mut old.entry(bin).or_insert((0, vec![(chunks[0].0, 0), (0, 0)])).1;
+            pseudo[0].1 = chunks[0].1;
+            pseudo[1].0 += chunks.get(1).map_or(0, |c| c.0);
+            pseudo[1].1 += chunks.get(1).map_or(0, |c| c.1);
+        } else if let Some((_, existing)) = old.get_mut(&bin) {
+            for chunk in chunks {
+                push_chunk(existing, chunk);
+            }
+        } else {
+            let ancestor = std::iter::successors(Some(bin), |&b| (b > 0).then(|| (b - 1) >> 3)).skip(1).find_map(|b| old.get(&b));
+            let bound = ancestor.map_or(seq_start, |(loff, _)| *loff);
+            old.insert(bin, (loff.min(bound), chunks));
+        }
+    }
+}
+
+/// Append GFF3 `records` to a bgzipped, sorted GFF3 and extend its `.csi`
+/// to cover them (see the module docs).  Returns the new file and index.
+/// Records out of order with the file's are `E_GFF_UNSORTED`.
+pub fn append_gff(bgzf: &[u8], csi: &[u8], records: &str) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    let mut index = CsiIndex::from_bgzf(csi)?;
+    let config = index.config.ok_or_else(|| io_error(ErrorCode::IndexParse, "the .csi has no tabix header"))?;
+    let members = gzip_members(bgzf)?;
+    let (last, ends_with_newline) = last_record(bgzf, &members, &config)?;
+    if last.as_ref().map(|(seq, _)| seq) != index.names.last() {
+        return Err(io_error(ErrorCode::IndexMismatch, "the .csi does not end with the file's last sequence"));
+    }
+
+    // The old blocks without the EOF marker, then the new records.
+    let base = members.last().map_or(0, |m| m.compressed_offset + m.compressed_len);
+    let mut writer = BgzfWriter::new(Vec::new());
+    if !ends_with_newline {
+        writer.write_all(b"\n")?;
+    }
+    writer.write_all(records.as_bytes())?;
+    if !records.is_empty() && !records.ends_with('\n') {
+        writer.write_all(b"\n")?;
+    }
+    let appended = writer.finish()?;
+
+    let mut appended_csi = Vec::new();
+    let n_lvls = csi_index_reader(BgzfReader::new(Cursor::new(&appended)), &mut appended_csi, &config)?;
+    let mut added = CsiIndex::from_bgzf(&appended_csi)?;
+    if added.min_shift != index.min_shift {
+        return Err(io_error(ErrorCode::IndexMismatch, format!("the .csi has min_shift {}, not {}", index.min_shift, added.min_shift)));
+    }
+    match n_lvls.cmp(&index.n_lvls) {
+        std::cmp::Ordering::Less => deepen(&mut added, index.n_lvls),
+        std::cmp::Ordering::Greater => deepen(&mut index, n_lvls),
+        std::cmp::Ordering::Equal => {}
+    }
+
+    let first = records.lines().find(|l| !l.trim().is_empty() && !l.starts_with(config.meta_char as char));
+    if let (Some((last_seq, last_start)), Some(first)) = (&last, first) {
+        let (seq, start) = parse_position(first.as_bytes(), &config)?;
+        if seq == *last_seq && start < *last_start {
+            return Err(io_error(ErrorCode::GffUnsorted, format!("{}:{} starts before the file's last record", seq, start + 1)));
+        }
+    }
+
+    let meta_bin = bin_first(index.n_lvls + 1) + 1;
+    let shift = |v: u64| ((v >> 16) + base) << 16 | (v & 0xffff);
+    let positions: HashMap<String, usize> = index.names.iter().cloned().enumerate().map(|(i, n)| (n, i)).collect();
+    for (i, (name, bins)) in added.names.into_iter().zip(added.seqs).enumerate() {
+        let bins: CsiBins = bins
+            .into_iter()
+            .map(|(bin, (loff, chunks))| {
+                let chunks = match bin == meta_bin {
+                    true => chunks.iter().enumerate().map(|(k, &(s, e))| if k == 0 { (shift(s), shift(e)) } else { (s, e) }).collect(),
+                    false => chunks.iter().map(|&(s, e)| (shift(s), shift(e))).collect(),
+                };
+                (bin, (if bin == meta_bin { loff } else { shift(loff) }, chunks))
+            })
+            .collect();
+        match positions.get(&name) {
+            Some(&tid) if i == 0 && tid == index.names.len() - 1 => merge_bins(&mut index.seqs[tid], bins, meta_bin),
+            Some(_) => return Err(io_error(ErrorCode::GffUnsorted, format!("records of {} are not contiguous", name))),
+            None => {
+                index.names.push(name);
+                index.seqs.push(bins);
+            }
+        }
+    }
+
+    let mut out = Vec::with_capacity(base as usize + appended.len());
+    out.extend_from_slice(&bgzf[..base as usize]);
+    out.extend_from_slice(&appended);
+    Ok((out, index.write_bgzf(Vec::new())?))
+}
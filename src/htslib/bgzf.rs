@@ -1,8 +1,16 @@
 use std::io::{self, Read, Write};
-use flate2::{write::DeflateEncoder, read::DeflateDecoder, Compression};
+use crate::error::{io_error, ErrorCode};
+use crate::progress::ContigProgress;
+use crate::scan;
+use super::crc::{crc32, Crc32};
+use super::members::run_workers;
+use super::pool::PooledBuf;
+use flate2::{write::DeflateEncoder, Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
 
 // Max uncompressed bytes per BGZF block
 const BGZF_BLOCK_SIZE: usize = 0xff00; // 65280
+// Max deflate bytes per BGZF block: 64 KiB less the header and footer
+const MAX_DEFLATE_SIZE: usize = 65536 - 26;
 
 // BGZF block header template (18 bytes)
 // Bytes 16–17 are BSIZE placeholder (total block size − 1), filled per block
@@ -22,22 +30,44 @@ pub const EOF_BLOCK: [u8; 28] = [
     0x03, 0x00, 0, 0, 0, 0, 0, 0, 0, 0,
 ];
 
+// Plain gzip member header: FLG=0 (no extra field), XFL=0, OS=255 (unknown)
+const GZIP_HEADER: [u8; 10] = [0x1f, 0x8b, 0x08, 0, 0, 0, 0, 0, 0, 0xff];
+
+/// Gzip member trailer: CRC32 then ISIZE (length mod 2^32), little-endian.
+fn gzip_footer(crc: u32, isize: u32) -> [u8; 8] {
+    let mut footer = [0u8; 8];
+    footer[..4].copy_from_slice(&crc.to_le_bytes());
+    footer[4..].copy_from_slice(&isize.to_le_bytes());
+    footer
+}
+
 // ---------------------------------------------------------------------------
 // BgzfWriter
 // ---------------------------------------------------------------------------
 
 pub struct BgzfWriter<W: Write> {
     inner: W,
-    buf: Vec<u8>,
+    buf: PooledBuf,
+    /// The block being assembled (header, deflate data, footer).
+    block: PooledBuf,
+    /// Deflate state, reset for every block.
+    compress: Compress,
     /// Compressed bytes written to inner so far.
     block_address: u64,
 }
 
 impl<W: Write> BgzfWriter<W> {
     pub fn new(inner: W) -> Self {
+        Self::with_level(inner, Compression::default().level())
+    }
+
+    /// Writer using deflate `level` (0–9, clamped) for every block.
+    pub fn with_level(inner: W, level: u32) -> Self {
         BgzfWriter {
             inner,
-            buf: Vec::with_capacity(BGZF_BLOCK_SIZE),
+            buf: PooledBuf::take(BGZF_BLOCK_SIZE),
+            block: PooledBuf::take(65536),
+            compress: Compress::new(Compression::new(level.min(9)), false),
             block_address: 0,
         }
     }
@@ -54,48 +84,32 @@ impl<W: Write> BgzfWriter<W> {
             return Ok(());
         }
 
-        let crc = crc32fast::hash(&self.buf);
+        let crc = crc32(&self.buf);
         let isize = self.buf.len() as u32;
 
-        // Try deflate compression
-        let compressed = {
-            let mut enc = DeflateEncoder::new(Vec::new(), Compression::default());
-            enc.write_all(&self.buf)?;
-            enc.finish()?
-        };
-
-        // Total block size = 18 (header) + compressed_data + 8 (footer)
-        // If it doesn't fit, fall back to a stored (non-compressed) block.
-        let compressed_data: Vec<u8> = if compressed.len() + 26 > 65536 {
+        self.block.clear();
+        self.block.extend_from_slice(&HEADER_TEMPLATE);
+        // If the deflate data doesn't fit in a block, fall back to a stored
+        // (non-compressed) block.
+        if !deflate_into(&mut self.compress, &self.buf, &mut self.block)? {
             // RFC 1951 stored block: [0x01][len_le][~len_le][data]
             let len = self.buf.len() as u16;
-            let mut stored = Vec::with_capacity(5 + self.buf.len());
-            stored.push(0x01); // BFINAL=1, BTYPE=00 (stored)
-            stored.extend_from_slice(&len.to_le_bytes());
-            stored.extend_from_slice(&(!len).to_le_bytes());
-            stored.extend_from_slice(&self.buf);
-            stored
-        } else {
-            compressed
-        };
+            self.block.truncate(HEADER_TEMPLATE.len());
+            self.block.push(0x01); // BFINAL=1, BTYPE=00 (stored)
+            self.block.extend_from_slice(&len.to_le_bytes());
+            self.block.extend_from_slice(&(!len).to_le_bytes());
+            self.block.extend_from_slice(&self.buf);
+        }
+        self.block.extend_from_slice(&gzip_footer(crc, isize));
 
-        // total = 18 header + data + 4 crc + 4 isize = data.len() + 26
-        let total = compressed_data.len() + 26;
+        // total = 18 header + data + 4 crc + 4 isize
+        let total = self.block.len();
         debug_assert!(total <= 65536, "BGZF block exceeds 65536 bytes");
-
-        let mut block = Vec::with_capacity(total);
-        block.extend_from_slice(&HEADER_TEMPLATE);
         // BSIZE = total − 1 (little-endian u16 at bytes 16–17)
-        let bsize = (total - 1) as u16;
-        block[16] = bsize as u8;
-        block[17] = (bsize >> 8) as u8;
+        self.block[16..18].copy_from_slice(&((total - 1) as u16).to_le_bytes());
 
-        block.extend_from_slice(&compressed_data);
-        block.extend_from_slice(&crc.to_le_bytes());
-        block.extend_from_slice(&isize.to_le_bytes());
-
-        self.inner.write_all(&block)?;
-        self.block_address += block.len() as u64;
+        self.inner.write_all(&self.block)?;
+        self.block_address += total as u64;
         self.buf.clear();
         Ok(())
     }
@@ -131,39 +145,209 @@ impl<W: Write> Write for BgzfWriter<W> {
     }
 }
 
+/// Deflate `data` (as one raw stream, with `compress` reset first) onto the
+/// end of `out`.  False if it takes more than [`MAX_DEFLATE_SIZE`] bytes;
+/// `out` then holds a partial stream past its old length.
+fn deflate_into(compress: &mut Compress, data: &[u8], out: &mut Vec<u8>) -> io::Result<bool> {
+    compress.reset();
+    let limit = out.len() + MAX_DEFLATE_SIZE;
+    out.reserve(MAX_DEFLATE_SIZE + 1);
+    loop {
+        let (in_before, out_before) = (compress.total_in(), compress.total_out());
+        let status = compress
+            .compress_vec(&data[in_before as usize..], out, FlushCompress::Finish)
+            .map_err(|e| io_error(ErrorCode::Internal, e.to_string()))?;
+        if status == Status::StreamEnd {
+            return Ok(out.len() <= limit);
+        }
+        if out.len() > limit || (compress.total_in() == in_before && compress.total_out() == out_before) {
+            return Ok(false);
+        }
+    }
+}
+
+/// Inflate the raw deflate stream `data` into `out` (cleared first, with
+/// room for `size_hint` bytes) using `decompress`, reset first.
+fn inflate_into(decompress: &mut Decompress, data: &[u8], out: &mut Vec<u8>, size_hint: usize) -> io::Result<()> {
+    decompress.reset(false);
+    out.clear();
+    out.reserve(size_hint);
+    loop {
+        if out.len() == out.capacity() {
+            out.reserve(0x10000);
+        }
+        let (in_before, out_before) = (decompress.total_in(), decompress.total_out());
+        let status = decompress
+            .decompress_vec(&data[in_before as usize..], out, FlushDecompress::Finish)
+            .map_err(|e| io_error(ErrorCode::BgzfCorrupt, e.to_string()))?;
+        if status == Status::StreamEnd {
+            return Ok(());
+        }
+        if decompress.total_in() == in_before && decompress.total_out() == out_before {
+            return Err(io_error(ErrorCode::BgzfCorrupt, "truncated deflate stream"));
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// GzipWriter
+// ---------------------------------------------------------------------------
+
+/// Plain gzip output: a single member with no BC subfield and no EOF block,
+/// for consumers that want `.gz` rather than BGZF.  Not randomly accessible,
+/// so nothing written this way can be indexed.
+pub struct GzipWriter<W: Write> {
+    enc: DeflateEncoder<W>,
+    crc: Crc32,
+    /// Uncompressed bytes written so far (mod 2^32, as stored in ISIZE).
+    isize: u32,
+    header_written: bool,
+}
+
+impl<W: Write> GzipWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self::with_level(inner, Compression::default().level())
+    }
+
+    /// Writer using deflate `level` (0–9, clamped).
+    pub fn with_level(inner: W, level: u32) -> Self {
+        GzipWriter {
+            enc: DeflateEncoder::new(inner, Compression::new(level.min(9))),
+            crc: Crc32::new(),
+            isize: 0,
+            header_written: false,
+        }
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            self.enc.get_mut().write_all(&GZIP_HEADER)?;
+            self.header_written = true;
+        }
+        Ok(())
+    }
+
+    /// Finish the deflate stream and append the trailer, returning the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_header()?;
+        let mut inner = self.enc.finish()?;
+        inner.write_all(&gzip_footer(self.crc.finalize(), self.isize))?;
+        Ok(inner)
+    }
+}
+
+impl<W: Write> Write for GzipWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.write_header()?;
+        let n = self.enc.write(data)?;
+        self.crc.update(&data[..n]);
+        self.isize = self.isize.wrapping_add(n as u32);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.enc.flush()
+    }
+}
+
 // ---------------------------------------------------------------------------
 // BgzfReader
 // ---------------------------------------------------------------------------
 
 pub struct BgzfReader<R: Read> {
-    inner: R,
+    inner: Pushback<R>,
     /// Compressed bytes consumed so far (= start of the *next* unread block).
     pub block_address: u64,
     /// Compressed start offset of the block currently loaded in `block`.
     cur_block_start: u64,
     /// Decompressed contents of the current block.
-    block: Vec<u8>,
+    block: PooledBuf,
+    /// Deflate data of the current block.
+    deflate_data: PooledBuf,
+    /// Inflate state, reset for every block.
+    decompress: Decompress,
     /// Read position within block.
     pos: usize,
     /// (compressed_offset, cumulative_uncompressed_offset) pairs — one per block.
     pub gzi: Vec<(u64, u64)>,
     /// Cumulative uncompressed bytes before the current block.
     pub uncompressed_addr: u64,
+    /// Accept any RFC 1952 member layout (see [`BgzfReader::lenient`]).
+    lenient: bool,
+    /// Members without a `BC` subfield read so far (lenient mode only).
+    plain_members: u64,
+    /// Check each block's CRC32 (see [`BgzfReader::verify_crc`]).
+    verify_crc: bool,
+    /// Per-contig progress of the stage reading (see [`BgzfReader::track`]).
+    progress: Option<ContigProgress>,
 }
 
 impl<R: Read> BgzfReader<R> {
     pub fn new(inner: R) -> Self {
         BgzfReader {
-            inner,
+            inner: Pushback { inner, pending: Vec::new(), pos: 0 },
             block_address: 0,
             cur_block_start: 0,
-            block: Vec::new(),
+            block: PooledBuf::take(0x10000),
+            deflate_data: PooledBuf::take(0x10000),
+            decompress: Decompress::new(false),
             pos: 0,
             gzi: Vec::new(),
             uncompressed_addr: 0,
+            lenient: false,
+            plain_members: 0,
+            verify_crc: true,
+            progress: None,
+        }
+    }
+
+    /// Reader for "bgzip" files written by other tools.  Headers are parsed
+    /// per RFC 1952 instead of assumed to be the 18-byte BGZF layout: extra
+    /// subfields besides `BC`, any XFL/OS value, FNAME/FCOMMENT/FHCRC and
+    /// empty blocks in mid-stream (concatenated files) are accepted, and
+    /// plain gzip members without `BC` are inflated whole.  A plain member
+    /// becomes one block, so virtual offsets inside it past 64 KiB are not
+    /// addressable; check [`BgzfReader::plain_members`] before indexing.
+    pub fn lenient(inner: R) -> Self {
+        BgzfReader { lenient: true, ..Self::new(inner) }
+    }
+
+    /// Whether to check each block's CRC32 (on by default).  Turning it off
+    /// saves a hashing pass over the data; only do so for data whose
+    /// integrity is known, such as output this process just wrote.  ISIZE
+    /// and the deflate stream are still checked.
+    pub fn verify_crc(mut self, verify: bool) -> Self {
+        self.verify_crc = verify;
+        self
+    }
+
+    /// Report per-contig progress through `progress` as blocks are read;
+    /// the indexer reading calls [`BgzfReader::start_contig`] at each
+    /// sequence.
+    pub fn track(mut self, progress: ContigProgress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// The sequence `name` starts at the current position.  A no-op unless
+    /// [`BgzfReader::track`]ed.
+    pub fn start_contig(&mut self, name: &str) {
+        let offset = self.uncompressed_offset();
+        if let Some(progress) = &mut self.progress {
+            progress.start_contig(name, offset);
         }
     }
 
+    /// The underlying reader, past what was read so far.
+    pub fn into_inner(self) -> R {
+        self.inner.inner
+    }
+
+    /// Number of plain gzip (non-BGZF) members read so far.
+    pub fn plain_members(&self) -> u64 {
+        self.plain_members
+    }
+
     /// Current virtual offset: (start_of_current_block << 16) | pos
     pub fn virtual_offset(&self) -> u64 {
         (self.cur_block_start << 16) | (self.pos as u64)
@@ -181,9 +365,21 @@ impl<R: Read> BgzfReader<R> {
         &self.gzi
     }
 
-    /// Read and decompress the next BGZF block.
+    /// Read and decompress the next block, advancing any tracked progress.
     /// Returns Ok(false) on clean EOF (empty read of header), Ok(true) on success.
     fn read_block(&mut self) -> io::Result<bool> {
+        let got = if self.lenient { self.read_member()? } else { self.read_bgzf_block()? };
+        if let Some(progress) = &mut self.progress {
+            match got {
+                true => progress.advance(self.uncompressed_addr - self.block.len() as u64),
+                false => progress.finish(self.uncompressed_addr),
+            }
+        }
+        Ok(got)
+    }
+
+    /// [`BgzfReader::read_block`] for strict BGZF.
+    fn read_bgzf_block(&mut self) -> io::Result<bool> {
         let caddr_before = self.block_address;
         let uaddr_before = self.uncompressed_addr;
 
@@ -197,18 +393,18 @@ impl<R: Read> BgzfReader<R> {
 
         // Validate magic and flags
         if header[0] != 0x1f || header[1] != 0x8b {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a gzip stream"));
+            return Err(io_error(ErrorCode::BgzfHeader, "not a gzip stream"));
         }
         if header[2] != 0x08 {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "unsupported gzip method"));
+            return Err(io_error(ErrorCode::BgzfHeader, "unsupported gzip method"));
         }
 
         let bsize = u16::from_le_bytes([header[16], header[17]]) as usize + 1;
         let deflate_len = bsize.checked_sub(26)
-            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "BGZF block too small"))?;
+            .ok_or_else(|| io_error(ErrorCode::BgzfCorrupt, "BGZF block too small"))?;
 
-        let mut deflate_data = vec![0u8; deflate_len];
-        read_exact_inner(&mut self.inner, &mut deflate_data)?;
+        self.deflate_data.resize(deflate_len, 0);
+        read_exact_inner(&mut self.inner, &mut self.deflate_data)?;
 
         let mut footer = [0u8; 8];
         read_exact_inner(&mut self.inner, &mut footer)?;
@@ -216,22 +412,18 @@ impl<R: Read> BgzfReader<R> {
         let expected_crc = u32::from_le_bytes([footer[0], footer[1], footer[2], footer[3]]);
         let expected_isize = u32::from_le_bytes([footer[4], footer[5], footer[6], footer[7]]) as usize;
 
-        // Decompress
-        self.block.clear();
-        self.block.reserve(expected_isize);
-        let mut dec = DeflateDecoder::new(&deflate_data[..]);
-        dec.read_to_end(&mut self.block)?;
+        // BGZF blocks hold at most 64 KiB; don't trust ISIZE further than that.
+        inflate_into(&mut self.decompress, &self.deflate_data, &mut self.block, expected_isize.min(0x10000))?;
 
         if self.block.len() != expected_isize {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
+            return Err(io_error(
+                ErrorCode::BgzfCorrupt,
                 format!("BGZF isize mismatch: got {} expected {}", self.block.len(), expected_isize),
             ));
         }
 
-        let actual_crc = crc32fast::hash(&self.block);
-        if actual_crc != expected_crc {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "BGZF CRC32 mismatch"));
+        if self.verify_crc && crc32(&self.block) != expected_crc {
+            return Err(io_error(ErrorCode::BgzfCorrupt, "BGZF CRC32 mismatch"));
         }
 
         self.cur_block_start = caddr_before;
@@ -248,6 +440,101 @@ impl<R: Read> BgzfReader<R> {
         Ok(true)
     }
 
+    /// Lenient counterpart of [`BgzfReader::read_block`]: parses a full gzip
+    /// member header and skips empty members.
+    fn read_member(&mut self) -> io::Result<bool> {
+        loop {
+            let caddr_before = self.block_address;
+            let uaddr_before = self.uncompressed_addr;
+
+            let MemberHeader { len: header_len, bsize } = match read_member_header(&mut self.inner)? {
+                Some(header) => header,
+                None => {
+                    self.block.clear();
+                    self.pos = 0;
+                    return Ok(false); // clean EOF
+                }
+            };
+
+            self.block.clear();
+            let deflate_len = match bsize {
+                Some(bsize) => {
+                    let deflate_len = bsize.checked_sub(header_len + 8)
+                        .ok_or_else(|| io_error(ErrorCode::BgzfCorrupt, "BGZF block too small"))?;
+                    self.deflate_data.resize(deflate_len, 0);
+                    read_exact_inner(&mut self.inner, &mut self.deflate_data)?;
+                    inflate_into(&mut self.decompress, &self.deflate_data, &mut self.block, 0x10000)?;
+                    deflate_len
+                }
+                None => {
+                    self.plain_members += 1;
+                    self.inflate_member()?
+                }
+            };
+
+            let mut footer = [0u8; 8];
+            read_exact_inner(&mut self.inner, &mut footer)?;
+            let expected_crc = u32::from_le_bytes([footer[0], footer[1], footer[2], footer[3]]);
+            let expected_isize = u32::from_le_bytes([footer[4], footer[5], footer[6], footer[7]]);
+            // ISIZE is the length modulo 2^32 (plain members may be larger).
+            if self.block.len() as u32 != expected_isize {
+                return Err(io_error(
+                    ErrorCode::BgzfCorrupt,
+                    format!("gzip isize mismatch: got {} expected {}", self.block.len(), expected_isize),
+                ));
+            }
+            if self.verify_crc && crc32(&self.block) != expected_crc {
+                return Err(io_error(ErrorCode::BgzfCorrupt, "gzip CRC32 mismatch"));
+            }
+
+            self.cur_block_start = caddr_before;
+            self.block_address += (header_len + deflate_len + 8) as u64;
+            self.pos = 0;
+            if self.block.is_empty() {
+                continue; // EOF marker of a concatenated file, or an empty member
+            }
+            if caddr_before > 0 || uaddr_before > 0 {
+                self.gzi.push((caddr_before, uaddr_before));
+            }
+            self.uncompressed_addr += self.block.len() as u64;
+            return Ok(true);
+        }
+    }
+
+    /// Inflate a deflate stream of unknown length into `block`, pushing back
+    /// whatever input follows it.  Returns the compressed length.
+    fn inflate_member(&mut self) -> io::Result<usize> {
+        let mut d = Decompress::new(false);
+        let mut input = PooledBuf::take(0x10000);
+        input.resize(0x10000, 0);
+        loop {
+            let n = self.inner.read(&mut input)?;
+            if n == 0 {
+                return Err(io_error(ErrorCode::BgzfTruncated, "truncated gzip member"));
+            }
+            let mut off = 0;
+            loop {
+                if self.block.len() == self.block.capacity() {
+                    self.block.reserve(0x10000);
+                }
+                let (in_before, out_before) = (d.total_in(), d.total_out());
+                let status = d.decompress_vec(&input[off..n], &mut self.block, FlushDecompress::None)
+                    .map_err(|e| io_error(ErrorCode::BgzfCorrupt, e.to_string()))?;
+                off += (d.total_in() - in_before) as usize;
+                if status == Status::StreamEnd {
+                    self.inner.unread(&input[off..n]);
+                    return Ok(d.total_in() as usize);
+                }
+                if off == n && self.block.len() < self.block.capacity() {
+                    break; // needs more input
+                }
+                if d.total_in() == in_before && d.total_out() == out_before && off < n {
+                    return Err(io_error(ErrorCode::BgzfCorrupt, "corrupt deflate stream"));
+                }
+            }
+        }
+    }
+
     /// Read bytes until `\n` (inclusive), appending to `buf`.
     /// Returns `(bytes_read, voff_at_line_start)`.
     /// Returns `(0, voff)` on EOF.
@@ -266,7 +553,7 @@ impl<R: Read> BgzfReader<R> {
         loop {
             // Scan for newline in current block
             let slice = &self.block[self.pos..];
-            match slice.iter().position(|&b| b == b'\n') {
+            match scan::find_byte(slice, b'\n') {
                 Some(nl) => {
                     let end = nl + 1;
                     buf.extend_from_slice(&slice[..end]);
@@ -319,12 +606,113 @@ impl<R: Read> Read for BgzfReader<R> {
     }
 }
 
+/// Reader that can push bytes back, for inflating gzip members whose
+/// compressed length is not known up front.
+struct Pushback<R: Read> {
+    inner: R,
+    pending: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> Pushback<R> {
+    /// Return `data` to the front of the stream.
+    fn unread(&mut self, data: &[u8]) {
+        let mut pending = data.to_vec();
+        pending.extend_from_slice(&self.pending[self.pos..]);
+        self.pending = pending;
+        self.pos = 0;
+    }
+}
+
+impl<R: Read> Read for Pushback<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.pos < self.pending.len() {
+            let take = (self.pending.len() - self.pos).min(out.len());
+            out[..take].copy_from_slice(&self.pending[self.pos..self.pos + take]);
+            self.pos += take;
+            return Ok(take);
+        }
+        self.inner.read(out)
+    }
+}
+
+/// The parts of a gzip member header needed to read the member.
+pub(super) struct MemberHeader {
+    /// Header length in bytes, optional fields included.
+    pub len: usize,
+    /// Total member size from a BGZF `BC` subfield, if present.
+    pub bsize: Option<usize>,
+}
+
+/// Parse an RFC 1952 member header: any XFL/OS, extra subfields besides
+/// `BC`, FNAME/FCOMMENT/FHCRC.  Returns `None` at a clean EOF.
+pub(super) fn read_member_header<R: Read>(r: &mut R) -> io::Result<Option<MemberHeader>> {
+    let mut header = [0u8; 10];
+    if r.read(&mut header[..1])? == 0 {
+        return Ok(None);
+    }
+    read_exact_inner(r, &mut header[1..])?;
+    if header[0] != 0x1f || header[1] != 0x8b {
+        return Err(io_error(ErrorCode::BgzfHeader, "not a gzip stream"));
+    }
+    if header[2] != 0x08 {
+        return Err(io_error(ErrorCode::BgzfHeader, "unsupported gzip method"));
+    }
+    let flags = header[3];
+    if flags & 0xe0 != 0 {
+        return Err(io_error(ErrorCode::BgzfHeader, "reserved gzip flags set"));
+    }
+
+    // Optional header fields; remember BSIZE if a BC subfield is present.
+    let mut len = 10usize;
+    let mut bsize = None;
+    if flags & 0x04 != 0 {
+        let mut xlen = [0u8; 2];
+        read_exact_inner(r, &mut xlen)?;
+        let mut extra = vec![0u8; u16::from_le_bytes(xlen) as usize];
+        read_exact_inner(r, &mut extra)?;
+        len += 2 + extra.len();
+        let mut i = 0;
+        while i + 4 <= extra.len() {
+            let slen = u16::from_le_bytes([extra[i + 2], extra[i + 3]]) as usize;
+            if &extra[i..i + 2] == b"BC" && slen == 2 && i + 6 <= extra.len() {
+                bsize = Some(u16::from_le_bytes([extra[i + 4], extra[i + 5]]) as usize + 1);
+            }
+            i += 4 + slen;
+        }
+    }
+    for flag in [0x08, 0x10] {
+        // FNAME, FCOMMENT: zero-terminated strings
+        if flags & flag != 0 {
+            len += skip_cstring(r)?;
+        }
+    }
+    if flags & 0x02 != 0 {
+        read_exact_inner(r, &mut [0u8; 2])?;
+        len += 2;
+    }
+    Ok(Some(MemberHeader { len, bsize }))
+}
+
+/// Consume a zero-terminated header string; returns its length including the NUL.
+fn skip_cstring<R: Read>(r: &mut R) -> io::Result<usize> {
+    let mut byte = [0u8; 1];
+    let mut n = 0;
+    loop {
+        read_exact_inner(r, &mut byte)?;
+        n += 1;
+        if byte[0] == 0 {
+            return Ok(n);
+        }
+    }
+}
+
 /// Like `read_exact` but for our inner reader (avoids naming conflicts).
-fn read_exact_inner<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<()> {
+pub(super) fn read_exact_inner<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<()> {
     let mut filled = 0;
     while filled < buf.len() {
         match r.read(&mut buf[filled..]) {
-            Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected EOF")),
+            Ok(0) => return Err(io_error(ErrorCode::BgzfTruncated, "unexpected EOF")),
             Ok(n) => filled += n,
             Err(e) => return Err(e),
         }
@@ -332,21 +720,156 @@ fn read_exact_inner<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<()> {
     Ok(())
 }
 
+// ---------------------------------------------------------------------------
+// Recovery
+// ---------------------------------------------------------------------------
+
+/// A stretch of a BGZF file that could not be decoded and was skipped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DamagedRange {
+    /// Compressed offset where decoding failed.
+    pub start: u64,
+    /// Compressed offset of the next block that decoded (or the file size).
+    pub end: u64,
+    /// Offset in the recovered data where the lost bytes would have been.
+    pub recovered_offset: u64,
+    /// Error of the first block that failed.
+    pub reason: String,
+}
+
+/// Output of [`recover_bgzf`].
+#[derive(Debug, Default)]
+pub struct BgzfRecovery {
+    /// Decompressed contents of every block that decoded.
+    pub data: Vec<u8>,
+    pub damaged: Vec<DamagedRange>,
+}
+
+/// Whether `data` could start a BGZF block: gzip magic, deflate, FEXTRA,
+/// and a `BC` subfield first in the extra field.
+pub fn plausible_block_header(data: &[u8]) -> bool {
+    data.len() >= 18 && data[..4] == [0x1f, 0x8b, 8, 4] && &data[12..14] == b"BC" && data[14..16] == [2, 0]
+}
+
+/// Decode the block at the start of `data`: `(contents, compressed size)`.
+fn decode_block(data: &[u8]) -> io::Result<(PooledBuf, usize)> {
+    let mut reader = BgzfReader::new(data);
+    if !reader.read_block()? {
+        return Err(io_error(ErrorCode::BgzfTruncated, "unexpected EOF"));
+    }
+    Ok((reader.block, reader.block_address as usize))
+}
+
+/// Decompress a partially corrupted BGZF file.  When a block fails to decode
+/// (bad header, deflate error, CRC or ISIZE mismatch, truncation) the bytes
+/// up to the next plausible block header that does decode are skipped and
+/// reported, and decoding continues from there.
+pub fn recover_bgzf(data: &[u8]) -> BgzfRecovery {
+    let mut out = BgzfRecovery::default();
+    let mut pos = 0;
+    while pos < data.len() {
+        let err = match decode_block(&data[pos..]) {
+            Ok((block, size)) => {
+                out.data.extend_from_slice(&block);
+                pos += size;
+                continue;
+            }
+            Err(e) => e,
+        };
+        let next = (pos + 1..data.len())
+            .filter(|&i| plausible_block_header(&data[i..]))
+            .find(|&i| decode_block(&data[i..]).is_ok())
+            .unwrap_or(data.len());
+        out.damaged.push(DamagedRange {
+            start: pos as u64,
+            end: next as u64,
+            recovered_offset: out.data.len() as u64,
+            reason: err.to_string(),
+        });
+        pos = next;
+    }
+    out
+}
+
 // ---------------------------------------------------------------------------
 // Convenience function
 // ---------------------------------------------------------------------------
 
 /// Compress all bytes from `input` into BGZF format, writing to `output`.
-pub fn bgzf_compress<R: Read, W: Write>(mut input: R, output: W) -> io::Result<()> {
-    let mut writer = BgzfWriter::new(output);
-    let mut buf = vec![0u8; 65536];
+pub fn bgzf_compress<R: Read, W: Write>(input: R, output: W) -> io::Result<()> {
+    bgzf_compress_level(input, output, Compression::default().level())
+}
+
+/// [`bgzf_compress`] with an explicit deflate level (0–9).
+pub fn bgzf_compress_level<R: Read, W: Write>(input: R, output: W, level: u32) -> io::Result<()> {
+    let mut writer = BgzfWriter::with_level(output, level);
+    copy_all(input, &mut writer)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// [`bgzf_compress_level`] over a sequence of chunks, written as they come
+/// rather than joined first.  Blocks fill to the same size whatever the
+/// chunk boundaries, so the output is that of the concatenation.
+pub fn bgzf_compress_chunks<I, W>(chunks: I, output: W, level: u32) -> io::Result<W>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+    W: Write,
+{
+    let mut writer = BgzfWriter::with_level(output, level);
+    for chunk in chunks {
+        writer.write_all(chunk.as_ref())?;
+    }
+    writer.finish()
+}
+
+/// Concatenate BGZF files into one, like `samtools cat`: the EOF block
+/// ending each part is dropped and a single one ends the result.  Blocks
+/// are copied as they are, so nothing is recompressed; a virtual offset
+/// into a part moves by the compressed length of the parts before it.
+pub fn bgzf_cat(parts: &[&[u8]]) -> Vec<u8> {
+    let parts: Vec<&[u8]> = parts.iter().map(|part| part.strip_suffix(&EOF_BLOCK[..]).unwrap_or(part)).collect();
+    let mut out = Vec::with_capacity(parts.iter().map(|p| p.len()).sum::<usize>() + EOF_BLOCK.len());
+    for part in parts {
+        out.extend_from_slice(part);
+    }
+    out.extend_from_slice(&EOF_BLOCK);
+    out
+}
+
+/// [`bgzf_compress_level`] of `data` as separately compressed pieces,
+/// split at `splits` (each rounded down to a whole block) and joined with
+/// [`bgzf_cat`].  With the `threads` feature the pieces are compressed on
+/// threads of their own.  Since every piece starts on a block boundary of
+/// the whole, the output is byte-identical to compressing it in one go.
+pub fn bgzf_compress_split(data: &[u8], splits: &[usize], level: u32) -> io::Result<Vec<u8>> {
+    let mut bounds: Vec<usize> = splits.iter().map(|&s| s.min(data.len()) / BGZF_BLOCK_SIZE * BGZF_BLOCK_SIZE).collect();
+    bounds.extend([0, data.len()]);
+    bounds.sort_unstable();
+    bounds.dedup();
+    let pieces: Vec<&[u8]> = bounds.windows(2).map(|w| &data[w[0]..w[1]]).collect();
+    let compressed = run_workers(&pieces, |piece| bgzf_compress_chunks([piece], Vec::new(), level));
+    let compressed = compressed.into_iter().collect::<io::Result<Vec<_>>>()?;
+    Ok(bgzf_cat(&compressed.iter().map(Vec::as_slice).collect::<Vec<_>>()))
+}
+
+/// Compress all bytes from `input` into a single plain gzip member.
+pub fn gzip_compress_level<R: Read, W: Write>(input: R, output: W, level: u32) -> io::Result<()> {
+    let mut writer = GzipWriter::with_level(output, level);
+    copy_all(input, &mut writer)?;
+    writer.finish()?;
+    Ok(())
+}
+
+fn copy_all<R: Read, W: Write>(mut input: R, writer: &mut W) -> io::Result<()> {
+    let mut buf = PooledBuf::take(65536);
+    buf.resize(65536, 0);
     loop {
         let n = input.read(&mut buf)?;
         if n == 0 {
-            break;
+            return Ok(());
         }
         writer.write_all(&buf[..n])?;
     }
-    writer.finish()?;
-    Ok(())
 }
@@ -0,0 +1,285 @@
+//! Structural comparison of two indexes of the same file, e.g. ours and one
+//! built by samtools/tabix.  Byte equality is too strict: htslib keeps bins
+//! in a hash table (so their order is arbitrary) and may merge neighbouring
+//! chunks differently, neither of which changes what a query returns.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Cursor, Read};
+
+use crate::error::{io_error, ErrorCode};
+use super::bgzf::BgzfReader;
+use super::query::{bin_first, check_names, parse_fai, parse_tabix_config, parse_tabix_names, read_count, read_i32, read_u64, CsiIndex};
+use super::tabix::HTS_MIN_MARKER_DIST;
+
+/// Differences kept per comparison; further ones are only counted.
+const MAX_DIFFERENCES: usize = 100;
+
+/// One way two indexes disagree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexDifference {
+    /// Sequence the difference belongs to; `None` for header fields.
+    pub seq: Option<String>,
+    /// Bin number, for `loff`, `chunks` and `pseudo_bin` differences.
+    pub bin: Option<u32>,
+    /// What differs: a header field (`min_shift`, `col_beg`, …), `names`,
+    /// `sequence` (present in only one index), `order`, `loff`, `chunks`,
+    /// `pseudo_bin`, `linear`, or a `.fai` column (`length`, `offset`, …).
+    pub field: &'static str,
+    pub ours: String,
+    pub theirs: String,
+}
+
+impl fmt::Display for IndexDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(seq) = &self.seq {
+            write!(f, "{} ", seq)?;
+        }
+        if let Some(bin) = self.bin {
+            write!(f, "bin {} ", bin)?;
+        }
+        write!(f, "{}: ours {}, theirs {}", self.field, self.ours, self.theirs)
+    }
+}
+
+/// Result of [`compare_index`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IndexDiff {
+    /// `"csi"`, `"tbi"` or `"fai"`.
+    pub format: &'static str,
+    /// The first [`MAX_DIFFERENCES`] differences.
+    pub differences: Vec<IndexDifference>,
+    /// Number of differences, including those past the cap.
+    pub total: usize,
+}
+
+impl IndexDiff {
+    /// The indexes answer every query the same way.
+    pub fn is_equivalent(&self) -> bool {
+        self.total == 0
+    }
+
+    fn push(&mut self, seq: Option<&str>, bin: Option<u32>, field: &'static str, ours: impl fmt::Debug, theirs: impl fmt::Debug) {
+        self.total += 1;
+        if self.differences.len() < MAX_DIFFERENCES {
+            let (ours, theirs) = (format!("{:?}", ours), format!("{:?}", theirs));
+            self.differences.push(IndexDifference { seq: seq.map(str::to_owned), bin, field, ours, theirs });
+        }
+    }
+
+    /// `{format, equivalent, total, differences: [{seq, bin, field, ours, theirs}]}`.
+    pub fn to_json(&self) -> json::JsonValue {
+        json::object! {
+            format: self.format,
+            equivalent: self.is_equivalent(),
+            total: self.total,
+            differences: self.differences.iter().map(|d| json::object! {
+                seq: d.seq.as_deref(),
+                bin: d.bin,
+                field: d.field,
+                ours: d.ours.as_str(),
+                theirs: d.theirs.as_str(),
+            }).collect::<Vec<_>>(),
+        }
+    }
+}
+
+/// Compare two `.csi`, `.tbi` or `.fai` indexes of the same file.  Binary
+/// indexes may be given BGZF-compressed (as written) or decompressed; the
+/// format is detected and both must be the same.
+///
+/// Binned indexes are compared by header fields, sequence names and, per
+/// bin, `loff` and chunk list, with chunks sorted and merged the way htslib
+/// merges block-adjacent chunks; TBI linear indexes are compared window by
+/// window.  `.fai` records are matched by name.
+pub fn compare_index(ours: &[u8], theirs: &[u8]) -> io::Result<IndexDiff> {
+    let (ours, theirs) = (inflate(ours)?, inflate(theirs)?);
+    let (format, other) = (detect(&ours), detect(&theirs));
+    if format != other {
+        return Err(io_error(
+            ErrorCode::IndexMismatch,
+            format!("cannot compare a .{} index with a .{} index", format, other),
+        ));
+    }
+    let mut diff = IndexDiff { format, ..Default::default() };
+    match format {
+        "fai" => compare_fai(&ours, &theirs, &mut diff)?,
+        _ => compare_binned(&Binned::parse(&ours)?, &Binned::parse(&theirs)?, &mut diff),
+    }
+    Ok(diff)
+}
+
+fn inflate(data: &[u8]) -> io::Result<Vec<u8>> {
+    if !data.starts_with(&[0x1f, 0x8b]) {
+        return Ok(data.to_vec());
+    }
+    let mut raw = Vec::new();
+    BgzfReader::lenient(Cursor::new(data)).read_to_end(&mut raw)?;
+    Ok(raw)
+}
+
+fn detect(raw: &[u8]) -> &'static str {
+    match raw.get(..4) {
+        Some(b"CSI\x01") => "csi",
+        Some(b"TBI\x01") => "tbi",
+        _ => "fai",
+    }
+}
+
+fn compare_fai(ours: &[u8], theirs: &[u8], diff: &mut IndexDiff) -> io::Result<()> {
+    let (ours, theirs) = (parse_fai(ours)?, parse_fai(theirs)?);
+    for a in &ours {
+        let Some(b) = theirs.iter().find(|b| b.name == a.name) else {
+            diff.push(Some(&a.name), None, "sequence", "present", "absent");
+            continue;
+        };
+        let columns = [
+            ("length", a.length, b.length),
+            ("offset", a.offset, b.offset),
+            ("line_bases", a.line_bases, b.line_bases),
+            ("line_width", a.line_width, b.line_width),
+        ];
+        for (field, x, y) in columns.into_iter().filter(|(_, x, y)| x != y) {
+            diff.push(Some(&a.name), None, field, x, y);
+        }
+    }
+    for b in theirs.iter().filter(|b| !ours.iter().any(|a| a.name == b.name)) {
+        diff.push(Some(&b.name), None, "sequence", "absent", "present");
+    }
+    let names = |recs: &[super::FaiRecord]| recs.iter().map(|r| r.name.clone()).collect::<Vec<_>>();
+    if diff.total == 0 && names(&ours) != names(&theirs) {
+        diff.push(None, None, "order", names(&ours), names(&theirs));
+    }
+    Ok(())
+}
+
+/// Bins of one sequence: bin → (loff, chunks).  TBI has no loff.
+type Bins = BTreeMap<u32, (Option<u64>, Vec<(u64, u64)>)>;
+
+/// A CSI or TBI index with its bins in a canonical form.
+struct Binned {
+    header: Vec<(&'static str, u64)>,
+    names: Vec<String>,
+    /// Per sequence, with chunks sorted and merged.
+    seqs: Vec<Bins>,
+    /// Per sequence: TBI linear index (empty for CSI).
+    linear: Vec<Vec<u64>>,
+    pseudo_bin: u32,
+}
+
+impl Binned {
+    fn parse(raw: &[u8]) -> io::Result<Self> {
+        if detect(raw) == "csi" {
+            let csi = CsiIndex::parse(raw)?;
+            let mut header = vec![("min_shift", csi.min_shift as u64), ("n_lvls", csi.n_lvls as u64)];
+            if let Some(config) = csi.config {
+                header.extend(config_fields(&config));
+            }
+            let seqs = csi.seqs.into_iter().map(|bins| bins.into_iter().map(|(bin, (loff, chunks))| (bin, (Some(loff), chunks))).collect());
+            return Ok(Binned::canonical(header, csi.names, seqs.collect(), Vec::new(), bin_first(csi.n_lvls + 1) + 1));
+        }
+
+        let mut r = Cursor::new(&raw[4..]);
+        // Each sequence has at least its bin and interval counts.
+        let n_ref = read_count(&mut r, 8, "sequence")?;
+        let mut meta = vec![0u8; 24];
+        r.read_exact(&mut meta)?;
+        let l_nm = read_count(&mut r, 1, "name byte")?;
+        meta.extend((l_nm as i32).to_le_bytes());
+        meta.resize(28 + l_nm, 0);
+        r.read_exact(&mut meta[28..])?;
+        let names = parse_tabix_names(&meta);
+        check_names(&names, n_ref)?;
+        let mut header = vec![("min_shift", 14), ("n_lvls", 5)];
+        header.extend(parse_tabix_config(&meta).iter().flat_map(config_fields));
+
+        let (mut seqs, mut linear) = (Vec::with_capacity(n_ref), Vec::with_capacity(n_ref));
+        for _ in 0..n_ref {
+            let mut bins = BTreeMap::new();
+            for _ in 0..read_count(&mut r, 8, "bin")? {
+                let bin = read_i32(&mut r)? as u32;
+                let chunks = (0..read_count(&mut r, 16, "chunk")?).map(|_| Ok((read_u64(&mut r)?, read_u64(&mut r)?))).collect::<io::Result<_>>()?;
+                bins.insert(bin, (None, chunks));
+            }
+            seqs.push(bins);
+            linear.push((0..read_count(&mut r, 8, "interval")?).map(|_| read_u64(&mut r)).collect::<io::Result<_>>()?);
+        }
+        Ok(Binned::canonical(header, names, seqs, linear, bin_first(6) + 1))
+    }
+
+    /// Sort and merge the chunks of every bin but the pseudo-bin, whose two
+    /// "chunks" are really file offsets and record counts.
+    fn canonical(
+        header: Vec<(&'static str, u64)>,
+        names: Vec<String>,
+        mut seqs: Vec<Bins>,
+        linear: Vec<Vec<u64>>,
+        pseudo_bin: u32,
+    ) -> Self {
+        for (bin, (_, chunks)) in seqs.iter_mut().flatten() {
+            if *bin != pseudo_bin {
+                *chunks = merge_chunks(std::mem::take(chunks));
+            }
+        }
+        Binned { header, names, seqs, linear, pseudo_bin }
+    }
+}
+
+fn config_fields(config: &super::TabixConfig) -> [(&'static str, u64); 6] {
+    [
+        ("preset", config.preset() as u64),
+        ("col_seq", config.col_seq as u64),
+        ("col_beg", config.col_beg as u64),
+        ("col_end", config.col_end as u64),
+        ("meta_char", config.meta_char as u64),
+        ("line_skip", config.line_skip as u64),
+    ]
+}
+
+/// Sort chunks and merge those htslib would have merged: a chunk starting
+/// within [`HTS_MIN_MARKER_DIST`] of the previous chunk's end.
+fn merge_chunks(mut chunks: Vec<(u64, u64)>) -> Vec<(u64, u64)> {
+    chunks.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(chunks.len());
+    for (s, e) in chunks {
+        match merged.last_mut() {
+            Some(last) if s <= last.1.saturating_add(HTS_MIN_MARKER_DIST) => last.1 = last.1.max(e),
+            _ => merged.push((s, e)),
+        }
+    }
+    merged
+}
+
+fn compare_binned(ours: &Binned, theirs: &Binned, diff: &mut IndexDiff) {
+    for (&(field, x), &(_, y)) in ours.header.iter().zip(&theirs.header).filter(|(a, b)| a != b) {
+        diff.push(None, None, field, x, y);
+    }
+    if ours.names != theirs.names {
+        diff.push(None, None, "names", &ours.names, &theirs.names);
+    }
+    for (i, name) in ours.names.iter().enumerate() {
+        let Some(j) = theirs.names.iter().position(|n| n == name) else {
+            continue;
+        };
+        let (a, b) = (&ours.seqs[i], &theirs.seqs[j]);
+        for bin in a.keys().chain(b.keys().filter(|bin| !a.contains_key(bin))) {
+            let field = if *bin == ours.pseudo_bin { "pseudo_bin" } else { "chunks" };
+            match (a.get(bin), b.get(bin)) {
+                (Some((la, ca)), Some((lb, cb))) => {
+                    if la != lb {
+                        diff.push(Some(name), Some(*bin), "loff", la.unwrap_or_default(), lb.unwrap_or_default());
+                    }
+                    if ca != cb {
+                        diff.push(Some(name), Some(*bin), field, ca, cb);
+                    }
+                }
+                (ca, cb) => diff.push(Some(name), Some(*bin), field, ca.map(|c| &c.1), cb.map(|c| &c.1)),
+            }
+        }
+        let (la, lb) = (ours.linear.get(i).map(Vec::as_slice).unwrap_or_default(), theirs.linear.get(j).map(Vec::as_slice).unwrap_or_default());
+        if la != lb {
+            let window = la.iter().zip(lb).position(|(x, y)| x != y).unwrap_or(la.len().min(lb.len()));
+            diff.push(Some(name), None, "linear", (window, la.get(window)), (window, lb.get(window)));
+        }
+    }
+}
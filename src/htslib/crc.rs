@@ -0,0 +1,95 @@
+//! CRC32 of gzip members, with a selectable implementation.
+//!
+//! Every BGZF block carries the CRC32 of its contents, computed when it is
+//! written and checked when it is read.  `crc32fast` (the default) picks
+//! the PCLMULQDQ (x86) or ARMv8 CRC instructions at run time and otherwise
+//! uses slice-by-16 tables, which is what wasm builds always get.  The
+//! scalar implementation here does one table lookup per byte: slower, but
+//! short enough to audit and a reference for the fast one.  The choice is
+//! process-wide and never changes output bytes.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use wasm_bindgen::prelude::*;
+
+/// CRC32 implementation used for gzip trailers.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Crc32Impl {
+    /// `crc32fast`, hardware-accelerated where the CPU allows.
+    Fast = "fast",
+    /// Byte-at-a-time table lookup.
+    Scalar = "scalar",
+}
+
+static SCALAR: AtomicBool = AtomicBool::new(false);
+
+/// Select the CRC32 implementation for every reader and writer.
+#[wasm_bindgen]
+pub fn set_crc32_impl(imp: Crc32Impl) {
+    SCALAR.store(imp == Crc32Impl::Scalar, Ordering::Relaxed);
+}
+
+/// The CRC32 implementation in use.
+#[wasm_bindgen]
+pub fn crc32_impl() -> Crc32Impl {
+    if SCALAR.load(Ordering::Relaxed) { Crc32Impl::Scalar } else { Crc32Impl::Fast }
+}
+
+/// CRC32 (gzip polynomial) of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut hasher = Crc32::new();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+/// Streaming CRC32 with the implementation selected when it was created.
+pub(crate) enum Crc32 {
+    Fast(crc32fast::Hasher),
+    /// Running value, pre-inverted.
+    Scalar(u32),
+}
+
+impl Crc32 {
+    pub(crate) fn new() -> Self {
+        match crc32_impl() {
+            Crc32Impl::Scalar => Crc32::Scalar(!0),
+            _ => Crc32::Fast(crc32fast::Hasher::new()),
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            Crc32::Fast(hasher) => hasher.update(data),
+            Crc32::Scalar(crc) => {
+                for &b in data {
+                    *crc = TABLE[((*crc ^ b as u32) & 0xff) as usize] ^ (*crc >> 8);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn finalize(self) -> u32 {
+        match self {
+            Crc32::Fast(hasher) => hasher.finalize(),
+            Crc32::Scalar(crc) => !crc,
+        }
+    }
+}
+
+/// CRC of every byte value, reflected polynomial 0xedb88320.
+const TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
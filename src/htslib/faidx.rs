@@ -1,127 +1,200 @@
 use std::io::{self, Read, Write};
+use wasm_bindgen::prelude::*;
+use crate::error::{io_error, ErrorCode};
+use crate::fasta::{utf8_name, FastaLine, FastaParser, FastaRecord};
+use crate::scan;
 use super::bgzf::BgzfReader;
+use super::pool::PooledBuf;
+
+/// What the offset column (3) of a `.fai` holds.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FaiOffsets {
+    /// Byte offset into the uncompressed text, located in the BGZF file via
+    /// the `.gzi`.  What `samtools faidx` writes and htslib expects.
+    Uncompressed = "uncompressed",
+    /// BGZF virtual offset (`compressed block offset << 16 | offset within
+    /// the block`), usable without a `.gzi` by readers that seek on virtual
+    /// offsets.  Line arithmetic from it is only valid within one block.
+    Virtual = "virtual",
+}
 
 // ---------------------------------------------------------------------------
 // Public API
 // ---------------------------------------------------------------------------
 
+/// Build faidx (`.fai`) and GZI (`.gzi`) indexes for a BGZF-compressed FASTA,
+/// with samtools' uncompressed offsets (see [`faidx_index_fasta_with`]).
+pub fn faidx_index_fasta<R: Read, F: Write, G: Write>(bgzf_input: R, fai_output: F, gzi_output: G) -> io::Result<()> {
+    faidx_index_fasta_with(bgzf_input, fai_output, gzi_output, FaiOffsets::Uncompressed)
+}
+
 /// Build faidx (`.fai`) and GZI (`.gzi`) indexes for a BGZF-compressed FASTA.
 ///
 /// - `bgzf_input`: BGZF-compressed FASTA byte stream.
 /// - `fai_output`: receives the text `.fai` index.
 /// - `gzi_output`: receives the binary `.gzi` block index.
-pub fn faidx_index_fasta<R: Read, F: Write, G: Write>(
+/// - `offsets`: convention of the `.fai` offset column.
+///
+/// Fails with `InvalidData` if a sequence has a line of a different length
+/// before its last line (see [`LineLayout`]), since the `.fai` could not
+/// locate bases past it.
+pub fn faidx_index_fasta_with<R: Read, F: Write, G: Write>(
     bgzf_input: R,
-    mut fai_output: F,
-    mut gzi_output: G,
+    fai_output: F,
+    gzi_output: G,
+    offsets: FaiOffsets,
 ) -> io::Result<()> {
-    let mut reader = BgzfReader::new(bgzf_input);
+    faidx_index_reader(BgzfReader::new(bgzf_input), fai_output, gzi_output, offsets)
+}
 
-    // State for current sequence
-    let mut cur_name: Option<String> = None;
-    let mut cur_seq_offset: u64 = 0; // virtual offset of first base
-    let mut cur_seq_len: u64 = 0;
-    let mut cur_line_blen: usize = 0; // raw bytes per line (including newline)
-    let mut cur_line_len: usize = 0;  // bases per line (excluding newline)
-    let mut first_data_line: bool = false;
-
-    // Helper: write one completed FAI record
-    let write_record = |fai: &mut F,
-                             name: &str,
-                             seq_len: u64,
-                             seq_offset: u64,
-                             line_blen: usize,
-                             line_len: usize| -> io::Result<()> {
-        let line = format!("{}\t{}\t{}\t{}\t{}\n", name, seq_len, seq_offset, line_len, line_blen);
-        fai.write_all(line.as_bytes())
-    };
-
-    let mut line_buf = Vec::with_capacity(4096);
-
-    loop {
-        line_buf.clear();
-        let (n, _voff_start) = reader.read_line(&mut line_buf)?;
-        if n == 0 {
-            // EOF — flush last sequence
-            if let Some(ref name) = cur_name {
-                write_record(
-                    &mut fai_output,
-                    name,
-                    cur_seq_len,
-                    cur_seq_offset,
-                    cur_line_blen,
-                    cur_line_len,
-                )?;
-            }
-            break;
+/// [`faidx_index_fasta_with`] reading through a configured `reader` (e.g.
+/// one that skips CRC checks).
+pub fn faidx_index_reader<R: Read, F: Write, G: Write>(
+    reader: BgzfReader<R>,
+    fai_output: F,
+    gzi_output: G,
+    offsets: FaiOffsets,
+) -> io::Result<()> {
+    let mut indexer = FaidxIndexer::new(reader, fai_output, offsets);
+    while !indexer.step(usize::MAX)? {}
+    indexer.finish(gzi_output).map(drop)
+}
+
+/// [`faidx_index_reader`] a few lines at a time, for callers that have to
+/// give control back in between (see `cooperative.rs`).
+pub struct FaidxIndexer<R: Read, F: Write> {
+    reader: BgzfReader<R>,
+    fai_output: F,
+    offsets: FaiOffsets,
+    // The parser keeps the current sequence's name and length; its offset
+    // and line layout are tracked here.
+    parser: FastaParser,
+    cur_seq_offset: u64, // offset of first base
+    layout: LineLayout,
+    line_buf: PooledBuf,
+    done: bool,
+}
+
+impl<R: Read, F: Write> FaidxIndexer<R, F> {
+    pub fn new(reader: BgzfReader<R>, fai_output: F, offsets: FaiOffsets) -> Self {
+        FaidxIndexer {
+            reader,
+            fai_output,
+            offsets,
+            parser: FastaParser::new(),
+            cur_seq_offset: 0,
+            layout: LineLayout::default(),
+            line_buf: PooledBuf::take(4096),
+            done: false,
         }
+    }
+
+    /// Write one completed FAI record.
+    fn write_record(&mut self, record: &FastaRecord) -> io::Result<()> {
+        let (offset, layout) = (self.cur_seq_offset, &self.layout);
+        let line = format!("{}\t{}\t{}\t{}\t{}\n", record.name, record.length, offset, layout.line_len, layout.line_blen);
+        self.fai_output.write_all(line.as_bytes())
+    }
 
-        if line_buf.is_empty() || line_buf[0] == b'\n' || line_buf[0] == b'\r' {
-            // skip blank lines
-            continue;
+    /// Index up to `lines` more lines; true once the input is exhausted.
+    pub fn step(&mut self, lines: usize) -> io::Result<bool> {
+        for _ in 0..lines {
+            if self.done {
+                break;
+            }
+            self.line()?;
         }
+        Ok(self.done)
+    }
 
-        if line_buf[0] == b'>' {
-            // Flush previous sequence
-            if let Some(ref name) = cur_name {
-                write_record(
-                    &mut fai_output,
-                    name,
-                    cur_seq_len,
-                    cur_seq_offset,
-                    cur_line_blen,
-                    cur_line_len,
-                )?;
+    fn line(&mut self) -> io::Result<()> {
+        self.line_buf.clear();
+        let (n, _voff_start) = self.reader.read_line(&mut self.line_buf)?;
+        if n == 0 {
+            // EOF — flush last sequence
+            if let Some(record) = self.parser.finish() {
+                self.write_record(&record)?;
             }
+            self.done = true;
+            return Ok(());
+        }
 
-            // Parse new header — name ends at first whitespace
-            let header = strip_newline(&line_buf[1..]); // skip '>'
-            let name_end = header
-                .iter()
-                .position(|&b| b == b' ' || b == b'\t')
-                .unwrap_or(header.len());
-            let name = std::str::from_utf8(&header[..name_end])
-                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "non-UTF8 sequence name"))?
-                .to_owned();
-
-            cur_name = Some(name);
-            cur_seq_len = 0;
-            cur_line_blen = 0;
-            cur_line_len = 0;
-            // seq_offset is the uncompressed byte position of the first base,
-            // which is what samtools faidx stores (not a BGZF virtual offset).
-            cur_seq_offset = reader.uncompressed_offset();
-            first_data_line = true;
-        } else {
-            // Data line
-            let raw_len = line_buf.len(); // includes newline chars
-            // Count printable (graph) chars — bases
-            let base_count = line_buf.iter().filter(|&&b| b.is_ascii_graphic()).count();
-
-            if first_data_line {
-                cur_line_blen = raw_len;
-                cur_line_len = base_count;
-                first_data_line = false;
+        let bases_before = self.parser.current().map_or(0, |r| r.length);
+        match self.parser.push(&self.line_buf) {
+            // skip blank lines, which end the sequence's regular lines
+            (FastaLine::Blank, _) => self.layout.blank(),
+            (FastaLine::Header { name, .. }, ended) => {
+                self.reader.start_contig(utf8_name(name)?);
+                // Flush previous sequence
+                if let Some(record) = ended {
+                    self.write_record(&record)?;
+                }
+                self.layout = LineLayout::default();
+                // seq_offset is the uncompressed byte position of the first base,
+                // which is what samtools faidx stores, unless asked for the BGZF
+                // virtual offset.
+                self.cur_seq_offset = if self.offsets == FaiOffsets::Virtual {
+                    self.reader.virtual_offset()
+                } else {
+                    self.reader.uncompressed_offset()
+                };
+            }
+            (FastaLine::Sequence(_), _) => {
+                // Includes the newline: 2 bytes for `\r\n`, which samtools also
+                // counts in the line width (the `\r` is not a base).
+                let raw_len = self.line_buf.len();
+                // Printable (graph) chars — bases, as counted by the parser
+                let base_count = match self.parser.current() {
+                    Some(record) => (record.length - bases_before) as usize,
+                    None => scan::count_graphic(&self.line_buf),
+                };
+
+                if !self.layout.data(raw_len, base_count) {
+                    return Err(io_error(
+                        ErrorCode::FastaLineLength,
+                        format!(
+                            "irregular line length in sequence {:?} at line {}",
+                            self.parser.current().map(|r| r.name.as_str()).unwrap_or_default(),
+                            self.parser.lines_read()
+                        ),
+                    ));
+                }
             }
+        }
+        Ok(())
+    }
 
-            cur_seq_len += base_count as u64;
+    /// Write the `.gzi` to `gzi_output` once [`FaidxIndexer::step`] is
+    /// done; returns the `.fai` output and the reader.
+    pub fn finish<G: Write>(self, gzi_output: G) -> io::Result<(F, BgzfReader<R>)> {
+        if !self.done {
+            return Err(io_error(ErrorCode::Internal, "the FASTA index is not complete"));
         }
+        write_gzi(self.reader.gzi_entries(), gzi_output)?;
+        Ok((self.fai_output, self.reader))
     }
+}
+
+/// Build the `.gzi` block index of any BGZF file (a bgzipped VCF, TSV,
+/// ...), as `bgzip -r` does.  Every block is read through, so corrupt
+/// blocks fail as they would for [`faidx_index_fasta`].
+pub fn gzi_index<R: Read, W: Write>(bgzf_input: R, gzi_output: W) -> io::Result<()> {
+    let mut reader = BgzfReader::new(bgzf_input);
+    io::copy(&mut reader, &mut io::sink())?;
+    write_gzi(reader.gzi_entries(), gzi_output)
+}
 
-    // -----------------------------------------------------------------------
-    // Write GZI
-    // -----------------------------------------------------------------------
-    // Format:
-    //   n_blocks: u64
-    //   For each block: caddr: u64, uaddr: u64
-    // The implicit (0,0) block is NOT written.
-    let entries = reader.gzi_entries();
+/// Write GZI entries:
+///   n_blocks: u64
+///   For each block: caddr: u64, uaddr: u64
+/// The implicit (0,0) block is NOT written.
+fn write_gzi<G: Write>(entries: &[(u64, u64)], mut gzi_output: G) -> io::Result<()> {
     gzi_output.write_all(&(entries.len() as u64).to_le_bytes())?;
     for &(caddr, uaddr) in entries {
         gzi_output.write_all(&caddr.to_le_bytes())?;
         gzi_output.write_all(&uaddr.to_le_bytes())?;
     }
-
     Ok(())
 }
 
@@ -129,10 +202,36 @@ pub fn faidx_index_fasta<R: Read, F: Write, G: Write>(
 // Helpers
 // ---------------------------------------------------------------------------
 
-fn strip_newline(buf: &[u8]) -> &[u8] {
-    let mut end = buf.len();
-    while end > 0 && (buf[end - 1] == b'\n' || buf[end - 1] == b'\r') {
-        end -= 1;
+/// Line layout of one sequence, following samtools' rule: every data line
+/// has the length of the first one except the last, which may differ.  A
+/// blank line counts as such a last line.
+#[derive(Default)]
+pub(crate) struct LineLayout {
+    /// Raw bytes per line (including newline) of the first data line.
+    pub(crate) line_blen: usize,
+    /// Bases per line of the first data line.
+    pub(crate) line_len: usize,
+    lines: usize,
+    ended: bool,
+}
+
+impl LineLayout {
+    pub(crate) fn blank(&mut self) {
+        self.ended |= self.lines > 0;
+    }
+
+    /// Record a data line; `false` if one of a different length came before.
+    pub(crate) fn data(&mut self, raw_len: usize, bases: usize) -> bool {
+        if self.ended {
+            return false;
+        }
+        if self.lines == 0 {
+            self.line_blen = raw_len;
+            self.line_len = bases;
+        } else if raw_len != self.line_blen || bases != self.line_len {
+            self.ended = true;
+        }
+        self.lines += 1;
+        true
     }
-    &buf[..end]
 }
@@ -0,0 +1,277 @@
+//! Member boundaries of multi-member gzip files.
+//!
+//! Pipelines that gzip each contig on its own and concatenate the results
+//! produce one `.gz` of many members.  Each member is a complete gzip stream,
+//! so its boundaries are natural chunking points: members inflate
+//! independently (e.g. in parallel workers) and concatenate to the same bytes
+//! a `MultiGzDecoder` yields.
+
+use std::io::{self, Cursor, Read};
+
+use flate2::{Decompress, FlushDecompress, Status};
+
+use super::bgzf::{read_exact_inner, read_member_header, BgzfReader, MemberHeader};
+use super::crc::crc32;
+use super::speculative::inflate_speculative;
+use crate::error::{io_error, ErrorCode};
+use crate::limits;
+
+/// Where one gzip member sits in the compressed file and in the
+/// decompressed stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GzipMember {
+    pub compressed_offset: u64,
+    /// Header, deflate data and trailer.
+    pub compressed_len: u64,
+    pub uncompressed_offset: u64,
+    pub uncompressed_len: u64,
+}
+
+impl GzipMember {
+    /// The member's bytes within the file it was found in.
+    pub fn slice<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        &data[self.compressed_offset as usize..(self.compressed_offset + self.compressed_len) as usize]
+    }
+
+    pub fn to_json(&self) -> json::JsonValue {
+        json::object! {
+            compressed_offset: self.compressed_offset,
+            compressed_len: self.compressed_len,
+            uncompressed_offset: self.uncompressed_offset,
+            uncompressed_len: self.uncompressed_len,
+        }
+    }
+}
+
+/// The non-empty members of a gzip file, in order; empty ones (such as the
+/// BGZF EOF block) are skipped.  Members carrying a BGZF `BC` size are
+/// stepped over without inflating; plain members have to be inflated once
+/// to find where they end, but their output is discarded.  CRCs are not
+/// checked here; [`inflate_member`] does that.
+pub fn gzip_members(data: &[u8]) -> io::Result<Vec<GzipMember>> {
+    let mut cursor = Cursor::new(data);
+    let mut members = Vec::new();
+    let mut uncompressed_offset = 0;
+    loop {
+        let start = cursor.position();
+        let Some(MemberHeader { len: header_len, bsize }) = read_member_header(&mut cursor)? else {
+            return Ok(members);
+        };
+        let body = start + header_len as u64;
+        let (deflate_len, inflated_len) = match bsize {
+            Some(bsize) => {
+                let deflate_len = bsize
+                    .checked_sub(header_len + 8)
+                    .ok_or_else(|| io_error(ErrorCode::BgzfCorrupt, "BGZF block too small"))?;
+                (deflate_len as u64, None)
+            }
+            None => {
+                let (deflate_len, inflated_len) = deflate_stream_len(&data[body as usize..])?;
+                (deflate_len, Some(inflated_len))
+            }
+        };
+        cursor.set_position(body + deflate_len);
+        let mut footer = [0u8; 8];
+        read_exact_inner(&mut cursor, &mut footer)?;
+        let isize = u32::from_le_bytes([footer[4], footer[5], footer[6], footer[7]]) as u64;
+        let uncompressed_len = inflated_len.unwrap_or(isize);
+        if uncompressed_len as u32 as u64 != isize {
+            return Err(io_error(
+                ErrorCode::BgzfCorrupt,
+                format!("gzip isize mismatch: got {} expected {}", uncompressed_len, isize),
+            ));
+        }
+        if uncompressed_len > 0 {
+            let compressed_len = cursor.position() - start;
+            members.push(GzipMember { compressed_offset: start, compressed_len, uncompressed_offset, uncompressed_len });
+            uncompressed_offset += uncompressed_len;
+        }
+    }
+}
+
+/// (compressed, decompressed) length of the raw deflate stream at the start
+/// of `data`, discarding the output.
+fn deflate_stream_len(data: &[u8]) -> io::Result<(u64, u64)> {
+    let mut d = Decompress::new(false);
+    let mut scratch = vec![0u8; 0x10000];
+    loop {
+        let (in_before, out_before) = (d.total_in(), d.total_out());
+        let status = d
+            .decompress(&data[in_before as usize..], &mut scratch, FlushDecompress::None)
+            .map_err(|e| io_error(ErrorCode::BgzfCorrupt, e.to_string()))?;
+        if status == Status::StreamEnd {
+            return Ok((d.total_in(), d.total_out()));
+        }
+        if d.total_in() == in_before && d.total_out() == out_before {
+            if d.total_in() as usize == data.len() {
+                return Err(io_error(ErrorCode::BgzfTruncated, "truncated gzip member"));
+            }
+            return Err(io_error(ErrorCode::BgzfCorrupt, "corrupt deflate stream"));
+        }
+    }
+}
+
+/// Inflate one member (its bytes as given by [`GzipMember::slice`]),
+/// checking its CRC and length.
+pub fn inflate_member(member: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    BgzfReader::lenient(member).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+/// Inflate `members` of `data` one by one and concatenate them: the same
+/// bytes as decoding the whole file, produced chunk by chunk.
+pub fn inflate_members(data: &[u8], members: &[GzipMember]) -> io::Result<Vec<u8>> {
+    let total = members.iter().map(|m| m.uncompressed_len).sum::<u64>();
+    let mut out = Vec::new();
+    limits::try_reserve(&mut out, total, "the decompressed input")?;
+    for member in members {
+        let chunk = inflate_member(member.slice(data))?;
+        if chunk.len() as u64 != member.uncompressed_len {
+            return Err(io_error(
+                ErrorCode::BgzfCorrupt,
+                format!("member at {} inflated to {} bytes, expected {}", member.compressed_offset, chunk.len(), member.uncompressed_len),
+            ));
+        }
+        out.extend_from_slice(&chunk);
+    }
+    Ok(out)
+}
+
+// ---------------------------------------------------------------------------
+// Chunked (parallel) decompression
+// ---------------------------------------------------------------------------
+
+/// One member at the start of `data`: (compressed length, contents), with
+/// its CRC and length checked.
+fn decode_member(data: &[u8]) -> io::Result<(usize, Vec<u8>)> {
+    let mut cursor = Cursor::new(data);
+    let header_len = read_member_header(&mut cursor)?
+        .ok_or_else(|| io_error(ErrorCode::BgzfTruncated, "unexpected EOF"))?
+        .len;
+    let mut d = Decompress::new(false);
+    let mut out = Vec::new();
+    loop {
+        out.reserve(0x10000);
+        let (in_before, out_before) = (d.total_in(), d.total_out());
+        let status = d
+            .decompress_vec(&data[header_len + in_before as usize..], &mut out, FlushDecompress::None)
+            .map_err(|e| io_error(ErrorCode::BgzfCorrupt, e.to_string()))?;
+        if status == Status::StreamEnd {
+            break;
+        }
+        if d.total_in() == in_before && d.total_out() == out_before {
+            return Err(io_error(ErrorCode::BgzfTruncated, "truncated gzip member"));
+        }
+    }
+    let end = header_len + d.total_in() as usize;
+    let footer = data.get(end..end + 8).ok_or_else(|| io_error(ErrorCode::BgzfTruncated, "truncated gzip member"))?;
+    if crc32(&out).to_le_bytes() != footer[..4] || (out.len() as u32).to_le_bytes() != footer[4..] {
+        return Err(io_error(ErrorCode::BgzfCorrupt, "gzip CRC32 or isize mismatch"));
+    }
+    Ok((end + 8, out))
+}
+
+/// [`decode_member`] appending to `out`; returns the compressed length.
+fn decode_member_into(data: &[u8], out: &mut Vec<u8>) -> io::Result<usize> {
+    let (len, member) = decode_member(data)?;
+    limits::try_reserve(out, member.len() as u64, "the decompressed input")?;
+    out.extend_from_slice(&member);
+    Ok(len)
+}
+
+/// A worker's output: members from `start` up to `end`.
+struct Chunk {
+    start: usize,
+    end: usize,
+    data: Vec<u8>,
+}
+
+/// Decode the members starting in `from..to`.  The first member is found
+/// speculatively: the first gzip magic at or after `from` that decodes as
+/// a whole member with a valid CRC (magic bytes inside deflate data fail
+/// that).  Decoding then runs on until a member starts at or after `to`.
+fn decode_range(data: &[u8], from: usize, to: usize) -> io::Result<Option<Chunk>> {
+    let mut pos = from;
+    let (start, first) = loop {
+        // Magic bytes starting before `to`; later ones are the next worker's.
+        let window = &data[pos..(to + 2).min(data.len())];
+        let Some(i) = window.windows(3).position(|w| w == [0x1f, 0x8b, 0x08]) else {
+            return Ok(None);
+        };
+        match decode_member(&data[pos + i..]) {
+            Ok(member) => break (pos + i, member),
+            Err(_) => pos += i + 1,
+        }
+    };
+    let (mut end, mut out) = (start + first.0, first.1);
+    while end < to && end < data.len() {
+        end += decode_member_into(&data[end..], &mut out)?;
+    }
+    Ok(Some(Chunk { start, end, data: out }))
+}
+
+/// Decompress a gzip file (BGZF or any concatenation of gzip members) as
+/// `workers` chunks of roughly equal compressed size.  With the `threads`
+/// feature each chunk gets its own thread (where threads can be spawned);
+/// otherwise they run one after another.  Since gzip members are
+/// independent, BGZF files split freely at member boundaries.  Plain gzip,
+/// a single member included, is split at deflate blocks instead (see
+/// [`inflate_speculative`]); where that does not decode cleanly, it is
+/// split at members as BGZF is.  Gaps between chunks are filled
+/// sequentially, so the result is always that of a sequential decoder.
+pub fn inflate_parallel(data: &[u8], workers: usize) -> io::Result<Vec<u8>> {
+    let workers = workers.clamp(1, data.len().max(1));
+    let bgzf = read_member_header(&mut Cursor::new(data)).ok().flatten().is_some_and(|header| header.bsize.is_some());
+    if workers > 1 && !bgzf {
+        if let Some((out, _)) = inflate_speculative(data, workers)? {
+            return Ok(out);
+        }
+    }
+    let bounds: Vec<usize> = (0..=workers).map(|i| data.len() * i / workers).collect();
+    let ranges: Vec<(usize, usize)> = bounds.windows(2).map(|w| (w[0], w[1])).collect();
+    let chunks = run_workers(&ranges, |&(from, to)| decode_range(data, from, to));
+
+    let mut out = Vec::new();
+    let mut pos = 0;
+    // Gaps before a chunk (its worker locked onto a fake header, or the one
+    // before it failed) are decoded sequentially, which also reports any
+    // error as a sequential reader would.  A chunk that starts inside a
+    // member already decoded is dropped.
+    for chunk in chunks.into_iter().flat_map(Result::ok).flatten() {
+        while pos < chunk.start {
+            pos += decode_member_into(&data[pos..], &mut out)?;
+        }
+        if pos == chunk.start {
+            limits::try_reserve(&mut out, chunk.data.len() as u64, "the decompressed input")?;
+            out.extend_from_slice(&chunk.data);
+            pos = chunk.end;
+        }
+    }
+    while pos < data.len() {
+        pos += decode_member_into(&data[pos..], &mut out)?;
+    }
+    Ok(out)
+}
+
+/// `f` over `items`, on scoped threads with the `threads` feature.  Items
+/// whose thread cannot be spawned (e.g. wasm without thread support) run on
+/// the calling thread.
+pub(super) fn run_workers<I: Sync, T: Send>(items: &[I], f: impl Fn(&I) -> T + Sync) -> Vec<T> {
+    if !cfg!(feature = "threads") || items.len() < 2 {
+        return items.iter().map(&f).collect();
+    }
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .iter()
+            .map(|item| std::thread::Builder::new().spawn_scoped(scope, || f(item)).map_err(|_| item))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| match handle {
+                Ok(handle) => handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)),
+                Err(item) => f(item),
+            })
+            .collect()
+    })
+}
@@ -0,0 +1,97 @@
+//! Reusable byte buffers.
+//!
+//! A run compresses each input, then reads the output back once per index,
+//! and every stage wants the same few block-sized buffers (a 64 KiB block,
+//! its deflate data, a line).  Taking them from a per-thread free list
+//! instead of the allocator lets each stage reuse the buffers of the one
+//! before; on wasm, whose memory only grows, that keeps the peak down.  A
+//! buffer goes back on the list when its [`PooledBuf`] is dropped.
+
+use std::cell::{Cell, RefCell};
+use std::ops::{Deref, DerefMut};
+
+/// Buffers kept on the free list at most.
+const MAX_FREE: usize = 8;
+/// Larger buffers (e.g. a whole plain gzip member) go back to the allocator.
+const MAX_KEPT_CAPACITY: usize = 256 << 10;
+
+thread_local! {
+    static FREE: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+    static STATS: Cell<PoolStats> = const { Cell::new(PoolStats { allocated: 0, reused: 0 }) };
+}
+
+/// Buffer requests on the calling thread since it started.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Served by a fresh allocation.
+    pub allocated: u64,
+    /// Served from the free list.
+    pub reused: u64,
+}
+
+/// Counters of the calling thread's pool.
+pub fn pool_stats() -> PoolStats {
+    STATS.with(Cell::get)
+}
+
+/// Capacity of the buffers on the calling thread's free list.
+pub fn pooled_bytes() -> usize {
+    FREE.with(|free| free.borrow().iter().map(Vec::capacity).sum())
+}
+
+/// An empty `Vec<u8>` from the pool, returned to it on drop.
+pub(crate) struct PooledBuf(Vec<u8>);
+
+impl PooledBuf {
+    /// A buffer with room for at least `capacity` bytes: the smallest free
+    /// one that fits, else a free one grown to fit, else a new one.
+    pub(crate) fn take(capacity: usize) -> Self {
+        let reused = FREE.with(|free| {
+            let mut free = free.borrow_mut();
+            let fit = (0..free.len())
+                .filter(|&i| free[i].capacity() >= capacity)
+                .min_by_key(|&i| free[i].capacity())
+                .or_else(|| (0..free.len()).max_by_key(|&i| free[i].capacity()))?;
+            Some(free.swap_remove(fit))
+        });
+        STATS.with(|stats| {
+            let mut s = stats.get();
+            if reused.is_some() { s.reused += 1 } else { s.allocated += 1 }
+            stats.set(s);
+        });
+        let mut buf = reused.unwrap_or_default();
+        buf.reserve(capacity);
+        PooledBuf(buf)
+    }
+}
+
+impl Deref for PooledBuf {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.0
+    }
+}
+
+impl DerefMut for PooledBuf {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.0
+    }
+}
+
+impl Drop for PooledBuf {
+    fn drop(&mut self) {
+        let mut buf = std::mem::take(&mut self.0);
+        if buf.capacity() == 0 || buf.capacity() > MAX_KEPT_CAPACITY {
+            return;
+        }
+        buf.clear();
+        // The free list may already be gone while the thread exits.
+        let _ = FREE.try_with(|free| {
+            let mut free = free.borrow_mut();
+            if free.len() < MAX_FREE {
+                free.push(buf);
+            }
+        });
+    }
+}
@@ -0,0 +1,539 @@
+use std::collections::HashMap;
+use std::io::{self, Cursor, Read, Write};
+use crate::error::{io_error, ErrorCode};
+use crate::limits;
+use super::bgzf::BgzfReader;
+use super::tabix::{IndexWriter, TabixConfig};
+
+// ---------------------------------------------------------------------------
+// Index parsing
+// ---------------------------------------------------------------------------
+
+/// One line of a `.fai` index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FaiRecord {
+    pub name: String,
+    pub length: u64,
+    /// Uncompressed byte offset of the first base.
+    pub offset: u64,
+    pub line_bases: u64,
+    pub line_width: u64,
+}
+
+/// Parse the text of a `.fai` index.
+pub fn parse_fai(fai: &[u8]) -> io::Result<Vec<FaiRecord>> {
+    let text = std::str::from_utf8(fai)
+        .map_err(|_| io_error(ErrorCode::IndexParse, "non-UTF8 .fai"))?;
+    let mut records = Vec::new();
+    for line in text.lines().filter(|l| !l.is_empty()) {
+        let f: Vec<&str> = line.split('\t').collect();
+        if f.len() < 5 {
+            return Err(io_error(ErrorCode::IndexParse, format!("malformed .fai line: {:?}", line)));
+        }
+        records.push(FaiRecord {
+            name: f[0].to_owned(),
+            length: parse_u64(f[1])?,
+            offset: parse_u64(f[2])?,
+            line_bases: parse_u64(f[3])?,
+            line_width: parse_u64(f[4])?,
+        });
+    }
+    Ok(records)
+}
+
+/// Parse a binary `.gzi` index into `(compressed_offset, uncompressed_offset)`
+/// pairs, including the implicit `(0, 0)` entry for the first block.  Both
+/// offsets must be in ascending order, as the blocks are.
+pub fn parse_gzi(gzi: &[u8]) -> io::Result<Vec<(u64, u64)>> {
+    let read_u64 = |pos: usize| -> io::Result<u64> {
+        gzi.get(pos..pos + 8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+            .ok_or_else(|| io_error(ErrorCode::IndexParse, "truncated .gzi"))
+    };
+    let n = read_u64(0)?;
+    // Checked against the bytes present before anything is allocated for it.
+    let n = usize::try_from(n)
+        .ok()
+        .filter(|&n| n <= gzi.len().saturating_sub(8) / 16)
+        .ok_or_else(|| io_error(ErrorCode::IndexParse, format!("truncated .gzi: {} entries declared", n)))?;
+    let mut entries = Vec::with_capacity(n + 1);
+    entries.push((0, 0));
+    for i in 0..n {
+        let entry = (read_u64(8 + i * 16)?, read_u64(16 + i * 16)?);
+        let &(c, u) = entries.last().unwrap();
+        if entry.0 < c || entry.1 < u {
+            return Err(io_error(ErrorCode::IndexParse, format!(".gzi entry {} ({}, {}) comes before the one ahead of it ({}, {})", i + 1, entry.0, entry.1, c, u)));
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Bins of one sequence: bin → (loff, chunks).
+pub type CsiBins = HashMap<u32, (u64, Vec<(u64, u64)>)>;
+
+/// A parsed `.csi` index: binning parameters plus per-sequence bins.
+pub struct CsiIndex {
+    pub min_shift: u32,
+    pub n_lvls: u32,
+    pub names: Vec<String>,
+    /// Column layout from the tabix meta block, if present.
+    pub config: Option<TabixConfig>,
+    pub seqs: Vec<CsiBins>,
+}
+
+impl CsiIndex {
+    /// Parse a BGZF-compressed `.csi` file.  Read leniently, as indexes come
+    /// from whatever tool the user ran and only their contents matter.
+    pub fn from_bgzf(csi_bgzf: &[u8]) -> io::Result<Self> {
+        let mut raw = Vec::new();
+        BgzfReader::lenient(Cursor::new(csi_bgzf)).read_to_end(&mut raw)?;
+        Self::parse(&raw)
+    }
+
+    /// Parse a decompressed `.csi` blob.
+    pub fn parse(csi: &[u8]) -> io::Result<Self> {
+        let mut r = Cursor::new(csi);
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != b"CSI\x01" {
+            return Err(io_error(ErrorCode::IndexParse, "not a CSI index"));
+        }
+        let min_shift = read_i32(&mut r)? as u32;
+        let n_lvls = read_i32(&mut r)? as u32;
+        // Bins are computed with shifts of up to `min_shift + 3 * n_lvls`.
+        if n_lvls > 9 || u64::from(min_shift) + 3 * u64::from(n_lvls) > 63 {
+            return Err(io_error(ErrorCode::IndexParse, format!("unsupported CSI binning: min_shift {} with {} levels", min_shift, n_lvls)));
+        }
+        let l_meta = read_count(&mut r, 1, "meta byte")?;
+        let mut meta = vec![0u8; l_meta];
+        r.read_exact(&mut meta)?;
+        let names = parse_tabix_names(&meta);
+        let config = parse_tabix_config(&meta);
+
+        // Each sequence has at least its bin count, each bin 16 bytes.
+        let n_ref = read_count(&mut r, 4, "sequence")?;
+        if config.is_some() {
+            check_names(&names, n_ref)?;
+        }
+        let mut seqs = Vec::with_capacity(n_ref);
+        for _ in 0..n_ref {
+            let n_bin = read_count(&mut r, 16, "bin")?;
+            let mut bins = HashMap::with_capacity(n_bin);
+            for _ in 0..n_bin {
+                let bin = read_i32(&mut r)? as u32;
+                let loff = read_u64(&mut r)?;
+                let n_chunk = read_count(&mut r, 16, "chunk")?;
+                let mut chunks = Vec::with_capacity(n_chunk);
+                for _ in 0..n_chunk {
+                    chunks.push((read_u64(&mut r)?, read_u64(&mut r)?));
+                }
+                bins.insert(bin, (loff, chunks));
+            }
+            seqs.push(bins);
+        }
+        Ok(CsiIndex { min_shift, n_lvls, names, config, seqs })
+    }
+
+    /// Serialize back to a BGZF-compressed `.csi`, with bins in ascending
+    /// order and `n_no_coor` 0 as [`csi_index`](super::csi_index) writes
+    /// them.  An index without a tabix meta block (`config` is `None`) is
+    /// written without one.
+    pub fn write_bgzf<W: Write>(&self, output: W) -> io::Result<W> {
+        let mut w = IndexWriter::new(output);
+        w.bytes(b"CSI\x01");
+        w.u32(self.min_shift);
+        w.u32(self.n_lvls);
+        match &self.config {
+            Some(config) => w.tabix_meta(config, self.names.iter().map(String::as_str), true),
+            None => w.u32(0),
+        }
+        w.u32(self.seqs.len() as u32);
+        w.end_section()?;
+        for bins in &self.seqs {
+            let mut sorted: Vec<_> = bins.iter().collect();
+            sorted.sort_unstable_by_key(|e| *e.0);
+            w.u32(sorted.len() as u32);
+            for (&bin, (loff, chunks)) in sorted {
+                w.bin(bin, Some(*loff), chunks.len(), chunks.iter().copied());
+            }
+            w.end_section()?;
+        }
+        w.u64(0);
+        w.finish()
+    }
+
+    /// Virtual-offset chunks that may contain records overlapping the
+    /// 0-based half-open interval `[beg, end)` on `seq`, sorted and merged.
+    /// `end` is clamped to the largest coordinate the index can bin, as
+    /// htslib's `hts_itr_query` does; an empty interval has no chunks.
+    pub fn query(&self, seq: &str, beg: u64, end: u64) -> Vec<(u64, u64)> {
+        let end = end.min(1u64 << (self.min_shift + 3 * self.n_lvls));
+        let Some(tid) = self.names.iter().position(|n| n == seq).filter(|_| beg < end) else {
+            return Vec::new();
+        };
+        let bins = &self.seqs[tid];
+        let meta_bin = bin_first(self.n_lvls + 1) + 1;
+
+        let mut chunks: Vec<(u64, u64)> = reg2bins(beg, end, self.min_shift, self.n_lvls)
+            .into_iter()
+            .filter(|b| *b != meta_bin)
+            .filter_map(|b| bins.get(&b))
+            .flat_map(|(_, c)| c.iter().copied())
+            .collect();
+        chunks.sort_unstable();
+
+        let mut merged: Vec<(u64, u64)> = Vec::with_capacity(chunks.len());
+        for (s, e) in chunks {
+            match merged.last_mut() {
+                Some(last) if s <= last.1 => last.1 = last.1.max(e),
+                _ => merged.push((s, e)),
+            }
+        }
+        merged
+    }
+}
+
+/// Chunks in one bin past which a run warns with `dense_index`: a query
+/// over the bin's region reads that many separate ranges.
+pub const DENSE_BIN_CHUNKS: usize = 512;
+
+/// How densely a `.csi` is populated, to spot inputs whose index makes
+/// region queries slow.  The pseudo-bin is not counted as a bin.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IndexDensity {
+    pub sequences: usize,
+    pub bins: usize,
+    pub chunks: usize,
+    /// Records indexed (the pseudo-bins' `n_mapped`).
+    pub records: u64,
+    /// Most bins of one sequence.
+    pub max_bins_per_sequence: usize,
+    /// Sequence, bin and chunk count of the bin with the most chunks.
+    pub fullest_bin: Option<(String, u32, usize)>,
+}
+
+impl IndexDensity {
+    /// `{sequences, bins, chunks, records, max_bins_per_sequence,
+    /// fullest_bin: {sequence, bin, chunks} | null}`.
+    pub fn to_json(&self) -> json::JsonValue {
+        json::object! {
+            sequences: self.sequences,
+            bins: self.bins,
+            chunks: self.chunks,
+            records: self.records,
+            max_bins_per_sequence: self.max_bins_per_sequence,
+            fullest_bin: self.fullest_bin.as_ref().map(|(seq, bin, chunks)| json::object! { sequence: seq.as_str(), bin: *bin, chunks: *chunks }),
+        }
+    }
+}
+
+impl CsiIndex {
+    /// Bin and chunk counts of the index.
+    pub fn density(&self) -> IndexDensity {
+        let meta_bin = bin_first(self.n_lvls + 1) + 1;
+        let mut density = IndexDensity { sequences: self.seqs.len(), ..Default::default() };
+        for (name, bins) in self.names.iter().zip(&self.seqs) {
+            if let Some((_, pseudo)) = bins.get(&meta_bin) {
+                density.records += pseudo.get(1).map_or(0, |c| c.0);
+            }
+            let regular = bins.iter().filter(|(&bin, _)| bin != meta_bin);
+            let mut n_bins = 0;
+            for (&bin, (_, chunks)) in regular {
+                n_bins += 1;
+                density.chunks += chunks.len();
+                // Ties go to the first sequence and its lowest bin.
+                let fuller = |f: &(String, u32, usize)| chunks.len() > f.2 || (chunks.len() == f.2 && f.0 == *name && bin < f.1);
+                if density.fullest_bin.as_ref().is_none_or(fuller) {
+                    density.fullest_bin = Some((name.clone(), bin, chunks.len()));
+                }
+            }
+            density.bins += n_bins;
+            density.max_bins_per_sequence = density.max_bins_per_sequence.max(n_bins);
+        }
+        density
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Region planning
+// ---------------------------------------------------------------------------
+
+/// A byte range of a BGZF file that must be fetched to serve a query.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockRange {
+    /// Compressed offset of the first block to fetch.
+    pub cstart: u64,
+    /// Compressed offset one past the last byte to fetch; `None` = to EOF.
+    pub cend: Option<u64>,
+    /// Uncompressed offset corresponding to `cstart`.
+    pub ustart: u64,
+}
+
+/// Uncompressed byte range `[u0, u1)` of the bases `[beg, end)` (0-based) of
+/// a FASTA record, including any newlines in between.  A record with bases
+/// but none per line, or whose offsets overflow, fails with
+/// [`ErrorCode::IndexParse`].
+pub fn fai_byte_range(rec: &FaiRecord, beg: u64, end: u64) -> io::Result<(u64, u64)> {
+    let end = end.min(rec.length);
+    let beg = beg.min(end);
+    if rec.line_bases == 0 {
+        if rec.length > 0 {
+            return Err(io_error(ErrorCode::IndexParse, format!("sequence {:?} has {} bases but 0 bases per line in the .fai", rec.name, rec.length)));
+        }
+        return Ok((rec.offset, rec.offset));
+    }
+    let pos = |p: u64| {
+        (p / rec.line_bases)
+            .checked_mul(rec.line_width)
+            .and_then(|lines| lines.checked_add(rec.offset))
+            .and_then(|u| u.checked_add(p % rec.line_bases))
+            .ok_or_else(|| io_error(ErrorCode::IndexParse, format!("offsets of sequence {:?} in the .fai overflow", rec.name)))
+    };
+    Ok((pos(beg)?, pos(end)?))
+}
+
+/// Blocks covering the uncompressed range `[u0, u1)`, located via the `.gzi`.
+pub fn gzi_block_range(gzi: &[(u64, u64)], u0: u64, u1: u64) -> BlockRange {
+    // Last block starting at or before an offset.
+    let block_of = |u: u64| gzi.partition_point(|&(_, ua)| ua <= u).saturating_sub(1);
+    let first = block_of(u0);
+    let last = block_of(u1.saturating_sub(1).max(u0));
+    BlockRange {
+        cstart: gzi[first].0,
+        cend: gzi.get(last + 1).map(|&(ca, _)| ca),
+        ustart: gzi[first].1,
+    }
+}
+
+/// Extract bases `[beg, end)` of `rec` from `blocks`, the compressed bytes
+/// fetched for `range`.  Newlines are removed.
+pub fn extract_fasta_region(blocks: &[u8], range: &BlockRange, rec: &FaiRecord, beg: u64, end: u64) -> io::Result<String> {
+    let (u0, u1) = fai_byte_range(rec, beg, end)?;
+    let mut reader = BgzfReader::new(Cursor::new(blocks));
+    io::copy(&mut (&mut reader).take(u0 - range.ustart), &mut io::sink())?;
+    let mut raw = Vec::with_capacity(limits::to_usize(u1 - u0, "the region")?);
+    (&mut reader).take(u1 - u0).read_to_end(&mut raw)?;
+    raw.retain(|b| !b.is_ascii_whitespace());
+    String::from_utf8(raw).map_err(|_| io_error(ErrorCode::FastaParse, "non-UTF8 sequence data"))
+}
+
+/// Cross-check a `.fai` against its `.gzi` and BGZF FASTA: every record's
+/// offset, translated through the `.gzi`, must land just after its own
+/// `>name` header line, and the records must follow each other in file
+/// order.  Catches offset accounting bugs that would otherwise only show up
+/// as shifted sequence in a genome browser.
+pub fn verify_fasta_index(bgzf: &[u8], fai: &[u8], gzi: &[u8]) -> io::Result<()> {
+    let (records, gzi) = (parse_fai(fai)?, parse_gzi(gzi)?);
+    let invalid = |msg: String| io_error(ErrorCode::IndexMismatch, msg);
+    for &(caddr, _) in &gzi {
+        let magic = usize::try_from(caddr).ok().and_then(|c| bgzf.get(c..c.checked_add(4)?));
+        if magic != Some(&[0x1f, 0x8b, 8, 4][..]) {
+            return Err(invalid(format!(".gzi entry at compressed offset {} is not a BGZF block start", caddr)));
+        }
+    }
+
+    // Sequence data ends where the next header starts (after its newline).
+    let mut prev_end = 0;
+    for rec in &records {
+        if rec.offset < prev_end {
+            return Err(invalid(format!("sequence {:?}: offset {} overlaps the previous sequence", rec.name, rec.offset)));
+        }
+        let range = gzi_block_range(&gzi, prev_end, rec.offset);
+        let blocks = usize::try_from(range.cstart).ok().and_then(|c| bgzf.get(c..));
+        let blocks = blocks.ok_or_else(|| invalid(format!("sequence {:?}: offset {} is past the end of the file", rec.name, rec.offset)))?;
+        let mut reader = BgzfReader::new(Cursor::new(blocks));
+        // A sorted `.gzi` starts the range at or before `prev_end`.
+        io::copy(&mut (&mut reader).take(prev_end.saturating_sub(range.ustart)), &mut io::sink())?;
+        let mut between = Vec::new();
+        (&mut reader).take(rec.offset - prev_end).read_to_end(&mut between)?;
+
+        let header = between
+            .strip_suffix(b"\n")
+            .map(|b| b.strip_suffix(b"\r").unwrap_or(b))
+            .and_then(|b| b.rsplit(|&c| c == b'\n').next())
+            .and_then(|line| line.strip_prefix(b">"));
+        let name_ok = header.is_some_and(|h| {
+            h.starts_with(rec.name.as_bytes()) && h.get(rec.name.len()).is_none_or(|&c| c == b' ' || c == b'\t')
+        });
+        if !name_ok {
+            return Err(invalid(format!(
+                "sequence {:?}: offset {} does not follow its header line",
+                rec.name, rec.offset
+            )));
+        }
+        prev_end = fai_byte_range(rec, 0, rec.length)?.1;
+    }
+    Ok(())
+}
+
+/// Compressed byte range to fetch for one CSI chunk.  The last block's size is
+/// unknown, so a full maximum-size block is requested past its start.
+pub fn chunk_block_range(chunk: (u64, u64)) -> (u64, u64) {
+    (chunk.0 >> 16, (chunk.1 >> 16) + 0x10000)
+}
+
+/// Read the GFF lines of one CSI chunk out of `blocks`, the bytes fetched
+/// starting at compressed offset `cstart`, keeping records that overlap
+/// `[beg, end)` (0-based) on `seq`.
+pub fn extract_gff_chunk(
+    blocks: &[u8],
+    cstart: u64,
+    chunk: (u64, u64),
+    seq: &str,
+    beg: u64,
+    end: u64,
+    out: &mut Vec<String>,
+) -> io::Result<()> {
+    let base = cstart << 16;
+    let mut reader = BgzfReader::new(Cursor::new(blocks));
+    // Position the reader at the chunk start.
+    io::copy(&mut (&mut reader).take(chunk.0 & 0xffff), &mut io::sink())?;
+
+    let mut line = Vec::new();
+    loop {
+        if reader.virtual_offset() + base >= chunk.1 {
+            break;
+        }
+        line.clear();
+        let (n, _) = reader.read_line(&mut line)?;
+        if n == 0 {
+            break;
+        }
+        let text = std::str::from_utf8(&line)
+            .map_err(|_| io_error(ErrorCode::GffParse, "non-UTF8 GFF line"))?
+            .trim_end_matches(['\n', '\r']);
+        let f: Vec<&str> = text.splitn(6, '\t').collect();
+        if f.len() < 5 || f[0] != seq {
+            continue;
+        }
+        let (Ok(s), Ok(e)) = (f[3].parse::<u64>(), f[4].parse::<u64>()) else {
+            continue;
+        };
+        if s.saturating_sub(1) < end && e > beg {
+            out.push(text.to_owned());
+        }
+    }
+    Ok(())
+}
+
+/// The line starting at virtual offset `voffset` of a BGZF file.
+pub fn record_at(bgzf: &[u8], voffset: u64) -> io::Result<String> {
+    let block = usize::try_from(voffset >> 16).ok().and_then(|c| bgzf.get(c..))
+        .ok_or_else(|| io_error(ErrorCode::BgzfTruncated, "virtual offset past end of file"))?;
+    let mut reader = BgzfReader::new(Cursor::new(block));
+    io::copy(&mut (&mut reader).take(voffset & 0xffff), &mut io::sink())?;
+    let mut line = Vec::new();
+    reader.read_line(&mut line)?;
+    let text = String::from_utf8(line).map_err(|_| io_error(ErrorCode::GffParse, "non-UTF8 line"))?;
+    Ok(text.trim_end_matches(['\n', '\r']).to_owned())
+}
+
+// ---------------------------------------------------------------------------
+// Helpers
+// ---------------------------------------------------------------------------
+
+/// First bin number at level `l`: 0, 1, 9, 73, 585, 4681, … (htslib
+/// `hts_bin_first`).
+pub(super) fn bin_first(l: u32) -> u32 {
+    ((1u32 << (3 * l)) - 1) / 7
+}
+
+/// Smallest bin containing `[beg, end)` (htslib `hts_reg2bin`).  Both the
+/// index writers and the query side use it.  As in htslib, an empty
+/// interval is binned by `end - 1`, which a zero-length record at a window
+/// boundary puts in a coarser bin.
+pub(super) fn reg2bin(beg: u64, end: u64, min_shift: u32, n_lvls: u32) -> u32 {
+    let end = end.saturating_sub(1);
+    let mut s = min_shift;
+    let mut t = bin_first(n_lvls) as u64;
+    for l in (1..=n_lvls).rev() {
+        if beg >> s == end >> s {
+            return (t + (beg >> s)) as u32;
+        }
+        s += 3;
+        t -= 1u64 << (3 * (l - 1));
+    }
+    0
+}
+
+/// All bins overlapping `[beg, end)` (htslib `hts_reg2bins`).
+pub(super) fn reg2bins(beg: u64, end: u64, min_shift: u32, n_lvls: u32) -> Vec<u32> {
+    let end = end.saturating_sub(1).max(beg);
+    let mut bins = Vec::new();
+    let mut s = min_shift + n_lvls * 3;
+    let mut t: u64 = 0;
+    for l in 0..=n_lvls {
+        let b = t + (beg >> s);
+        let e = t + (end >> s);
+        bins.extend((b..=e).map(|x| x as u32));
+        t += 1u64 << (l * 3);
+        s = s.saturating_sub(3);
+    }
+    bins
+}
+
+/// Sequence names from the tabix-style CSI meta block.
+pub(super) fn parse_tabix_names(meta: &[u8]) -> Vec<String> {
+    // 7 i32 fields precede the names blob: preset, col_seq, col_beg, col_end,
+    // meta_char, line_skip, l_nm.
+    if meta.len() < 28 {
+        return Vec::new();
+    }
+    meta[28..]
+        .split(|&b| b == 0)
+        .filter(|n| !n.is_empty())
+        .map(|n| String::from_utf8_lossy(n).into_owned())
+        .collect()
+}
+
+/// Column layout from the tabix-style CSI meta block.
+pub(super) fn parse_tabix_config(meta: &[u8]) -> Option<TabixConfig> {
+    let field = |i: usize| u32::from_le_bytes(meta[i * 4..i * 4 + 4].try_into().unwrap());
+    (meta.len() >= 28).then(|| TabixConfig {
+        col_seq: field(1),
+        col_beg: field(2),
+        col_end: field(3),
+        meta_char: field(4) as u8,
+        line_skip: field(5),
+        zero_based: field(0) & TabixConfig::ZERO_BASED_FLAG != 0,
+    })
+}
+
+pub(super) fn read_i32<R: Read>(r: &mut R) -> io::Result<i32> {
+    let mut b = [0u8; 4];
+    r.read_exact(&mut b)?;
+    Ok(i32::from_le_bytes(b))
+}
+
+/// Reads an `i32` count of items of at least `item_size` bytes each,
+/// rejecting negative counts and counts the rest of `r` cannot hold, so a
+/// corrupt index fails to parse instead of allocating without bound.
+pub(super) fn read_count(r: &mut Cursor<&[u8]>, item_size: u64, what: &str) -> io::Result<usize> {
+    let n = read_i32(r)?;
+    let left = (r.get_ref().len() as u64).saturating_sub(r.position());
+    usize::try_from(n)
+        .ok()
+        .filter(|&n| (n as u64).saturating_mul(item_size) <= left)
+        .ok_or_else(|| io_error(ErrorCode::IndexParse, format!("bad {} count {} with {} bytes left", what, n, left)))
+}
+
+/// Checks that an index with a names block names each of its `n_ref`
+/// sequences.
+pub(super) fn check_names(names: &[String], n_ref: usize) -> io::Result<()> {
+    if names.len() != n_ref {
+        return Err(io_error(ErrorCode::IndexParse, format!("index names {} sequences but has {}", names.len(), n_ref)));
+    }
+    Ok(())
+}
+
+pub(super) fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut b = [0u8; 8];
+    r.read_exact(&mut b)?;
+    Ok(u64::from_le_bytes(b))
+}
+
+fn parse_u64(s: &str) -> io::Result<u64> {
+    s.trim().parse::<u64>()
+        .map_err(|_| io_error(ErrorCode::IndexParse, format!("cannot parse integer: {:?}", s)))
+}
@@ -0,0 +1,210 @@
+//! Replacing the header of a bgzipped, indexed GFF3 in place, like
+//! `tabix -r`.
+//!
+//! The header is the run of `#` lines the file starts with.  Only the
+//! blocks holding it are rewritten: the new header, followed by whatever
+//! data shared the last of those blocks, goes into fresh blocks, and every
+//! block after is copied byte for byte.  The `.csi` or `.tbi` is then
+//! patched rather than rebuilt: a virtual offset into a copied block moves
+//! by the change in compressed size, one into a rewritten block is placed
+//! in the new blocks.  Either way it still addresses the same record.
+
+use std::io::{self, Cursor, Read};
+
+use super::bgzf::{BgzfReader, BgzfWriter, EOF_BLOCK};
+use super::members::{gzip_members, inflate_member, GzipMember};
+use super::query::bin_first;
+use super::bgzf_compress;
+use crate::error::{io_error, ErrorCode};
+
+/// The uncompressed start of a BGZF file, up to the end of the block in
+/// which its header ends.
+struct Head {
+    data: Vec<u8>,
+    /// Bytes of `data` that are header lines.
+    header_len: usize,
+    /// Blocks `data` came from.
+    blocks: usize,
+}
+
+/// Inflate blocks until the first line not starting with `#`.
+fn read_head(bgzf: &[u8], members: &[GzipMember]) -> io::Result<Head> {
+    let mut data = Vec::new();
+    let mut line = 0;
+    for (i, member) in members.iter().enumerate() {
+        let before = data.len();
+        data.extend_from_slice(&inflate_member(member.slice(bgzf))?);
+        while line < data.len() {
+            if data[line] != b'#' {
+                // A header ending on a block boundary leaves that block alone.
+                let blocks = if line == before { data.truncate(before); i } else { i + 1 };
+                return Ok(Head { data, header_len: line, blocks });
+            }
+            match data[line..].iter().position(|&b| b == b'\n') {
+                Some(nl) => line += nl + 1,
+                // The line goes on in the next block.
+                None => break,
+            }
+        }
+    }
+    let header_len = data.len();
+    Ok(Head { data, header_len, blocks: members.len() })
+}
+
+/// The header lines of a bgzipped GFF3: the `#` lines it starts with.
+pub fn gff_header(bgzf: &[u8]) -> io::Result<String> {
+    let head = read_head(bgzf, &gzip_members(bgzf)?)?;
+    String::from_utf8(head.data[..head.header_len].to_vec()).map_err(|_| io_error(ErrorCode::GffEncoding, "header is not valid UTF-8"))
+}
+
+/// Where the virtual offsets of the old file land in the new one.
+struct OffsetMap<'a> {
+    old: &'a [GzipMember],
+    /// Blocks of the rewritten head, without the copied ones.
+    new: Vec<GzipMember>,
+    /// Compressed end of the old and the new head.
+    old_end: u64,
+    new_end: u64,
+    old_header_len: u64,
+    new_header_len: u64,
+}
+
+impl OffsetMap<'_> {
+    fn map(&self, voffset: u64) -> io::Result<u64> {
+        let (coffset, uoffset) = (voffset >> 16, voffset & 0xffff);
+        if coffset >= self.old_end {
+            return Ok((coffset - self.old_end + self.new_end) << 16 | uoffset);
+        }
+        let block = self
+            .old
+            .binary_search_by_key(&coffset, |m| m.compressed_offset)
+            .map_err(|_| io_error(ErrorCode::IndexMismatch, format!("virtual offset {} is not at a block start", voffset)))?;
+        // Offsets into the old header can only mean the start of the data.
+        let u = (self.old[block].uncompressed_offset + uoffset).max(self.old_header_len) - self.old_header_len + self.new_header_len;
+        let i = self.new.partition_point(|m| m.uncompressed_offset + m.uncompressed_len <= u);
+        Ok(match self.new.get(i) {
+            Some(m) => m.compressed_offset << 16 | (u - m.uncompressed_offset),
+            None => self.new_end << 16,
+        })
+    }
+}
+
+/// Decompressed index being walked and patched in place.
+struct Patcher {
+    raw: Vec<u8>,
+    at: usize,
+}
+
+impl Patcher {
+    fn take(&mut self, n: usize) -> io::Result<&mut [u8]> {
+        let at = self.at;
+        self.at = at.checked_add(n).filter(|&end| end <= self.raw.len()).ok_or_else(|| io_error(ErrorCode::IndexParse, "truncated index"))?;
+        Ok(&mut self.raw[at..self.at])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn offset(&mut self, map: &OffsetMap) -> io::Result<()> {
+        let field = self.take(8)?;
+        let mapped = map.map(u64::from_le_bytes((&*field).try_into().unwrap()))?;
+        field.copy_from_slice(&mapped.to_le_bytes());
+        Ok(())
+    }
+
+    /// The bins of one sequence; `loff` for CSI.  The pseudo-bin's first
+    /// chunk is an offset range, its second the record counts.
+    fn bins(&mut self, map: &OffsetMap, meta_bin: u32, loff: bool) -> io::Result<()> {
+        for _ in 0..self.u32()? {
+            let bin = self.u32()?;
+            if loff && bin == meta_bin {
+                self.take(8)?;
+            } else if loff {
+                self.offset(map)?;
+            }
+            let n_chunk = self.u32()?;
+            let mapped = if bin == meta_bin { n_chunk.min(1) } else { n_chunk };
+            for _ in 0..mapped {
+                self.offset(map)?;
+                self.offset(map)?;
+            }
+            // In u64: 16 bytes a chunk can pass a 32-bit usize.
+            let rest = u64::from(n_chunk - mapped).checked_mul(16).and_then(|n| usize::try_from(n).ok());
+            self.take(rest.ok_or_else(|| io_error(ErrorCode::IndexParse, "truncated index"))?)?;
+        }
+        Ok(())
+    }
+
+    fn csi(&mut self, map: &OffsetMap) -> io::Result<()> {
+        self.take(4)?; // min_shift
+        let n_lvls = self.u32()?;
+        if n_lvls > 9 {
+            return Err(io_error(ErrorCode::IndexParse, format!("unsupported CSI depth {}", n_lvls)));
+        }
+        let l_meta = self.u32()? as usize;
+        self.take(l_meta)?;
+        let meta_bin = bin_first(n_lvls + 1) + 1;
+        for _ in 0..self.u32()? {
+            self.bins(map, meta_bin, true)?;
+        }
+        Ok(())
+    }
+
+    fn tbi(&mut self, map: &OffsetMap) -> io::Result<()> {
+        let n_ref = self.u32()?;
+        self.take(24)?; // format, col_seq, col_beg, col_end, meta, skip
+        let l_nm = self.u32()? as usize;
+        self.take(l_nm)?;
+        for _ in 0..n_ref {
+            self.bins(map, bin_first(6) + 1, false)?;
+            for _ in 0..self.u32()? {
+                self.offset(map)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Replace the header of a bgzipped GFF3 (see the module docs) and patch
+/// its `.csi` or `.tbi` to match.  `header` must be empty or `#` lines; a
+/// final newline is added if missing.  Returns the new file and index.
+pub fn reheader_gff(bgzf: &[u8], index: &[u8], header: &str) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    let mut header = header.to_owned();
+    if !header.is_empty() && !header.ends_with('\n') {
+        header.push('\n');
+    }
+    if let Some((n, line)) = header.lines().enumerate().find(|(_, l)| !l.starts_with('#')) {
+        return Err(io_error(ErrorCode::InvalidOption, format!("header line {} does not start with '#': {:?}", n + 1, line)));
+    }
+
+    let members = gzip_members(bgzf)?;
+    let head = read_head(bgzf, &members)?;
+    let old_end = members[..head.blocks].last().map_or(0, |m| m.compressed_offset + m.compressed_len);
+    let mut writer = BgzfWriter::new(Vec::new());
+    io::Write::write_all(&mut writer, header.as_bytes())?;
+    io::Write::write_all(&mut writer, &head.data[head.header_len..])?;
+    let mut out = writer.finish()?;
+    out.truncate(out.len() - EOF_BLOCK.len());
+    let map = OffsetMap {
+        old: &members,
+        new: gzip_members(&out)?,
+        old_end,
+        new_end: out.len() as u64,
+        old_header_len: head.header_len as u64,
+        new_header_len: header.len() as u64,
+    };
+    out.extend_from_slice(&bgzf[old_end as usize..]);
+
+    let mut raw = Vec::new();
+    BgzfReader::lenient(Cursor::new(index)).read_to_end(&mut raw)?;
+    let mut patcher = Patcher { raw, at: 4 };
+    match patcher.raw.get(..4) {
+        Some(b"CSI\x01") => patcher.csi(&map)?,
+        Some(b"TBI\x01") => patcher.tbi(&map)?,
+        _ => return Err(io_error(ErrorCode::IndexParse, "not a .csi or .tbi index")),
+    }
+    let mut new_index = Vec::new();
+    bgzf_compress(Cursor::new(&patcher.raw), &mut new_index)?;
+    Ok((out, new_index))
+}
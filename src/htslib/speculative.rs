@@ -0,0 +1,535 @@
+//! Parallel decompression of plain gzip, single members included, after
+//! rapidgzip (Knespel & Brunst, 2023).
+//!
+//! A plain `gzip genome.fa` is one member, so it cannot be split at member
+//! boundaries the way [`super::inflate_parallel`] splits BGZF.  Its deflate
+//! stream is a sequence of blocks, though, and a block decodes on its own
+//! except for the 32 KiB window of earlier output its back-references may
+//! reach into.  Each worker but the first looks for the first block header
+//! in its share of the file (a non-final block with dynamic Huffman codes
+//! that decodes cleanly) and decodes from there with the window unknown: a
+//! reference into the window is written as a marker naming the window
+//! position, in 16-bit symbols, until [`WINDOW`] symbols pass without a
+//! marker and the rest can be plain bytes.  It stops at the first block or
+//! member starting past its share.
+//!
+//! The chunks are joined in order, each one's markers replaced with the end
+//! of the output before it, once it is known to start where the previous
+//! one stopped.  A gap (a worker that locked onto a false header, or whose
+//! share starts in stored or fixed-code blocks) is decoded from where the
+//! previous chunk stopped with the window known.  Finally every member's
+//! CRC32 and length are checked; [`inflate_speculative`] returns `None`
+//! when anything does not add up, for the caller to decode sequentially.
+
+use std::io::{self, Cursor};
+
+use super::bgzf::read_member_header;
+use super::crc::crc32;
+use super::members::run_workers;
+use crate::limits;
+
+/// How far back a deflate reference reaches.
+const WINDOW: usize = 1 << 15;
+
+/// Symbols from here on are markers: `MARKER + i` stands for byte `i` of
+/// the unknown window before a chunk.
+const MARKER: u16 = 256;
+
+const LEN_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LEN_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+/// Order of the code-length code lengths in a dynamic block header.
+const CL_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Data that does not decode from where it was tried.  No reason is kept:
+/// the sequential decoder the caller falls back to reports it.
+struct Bad;
+
+type Res<T> = Result<T, Bad>;
+
+/// LSB-first bit reader.
+struct Bits<'a> {
+    data: &'a [u8],
+    /// Next byte to load into `buf`.
+    pos: usize,
+    buf: u64,
+    /// Bits in `buf`.
+    n: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn at(data: &'a [u8], bit: u64) -> Res<Self> {
+        let pos = usize::try_from(bit / 8).map_err(|_| Bad)?;
+        if pos > data.len() {
+            return Err(Bad);
+        }
+        let mut bits = Bits { data, pos, buf: 0, n: 0 };
+        bits.consume((bit % 8) as u32)?;
+        Ok(bits)
+    }
+
+    fn refill(&mut self) {
+        while self.n <= 56 {
+            let Some(&b) = self.data.get(self.pos) else {
+                break;
+            };
+            self.buf |= (b as u64) << self.n;
+            self.pos += 1;
+            self.n += 8;
+        }
+    }
+
+    /// The next `k` bits, zero past the end of the data.
+    fn peek(&mut self, k: u32) -> u64 {
+        if self.n < k {
+            self.refill();
+        }
+        self.buf & ((1u64 << k) - 1)
+    }
+
+    fn consume(&mut self, k: u32) -> Res<()> {
+        if self.n < k {
+            self.refill();
+            if self.n < k {
+                return Err(Bad);
+            }
+        }
+        self.buf >>= k;
+        self.n -= k;
+        Ok(())
+    }
+
+    fn bits(&mut self, k: u32) -> Res<u32> {
+        let v = self.peek(k) as u32;
+        self.consume(k)?;
+        Ok(v)
+    }
+
+    /// Skip to the next byte boundary.
+    fn align(&mut self) {
+        let r = self.n % 8;
+        self.buf >>= r;
+        self.n -= r;
+    }
+
+    fn position(&self) -> u64 {
+        self.pos as u64 * 8 - self.n as u64
+    }
+}
+
+/// A canonical Huffman code as a table indexed by the next `bits` input
+/// bits: `symbol << 4 | code length`, 0 where no code matches.
+struct Huffman {
+    table: Vec<u16>,
+    bits: u32,
+}
+
+impl Huffman {
+    /// The code with symbol code `lengths` (0: unused).  Like zlib, refuses
+    /// over-subscribed codes, and incomplete ones but a single 1-bit code;
+    /// `complete` (the code-length code) refuses those too.
+    fn new(lengths: &[u8], complete: bool) -> Res<Self> {
+        let mut count = [0u32; 16];
+        for &l in lengths {
+            count[l as usize] += 1;
+        }
+        count[0] = 0;
+        let max = (1..16).rev().find(|&l| count[l] > 0).unwrap_or(0) as u32;
+        let mut left: i64 = 1;
+        for &n in &count[1..] {
+            left = (left << 1) - n as i64;
+            if left < 0 {
+                return Err(Bad);
+            }
+        }
+        if left > 0 && (complete || max > 1) {
+            return Err(Bad);
+        }
+        let bits = max.max(1);
+        let mut next = [0u32; 16];
+        let mut code = 0;
+        for l in 1..16 {
+            code = (code + count[l - 1]) << 1;
+            next[l] = code;
+        }
+        let mut table = vec![0u16; 1 << bits];
+        for (symbol, &l) in lengths.iter().enumerate().filter(|&(_, &l)| l > 0) {
+            let l = l as u32;
+            let c = next[l as usize];
+            next[l as usize] += 1;
+            let reversed = (c.reverse_bits() >> (32 - l)) as usize;
+            for entry in table.iter_mut().skip(reversed).step_by(1 << l) {
+                *entry = (symbol as u16) << 4 | l as u16;
+            }
+        }
+        Ok(Huffman { table, bits })
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Res<u16> {
+        let entry = self.table[bits.peek(self.bits) as usize];
+        if entry == 0 {
+            return Err(Bad);
+        }
+        bits.consume((entry & 15) as u32)?;
+        Ok(entry >> 4)
+    }
+}
+
+/// The fixed literal/length and distance codes.
+fn fixed_codes() -> Res<(Huffman, Huffman)> {
+    let mut lengths = [8u8; 288];
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    Ok((Huffman::new(&lengths, false)?, Huffman::new(&[5; 32], false)?))
+}
+
+/// The codes of a dynamic block, read from its header after the block type.
+fn dynamic_codes(bits: &mut Bits) -> Res<(Huffman, Huffman)> {
+    let hlit = bits.bits(5)? as usize + 257;
+    let hdist = bits.bits(5)? as usize + 1;
+    let hclen = bits.bits(4)? as usize + 4;
+    if hlit > 286 || hdist > 30 {
+        return Err(Bad);
+    }
+    let mut cl = [0u8; 19];
+    for &i in &CL_ORDER[..hclen] {
+        cl[i] = bits.bits(3)? as u8;
+    }
+    let cl = Huffman::new(&cl, true)?;
+    let n = hlit + hdist;
+    let mut lengths = [0u8; 316];
+    let mut i = 0;
+    while i < n {
+        let (value, repeat) = match cl.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths[..i].last().ok_or(Bad)?, 3 + bits.bits(2)? as usize),
+            17 => (0, 3 + bits.bits(3)? as usize),
+            _ => (0, 11 + bits.bits(7)? as usize),
+        };
+        if i + repeat > n {
+            return Err(Bad);
+        }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+    if lengths[256] == 0 {
+        return Err(Bad);
+    }
+    Ok((Huffman::new(&lengths[..hlit], false)?, Huffman::new(&lengths[hlit..n], false)?))
+}
+
+/// Whether a non-final dynamic block header with a valid code-length code
+/// starts at bit `at`: a cheap filter before trying to decode from there.
+fn plausible_block(data: &[u8], at: u64) -> bool {
+    let header = || -> Res<bool> {
+        let mut bits = Bits::at(data, at)?;
+        // BFINAL 0, BTYPE 2 (dynamic)
+        if bits.bits(3)? != 0b100 || bits.bits(5)? > 29 || bits.bits(5)? > 29 {
+            return Ok(false);
+        }
+        let hclen = bits.bits(4)? as usize + 4;
+        let mut cl = [0u8; 19];
+        for &i in &CL_ORDER[..hclen] {
+            cl[i] = bits.bits(3)? as u8;
+        }
+        Ok(Huffman::new(&cl, true).is_ok())
+    };
+    header().unwrap_or(false)
+}
+
+/// Decoded output of a chunk.  While back-references may reach the unknown
+/// window before it, symbols are 16-bit (`marked`); once [`WINDOW`] of them
+/// pass without a marker, they move to `bytes`, which takes the rest.
+struct Output {
+    marked: Vec<u16>,
+    bytes: Vec<u8>,
+    /// Leading bytes of `bytes` that are a known window, not output.
+    skip: usize,
+    /// Whether output goes to `bytes`.
+    known: bool,
+    /// Symbols since the last marker.
+    clean: usize,
+    /// Where the current member's output starts, once in the chunk:
+    /// references may not reach before it.
+    floor: Option<usize>,
+}
+
+impl Output {
+    fn unknown() -> Self {
+        Output { marked: Vec::new(), bytes: Vec::new(), skip: 0, known: false, clean: 0, floor: None }
+    }
+
+    /// Output following `window`, the bytes before it.
+    fn known(window: &[u8]) -> Self {
+        Output { marked: Vec::new(), bytes: window.to_vec(), skip: window.len(), known: true, clean: 0, floor: Some(0) }
+    }
+
+    fn len(&self) -> usize {
+        self.marked.len() + self.bytes.len()
+    }
+
+    fn new_member(&mut self) {
+        self.floor = Some(self.len());
+    }
+
+    /// Moves to bytes once the last [`WINDOW`] symbols hold no marker.
+    fn settle(&mut self) {
+        if !self.known && self.clean >= WINDOW {
+            let from = self.marked.len() - self.clean;
+            self.bytes = self.marked.drain(from..).map(|s| s as u8).collect();
+            self.known = true;
+        }
+    }
+
+    fn literal(&mut self, b: u8) {
+        if self.known {
+            self.bytes.push(b);
+        } else {
+            self.marked.push(b as u16);
+            self.clean += 1;
+            self.settle();
+        }
+    }
+
+    fn literals(&mut self, bytes: &[u8]) {
+        if self.known {
+            self.bytes.extend_from_slice(bytes);
+        } else {
+            self.marked.extend(bytes.iter().map(|&b| b as u16));
+            self.clean += bytes.len();
+            self.settle();
+        }
+    }
+
+    /// Copy `len` symbols from `distance` back.
+    fn copy(&mut self, distance: usize, len: usize) -> Res<()> {
+        let reach = match self.floor {
+            Some(floor) => self.len() - floor,
+            None => self.len() + WINDOW,
+        };
+        if distance > reach {
+            return Err(Bad);
+        }
+        if self.known {
+            let start = self.bytes.len().checked_sub(distance).ok_or(Bad)?;
+            if distance >= len {
+                self.bytes.extend_from_within(start..start + len);
+            } else {
+                for i in start..start + len {
+                    let b = self.bytes[i];
+                    self.bytes.push(b);
+                }
+            }
+        } else {
+            for _ in 0..len {
+                let at = self.marked.len();
+                let symbol = match at.checked_sub(distance) {
+                    Some(i) => self.marked[i],
+                    None => MARKER + (WINDOW + at - distance) as u16,
+                };
+                self.marked.push(symbol);
+                self.clean = if symbol >= MARKER { 0 } else { self.clean + 1 };
+            }
+            self.settle();
+        }
+        Ok(())
+    }
+
+    /// Append the output to `out`, markers resolved against the bytes
+    /// already there; `false` if a marker reaches before its start.
+    fn append_to(&self, out: &mut Vec<u8>) -> io::Result<bool> {
+        let base = out.len();
+        if self.marked.iter().any(|&s| s >= MARKER && base + ((s - MARKER) as usize) < WINDOW) {
+            return Ok(false);
+        }
+        limits::try_reserve(out, (self.len() - self.skip) as u64, "the decompressed input")?;
+        for &s in &self.marked {
+            let b = if s < MARKER { s as u8 } else { out[base + (s - MARKER) as usize - WINDOW] };
+            out.push(b);
+        }
+        out.extend_from_slice(&self.bytes[self.skip..]);
+        Ok(true)
+    }
+}
+
+/// What starts where decoding starts or stops.
+#[derive(Clone, Copy, PartialEq)]
+enum Unit {
+    Member,
+    Block,
+}
+
+/// One stretch of decoded blocks.
+struct Chunk {
+    /// Bit position of its first unit.
+    start: u64,
+    unit: Unit,
+    /// Bit position where it stopped, and what starts there (`None`: the
+    /// end of the data).
+    end: u64,
+    next: Option<Unit>,
+    output: Output,
+    /// Members ending in the chunk: output length at their end and the
+    /// CRC32 and length of their trailer.
+    trailers: Vec<(usize, u32, u32)>,
+}
+
+/// Decode from bit `start`, where `unit` starts, up to the first unit
+/// starting at or after bit `stop`, or the end of the data.
+fn decode(data: &[u8], start: u64, unit: Unit, mut output: Output, stop: u64) -> Res<Chunk> {
+    let (fixed_lit, fixed_dist) = fixed_codes()?;
+    let mut bits = Bits::at(data, start)?;
+    let mut next = unit;
+    let mut trailers = Vec::new();
+    loop {
+        let here = bits.position();
+        let at_end = next == Unit::Member && here / 8 == data.len() as u64;
+        if at_end || here >= stop {
+            let next = (!at_end).then_some(next);
+            return Ok(Chunk { start, unit, end: here, next, output, trailers });
+        }
+        if next == Unit::Member {
+            let from = (here / 8) as usize;
+            let header = read_member_header(&mut Cursor::new(&data[from..])).ok().flatten().ok_or(Bad)?;
+            bits = Bits::at(data, (from + header.len) as u64 * 8)?;
+            output.new_member();
+            next = Unit::Block;
+            continue;
+        }
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => {
+                bits.align();
+                let len = bits.bits(16)?;
+                if bits.bits(16)? != !len & 0xffff {
+                    return Err(Bad);
+                }
+                let from = (bits.position() / 8) as usize;
+                output.literals(data.get(from..from + len as usize).ok_or(Bad)?);
+                bits = Bits::at(data, (from as u64 + len as u64) * 8)?;
+            }
+            1 => inflate_block(&mut bits, &fixed_lit, &fixed_dist, &mut output)?,
+            2 => {
+                let (lit, dist) = dynamic_codes(&mut bits)?;
+                inflate_block(&mut bits, &lit, &dist, &mut output)?;
+            }
+            _ => return Err(Bad),
+        }
+        if last {
+            bits.align();
+            let from = (bits.position() / 8) as usize;
+            let trailer = data.get(from..from + 8).ok_or(Bad)?;
+            let word = |i: usize| u32::from_le_bytes([trailer[i], trailer[i + 1], trailer[i + 2], trailer[i + 3]]);
+            trailers.push((output.len() - output.skip, word(0), word(4)));
+            bits = Bits::at(data, (from as u64 + 8) * 8)?;
+            next = Unit::Member;
+        }
+    }
+}
+
+/// The symbols of one Huffman-coded block, up to its end-of-block code.
+fn inflate_block(bits: &mut Bits, lit: &Huffman, dist: &Huffman, output: &mut Output) -> Res<()> {
+    loop {
+        let symbol = lit.decode(bits)? as usize;
+        if symbol < 256 {
+            output.literal(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+        let i = symbol - 257;
+        if i >= LEN_BASE.len() {
+            return Err(Bad);
+        }
+        let len = LEN_BASE[i] as usize + bits.bits(LEN_EXTRA[i] as u32)? as usize;
+        let d = dist.decode(bits)? as usize;
+        if d >= DIST_BASE.len() {
+            return Err(Bad);
+        }
+        let distance = DIST_BASE[d] as usize + bits.bits(DIST_EXTRA[d] as u32)? as usize;
+        output.copy(distance, len)?;
+    }
+}
+
+/// A worker's chunk: from the first plausible block header in `from..to`
+/// that decodes, up to `to`.
+fn decode_from_block(data: &[u8], from: u64, to: u64) -> Option<Chunk> {
+    (from..to).filter(|&at| plausible_block(data, at)).find_map(|at| decode(data, at, Unit::Block, Output::unknown(), to).ok())
+}
+
+/// Append a chunk to `out`, its members' trailers to `trailers`; `false`
+/// if it cannot follow what is there.
+fn append(out: &mut Vec<u8>, trailers: &mut Vec<(usize, u32, u32)>, chunk: &Chunk) -> io::Result<bool> {
+    let base = out.len();
+    if !chunk.output.append_to(out)? {
+        return Ok(false);
+    }
+    trailers.extend(chunk.trailers.iter().map(|&(end, crc, len)| (base + end, crc, len)));
+    Ok(true)
+}
+
+/// The last [`WINDOW`] bytes of `out`.
+fn window(out: &[u8]) -> &[u8] {
+    &out[out.len().saturating_sub(WINDOW)..]
+}
+
+/// Decompress gzip `data` (one member or several) as `workers` chunks of
+/// roughly equal compressed size, on threads as [`super::inflate_parallel`]
+/// runs them: the output and how many chunks were used as their workers
+/// decoded them.  `None` if the data does not decode or a member's CRC32
+/// or length does not match; the caller decodes it sequentially then, to
+/// report the error where it is.
+pub fn inflate_speculative(data: &[u8], workers: usize) -> io::Result<Option<(Vec<u8>, usize)>> {
+    let workers = workers.clamp(1, data.len().max(1)) as u64;
+    let total = data.len() as u64 * 8;
+    let ranges: Vec<(u64, u64)> = (0..workers).map(|i| (total * i / workers, total * (i + 1) / workers)).collect();
+    let chunks = run_workers(&ranges, |&(from, to)| match from {
+        0 => decode(data, 0, Unit::Member, Output::known(&[]), to).ok(),
+        _ => decode_from_block(data, from, to),
+    });
+
+    let mut out = Vec::new();
+    let mut trailers = Vec::new();
+    let (mut pos, mut next, mut used) = (0, Some(Unit::Member), 0);
+    for chunk in chunks.into_iter().flatten() {
+        if let Some(unit) = next.filter(|_| chunk.start > pos) {
+            let Ok(gap) = decode(data, pos, unit, Output::known(window(&out)), chunk.start) else {
+                return Ok(None);
+            };
+            if !append(&mut out, &mut trailers, &gap)? {
+                return Ok(None);
+            }
+            (pos, next) = (gap.end, gap.next);
+        }
+        // A chunk that does not start where the last one stopped began at a
+        // false header, or inside a stretch already decoded.
+        if chunk.start == pos && next == Some(chunk.unit) {
+            if !append(&mut out, &mut trailers, &chunk)? {
+                return Ok(None);
+            }
+            (pos, next, used) = (chunk.end, chunk.next, used + 1);
+        }
+    }
+    if let Some(unit) = next {
+        let Ok(rest) = decode(data, pos, unit, Output::known(window(&out)), u64::MAX) else {
+            return Ok(None);
+        };
+        if !append(&mut out, &mut trailers, &rest)? {
+            return Ok(None);
+        }
+    }
+
+    let mut start = 0;
+    for &(end, crc, len) in &trailers {
+        if crc32(&out[start..end]) != crc || (end - start) as u32 != len {
+            return Ok(None);
+        }
+        start = end;
+    }
+    Ok((start == out.len()).then_some((out, used)))
+}
@@ -1,6 +1,13 @@
 use std::collections::HashMap;
 use std::io::{self, Read, Write};
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{io_error, ErrorCode};
+use crate::gff::record::column_ranges;
 use super::bgzf::{BgzfReader, BgzfWriter};
+use super::pool::PooledBuf;
+use super::query::{bin_first, reg2bin};
 
 // ---------------------------------------------------------------------------
 // CSI format constants (tabix -C -p gff, htslib default)
@@ -8,27 +15,22 @@ use super::bgzf::{BgzfReader, BgzfWriter};
 const MIN_SHIFT: u32 = 14;
 /// Number of index levels used by `tabix -C` for GFF (= 8).
 /// Covers coordinates up to 2^(14+3*8) = 2^38 ≈ 274 GB.
-const N_LVLS: u32 = 8;
-/// Number of regular bins: hts_bin_first(N_LVLS+1) = ((1<<27)-1)/7 = 19173961.
-const N_BINS: u32 = 19_173_961;
-/// Pseudo-bin for per-sequence metadata (N_BINS + 1).
-const META_BIN: u32 = 19_173_962;
+pub const N_LVLS: u32 = 8;
+/// Most CSI levels we grow to for larger coordinates: 2^(14+3*9) = 2^41.
+/// Bin numbers of a tenth level would not fit in 32 bits.
+const MAX_N_LVLS: u32 = 9;
+/// Number of index levels of the TBI format (fixed by the format).
+/// Covers coordinates up to 2^(14+3*5) = 2^29 ≈ 537 Mb.
+const TBI_N_LVLS: u32 = 5;
 
 /// Minimum compressed-byte span for a bin to be kept at its level rather than
 /// merged into its parent (= HTS_MIN_MARKER_DIST = 0x10000 = one BGZF block).
-const HTS_MIN_MARKER_DIST: u64 = 0x10000;
+pub(super) const HTS_MIN_MARKER_DIST: u64 = 0x10000;
 
 // ---------------------------------------------------------------------------
 // Binning helpers
 // ---------------------------------------------------------------------------
 
-/// First bin number at level l.
-/// l=0 → 0, l=1 → 1, l=2 → 9, l=3 → 73, l=4 → 585, l=5 → 4681,
-/// l=6 → 37449, l=7 → 299593, l=8 → 2396745, l=9 → 19173961.
-fn hts_bin_first(l: u32) -> u32 {
-    ((1u32 << (3 * l)) - 1) / 7
-}
-
 /// Parent bin of b (htslib: (b-1) >> 3).
 fn hts_bin_parent(b: u32) -> u32 {
     (b - 1) >> 3
@@ -45,50 +47,45 @@ fn hts_bin_level(b: u32) -> u32 {
     level
 }
 
-/// Bottom linear-index slot covered by bin b (with N_LVLS levels).
-fn hts_bin_bot(b: u32) -> u64 {
+/// Number of regular bins with n_lvls levels: bin_first(n_lvls+1)
+/// (19173961 for CSI's 8 levels, 37449 for TBI's 5).
+fn n_bins(n_lvls: u32) -> u32 {
+    bin_first(n_lvls + 1)
+}
+
+/// Pseudo-bin for per-sequence metadata (n_bins + 1).
+fn meta_bin(n_lvls: u32) -> u32 {
+    n_bins(n_lvls) + 1
+}
+
+/// Bin `bin` of an index with `from` levels, renumbered for one with `to`
+/// levels: the added levels are coarser, so the bin keeps its offset within
+/// its level and moves `to - from` levels down.
+pub(super) fn deepen_bin(bin: u32, from: u32, to: u32) -> u32 {
+    let level = hts_bin_level(bin);
+    bin - bin_first(level) + bin_first(level + to - from)
+}
+
+/// Bottom linear-index slot covered by bin b (with n_lvls levels).
+fn hts_bin_bot(b: u32, n_lvls: u32) -> u64 {
     let level = hts_bin_level(b);
-    let offset = b - hts_bin_first(level);
-    (offset as u64) << ((N_LVLS - level) * 3)
+    let offset = b - bin_first(level);
+    (offset as u64) << ((n_lvls - level) * 3)
 }
 
 /// Compute loff for a bin: lidx[hts_bin_bot(bin)], falling back to the last
 /// non-zero lidx entry (mirrors htslib update_loff).
-fn compute_loff(bin: u32, lidx: &[u64]) -> u64 {
-    if bin >= N_BINS {
+fn compute_loff(bin: u32, lidx: &[u64], n_lvls: u32) -> u64 {
+    if bin >= n_bins(n_lvls) {
         return 0;
     }
     // offset0 = last non-zero lidx entry (fallback when bot slot is 0)
     let offset0 = lidx.iter().rev().find(|&&v| v != 0).copied().unwrap_or(0);
-    let bot = hts_bin_bot(bin) as usize;
+    let bot = hts_bin_bot(bin, n_lvls) as usize;
     let val = if bot < lidx.len() { lidx[bot] } else { 0 };
     if val != 0 { val } else { offset0 }
 }
 
-// ---------------------------------------------------------------------------
-// Binning scheme: old BAM / htslib reg2bin — finest-level first
-// ---------------------------------------------------------------------------
-
-/// Compute the bin number for a 0-based half-open interval [beg, end).
-///
-/// Uses the same finest-first algorithm as TBI / htslib `hts_reg2bin` (old BAM
-/// style), extended to N_LVLS=8 levels.  A feature is placed in the finest bin
-/// whose span fully contains [beg, end-1].
-fn reg2bin(beg: u64, end: u64) -> u32 {
-    let e = end.saturating_sub(1);
-    let mut s: u32 = MIN_SHIFT; // 14
-    // t starts at hts_bin_first(N_LVLS+1) = hts_bin_first(9) = 19173961
-    let mut t: u64 = ((1u64 << (3 * N_LVLS + 3)) - 1) / 7;
-    for l in (1..=N_LVLS).rev() {
-        t -= 1u64 << (3 * l);
-        if (beg >> s) == (e >> s) {
-            return (t + (beg >> s)) as u32;
-        }
-        s += 3;
-    }
-    0
-}
-
 // ---------------------------------------------------------------------------
 // Index data structures
 // ---------------------------------------------------------------------------
@@ -99,10 +96,118 @@ struct Chunk {
     end: u64,
 }
 
+/// Compressed-byte span of `chunks`, from the first start to the last end.
+fn span<'a>(chunks: impl Iterator<Item = &'a Chunk>) -> u64 {
+    let (first_start, last_end) = chunks.fold((u64::MAX, 0), |(s, e), c| (s.min(c.start), e.max(c.end)));
+    (last_end >> 16).saturating_sub(first_start.min(last_end) >> 16)
+}
+
+/// The chunks of one bin, the first inline: once block-adjacent chunks are
+/// merged as records arrive, most bins hold a single chunk and need no
+/// allocation of their own.
+struct Chunks {
+    first: Chunk,
+    rest: Vec<Chunk>,
+}
+
+impl Chunks {
+    fn new(first: Chunk) -> Self {
+        Chunks { first, rest: Vec::new() }
+    }
+
+    fn len(&self) -> usize {
+        1 + self.rest.len()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Chunk> {
+        std::iter::once(&self.first).chain(&self.rest)
+    }
+
+    /// Add a chunk that starts after every chunk so far.  Merging it into
+    /// the last one when block-adjacent gives what
+    /// [`merge_chunks_block_adjacent`] would later, without one chunk per
+    /// record in the meantime.
+    fn push(&mut self, chunk: Chunk) {
+        let last = self.rest.last_mut().unwrap_or(&mut self.first);
+        if chunk.start <= last.end.saturating_add(HTS_MIN_MARKER_DIST) {
+            last.end = last.end.max(chunk.end);
+        } else {
+            self.rest.push(chunk);
+        }
+    }
+
+    fn extend(&mut self, other: Chunks) {
+        self.rest.push(other.first);
+        self.rest.extend(other.rest);
+    }
+
+    fn span(&self) -> u64 {
+        span(self.iter())
+    }
+
+    /// Sort and merge block-adjacent chunks.
+    fn merge_block_adjacent(&mut self) {
+        if self.rest.is_empty() {
+            return;
+        }
+        let mut all: Vec<Chunk> = self.iter().cloned().collect();
+        merge_chunks_block_adjacent(&mut all);
+        let mut all = all.into_iter();
+        self.first = all.next().expect("merging keeps at least one chunk");
+        self.rest = all.collect();
+    }
+}
+
+/// The bins of one sequence as one sorted vector per level.  Records come
+/// sorted by start, so the bins each level receives come in ascending
+/// order: a record's bin is its level's last or a new one appended after
+/// it, and the levels concatenated are the bins in the order written.
+struct Bins {
+    levels: Vec<Vec<(u32, Chunks)>>,
+}
+
+impl Bins {
+    fn new(n_lvls: u32) -> Self {
+        Bins { levels: (0..=n_lvls).map(|_| Vec::new()).collect() }
+    }
+
+    fn len(&self) -> usize {
+        self.levels.iter().map(Vec::len).sum()
+    }
+
+    /// Add a chunk to `bin`, starting after every chunk added so far.
+    fn push(&mut self, bin: u32, chunk: Chunk) {
+        let level = &mut self.levels[hts_bin_level(bin) as usize];
+        match level.last_mut() {
+            Some((last, chunks)) if *last == bin => chunks.push(chunk),
+            _ => level.push((bin, Chunks::new(chunk))),
+        }
+    }
+
+    /// Renumber the bins for `to` levels instead of `from`: the added
+    /// levels are the coarsest, so existing ones move down as a whole.
+    fn deepen(&mut self, from: u32, to: u32) {
+        for level in &mut self.levels {
+            for (bin, _) in level.iter_mut() {
+                *bin = deepen_bin(*bin, from, to);
+            }
+        }
+        self.levels.splice(0..0, (from..to).map(|_| Vec::new()));
+    }
+
+    /// All bins, ascending.
+    fn iter(&self) -> impl Iterator<Item = &(u32, Chunks)> {
+        self.levels.iter().flatten()
+    }
+}
+
 struct SeqIdx {
     name: String,
-    bins: HashMap<u32, Vec<Chunk>>,
+    bins: Bins,
     lidx: Vec<u64>,
+    /// htslib-style linear index for TBI: first voff per 16 kb window,
+    /// `u64::MAX` where no record has been seen yet.
+    tbi_lidx: Vec<u64>,
     /// Smallest virtual offset of any feature start in this sequence.
     min_voff: u64,
     /// Largest virtual offset of any feature end in this sequence.
@@ -112,11 +217,12 @@ struct SeqIdx {
 }
 
 impl SeqIdx {
-    fn new(name: String) -> Self {
+    fn new(name: String, n_lvls: u32) -> Self {
         SeqIdx {
             name,
-            bins: HashMap::new(),
+            bins: Bins::new(n_lvls),
             lidx: Vec::new(),
+            tbi_lidx: Vec::new(),
             min_voff: u64::MAX,
             max_voff: 0,
             n_mapped: 0,
@@ -131,7 +237,14 @@ impl SeqIdx {
             self.max_voff = chunk.end;
         }
         self.n_mapped += 1;
-        self.bins.entry(bin).or_default().push(chunk);
+        self.bins.push(bin, chunk);
+    }
+
+    /// The pseudo-bin: the sequence's virtual-offset range, then the
+    /// mapped and unmapped record counts.
+    fn pseudo_bin(&self) -> Chunks {
+        let min_voff = if self.min_voff == u64::MAX { 0 } else { self.min_voff };
+        Chunks { first: Chunk { start: min_voff, end: self.max_voff }, rest: vec![Chunk { start: self.n_mapped, end: 0 }] }
     }
 
     fn update_lidx(&mut self, beg: u64, end: u64, voff: u64) {
@@ -148,6 +261,28 @@ impl SeqIdx {
                 self.lidx[i] = voff;
             }
         }
+        if win_end >= self.tbi_lidx.len() {
+            self.tbi_lidx.resize(win_end + 1, u64::MAX);
+        }
+        for slot in &mut self.tbi_lidx[win_beg..=win_end] {
+            if *slot == u64::MAX {
+                *slot = voff;
+            }
+        }
+    }
+
+    /// Finished TBI linear index (htslib update_loff): leading empty windows
+    /// take the first record's offset, later ones the previous window's.
+    fn tbi_linear_index(&self) -> Vec<u64> {
+        let mut out = self.tbi_lidx.clone();
+        let mut prev = if self.min_voff == u64::MAX { 0 } else { self.min_voff };
+        for slot in &mut out {
+            if *slot == u64::MAX {
+                *slot = prev;
+            }
+            prev = *slot;
+        }
+        out
     }
 }
 
@@ -176,56 +311,86 @@ fn merge_chunks_block_adjacent(chunks: &mut Vec<Chunk>) {
     *chunks = out;
 }
 
+/// How [`compress_binning`] rolls sparse fine bins into coarser ones.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinRollup {
+    /// As htslib: a bin spanning less than one BGZF block moves into its
+    /// parent only when the parent already holds records.
+    Htslib = "htslib",
+    /// Also create the parent when two or more such siblings, together
+    /// still within one block, would move into it.  Fewer bins, so a
+    /// smaller index, while a query still reads at most about one block
+    /// more than with `Htslib`.
+    Aggressive = "aggressive",
+}
+
+/// Roll `orphans`, sibling bins too small to keep whose parent does not
+/// exist, into a new parent appended to `parents` when `rollup` allows it,
+/// or else move them to `kept`.
+fn adopt_orphans(orphans: &mut Vec<(u32, Chunks)>, kept: &mut Vec<(u32, Chunks)>, parents: &mut Vec<(u32, Chunks)>, rollup: BinRollup) {
+    let Some(&(first, _)) = orphans.first() else { return };
+    let together = span(orphans.iter().flat_map(|(_, chunks)| chunks.iter()));
+    if rollup != BinRollup::Aggressive || orphans.len() < 2 || together >= HTS_MIN_MARKER_DIST {
+        kept.append(orphans);
+        return;
+    }
+    let mut group = orphans.drain(..).map(|(_, chunks)| chunks);
+    let mut combined = group.next().expect("two or more orphans");
+    group.for_each(|chunks| combined.extend(chunks));
+    parents.push((hts_bin_parent(first), combined));
+}
+
 /// Replicate htslib compress_binning:
 ///
-/// 1. First pass (level N_LVLS → 1): roll fine bins into their parent when the
+/// 1. First pass (level n_lvls → 1): roll fine bins into their parent when the
 ///    bin's compressed-byte span is less than HTS_MIN_MARKER_DIST AND the parent
-///    bin already exists in the map.
+///    bin already exists.  With [`BinRollup::Aggressive`], bins whose parent
+///    does not exist are then rolled into a new parent when at least two
+///    siblings qualify and their combined span is still below
+///    HTS_MIN_MARKER_DIST; the new parent is a candidate at the next level.
 /// 2. Second pass: merge block-adjacent chunks within every remaining bin.
-fn compress_binning(bins: &mut HashMap<u32, Vec<Chunk>>) {
-    // Sort all existing bins' chunks before starting.
-    for chunks in bins.values_mut() {
-        chunks.sort_unstable_by_key(|c| c.start);
-    }
-
+///
+/// Parents are in ascending order like their children, so each level is
+/// one merge-join walk over the two.
+fn compress_binning(bins: &mut Bins, n_lvls: u32, rollup: BinRollup) {
     // First pass: level-based rollup, finest → coarsest.
-    for l in (1..=N_LVLS).rev() {
-        let level_first = hts_bin_first(l);
-        let level_last = hts_bin_first(l + 1); // exclusive upper bound
-
-        // Collect bins at this level (avoid borrowing bins while we mutate it).
-        let candidates: Vec<u32> = bins
-            .keys()
-            .filter(|&&b| b >= level_first && b < level_last)
-            .cloned()
-            .collect();
-
-        for b in candidates {
+    for l in (1..=n_lvls as usize).rev() {
+        let children = std::mem::take(&mut bins.levels[l]);
+        let parents = std::mem::take(&mut bins.levels[l - 1]);
+        let mut parents = parents.into_iter().peekable();
+        let (mut kept, mut merged_parents) = (Vec::new(), Vec::new());
+        // Small bins whose parent is missing, all siblings.
+        let mut orphans: Vec<(u32, Chunks)> = Vec::new();
+        for (b, chunks) in children {
             let parent = hts_bin_parent(b);
-            if !bins.contains_key(&parent) {
-                continue;
+            if orphans.first().is_some_and(|(o, _)| hts_bin_parent(*o) != parent) {
+                adopt_orphans(&mut orphans, &mut kept, &mut merged_parents, rollup);
             }
-            // Compute compressed-byte span of this bin.
-            let chunks = bins.get(&b).unwrap();
-            if chunks.is_empty() {
-                continue;
+            // Parents before this child's are final.
+            while parents.peek().is_some_and(|(p, _)| *p < parent) {
+                merged_parents.push(parents.next().expect("peeked"));
             }
-            let first_start = chunks.iter().map(|c| c.start).min().unwrap();
-            let last_end   = chunks.iter().map(|c| c.end).max().unwrap();
-            let span = (last_end >> 16).saturating_sub(first_start >> 16);
-            if span < HTS_MIN_MARKER_DIST {
+            if chunks.span() >= HTS_MIN_MARKER_DIST {
+                kept.push((b, chunks));
+            } else if let Some((_, parent_chunks)) = parents.peek_mut().filter(|(p, _)| *p == parent) {
                 // Remove child and extend parent.
-                let child_chunks = bins.remove(&b).unwrap();
-                let parent_chunks = bins.get_mut(&parent).unwrap();
-                parent_chunks.extend(child_chunks);
-                parent_chunks.sort_unstable_by_key(|c| c.start);
+                parent_chunks.extend(chunks);
+            } else {
+                orphans.push((b, chunks));
             }
         }
+        adopt_orphans(&mut orphans, &mut kept, &mut merged_parents, rollup);
+        merged_parents.extend(parents);
+        // Orphans kept join after bigger siblings read after them.
+        kept.sort_by_key(|e| e.0);
+        bins.levels[l] = kept;
+        bins.levels[l - 1] = merged_parents;
     }
 
     // Second pass: block-adjacent merge within every remaining bin.
-    for chunks in bins.values_mut() {
-        merge_chunks_block_adjacent(chunks);
+    for (_, chunks) in bins.levels.iter_mut().flatten() {
+        chunks.merge_block_adjacent();
     }
 }
 
@@ -233,159 +398,476 @@ fn compress_binning(bins: &mut HashMap<u32, Vec<Chunk>>) {
 // Public API
 // ---------------------------------------------------------------------------
 
+/// Layout of a tab-separated file to index, mirroring the tabix meta block.
+/// Columns are 1-based; coordinates are 1-based inclusive unless `zero_based`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TabixConfig {
+    pub col_seq: u32,
+    pub col_beg: u32,
+    /// `0` when records have no end column (each spans one base).
+    pub col_end: u32,
+    /// Lines starting with this byte are headers/comments and not indexed.
+    pub meta_char: u8,
+    /// Number of leading lines to skip unconditionally (e.g. a `track` line).
+    pub line_skip: u32,
+    /// BED-style 0-based, half-open coordinates (tabix `TBX_UCSC`, preset
+    /// bit 0x10000).
+    pub zero_based: bool,
+}
+
+impl TabixConfig {
+    /// GFF3: seqid, start, end in columns 1, 4, 5 (`tabix -p gff`).
+    pub const GFF: TabixConfig = TabixConfig { col_seq: 1, col_beg: 4, col_end: 5, meta_char: b'#', line_skip: 0, zero_based: false };
+
+    /// BED: chrom, chromStart, chromEnd in columns 1-3, 0-based (`tabix -p bed`).
+    pub const BED: TabixConfig = TabixConfig { col_seq: 1, col_beg: 2, col_end: 3, meta_char: b'#', line_skip: 0, zero_based: true };
+
+    /// Preset flag bit marking 0-based coordinates.
+    pub const ZERO_BASED_FLAG: u32 = 0x10000;
+
+    /// Value of the meta block's `preset` field (generic format + flags).
+    pub fn preset(&self) -> u32 {
+        if self.zero_based { Self::ZERO_BASED_FLAG } else { 0 }
+    }
+
+    fn field<'a>(fields: &[&'a [u8]], col: u32) -> io::Result<&'a [u8]> {
+        fields.get(col as usize - 1).copied().ok_or_else(|| {
+            io_error(ErrorCode::GffParse, format!("line has no column {}", col))
+        })
+    }
+}
+
 /// Build a CSI index for a BGZF-compressed GFF3 file.
 ///
 /// Reads from `bgzf_input` (a BGZF-compressed byte stream) and writes the
 /// binary `.csi` index to `csi_output`.
-pub fn csi_index_gff<R: Read, W: Write>(bgzf_input: R, csi_output: W) -> io::Result<()> {
-    let mut reader = BgzfReader::new(bgzf_input);
+pub fn csi_index_gff<R: Read, W: Write>(bgzf_input: R, csi_output: W) -> io::Result<u32> {
+    csi_index(bgzf_input, csi_output, &TabixConfig::GFF)
+}
+
+/// Build a CSI index for any sorted, BGZF-compressed tab-separated file whose
+/// sequence and coordinate columns are described by `config`.  Returns the
+/// number of binning levels, as [`csi_index_reader`].
+pub fn csi_index<R: Read, W: Write>(bgzf_input: R, csi_output: W, config: &TabixConfig) -> io::Result<u32> {
+    csi_index_reader(BgzfReader::new(bgzf_input), csi_output, config)
+}
 
-    let mut seqs: Vec<SeqIdx> = Vec::new();
-    let mut seq_map: HashMap<String, usize> = HashMap::new();
+/// [`csi_index`] reading through a configured `reader`.  Returns the number
+/// of binning levels written: [`N_LVLS`], or more (up to 9, i.e. 2^41) when
+/// a record ends beyond 2^38.
+pub fn csi_index_reader<R: Read, W: Write>(reader: BgzfReader<R>, csi_output: W, config: &TabixConfig) -> io::Result<u32> {
+    csi_index_reader_with(reader, csi_output, config, BinRollup::Htslib)
+}
+
+/// [`csi_index_reader`] with the bin rollup strategy `rollup`.
+pub fn csi_index_reader_with<R: Read, W: Write>(reader: BgzfReader<R>, csi_output: W, config: &TabixConfig, rollup: BinRollup) -> io::Result<u32> {
+    let (seqs, n_lvls) = build_index(reader, config, N_LVLS, MAX_N_LVLS, rollup)?;
+    write_csi(&seqs, csi_output, config, n_lvls)?;
+    Ok(n_lvls)
+}
+
+/// Build a classic `.tbi` index for a BGZF-compressed GFF3 file.
+pub fn tbi_index_gff<R: Read, W: Write>(bgzf_input: R, tbi_output: W) -> io::Result<()> {
+    tbi_index(bgzf_input, tbi_output, &TabixConfig::GFF)
+}
+
+/// Build a `.tbi` index (5 binning levels plus a linear index), for readers
+/// that do not understand CSI.  TBI cannot address coordinates at or beyond
+/// 2^29; such input is rejected.
+pub fn tbi_index<R: Read, W: Write>(bgzf_input: R, tbi_output: W, config: &TabixConfig) -> io::Result<()> {
+    tbi_index_reader(BgzfReader::new(bgzf_input), tbi_output, config)
+}
+
+/// [`tbi_index`] reading through a configured `reader`.
+pub fn tbi_index_reader<R: Read, W: Write>(reader: BgzfReader<R>, tbi_output: W, config: &TabixConfig) -> io::Result<()> {
+    tbi_index_reader_with(reader, tbi_output, config, BinRollup::Htslib)
+}
+
+/// [`tbi_index_reader`] with the bin rollup strategy `rollup`; the linear
+/// index does not depend on it.
+pub fn tbi_index_reader_with<R: Read, W: Write>(reader: BgzfReader<R>, tbi_output: W, config: &TabixConfig, rollup: BinRollup) -> io::Result<()> {
+    let (seqs, _) = build_index(reader, config, TBI_N_LVLS, TBI_N_LVLS, rollup)?;
+    write_tbi(&seqs, tbi_output, config)
+}
 
-    let mut line_buf = Vec::with_capacity(4096);
+/// Scan the records of `reader` and build the binning of every sequence
+/// with `n_lvls` levels, including the pseudo-bin, rolled up as `rollup`
+/// says.  A record ending beyond what they cover adds levels, up to
+/// `max_n_lvls`, and fails past that.  Returns the sequences and the number
+/// of levels used.
+fn build_index<R: Read>(
+    reader: BgzfReader<R>,
+    config: &TabixConfig,
+    n_lvls: u32,
+    max_n_lvls: u32,
+    rollup: BinRollup,
+) -> io::Result<(Vec<SeqIdx>, u32)> {
+    let mut scan = IndexScan::new(reader, config, n_lvls, max_n_lvls, rollup)?;
+    while !scan.step(usize::MAX)? {}
+    let (seqs, n_lvls, _) = scan.finish();
+    Ok((seqs, n_lvls))
+}
+
+/// The scan of [`build_index`], a line at a time.
+struct IndexScan<R: Read> {
+    reader: BgzfReader<R>,
+    config: TabixConfig,
+    n_lvls: u32,
+    max_n_lvls: u32,
+    rollup: BinRollup,
+    /// End of the coordinates `n_lvls` cover.
+    max_end: u64,
+    n_cols: usize,
+    seqs: Vec<SeqIdx>,
+    seq_map: HashMap<String, usize>,
+    line_buf: PooledBuf,
+    line_no: u64,
+    // Sequence and start of the previous record: the index is only valid
+    // for records grouped by sequence and sorted by start within each.
+    last: Option<(usize, u64)>,
+    done: bool,
+}
+
+fn covered(n_lvls: u32) -> u64 {
+    1u64 << (MIN_SHIFT + 3 * n_lvls)
+}
+
+impl<R: Read> IndexScan<R> {
+    fn new(reader: BgzfReader<R>, config: &TabixConfig, n_lvls: u32, max_n_lvls: u32, rollup: BinRollup) -> io::Result<Self> {
+        if config.col_seq == 0 || config.col_beg == 0 {
+            return Err(io_error(ErrorCode::InvalidOption, "col_seq and col_beg are 1-based"));
+        }
+        Ok(IndexScan {
+            reader,
+            config: *config,
+            n_lvls,
+            max_n_lvls,
+            rollup,
+            max_end: covered(n_lvls),
+            n_cols: config.col_seq.max(config.col_beg).max(config.col_end) as usize,
+            seqs: Vec::new(),
+            seq_map: HashMap::new(),
+            line_buf: PooledBuf::take(4096),
+            line_no: 0,
+            last: None,
+            done: false,
+        })
+    }
+
+    /// Scan up to `lines` more lines; true once the input is exhausted.
+    fn step(&mut self, lines: usize) -> io::Result<bool> {
+        for _ in 0..lines {
+            if self.done {
+                break;
+            }
+            self.line()?;
+        }
+        Ok(self.done)
+    }
 
-    loop {
-        line_buf.clear();
-        let (n, voff_start) = reader.read_line(&mut line_buf)?;
+    fn line(&mut self) -> io::Result<()> {
+        let config = &self.config;
+        let line_no = self.line_no + 1;
+        self.line_buf.clear();
+        let (n, voff_start) = self.reader.read_line(&mut self.line_buf)?;
         if n == 0 {
-            break;
+            self.done = true;
+            return Ok(());
+        }
+        self.line_no = line_no;
+        if line_no <= config.line_skip as u64 {
+            return Ok(());
         }
 
         // Strip trailing newline/CR for parsing, but keep voff_start
-        let line = strip_newline(&line_buf);
+        let line = strip_newline(&self.line_buf);
 
-        // Skip empty lines and comment/meta lines
-        if line.is_empty() || line[0] == b'#' {
-            continue;
+        // Skip blank lines and comment/meta lines
+        if line.iter().all(u8::is_ascii_whitespace) || line[0] == config.meta_char {
+            return Ok(());
         }
 
-        // Split on tabs
-        let fields: Vec<&[u8]> = line.splitn(6, |&b| b == b'\t').collect();
-        if fields.len() < 5 {
-            continue;
+        // Split on tabs.  A record too short to place would be missing from
+        // the index while still in the file, so it is an error rather than
+        // skipped.
+        let fields: Vec<&[u8]> = column_ranges(line, self.n_cols + 1).map(|r| &line[r]).collect();
+        if fields.len() < self.n_cols {
+            return Err(io_error(
+                ErrorCode::GffParse,
+                format!("line {}: {} column(s), fewer than the {} needed to index it", line_no, fields.len(), self.n_cols),
+            ));
         }
 
-        let seqname = std::str::from_utf8(fields[0])
-            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "non-UTF8 sequence name"))?
-            .to_owned();
-
-        let start_1: u64 = parse_u64(fields[3])?;
-        let end_1: u64 = parse_u64(fields[4])?;
+        let seqname = std::str::from_utf8(TabixConfig::field(&fields, config.col_seq)?)
+            .map_err(|_| io_error(ErrorCode::GffParse, "non-UTF8 sequence name"))?;
 
-        // GFF3 columns are 1-based, inclusive → convert to 0-based half-open
-        let beg = start_1.saturating_sub(1);
-        let end = end_1;
+        let start: u64 = parse_u64(TabixConfig::field(&fields, config.col_beg)?)?;
+        // Convert to 0-based half-open: 1-based inclusive starts move down by
+        // one, ends are the same number in both conventions.
+        let beg = if config.zero_based { start } else { start.saturating_sub(1) };
+        let end: u64 = match config.col_end {
+            0 => beg + 1,
+            col => parse_u64(TabixConfig::field(&fields, col)?)?,
+        };
+        if end > self.max_end {
+            let max_n_lvls = self.max_n_lvls;
+            let Some(needed) = (self.n_lvls + 1..=max_n_lvls).find(|&l| end <= covered(l)) else {
+                let (format, hint) = if max_n_lvls == TBI_N_LVLS { ("a TBI", "; index it as CSI instead") } else { ("any CSI", "") };
+                return Err(io_error(
+                    ErrorCode::CoordinateLimit,
+                    format!(
+                        "line {}: {}:{}-{} ends beyond {}, the largest coordinate {} index can address{}",
+                        line_no, seqname, start, end, covered(max_n_lvls), format, hint
+                    ),
+                ));
+            };
+            for seq in &mut self.seqs {
+                seq.bins.deepen(self.n_lvls, needed);
+            }
+            (self.n_lvls, self.max_end) = (needed, covered(needed));
+        }
 
         // Virtual offset after the line
-        let voff_end = reader.virtual_offset();
-        let bin = reg2bin(beg, end);
+        let voff_end = self.reader.virtual_offset();
+        let bin = reg2bin(beg, end, MIN_SHIFT, self.n_lvls);
 
-        let tid = match seq_map.get(&seqname) {
+        let tid = match self.seq_map.get(seqname) {
             Some(&id) => id,
             None => {
-                let id = seqs.len();
-                seqs.push(SeqIdx::new(seqname.clone()));
-                seq_map.insert(seqname, id);
+                let id = self.seqs.len();
+                self.reader.start_contig(seqname);
+                self.seqs.push(SeqIdx::new(seqname.to_owned(), self.n_lvls));
+                self.seq_map.insert(seqname.to_owned(), id);
                 id
             }
         };
+        match self.last {
+            Some((last_tid, _)) if last_tid != tid && tid < self.seqs.len() - 1 => {
+                return Err(io_error(
+                    ErrorCode::GffUnsorted,
+                    format!("line {}: records of {} are not contiguous", line_no, seqname),
+                ));
+            }
+            Some((last_tid, last_beg)) if last_tid == tid && beg < last_beg => {
+                return Err(io_error(
+                    ErrorCode::GffUnsorted,
+                    format!("line {}: {}:{} starts before the record above it", line_no, seqname, start),
+                ));
+            }
+            _ => {}
+        }
+        self.last = Some((tid, beg));
 
         let chunk = Chunk { start: voff_start, end: voff_end };
-        seqs[tid].add_chunk(bin, chunk);
-        seqs[tid].update_lidx(beg, end, voff_start);
-    }
-
-    // Fill trailing zeros in lidx.
-    let eof_voff = reader.virtual_offset();
-    for seq in &mut seqs {
-        let mut seen_nonzero = false;
-        for slot in seq.lidx.iter_mut() {
-            if *slot != 0 {
-                seen_nonzero = true;
-            } else if seen_nonzero {
-                *slot = eof_voff;
+        self.seqs[tid].add_chunk(bin, chunk);
+        self.seqs[tid].update_lidx(beg, end, voff_start);
+        Ok(())
+    }
+
+    /// The sequences and number of levels once [`IndexScan::step`] is
+    /// done, and the reader.
+    fn finish(mut self) -> (Vec<SeqIdx>, u32, BgzfReader<R>) {
+        // Fill trailing zeros in lidx.
+        let eof_voff = self.reader.virtual_offset();
+        for seq in &mut self.seqs {
+            let mut seen_nonzero = false;
+            for slot in seq.lidx.iter_mut() {
+                if *slot != 0 {
+                    seen_nonzero = true;
+                } else if seen_nonzero {
+                    *slot = eof_voff;
+                }
             }
         }
+
+        for seq in &mut self.seqs {
+            compress_binning(&mut seq.bins, self.n_lvls, self.rollup);
+        }
+
+        (self.seqs, self.n_lvls, self.reader)
     }
+}
 
-    // Apply compress_binning and inject pseudo-bin META_BIN per sequence.
-    for seq in &mut seqs {
-        compress_binning(&mut seq.bins);
-
-        let min_voff = if seq.min_voff == u64::MAX { 0 } else { seq.min_voff };
-        seq.bins.insert(
-            META_BIN,
-            vec![
-                Chunk { start: min_voff,      end: seq.max_voff },
-                Chunk { start: seq.n_mapped,  end: 0 },
-            ],
-        );
-    }
-
-    // -----------------------------------------------------------------------
-    // Write the .csi binary format (all little-endian), BGZF-compressed
-    // -----------------------------------------------------------------------
-    let mut w = BgzfWriter::new(csi_output);
-
-    // Magic
-    w.write_all(b"CSI\x01")?;
-
-    // min_shift, n_lvls
-    w.write_all(&(MIN_SHIFT as i32).to_le_bytes())?;
-    w.write_all(&(N_LVLS as i32).to_le_bytes())?;
-
-    // Build names buffer for meta section
-    let mut names_buf: Vec<u8> = Vec::new();
-    for seq in &seqs {
-        names_buf.extend_from_slice(seq.name.as_bytes());
-        names_buf.push(0);
-    }
-    let l_nm = names_buf.len() as u32;
-
-    // l_meta = 7 u32 fields (28 bytes) + names blob
-    let l_meta: u32 = 28 + l_nm;
-    w.write_all(&l_meta.to_le_bytes())?;
-
-    // Meta blob: same layout as TBI header fields (1-based column numbers),
-    // stored as u32: preset, col_seq, col_beg, col_end, meta_char, line_skip, l_nm, names.
-    w.write_all(&0u32.to_le_bytes())?;   // preset = TBX_GENERIC
-    w.write_all(&1u32.to_le_bytes())?;   // col_seq = 1 (1-based)
-    w.write_all(&4u32.to_le_bytes())?;   // col_beg = 4 (1-based)
-    w.write_all(&5u32.to_le_bytes())?;   // col_end = 5 (1-based)
-    w.write_all(&35u32.to_le_bytes())?;  // meta_char = '#'
-    w.write_all(&0u32.to_le_bytes())?;   // line_skip = 0
-    w.write_all(&l_nm.to_le_bytes())?;   // l_nm
-    w.write_all(&names_buf)?;            // seq names (null-terminated, concatenated)
-
-    // n_ref
-    w.write_all(&(seqs.len() as i32).to_le_bytes())?;
-
-    // Per-sequence index data
-    for seq in &seqs {
-        let mut bin_ids: Vec<u32> = seq.bins.keys().cloned().collect();
-        bin_ids.sort_unstable();
-
-        w.write_all(&(bin_ids.len() as i32).to_le_bytes())?;
-        for bin in &bin_ids {
-            let chunks = &seq.bins[bin];
-            let loff = compute_loff(*bin, &seq.lidx);
-            w.write_all(&bin.to_le_bytes())?;
-            w.write_all(&loff.to_le_bytes())?;  // CSI extra field (not in TBI)
-            w.write_all(&(chunks.len() as i32).to_le_bytes())?;
-            for chunk in chunks {
-                w.write_all(&chunk.start.to_le_bytes())?;
-                w.write_all(&chunk.end.to_le_bytes())?;
-            }
+/// [`csi_index_reader_with`] or [`tbi_index_reader_with`] a few lines at a
+/// time, for callers that have to give control back in between (see
+/// `cooperative.rs`).
+pub struct TabixIndexer<R: Read> {
+    scan: IndexScan<R>,
+    tbi: bool,
+}
+
+impl<R: Read> TabixIndexer<R> {
+    /// A `.csi` indexer.
+    pub fn csi(reader: BgzfReader<R>, config: &TabixConfig, rollup: BinRollup) -> io::Result<Self> {
+        Ok(TabixIndexer { scan: IndexScan::new(reader, config, N_LVLS, MAX_N_LVLS, rollup)?, tbi: false })
+    }
+
+    /// A `.tbi` indexer.
+    pub fn tbi(reader: BgzfReader<R>, config: &TabixConfig, rollup: BinRollup) -> io::Result<Self> {
+        Ok(TabixIndexer { scan: IndexScan::new(reader, config, TBI_N_LVLS, TBI_N_LVLS, rollup)?, tbi: true })
+    }
+
+    /// Index up to `lines` more lines; true once the input is exhausted.
+    pub fn step(&mut self, lines: usize) -> io::Result<bool> {
+        self.scan.step(lines)
+    }
+
+    /// Write the index to `output` once [`TabixIndexer::step`] is done;
+    /// returns the number of binning levels, as [`csi_index_reader`], and
+    /// the reader.
+    pub fn finish<W: Write>(self, output: W) -> io::Result<(u32, BgzfReader<R>)> {
+        if !self.scan.done {
+            return Err(io_error(ErrorCode::Internal, "the index scan is not complete"));
         }
+        let config = self.scan.config;
+        let (seqs, n_lvls, reader) = self.scan.finish();
+        match self.tbi {
+            true => write_tbi(&seqs, output, &config)?,
+            false => write_csi(&seqs, output, &config, n_lvls)?,
+        }
+        Ok((n_lvls, reader))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Serialization
+// ---------------------------------------------------------------------------
+
+/// Writes an index a section at a time: the header, then each sequence,
+/// is assembled in a scratch buffer reused throughout and handed to the
+/// BGZF writer in one call, rather than field by field.
+pub(super) struct IndexWriter<W: Write> {
+    w: BgzfWriter<W>,
+    scratch: Vec<u8>,
+}
 
-        // No linear index section in CSI format (omit n_intv + offset array)
+impl<W: Write> IndexWriter<W> {
+    pub(super) fn new(output: W) -> Self {
+        IndexWriter { w: BgzfWriter::new(output), scratch: Vec::with_capacity(4096) }
+    }
+
+    pub(super) fn u32(&mut self, v: u32) {
+        self.scratch.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub(super) fn u64(&mut self, v: u64) {
+        self.scratch.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub(super) fn bytes(&mut self, b: &[u8]) {
+        self.scratch.extend_from_slice(b);
+    }
+
+    /// Tabix header fields shared by the CSI meta block and the TBI header:
+    /// preset, col_seq, col_beg, col_end, meta_char, line_skip, l_nm, then
+    /// the null-terminated names.  With `l_meta`, the CSI length prefix
+    /// goes first.
+    pub(super) fn tabix_meta<'n>(&mut self, config: &TabixConfig, names: impl Iterator<Item = &'n str> + Clone, l_meta: bool) {
+        let l_nm: usize = names.clone().map(|n| n.len() + 1).sum();
+        if l_meta {
+            self.u32(28 + l_nm as u32);
+        }
+        self.u32(config.preset()); // preset = TBX_GENERIC (+ 0-based flag)
+        self.u32(config.col_seq);
+        self.u32(config.col_beg);
+        self.u32(config.col_end);
+        self.u32(config.meta_char as u32);
+        self.u32(config.line_skip);
+        self.u32(l_nm as u32);
+        for name in names {
+            self.bytes(name.as_bytes());
+            self.bytes(&[0]);
+        }
+    }
+
+    /// One bin entry: bin, loff (CSI only, not in TBI), n_chunk, chunks.
+    pub(super) fn bin(&mut self, bin: u32, loff: Option<u64>, n_chunk: usize, chunks: impl Iterator<Item = (u64, u64)>) {
+        self.u32(bin);
+        if let Some(loff) = loff {
+            self.u64(loff);
+        }
+        self.u32(n_chunk as u32);
+        for (start, end) in chunks {
+            self.u64(start);
+            self.u64(end);
+        }
+    }
+
+    /// Write out the section assembled so far.
+    pub(super) fn end_section(&mut self) -> io::Result<()> {
+        self.w.write_all(&self.scratch)?;
+        self.scratch.clear();
+        Ok(())
+    }
+
+    /// Write out the last section and the EOF block.
+    pub(super) fn finish(mut self) -> io::Result<W> {
+        self.end_section()?;
+        self.w.finish()
+    }
+}
+
+/// Write the .csi binary format (all little-endian), BGZF-compressed.
+fn write_csi<W: Write>(seqs: &[SeqIdx], csi_output: W, config: &TabixConfig, n_lvls: u32) -> io::Result<()> {
+    let mut w = IndexWriter::new(csi_output);
+
+    // Magic, min_shift, n_lvls, the meta block and n_ref
+    w.bytes(b"CSI\x01");
+    w.u32(MIN_SHIFT);
+    w.u32(n_lvls);
+    w.tabix_meta(config, seqs.iter().map(|s| s.name.as_str()), true);
+    w.u32(seqs.len() as u32);
+    w.end_section()?;
+
+    // Per-sequence index data: regular bins, then the pseudo-bin (loff 0).
+    // No linear index section in CSI format (omit n_intv + offset array).
+    for seq in seqs {
+        w.u32(seq.bins.len() as u32 + 1);
+        for (bin, chunks) in seq.bins.iter() {
+            let loff = compute_loff(*bin, &seq.lidx, n_lvls);
+            w.bin(*bin, Some(loff), chunks.len(), chunks.iter().map(|c| (c.start, c.end)));
+        }
+        let pseudo = seq.pseudo_bin();
+        w.bin(meta_bin(n_lvls), Some(0), pseudo.len(), pseudo.iter().map(|c| (c.start, c.end)));
+        w.end_section()?;
     }
 
     // n_no_coor = 0
-    w.write_all(&0u64.to_le_bytes())?;
+    w.u64(0);
     w.finish()?;
+    Ok(())
+}
+
+/// Write the .tbi binary format (all little-endian), BGZF-compressed.
+fn write_tbi<W: Write>(seqs: &[SeqIdx], tbi_output: W, config: &TabixConfig) -> io::Result<()> {
+    let mut w = IndexWriter::new(tbi_output);
+
+    // Magic, n_ref, then the tabix header without a length prefix
+    w.bytes(b"TBI\x01");
+    w.u32(seqs.len() as u32);
+    w.tabix_meta(config, seqs.iter().map(|s| s.name.as_str()), false);
+    w.end_section()?;
+
+    for seq in seqs {
+        w.u32(seq.bins.len() as u32 + 1);
+        for (bin, chunks) in seq.bins.iter() {
+            w.bin(*bin, None, chunks.len(), chunks.iter().map(|c| (c.start, c.end)));
+        }
+        let pseudo = seq.pseudo_bin();
+        w.bin(meta_bin(TBI_N_LVLS), None, pseudo.len(), pseudo.iter().map(|c| (c.start, c.end)));
+
+        // Linear index: n_intv + one virtual offset per 16 kb window
+        let ioff = seq.tbi_linear_index();
+        w.u32(ioff.len() as u32);
+        for off in ioff {
+            w.u64(off);
+        }
+        w.end_section()?;
+    }
 
+    // n_no_coor = 0
+    w.u64(0);
+    w.finish()?;
     Ok(())
 }
 
@@ -403,8 +885,8 @@ fn strip_newline(buf: &[u8]) -> &[u8] {
 
 fn parse_u64(bytes: &[u8]) -> io::Result<u64> {
     let s = std::str::from_utf8(bytes)
-        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "non-UTF8 field"))?
+        .map_err(|_| io_error(ErrorCode::GffParse, "non-UTF8 field"))?
         .trim();
     s.parse::<u64>()
-        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("cannot parse integer: {:?}", s)))
+        .map_err(|_| io_error(ErrorCode::GffParse, format!("cannot parse integer: {:?}", s)))
 }
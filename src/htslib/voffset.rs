@@ -0,0 +1,85 @@
+//! Virtual offset and binning arithmetic, for checking indexes by hand.
+//!
+//! A BGZF virtual offset packs the compressed offset of a block start (48
+//! bits) above an offset into its uncompressed data (16 bits).  A `.gzi`
+//! maps block starts to uncompressed offsets, so it converts between
+//! virtual and plain uncompressed offsets.  The binning functions are
+//! htslib's `hts_reg2bin` and `hts_reg2bins` for any `min_shift` and depth.
+//!
+//! JS gets them as static methods of `voffset` (see `htslib.rs`), with
+//! offsets as `BigInt`s.
+
+use std::io;
+
+use super::query::{reg2bin, reg2bins};
+use crate::error::{io_error, ErrorCode};
+
+/// `coffset << 16 | uoffset`.  Fails for a `uoffset` past 16 bits or a
+/// `coffset` past 48.
+pub fn compose_voffset(coffset: u64, uoffset: u64) -> io::Result<u64> {
+    if uoffset > 0xffff || coffset >> 48 != 0 {
+        return Err(io_error(ErrorCode::InvalidOption, format!("({}, {}) is not a virtual offset: at most (2^48 - 1, 65535)", coffset, uoffset)));
+    }
+    Ok(coffset << 16 | uoffset)
+}
+
+/// `(coffset, uoffset)` of a virtual offset.
+pub fn split_voffset(voffset: u64) -> (u64, u64) {
+    (voffset >> 16, voffset & 0xffff)
+}
+
+/// The virtual offset of uncompressed offset `offset`, through `gzi` as
+/// [`parse_gzi`](super::parse_gzi) returns it.  An offset on a block
+/// boundary is placed at the start of the later block, as readers seek.
+/// Past the last block start only the 16-bit limit is checked, as the
+/// `.gzi` does not record where the data ends.  Entries out of order, which
+/// can put the block after `offset`, fail with `E_INDEX_PARSE`.
+pub fn uncompressed_to_voffset(gzi: &[(u64, u64)], offset: u64) -> io::Result<u64> {
+    let block = gzi.partition_point(|&(_, u)| u <= offset).saturating_sub(1);
+    let &(coffset, ustart) = gzi.get(block).ok_or_else(|| io_error(ErrorCode::IndexParse, "empty .gzi"))?;
+    let uoffset = offset.checked_sub(ustart).ok_or_else(|| {
+        io_error(ErrorCode::IndexParse, format!(".gzi puts offset {} in a block starting at {}; its entries are out of order", offset, ustart))
+    })?;
+    compose_voffset(coffset, uoffset).map_err(|_| io_error(ErrorCode::IndexMismatch, format!("offset {} is past the block the .gzi puts it in", offset)))
+}
+
+/// The uncompressed offset of `voffset`, through `gzi`.  Its block must be
+/// one the `.gzi` lists.
+pub fn voffset_to_uncompressed(gzi: &[(u64, u64)], voffset: u64) -> io::Result<u64> {
+    let (coffset, uoffset) = split_voffset(voffset);
+    let block = gzi
+        .binary_search_by_key(&coffset, |&(c, _)| c)
+        .map_err(|_| io_error(ErrorCode::IndexMismatch, format!("virtual offset {} is not in a block the .gzi lists", voffset)))?;
+    let offset = gzi[block]
+        .1
+        .checked_add(uoffset)
+        .ok_or_else(|| io_error(ErrorCode::IndexParse, format!(".gzi puts the block of virtual offset {} past 2^64 bytes", voffset)))?;
+    match gzi.get(block + 1) {
+        Some(&(_, next)) if offset > next => Err(io_error(ErrorCode::IndexMismatch, format!("virtual offset {} is past the end of its block", voffset))),
+        _ => Ok(offset),
+    }
+}
+
+/// Check binning parameters and a 0-based half-open interval against them.
+fn check_binning(beg: u64, end: u64, min_shift: u32, n_lvls: u32) -> io::Result<()> {
+    if !(1..=9).contains(&n_lvls) || min_shift == 0 || min_shift + 3 * n_lvls > 62 {
+        return Err(io_error(ErrorCode::InvalidOption, format!("min_shift {} with {} levels is not a binning scheme", min_shift, n_lvls)));
+    }
+    let limit = 1u64 << (min_shift + 3 * n_lvls);
+    if beg >= end || end > limit {
+        return Err(io_error(ErrorCode::CoordinateLimit, format!("[{}, {}) is empty or ends past {}", beg, end, limit)));
+    }
+    Ok(())
+}
+
+/// The bin a record spanning `[beg, end)` goes in.
+pub fn region_bin(beg: u64, end: u64, min_shift: u32, n_lvls: u32) -> io::Result<u32> {
+    check_binning(beg, end, min_shift, n_lvls)?;
+    Ok(reg2bin(beg, end, min_shift, n_lvls))
+}
+
+/// Every bin a query of `[beg, end)` reads, root first.
+pub fn region_bins(beg: u64, end: u64, min_shift: u32, n_lvls: u32) -> io::Result<Vec<u32>> {
+    check_binning(beg, end, min_shift, n_lvls)?;
+    Ok(reg2bins(beg, end, min_shift, n_lvls))
+}
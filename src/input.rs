@@ -0,0 +1,296 @@
+//! Input sources for the preprocessing pipeline.
+//!
+//! Every source is read fully into memory before processing, so the only
+//! thing a source has to provide is its raw (possibly gzip-compressed) bytes.
+//! Reads stop at the memory budget of the build (see [`crate::limits`]).
+
+use std::fs;
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+use flate2::bufread::MultiGzDecoder;
+use wasm_bindgen_file_reader::WebSysFile;
+
+use crate::decompress::{open_file_maybe_gz, GZ_MAGIC};
+use crate::error::{io_error, with_code, ErrorCode};
+use crate::htslib::{inflate_parallel, plausible_block_header, EOF_BLOCK};
+use crate::limits::{self, read_to_end_within, too_large, Measure};
+use crate::seed::SeededRng;
+
+pub mod fetch;
+pub mod shared;
+pub mod stream;
+
+pub use fetch::{download_complete, fetch_bytes, fetch_range, range_header, range_reply, retry_delays, FetchOptions, RangeReply};
+pub use shared::{js_input, shared_memory_available, SharedReader};
+pub use stream::{read_js_bytes, visit_js_chunks};
+
+/// Where an input file comes from.
+pub enum InputSource {
+    /// A browser `File`, read synchronously (worker only).
+    File(web_sys::File),
+    /// Raw bytes already resident in wasm memory, e.g. fetched from a URL.
+    Bytes(Vec<u8>),
+    /// A byte view over a `SharedArrayBuffer`, read in place (see
+    /// `input/shared.rs`).
+    Shared(js_sys::Uint8Array),
+    /// A file on disk (native and WASI builds; see `src/bin/mgnify-preprocess.rs`).
+    Path(PathBuf),
+}
+
+/// An input read whole: its decompressed bytes, and the file as stored
+/// when that is a complete BGZF file (a block header first, the EOF block
+/// last), which may be the output of an earlier run.
+pub struct StoredInput {
+    pub bytes: Vec<u8>,
+    pub bgzf: Option<Vec<u8>>,
+}
+
+/// [`read_pair`], keeping inputs that are BGZF files as stored too.  Only
+/// the decompressed bytes count against the budget: the stored copies are
+/// dropped as soon as preprocessing shows they cannot be kept.
+pub fn read_pair_stored(fa: InputSource, gff: InputSource) -> io::Result<(StoredInput, StoredInput)> {
+    let budget = limits::input_budget();
+    let fa = fa.read_stored_within(budget, "the FASTA input")?;
+    let gff = gff.read_stored_within(budget.saturating_sub(fa.bytes.len() as u64), "the FASTA and GFF3 inputs together")?;
+    Ok((fa, gff))
+}
+
+/// Read a FASTA and a GFF3 input, both within one [`limits::input_budget`].
+pub fn read_pair(fa: InputSource, gff: InputSource) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    let budget = limits::input_budget();
+    let fa_bytes = fa.read_all_within(budget, "the FASTA input")?;
+    let gff_bytes = gff.read_all_within(budget - fa_bytes.len() as u64, "the FASTA and GFF3 inputs together")?;
+    Ok((fa_bytes, gff_bytes))
+}
+
+impl InputSource {
+    /// Read the whole input, transparently decompressing gzip.
+    pub fn read_all(self) -> io::Result<Vec<u8>> {
+        self.read_all_within(limits::input_budget(), "the input")
+    }
+
+    /// [`InputSource::read_all`], failing with `E_INPUT_TOO_LARGE` (naming
+    /// the input `what`) once more than `budget` decompressed bytes come out.
+    /// Sources larger than `budget` as stored fail before anything is read.
+    pub fn read_all_within(self, budget: u64, what: &str) -> io::Result<Vec<u8>> {
+        let raw_len = match &self {
+            InputSource::File(file) => file.size() as u64,
+            InputSource::Bytes(bytes) => bytes.len() as u64,
+            InputSource::Shared(view) => view.length() as u64,
+            InputSource::Path(path) => fs::metadata(path)?.len(),
+        };
+        if raw_len > budget {
+            return Err(too_large(what, raw_len, Measure::Stored, budget));
+        }
+        if raw_len < 2 {
+            return Err(io_error(ErrorCode::InputEmpty, format!("{} is empty ({} bytes)", what, raw_len)));
+        }
+        if cfg!(feature = "threads") {
+            return self.read_all_parallel(budget, what);
+        }
+        let mut out = Vec::new();
+        match self {
+            InputSource::File(file) => {
+                let mut wf = WebSysFile::new(file);
+                read_to_end_within(open_file_maybe_gz(&mut wf)?, &mut out, budget, Measure::Decompressed, what).map_err(decompress_error)?;
+            }
+            InputSource::Bytes(bytes) => {
+                let mut cursor = Cursor::new(bytes);
+                read_to_end_within(open_file_maybe_gz(&mut cursor)?, &mut out, budget, Measure::Decompressed, what).map_err(decompress_error)?;
+            }
+            InputSource::Shared(view) => {
+                let mut reader = SharedReader::new(view);
+                read_to_end_within(open_file_maybe_gz(&mut reader)?, &mut out, budget, Measure::Decompressed, what).map_err(decompress_error)?;
+            }
+            InputSource::Path(path) => {
+                let mut file = BufReader::new(fs::File::open(path)?);
+                read_to_end_within(open_file_maybe_gz(&mut file)?, &mut out, budget, Measure::Decompressed, what).map_err(decompress_error)?;
+            }
+        }
+        Ok(out)
+    }
+
+    /// [`InputSource::read_all_within`], keeping the input as stored when
+    /// it is a complete BGZF file.  Other inputs are read as before.
+    pub fn read_stored_within(self, budget: u64, what: &str) -> io::Result<StoredInput> {
+        let mut head = Vec::with_capacity(18);
+        self.open_raw()?.take(18).read_to_end(&mut head)?;
+        if !plausible_block_header(&head) {
+            return Ok(StoredInput { bytes: self.read_all_within(budget, what)?, bgzf: None });
+        }
+        let raw = match self {
+            InputSource::Bytes(bytes) if bytes.len() as u64 > budget => return Err(too_large(what, bytes.len() as u64, Measure::Stored, budget)),
+            InputSource::Bytes(bytes) => bytes,
+            source => {
+                let mut raw = Vec::new();
+                read_to_end_within(source.open_raw()?, &mut raw, budget, Measure::Stored, what)?;
+                raw
+            }
+        };
+        let bytes = inflate_within(&raw, budget, what)?;
+        Ok(StoredInput { bytes, bgzf: raw.ends_with(&EOF_BLOCK).then_some(raw) })
+    }
+
+    /// [`InputSource::read_all_within`] with the `threads` feature: holds
+    /// the whole compressed input, then inflates it with
+    /// [`inflate_parallel`], one worker per core and at least 4 MiB of it
+    /// per worker.
+    fn read_all_parallel(self, budget: u64, what: &str) -> io::Result<Vec<u8>> {
+        let raw = match self {
+            InputSource::File(file) => {
+                let mut raw = Vec::new();
+                read_to_end_within(WebSysFile::new(file), &mut raw, budget, Measure::Stored, what)?;
+                raw
+            }
+            InputSource::Bytes(bytes) => bytes,
+            InputSource::Shared(view) => view.to_vec(),
+            InputSource::Path(path) => {
+                let mut raw = Vec::new();
+                read_to_end_within(fs::File::open(path)?, &mut raw, budget, Measure::Stored, what)?;
+                raw
+            }
+        };
+        if !raw.starts_with(&GZ_MAGIC) {
+            return Ok(raw);
+        }
+        inflate_within(&raw, budget, what)
+    }
+
+    /// The input's bytes as stored, without decompressing; each call starts
+    /// a new pass from the beginning.
+    pub fn open_raw(&self) -> io::Result<Box<dyn Read + '_>> {
+        Ok(match self {
+            InputSource::File(file) => Box::new(BufReader::with_capacity(1 << 20, WebSysFile::new(file.clone()))),
+            InputSource::Bytes(bytes) => Box::new(&bytes[..]),
+            InputSource::Shared(view) => Box::new(BufReader::with_capacity(1 << 20, SharedReader::new(view.clone()))),
+            InputSource::Path(path) => Box::new(BufReader::new(fs::File::open(path)?)),
+        })
+    }
+
+    /// The first `len` decompressed bytes of the input, and whether more
+    /// follow.
+    pub fn read_prefix(&self, len: u64) -> io::Result<(Vec<u8>, bool)> {
+        let mut raw = self.open_raw()?;
+        let mut dec = open_file_maybe_gz(&mut raw)?;
+        let mut out = Vec::new();
+        limits::try_reserve(&mut out, len, "the preview")?;
+        (&mut dec).take(len).read_to_end(&mut out).map_err(decompress_error)?;
+        let more = out.len() as u64 == len && dec.read(&mut [0u8]).map_err(decompress_error)? > 0;
+        Ok((out, more))
+    }
+
+    /// Sample the decompressed contents without reading the whole input:
+    /// `count` slices of `size` bytes for plain inputs, one in each of
+    /// `count` equal stretches (at the start of the first, at a position
+    /// drawn from `rng` in the others), or the first `count * size` bytes
+    /// of a gzipped one (gzip cannot seek).
+    pub fn sample(self, count: usize, size: usize, rng: &mut SeededRng) -> io::Result<InputSample> {
+        match self {
+            InputSource::File(file) => sample_reader(WebSysFile::new(file), count, size, rng),
+            InputSource::Bytes(bytes) => sample_reader(Cursor::new(bytes), count, size, rng),
+            InputSource::Shared(view) => sample_reader(SharedReader::new(view), count, size, rng),
+            InputSource::Path(path) => sample_reader(fs::File::open(path)?, count, size, rng),
+        }
+    }
+}
+
+/// Slices of an input taken by [`InputSource::sample`].
+pub struct InputSample {
+    /// Size of the input as stored (compressed, if gzipped).
+    pub raw_len: u64,
+    pub gzipped: bool,
+    /// Decompressed size; extrapolated from the prefix for large gzip inputs.
+    pub len: u64,
+    /// Whether `chunks` hold the entire input, making `len` exact.
+    pub exact: bool,
+    /// Sampled decompressed bytes, one entry per contiguous slice.
+    pub chunks: Vec<Vec<u8>>,
+}
+
+impl InputSample {
+    /// Total sampled bytes.
+    pub fn sampled_len(&self) -> u64 {
+        self.chunks.iter().map(|c| c.len() as u64).sum()
+    }
+}
+
+/// Counts bytes pulled through it, i.e. the compressed bytes a decoder used.
+struct Counting<R> {
+    inner: R,
+    n: u64,
+}
+
+impl<R: Read> Read for Counting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.n += n as u64;
+        Ok(n)
+    }
+}
+
+fn sample_reader<R: Read + Seek>(mut r: R, count: usize, size: usize, rng: &mut SeededRng) -> io::Result<InputSample> {
+    let raw_len = r.seek(SeekFrom::End(0))?;
+    r.seek(SeekFrom::Start(0))?;
+    let mut magic = [0u8; 2];
+    let gzipped = raw_len >= 2 && { r.read_exact(&mut magic)?; magic == GZ_MAGIC };
+    r.seek(SeekFrom::Start(0))?;
+    let budget = (count * size) as u64;
+
+    if gzipped {
+        let mut dec = MultiGzDecoder::new(BufReader::new(Counting { inner: r, n: 0 }));
+        let mut prefix = Vec::new();
+        (&mut dec).take(budget).read_to_end(&mut prefix)?;
+        let exact = (prefix.len() as u64) < budget || dec.read(&mut [0u8])? == 0;
+        let buffered = dec.get_ref().buffer().len() as u64;
+        let consumed = (dec.get_ref().get_ref().n - buffered).max(1);
+        let len = if exact { prefix.len() as u64 } else { prefix.len() as u64 * raw_len / consumed };
+        return Ok(InputSample { raw_len, gzipped, len, exact, chunks: vec![prefix] });
+    }
+
+    if raw_len <= budget || count < 2 {
+        let mut all = Vec::new();
+        r.take(budget).read_to_end(&mut all)?;
+        return Ok(InputSample { raw_len, gzipped, len: raw_len, exact: raw_len <= budget, chunks: vec![all] });
+    }
+    let mut chunks = Vec::with_capacity(count);
+    for i in 0..count as u64 {
+        // Stretches are at least `size` long, so slices never overlap.
+        let (from, to) = (i * raw_len / count as u64, (i + 1) * raw_len / count as u64);
+        let offset = if i == 0 { 0 } else { from + rng.below(to - from - size as u64 + 1) };
+        r.seek(SeekFrom::Start(offset))?;
+        let mut chunk = vec![0u8; size];
+        r.read_exact(&mut chunk)?;
+        chunks.push(chunk);
+    }
+    Ok(InputSample { raw_len, gzipped, len: raw_len, exact: false, chunks })
+}
+
+/// Decompress a gzip input held in memory, failing past `budget` bytes;
+/// in parallel with the `threads` feature.
+fn inflate_within(raw: &[u8], budget: u64, what: &str) -> io::Result<Vec<u8>> {
+    if !cfg!(feature = "threads") {
+        let mut out = Vec::new();
+        read_to_end_within(MultiGzDecoder::new(raw), &mut out, budget, Measure::Decompressed, what).map_err(decompress_error)?;
+        return Ok(out);
+    }
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let workers = cores.min(raw.len() >> 22).max(1);
+    let out = inflate_parallel(raw, workers).map_err(|e| match e.kind() {
+        io::ErrorKind::OutOfMemory => e,
+        _ => io_error(ErrorCode::InputDecompress, e.to_string()),
+    })?;
+    if out.len() as u64 > budget {
+        return Err(too_large(what, out.len() as u64, Measure::Decompressed, budget));
+    }
+    Ok(out)
+}
+
+/// Failures of the gzip layer (bad header, CRC, truncation) get
+/// [`ErrorCode::InputDecompress`]; read errors of the source keep theirs.
+fn decompress_error(e: io::Error) -> io::Error {
+    match e.kind() {
+        io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput | io::ErrorKind::UnexpectedEof => with_code(e, ErrorCode::InputDecompress),
+        _ => e,
+    }
+}
@@ -0,0 +1,214 @@
+//! `fetch()`-backed input source.
+//!
+//! Files are downloaded in fixed-size HTTP Range requests so that a flaky
+//! connection only costs one chunk on retry, and so progress can be reported
+//! as each chunk arrives.  Servers that ignore `Range` (plain `200 OK`) are
+//! handled by taking the whole body from the first response.
+
+use std::io;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+use crate::error::{io_error, js_error, ErrorCode};
+use crate::limits;
+use crate::progress;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_name = fetch)]
+    fn fetch_with_request(req: &web_sys::Request) -> js_sys::Promise;
+
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(handler: &js_sys::Function, timeout: i32) -> JsValue;
+}
+
+/// Options controlling how remote inputs are downloaded.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct FetchOptions {
+    /// Bytes requested per HTTP Range request.
+    pub chunk_size: u32,
+    /// Attempts per chunk after the first one fails.
+    pub max_retries: u32,
+    /// Delay before the first retry; doubled on every further attempt.
+    pub retry_delay_ms: u32,
+}
+
+#[wasm_bindgen]
+impl FetchOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        FetchOptions {
+            chunk_size: 8 * 1024 * 1024,
+            max_retries: 3,
+            retry_delay_ms: 500,
+        }
+    }
+}
+
+/// What a response to a Range request holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RangeReply {
+    /// `206`: the requested bytes, with the size of the resource if the
+    /// `Content-Range` gives it.
+    Partial { total: Option<u64> },
+    /// `200`: the server ignored `Range` and sent everything.
+    Whole,
+    /// `416`: the range starts at or past the end of the resource.
+    PastEnd,
+}
+
+/// The `Range` header asking for `len` bytes from `start` (`None` → to the
+/// end); a zero `len` asks for one byte.
+pub fn range_header(start: u64, len: Option<u64>) -> String {
+    match len {
+        Some(len) => format!("bytes={}-{}", start, start.saturating_add(len.max(1) - 1)),
+        None => format!("bytes={}-", start),
+    }
+}
+
+/// How to read a response to a Range request for `url` with HTTP `status`
+/// and `Content-Range` header `content_range`.  Any other status fails
+/// with `E_FETCH`.
+pub fn range_reply(url: &str, status: u16, status_text: &str, content_range: Option<&str>) -> io::Result<RangeReply> {
+    match status {
+        206 => Ok(RangeReply::Partial { total: content_range.and_then(parse_content_range_total) }),
+        200 => Ok(RangeReply::Whole),
+        416 => Ok(RangeReply::PastEnd),
+        _ => Err(io_error(ErrorCode::Fetch, format!("{}: HTTP {} {}", url, status, status_text))),
+    }
+}
+
+/// The waits before each retry of a failed chunk: `max_retries` of them,
+/// from `retry_delay_ms` and doubling.
+pub fn retry_delays(opts: &FetchOptions) -> impl Iterator<Item = u32> {
+    std::iter::successors(Some(opts.retry_delay_ms), |delay| Some(delay.saturating_mul(2))).take(opts.max_retries as usize)
+}
+
+/// Whether a download of `url` is complete at `have` bytes, after a chunk
+/// of `got` of the `requested` bytes.  A server may send fewer bytes than
+/// asked for; without a known `total` a short chunk marks the end, with
+/// one an empty chunk before it fails with `E_FETCH`.
+pub fn download_complete(url: &str, have: u64, got: u64, requested: u64, total: Option<u64>) -> io::Result<bool> {
+    match total {
+        Some(total) if have >= total => Ok(true),
+        Some(_) if got == 0 => Err(io_error(ErrorCode::Fetch, format!("{}: server returned an empty range before the end of the file", url))),
+        Some(_) => Ok(false),
+        None => Ok(got < requested),
+    }
+}
+
+/// One successfully fetched chunk.
+struct Chunk {
+    bytes: Vec<u8>,
+    /// Total size of the resource, if the server told us.
+    total: Option<u64>,
+    /// `false` when the server ignored the Range header and sent everything.
+    partial: bool,
+}
+
+/// Download `url` completely, reporting progress under `stage`.  A resource
+/// larger than [`limits::input_budget`] fails with `E_INPUT_TOO_LARGE`, up
+/// front when the server reports its size.
+pub async fn fetch_bytes(url: &str, opts: &FetchOptions, stage: &str) -> Result<Vec<u8>, JsValue> {
+    let budget = limits::input_budget();
+    let chunk_size = opts.chunk_size.max(1) as u64;
+
+    let first = fetch_chunk_with_retry(url, 0, Some(chunk_size), opts).await?;
+    if !first.partial {
+        progress::report(stage, first.bytes.len() as u64, Some(first.bytes.len() as u64));
+        return Ok(first.bytes);
+    }
+
+    let total = first.total;
+    if let Some(total) = total.filter(|&t| t > budget) {
+        return Err(js_error(limits::too_large(url, total, limits::Measure::Stored, budget)));
+    }
+    let mut out = first.bytes;
+    progress::report(stage, out.len() as u64, total);
+
+    let mut complete = total.is_some_and(|total| out.len() as u64 >= total);
+    while !complete {
+        let chunk = fetch_chunk_with_retry(url, out.len() as u64, Some(chunk_size), opts).await?;
+        let got = chunk.bytes.len() as u64;
+        if out.len() as u64 + got > budget {
+            return Err(js_error(limits::too_large(url, out.len() as u64 + got, limits::Measure::Stored, budget)));
+        }
+        limits::try_reserve(&mut out, got, url).map_err(js_error)?;
+        out.extend_from_slice(&chunk.bytes);
+        progress::report(stage, out.len() as u64, total);
+        complete = download_complete(url, out.len() as u64, got, chunk_size, total).map_err(js_error)?;
+    }
+    Ok(out)
+}
+
+/// Download bytes `[start, end)` of `url` (`end = None` → to the end of the
+/// resource), retrying according to `opts`.
+pub async fn fetch_range(url: &str, start: u64, end: Option<u64>, opts: &FetchOptions) -> Result<Vec<u8>, JsValue> {
+    let chunk = fetch_chunk_with_retry(url, start, end.map(|e| e.saturating_sub(start)), opts).await?;
+    if chunk.partial {
+        return Ok(chunk.bytes);
+    }
+    // The server ignored the Range header; cut the range out locally.
+    let len = chunk.bytes.len() as u64;
+    let (s, e) = (start.min(len), end.unwrap_or(len).min(len));
+    // Both are clamped to a length held in memory, so they fit a usize.
+    Ok(chunk.bytes[s as usize..e as usize].to_vec())
+}
+
+async fn fetch_chunk_with_retry(url: &str, start: u64, len: Option<u64>, opts: &FetchOptions) -> Result<Chunk, JsValue> {
+    let mut delays = retry_delays(opts);
+    loop {
+        match fetch_chunk(url, start, len).await {
+            Ok(chunk) => return Ok(chunk),
+            Err(e) => {
+                let Some(delay) = delays.next() else {
+                    return Err(e);
+                };
+                crate::logw(&format!("fetch {} (bytes {}+) failed, retrying: {:?}", url, start, e), Some("fetch"));
+                sleep(delay).await;
+            }
+        }
+    }
+}
+
+async fn fetch_chunk(url: &str, start: u64, len: Option<u64>) -> Result<Chunk, JsValue> {
+    let init = web_sys::RequestInit::new();
+    init.set_method("GET");
+    let headers = web_sys::Headers::new()?;
+    headers.set("Range", &range_header(start, len))?;
+    init.set_headers(&headers);
+    let req = web_sys::Request::new_with_str_and_init(url, &init)?;
+
+    let resp: web_sys::Response = JsFuture::from(fetch_with_request(&req)).await?.dyn_into()?;
+    let content_range = resp.headers().get("Content-Range")?;
+    let (partial, total) = match range_reply(url, resp.status(), &resp.status_text(), content_range.as_deref()).map_err(js_error)? {
+        RangeReply::PastEnd => return Ok(Chunk { bytes: Vec::new(), total: None, partial: true }),
+        RangeReply::Partial { total } => (true, total),
+        RangeReply::Whole => (false, None),
+    };
+
+    let buf = JsFuture::from(resp.array_buffer()?).await?;
+    let bytes = js_sys::Uint8Array::new(&buf).to_vec();
+    Ok(Chunk { bytes, total, partial })
+}
+
+/// Total length from a `Content-Range: bytes a-b/total` header (`*` → unknown).
+fn parse_content_range_total(value: &str) -> Option<u64> {
+    value.rsplit('/').next()?.trim().parse().ok()
+}
+
+async fn sleep(ms: u32) {
+    let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+        set_timeout(&resolve, ms as i32);
+    });
+    let _ = JsFuture::from(promise).await;
+}
@@ -0,0 +1,99 @@
+//! Inputs in a `SharedArrayBuffer`.
+//!
+//! On a cross-origin isolated page (served with `Cross-Origin-Opener-Policy:
+//! same-origin` and `Cross-Origin-Embedder-Policy: require-corp`) the main
+//! thread can read a file into a `SharedArrayBuffer` and post it to the
+//! worker, which sees the same memory rather than a copy.  The worker then
+//! reads it in slices, as it reads a `File`, without `FileReaderSync`: a
+//! gzip input is inflated straight from the shared buffer, so only the
+//! decompressed bytes ever land in wasm memory, and a plain one is copied
+//! in once.
+//!
+//! Without isolation `SharedArrayBuffer` is not defined, and
+//! [`js_input`] falls back: a `File` is read as a `File`, and an
+//! `ArrayBuffer` or typed array is copied into wasm memory.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use super::InputSource;
+use crate::error::{js_error_code, ErrorCode};
+
+/// Whether this context can receive shared input: a browser, cross-origin
+/// isolated, with `SharedArrayBuffer` defined.
+pub fn shared_memory_available() -> bool {
+    if !cfg!(all(target_arch = "wasm32", not(target_os = "wasi"))) {
+        return false;
+    }
+    let global = js_sys::global();
+    let flag = |name: &str| js_sys::Reflect::get(&global, &JsValue::from_str(name)).ok();
+    flag("crossOriginIsolated").is_some_and(|v| v.is_truthy()) && flag("SharedArrayBuffer").is_some_and(|v| v.is_function())
+}
+
+/// The input `value` names: a `SharedArrayBuffer`, or a typed array over
+/// one, read in place; a `File`; or an `ArrayBuffer` or typed array,
+/// copied.  Anything else is `E_INPUT_TYPE`.
+pub fn js_input(value: &JsValue, what: &str) -> Result<InputSource, JsValue> {
+    if let Some(file) = value.dyn_ref::<web_sys::File>() {
+        return Ok(InputSource::File(file.clone()));
+    }
+    let view = if let Some(buffer) = value.dyn_ref::<js_sys::SharedArrayBuffer>() {
+        js_sys::Uint8Array::new(buffer)
+    } else if let Some(buffer) = value.dyn_ref::<js_sys::ArrayBuffer>() {
+        return Ok(InputSource::Bytes(js_sys::Uint8Array::new(buffer).to_vec()));
+    } else if let Some(view) = value.dyn_ref::<js_sys::Uint8Array>() {
+        view.clone()
+    } else if let Some(view) = value.dyn_ref::<js_sys::Object>().filter(|v| js_sys::ArrayBuffer::is_view(v)) {
+        let view: &js_sys::DataView = view.unchecked_ref();
+        js_sys::Uint8Array::new_with_byte_offset_and_length(&view.buffer(), view.byte_offset() as u32, view.byte_length() as u32)
+    } else {
+        return Err(js_error_code(ErrorCode::InputType, &format!("{} must be a File, SharedArrayBuffer, ArrayBuffer or typed array", what)));
+    };
+    if view.buffer().is_instance_of::<js_sys::SharedArrayBuffer>() {
+        Ok(InputSource::Shared(view))
+    } else {
+        Ok(InputSource::Bytes(view.to_vec()))
+    }
+}
+
+/// Reads a byte view over a `SharedArrayBuffer` a slice at a time.
+pub struct SharedReader {
+    view: js_sys::Uint8Array,
+    pos: u64,
+}
+
+impl SharedReader {
+    pub fn new(view: js_sys::Uint8Array) -> Self {
+        SharedReader { view, pos: 0 }
+    }
+
+    fn len(&self) -> u64 {
+        self.view.length() as u64
+    }
+}
+
+impl Read for SharedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let end = self.len().min(self.pos + buf.len() as u64);
+        let n = (end - self.pos) as usize;
+        if n > 0 {
+            self.view.subarray(self.pos as u32, end as u32).copy_to(&mut buf[..n]);
+            self.pos = end;
+        }
+        Ok(n)
+    }
+}
+
+impl Seek for SharedReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(d) => self.len().checked_add_signed(d),
+            SeekFrom::Current(d) => self.pos.checked_add_signed(d),
+        };
+        self.pos = target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start"))?;
+        Ok(self.pos)
+    }
+}
@@ -0,0 +1,75 @@
+//! Inputs handed over from Node.js: `Buffer`s and streams.
+//!
+//! Node has no `File`/`FileReaderSync`, but a `Buffer` is a `Uint8Array` and
+//! both Node `Readable` streams (`fs.createReadStream`) and web
+//! `ReadableStream`s are async iterables of `Uint8Array` chunks, so neither
+//! needs `web_sys`.
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+use crate::error::{js_error, js_error_code, ErrorCode};
+use crate::limits;
+use crate::progress;
+
+/// The raw bytes of `input`: a `Uint8Array` (including a Node `Buffer`), an
+/// array of them or an async iterable yielding them.  `stage` names the
+/// progress messages.  A stream longer than [`limits::input_budget`] fails
+/// with `E_INPUT_TOO_LARGE` as soon as it passes the budget.
+pub async fn read_js_bytes(input: &JsValue, stage: &str) -> Result<Vec<u8>, JsValue> {
+    if let Some(array) = input.dyn_ref::<js_sys::Uint8Array>() {
+        return Ok(array.to_vec());
+    }
+    let mut out = Vec::new();
+    visit_js_chunks(input, |chunk| {
+        let (start, len) = (out.len(), chunk.length() as u64);
+        if start as u64 + len > limits::input_budget() {
+            return Err(js_error(limits::too_large(stage, start as u64 + len, limits::Measure::Stored, limits::input_budget())));
+        }
+        limits::try_reserve(&mut out, len, stage).map_err(js_error)?;
+        out.resize(start + len as usize, 0);
+        chunk.copy_to(&mut out[start..]);
+        progress::report(stage, out.len() as u64, None);
+        Ok(())
+    })
+    .await?;
+    Ok(out)
+}
+
+/// Call `f` with each chunk of `input`, in order: the `Uint8Array` itself,
+/// the elements of an array, or what an async iterable yields.  Anything
+/// else, or a chunk that is not a `Uint8Array`, is `E_INPUT_TYPE`.
+pub async fn visit_js_chunks(
+    input: &JsValue,
+    mut f: impl FnMut(&js_sys::Uint8Array) -> Result<(), JsValue>,
+) -> Result<(), JsValue> {
+    if let Some(array) = input.dyn_ref::<js_sys::Uint8Array>() {
+        return f(array);
+    }
+    if let Some(array) = input.dyn_ref::<js_sys::Array>() {
+        for value in array.iter() {
+            let chunk: js_sys::Uint8Array = value
+                .dyn_into()
+                .map_err(|_| js_error_code(ErrorCode::InputType, "array element is not a Buffer/Uint8Array"))?;
+            f(&chunk)?;
+        }
+        return Ok(());
+    }
+    let iterator_fn: js_sys::Function = js_sys::Reflect::get(input, &js_sys::Symbol::async_iterator())?
+        .dyn_into()
+        .map_err(|_| js_error_code(ErrorCode::InputType, "expected a Buffer/Uint8Array, an array of them or an async iterable stream"))?;
+    let iter = iterator_fn.call0(input)?;
+    let next: js_sys::Function = js_sys::Reflect::get(&iter, &JsValue::from_str("next"))?.dyn_into()?;
+    loop {
+        let step: js_sys::IteratorNext = JsFuture::from(js_sys::Promise::resolve(&next.call0(&iter)?)).await?.unchecked_into();
+        if step.done() {
+            return Ok(());
+        }
+        let chunk: js_sys::Uint8Array = step
+            .value()
+            .dyn_into()
+            .map_err(|_| js_error_code(ErrorCode::InputType, "stream yielded a string chunk; open it without an encoding"))?;
+        f(&chunk)?;
+    }
+}
@@ -1,14 +1,72 @@
-use std::io::Read;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::{self, Cursor, Read};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU32, Ordering};
 
 use wasm_bindgen::prelude::*;
-use wasm_bindgen_file_reader::WebSysFile;
 
-use crate::decompress::open_file_maybe_gz;
 extern crate console_error_panic_hook;
 mod decompress;
 
+pub mod bins;
+pub mod builder;
+pub mod cache;
+pub mod capabilities;
+pub mod cooperative;
+pub mod coordinator;
+pub mod coverage;
+pub mod error;
+pub mod estimate;
+pub mod export;
+pub mod fasta;
+pub mod gfa;
+pub mod gff;
+pub mod hash;
+pub mod health;
 pub mod htslib;
-use crate::htslib::{compress_bgzf, index_gff_csi, index_fasta_fai, FaidxResult};
+pub mod input;
+pub mod limits;
+pub mod manifest;
+pub mod naming;
+pub mod options;
+pub mod preview;
+pub mod progress;
+#[cfg(feature = "reference")]
+pub mod reference;
+pub mod remote;
+pub mod scan;
+pub mod scrub;
+pub mod search;
+pub mod seed;
+pub mod stats;
+pub mod transform;
+pub mod translate;
+pub mod upload;
+pub mod validate;
+pub mod warnings;
+use crate::error::{io_error, js_error, js_error_code, ErrorCode, PreprocessError};
+use crate::fasta::track::SequenceTrack;
+use crate::fasta::{gaps, orfs, repeats, rna};
+use crate::fasta::split::ContigMap;
+use crate::transform::unique_ids::IdRenames;
+use crate::htslib::{
+    bgzf_compress_level, bgzf_compress_split, csi_index_reader_with, faidx_index_reader, gzip_compress_level, parse_fai, pooled_bytes, tbi_index_reader_with, verify_fasta_index,
+    BgzfReader, BgzfWriter, GzipWriter, TabixConfig,
+};
+use crate::input::{fetch_bytes, read_js_bytes, read_pair, read_pair_stored, FetchOptions, InputSource};
+use crate::preview::{PreviewOptions, PreviewReport};
+use crate::progress::{ContigProgress, Extent};
+use crate::transform::{Pipeline, Pipelines, TransformContext};
+use crate::upload::{HeldOutput, PartLimits, UploadChunks};
+use crate::warnings::Warnings;
+pub use crate::builder::IndexGenBuilder;
+pub use crate::cooperative::CooperativeRun;
+pub use crate::export::{IgvOptions, JBrowseOptions};
+pub use crate::naming::OutputNames;
+pub use crate::gff::gff_preprocess;
+pub use crate::htslib::{BinRollup, FaiOffsets};
+pub use crate::options::{CircularPolicy, Collation, ControlCharPolicy, IndexFormat, IndexGenOptions, MalformedRecordPolicy, OutputContainer, TrackFormat};
 
 #[wasm_bindgen]
 extern "C" {
@@ -21,24 +79,40 @@ extern "C" {
 
 /// Logging wrapper function
 pub fn logw(text : &str, typ : Option<&str>) {
-    if typ.is_some() {
-        log((String::from("mgnify_preprocess::") + typ.unwrap() + "::" + text).as_str());
+    let msg = match typ {
+        Some(typ) => String::from("mgnify_preprocess::") + typ + "::" + text,
+        None => text.to_string(),
+    };
+    // Imported JS functions cannot be called outside the browser (e.g. under
+    // `cargo test` or in a WASI runtime)
+    if cfg!(all(target_arch = "wasm32", not(target_os = "wasi"))) {
+        log(&msg);
     } else {
-        log(text);
+        ::log::info!("{}", msg);
     }
 }
 
 #[wasm_bindgen]
 /// Function that allows to propagate panic error messages when compiling to wasm, see https://github.com/rustwasm/console_error_panic_hook
 pub fn init_panic_hook() {
-    console_error_panic_hook::set_once();
+    health::install_panic_hook();
 }
 
-/// Convert an owned `Vec<u8>` into a JS `Blob` with one copy (Rust heap → JS heap).
-fn vec_to_blob(data: Vec<u8>) -> Result<web_sys::Blob, JsValue> {
-    let arr = js_sys::Uint8Array::from(data.as_slice());
-    let seq = js_sys::Array::of1(&arr);
-    web_sys::Blob::new_with_u8_array_sequence(&seq)
+/// Size of the `Uint8Array` parts a Blob is assembled from.
+const BLOB_PART: usize = 8 << 20;
+
+/// Move `data` into a JS `Blob` with one copy (Rust heap → JS heap).  The
+/// bytes go over in [`BLOB_PART`]-sized `Uint8Array`s, so a gigabyte output
+/// never needs one contiguous JS allocation of its size.  The buffer is
+/// freed only once the Blob exists, so a failed call can be retried.
+fn take_blob(data: &mut Vec<u8>) -> Result<web_sys::Blob, JsValue> {
+    let parts = js_sys::Array::new();
+    for part in data.chunks(BLOB_PART) {
+        parts.push(&js_sys::Uint8Array::from(part));
+    }
+    let blob = web_sys::Blob::new_with_u8_array_sequence(&parts)?;
+    *data = Vec::new();
+    Ok(blob)
 }
 
 #[wasm_bindgen]
@@ -49,122 +123,1468 @@ pub struct IndexGen {
     fasta_gzi: Vec<u8>,
     gff_bgz: Vec<u8>,
     gff_idx: Vec<u8>,
+    /// `.tbi` index; empty unless `index_format` asks for TBI.
+    gff_tbi: Vec<u8>,
+    /// Attribute search index; empty unless `search_keys` was set.
+    gff_search: Vec<u8>,
+    /// Bytes removed from the GFF by the attribute whitelist/blacklist.
+    attribute_bytes_saved: u64,
+    /// Non-fatal issues met while processing.
+    warnings: Warnings,
+    /// Stages of a partial run that failed; their outputs are empty.
+    failures: Vec<StageFailure>,
+    /// Output cache key, set when the instance came from / went to the cache.
+    cache_key: Option<String>,
+    /// The options the outputs were made with; the manifest records their
+    /// digest, seed and collation.
+    options: IndexGenOptions,
+    /// OPFS-backed outputs written by `spill_to_opfs`, in `CACHE_ARTIFACTS`
+    /// order; taken by the Blob accessors.
+    spilled: [Option<web_sys::File>; 7],
+    /// What a preview run covered; `None` for a full run.
+    preview: Option<PreviewReport>,
+    /// Where `split_at_gaps` put each contig.
+    contigs: ContigMap,
+    /// What `unique_ids` renamed.
+    id_renames: IdRenames,
+    /// Feature tracks found in the FASTA (see `fasta/track.rs`), by name.
+    tracks: BTreeMap<&'static str, SequenceTrack>,
 }
 
+/// A stage of a partial run that failed (see [`IndexGen::from_bytes_partial`]).
+#[derive(Clone, Debug)]
+pub struct StageFailure {
+    /// `"fasta"` or `"gff"`.
+    pub stage: &'static str,
+    pub error: PreprocessError,
+}
+
+/// Distinguishes spill directories created in the same millisecond.
+static SPILL_SEQ: AtomicU32 = AtomicU32::new(0);
+
+/// Artifact file names used for cache entries, in `IndexGen` field order.
+const CACHE_ARTIFACTS: [&str; 7] = ["fasta.bgz", "fasta.fai", "fasta.gzi", "gff.bgz", "gff.csi", "gff.tbi", "gff.search"];
+
+
 
 #[wasm_bindgen]
 impl IndexGen {
     /// Constructor/initialiser of the wasm assembler. It also performs the preprocessing.
+    ///
+    /// @deprecated Use `new IndexGenBuilder().run(fa_file, gff_file)`, which
+    /// takes options without adding positional parameters.
+    #[deprecated(note = "use `new IndexGenBuilder().run(fa_file, gff_file)`")]
+    // The export shim wasm-bindgen generates calls it.
+    #[allow(deprecated)]
     pub fn new(fa_file : web_sys::File, gff_file : web_sys::File) -> Self {
-        if cfg!(debug_assertions) {
-            init_panic_hook();
-        }
+        Self::with_options(fa_file, gff_file, &IndexGenOptions::default())
+    }
+
+    /// Like [`IndexGen::new`], with explicit preprocessing options.
+    pub fn with_options(fa_file : web_sys::File, gff_file : web_sys::File, options: &IndexGenOptions) -> Self {
+        health::contain(|| Self::from_sources(InputSource::File(fa_file), InputSource::File(gff_file), options).map_err(js_error))
+            .unwrap_or_else(|e| wasm_bindgen::throw_val(e))
+    }
+
+    /// Downloads both inputs with `fetch()` (ranged, with retries and progress
+    /// messages) and then preprocesses them exactly like [`IndexGen::new`].
+    pub async fn from_urls(fa_url: String, gff_url: String, options: Option<FetchOptions>) -> Result<IndexGen, JsValue> {
+        health::check()?;
+        let options = options.unwrap_or_default();
+
+        logw(&format!("Fetching {}", fa_url), None);
+        let fa_bytes = fetch_bytes(&fa_url, &options, "fetch_fasta").await?;
+        logw(&format!("Fetching {}", gff_url), None);
+        let gff_bytes = fetch_bytes(&gff_url, &options, "fetch_gff").await?;
+
+        health::contain(|| {
+            Self::from_sources(InputSource::Bytes(fa_bytes), InputSource::Bytes(gff_bytes), &IndexGenOptions::default())
+                .map_err(js_error)
+        })
+    }
+
+    /// Node.js entry point: preprocesses `Buffer`s / `Uint8Array`s (plain or
+    /// gzip) without going through `web_sys::File`.
+    pub fn from_buffers(fa: Vec<u8>, gff: Vec<u8>, options: Option<IndexGenOptions>) -> Result<IndexGen, JsValue> {
+        health::contain(|| {
+            Self::from_sources(InputSource::Bytes(fa), InputSource::Bytes(gff), &options.unwrap_or_default()).map_err(js_error)
+        })
+    }
+
+    /// Like [`IndexGen::from_buffers`], each input a `SharedArrayBuffer` (or
+    /// a typed array over one) filled by another thread and read in place,
+    /// a `File`, or an `ArrayBuffer`/typed array, copied (see
+    /// `input/shared.rs`).
+    pub fn from_shared(fa: JsValue, gff: JsValue, options: Option<IndexGenOptions>) -> Result<IndexGen, JsValue> {
+        health::contain(|| {
+            let (fa, gff) = (input::js_input(&fa, "the FASTA input")?, input::js_input(&gff, "the GFF3 input")?);
+            Self::from_sources(fa, gff, &options.unwrap_or_default()).map_err(js_error)
+        })
+    }
+
+    /// Like [`IndexGen::from_buffers`], but returns a handle that runs the
+    /// pipeline `slice_ms` (default 15) milliseconds per `step()`, for hosts
+    /// without a worker (see `cooperative.rs`).
+    pub fn start_cooperative(fa: Vec<u8>, gff: Vec<u8>, options: Option<IndexGenOptions>, slice_ms: Option<f64>) -> Result<CooperativeRun, JsValue> {
+        health::contain(|| {
+            let slice_ms = slice_ms.unwrap_or(cooperative::DEFAULT_SLICE_MS);
+            CooperativeRun::start(fa, gff, &options.unwrap_or_default(), slice_ms).map_err(js_error)
+        })
+    }
+
+    /// Like [`IndexGen::from_buffers`], reading each input from a `Buffer` or
+    /// an async iterable stream (e.g. `fs.createReadStream(path)`).
+    pub async fn from_streams(fa: JsValue, gff: JsValue, options: Option<IndexGenOptions>) -> Result<IndexGen, JsValue> {
+        health::check()?;
+        let fa_bytes = read_js_bytes(&fa, "read_fasta").await?;
+        let gff_bytes = read_js_bytes(&gff, "read_gff").await?;
+        Self::from_buffers(fa_bytes, gff_bytes, options)
+    }
+
+    /// Like [`IndexGen::with_options`], but first looks the inputs up in the
+    /// OPFS output cache (keyed on input SHA-256s + options digest). On a miss
+    /// the pipeline runs and its outputs are stored for next time.
+    pub async fn cached(fa_file : web_sys::File, gff_file : web_sys::File, options: Option<IndexGenOptions>) -> Result<IndexGen, JsValue> {
+        health::check()?;
+        let options = options.unwrap_or_default();
 
-        // Read in files and preprocess
         logw("Reading fasta and gff into memory", None);
-        let mut wf_fa = WebSysFile::new(fa_file);
-        let mut wf_gff = WebSysFile::new(gff_file);
+        let (fa_bytes, gff_bytes) = read_pair(InputSource::File(fa_file), InputSource::File(gff_file)).map_err(js_error)?;
+        let [fa_sha, gff_sha] = hash::sha256_hex_all([&fa_bytes, &gff_bytes], options.webcrypto_hashing).await;
+        let key = cache::cache_key(&fa_sha, &gff_sha, &options.digest());
 
-        let mut fa_reader = open_file_maybe_gz(&mut wf_fa);
-        let mut gff_reader = open_file_maybe_gz(&mut wf_gff);
+        if let Some(artifacts) = cache::load(&key, &CACHE_ARTIFACTS).await? {
+            logw(&format!("Using cached outputs {}", key), None);
+            let [fasta_bgz, fasta_fai, fasta_gzi, gff_bgz, gff_idx, gff_tbi, gff_search]: [Vec<u8>; 7] =
+                artifacts.try_into().map_err(|_| js_error_code(ErrorCode::Cache, "corrupt cache entry"))?;
+            let meta = cache::load_meta(&key).await?.unwrap_or(json::JsonValue::Null);
+            let attribute_bytes_saved = meta["attribute_bytes_saved"].as_u64().unwrap_or(0);
+            let mut tracks = BTreeMap::new();
+            for (name, format) in track_formats(&options) {
+                let files = [format!("{}.bgz", name), format!("{}.csi", name)];
+                let files = cache::load(&key, &files.each_ref().map(String::as_str)).await?;
+                let [bgz, csi]: [Vec<u8>; 2] =
+                    files.and_then(|f| f.try_into().ok()).ok_or_else(|| js_error_code(ErrorCode::Cache, "incomplete cache entry"))?;
+                tracks.insert(name, SequenceTrack::from_parts(name, format, bgz, csi, meta["tracks"][name].clone()));
+            }
+            return Ok(Self {
+                fasta_bgz,
+                fasta_fai,
+                fasta_gzi,
+                gff_bgz,
+                gff_idx,
+                gff_tbi,
+                gff_search,
+                attribute_bytes_saved,
+                warnings: Warnings::from_json(&meta["warnings"]),
+                failures: Vec::new(),
+                cache_key: Some(key),
+                options: options.clone(),
+                spilled: Default::default(),
+                preview: None,
+                contigs: ContigMap::from_json(&meta["contigs"]),
+                id_renames: IdRenames::from_json(&meta["id_renames"]),
+                tracks,
+            });
+        }
 
-        let mut fa_bytes = Vec::new();
-        fa_reader.read_to_end(&mut fa_bytes).expect_throw("fasta read failed");
+        let mut out = health::contain(|| Self::from_bytes(&fa_bytes, &gff_bytes, &options).map_err(js_error))?;
+        let meta = out.cache_meta(fa_sha, gff_sha, &options);
+        let names: Vec<[String; 2]> = out.tracks.keys().map(|name| [format!("{}.bgz", name), format!("{}.csi", name)]).collect();
+        let mut files = out.artifacts().to_vec();
+        for ([bgz, csi], track) in names.iter().zip(out.tracks.values()) {
+            files.extend([(bgz.as_str(), track.bgz_bytes()), (csi.as_str(), track.csi_bytes())]);
+        }
+        cache::store(&key, &files, meta).await?;
+        out.cache_key = Some(key);
+        Ok(out)
+    }
 
-        let mut gff_string = String::new();
-        gff_reader.read_to_string(&mut gff_string).expect_throw("GFF read failed");
-        gff_string = gff_preprocess(&gff_string);
+    /// Rebuilds only the GFF outputs for a new annotation file, reusing the
+    /// FASTA `.bgz`/`.fai`/`.gzi` bytes returned by a previous run.
+    pub fn reindex_gff(
+        fasta_bgz: Vec<u8>,
+        fasta_fai: Vec<u8>,
+        fasta_gzi: Vec<u8>,
+        gff_file: web_sys::File,
+        options: Option<IndexGenOptions>,
+    ) -> Result<IndexGen, JsValue> {
+        health::contain(|| {
+            parse_fai(&fasta_fai)?;
+            let gff_bytes = InputSource::File(gff_file).read_all()?;
+            Self::with_fasta_artifacts(fasta_bgz, fasta_fai, fasta_gzi, &gff_bytes, &options.unwrap_or_default())
+        })
+        .map_err(js_error)
+    }
 
-        // Output fasta files
-        logw("Compressing and indexing fasta", None);
-        // bgzip
-        let fasta_bgz = compress_bgzf(&fa_bytes);
-        // faidx
-        let FaidxResult { fai: fasta_fai, gzi: fasta_gzi } = index_fasta_fai(&fasta_bgz);
-
-        // Output gff files
-        logw("Compressing and indexing gff", None);
-        // bgzip
-        let gff_bgz = compress_bgzf(gff_string.as_bytes());
-        let gff_idx = index_gff_csi(&gff_bgz);
-
-        Self {
-            fasta_bgz,
-            fasta_fai,
-            fasta_gzi,
-            gff_bgz,
-            gff_idx,
+    /// Builds only the indexes (`.fai`, `.gzi`, `.csi`/`.tbi`, search) for a
+    /// FASTA and a sorted GFF3 that are already BGZF (e.g. from `bgzip`),
+    /// reading both files in a streaming fashion without decompressing them
+    /// into memory.  The `*_bgz_blob()` outputs are empty: the inputs are
+    /// the compressed files.  Fails with `E_BGZF_HEADER` for plain gzip and
+    /// `E_GFF_UNSORTED` for an unsorted GFF3.
+    pub fn indexes_only(fa_bgz_file: web_sys::File, gff_bgz_file: web_sys::File, options: Option<IndexGenOptions>) -> Result<IndexGen, JsValue> {
+        health::contain(|| {
+            Self::indexes_from_sources(InputSource::File(fa_bgz_file), InputSource::File(gff_bgz_file), &options.unwrap_or_default())
+        })
+        .map_err(js_error)
+    }
+
+    /// Like [`IndexGen::reindex_gff`], taking the FASTA artifacts from the
+    /// cache entry `cache_key`. The result is stored as a new cache entry.
+    pub async fn reindex_gff_cached(
+        cache_key: String,
+        gff_file: web_sys::File,
+        options: Option<IndexGenOptions>,
+    ) -> Result<IndexGen, JsValue> {
+        health::check()?;
+        let options = options.unwrap_or_default();
+
+        let meta = cache::load_meta(&cache_key).await?
+            .ok_or_else(|| js_error_code(ErrorCode::Cache, &format!("no cache entry {}", cache_key)))?;
+        // The FASTA artifacts are only valid for options that write the same
+        // FASTA bytes, and the new entry is keyed on the FASTA's hash.
+        if meta["fasta_options_digest"].as_str() != Some(options.fasta_digest().as_str()) {
+            let message = format!("cache entry {} was built with different FASTA options", cache_key);
+            return Err(js_error_code(ErrorCode::Cache, &message));
+        }
+        let fa_sha = meta["fasta_sha256"]
+            .as_str()
+            .ok_or_else(|| js_error_code(ErrorCode::Cache, &format!("cache entry {} has no fasta_sha256", cache_key)))?
+            .to_owned();
+        let fasta = cache::load(&cache_key, &CACHE_ARTIFACTS[..3]).await?
+            .ok_or_else(|| js_error_code(ErrorCode::Cache, &format!("incomplete cache entry {}", cache_key)))?;
+        let [fasta_bgz, fasta_fai, fasta_gzi]: [Vec<u8>; 3] =
+            fasta.try_into().map_err(|_| js_error_code(ErrorCode::Cache, "corrupt cache entry"))?;
+
+        let gff_bytes = InputSource::File(gff_file).read_all().map_err(js_error)?;
+        let mut out = health::contain(|| Self::with_fasta_artifacts(fasta_bgz, fasta_fai, fasta_gzi, &gff_bytes, &options))
+            .map_err(js_error)?;
+
+        let [gff_sha] = hash::sha256_hex_all([&gff_bytes], options.webcrypto_hashing).await;
+        let key = cache::cache_key(&fa_sha, &gff_sha, &options.digest());
+        let meta = out.cache_meta(fa_sha, gff_sha, &options);
+        cache::store(&key, &out.artifacts(), meta).await?;
+        out.cache_key = Some(key);
+        Ok(out)
+    }
+
+    /// Like [`IndexGen::with_options`], but a failing FASTA or GFF stage
+    /// does not take the other one's outputs with it: the failed stage's
+    /// outputs are empty and `failures_json()` says why.  Rerun it with
+    /// `retry_fasta` or `retry_gff`.  Fails only if an input cannot be read.
+    pub fn partial(fa_file: web_sys::File, gff_file: web_sys::File, options: Option<IndexGenOptions>) -> Result<IndexGen, JsValue> {
+        health::contain(|| {
+            Self::from_sources_partial(InputSource::File(fa_file), InputSource::File(gff_file), &options.unwrap_or_default())
+        })
+        .map_err(js_error)
+    }
+
+    /// Quick draft outputs for a UI preview: runs the pipeline over only
+    /// the start of each input as `preview` limits it (see `preview.rs`).
+    /// The manifest marks the result partial, each cut input gets a
+    /// `preview_truncated` warning, and `preview_json()` says what was
+    /// covered.
+    pub fn preview(fa_file: web_sys::File, gff_file: web_sys::File, options: Option<IndexGenOptions>, preview: Option<PreviewOptions>) -> Result<IndexGen, JsValue> {
+        health::contain(|| {
+            Self::preview_from_sources(InputSource::File(fa_file), InputSource::File(gff_file), &options.unwrap_or_default(), &preview.unwrap_or_default())
+        })
+        .map_err(js_error)
+    }
+
+    /// What a preview run covered, as the JSON of
+    /// [`PreviewReport::to_json`]; undefined for a full run.
+    pub fn preview_json(&self) -> Option<String> {
+        self.preview.as_ref().map(|p| p.to_json().dump())
+    }
+
+    /// Whether the outputs cover only part of the inputs (a truncated
+    /// preview).
+    pub fn is_partial(&self) -> bool {
+        self.preview.as_ref().is_some_and(|p| p.fasta.truncated || p.gff.truncated)
+    }
+
+    /// The stages that failed, as JSON `[{stage, code, message}]`; empty
+    /// when every stage succeeded.
+    pub fn failures_json(&self) -> String {
+        let failures: Vec<json::JsonValue> = self
+            .failures
+            .iter()
+            .map(|f| json::object! { stage: f.stage, code: f.error.code.as_str(), message: f.error.message.as_str() })
+            .collect();
+        json::JsonValue::from(failures).dump()
+    }
+
+    /// Reruns the FASTA stage over `fa_file` with `options` (e.g. with
+    /// `rewrap_irregular` set after `E_FASTA_LINE_LENGTH`), keeping the GFF
+    /// outputs.  On failure the FASTA outputs are empty and the error is
+    /// both thrown and listed in `failures_json()`.
+    pub fn retry_fasta(&mut self, fa_file: web_sys::File, options: Option<IndexGenOptions>) -> Result<(), JsValue> {
+        health::contain(|| {
+            let fa_bytes = InputSource::File(fa_file).read_all()?;
+            self.retry_fasta_bytes(&fa_bytes, &options.unwrap_or_default())
+        })
+        .map_err(js_error)
+    }
+
+    /// Like [`IndexGen::retry_fasta`], for the GFF stage.
+    pub fn retry_gff(&mut self, gff_file: web_sys::File, options: Option<IndexGenOptions>) -> Result<(), JsValue> {
+        health::contain(|| {
+            let gff_bytes = InputSource::File(gff_file).read_all()?;
+            self.retry_gff_bytes(&gff_bytes, &options.unwrap_or_default())
+        })
+        .map_err(js_error)
+    }
+
+    /// Cache key of this result, if it was loaded from or stored to the cache.
+    pub fn cache_key(&self) -> Option<String> {
+        self.cache_key.clone()
+    }
+
+    /// Bytes removed from the GFF by `attribute_whitelist`/`attribute_blacklist`.
+    pub fn attribute_bytes_saved(&self) -> u64 {
+        self.attribute_bytes_saved
+    }
+
+    /// Non-fatal issues met while processing, as the JSON of
+    /// [`Warnings::to_json`]: `{total, counts, stages, warnings: [{code,
+    /// stage, line, message}]}`.
+    pub fn warnings_json(&self) -> String {
+        self.warnings.to_json().dump()
+    }
+
+    /// Where `split_at_gaps` put each contig, as the JSON of
+    /// [`ContigMap::to_json`]: `{min_gap, scaffolds: [{name, length,
+    /// contigs: [{name, start, end}]}]}`, with 1-based inclusive positions on
+    /// the scaffold.  `min_gap` is `null` when no FASTA was split.
+    pub fn contig_map_json(&self) -> String {
+        self.contigs.to_json().dump()
+    }
+
+    /// Every `ID` the `unique_ids` transform changed and every `Parent` or
+    /// `Derives_from` value it rewrote, as the JSON of
+    /// [`IdRenames::to_json`]: `{features: [{seqid, type, id, new_id,
+    /// line}], references: [{line, attribute, id, new_id}]}`.  Unlike the
+    /// `id_renamed` warnings, the list is not capped.
+    pub fn id_renames_json(&self) -> String {
+        self.id_renames.to_json().dump()
+    }
+
+    /// Names of the feature tracks found in the FASTA and not taken yet:
+    /// `gaps` (`gap_track`), `orfs` (`orf_track`), `repeats` (`repeat_track`),
+    /// `rna` (`rna_track`).
+    pub fn track_names(&self) -> Vec<String> {
+        self.tracks.keys().map(|name| name.to_string()).collect()
+    }
+
+    /// Moves the feature track `name` out (see `fasta/track.rs`):
+    /// `undefined` unless its option was set, or once taken.
+    pub fn take_track(&mut self, name: &str) -> Option<SequenceTrack> {
+        self.tracks.remove(name)
+    }
+
+    /// Reads a depth file (`samtools depth` or
+    /// `jgi_summarize_bam_contig_depths` output, plain or gzip) of the
+    /// FASTA of this run into the `coverage` track, a bedGraph with its
+    /// `.csi` (see `coverage.rs`), compressed at the `compression_level` of
+    /// `options`.  Needs the `.fai`, so call it before taking that.
+    pub fn add_coverage(&mut self, depth_file: web_sys::File, options: Option<IndexGenOptions>) -> Result<(), JsValue> {
+        health::contain(|| {
+            let depth = InputSource::File(depth_file).read_all()?;
+            self.add_coverage_bytes(&depth, &options.unwrap_or_default())
+        })
+        .map_err(js_error)
+    }
+
+    /// Reads a `contig  bin` table (plain or gzip) of the FASTA of this run
+    /// into the `bins` track, a BED9 coloured by bin with its `.csi` (see
+    /// `bins.rs`), compressed at the `compression_level` of `options`.
+    /// Reads the compressed FASTA for the GC of every bin, so call it before
+    /// taking that.
+    pub fn add_bins(&mut self, bins_file: web_sys::File, options: Option<IndexGenOptions>) -> Result<(), JsValue> {
+        health::contain(|| {
+            let tsv = InputSource::File(bins_file).read_all()?;
+            self.add_bins_bytes(&tsv, &options.unwrap_or_default())
+        })
+        .map_err(js_error)
+    }
+
+    /// Reads a GFA1 assembly graph (plain or gzip) of the FASTA of this run
+    /// and returns the JSON of [`Gfa::report`](gfa::Gfa::report): links,
+    /// dead ends and components, and which segments match the sequences of
+    /// the `.fai` by name and length.  Call it before taking the `.fai`.
+    pub fn check_gfa(&self, gfa_file: web_sys::File) -> Result<String, JsValue> {
+        health::contain(|| {
+            let gfa = InputSource::File(gfa_file).read_all()?;
+            Ok(self.check_gfa_bytes(&gfa)?.dump())
+        })
+        .map_err(js_error)
+    }
+
+    /// The JSON of `manifest.json` for the outputs not taken yet: sizes,
+    /// SHA-256, format checks and the producing version, options digest and
+    /// seed (see `manifest.rs`).
+    pub fn manifest_json(&self) -> String {
+        self.manifest().dump()
+    }
+
+    /// [`IndexGen::manifest_json`] with the file name each output is saved
+    /// under in `names` (see `naming.rs`).
+    pub fn manifest_json_named(&self, names: &OutputNames) -> String {
+        self.manifest_named(names).dump()
+    }
+
+    /// JBrowse 2 config (JSON) for these outputs served as `options`
+    /// describes (see `export/jbrowse.rs`).  Build it before taking the GFF3
+    /// output: its text supplies the track metadata and refName aliases.
+    pub fn jbrowse_config(&self, options: &JBrowseOptions) -> Result<String, JsValue> {
+        health::contain(|| self.jbrowse_config_json(options).map(|config| config.dump()).map_err(js_error))
+    }
+
+    /// JBrowse text search index (`.ix` and `.ixx`) of the GFF3 output
+    /// for the track of the config `options` describes (see
+    /// `export/trix.rs`).  Build it before taking the GFF3 output.
+    pub fn trix_index(&self, options: &JBrowseOptions) -> Result<export::TrixIndex, JsValue> {
+        health::contain(|| self.trix_index_files(options)).map_err(js_error)
+    }
+
+    /// Suggested display colours and priorities per feature type and
+    /// source of the GFF3 output, as JSON (see `export/styles.rs`).  Build
+    /// it before taking the GFF3 output.
+    pub fn track_styles(&self) -> Result<String, JsValue> {
+        health::contain(|| self.track_styles_json().map(|styles| styles.dump()).map_err(js_error))
+    }
+
+    /// igv.js session (JSON) for these outputs at the URLs `options`
+    /// builds (see `export/igv.rs`).
+    pub fn igv_session(&self, options: &IgvOptions) -> Result<String, JsValue> {
+        health::contain(|| self.igv_session_json(options).map(|session| session.dump()).map_err(js_error))
+    }
+
+    /// "Check my files": parse, validate and sort the inputs without
+    /// compressing or indexing. Returns the JSON of `dry_run_bytes`, with
+    /// the mean depths of `depth_file` when given (see
+    /// [`IndexGen::dry_run_with_depth`]).
+    pub fn dry_run(
+        fa_file: web_sys::File,
+        gff_file: web_sys::File,
+        options: Option<IndexGenOptions>,
+        depth_file: Option<web_sys::File>,
+    ) -> Result<String, JsValue> {
+        health::contain(|| {
+            logw("Reading fasta and gff into memory", None);
+            let (fa_bytes, gff_bytes) = read_pair(InputSource::File(fa_file), InputSource::File(gff_file))?;
+            let depth = depth_file.map(|file| InputSource::File(file).read_all()).transpose()?;
+            Ok(Self::dry_run_with_depth(&fa_bytes, &gff_bytes, depth.as_deref(), &options.unwrap_or_default()).dump())
+        })
+        .map_err(js_error)
+    }
+
+    /// [`IndexGen::dry_run`] over `Buffer`s / `Uint8Array`s, for Node.js.
+    pub fn dry_run_buffers(fa: Vec<u8>, gff: Vec<u8>, options: Option<IndexGenOptions>, depth: Option<Vec<u8>>) -> Result<String, JsValue> {
+        health::contain(|| {
+            let (fa_bytes, gff_bytes) = read_pair(InputSource::Bytes(fa), InputSource::Bytes(gff))?;
+            let depth = depth.map(|bytes| InputSource::Bytes(bytes).read_all()).transpose()?;
+            Ok(Self::dry_run_with_depth(&fa_bytes, &gff_bytes, depth.as_deref(), &options.unwrap_or_default()).dump())
+        })
+        .map_err(js_error)
+    }
+
+    /// Moves the bytes of one output out (a `Uint8Array`, i.e. what Node's
+    /// `fs.writeFile` takes), by its name: `fasta.bgz`, `fasta.fai`,
+    /// `fasta.gzi`, `gff.bgz`, `gff.csi`, `gff.tbi` or `gff.search`.
+    /// Undefined for an unknown name; empty once taken or disposed.
+    pub fn take_artifact(&mut self, name: &str) -> Option<Vec<u8>> {
+        let i = CACHE_ARTIFACTS.iter().position(|a| *a == name)?;
+        Some(std::mem::take(self.buffers_mut()[i]))
+    }
+
+    /// Moves one output out (like `take_artifact`, spilled outputs
+    /// included) as an `UploadChunks` cutting it into `chunk_size`-byte
+    /// chunks with a CRC32 each (see `upload.rs`).  Throws
+    /// `E_INVALID_OPTION` for an unknown name or a zero chunk size.
+    pub fn upload_chunks(&mut self, name: &str, chunk_size: u32) -> Result<UploadChunks, JsValue> {
+        health::contain(|| {
+            upload::check_chunk_size(chunk_size as u64)?;
+            let (artifact, output) = self.take_held_output(name)?;
+            UploadChunks::new(artifact, output, chunk_size as u64)
+        })
+        .map_err(js_error)
+    }
+
+    /// Like [`IndexGen::upload_chunks`], sizing the chunks as parts of a
+    /// multipart upload within `limits`, cut at BGZF block starts so each
+    /// part decompresses on its own.  Throws `E_INVALID_OPTION` when the
+    /// output cannot be cut within them.
+    pub fn upload_parts(&mut self, name: &str, limits: &PartLimits) -> Result<UploadChunks, JsValue> {
+        health::contain(|| {
+            let (artifact, output) = self.take_held_output(name)?;
+            UploadChunks::parts(artifact, output, limits)
+        })
+        .map_err(js_error)
+    }
+
+    /// Frees every output buffer not taken yet, so forgetting an accessor
+    /// does not keep hundreds of MB alive until the JS handle is collected.
+    /// Safe to call more than once; afterwards the accessors return empty
+    /// data and `warnings_json()` still works.  Spilled files stay in OPFS
+    /// until `spill_clear()`.
+    pub fn dispose(&mut self) {
+        for buf in self.buffers_mut() {
+            *buf = Vec::new();
+        }
+        self.spilled = Default::default();
+    }
+
+    /// Moves every output still held in wasm memory into an OPFS file
+    /// (written in 8 MiB pieces) and frees its buffer.  The Blob accessors
+    /// then return the OPFS-backed `File`, which the browser reads from
+    /// disk: a multi-gigabyte output costs neither wasm memory nor an
+    /// in-memory Blob, whose size browsers cap.  `take_artifact` returns
+    /// empty data for spilled outputs.  The files stay until
+    /// `spill_clear()`.
+    pub async fn spill_to_opfs(&mut self) -> Result<(), JsValue> {
+        health::check()?;
+        let id = format!("{}-{}", js_sys::Date::now() as u64, SPILL_SEQ.fetch_add(1, Ordering::Relaxed));
+        let pending: Vec<usize> = (0..CACHE_ARTIFACTS.len()).filter(|&i| !self.buffers()[i].is_empty()).collect();
+        let artifacts: Vec<(&str, &[u8])> = pending.iter().map(|&i| self.artifacts()[i]).collect();
+        let files = cache::spill(&id, &artifacts).await?;
+        for (i, file) in pending.into_iter().zip(files) {
+            *self.buffers_mut()[i] = Vec::new();
+            self.spilled[i] = Some(file);
+        }
+        logw(&format!("Spilled outputs to OPFS {}", id), None);
+        Ok(())
+    }
+
+    /// JSON `{retained_bytes, artifacts: {name: bytes}, spilled_bytes,
+    /// pooled_bytes, wasm_memory_bytes}`: the bytes this result still holds
+    /// per artifact (zero once taken, disposed or spilled), the size of the
+    /// spilled files not taken yet, the block and line buffers kept for
+    /// reuse by the next run, and the size of the whole wasm linear memory
+    /// (`null` outside wasm).  Linear memory never shrinks; freed bytes are
+    /// reused by later runs.
+    pub fn memory_usage(&self) -> String {
+        let mut artifacts = json::JsonValue::new_object();
+        for (name, bytes) in CACHE_ARTIFACTS.iter().zip(self.buffers()) {
+            artifacts[*name] = bytes.capacity().into();
+        }
+        let spilled_bytes: u64 = self.spilled.iter().flatten().map(|f| f.size() as u64).sum();
+        json::object! {
+            retained_bytes: self.retained_bytes(),
+            artifacts: artifacts,
+            spilled_bytes: spilled_bytes,
+            pooled_bytes: pooled_bytes(),
+            wasm_memory_bytes: wasm_memory_bytes(),
         }
+        .dump()
     }
 
-    /// Returns the BGZF-compressed FASTA as a Blob. Drains the field; call once.
+    /// Returns the BGZF-compressed FASTA as a Blob. Drains the field; empty once
+    /// taken or disposed.
     pub fn fasta_bgz_blob(&mut self) -> Result<web_sys::Blob, JsValue> {
-        vec_to_blob(std::mem::take(&mut self.fasta_bgz))
+        self.take_output_blob(0)
     }
 
-    /// Returns the FASTA `.fai` index as a Blob. Drains the field; call once.
+    /// Returns the FASTA `.fai` index as a Blob. Drains the field; empty once taken
+    /// or disposed.
     pub fn fasta_fai_blob(&mut self) -> Result<web_sys::Blob, JsValue> {
-        vec_to_blob(std::mem::take(&mut self.fasta_fai))
+        self.take_output_blob(1)
     }
 
-    /// Returns the FASTA `.gzi` block index as a Blob. Drains the field; call once.
+    /// Returns the FASTA `.gzi` block index as a Blob. Drains the field; empty once
+    /// taken or disposed.
     pub fn fasta_gzi_blob(&mut self) -> Result<web_sys::Blob, JsValue> {
-        vec_to_blob(std::mem::take(&mut self.fasta_gzi))
+        self.take_output_blob(2)
     }
 
-    /// Returns the BGZF-compressed GFF3 as a Blob. Drains the field; call once.
+    /// Returns the BGZF-compressed GFF3 as a Blob. Drains the field; empty once
+    /// taken or disposed.
     pub fn gff_bgz_blob(&mut self) -> Result<web_sys::Blob, JsValue> {
-        vec_to_blob(std::mem::take(&mut self.gff_bgz))
+        self.take_output_blob(3)
     }
 
-    /// Returns the GFF3 `.csi` tabix index as a Blob. Drains the field; call once.
+    /// Returns the GFF3 `.csi` tabix index as a Blob; empty when `index_format`
+    /// is `tbi`. Drains the field; empty once taken or disposed.
     pub fn gff_csi_blob(&mut self) -> Result<web_sys::Blob, JsValue> {
-        vec_to_blob(std::mem::take(&mut self.gff_idx))
+        self.take_output_blob(4)
+    }
+
+    /// Returns the GFF3 `.tbi` tabix index as a Blob; empty unless `index_format`
+    /// is `tbi` or `both`. Drains the field; empty once taken or disposed.
+    pub fn gff_tbi_blob(&mut self) -> Result<web_sys::Blob, JsValue> {
+        self.take_output_blob(5)
+    }
+
+    /// Returns the attribute search index (load it with `new SearchIndex(bytes)`)
+    /// as a Blob; empty unless `search_keys` was set. Drains the field; empty once
+    /// taken or disposed.
+    pub fn gff_search_blob(&mut self) -> Result<web_sys::Blob, JsValue> {
+        self.take_output_blob(6)
     }
 }
 
-// Reorders start for indexing and removes sequence if present
-pub fn gff_preprocess(gff_string: &str) -> String {
-    let mut outbuf = String::new();
-    let mut records: Vec<&str> = Vec::new();
+impl IndexGen {
+    /// Runs the whole pipeline over the given inputs.  An input that is
+    /// already an output of this pipeline (BGZF, and for the GFF3 sorted
+    /// without a `##FASTA` section) is kept as it is and only indexed.
+    pub fn from_sources(fa_source: InputSource, gff_source: InputSource, options: &IndexGenOptions) -> io::Result<Self> {
+        // Read in files and preprocess
+        logw("Reading fasta and gff into memory", None);
+        let (fa, gff) = read_pair_stored(fa_source, gff_source)?;
+        Self::run(&fa.bytes, &gff.bytes, [fa.bgzf, gff.bgzf], options, &Pipelines::from_options(options)?)
+    }
+
+    /// Runs the pipeline over already-decompressed FASTA and GFF3 bytes.
+    pub fn from_bytes(fa_bytes: &[u8], gff_bytes: &[u8], options: &IndexGenOptions) -> io::Result<Self> {
+        Self::from_bytes_with(fa_bytes, gff_bytes, options, &Pipelines::from_options(options)?)
+    }
 
-    for line in gff_string.split('\n') {
-        if line.starts_with("##FASTA") {
-            break;
+    /// Like [`IndexGen::from_bytes`], preprocessing the inputs with
+    /// `pipelines` instead of the ones `options` describe (see
+    /// `transform.rs`).
+    pub fn from_bytes_with(fa_bytes: &[u8], gff_bytes: &[u8], options: &IndexGenOptions, pipelines: &Pipelines) -> io::Result<Self> {
+        Self::run(fa_bytes, gff_bytes, [None, None], options, pipelines)
+    }
+
+    /// The pipeline over decompressed inputs, with `stored` the BGZF files
+    /// they came from, if any.  A stored file is the output as it is when
+    /// preprocessing leaves its contents alone and, for the GFF3, they are
+    /// already in index order: it is only indexed, with an
+    /// `already_preprocessed` warning.
+    fn run(fa_bytes: &[u8], gff_bytes: &[u8], stored: [Option<Vec<u8>>; 2], options: &IndexGenOptions, pipelines: &Pipelines) -> io::Result<Self> {
+        if cfg!(debug_assertions) && cfg!(all(target_arch = "wasm32", not(target_os = "wasi"))) {
+            init_panic_hook();
+        }
+        let mut warnings = Warnings::default();
+        check_options(options, &mut warnings);
+        let mut contigs = ContigMap::default();
+        let mut id_renames = IdRenames::default();
+        let [fa_stored, gff_stored] = stored;
+        let fa = prepare_fasta(fa_bytes, options, &pipelines.fasta, &mut warnings, &mut contigs)?;
+        // A stored file that will not be kept is dropped right away.
+        let fa_stored = fa_stored.filter(|_| options.fasta_output == OutputContainer::Bgzf && *fa == *fa_bytes);
+        if fa_stored.is_some() {
+            let message = kept_as_is("the FASTA is already bgzipped and needs no preprocessing; kept as it is and indexed", options);
+            warnings.push("fasta", "already_preprocessed", None, message);
         }
-        if line.starts_with('#') {
-            outbuf.push_str(line);
-            outbuf.push('\n');
-        } else if !line.is_empty() {
-            records.push(line);
+        let gff_stored = gff_stored.filter(|_| options.gff_output == OutputContainer::Bgzf);
+        let tracks = fasta_tracks(&fa, options)?;
+        let prepare = |warnings: &mut Warnings| {
+            let gff = prepare_gff(gff_bytes, options, &pipelines.gff, warnings, &mut contigs, &mut id_renames)?;
+            let unchanged = gff.as_bytes() == gff_bytes;
+            Ok((gff, gff_stored.filter(|_| unchanged)))
+        };
+        let (fasta, gff) = run_branches(Ok(fa), fa_stored, prepare, options, &mut warnings);
+        // A GFF3 failure is only reported once the FASTA branch succeeded.
+        let (fasta_bgz, fasta_fai, fasta_gzi) = fasta?;
+        let gff = gff?;
+        let mut out = Self::from_parts(fasta_bgz, fasta_fai, fasta_gzi, gff, warnings, options);
+        out.contigs = contigs;
+        out.id_renames = id_renames;
+        out.tracks = tracks;
+        Ok(out)
+    }
+
+    /// The pipeline over the start of each input only (see
+    /// [`IndexGen::preview`]).
+    pub fn preview_from_sources(fa_source: InputSource, gff_source: InputSource, options: &IndexGenOptions, preview: &PreviewOptions) -> io::Result<Self> {
+        logw("Reading the start of fasta and gff into memory", None);
+        let (fa_bytes, gff_bytes, report) = preview::read_preview(&fa_source, &gff_source, preview)?;
+        let mut out = Self::from_bytes(&fa_bytes, &gff_bytes, options)?;
+        for (stage, cut) in [("fasta", &report.fasta), ("gff", &report.gff)] {
+            if cut.truncated {
+                let message = format!("preview covers only the first {} bytes ({} sequences) of the input", cut.bytes, cut.sequences);
+                out.warnings.push(stage, "preview_truncated", None, message);
+            }
         }
+        out.preview = Some(report);
+        Ok(out)
     }
 
-    // Emulating `sort -k1,1d -k4,4n -k5,5n`
-    records.sort_by(|a, b| {
-        let a_fields: Vec<&str> = a.split('\t').collect();
-        let b_fields: Vec<&str> = b.split('\t').collect();
+    /// Like [`IndexGen::from_sources`], keeping the outputs of the stage
+    /// that succeeded when the other fails (see
+    /// [`IndexGen::from_bytes_partial`]).  Reading the inputs still fails
+    /// the whole run.
+    pub fn from_sources_partial(fa_source: InputSource, gff_source: InputSource, options: &IndexGenOptions) -> io::Result<Self> {
+        logw("Reading fasta and gff into memory", None);
+        let (fa_bytes, gff_bytes) = read_pair(fa_source, gff_source)?;
+        Ok(Self::from_bytes_partial(&fa_bytes, &gff_bytes, options))
+    }
 
-        // k1,1d - dictionary order on field 1 (index 0)
-        a_fields[0].cmp(&b_fields[0])
-            // k4,4n - numeric on field 4 (index 3)
-            .then_with(|| {
-                let a4: i64 = a_fields[3].parse().unwrap_or(0);
-                let b4: i64 = b_fields[3].parse().unwrap_or(0);
-                a4.cmp(&b4)
-            })
-            // k5,5n - numeric on field 5 (index 4)
-            .then_with(|| {
-                let a5: i64 = a_fields[4].parse().unwrap_or(0);
-                let b5: i64 = b_fields[4].parse().unwrap_or(0);
-                a5.cmp(&b5)
-            })
-    });
+    /// Like [`IndexGen::from_bytes`], but each branch completes on its own:
+    /// a failing one leaves its outputs empty and is listed in
+    /// [`IndexGen::failures`], and the other's outputs are kept.  Rerun a
+    /// failed branch with [`IndexGen::retry_fasta_bytes`] or
+    /// [`IndexGen::retry_gff_bytes`].
+    pub fn from_bytes_partial(fa_bytes: &[u8], gff_bytes: &[u8], options: &IndexGenOptions) -> Self {
+        let mut warnings = Warnings::default();
+        check_options(options, &mut warnings);
+        let mut failures = Vec::new();
+        let mut contigs = ContigMap::default();
+        let mut id_renames = IdRenames::default();
+        let fa = Pipeline::fasta(options).and_then(|pipeline| prepare_fasta(fa_bytes, options, &pipeline, &mut warnings, &mut contigs));
+        let (fa, tracks) = match fa.and_then(|fa| Ok((fasta_tracks(&fa, options)?, fa))) {
+            Ok((tracks, fa)) => (Ok(fa), tracks),
+            Err(e) => (Err(e), BTreeMap::new()),
+        };
+        let prepare = |warnings: &mut Warnings| {
+            let pipeline = Pipeline::gff(options)?;
+            Ok((prepare_gff(gff_bytes, options, &pipeline, warnings, &mut contigs, &mut id_renames)?, None))
+        };
+        let (fasta, gff) = run_branches(fa, None, prepare, options, &mut warnings);
+        let (fasta_bgz, fasta_fai, fasta_gzi) = salvage("fasta", fasta, &mut failures);
+        let gff = salvage("gff", gff, &mut failures);
+        let mut out = Self::from_parts(fasta_bgz, fasta_fai, fasta_gzi, gff, warnings, options);
+        out.failures = failures;
+        out.contigs = contigs;
+        out.id_renames = id_renames;
+        out.tracks = tracks;
+        out
+    }
 
-    for rec in &records {
-        outbuf.push_str(rec);
-        outbuf.push('\n');
+    /// Reruns the FASTA branch with `options`, replacing its outputs,
+    /// warnings and failure; the GFF outputs are kept.  `options` may only
+    /// differ from those of the run in options the GFF3 branch does not
+    /// read (see [`IndexGenOptions::differences_read_by`]), else this fails
+    /// with `E_INVALID_OPTION` and changes nothing; the manifest then
+    /// records the digest of these options.  With `split_at_gaps`, retry
+    /// the GFF branch after it, as the contigs may have changed.
+    pub fn retry_fasta_bytes(&mut self, fa_bytes: &[u8], options: &IndexGenOptions) -> io::Result<()> {
+        self.check_retry("fasta", options)?;
+        let mut warnings = Warnings::default();
+        check_options(options, &mut warnings);
+        let mut contigs = ContigMap::default();
+        let mut tracks = BTreeMap::new();
+        let result = Pipeline::fasta(options).and_then(|pipeline| fasta_branch(fa_bytes, options, &pipeline, &mut warnings, &mut contigs, &mut tracks));
+        self.contigs = contigs;
+        self.tracks = tracks;
+        let (fasta_bgz, fasta_fai, fasta_gzi) = self.retried("fasta", 0..3, warnings, options, result)?;
+        (self.fasta_bgz, self.fasta_fai, self.fasta_gzi) = (fasta_bgz, fasta_fai, fasta_gzi);
+        self.shrink_buffers();
+        Ok(())
     }
 
-    outbuf
+    /// Like [`IndexGen::retry_fasta_bytes`], for the GFF branch: `options`
+    /// may only differ in options the FASTA branch does not read.
+    pub fn retry_gff_bytes(&mut self, gff_bytes: &[u8], options: &IndexGenOptions) -> io::Result<()> {
+        self.check_retry("gff", options)?;
+        let mut warnings = Warnings::default();
+        check_options(options, &mut warnings);
+        let mut contigs = self.contigs.clone();
+        let mut id_renames = IdRenames::default();
+        let result = Pipeline::gff(options).and_then(|pipeline| gff_branch(gff_bytes, options, &pipeline, &mut warnings, &mut contigs, &mut id_renames));
+        self.attribute_bytes_saved = 0;
+        self.id_renames = id_renames;
+        let gff = self.retried("gff", 3..7, warnings, options, result)?;
+        (self.gff_bgz, self.gff_idx, self.gff_tbi, self.gff_search) = (gff.bgz, gff.csi, gff.tbi, gff.search);
+        self.attribute_bytes_saved = gff.attribute_bytes_saved;
+        self.shrink_buffers();
+        Ok(())
+    }
+
+    /// Fails with `E_INVALID_OPTION` if rerunning `stage` with `options`
+    /// would leave the other branch's outputs made with other options.
+    fn check_retry(&self, stage: &str, options: &IndexGenOptions) -> io::Result<()> {
+        let other = if stage == "fasta" { "gff" } else { "fasta" };
+        let changed = self.options.differences_read_by(options, other);
+        if changed.is_empty() {
+            return Ok(());
+        }
+        Err(io_error(
+            ErrorCode::InvalidOption,
+            format!("retrying the {} stage cannot change {}, which the {} outputs depend on; rerun both stages", stage, changed.join(", "), other),
+        ))
+    }
+
+    /// Forget what `stage` produced (the outputs at `outputs`, in
+    /// `CACHE_ARTIFACTS` order, its warnings, the option warnings and its
+    /// failure) and record the rerun's warnings, which include the option
+    /// warnings of `options`, and outcome.
+    fn retried<T>(&mut self, stage: &'static str, outputs: Range<usize>, warnings: Warnings, options: &IndexGenOptions, result: io::Result<T>) -> io::Result<T> {
+        for i in outputs {
+            *self.buffers_mut()[i] = Vec::new();
+            self.spilled[i] = None;
+        }
+        self.warnings.remove_stage("options");
+        self.warnings.remove_stage(stage);
+        self.warnings.extend(warnings);
+        self.failures.retain(|f| f.stage != stage);
+        if let Err(e) = &result {
+            record_failure(stage, e, &mut self.failures);
+        }
+        self.cache_key = None;
+        self.options = options.clone();
+        result
+    }
+
+    /// Stages of a partial run that failed, in pipeline order.
+    pub fn failures(&self) -> &[StageFailure] {
+        &self.failures
+    }
+
+    /// Runs only the GFF branch (sort, bgzip, CSI), reusing FASTA artifacts
+    /// produced by an earlier run.
+    pub fn with_fasta_artifacts(
+        fasta_bgz: Vec<u8>,
+        fasta_fai: Vec<u8>,
+        fasta_gzi: Vec<u8>,
+        gff_bytes: &[u8],
+        options: &IndexGenOptions,
+    ) -> io::Result<Self> {
+        let mut warnings = Warnings::default();
+        check_options(options, &mut warnings);
+        let mut id_renames = IdRenames::default();
+        let gff = gff_branch(gff_bytes, options, &Pipeline::gff(options)?, &mut warnings, &mut ContigMap::default(), &mut id_renames)?;
+        let mut out = Self::from_parts(fasta_bgz, fasta_fai, fasta_gzi, gff, warnings, options);
+        out.id_renames = id_renames;
+        Ok(out)
+    }
+
+    /// Index-only mode: builds the `.fai`/`.gzi` and the GFF3 indexes
+    /// (`index_format`, `search_keys`) over files that are already BGZF,
+    /// the GFF3 sorted.  Nothing is decompressed into memory, sorted or
+    /// recompressed; each input is streamed once per index.  The
+    /// `fasta.bgz`/`gff.bgz` outputs stay empty, and options that change
+    /// the compressed files are ignored.
+    pub fn indexes_from_sources(fa_bgz: InputSource, gff_bgz: InputSource, options: &IndexGenOptions) -> io::Result<Self> {
+        let mut warnings = Warnings::default();
+        check_options(options, &mut warnings);
+        progress::report("fasta", 0, None);
+        logw("Indexing fasta", None);
+        let (mut fasta_fai, mut fasta_gzi) = (Vec::new(), Vec::new());
+        let fa_reader = open_bgzf(&fa_bgz, "FASTA")?.track(ContigProgress::new("faidx", Extent::default()));
+        faidx_index_reader(fa_reader, &mut fasta_fai, &mut fasta_gzi, options.fai_offsets)?;
+        progress::report("gff", 0, None);
+        logw("Indexing gff", None);
+        let (csi, tbi, search) = index_gff(|| open_bgzf(&gff_bgz, "GFF3"), Extent::default(), options, &mut warnings)?;
+        let gff = GffBranch { bgz: Vec::new(), csi, tbi, search, attribute_bytes_saved: 0 };
+        Ok(Self::from_parts(Vec::new(), fasta_fai, fasta_gzi, gff, warnings, options))
+    }
+
+    fn from_parts(fasta_bgz: Vec<u8>, fasta_fai: Vec<u8>, fasta_gzi: Vec<u8>, gff: GffBranch, warnings: Warnings, options: &IndexGenOptions) -> Self {
+        let mut out = Self {
+            fasta_bgz,
+            fasta_fai,
+            fasta_gzi,
+            gff_bgz: gff.bgz,
+            gff_idx: gff.csi,
+            gff_tbi: gff.tbi,
+            gff_search: gff.search,
+            attribute_bytes_saved: gff.attribute_bytes_saved,
+            warnings,
+            failures: Vec::new(),
+            cache_key: None,
+            options: options.clone(),
+            spilled: Default::default(),
+            preview: None,
+            contigs: ContigMap::default(),
+            id_renames: IdRenames::default(),
+            tracks: BTreeMap::new(),
+        };
+        out.shrink_buffers();
+        out
+    }
+
+    /// Growing writers leave up to half of each buffer unused.
+    fn shrink_buffers(&mut self) {
+        for buf in self.buffers_mut() {
+            buf.shrink_to_fit();
+        }
+    }
+
+    /// Non-fatal issues met while processing.
+    pub fn warnings(&self) -> &Warnings {
+        &self.warnings
+    }
+
+    /// [`IndexGen::contig_map_json`] natively.
+    pub fn contig_map(&self) -> &ContigMap {
+        &self.contigs
+    }
+
+    /// [`IndexGen::id_renames_json`] natively.
+    pub fn id_renames(&self) -> &IdRenames {
+        &self.id_renames
+    }
+
+    /// The feature track `name`, when its option asked for one (see
+    /// [`IndexGen::track_names`]).
+    pub fn track(&self, name: &str) -> Option<&SequenceTrack> {
+        self.tracks.get(name)
+    }
+
+    /// [`IndexGen::add_coverage`] for a depth file already decompressed.
+    pub fn add_coverage_bytes(&mut self, depth: &[u8], options: &IndexGenOptions) -> io::Result<()> {
+        let lengths: Vec<(String, u64)> = parse_fai(&self.fasta_fai)?.into_iter().map(|r| (r.name, r.length)).collect();
+        logw("Writing the coverage track", None);
+        let track = coverage::Coverage::parse(depth, &lengths)?.track(options.compression_level)?;
+        self.tracks.insert("coverage", track);
+        Ok(())
+    }
+
+    /// [`IndexGen::add_bins`] for a bins table already decompressed.
+    pub fn add_bins_bytes(&mut self, tsv: &[u8], options: &IndexGenOptions) -> io::Result<()> {
+        let sequences = bins::sequence_composition(Cursor::new(&self.fasta_bgz))?;
+        logw("Writing the bins track", None);
+        let track = bins::Bins::parse(tsv, sequences)?.track(options.compression_level)?;
+        self.tracks.insert("bins", track);
+        Ok(())
+    }
+
+    /// [`IndexGen::check_gfa`] for a graph already decompressed.
+    pub fn check_gfa_bytes(&self, gfa: &[u8]) -> io::Result<json::JsonValue> {
+        let lengths: Vec<(String, u64)> = parse_fai(&self.fasta_fai)?.into_iter().map(|r| (r.name, r.length)).collect();
+        Ok(gfa::Gfa::parse(gfa, false)?.report(&lengths))
+    }
+
+    /// [`IndexGen::manifest_json`] as a JSON value.
+    pub fn manifest(&self) -> json::JsonValue {
+        let mut manifest = manifest::manifest(&self.artifacts(), &self.options.digest(), self.options.seed);
+        manifest["producer"]["collation"] = self.options.gff_collation.as_str().into();
+        if let Some(preview) = &self.preview {
+            manifest["partial"] = self.is_partial().into();
+            manifest["preview"] = preview.to_json();
+        }
+        manifest
+    }
+
+    /// [`IndexGen::manifest_json_named`] as a JSON value.
+    pub fn manifest_named(&self, names: &OutputNames) -> json::JsonValue {
+        let mut manifest = self.manifest();
+        manifest::name_files(&mut manifest, names);
+        manifest
+    }
+
+    /// [`IndexGen::jbrowse_config`] as a JSON value.
+    pub fn jbrowse_config_json(&self, options: &JBrowseOptions) -> io::Result<json::JsonValue> {
+        export::jbrowse_config(options, &self.fasta_fai, self.gff_text()?.as_deref(), self.gff_index())
+    }
+
+    /// [`IndexGen::trix_index`] natively.
+    pub fn trix_index_files(&self, options: &JBrowseOptions) -> io::Result<export::TrixIndex> {
+        let gff = self.gff_text()?.ok_or_else(|| io_error(ErrorCode::InvalidOption, "the text index needs the GFF3 output, which was taken or not produced"))?;
+        Ok(export::TrixIndex::build(&gff, &options.track_id()))
+    }
+
+    /// [`IndexGen::track_styles`] as a JSON value.
+    pub fn track_styles_json(&self) -> io::Result<json::JsonValue> {
+        let gff = self.gff_text()?.unwrap_or_default();
+        Ok(export::track_styles(&stats::GffStats::from_gff(&gff)))
+    }
+
+    /// The GFF3 output decompressed, unless it was taken or not produced.
+    fn gff_text(&self) -> io::Result<Option<String>> {
+        if self.gff_bgz.is_empty() {
+            return Ok(None);
+        }
+        let mut text = Vec::new();
+        limits::read_to_end_within(flate2::read::MultiGzDecoder::new(&self.gff_bgz[..]), &mut text, limits::input_budget(), limits::Measure::Decompressed, "the GFF3 output")?;
+        String::from_utf8(text).map(Some).map_err(|_| io_error(ErrorCode::GffEncoding, "GFF is not valid UTF-8"))
+    }
+
+    /// [`IndexGen::igv_session`] as a JSON value.
+    pub fn igv_session_json(&self, options: &IgvOptions) -> io::Result<json::JsonValue> {
+        export::igv_session(options, &self.fasta_fai, self.gff_index())
+    }
+
+    /// The GFF3 index a browser should load: the CSI if there is one.
+    fn gff_index(&self) -> Option<IndexFormat> {
+        match (self.gff_idx.is_empty(), self.gff_tbi.is_empty()) {
+            (false, _) => Some(IndexFormat::Csi),
+            (true, false) => Some(IndexFormat::Tbi),
+            (true, true) => None,
+        }
+    }
+
+    /// `meta.json` contents for storing this result in the output cache.
+    fn cache_meta(&self, fasta_sha256: String, gff_sha256: String, options: &IndexGenOptions) -> json::JsonValue {
+        json::object! {
+            fasta_sha256: fasta_sha256,
+            gff_sha256: gff_sha256,
+            options_digest: options.digest(),
+            fasta_options_digest: options.fasta_digest(),
+            attribute_bytes_saved: self.attribute_bytes_saved,
+            warnings: self.warnings.to_json(),
+            contigs: self.contigs.to_json(),
+            id_renames: self.id_renames.to_json(),
+            tracks: self.tracks.iter().map(|(name, track)| (*name, track.stats().clone())).collect::<json::object::Object>(),
+        }
+    }
+
+    /// Dry run: parse, validate and sort already-decompressed inputs without
+    /// compressing or indexing them.  Returns `{valid, validation, stats,
+    /// conflicts, warnings}` with the report of
+    /// [`validate::ValidationReport::to_json`], `stats: {fasta, gff,
+    /// gff_dialect, sorted_gff_bytes}`, the overlaps of
+    /// [`gff::conflict_report`] and the warnings GTF conversion and sorting
+    /// raised (see [`Warnings::to_json`]).
+    pub fn dry_run_bytes(fa_bytes: &[u8], gff_bytes: &[u8], options: &IndexGenOptions) -> json::JsonValue {
+        Self::dry_run_with_depth(fa_bytes, gff_bytes, None, options)
+    }
+
+    /// [`IndexGen::dry_run_bytes`], adding `stats.coverage`, the
+    /// [`Coverage::to_json`](coverage::Coverage::to_json) figures of the
+    /// depth file `depth`, when given.  A depth file that cannot be read
+    /// leaves it `null` with a `depth_ignored` warning.
+    pub fn dry_run_with_depth(fa_bytes: &[u8], gff_bytes: &[u8], depth: Option<&[u8]>, options: &IndexGenOptions) -> json::JsonValue {
+        let report = validate::validate(fa_bytes, gff_bytes, options.circular_features, options.genetic_code);
+        let gff_string = String::from_utf8_lossy(gff_bytes);
+        let dialect = gff::detect_dialect(&gff_string);
+        let mut warnings = Warnings::default();
+        check_options(options, &mut warnings);
+        let pipeline = Pipeline::gff(options).unwrap_or_else(|e| {
+            warnings.push("options", "gff_transforms_ignored", None, format!("{}; using the default pipeline", e));
+            Pipeline::gff(&IndexGenOptions { gff_transforms: Vec::new(), ..options.clone() }).unwrap_or_default()
+        });
+        // Unplaceable records and origin-crossing features under the
+        // "error" policies are already in the report; sort what is there.
+        let cx = &mut TransformContext { options, warnings: &mut warnings, contigs: &mut ContigMap::default(), id_renames: &mut IdRenames::default() };
+        let gff_string = pipeline.run_lenient(gff_string, cx);
+        let gff_stats = stats::GffStats::from_gff(&gff_string);
+        let ties = gff_tie_break(options).unwrap_or_else(|e| {
+            warnings.push("options", "gff_sort_keys_ignored", None, format!("{}; ignored", e));
+            gff::TieBreak::default().with_collation(options.gff_collation)
+        });
+        // Sorting malformed records is meaningless (and costly); only sort
+        // what a real run would accept.
+        let sorted_gff_bytes = if report.is_valid() {
+            gff::SortedGff::with_ties(&gff_string, options.repair_coordinates, &ties, &mut warnings).output_len(attribute_filter(options))
+        } else {
+            0
+        };
+        let coverage = depth.and_then(|depth| match coverage::Coverage::parse(depth, &coverage::sequence_lengths(fa_bytes)) {
+            Ok(coverage) => Some(coverage.to_json()),
+            Err(e) => {
+                warnings.push("fasta", "depth_ignored", None, format!("depth file not read: {}", e));
+                None
+            }
+        });
+        json::object! {
+            valid: report.is_valid(),
+            validation: report.to_json(),
+            stats: {
+                fasta: stats::FastaStats::with_min_gap(fa_bytes, u64::from(options.gap_min_length.max(1))).to_json(),
+                coverage: coverage,
+                gff: gff_stats.to_json(),
+                gff_dialect: dialect.as_str(),
+                sorted_gff_bytes: sorted_gff_bytes,
+            },
+            conflicts: gff::conflict_report(&gff_string),
+            warnings: warnings.to_json(),
+        }
+    }
+
+    /// Heap bytes held by the output buffers not yet taken or disposed.
+    pub fn retained_bytes(&self) -> usize {
+        self.buffers().iter().map(|b| b.capacity()).sum()
+    }
+
+    /// Output `i` (in `CACHE_ARTIFACTS` order) as a Blob: its spilled file,
+    /// or its buffer moved into a new Blob.
+    fn take_output_blob(&mut self, i: usize) -> Result<web_sys::Blob, JsValue> {
+        match self.spilled[i].take() {
+            Some(file) => Ok(file.into()),
+            None => take_blob(self.buffers_mut()[i]),
+        }
+    }
+
+    /// Moves the output `name` out: its spilled file, or its buffer.
+    fn take_held_output(&mut self, name: &str) -> io::Result<(&'static str, HeldOutput)> {
+        let i = CACHE_ARTIFACTS
+            .iter()
+            .position(|a| *a == name)
+            .ok_or_else(|| io_error(ErrorCode::InvalidOption, format!("unknown output {:?}; expected one of {}", name, CACHE_ARTIFACTS.join(", "))))?;
+        let output = match self.spilled[i].take() {
+            Some(file) => HeldOutput::Spilled(file),
+            None => HeldOutput::Bytes(std::mem::take(self.buffers_mut()[i])),
+        };
+        Ok((CACHE_ARTIFACTS[i], output))
+    }
+
+    /// The output buffers in `CACHE_ARTIFACTS` order.
+    fn buffers(&self) -> [&Vec<u8>; 7] {
+        [&self.fasta_bgz, &self.fasta_fai, &self.fasta_gzi, &self.gff_bgz, &self.gff_idx, &self.gff_tbi, &self.gff_search]
+    }
+
+    fn buffers_mut(&mut self) -> [&mut Vec<u8>; 7] {
+        [
+            &mut self.fasta_bgz,
+            &mut self.fasta_fai,
+            &mut self.fasta_gzi,
+            &mut self.gff_bgz,
+            &mut self.gff_idx,
+            &mut self.gff_tbi,
+            &mut self.gff_search,
+        ]
+    }
+
+    /// Output artifacts paired with their cache file names.
+    pub fn artifacts(&self) -> [(&str, &[u8]); 7] {
+        [
+            (CACHE_ARTIFACTS[0], &self.fasta_bgz),
+            (CACHE_ARTIFACTS[1], &self.fasta_fai),
+            (CACHE_ARTIFACTS[2], &self.fasta_gzi),
+            (CACHE_ARTIFACTS[3], &self.gff_bgz),
+            (CACHE_ARTIFACTS[4], &self.gff_idx),
+            (CACHE_ARTIFACTS[5], &self.gff_tbi),
+            (CACHE_ARTIFACTS[6], &self.gff_search),
+        ]
+    }
+}
+
+/// Size of the wasm linear memory, `None` when not running as wasm.
+fn wasm_memory_bytes() -> Option<usize> {
+    #[cfg(target_arch = "wasm32")]
+    return Some(core::arch::wasm32::memory_size(0) * 65536);
+    #[cfg(not(target_arch = "wasm32"))]
+    None
+}
+
+/// Warn about option values that were adjusted rather than rejected.
+fn check_options(options: &IndexGenOptions, warnings: &mut Warnings) {
+    if options.compression_level > 9 {
+        warnings.push(
+            "options",
+            "compression_level_clamped",
+            None,
+            format!("compression level {} is out of range, using 9", options.compression_level),
+        );
+    }
+}
+
+/// The `already_preprocessed` warning `message`, saying that a
+/// non-default `compression_level` did not apply to the kept file.
+fn kept_as_is(message: &str, options: &IndexGenOptions) -> String {
+    match options.compression_level {
+        level if level == IndexGenOptions::default().compression_level => message.to_owned(),
+        level => format!("{} (compression_level {} was not applied)", message, level),
+    }
+}
+
+/// Reader over BGZF output this run just wrote.
+fn written<'a>(bgz: &'a [u8], options: &IndexGenOptions) -> BgzfReader<&'a [u8]> {
+    BgzfReader::new(bgz).verify_crc(options.verify_written_crc)
+}
+
+/// bgzip + faidx: returns `(fasta_bgz, fasta_fai, fasta_gzi)`.
+fn fasta_branch(
+    fa_bytes: &[u8],
+    options: &IndexGenOptions,
+    pipeline: &Pipeline<[u8]>,
+    warnings: &mut Warnings,
+    contigs: &mut ContigMap,
+    tracks: &mut BTreeMap<&'static str, SequenceTrack>,
+) -> io::Result<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+    let fa = prepare_fasta(fa_bytes, options, pipeline, warnings, contigs)?;
+    *tracks = fasta_tracks(&fa, options)?;
+    write_fasta(&fa, None, options)
+}
+
+/// The feature tracks `options` ask for, by name, with their formats.
+fn track_formats(options: &IndexGenOptions) -> Vec<(&'static str, TrackFormat)> {
+    [("gaps", options.gap_track), ("orfs", options.orf_track), ("repeats", options.repeat_track), ("rna", options.rna_track)]
+        .into_iter()
+        .filter(|(_, format)| *format != TrackFormat::Off)
+        .collect()
+}
+
+/// The feature tracks `options` ask for, found in the prepared FASTA.
+fn fasta_tracks(fa: &[u8], options: &IndexGenOptions) -> io::Result<BTreeMap<&'static str, SequenceTrack>> {
+    let mut out = BTreeMap::new();
+    for (name, format) in track_formats(options) {
+        logw(&format!("Writing the {} track", name), None);
+        let level = options.compression_level;
+        let track = match name {
+            "gaps" => gaps::gap_track(fa, u64::from(options.gap_min_length.max(1)), format, level)?,
+            "orfs" => orfs::orf_track(fa, u64::from(options.orf_min_length.max(1)), options.genetic_code, format, level)?,
+            "repeats" => {
+                let (homopolymer, tandem) = (options.homopolymer_min_length.max(1), options.tandem_min_length.max(1));
+                repeats::repeat_track(fa, u64::from(homopolymer), u64::from(tandem), format, level)?
+            }
+            _ => rna::rna_track(fa, format, level)?,
+        };
+        out.insert(name, track);
+    }
+    Ok(out)
+}
+
+/// The read phase of the FASTA branch: the control character policy and
+/// the transform pipeline, which records any contigs it splits in `contigs`.
+fn prepare_fasta<'a>(
+    fa_bytes: &'a [u8],
+    options: &IndexGenOptions,
+    pipeline: &Pipeline<[u8]>,
+    warnings: &mut Warnings,
+    contigs: &mut ContigMap,
+) -> io::Result<Cow<'a, [u8]>> {
+    progress::report("fasta", 0, None);
+    let fa_bytes = scrub::scrub_fasta(fa_bytes, options.control_chars, warnings)?;
+    pipeline.run(fa_bytes, &mut TransformContext { options, warnings, contigs, id_renames: &mut IdRenames::default() })
+}
+
+/// The write phase of the FASTA branch: compression and indexing, or only
+/// indexing of `stored`, the BGZF file `fa_bytes` came from unchanged.
+fn write_fasta(fa_bytes: &[u8], stored: Option<Vec<u8>>, options: &IndexGenOptions) -> io::Result<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+    let mut fasta_bgz = Vec::new();
+    let (mut fasta_fai, mut fasta_gzi) = (Vec::new(), Vec::new());
+    if options.fasta_output == OutputContainer::Gzip {
+        logw("Compressing fasta as plain gzip (no index)", None);
+        gzip_compress_level(fa_bytes, &mut fasta_bgz, options.compression_level)?;
+        return Ok((fasta_bgz, fasta_fai, fasta_gzi));
+    }
+    if let Some(stored) = stored {
+        logw("FASTA is already bgzipped; indexing it as it is", None);
+        fasta_bgz = stored;
+    } else {
+        logw("Compressing and indexing fasta", None);
+        if cfg!(feature = "threads") && fa_bytes.len() >= 2 * MIN_FASTA_PIECE {
+            fasta_bgz = bgzf_compress_split(fa_bytes, &piece_splits(fa_bytes.len()), options.compression_level)?;
+        } else {
+            bgzf_compress_level(fa_bytes, &mut fasta_bgz, options.compression_level)?;
+        }
+    }
+    let extent = Extent { bytes: Some(fa_bytes.len() as u64), contigs: Some(contig_count(fa_bytes)) };
+    let reader = written(&fasta_bgz, options).track(ContigProgress::new("faidx", extent));
+    faidx_index_reader(reader, &mut fasta_fai, &mut fasta_gzi, options.fai_offsets)?;
+    if options.fai_offsets == FaiOffsets::Uncompressed {
+        verify_fasta_index(&fasta_bgz, &fasta_fai, &fasta_gzi)
+            .map_err(|e| io_error(ErrorCode::Internal, format!("internal .fai/.gzi cross-check failed: {}", e)))?;
+    }
+    Ok((fasta_bgz, fasta_fai, fasta_gzi))
+}
+
+/// Least FASTA bytes worth a compression thread of their own.
+const MIN_FASTA_PIECE: usize = 4 << 20;
+
+/// Number of `>` header lines of a FASTA.
+fn contig_count(fa: &[u8]) -> u64 {
+    let mut count = u64::from(fa.first() == Some(&b'>'));
+    let mut at = 0;
+    while let Some(nl) = scan::find_byte(&fa[at..], b'\n') {
+        at += nl + 1;
+        count += u64::from(fa.get(at) == Some(&b'>'));
+    }
+    count
+}
+
+/// Where to split a FASTA of `len` bytes to compress it in parallel (see
+/// [`bgzf_compress_split`], which moves each split to a block boundary):
+/// one piece per core, each at least [`MIN_FASTA_PIECE`] long.
+fn piece_splits(len: usize) -> Vec<usize> {
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let pieces = cores.min(len / MIN_FASTA_PIECE).max(1);
+    (1..pieces).map(|i| len / pieces * i).collect()
+}
+
+/// Both branches from the prepared FASTA `fa`: with `parallel_branches`
+/// in a build that allows it, the GFF3 read phase (`prepare_gff`, which
+/// returns the stored file to keep, if any) and then both write phases
+/// side by side (see `coordinator.rs`); otherwise the FASTA write phase
+/// first, dropping `fa` before the GFF3 is read, so only one branch's
+/// buffers are held at a time.  A branch whose read phase failed passes
+/// its error through.  Warnings come out in the same order either way.
+#[allow(clippy::type_complexity)]
+fn run_branches<'g>(
+    fa: io::Result<Cow<[u8]>>,
+    fa_stored: Option<Vec<u8>>,
+    prepare_gff: impl FnOnce(&mut Warnings) -> io::Result<(Cow<'g, str>, Option<Vec<u8>>)>,
+    options: &IndexGenOptions,
+    warnings: &mut Warnings,
+) -> (io::Result<(Vec<u8>, Vec<u8>, Vec<u8>)>, io::Result<GffBranch>) {
+    if !(options.parallel_branches && cfg!(feature = "threads")) {
+        let fasta = fa.and_then(|fa| write_fasta(&fa, fa_stored, options));
+        let gff = prepare_gff(warnings).and_then(|(gff, stored)| write_gff(&gff, stored, options, warnings));
+        return (fasta, gff);
+    }
+    let gff = prepare_gff(warnings);
+    let mut gff_warnings = Warnings::default();
+    let (fasta, gff) = coordinator::join(
+        options.parallel_branches,
+        || fa.and_then(|fa| write_fasta(&fa, fa_stored, options)),
+        || gff.and_then(|(gff, stored)| write_gff(&gff, stored, options, &mut gff_warnings)),
+    );
+    warnings.extend(gff_warnings);
+    (fasta, gff)
+}
+
+/// A BGZF reader over `source` from its start; [`ErrorCode::BgzfHeader`]
+/// unless it begins with a BGZF block.  `what` names the input.
+fn open_bgzf<'a>(source: &'a InputSource, what: &str) -> io::Result<BgzfReader<Box<dyn Read + 'a>>> {
+    let mut raw = source.open_raw()?;
+    let mut header = Vec::with_capacity(18);
+    (&mut raw).take(18).read_to_end(&mut header)?;
+    if !htslib::plausible_block_header(&header) {
+        return Err(io_error(
+            ErrorCode::BgzfHeader,
+            format!("the {} is not BGZF; run the full pipeline to compress it with bgzip framing", what),
+        ));
+    }
+    Ok(BgzfReader::new(Box::new(io::Cursor::new(header).chain(raw))))
+}
+
+/// The outputs of a branch, or empty ones with its failure recorded.
+fn salvage<T: Default>(stage: &'static str, result: io::Result<T>, failures: &mut Vec<StageFailure>) -> T {
+    result.unwrap_or_else(|e| {
+        record_failure(stage, &e, failures);
+        T::default()
+    })
+}
+
+/// Log the failure of `stage` and add it to `failures`.
+fn record_failure(stage: &'static str, e: &io::Error, failures: &mut Vec<StageFailure>) {
+    logw(&format!("The {} stage failed: {}", stage, e), Some("error"));
+    failures.push(StageFailure { stage, error: PreprocessError::from_io(e) });
+}
+
+/// Outputs of [`gff_branch`].
+#[derive(Default)]
+struct GffBranch {
+    bgz: Vec<u8>,
+    csi: Vec<u8>,
+    tbi: Vec<u8>,
+    search: Vec<u8>,
+    attribute_bytes_saved: u64,
+}
+
+/// The attribute projection selected by `options`.
+fn attribute_filter(options: &IndexGenOptions) -> gff::AttributeFilter<'_> {
+    gff::AttributeFilter { whitelist: &options.attribute_whitelist, blacklist: &options.attribute_blacklist }
+}
+
+/// Order of GFF3 seqids and of records with identical coordinates.
+fn gff_tie_break(options: &IndexGenOptions) -> io::Result<gff::TieBreak<'_>> {
+    Ok(gff::TieBreak::parse(&options.gff_sort_keys, &options.gff_type_priority)?.with_collation(options.gff_collation))
+}
+
+/// Write the sorted, projected GFF3 to `out`; returns the bytes the
+/// projection removed.
+fn write_sorted_gff<W: io::Write>(sorted: &gff::SortedGff, options: &IndexGenOptions, out: W) -> io::Result<u64> {
+    let filter = attribute_filter(options);
+    let mut out = io::BufWriter::with_capacity(1 << 16, out);
+    let saved = sorted.write_to(&mut out, filter)?;
+    out.into_inner().map_err(io::IntoInnerError::into_error)?;
+    if !filter.keeps_all() {
+        logw(&format!("Attribute projection removed {} bytes", saved), None);
+    }
+    Ok(saved)
+}
+
+/// Sort, project attributes, bgzip and CSI/TBI-index the GFF3.
+fn gff_branch(
+    gff_bytes: &[u8],
+    options: &IndexGenOptions,
+    pipeline: &Pipeline<str>,
+    warnings: &mut Warnings,
+    contigs: &mut ContigMap,
+    id_renames: &mut IdRenames,
+) -> io::Result<GffBranch> {
+    write_gff(&prepare_gff(gff_bytes, options, pipeline, warnings, contigs, id_renames)?, None, options, warnings)
+}
+
+/// The read phase of the GFF3 branch: the control character policy, UTF-8
+/// decoding and the transform pipeline, which moves features onto the
+/// contigs the FASTA was split into, if any, and records in `id_renames`
+/// what `unique_ids` renamed.
+fn prepare_gff<'a>(
+    gff_bytes: &'a [u8],
+    options: &IndexGenOptions,
+    pipeline: &Pipeline<str>,
+    warnings: &mut Warnings,
+    contigs: &mut ContigMap,
+    id_renames: &mut IdRenames,
+) -> io::Result<Cow<'a, str>> {
+    let not_utf8 = || io_error(ErrorCode::GffEncoding, "GFF is not valid UTF-8");
+    let gff_string = match scrub::scrub_gff(gff_bytes, options.control_chars, warnings)? {
+        Cow::Borrowed(bytes) => Cow::Borrowed(std::str::from_utf8(bytes).map_err(|_| not_utf8())?),
+        Cow::Owned(bytes) => Cow::Owned(String::from_utf8(bytes).map_err(|_| not_utf8())?),
+    };
+    pipeline.run(gff_string, &mut TransformContext { options, warnings, contigs, id_renames })
+}
+
+/// The write phase of the GFF3 branch.  The sorted lines are borrowed from
+/// the text and streamed into the compressor, so no sorted copy of it is
+/// held.  `stored` is the BGZF file `gff_string` came from unchanged; when
+/// sorting would write the text back as it is, that file is only indexed.
+fn write_gff(gff_string: &str, stored: Option<Vec<u8>>, options: &IndexGenOptions, warnings: &mut Warnings) -> io::Result<GffBranch> {
+    let sorted = gff::SortedGff::with_ties(gff_string, options.repair_coordinates, &gff_tie_break(options)?, warnings);
+    let contigs = Some(sorted.seqid_count());
+    if let Some(gff_bgz) = stored.filter(|_| sorted.writes_input(attribute_filter(options))) {
+        drop(sorted);
+        logw("GFF is already bgzipped in index order; indexing it as it is", None);
+        let message = kept_as_is("the GFF3 is already bgzipped in index order; kept as it is and indexed", options);
+        warnings.push("gff", "already_preprocessed", None, message);
+        let extent = Extent { bytes: Some(gff_string.len() as u64), contigs };
+        let (csi, tbi, search) = index_gff(|| Ok(written(&gff_bgz, options)), extent, options, warnings)?;
+        return Ok(GffBranch { bgz: gff_bgz, csi, tbi, search, attribute_bytes_saved: 0 });
+    }
+    if sorted.was_in_order() {
+        logw("GFF already in index order; streaming it without sorting", None);
+    }
+
+    progress::report("gff", 0, None);
+    if options.gff_output == OutputContainer::Gzip {
+        logw("Compressing gff as plain gzip (no index)", None);
+        let mut writer = GzipWriter::with_level(Vec::new(), options.compression_level);
+        let attribute_bytes_saved = write_sorted_gff(&sorted, options, &mut writer)?;
+        let gff_bgz = writer.finish()?;
+        return Ok(GffBranch { bgz: gff_bgz, csi: Vec::new(), tbi: Vec::new(), search: Vec::new(), attribute_bytes_saved });
+    }
+    logw("Compressing and indexing gff", None);
+    let mut writer = BgzfWriter::with_level(Vec::new(), options.compression_level);
+    let attribute_bytes_saved = write_sorted_gff(&sorted, options, &mut writer)?;
+    let gff_bgz = writer.finish()?;
+    drop(sorted);
+    let bytes = htslib::gzip_members(&gff_bgz)?.last().map(|m| m.uncompressed_offset + m.uncompressed_len);
+    let (csi, tbi, search) = index_gff(|| Ok(written(&gff_bgz, options)), Extent { bytes, contigs }, options, warnings)?;
+    Ok(GffBranch { bgz: gff_bgz, csi, tbi, search, attribute_bytes_saved })
+}
+
+/// Warn about a `.csi` of `n_lvls` levels deeper than tabix's, or with a
+/// bin holding many chunks.
+fn check_csi(n_lvls: u32, csi: &[u8], warnings: &mut Warnings) -> io::Result<()> {
+    if n_lvls > htslib::CSI_N_LVLS {
+        warnings.push(
+            "gff",
+            "csi_depth_increased",
+            None,
+            format!("records end beyond 2^38, so the .csi has {} levels instead of tabix's {}; readers that assume the default may not load it", n_lvls, htslib::CSI_N_LVLS),
+        );
+    }
+    let density = htslib::CsiIndex::from_bgzf(csi)?.density();
+    if let Some((seq, bin, chunks)) = density.fullest_bin.filter(|f| f.2 > htslib::DENSE_BIN_CHUNKS) {
+        warnings.push(
+            "gff",
+            "dense_index",
+            None,
+            format!("bin {} of {} holds {} chunks (long features spread along the sequence), so region queries over it read as many ranges", bin, seq, chunks),
+        );
+    }
+    Ok(())
+}
+
+/// The CSI, TBI and search indexes `options` ask for, over a BGZF GFF3 that
+/// `open` reads from the start, once per index; `extent` is what is known
+/// of it for progress.
+fn index_gff<R: Read>(
+    open: impl Fn() -> io::Result<BgzfReader<R>>,
+    extent: Extent,
+    options: &IndexGenOptions,
+    warnings: &mut Warnings,
+) -> io::Result<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+    let (mut gff_idx, mut gff_tbi) = (Vec::new(), Vec::new());
+    if options.index_format != IndexFormat::Tbi {
+        let n_lvls = csi_index_reader_with(open()?.track(ContigProgress::new("csi", extent)), &mut gff_idx, &TabixConfig::GFF, options.bin_rollup)?;
+        check_csi(n_lvls, &gff_idx, warnings)?;
+    }
+    if options.index_format != IndexFormat::Csi {
+        tbi_index_reader_with(open()?.track(ContigProgress::new("tbi", extent)), &mut gff_tbi, &TabixConfig::GFF, options.bin_rollup)?;
+    }
+    let gff_search = if options.search_keys.is_empty() {
+        Vec::new()
+    } else {
+        logw("Building attribute search index", None);
+        search::build_search_index_reader(open()?, &options.search_keys)?
+    };
+    Ok((gff_idx, gff_tbi, gff_search))
 }
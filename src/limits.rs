@@ -0,0 +1,111 @@
+//! Size limits of 32-bit (wasm32) builds.
+//!
+//! wasm32 linear memory stops at 4 GiB, and the pipeline holds each input
+//! decompressed together with its compressed output and indexes.  Rather
+//! than trapping on a failed allocation halfway through a multi-gigabyte
+//! assembly, inputs are read against a budget and every allocation whose
+//! size comes from the input is made fallibly, so a run that cannot fit
+//! fails up front with [`ErrorCode::InputTooLarge`] and says what to do
+//! instead.  Sizes are `u64` everywhere until they index memory; 64-bit
+//! native builds have no budget.
+
+use std::io::{self, Read};
+
+use crate::error::{io_error, ErrorCode};
+
+/// Linear memory a wasm32 module can address.
+pub const WASM32_MEMORY_BYTES: u64 = 4 << 30;
+
+/// Decompressed input (FASTA and GFF3 together) a 32-bit build accepts,
+/// leaving the rest of [`WASM32_MEMORY_BYTES`] for the outputs.
+pub const INPUT_BUDGET_32: u64 = 3 << 30;
+
+/// Bytes read per step by [`read_to_end_within`].
+const READ_STEP: usize = 1 << 20;
+
+/// Decompressed input bytes this build accepts per run.
+pub fn input_budget() -> u64 {
+    if cfg!(target_pointer_width = "32") {
+        INPUT_BUDGET_32
+    } else {
+        u64::MAX
+    }
+}
+
+/// What to do about an input that does not fit.
+const GUIDANCE: &str = "run the native mgnify-preprocess CLI on it, or split the assembly into smaller files";
+
+/// Which size of an input went over a budget.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Measure {
+    /// The bytes as read (gzipped or not), before decompression.
+    Stored,
+    /// The decompressed text.
+    Decompressed,
+}
+
+impl Measure {
+    fn describe(self) -> &'static str {
+        match self {
+            Measure::Stored => "as stored",
+            Measure::Decompressed => "decompressed",
+        }
+    }
+}
+
+/// [`ErrorCode::InputTooLarge`] for `what`, at least `len` bytes measured
+/// as `measure`.
+pub fn too_large(what: &str, len: u64, measure: Measure, budget: u64) -> io::Error {
+    io_error(
+        ErrorCode::InputTooLarge,
+        format!("{} is at least {} bytes {}, more than the {} bytes this build accepts; {}", what, len, measure.describe(), budget, GUIDANCE),
+    )
+}
+
+/// `len` as a `usize`, or [`ErrorCode::InputTooLarge`] when it does not fit
+/// the address space.
+pub fn to_usize(len: u64, what: &str) -> io::Result<usize> {
+    usize::try_from(len)
+        .map_err(|_| io_error(ErrorCode::InputTooLarge, format!("{} needs {} bytes, more than this build can address; {}", what, len, GUIDANCE)))
+}
+
+/// Reserve room for `additional` more bytes in `buf`, failing with
+/// [`ErrorCode::InputTooLarge`] instead of aborting when memory runs out.
+pub fn try_reserve(buf: &mut Vec<u8>, additional: u64, what: &str) -> io::Result<()> {
+    buf.try_reserve(to_usize(additional, what)?).map_err(|_| {
+        let wanted = buf.len() as u64 + additional;
+        io_error(ErrorCode::InputTooLarge, format!("out of memory holding {} bytes of {}; {}", wanted, what, GUIDANCE))
+    })
+}
+
+/// Like `read_to_end`, stopping with [`ErrorCode::InputTooLarge`] once
+/// `out` would exceed `budget` bytes or cannot grow; `measure` says what
+/// the reader yields, for the message.
+pub fn read_to_end_within<R: Read>(mut reader: R, out: &mut Vec<u8>, budget: u64, measure: Measure, what: &str) -> io::Result<()> {
+    loop {
+        if out.len() as u64 >= budget {
+            // One more byte proves the input is over budget rather than exactly on it.
+            if reader.read(&mut [0u8])? == 0 {
+                return Ok(());
+            }
+            return Err(too_large(what, out.len() as u64 + 1, measure, budget));
+        }
+        let step = (budget - out.len() as u64).min(READ_STEP as u64) as usize;
+        if out.capacity() - out.len() < step {
+            try_reserve(out, out.len().max(step) as u64, what)?;
+        }
+        let start = out.len();
+        out.resize(start + step, 0);
+        let n = loop {
+            match reader.read(&mut out[start..]) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                other => break other,
+            }
+        };
+        let n = n.inspect_err(|_| out.truncate(start))?;
+        out.truncate(start + n);
+        if n == 0 {
+            return Ok(());
+        }
+    }
+}
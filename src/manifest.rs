@@ -0,0 +1,95 @@
+//! `manifest.json`: the outputs of a run with their sizes, SHA-256 and a
+//! check that each one's bytes are the format its name says, plus what
+//! produced them, so ingestion can verify an upload server-side:
+//!
+//! ```text
+//! {"manifest_version":1,
+//!  "producer":{"name":"mgnify-wasm","version":"0.1.0","options_digest":"…","seed":0},
+//!  "artifacts":[{"name":"fasta.bgz","size":123,"sha256":"…","format":"bgzf","magic_ok":true},…],
+//!  "valid":true}
+//! ```
+//!
+//! Outputs that were not produced (or were already taken) are not listed.
+//! A run's manifest also records the GFF3 seqid `collation` under
+//! `producer`, which decides the record order.
+//! With [`OutputNames`] each entry also has the `file_name` it is saved
+//! under (see `naming.rs`).
+
+use std::io::{Cursor, Read};
+
+use crate::decompress::GZ_MAGIC;
+use crate::hash::sha256_hex;
+use crate::naming::OutputNames;
+use crate::htslib::{parse_fai, parse_gzi, plausible_block_header, BgzfReader, EOF_BLOCK};
+use crate::search::SearchIndex;
+
+/// File name of the manifest next to the outputs.
+pub const MANIFEST_NAME: &str = "manifest.json";
+
+/// Bumped when a field changes meaning.
+const MANIFEST_VERSION: u32 = 1;
+
+/// A BGZF file: a block header at the start and the EOF block at the end.
+fn is_bgzf(data: &[u8]) -> bool {
+    plausible_block_header(data) && data.ends_with(&EOF_BLOCK)
+}
+
+/// A BGZF file whose contents start with `magic`.
+fn bgzf_starts_with(data: &[u8], magic: &[u8]) -> bool {
+    let mut head = vec![0u8; magic.len()];
+    is_bgzf(data) && BgzfReader::new(Cursor::new(data)).read_exact(&mut head).is_ok() && head == magic
+}
+
+/// Format of the output `name` and whether `data` passes its check.
+fn check(name: &str, data: &[u8]) -> (&'static str, bool) {
+    match name {
+        "fasta.bgz" | "gff.bgz" if data.starts_with(&GZ_MAGIC) && !plausible_block_header(data) => ("gzip", true),
+        "fasta.bgz" | "gff.bgz" => ("bgzf", is_bgzf(data)),
+        "fasta.fai" => ("fai", parse_fai(data).is_ok_and(|records| !records.is_empty())),
+        "fasta.gzi" => ("gzi", parse_gzi(data).is_ok_and(|entries| 8 + 16 * (entries.len() - 1) == data.len())),
+        "gff.csi" => ("csi", bgzf_starts_with(data, b"CSI\x01")),
+        "gff.tbi" => ("tbi", bgzf_starts_with(data, b"TBI\x01")),
+        "gff.search" => ("gsi", SearchIndex::parse(data).is_ok()),
+        _ => ("unknown", false),
+    }
+}
+
+/// The manifest of `artifacts` (name, bytes), produced with the options
+/// whose digest is `options_digest` and the seed `seed`.
+pub fn manifest(artifacts: &[(&str, &[u8])], options_digest: &str, seed: u32) -> json::JsonValue {
+    let mut valid = true;
+    let entries: Vec<json::JsonValue> = artifacts
+        .iter()
+        .filter(|(_, data)| !data.is_empty())
+        .map(|&(name, data)| {
+            let (format, magic_ok) = check(name, data);
+            valid &= magic_ok;
+            json::object! {
+                name: name,
+                size: data.len(),
+                sha256: sha256_hex(data),
+                format: format,
+                magic_ok: magic_ok,
+            }
+        })
+        .collect();
+    json::object! {
+        manifest_version: MANIFEST_VERSION,
+        producer: {
+            name: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            options_digest: options_digest,
+            seed: seed,
+        },
+        artifacts: entries,
+        valid: valid,
+    }
+}
+
+/// Add the `file_name` of every artifact of `manifest` under `names`.
+pub fn name_files(manifest: &mut json::JsonValue, names: &OutputNames) {
+    for entry in manifest["artifacts"].members_mut() {
+        let file_name = entry["name"].as_str().map(|artifact| names.get(artifact).to_owned());
+        entry["file_name"] = file_name.into();
+    }
+}
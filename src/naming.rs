@@ -0,0 +1,124 @@
+//! File names of a run's outputs.
+//!
+//! Two conventions, each with per-output overrides:
+//!
+//! - `prefix` (the default, and what the CLI writes for `<OUT_PREFIX>`):
+//!   `<prefix>.fasta.bgz`, `<prefix>.fasta.fai`, … `<prefix>.gff.csi`.
+//! - `samtools`: named after the inputs as bgzip, samtools faidx and tabix
+//!   name their outputs, so the files drop in where server-side tooling
+//!   output went: `genome.fa.gz`, `genome.fa.gz.fai`, `genome.fa.gz.gzi`,
+//!   `annotations.gff3.gz`, `annotations.gff3.gz.csi` (or `.tbi`), and
+//!   `annotations.gff3.gz.search` for the search index.  An input that is
+//!   already `.gz`/`.bgz` keeps its name with `.gz`.
+//!
+//! The manifest lists each output's file name, and the JBrowse and igv.js
+//! configs point at them.
+
+use std::collections::BTreeMap;
+use std::io;
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{io_error, js_error, ErrorCode};
+use crate::CACHE_ARTIFACTS;
+
+/// The file name of every output, by artifact (`fasta.bgz`, `gff.csi`, …).
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutputNames {
+    names: BTreeMap<&'static str, String>,
+}
+
+#[wasm_bindgen]
+impl OutputNames {
+    /// The `prefix` convention: `<prefix>.<artifact>`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(prefix: &str) -> Self {
+        OutputNames { names: CACHE_ARTIFACTS.iter().map(|&a| (a, format!("{}.{}", prefix, a))).collect() }
+    }
+
+    /// The `samtools` convention for inputs named `fasta_input` and
+    /// `gff_input` (paths are reduced to their file names).
+    pub fn samtools(fasta_input: &str, gff_input: &str) -> Result<OutputNames, JsValue> {
+        Self::from_inputs(fasta_input, gff_input).map_err(js_error)
+    }
+
+    /// Name `artifact` `file_name` instead.  Throws `E_INVALID_OPTION` for
+    /// an unknown artifact, a name with a path separator, or one another
+    /// output already has.
+    pub fn set_name(&mut self, artifact: &str, file_name: String) -> Result<(), JsValue> {
+        self.rename(artifact, file_name).map_err(js_error)
+    }
+
+    /// The file name of `artifact`, if it is one.
+    pub fn name(&self, artifact: &str) -> Option<String> {
+        self.names.get(artifact).cloned()
+    }
+
+    /// `{artifact: file_name, …}` as JSON.
+    pub fn to_json(&self) -> String {
+        self.json().dump()
+    }
+}
+
+impl OutputNames {
+    /// [`OutputNames::samtools`] natively.
+    pub fn from_inputs(fasta_input: &str, gff_input: &str) -> io::Result<Self> {
+        let compressed = |input: &str| -> io::Result<String> {
+            let name = input.rsplit(['/', '\\']).next().unwrap_or(input);
+            if name.is_empty() || name == "-" {
+                return Err(io_error(ErrorCode::InvalidOption, format!("cannot name outputs after {:?}; give them a prefix instead", input)));
+            }
+            let stem = [".gz", ".bgz", ".bgzf"].iter().find_map(|ext| name.strip_suffix(ext)).filter(|s| !s.is_empty()).unwrap_or(name);
+            Ok(format!("{}.gz", stem))
+        };
+        let (fasta, gff) = (compressed(fasta_input)?, compressed(gff_input)?);
+        if fasta == gff {
+            return Err(io_error(ErrorCode::InvalidOption, format!("the FASTA and GFF3 outputs would both be {}", fasta)));
+        }
+        let names = CACHE_ARTIFACTS
+            .iter()
+            .map(|&a| {
+                let name = match a.split_once('.') {
+                    Some(("fasta", "bgz")) => fasta.clone(),
+                    Some(("gff", "bgz")) => gff.clone(),
+                    Some(("fasta", ext)) => format!("{}.{}", fasta, ext),
+                    Some((_, ext)) => format!("{}.{}", gff, ext),
+                    None => unreachable!("artifact names are <input>.<ext>"),
+                };
+                (a, name)
+            })
+            .collect();
+        Ok(OutputNames { names })
+    }
+
+    /// [`OutputNames::set_name`] natively.
+    pub fn rename(&mut self, artifact: &str, file_name: String) -> io::Result<()> {
+        let Some(&key) = CACHE_ARTIFACTS.iter().find(|&&a| a == artifact) else {
+            return Err(io_error(ErrorCode::InvalidOption, format!("unknown output {:?}; expected one of {}", artifact, CACHE_ARTIFACTS.join(", "))));
+        };
+        if file_name.is_empty() || file_name == "." || file_name == ".." || file_name.contains(['/', '\\']) {
+            return Err(io_error(ErrorCode::InvalidOption, format!("{:?} is not a file name", file_name)));
+        }
+        if let Some((other, _)) = self.names.iter().find(|&(&a, n)| a != key && *n == file_name) {
+            return Err(io_error(ErrorCode::InvalidOption, format!("{:?} is already the name of {}", file_name, other)));
+        }
+        self.names.insert(key, file_name);
+        Ok(())
+    }
+
+    /// The file name of `artifact`, one of the names of
+    /// [`IndexGen::artifacts`](crate::IndexGen::artifacts).
+    pub fn get<'a>(&'a self, artifact: &'a str) -> &'a str {
+        self.names.get(artifact).map_or(artifact, String::as_str)
+    }
+
+    /// [`OutputNames::to_json`] as a JSON value.
+    pub fn json(&self) -> json::JsonValue {
+        let mut out = json::JsonValue::new_object();
+        for (artifact, name) in &self.names {
+            out[*artifact] = name.as_str().into();
+        }
+        out
+    }
+}
@@ -0,0 +1,454 @@
+//! Options shared by the IndexGen entry points.
+
+use wasm_bindgen::prelude::*;
+
+use crate::fasta::gaps::DEFAULT_MIN_GAP;
+use crate::fasta::orfs::DEFAULT_MIN_ORF;
+use crate::fasta::repeats::{DEFAULT_MIN_HOMOPOLYMER, DEFAULT_MIN_TANDEM};
+use crate::hash::sha256_hex;
+use crate::htslib::{BinRollup, FaiOffsets};
+use crate::search::DEFAULT_SEARCH_KEYS;
+use crate::translate::DEFAULT_TABLE;
+
+/// Version of the [`IndexGenOptions`] fields and their meaning, reported
+/// by `capabilities()`.  Bump it whenever a field is added, removed or
+/// changes meaning.
+pub const OPTIONS_SCHEMA_VERSION: u32 = 16;
+
+/// Preprocessing options. Every field that can change output bytes must be
+/// included in [`IndexGenOptions::digest`], which keys the output cache.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct IndexGenOptions {
+    /// Deflate level used for BGZF blocks, 0 (store) to 9 (smallest).
+    pub compression_level: u32,
+    /// Attribute keys to build the search index over (see `search.rs`);
+    /// empty disables it.  `DEFAULT_SEARCH_KEYS` is ID, gene, product, locus_tag.
+    #[wasm_bindgen(getter_with_clone)]
+    pub search_keys: Vec<String>,
+    /// Column-9 attributes to keep; empty keeps all.
+    #[wasm_bindgen(getter_with_clone)]
+    pub attribute_whitelist: Vec<String>,
+    /// Column-9 attributes to drop, applied after the whitelist.
+    #[wasm_bindgen(getter_with_clone)]
+    pub attribute_blacklist: Vec<String>,
+    /// Which GFF index format(s) to emit.
+    pub index_format: IndexFormat,
+    /// Container of the compressed FASTA.
+    pub fasta_output: OutputContainer,
+    /// Container of the compressed GFF3.
+    pub gff_output: OutputContainer,
+    /// Keys ordering GFF3 records with the same seqid, start and end, in
+    /// order: `"type"` (by `gff_type_priority`, then by name) and
+    /// `"strand"` (`+`, `-`, `.`, `?`).  Empty keeps such records in input
+    /// order.
+    #[wasm_bindgen(getter_with_clone)]
+    pub gff_sort_keys: Vec<String>,
+    /// Feature types the `"type"` sort key puts first, in this order (e.g.
+    /// gene, mRNA, exon, CDS); unlisted types follow.
+    #[wasm_bindgen(getter_with_clone)]
+    pub gff_type_priority: Vec<String>,
+    /// How GFF3 seqids are compared when sorting, which decides the order
+    /// of the sequences in the sorted file and its index.  Recorded in the
+    /// manifest, so a server-side sort can be made to match.
+    pub gff_collation: Collation,
+    /// Swap start and end of GFF3 records that have start > end instead of
+    /// indexing them as they are.
+    pub repair_coordinates: bool,
+    /// What to do with NUL and other control characters in the GFF3 and in
+    /// FASTA sequence lines.
+    pub control_chars: ControlCharPolicy,
+    /// What to do with GFF3 records that cannot be placed in the index
+    /// (fewer than five columns, or a start or end that is not a number).
+    /// Short records that can be placed are padded with `.` either way.
+    pub malformed_records: MalformedRecordPolicy,
+    /// What to do with GFF3 features that cross the origin of a circular
+    /// sequence (one with an `Is_circular=true` record).
+    pub circular_features: CircularPolicy,
+    /// Rewrap a FASTA whose sequences have irregular line lengths (which
+    /// `.fai` cannot describe) to the width of its first line, instead of
+    /// failing.
+    pub rewrap_irregular: bool,
+    /// Convert Windows (`\r\n`) line endings of the FASTA to `\n` while
+    /// compressing.  Off, they are kept and the `.fai` line widths include
+    /// the `\r`, as `samtools faidx` records them.
+    pub strip_cr: bool,
+    /// Convention of the `.fai` offset column; `Uncompressed` (samtools)
+    /// unless the consumer seeks on BGZF virtual offsets.
+    pub fai_offsets: FaiOffsets,
+    /// How sparse fine bins of the `.csi`/`.tbi` are rolled into coarser
+    /// ones: `Htslib` (as tabix) or `Aggressive` (smaller indexes).
+    pub bin_rollup: BinRollup,
+    /// Format of the assembly gap track written alongside the FASTA (see
+    /// `fasta/gaps.rs`); `Off` writes none.
+    pub gap_track: TrackFormat,
+    /// Shortest run of `N`s that counts as a gap, for the gap track and
+    /// the gap figures of the stats.
+    pub gap_min_length: u32,
+    /// Format of the homopolymer and tandem repeat track (see
+    /// `fasta/repeats.rs`); `Off` writes none.
+    pub repeat_track: TrackFormat,
+    /// Shortest homopolymer of the repeat track, in bases.
+    pub homopolymer_min_length: u32,
+    /// Shortest tandem repeat of 2-6 base units of the repeat track, in
+    /// bases.
+    pub tandem_min_length: u32,
+    /// Format of the six-frame ORF track, a provisional annotation for
+    /// assemblies without one (see `fasta/orfs.rs`); `Off` writes none.
+    pub orf_track: TrackFormat,
+    /// Shortest ORF of the ORF track, in bases from the start codon through
+    /// the stop codon.
+    pub orf_min_length: u32,
+    /// Format of the low-confidence rRNA/tRNA quick scan track (see
+    /// `fasta/rna.rs`); `Off` writes none.
+    pub rna_track: TrackFormat,
+    /// NCBI genetic code table of translations a feature does not give
+    /// one for with `transl_table` (the ORF track; see `translate.rs`).
+    pub genetic_code: u32,
+    /// Preprocessing steps for the FASTA, as transform specs (`name` or
+    /// `name:arguments`, see `transform.rs`); empty runs the default steps.
+    #[wasm_bindgen(getter_with_clone)]
+    pub fasta_transforms: Vec<String>,
+    /// Like `fasta_transforms`, for the GFF3.
+    #[wasm_bindgen(getter_with_clone)]
+    pub gff_transforms: Vec<String>,
+    /// Replicon metadata as `contig  replicon type  topology` TSV text,
+    /// tagged onto FASTA headers and GFF3 `region` records (see
+    /// `transform/replicons.rs`); empty tags nothing.
+    #[wasm_bindgen(getter_with_clone)]
+    pub replicon_metadata: String,
+    /// Check block CRC32s when reading back the BGZF files just written to
+    /// index them.  Off saves one hashing pass per index built; outputs
+    /// are the same either way, so it is not part of the digest.
+    pub verify_written_crc: bool,
+    /// Hash inputs with the browser's WebCrypto where the cache needs
+    /// their SHA-256, instead of in wasm (see `hash.rs`).  The hashes are
+    /// the same either way, so it is not part of the digest.
+    pub webcrypto_hashing: bool,
+    /// Run the write phases of the FASTA and GFF3 branches (compression,
+    /// sorting, indexing) side by side in builds with the `threads`
+    /// feature (see `coordinator.rs`), which needs both prepared inputs at
+    /// once.  Off (and in builds without the feature) the FASTA is written
+    /// before the GFF3 is read and transformed, so only one branch's
+    /// buffers are held at a time.  Outputs are the same either way, so it
+    /// is not part of the digest.
+    pub parallel_branches: bool,
+    /// Seed of the features that sample their input (see `seed.rs`), so
+    /// they give the same result on every run.  Outputs never depend on
+    /// it, so it is not part of the digest; the manifest records it.
+    pub seed: u32,
+}
+
+/// GFF index layout.  CSI is what `tabix -C` writes; TBI adds the linear
+/// index that some older readers (e.g. early JS tabix ports) insist on, but
+/// cannot address positions beyond 2^29.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexFormat {
+    Csi = "csi",
+    Tbi = "tbi",
+    Both = "both",
+}
+
+/// Compressed-output container.  Plain gzip is a single member without the
+/// BGZF block structure, so no index (`.fai`/`.gzi`, `.csi`/`.tbi`, search
+/// index) is produced for an output written that way; the `*_bgz_blob()`
+/// accessors then return the `.gz` bytes.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputContainer {
+    Bgzf = "bgzf",
+    Gzip = "gzip",
+}
+
+/// Format of the feature tracks found in the FASTA (see `fasta/track.rs`).
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrackFormat {
+    Off = "none",
+    /// `seqid start end name`, 0-based half-open.
+    Bed = "bed",
+    /// `gap` features with `estimated_length`.
+    Gff = "gff",
+}
+
+impl TrackFormat {
+    /// File name extension of the track before `.gz`.
+    pub fn extension(self) -> &'static str {
+        match self {
+            TrackFormat::Gff => "gff3",
+            _ => "bed",
+        }
+    }
+}
+
+/// Order of GFF3 seqids (see [`gff::seqid_cmp`](crate::gff::seqid_cmp)).
+/// None depends on the locale; seqids equal under `Dictionary` or
+/// `Natural` fall back to `Bytewise`, so each sequence stays together.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Collation {
+    /// Byte by byte, as `LC_ALL=C sort -k1,1`.
+    Bytewise = "bytewise",
+    /// Only ASCII letters, digits and blanks, as `LC_ALL=C sort -k1,1d`.
+    Dictionary = "dictionary",
+    /// Runs of digits as numbers (`chr2` before `chr10`), as
+    /// `LC_ALL=C sort -k1,1V` for typical seqids.
+    Natural = "natural",
+}
+
+impl Collation {
+    /// The option value naming this collation.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Collation::Dictionary => "dictionary",
+            Collation::Natural => "natural",
+            _ => "bytewise",
+        }
+    }
+}
+
+/// Handling of control characters in the inputs (see `scrub.rs`).
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ControlCharPolicy {
+    /// Fail with the offending line number.
+    Error = "error",
+    /// Remove them.
+    Strip = "strip",
+    /// `%XX`-escape them in the GFF3, turn them into `N` in sequences.
+    Replace = "replace",
+}
+
+/// Handling of GFF3 records that cannot be placed in the index (see
+/// `gff/columns.rs`).
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MalformedRecordPolicy {
+    /// Fail with the offending line number.
+    Error = "error",
+    /// Remove them, with a `record_dropped` warning each.
+    Drop = "drop",
+}
+
+/// Handling of features that cross the origin of a circular sequence (see
+/// `gff/circular.rs`).
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircularPolicy {
+    /// Write them as two records sharing the feature's ID, one up to the
+    /// sequence end and one from base 1.
+    Split = "split",
+    /// End them at the sequence end, dropping the part after the origin.
+    Clamp = "clamp",
+    /// Fail with the offending line number.
+    Error = "error",
+}
+
+/// The part of a run that reads an option.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptionReader {
+    /// The FASTA branch, for the `.bgz`/`.fai`/`.gzi`.
+    FastaFiles,
+    /// The FASTA branch, for the feature tracks found in the sequence.
+    FastaTracks,
+    /// Both branches.
+    Shared,
+    /// The GFF3 branch.
+    Gff,
+}
+
+/// Every key of [`IndexGenOptions::canonical`] but `version`, with the part
+/// of a run that reads it.  Cache reuse ([`IndexGenOptions::fasta_digest`])
+/// and stage retry ([`IndexGenOptions::differences_read_by`]) both go by
+/// this table.
+pub const OPTION_READERS: [(&str, OptionReader); 30] = [
+    ("compression_level", OptionReader::Shared),
+    ("search_keys", OptionReader::Gff),
+    ("attribute_whitelist", OptionReader::Gff),
+    ("attribute_blacklist", OptionReader::Gff),
+    ("index_format", OptionReader::Gff),
+    ("fasta_output", OptionReader::FastaFiles),
+    ("gff_output", OptionReader::Gff),
+    ("gff_sort_keys", OptionReader::Gff),
+    ("gff_type_priority", OptionReader::Gff),
+    ("gff_collation", OptionReader::Gff),
+    ("repair_coordinates", OptionReader::Gff),
+    ("control_chars", OptionReader::Shared),
+    ("malformed_records", OptionReader::Gff),
+    ("circular_features", OptionReader::Gff),
+    ("rewrap_irregular", OptionReader::FastaFiles),
+    ("strip_cr", OptionReader::FastaFiles),
+    ("fai_offsets", OptionReader::FastaFiles),
+    ("bin_rollup", OptionReader::Gff),
+    ("gap_track", OptionReader::FastaTracks),
+    ("gap_min_length", OptionReader::FastaTracks),
+    ("repeat_track", OptionReader::FastaTracks),
+    ("homopolymer_min_length", OptionReader::FastaTracks),
+    ("tandem_min_length", OptionReader::FastaTracks),
+    ("orf_track", OptionReader::FastaTracks),
+    ("orf_min_length", OptionReader::FastaTracks),
+    ("rna_track", OptionReader::FastaTracks),
+    ("genetic_code", OptionReader::FastaTracks),
+    ("fasta_transforms", OptionReader::FastaFiles),
+    ("gff_transforms", OptionReader::Gff),
+    ("replicon_metadata", OptionReader::Shared),
+];
+
+/// Options that do not change output bytes, so are not in
+/// [`IndexGenOptions::canonical`].
+pub const RUN_OPTIONS: [&str; 4] = ["verify_written_crc", "webcrypto_hashing", "parallel_branches", "seed"];
+
+/// Who reads the canonical option `key`.  A key missing from
+/// [`OPTION_READERS`] counts as read by both branches, so it never leaves
+/// stale outputs in use.
+pub fn option_reader(key: &str) -> OptionReader {
+    OPTION_READERS.iter().find(|(k, _)| *k == key).map_or(OptionReader::Shared, |&(_, reader)| reader)
+}
+
+#[wasm_bindgen]
+impl IndexGenOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hex SHA-256 over every output-affecting option plus the crate version.
+    pub fn digest(&self) -> String {
+        sha256_hex(self.canonical().as_bytes())
+    }
+
+    /// Hex SHA-256 over the options that change the FASTA `.bgz`/`.fai`/
+    /// `.gzi` (see [`OPTION_READERS`]) plus the crate version.
+    pub fn fasta_digest(&self) -> String {
+        let canonical = self.canonical();
+        let lines: Vec<&str> = canonical
+            .lines()
+            .filter(|line| {
+                line.split_once('=').is_some_and(|(key, _)| {
+                    key == "version" || matches!(option_reader(key), OptionReader::FastaFiles | OptionReader::Shared)
+                })
+            })
+            .collect();
+        sha256_hex(lines.join("\n").as_bytes())
+    }
+}
+
+impl IndexGenOptions {
+    /// Enable the attribute search index over `DEFAULT_SEARCH_KEYS`.
+    pub fn with_default_search_keys(mut self) -> Self {
+        self.search_keys = DEFAULT_SEARCH_KEYS.iter().map(|k| k.to_string()).collect();
+        self
+    }
+
+    /// Output-affecting options that differ between `self` and `other` and
+    /// that the branch `stage` (`"fasta"` or `"gff"`) reads.
+    pub fn differences_read_by(&self, other: &Self, stage: &str) -> Vec<String> {
+        let reads = |key: &str| match (stage, option_reader(key)) {
+            (_, OptionReader::Shared) => true,
+            ("fasta", reader) => reader != OptionReader::Gff,
+            (_, reader) => reader == OptionReader::Gff,
+        };
+        let (ours, theirs) = (self.canonical(), other.canonical());
+        ours.lines()
+            .zip(theirs.lines())
+            .filter(|(a, b)| a != b)
+            .filter_map(|(a, _)| a.split_once('=').map(|(key, _)| key))
+            .filter(|key| reads(key))
+            .map(str::to_owned)
+            .collect()
+    }
+
+    /// Names of the fields: the keys of [`IndexGenOptions::canonical`] plus
+    /// the ones that do not change output bytes.
+    pub(crate) fn field_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .canonical()
+            .lines()
+            .filter_map(|line| line.split_once('=').map(|(key, _)| key.to_owned()))
+            .filter(|key| key != "version")
+            .collect();
+        names.extend(RUN_OPTIONS.map(str::to_owned));
+        names
+    }
+
+    /// Stable `key=value` listing of the options, one per line.
+    fn canonical(&self) -> String {
+        let mut out = format!("version={}\n", env!("CARGO_PKG_VERSION"));
+        out.push_str(&format!("compression_level={}\n", self.compression_level));
+        // Attribute tags and values may contain commas, so lists of them
+        // are JSON, as are the transform specs below.
+        let list = |values: &[String]| json::JsonValue::from(values.to_vec()).dump();
+        out.push_str(&format!("search_keys={}\n", list(&self.search_keys)));
+        out.push_str(&format!("attribute_whitelist={}\n", list(&self.attribute_whitelist)));
+        out.push_str(&format!("attribute_blacklist={}\n", list(&self.attribute_blacklist)));
+        out.push_str(&format!("index_format={:?}\n", self.index_format));
+        out.push_str(&format!("fasta_output={:?}\n", self.fasta_output));
+        out.push_str(&format!("gff_output={:?}\n", self.gff_output));
+        out.push_str(&format!("gff_sort_keys={}\n", list(&self.gff_sort_keys)));
+        out.push_str(&format!("gff_type_priority={}\n", list(&self.gff_type_priority)));
+        out.push_str(&format!("gff_collation={:?}\n", self.gff_collation));
+        out.push_str(&format!("repair_coordinates={}\n", self.repair_coordinates));
+        out.push_str(&format!("control_chars={:?}\n", self.control_chars));
+        out.push_str(&format!("malformed_records={:?}\n", self.malformed_records));
+        out.push_str(&format!("circular_features={:?}\n", self.circular_features));
+        out.push_str(&format!("rewrap_irregular={}\n", self.rewrap_irregular));
+        out.push_str(&format!("strip_cr={}\n", self.strip_cr));
+        out.push_str(&format!("fai_offsets={:?}\n", self.fai_offsets));
+        out.push_str(&format!("bin_rollup={:?}\n", self.bin_rollup));
+        out.push_str(&format!("gap_track={:?}\n", self.gap_track));
+        out.push_str(&format!("gap_min_length={}\n", self.gap_min_length));
+        out.push_str(&format!("repeat_track={:?}\n", self.repeat_track));
+        out.push_str(&format!("homopolymer_min_length={}\n", self.homopolymer_min_length));
+        out.push_str(&format!("tandem_min_length={}\n", self.tandem_min_length));
+        out.push_str(&format!("orf_track={:?}\n", self.orf_track));
+        out.push_str(&format!("orf_min_length={}\n", self.orf_min_length));
+        out.push_str(&format!("rna_track={:?}\n", self.rna_track));
+        out.push_str(&format!("genetic_code={}\n", self.genetic_code));
+        out.push_str(&format!("fasta_transforms={}\n", list(&self.fasta_transforms)));
+        out.push_str(&format!("gff_transforms={}\n", list(&self.gff_transforms)));
+        out.push_str(&format!("replicon_metadata={:?}\n", self.replicon_metadata));
+        out
+    }
+}
+
+impl Default for IndexGenOptions {
+    fn default() -> Self {
+        IndexGenOptions {
+            compression_level: 6,
+            search_keys: Vec::new(),
+            attribute_whitelist: Vec::new(),
+            attribute_blacklist: Vec::new(),
+            index_format: IndexFormat::Csi,
+            fasta_output: OutputContainer::Bgzf,
+            gff_output: OutputContainer::Bgzf,
+            gff_sort_keys: Vec::new(),
+            gff_type_priority: Vec::new(),
+            gff_collation: Collation::Bytewise,
+            repair_coordinates: false,
+            control_chars: ControlCharPolicy::Error,
+            malformed_records: MalformedRecordPolicy::Error,
+            circular_features: CircularPolicy::Split,
+            rewrap_irregular: false,
+            strip_cr: false,
+            fai_offsets: FaiOffsets::Uncompressed,
+            bin_rollup: BinRollup::Htslib,
+            gap_track: TrackFormat::Off,
+            gap_min_length: DEFAULT_MIN_GAP as u32,
+            repeat_track: TrackFormat::Off,
+            homopolymer_min_length: DEFAULT_MIN_HOMOPOLYMER as u32,
+            tandem_min_length: DEFAULT_MIN_TANDEM as u32,
+            orf_track: TrackFormat::Off,
+            orf_min_length: DEFAULT_MIN_ORF as u32,
+            rna_track: TrackFormat::Off,
+            genetic_code: DEFAULT_TABLE,
+            fasta_transforms: Vec::new(),
+            gff_transforms: Vec::new(),
+            replicon_metadata: String::new(),
+            verify_written_crc: true,
+            webcrypto_hashing: false,
+            parallel_branches: true,
+            seed: 0,
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Preview runs for low-end devices: the pipeline over the start of each
+//! input only, giving draft indexes and stats for a UI to show at once
+//! while the full run happens later or server-side.
+//!
+//! Each input is read (and inflated) only up to `max_bytes`, cut back to
+//! whole lines, and then to its first `max_sequences` sequences: FASTA
+//! records, or the records of the first GFF3 seqids met (comments are
+//! kept).  A result built this way is marked partial in its manifest and
+//! warnings, and is never stored in the output cache.
+
+use std::collections::HashSet;
+use std::io;
+
+use wasm_bindgen::prelude::*;
+
+use crate::input::InputSource;
+use crate::limits;
+use crate::stats::{FastaStats, GffStats};
+
+/// How much of each input a preview reads.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct PreviewOptions {
+    /// Decompressed bytes read from each input (default 16 MiB).
+    pub max_bytes: u32,
+    /// Sequences kept from each input; 0 keeps every one within
+    /// `max_bytes`.
+    pub max_sequences: u32,
+}
+
+#[wasm_bindgen]
+impl PreviewOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Default for PreviewOptions {
+    fn default() -> Self {
+        PreviewOptions { max_bytes: 16 << 20, max_sequences: 0 }
+    }
+}
+
+/// What a preview kept of one input.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InputCut {
+    /// Bytes kept.
+    pub bytes: u64,
+    /// Sequences kept: FASTA records, or distinct GFF3 seqids.
+    pub sequences: usize,
+    /// Whether anything was left out.
+    pub truncated: bool,
+}
+
+impl InputCut {
+    pub fn to_json(&self) -> json::JsonValue {
+        json::object! { bytes: self.bytes, sequences: self.sequences, truncated: self.truncated }
+    }
+}
+
+/// What a preview run covered, with the stats of the parts it read.
+#[derive(Debug, Default, PartialEq)]
+pub struct PreviewReport {
+    pub fasta: InputCut,
+    pub gff: InputCut,
+    pub fasta_stats: FastaStats,
+    pub gff_stats: GffStats,
+}
+
+impl PreviewReport {
+    /// `{partial, fasta: {bytes, sequences, truncated}, gff: {…}, stats:
+    /// {fasta, gff}}`; `partial` is true when either input was truncated.
+    pub fn to_json(&self) -> json::JsonValue {
+        json::object! {
+            partial: self.fasta.truncated || self.gff.truncated,
+            fasta: self.fasta.to_json(),
+            gff: self.gff.to_json(),
+            stats: {
+                fasta: self.fasta_stats.to_json(),
+                gff: self.gff_stats.to_json(),
+            },
+        }
+    }
+}
+
+/// `data` up to its last complete line, unless it is the whole input.
+fn whole_lines(data: &[u8], more: bool) -> &[u8] {
+    match data.iter().rposition(|&b| b == b'\n') {
+        Some(i) if more => &data[..i + 1],
+        None if more => &[],
+        _ => data,
+    }
+}
+
+/// The first `max_sequences` (0: all) records of a FASTA prefix.  A header
+/// whose sequence lines were all cut off goes too.
+pub fn cut_fasta(fa: &[u8], more: bool, max_sequences: u32) -> (Vec<u8>, InputCut) {
+    let kept = whole_lines(fa, more);
+    let mut truncated = more;
+    let (mut end, mut header) = (kept.len(), None);
+    let mut sequences = 0;
+    let mut offset = 0;
+    for line in kept.split_inclusive(|&b| b == b'\n') {
+        if line.starts_with(b">") {
+            if max_sequences > 0 && sequences == max_sequences as usize {
+                (end, truncated, header) = (offset, true, None);
+                break;
+            }
+            sequences += 1;
+            header = Some(offset);
+        } else {
+            header = None;
+        }
+        offset += line.len();
+    }
+    if let (Some(start), true) = (header, more) {
+        (end, sequences) = (start, sequences - 1);
+    }
+    let kept = kept[..end].to_vec();
+    let cut = InputCut { bytes: kept.len() as u64, sequences, truncated };
+    (kept, cut)
+}
+
+/// The `#` lines of a GFF3 prefix and the records of its first
+/// `max_sequences` (0: all) seqids.
+pub fn cut_gff(gff: &[u8], more: bool, max_sequences: u32) -> (Vec<u8>, InputCut) {
+    let kept = whole_lines(gff, more);
+    let mut truncated = more;
+    let mut seqids: HashSet<&[u8]> = HashSet::new();
+    let mut out = Vec::with_capacity(kept.len());
+    for line in kept.split_inclusive(|&b| b == b'\n') {
+        let seqid = line.split(|&b| b == b'\t').next().unwrap_or_default();
+        let record = !line.starts_with(b"#") && line.contains(&b'\t');
+        if record && !seqids.contains(seqid) {
+            if max_sequences > 0 && seqids.len() == max_sequences as usize {
+                truncated = true;
+                continue;
+            }
+            seqids.insert(seqid);
+        }
+        out.extend_from_slice(line);
+    }
+    let cut = InputCut { bytes: out.len() as u64, sequences: seqids.len(), truncated };
+    (out, cut)
+}
+
+/// Read the parts of both inputs a preview covers: `(fasta, gff, report)`.
+pub fn read_preview(fa: &InputSource, gff: &InputSource, preview: &PreviewOptions) -> io::Result<(Vec<u8>, Vec<u8>, PreviewReport)> {
+    let max_bytes = (preview.max_bytes as u64).min(limits::input_budget() / 2);
+    let (fa_prefix, fa_more) = fa.read_prefix(max_bytes)?;
+    let (gff_prefix, gff_more) = gff.read_prefix(max_bytes)?;
+    let (fa_bytes, fasta) = cut_fasta(&fa_prefix, fa_more, preview.max_sequences);
+    let (gff_bytes, gff_cut) = cut_gff(&gff_prefix, gff_more, preview.max_sequences);
+    let report = PreviewReport {
+        fasta,
+        gff: gff_cut,
+        fasta_stats: FastaStats::from_fasta(&fa_bytes),
+        gff_stats: GffStats::from_gff(&String::from_utf8_lossy(&gff_bytes)),
+    };
+    Ok((fa_bytes, gff_bytes, report))
+}
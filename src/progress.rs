@@ -0,0 +1,196 @@
+//! Progress reporting to the hosting worker.
+//!
+//! Messages are posted as plain objects `{ type: "progress", stage, done, total }`
+//! so the UI can drive a progress bar without parsing console output.
+//! `total` is omitted when the size of the stage is not known in advance.
+//!
+//! The `faidx`, `csi` and `tbi` stages also say which sequence they are on,
+//! as metagenome assemblies spend most of a run in them over thousands of
+//! contigs: `contig: { index, count, name, done }` (`index` 1-based,
+//! `count` when known, `done` bytes into it), and `eta_ms` once a rate has
+//! been measured and `total` is known.  These are posted at most every
+//! [`MIN_INTERVAL_MS`], plus once at the end of the stage.
+
+use wasm_bindgen::prelude::*;
+
+use crate::post_message;
+
+/// Least time between two per-contig messages of a stage.
+pub const MIN_INTERVAL_MS: f64 = 250.0;
+
+/// Weight of the newest rate sample in [`Eta`].
+const ETA_SMOOTHING: f64 = 0.3;
+
+/// Wall-clock milliseconds (`Date.now()` in the browser).
+pub(crate) fn now_ms() -> f64 {
+    if cfg!(all(target_arch = "wasm32", not(target_os = "wasi"))) {
+        js_sys::Date::now()
+    } else {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs_f64() * 1000.0)
+            .unwrap_or(0.0)
+    }
+}
+
+/// Whether messages can be posted: in the browser, with a global
+/// `postMessage` (not the Node.js main thread).
+fn can_post() -> bool {
+    cfg!(all(target_arch = "wasm32", not(target_os = "wasi")))
+        && js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("postMessage")).is_ok_and(|f| f.is_function())
+}
+
+/// Post a progress update for `stage`. A no-op outside the browser, and in
+/// JS hosts without a global `postMessage` (Node.js main thread).
+pub fn report(stage: &str, done: u64, total: Option<u64>) {
+    if !can_post() {
+        return;
+    }
+    let msg = js_sys::Object::new();
+    let set = |key: &str, value: JsValue| {
+        let _ = js_sys::Reflect::set(&msg, &JsValue::from_str(key), &value);
+    };
+    set("type", JsValue::from_str("progress"));
+    set("stage", JsValue::from_str(stage));
+    set("done", JsValue::from_f64(done as f64));
+    if let Some(total) = total {
+        set("total", JsValue::from_f64(total as f64));
+    }
+    post_message(&msg);
+}
+
+/// Time-to-completion estimate from an exponentially smoothed byte rate,
+/// so that one slow or fast block does not swing it.
+#[derive(Clone, Debug, Default)]
+pub struct Eta {
+    /// Smoothed bytes per millisecond.
+    rate: Option<f64>,
+    /// Time and progress of the last sample.
+    last: Option<(f64, u64)>,
+}
+
+impl Eta {
+    /// Record that `done` bytes were processed by `now_ms`.  Samples no
+    /// later than the previous one are ignored.
+    pub fn sample(&mut self, now_ms: f64, done: u64) {
+        if let Some((then, before)) = self.last {
+            if now_ms <= then {
+                return;
+            }
+            let rate = done.saturating_sub(before) as f64 / (now_ms - then);
+            self.rate = Some(self.rate.map_or(rate, |old| old + ETA_SMOOTHING * (rate - old)));
+        }
+        self.last = Some((now_ms, done));
+    }
+
+    /// Smoothed bytes per millisecond, after at least two samples.
+    pub fn rate(&self) -> Option<f64> {
+        self.rate
+    }
+
+    /// Milliseconds left to reach `total` bytes at the smoothed rate, from
+    /// the last sample; `None` until the rate is measured and positive.
+    pub fn remaining_ms(&self, total: u64) -> Option<f64> {
+        let (_, done) = self.last?;
+        let rate = self.rate.filter(|&r| r > 0.0)?;
+        Some(total.saturating_sub(done) as f64 / rate)
+    }
+}
+
+/// What is known ahead about the data a stage goes through.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Extent {
+    /// Uncompressed bytes.
+    pub bytes: Option<u64>,
+    /// Sequences.
+    pub contigs: Option<u64>,
+}
+
+/// Per-contig progress of one stage (see the module docs), fed the
+/// uncompressed offset by the reader the stage goes through.
+#[derive(Clone, Debug)]
+pub struct ContigProgress {
+    stage: &'static str,
+    extent: Extent,
+    /// 1-based index of the current contig; 0 before the first.
+    index: u64,
+    name: String,
+    /// Offset the current contig starts at.
+    contig_start: u64,
+    done: u64,
+    eta: Eta,
+    /// When the last message was posted, if one was.
+    last_post: Option<f64>,
+    finished: bool,
+}
+
+impl ContigProgress {
+    pub fn new(stage: &'static str, extent: Extent) -> Self {
+        ContigProgress { stage, extent, index: 0, name: String::new(), contig_start: 0, done: 0, eta: Eta::default(), last_post: None, finished: false }
+    }
+
+    /// The next contig, `name`, starts at `offset`.
+    pub fn start_contig(&mut self, name: &str, offset: u64) {
+        self.index += 1;
+        self.name.clear();
+        self.name.push_str(name);
+        self.contig_start = offset;
+        self.advance(offset);
+    }
+
+    /// The stage has reached `offset`; posts if [`MIN_INTERVAL_MS`] has
+    /// passed since the last message.
+    pub fn advance(&mut self, offset: u64) {
+        self.done = offset;
+        let now = now_ms();
+        if self.last_post.is_some_and(|last| now - last < MIN_INTERVAL_MS) {
+            return;
+        }
+        self.eta.sample(now, offset);
+        self.last_post = Some(now);
+        self.post();
+    }
+
+    /// The stage ended at `offset`: posts the final message, once.
+    pub fn finish(&mut self, offset: u64) {
+        if std::mem::replace(&mut self.finished, true) {
+            return;
+        }
+        self.done = offset;
+        self.eta.sample(now_ms(), offset);
+        self.post();
+    }
+
+    /// 1-based index of the current contig and its name.
+    pub fn contig(&self) -> (u64, &str) {
+        (self.index, &self.name)
+    }
+
+    /// The message as it is posted.
+    pub fn message(&self) -> json::JsonValue {
+        let mut msg = json::object! { type: "progress", stage: self.stage, done: self.done };
+        if let Some(total) = self.extent.bytes {
+            msg["total"] = total.into();
+        }
+        if self.index > 0 {
+            let mut contig = json::object! { index: self.index, name: self.name.as_str(), done: self.done - self.contig_start };
+            if let Some(count) = self.extent.contigs {
+                contig["count"] = count.into();
+            }
+            msg["contig"] = contig;
+        }
+        if let Some(eta) = self.extent.bytes.and_then(|total| self.eta.remaining_ms(total)) {
+            msg["eta_ms"] = eta.round().into();
+        }
+        msg
+    }
+
+    fn post(&self) {
+        if !can_post() {
+            return;
+        }
+        if let Ok(msg) = js_sys::JSON::parse(&self.message().dump()) {
+            post_message(&msg);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Differential checks against htslib (`reference` feature, native only).
+//!
+//! Builds our `.fai`/`.gzi`/`.csi`/`.tbi` for a FASTA/GFF3 pair, obtains the
+//! same indexes from `samtools faidx` and `tabix` (or from committed golden
+//! files when the tools are not installed), and reports per artifact whether
+//! the two are byte-identical, equivalent after normalising hash-table order
+//! and chunk merging, or diverged — with the first difference found.
+
+use std::fmt;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+use std::process::Command;
+
+use crate::gff_preprocess;
+use crate::htslib::{bgzf_compress, compare_index, csi_index_gff, faidx_index_fasta, parse_gzi, tbi_index_gff, BgzfReader};
+
+/// The four index files of a FASTA/GFF3 pair.  `tbi` is optional because
+/// older golden sets only carry `.csi`.
+#[derive(Debug, Default)]
+pub struct Artifacts {
+    pub fai: Vec<u8>,
+    pub gzi: Vec<u8>,
+    pub csi: Vec<u8>,
+    pub tbi: Option<Vec<u8>>,
+}
+
+/// BGZF-compress a FASTA and a preprocessed GFF3 the way the pipeline does,
+/// so both sides index identical bytes.  Returns `(fasta_bgz, gff_bgz)`.
+pub fn bgzf_pair(fasta: &[u8], gff: &str) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    let (mut fasta_bgz, mut gff_bgz) = (Vec::new(), Vec::new());
+    bgzf_compress(Cursor::new(fasta), &mut fasta_bgz)?;
+    bgzf_compress(Cursor::new(gff_preprocess(gff).as_bytes()), &mut gff_bgz)?;
+    Ok((fasta_bgz, gff_bgz))
+}
+
+/// Our indexes of the BGZF files.
+pub fn our_artifacts(fasta_bgz: &[u8], gff_bgz: &[u8]) -> io::Result<Artifacts> {
+    let mut out = Artifacts::default();
+    faidx_index_fasta(fasta_bgz, &mut out.fai, &mut out.gzi)?;
+    csi_index_gff(gff_bgz, &mut out.csi)?;
+    let mut tbi = Vec::new();
+    tbi_index_gff(gff_bgz, &mut tbi)?;
+    out.tbi = Some(tbi);
+    Ok(out)
+}
+
+/// Whether `samtools` and `tabix` can be run.
+pub fn tools_available() -> bool {
+    ["samtools", "tabix"].iter().all(|tool| Command::new(tool).arg("--version").output().is_ok_and(|o| o.status.success()))
+}
+
+fn run(cmd: &mut Command) -> io::Result<()> {
+    let out = cmd.output()?;
+    if !out.status.success() {
+        return Err(io::Error::other(format!("{:?} failed: {}", cmd, String::from_utf8_lossy(&out.stderr).trim())));
+    }
+    Ok(())
+}
+
+/// htslib's indexes of the BGZF files, produced by `samtools faidx` and
+/// `tabix` in `workdir` (which must exist).
+pub fn htslib_artifacts(fasta_bgz: &[u8], gff_bgz: &[u8], workdir: &Path) -> io::Result<Artifacts> {
+    let (fa, gff) = (workdir.join("ref.fasta.bgz"), workdir.join("ref.gff3.bgz"));
+    std::fs::write(&fa, fasta_bgz)?;
+    std::fs::write(&gff, gff_bgz)?;
+    run(Command::new("samtools").arg("faidx").arg(&fa))?;
+    run(Command::new("tabix").args(["-f", "-C", "-p", "gff"]).arg(&gff))?;
+    run(Command::new("tabix").args(["-f", "-p", "gff"]).arg(&gff))?;
+    let with_ext = |p: &Path, ext: &str| std::fs::read(format!("{}.{}", p.display(), ext));
+    Ok(Artifacts {
+        fai: with_ext(&fa, "fai")?,
+        gzi: with_ext(&fa, "gzi")?,
+        csi: with_ext(&gff, "csi")?,
+        tbi: Some(with_ext(&gff, "tbi")?),
+    })
+}
+
+/// How one of our artifacts compares to htslib's.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Identical,
+    /// Same content in a different order (e.g. bins of a hash table).
+    Equivalent(String),
+    /// Content differs; the first difference found.
+    Diverged(String),
+    /// No reference to compare against.
+    Missing,
+}
+
+/// One line of a [`Report`].
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub fixture: String,
+    pub artifact: &'static str,
+    pub outcome: Outcome,
+}
+
+/// Divergence report over any number of fixtures.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub entries: Vec<Entry>,
+}
+
+impl Report {
+    /// No artifact diverged.
+    pub fn is_compatible(&self) -> bool {
+        self.entries.iter().all(|e| !matches!(e.outcome, Outcome::Diverged(_)))
+    }
+
+    /// Compare the artifacts of one fixture and append the outcomes.
+    pub fn compare(&mut self, fixture: &str, ours: &Artifacts, theirs: &Artifacts) {
+        let mut push = |artifact, outcome| self.entries.push(Entry { fixture: fixture.to_owned(), artifact, outcome });
+        push("fai", compare_structure(&ours.fai, &theirs.fai));
+        push("gzi", compare_gzi(&ours.gzi, &theirs.gzi));
+        push("csi", compare_structure(&ours.csi, &theirs.csi));
+        push("tbi", match (&ours.tbi, &theirs.tbi) {
+            (Some(ours), Some(theirs)) => compare_structure(ours, theirs),
+            _ => Outcome::Missing,
+        });
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for e in &self.entries {
+            let (status, detail) = match &e.outcome {
+                Outcome::Identical => ("identical", ""),
+                Outcome::Equivalent(d) => ("equivalent", d.as_str()),
+                Outcome::Diverged(d) => ("DIVERGED", d.as_str()),
+                Outcome::Missing => ("no reference", ""),
+            };
+            writeln!(f, "{:<24} {:<4} {:<12} {}", e.fixture, e.artifact, status, detail)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare a `.fai`, `.csi` or `.tbi`: bytes (decompressed, for the binary
+/// indexes) first, then structurally via [`compare_index`].
+fn compare_structure(ours: &[u8], theirs: &[u8]) -> Outcome {
+    let inflate = |data: &[u8]| {
+        if !data.starts_with(&[0x1f, 0x8b]) {
+            return Ok(data.to_vec());
+        }
+        let mut raw = Vec::new();
+        BgzfReader::lenient(Cursor::new(data)).read_to_end(&mut raw).map(|_| raw)
+    };
+    match (inflate(ours), inflate(theirs)) {
+        (Ok(ours), Ok(theirs)) if ours == theirs => return Outcome::Identical,
+        (Ok(_), Ok(_)) => {}
+        _ => return Outcome::Diverged("not BGZF".into()),
+    }
+    match compare_index(ours, theirs) {
+        Ok(diff) if diff.is_equivalent() => Outcome::Equivalent("same bins and chunks, different order or merging".into()),
+        Ok(diff) => Outcome::Diverged(format!("{} ({} difference(s))", diff.differences[0], diff.total)),
+        Err(e) => Outcome::Diverged(format!("unparseable: {}", e)),
+    }
+}
+
+fn compare_gzi(ours: &[u8], theirs: &[u8]) -> Outcome {
+    if ours == theirs {
+        return Outcome::Identical;
+    }
+    let (Ok(ours), Ok(theirs)) = (parse_gzi(ours), parse_gzi(theirs)) else {
+        return Outcome::Diverged("unparseable .gzi".into());
+    };
+    match ours.iter().zip(&theirs).position(|(a, b)| a != b) {
+        Some(i) => Outcome::Diverged(format!("block {}: ours {:?}, htslib {:?}", i, ours[i], theirs[i])),
+        None => Outcome::Diverged(format!("{} blocks, htslib {}", ours.len(), theirs.len())),
+    }
+}
@@ -0,0 +1,102 @@
+//! Region queries against already-uploaded BGZF files.
+//!
+//! The small index files (`.fai` + `.gzi`, or `.csi`) are handed over from JS;
+//! only the BGZF blocks covering the requested region are then fetched with
+//! HTTP Range requests and decompressed.  Regions are 1-based and inclusive,
+//! matching `samtools faidx` / `tabix` region strings.
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::future_to_promise;
+
+use crate::error::{js_error, js_error_code, ErrorCode};
+use crate::health;
+use crate::htslib::{
+    chunk_block_range, extract_fasta_region, extract_gff_chunk, fai_byte_range, gzi_block_range,
+    parse_fai, parse_gzi, CsiIndex, FaiRecord,
+};
+use crate::input::{fetch_range, FetchOptions};
+
+/// A remote BGZF-compressed FASTA indexed by `.fai` + `.gzi`.
+#[wasm_bindgen]
+pub struct RemoteFasta {
+    url: String,
+    fai: Vec<FaiRecord>,
+    gzi: Vec<(u64, u64)>,
+    options: FetchOptions,
+}
+
+#[wasm_bindgen]
+impl RemoteFasta {
+    #[wasm_bindgen(constructor)]
+    pub fn new(url: String, fai: &[u8], gzi: &[u8], options: Option<FetchOptions>) -> Result<RemoteFasta, JsValue> {
+        health::contain(|| {
+            Ok(RemoteFasta { url, fai: parse_fai(fai)?, gzi: parse_gzi(gzi)?, options: options.unwrap_or_default() })
+        })
+        .map_err(js_error)
+    }
+
+    /// Sequence names in `.fai` order.
+    pub fn sequence_names(&self) -> Vec<String> {
+        self.fai.iter().map(|r| r.name.clone()).collect()
+    }
+
+    /// Fetch bases `start..=end` (1-based) of `seq`. Resolves to a string.
+    pub fn fetch_region(&self, seq: String, start: u64, end: u64) -> Result<js_sys::Promise, JsValue> {
+        let rec = self.fai.iter().find(|r| r.name == seq).cloned()
+            .ok_or_else(|| js_error_code(ErrorCode::UnknownSequence, &format!("unknown sequence {:?}", seq)))?;
+        let beg = start.saturating_sub(1);
+        let (u0, u1) = fai_byte_range(&rec, beg, end).map_err(js_error)?;
+        let range = gzi_block_range(&self.gzi, u0, u1);
+        let (url, options) = (self.url.clone(), self.options);
+
+        Ok(future_to_promise(async move {
+            let blocks = fetch_range(&url, range.cstart, range.cend, &options).await?;
+            let bases = extract_fasta_region(&blocks, &range, &rec, beg, end).map_err(js_error)?;
+            Ok(JsValue::from_str(&bases))
+        }))
+    }
+}
+
+/// A remote BGZF-compressed, sorted GFF3 indexed by `.csi`.
+#[wasm_bindgen]
+pub struct RemoteGff {
+    url: String,
+    csi: CsiIndex,
+    options: FetchOptions,
+}
+
+#[wasm_bindgen]
+impl RemoteGff {
+    #[wasm_bindgen(constructor)]
+    pub fn new(url: String, csi: &[u8], options: Option<FetchOptions>) -> Result<RemoteGff, JsValue> {
+        health::contain(|| Ok(RemoteGff { url, csi: CsiIndex::from_bgzf(csi)?, options: options.unwrap_or_default() }))
+            .map_err(js_error)
+    }
+
+    /// Sequence names present in the index.
+    pub fn sequence_names(&self) -> Vec<String> {
+        self.csi.names.clone()
+    }
+
+    /// Fetch the GFF records overlapping `start..=end` (1-based) on `seq`.
+    /// Resolves to an array of GFF lines.
+    pub fn fetch_region(&self, seq: String, start: u64, end: u64) -> js_sys::Promise {
+        let beg = start.saturating_sub(1);
+        let chunks = self.csi.query(&seq, beg, end);
+        let (url, options) = (self.url.clone(), self.options);
+
+        future_to_promise(async move {
+            let mut lines = Vec::new();
+            for chunk in chunks {
+                let (cstart, cend) = chunk_block_range(chunk);
+                let blocks = fetch_range(&url, cstart, Some(cend), &options).await?;
+                extract_gff_chunk(&blocks, cstart, chunk, &seq, beg, end, &mut lines).map_err(js_error)?;
+            }
+            let out = js_sys::Array::new();
+            for line in lines {
+                out.push(&JsValue::from_str(&line));
+            }
+            Ok(out.into())
+        })
+    }
+}
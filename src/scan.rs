@@ -0,0 +1,234 @@
+//! Byte scanning for the indexing passes: newline search, base counting and
+//! GC counting.
+//!
+//! These loops are most of the time spent in the `.fai` pass and
+//! `BgzfReader::read_line`.  They run 16 bytes at a time with SSE2 on x86_64
+//! and with `simd128` on wasm builds that enable it
+//! (`RUSTFLAGS="-C target-feature=+simd128"`; a wasm module cannot probe
+//! for SIMD at run time, so it is a build choice).  Other targets, and the
+//! tail of every slice, take the scalar path.  `std::simd` would cover both
+//! targets but is not stable.
+
+/// Bases counted by [`count_bases`], case-insensitively.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BaseCounts {
+    /// `G`/`C`.
+    pub gc: u64,
+    /// `A`/`T`/`U`.
+    pub at: u64,
+    /// `N`.
+    pub n: u64,
+}
+
+impl std::ops::AddAssign for BaseCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.gc += other.gc;
+        self.at += other.at;
+        self.n += other.n;
+    }
+}
+
+/// Implementation in use: `"sse2"`, `"simd128"` or `"scalar"`.
+pub fn backend() -> &'static str {
+    vector::NAME
+}
+
+/// Index of the first `needle` in `haystack`.
+pub fn find_byte(haystack: &[u8], needle: u8) -> Option<usize> {
+    let start = match vector::find_byte(haystack, needle) {
+        Ok(i) => return Some(i),
+        Err(scanned) => scanned,
+    };
+    haystack[start..].iter().position(|&b| b == needle).map(|i| start + i)
+}
+
+/// `bytes` split on `\n`, like `bytes.split(|&b| b == b'\n')`.
+pub fn split_lines(bytes: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut rest = Some(bytes);
+    std::iter::from_fn(move || {
+        let bytes = rest?;
+        match find_byte(bytes, b'\n') {
+            Some(i) => {
+                rest = Some(&bytes[i + 1..]);
+                Some(&bytes[..i])
+            }
+            None => rest.take(),
+        }
+    })
+}
+
+/// Number of printable non-space ASCII bytes (`is_ascii_graphic`): the
+/// bases of a FASTA data line.
+pub fn count_graphic(bytes: &[u8]) -> usize {
+    let (scanned, count) = vector::count_graphic(bytes);
+    count + bytes[scanned..].iter().filter(|b| b.is_ascii_graphic()).count()
+}
+
+/// G+C, A+T(+U) and N counts of `bytes`.
+pub fn count_bases(bytes: &[u8]) -> BaseCounts {
+    let (scanned, mut counts) = vector::count_bases(bytes);
+    for &b in &bytes[scanned..] {
+        match b.to_ascii_uppercase() {
+            b'G' | b'C' => counts.gc += 1,
+            b'A' | b'T' | b'U' => counts.at += 1,
+            b'N' => counts.n += 1,
+            _ => {}
+        }
+    }
+    counts
+}
+
+// ---------------------------------------------------------------------------
+// 16-lane operations per target
+// ---------------------------------------------------------------------------
+
+#[cfg(all(target_arch = "x86_64", target_feature = "sse2"))]
+mod arch {
+    use std::arch::x86_64::*;
+
+    pub const NAME: &str = "sse2";
+
+    // SAFETY (all `unsafe` below): the module is only built with SSE2
+    // enabled, which every x86_64 target is.
+
+    #[derive(Clone, Copy)]
+    pub struct V(__m128i);
+
+    /// The first 16 bytes of `chunk`.
+    #[inline]
+    pub fn load(chunk: &[u8]) -> V {
+        assert!(chunk.len() >= 16);
+        // 16 bytes are readable; `loadu` has no alignment requirement.
+        V(unsafe { _mm_loadu_si128(chunk.as_ptr().cast()) })
+    }
+
+    #[inline]
+    pub fn splat(b: u8) -> V {
+        V(unsafe { _mm_set1_epi8(b as i8) })
+    }
+
+    #[inline]
+    pub fn and(a: V, b: V) -> V {
+        V(unsafe { _mm_and_si128(a.0, b.0) })
+    }
+
+    /// One bit per lane where `a == b`.
+    #[inline]
+    pub fn eq(a: V, b: V) -> u32 {
+        unsafe { _mm_movemask_epi8(_mm_cmpeq_epi8(a.0, b.0)) as u32 }
+    }
+
+    /// One bit per lane in `0x21..=0x7e`.
+    #[inline]
+    pub fn graphic(v: V) -> u32 {
+        unsafe {
+            let x = _mm_sub_epi8(v.0, splat(0x21).0);
+            _mm_movemask_epi8(_mm_cmpeq_epi8(_mm_min_epu8(x, splat(0x5d).0), x)) as u32
+        }
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+mod arch {
+    use std::arch::wasm32::*;
+
+    pub const NAME: &str = "simd128";
+
+    #[derive(Clone, Copy)]
+    pub struct V(v128);
+
+    /// The first 16 bytes of `chunk`.
+    #[inline]
+    pub fn load(chunk: &[u8]) -> V {
+        assert!(chunk.len() >= 16);
+        // SAFETY: 16 bytes are readable; `v128.load` has no alignment requirement.
+        V(unsafe { v128_load(chunk.as_ptr().cast()) })
+    }
+
+    #[inline]
+    pub fn splat(b: u8) -> V {
+        V(u8x16_splat(b))
+    }
+
+    #[inline]
+    pub fn and(a: V, b: V) -> V {
+        V(v128_and(a.0, b.0))
+    }
+
+    /// One bit per lane where `a == b`.
+    #[inline]
+    pub fn eq(a: V, b: V) -> u32 {
+        u8x16_bitmask(u8x16_eq(a.0, b.0)) as u32
+    }
+
+    /// One bit per lane in `0x21..=0x7e`.
+    #[inline]
+    pub fn graphic(v: V) -> u32 {
+        u8x16_bitmask(u8x16_le(u8x16_sub(v.0, splat(0x21).0), splat(0x5d).0)) as u32
+    }
+}
+
+/// The whole 16-byte chunks of each scan; each returns how many bytes it
+/// covered so the caller finishes the tail.
+#[cfg(any(all(target_arch = "x86_64", target_feature = "sse2"), all(target_arch = "wasm32", target_feature = "simd128")))]
+mod vector {
+    use super::arch::{and, eq, graphic, load, splat};
+    use super::BaseCounts;
+
+    pub use super::arch::NAME;
+
+    const LANES: usize = 16;
+
+    pub fn find_byte(haystack: &[u8], needle: u8) -> Result<usize, usize> {
+        let needle = splat(needle);
+        let chunks = haystack.chunks_exact(LANES);
+        let scanned = haystack.len() - chunks.remainder().len();
+        for (i, chunk) in chunks.enumerate() {
+            let mask = eq(load(chunk), needle);
+            if mask != 0 {
+                return Ok(i * LANES + mask.trailing_zeros() as usize);
+            }
+        }
+        Err(scanned)
+    }
+
+    pub fn count_graphic(bytes: &[u8]) -> (usize, usize) {
+        let chunks = bytes.chunks_exact(LANES);
+        let scanned = bytes.len() - chunks.remainder().len();
+        (scanned, chunks.map(|chunk| graphic(load(chunk)).count_ones() as usize).sum())
+    }
+
+    pub fn count_bases(bytes: &[u8]) -> (usize, BaseCounts) {
+        let [fold, g, c, a, t, u, n] = [0xdf, b'G', b'C', b'A', b'T', b'U', b'N'].map(splat);
+        let chunks = bytes.chunks_exact(LANES);
+        let scanned = bytes.len() - chunks.remainder().len();
+        let mut counts = BaseCounts::default();
+        for chunk in chunks {
+            // Clearing bit 5 upper-cases letters and maps nothing else onto them.
+            let upper = and(load(chunk), fold);
+            counts.gc += (eq(upper, g) | eq(upper, c)).count_ones() as u64;
+            counts.at += (eq(upper, a) | eq(upper, t) | eq(upper, u)).count_ones() as u64;
+            counts.n += eq(upper, n).count_ones() as u64;
+        }
+        (scanned, counts)
+    }
+}
+
+#[cfg(not(any(all(target_arch = "x86_64", target_feature = "sse2"), all(target_arch = "wasm32", target_feature = "simd128"))))]
+mod vector {
+    use super::BaseCounts;
+
+    pub const NAME: &str = "scalar";
+
+    pub fn find_byte(_: &[u8], _: u8) -> Result<usize, usize> {
+        Err(0)
+    }
+
+    pub fn count_graphic(_: &[u8]) -> (usize, usize) {
+        (0, 0)
+    }
+
+    pub fn count_bases(_: &[u8]) -> (usize, BaseCounts) {
+        (0, BaseCounts::default())
+    }
+}
@@ -0,0 +1,72 @@
+//! Control-character scrubbing of the text inputs.
+//!
+//! NUL and other C0 control bytes (everything below 0x20 except tab, LF and
+//! CR, plus DEL) have no place in FASTA or GFF3, but do turn up when binary
+//! data is decoded as text, e.g. a truncated gzip.  Left in, they end up in
+//! sequence names, seqids and line lengths and corrupt the indexes.
+
+use std::borrow::Cow;
+use std::io;
+
+use crate::error::{io_error, ErrorCode};
+use crate::options::ControlCharPolicy;
+use crate::warnings::Warnings;
+
+fn is_control(b: u8) -> bool {
+    (b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r')) || b == 0x7f
+}
+
+/// Scrub every line of `data` selected by `applies`, replacing control bytes
+/// with `replace(byte)` (an empty replacement strips them).
+fn scrub<'a>(
+    data: &'a [u8],
+    policy: ControlCharPolicy,
+    stage: &'static str,
+    applies: impl Fn(&[u8]) -> bool,
+    replace: impl Fn(u8) -> Vec<u8>,
+    warnings: &mut Warnings,
+) -> io::Result<Cow<'a, [u8]>> {
+    if !data.iter().any(|&b| is_control(b)) {
+        return Ok(Cow::Borrowed(data));
+    }
+    let mut out = Vec::with_capacity(data.len());
+    for (i, line) in data.split_inclusive(|&b| b == b'\n').enumerate() {
+        let count = line.iter().filter(|&&b| is_control(b)).count();
+        if count == 0 || !applies(line) {
+            out.extend_from_slice(line);
+            continue;
+        }
+        if policy == ControlCharPolicy::Error {
+            let first = line.iter().find(|&&b| is_control(b)).copied().unwrap_or_default();
+            return Err(io_error(
+                ErrorCode::ControlCharacter,
+                format!("{} line {} contains control character 0x{:02x}", stage, i + 1, first),
+            ));
+        }
+        for &b in line {
+            if is_control(b) {
+                out.extend(replace(b));
+            } else {
+                out.push(b);
+            }
+        }
+        let action = if policy == ControlCharPolicy::Strip { "removed" } else { "replaced" };
+        warnings.push(stage, "control_characters", Some(i + 1), format!("{} {} control character(s)", action, count));
+    }
+    Ok(Cow::Owned(out))
+}
+
+/// Apply `policy` to the GFF3 text: `Strip` drops control bytes and
+/// `Replace` writes them as GFF3 `%XX` escapes.
+pub fn scrub_gff<'a>(gff: &'a [u8], policy: ControlCharPolicy, warnings: &mut Warnings) -> io::Result<Cow<'a, [u8]>> {
+    let replace = |b: u8| if policy == ControlCharPolicy::Strip { Vec::new() } else { format!("%{:02X}", b).into_bytes() };
+    scrub(gff, policy, "gff", |_| true, replace, warnings)
+}
+
+/// Apply `policy` to the sequence lines of a FASTA file (headers are left
+/// alone): `Strip` drops control bytes and `Replace` turns each into an `N`,
+/// keeping the line length.
+pub fn scrub_fasta<'a>(fa: &'a [u8], policy: ControlCharPolicy, warnings: &mut Warnings) -> io::Result<Cow<'a, [u8]>> {
+    let replace = |_| if policy == ControlCharPolicy::Strip { Vec::new() } else { vec![b'N'] };
+    scrub(fa, policy, "fasta", |line| !line.starts_with(b">"), replace, warnings)
+}
@@ -0,0 +1,196 @@
+//! Attribute search index: "jump to gene by name" without scanning the GFF.
+//!
+//! Maps the values of selected column-9 attributes to the BGZF virtual offset
+//! of their line in the compressed, sorted GFF.  Serialized layout (all
+//! integers little-endian):
+//!
+//! ```text
+//! magic "GSI\x01"
+//! u8  n_keys, then per key: u8 len, key bytes
+//! u32 n_entries, then per entry (sorted by lower-cased value):
+//!     u16 len, value bytes, u8 key index, u64 virtual offset
+//! ```
+
+use std::collections::HashSet;
+use std::io::{self, Cursor, Read};
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{io_error, js_error, ErrorCode};
+use crate::gff::{attribute_values, part_key, GffFields};
+use crate::health;
+use crate::htslib::{BgzfReader, PooledBuf};
+
+const MAGIC: &[u8; 4] = b"GSI\x01";
+
+/// Attribute keys indexed when search is enabled without an explicit list.
+pub const DEFAULT_SEARCH_KEYS: [&str; 4] = ["ID", "gene", "product", "locus_tag"];
+
+fn invalid(msg: &str) -> io::Error {
+    io_error(ErrorCode::IndexParse, msg.to_owned())
+}
+
+/// Build the serialized index over `keys` for a BGZF-compressed GFF3.
+pub fn build_search_index(gff_bgz: &[u8], keys: &[String]) -> io::Result<Vec<u8>> {
+    build_search_index_reader(BgzfReader::new(Cursor::new(gff_bgz)), keys)
+}
+
+/// [`build_search_index`] reading through a configured `reader`.  A
+/// feature in several parts is found at its first one.
+pub fn build_search_index_reader<R: Read>(mut reader: BgzfReader<R>, keys: &[String]) -> io::Result<Vec<u8>> {
+    if keys.len() > u8::MAX as usize || keys.iter().any(|k| k.len() > u8::MAX as usize) {
+        return Err(io_error(ErrorCode::InvalidOption, "too many or too long search keys"));
+    }
+    let mut entries: Vec<(String, u8, u64)> = Vec::new();
+    let mut features: HashSet<(String, String, String)> = HashSet::new();
+    let mut line = PooledBuf::take(4096);
+    loop {
+        line.clear();
+        let (n, voff) = reader.read_line(&mut line)?;
+        if n == 0 {
+            break;
+        }
+        let Ok(text) = std::str::from_utf8(&line) else {
+            continue;
+        };
+        let Some(f) = GffFields::parse(text) else {
+            continue;
+        };
+        if let Some((seqid, ftype, id)) = part_key(&f) {
+            if !features.insert((seqid.to_owned(), ftype.to_owned(), id.to_owned())) {
+                continue;
+            }
+        }
+        for (ki, key) in keys.iter().enumerate() {
+            for value in f.attribute(key).map(attribute_values).unwrap_or_default() {
+                if !value.is_empty() && value.len() <= u16::MAX as usize {
+                    entries.push((value, ki as u8, voff));
+                }
+            }
+        }
+    }
+    entries.sort_by(|a, b| a.0.to_lowercase().cmp(&b.0.to_lowercase()).then(a.2.cmp(&b.2)));
+    entries.dedup();
+
+    let mut out = MAGIC.to_vec();
+    out.push(keys.len() as u8);
+    for key in keys {
+        out.push(key.len() as u8);
+        out.extend_from_slice(key.as_bytes());
+    }
+    out.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    for (value, ki, voff) in &entries {
+        out.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        out.extend_from_slice(value.as_bytes());
+        out.push(*ki);
+        out.extend_from_slice(&voff.to_le_bytes());
+    }
+    Ok(out)
+}
+
+/// One hit of [`SearchIndex::find`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchHit {
+    pub key: String,
+    pub value: String,
+    pub voffset: u64,
+}
+
+/// A parsed attribute search index.
+#[wasm_bindgen]
+pub struct SearchIndex {
+    keys: Vec<String>,
+    /// `(lower-cased value, value, key index, virtual offset)`, sorted.
+    entries: Vec<(String, String, u8, u64)>,
+}
+
+impl SearchIndex {
+    /// Parse a serialized index.
+    pub fn parse(blob: &[u8]) -> io::Result<Self> {
+        let mut r = Cursor::new(blob);
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a GFF search index"));
+        }
+        let read_str = |r: &mut Cursor<&[u8]>, len: usize| -> io::Result<String> {
+            let mut b = vec![0u8; len];
+            r.read_exact(&mut b)?;
+            String::from_utf8(b).map_err(|_| invalid("non-UTF8 search index entry"))
+        };
+        let mut b1 = [0u8; 1];
+        r.read_exact(&mut b1)?;
+        let mut keys = Vec::with_capacity(b1[0] as usize);
+        for _ in 0..b1[0] {
+            r.read_exact(&mut b1)?;
+            keys.push(read_str(&mut r, b1[0] as usize)?);
+        }
+        let mut b4 = [0u8; 4];
+        r.read_exact(&mut b4)?;
+        // Each entry takes at least 11 bytes (length, key, offset), so the
+        // count is checked against the bytes left before allocating for it.
+        let n = u32::from_le_bytes(b4) as usize;
+        let left = (blob.len() as u64).saturating_sub(r.position());
+        if (n as u64).saturating_mul(11) > left {
+            return Err(invalid("bad search index entry count"));
+        }
+        let mut entries = Vec::with_capacity(n);
+        for _ in 0..n {
+            let mut b2 = [0u8; 2];
+            r.read_exact(&mut b2)?;
+            let value = read_str(&mut r, u16::from_le_bytes(b2) as usize)?;
+            r.read_exact(&mut b1)?;
+            if b1[0] as usize >= keys.len() {
+                return Err(invalid("search index key out of range"));
+            }
+            let mut b8 = [0u8; 8];
+            r.read_exact(&mut b8)?;
+            entries.push((value.to_lowercase(), value, b1[0], u64::from_le_bytes(b8)));
+        }
+        Ok(SearchIndex { keys, entries })
+    }
+
+    /// All entries whose value equals `name`, ignoring case.
+    pub fn find(&self, name: &str) -> Vec<SearchHit> {
+        let needle = name.to_lowercase();
+        let first = self.entries.partition_point(|e| e.0 < needle);
+        self.entries[first..]
+            .iter()
+            .take_while(|e| e.0 == needle)
+            .map(|(_, value, ki, voffset)| SearchHit {
+                key: self.keys[*ki as usize].clone(),
+                value: value.clone(),
+                voffset: *voffset,
+            })
+            .collect()
+    }
+}
+
+#[wasm_bindgen]
+impl SearchIndex {
+    #[wasm_bindgen(constructor)]
+    pub fn new(blob: &[u8]) -> Result<SearchIndex, JsValue> {
+        health::contain(|| Self::parse(blob)).map_err(js_error)
+    }
+
+    /// Case-insensitive exact lookup. Returns a JSON array of
+    /// `{key, value, voffset}`; pass `voffset` to `gff_record_at` or use
+    /// `voffset >> 16` as the compressed block to fetch.
+    pub fn lookup(&self, name: &str) -> String {
+        let hits: Vec<json::JsonValue> = self
+            .find(name)
+            .into_iter()
+            .map(|h| json::object! { key: h.key, value: h.value, voffset: h.voffset })
+            .collect();
+        json::JsonValue::from(hits).dump()
+    }
+
+    /// Number of indexed values.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
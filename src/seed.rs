@@ -0,0 +1,61 @@
+//! Seeded randomness for the features that sample their input (see
+//! `estimate.rs`), so a run gives the same result on every machine and in
+//! every browser for the same seed.
+//!
+//! Each feature draws from its own stream, derived from the seed and the
+//! feature's name, so adding draws to one feature does not change what
+//! another samples.
+
+use crate::options::IndexGenOptions;
+
+/// SplitMix64: small, fast and the same on every platform, which is all
+/// sampling needs (not for anything security-related).
+#[derive(Clone, Debug)]
+pub struct SeededRng(u64);
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        SeededRng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..n`; 0 when `n` is 0.
+    pub fn below(&mut self, n: u64) -> u64 {
+        if n == 0 {
+            return 0;
+        }
+        // Rejecting the top partial range keeps every value equally likely.
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let x = self.next_u64();
+            if x < zone {
+                return x % n;
+            }
+        }
+    }
+}
+
+/// Something that fixes the seed of the sampling features.
+pub trait Seedable {
+    fn seed(&self) -> u64;
+
+    /// The random stream of the feature `stream` (e.g. `"estimate"`).
+    fn rng(&self, stream: &str) -> SeededRng {
+        // FNV-1a of the name, so streams differ for the same seed.
+        let name = stream.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| (h ^ b as u64).wrapping_mul(0x100_0000_01b3));
+        SeededRng::new(self.seed() ^ name)
+    }
+}
+
+impl Seedable for IndexGenOptions {
+    fn seed(&self) -> u64 {
+        self.seed as u64
+    }
+}
@@ -0,0 +1,187 @@
+//! Summary statistics of the inputs, for the "check my files" report.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::fasta::gaps::{GapFinder, GapStats, DEFAULT_MIN_GAP};
+use crate::fasta::{FastaLine, FastaParser};
+use crate::gff::{part_key, GffFields};
+use crate::scan::{self, BaseCounts};
+
+/// Length of the sequence at which the longest `lengths`, summed, first
+/// reach half the total; 0 without any.
+pub fn n50(lengths: &[u64]) -> u64 {
+    let total: u64 = lengths.iter().sum();
+    let mut sorted = lengths.to_vec();
+    sorted.sort_unstable_by(|a, b| b.cmp(a));
+    let mut acc = 0;
+    sorted
+        .into_iter()
+        .find(|&l| {
+            acc += l;
+            acc * 2 >= total
+        })
+        .unwrap_or(0)
+}
+
+/// Sequence counts and length distribution of a FASTA file.
+#[derive(Debug, Default, PartialEq)]
+pub struct FastaStats {
+    pub sequences: usize,
+    pub total_length: u64,
+    pub min_length: u64,
+    pub max_length: u64,
+    pub n50: u64,
+    /// G+C over A+C+G+T (ambiguous bases excluded); 0 without ACGT.
+    pub gc_fraction: f64,
+    /// `N`/`n` bases (gaps / unknown).
+    pub n_count: u64,
+    /// Runs of `N`s long enough to be gaps.
+    pub gaps: GapStats,
+}
+
+impl FastaStats {
+    /// Scan an uncompressed FASTA file, counting runs of
+    /// [`DEFAULT_MIN_GAP`] or more `N`s as gaps.
+    pub fn from_fasta(fa: &[u8]) -> Self {
+        Self::with_min_gap(fa, DEFAULT_MIN_GAP)
+    }
+
+    /// Like [`FastaStats::from_fasta`], counting runs of `min_gap` or more
+    /// `N`s as gaps.
+    pub fn with_min_gap(fa: &[u8], min_gap: u64) -> Self {
+        let mut lengths: Vec<u64> = Vec::new();
+        let mut bases = BaseCounts::default();
+        let mut gaps = GapStats { min_gap, ..Default::default() };
+        let mut finder = GapFinder::new(min_gap);
+        let mut parser = FastaParser::new();
+        for line in scan::split_lines(fa) {
+            match parser.push(line) {
+                (FastaLine::Header { .. }, Some(record)) => {
+                    lengths.push(record.length);
+                    gaps.add(&finder.finish());
+                }
+                (FastaLine::Sequence(line), _) if parser.current().is_some() => {
+                    bases += scan::count_bases(line);
+                    finder.push(line);
+                }
+                _ => {}
+            }
+        }
+        if let Some(record) = parser.finish() {
+            lengths.push(record.length);
+            gaps.add(&finder.finish());
+        }
+
+        let total_length: u64 = lengths.iter().sum();
+        FastaStats {
+            sequences: lengths.len(),
+            total_length,
+            min_length: lengths.iter().copied().min().unwrap_or(0),
+            max_length: lengths.iter().copied().max().unwrap_or(0),
+            n50: n50(&lengths),
+            gc_fraction: if bases.gc + bases.at == 0 { 0.0 } else { bases.gc as f64 / (bases.gc + bases.at) as f64 },
+            n_count: bases.n,
+            gaps,
+        }
+    }
+
+    pub fn to_json(&self) -> json::JsonValue {
+        json::object! {
+            sequences: self.sequences,
+            total_length: self.total_length,
+            min_length: self.min_length,
+            max_length: self.max_length,
+            n50: self.n50,
+            gc_fraction: self.gc_fraction,
+            n_count: self.n_count,
+            gaps: self.gaps.to_json(),
+        }
+    }
+}
+
+/// Record counts of a GFF3 file.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct GffStats {
+    /// Parseable data lines.
+    pub records: usize,
+    /// Features: records sharing seqid, type and `ID` (the parts of a
+    /// spliced CDS, say) count once.
+    pub features: usize,
+    /// Features of more than one record.
+    pub multi_part_features: usize,
+    /// Data lines that are not valid nine-column records.
+    pub unparsed: usize,
+    /// `#` lines before any `##FASTA` section.
+    pub comments: usize,
+    pub seqids: usize,
+    /// Records per feature type (column 3).
+    pub types: BTreeMap<String, usize>,
+    /// Records per source (column 2).
+    pub sources: BTreeMap<String, usize>,
+    /// Whether an embedded `##FASTA` section was found (and dropped).
+    pub fasta_section: bool,
+}
+
+impl GffStats {
+    /// Scan GFF3 text, stopping at any `##FASTA` section.
+    pub fn from_gff(gff: &str) -> Self {
+        let mut out = GffStats::default();
+        let mut seqids: Vec<&str> = Vec::new();
+        let mut parts: HashMap<(&str, &str, &str), usize> = HashMap::new();
+        for line in gff.split('\n') {
+            if line.starts_with("##FASTA") {
+                out.fasta_section = true;
+                break;
+            }
+            if line.starts_with('#') {
+                out.comments += 1;
+                continue;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            match GffFields::parse(line) {
+                Some(f) => {
+                    out.records += 1;
+                    match part_key(&f) {
+                        Some(key) => *parts.entry(key).or_default() += 1,
+                        None => out.features += 1,
+                    }
+                    *out.types.entry(f.ftype.to_owned()).or_default() += 1;
+                    *out.sources.entry(f.source.to_owned()).or_default() += 1;
+                    if seqids.last() != Some(&f.seqid) {
+                        seqids.push(f.seqid);
+                    }
+                }
+                None => out.unparsed += 1,
+            }
+        }
+        seqids.sort_unstable();
+        seqids.dedup();
+        out.seqids = seqids.len();
+        out.features += parts.len();
+        out.multi_part_features = parts.values().filter(|&&n| n > 1).count();
+        out
+    }
+
+    pub fn to_json(&self) -> json::JsonValue {
+        let counts = |map: &BTreeMap<String, usize>| {
+            let mut out = json::JsonValue::new_object();
+            for (key, n) in map {
+                out[key.as_str()] = (*n).into();
+            }
+            out
+        };
+        json::object! {
+            records: self.records,
+            features: self.features,
+            multi_part_features: self.multi_part_features,
+            unparsed: self.unparsed,
+            comments: self.comments,
+            seqids: self.seqids,
+            types: counts(&self.types),
+            sources: counts(&self.sources),
+            fasta_section: self.fasta_section,
+        }
+    }
+}
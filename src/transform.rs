@@ -0,0 +1,510 @@
+//! Preprocessing steps as an ordered, configurable pipeline per input.
+//!
+//! Each input goes through three phases: reading (decompression, the
+//! control character policy and, for the GFF3, UTF-8 decoding), its
+//! [`Pipeline`] of [`Transform`]s, and writing (sorting, compression and
+//! indexing, which the indexes depend on).  Only the middle phase is
+//! configurable.  `IndexGenOptions::fasta_transforms` and
+//! `gff_transforms` list it as named specs, `name` or `name:arguments`:
+//!
+//! | Input | Spec | Step |
+//! |-------|------|------|
+//! | FASTA | `strip_cr` | `\r\n` line endings to `\n` |
+//! | FASTA | `rewrap` | rewrap sequences with irregular line lengths, with a `fasta_rewrapped` warning |
+//! | FASTA | `filter_sequences:keep=a,b` / `:drop=a,b` | keep or drop records by name |
+//! | FASTA | `split_at_gaps` / `split_at_gaps:min_n=<n>` | split scaffolds into contigs at runs of `n` (default 10) or more `N`s (see `fasta/split.rs`) |
+//! | both  | `rename_seqids:old=new,…` | rename sequences (FASTA headers, GFF3 column 1 and `##sequence-region`) |
+//! | GFF3  | `strip_fasta_section` | drop an embedded `##FASTA` section (sorting drops it too) |
+//! | GFF3  | `gtf_to_gff3` | convert GTF/GFF2 column 9, with a `gtf_converted` warning |
+//! | GFF3  | `normalize_columns` | pad short records, per `malformed_records` (see `gff/columns.rs`) |
+//! | GFF3  | `normalize_circular` | split or clamp origin-crossing features, per `circular_features` |
+//! | GFF3  | `filter_types:keep=gene,CDS` / `:drop=region` | keep or drop records by type |
+//! | GFF3  | `unique_ids` | suffix `ID`s shared by several features and their `Parent` references (see [`unique_ids`]) |
+//! | GFF3  | `renumber_locus_tags:prefix=<P>` | new `locus_tag`s `<P>_00001`, … and matching `ID`/`Parent` (see [`locus_tags`]) |
+//! | both  | `hook:<label>` | a callback registered at runtime (see [`hook`]) |
+//! | both  | `tag_replicons` | added when `replicon_metadata` is set (see [`replicons`]) |
+//!
+//! An empty list runs the default pipeline: `strip_cr` and `rewrap` when
+//! `strip_cr` and `rewrap_irregular` are set, and `gtf_to_gff3`,
+//! `normalize_columns`, `normalize_circular`.  A list replaces it entirely.
+//! With `split_at_gaps` in the FASTA list, the GFF3 pipeline ends with a
+//! `remap_contigs` step moving features onto the new contigs.
+//! Rust callers can add their own steps by implementing [`Transform`] and
+//! passing the pipelines to `IndexGen::from_bytes_with`.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io;
+
+use crate::error::{io_error, ErrorCode};
+use crate::fasta;
+use crate::fasta::split::ContigMap;
+use crate::gff::{self, rewrite_lines};
+use crate::options::IndexGenOptions;
+use crate::warnings::Warnings;
+use replicons::Replicons;
+use unique_ids::IdRenames;
+
+pub mod hook;
+pub mod locus_tags;
+pub mod replicons;
+pub mod unique_ids;
+
+/// Spec names of the built-in FASTA steps.
+pub const FASTA_TRANSFORMS: &[&str] = &["strip_cr", "rewrap", "filter_sequences", "split_at_gaps", "rename_seqids", "hook"];
+
+/// Spec names of the built-in GFF3 steps.
+pub const GFF_TRANSFORMS: &[&str] =
+    &["strip_fasta_section", "gtf_to_gff3", "normalize_columns", "normalize_circular", "filter_types", "rename_seqids", "unique_ids", "renumber_locus_tags", "hook"];
+
+/// What a step may consult and report to.
+pub struct TransformContext<'a> {
+    pub options: &'a IndexGenOptions,
+    pub warnings: &'a mut Warnings,
+    /// Filled by `split_at_gaps` and read by `remap_contigs`.
+    pub contigs: &'a mut ContigMap,
+    /// Filled by `unique_ids`.
+    pub id_renames: &'a mut IdRenames,
+}
+
+/// One preprocessing step over the whole text of an input: `[u8]` for the
+/// FASTA, `str` for the GFF3.
+pub trait Transform<T: ?Sized + ToOwned> {
+    /// Name in specs, logs and errors.
+    fn name(&self) -> &str;
+
+    /// `text` after this step; borrowed when the step changes nothing.
+    fn apply<'t>(&self, text: Cow<'t, T>, cx: &mut TransformContext) -> io::Result<Cow<'t, T>>;
+}
+
+/// `text` after `f`, which returns its argument borrowed when unchanged.
+fn chain<'t, T: ?Sized + ToOwned>(text: Cow<'t, T>, f: impl FnOnce(&T) -> io::Result<Cow<'_, T>>) -> io::Result<Cow<'t, T>> {
+    let changed = match f(&text)? {
+        Cow::Borrowed(_) => None,
+        Cow::Owned(owned) => Some(owned),
+    };
+    Ok(changed.map_or(text, Cow::Owned))
+}
+
+/// Ordered steps for one input.
+pub struct Pipeline<T: ?Sized + ToOwned + 'static> {
+    steps: Vec<Box<dyn Transform<T>>>,
+}
+
+impl<T: ?Sized + ToOwned + 'static> Default for Pipeline<T> {
+    fn default() -> Self {
+        Pipeline { steps: Vec::new() }
+    }
+}
+
+impl<T: ?Sized + ToOwned + 'static> Pipeline<T> {
+    /// Append a step.
+    pub fn push(&mut self, step: impl Transform<T> + 'static) -> &mut Self {
+        self.steps.push(Box::new(step));
+        self
+    }
+
+    /// Names of the steps, in order.
+    pub fn names(&self) -> Vec<&str> {
+        self.steps.iter().map(|s| s.name()).collect()
+    }
+
+    /// Run every step in order.
+    pub fn run<'t>(&self, mut text: Cow<'t, T>, cx: &mut TransformContext) -> io::Result<Cow<'t, T>> {
+        for step in &self.steps {
+            text = step.apply(text, cx)?;
+        }
+        Ok(text)
+    }
+
+    /// Like [`Pipeline::run`], skipping steps that fail (for dry runs,
+    /// whose report already covers what they reject).
+    pub fn run_lenient<'t>(&self, mut text: Cow<'t, T>, cx: &mut TransformContext) -> Cow<'t, T> {
+        for step in &self.steps {
+            let next = match step.apply(Cow::Borrowed(&*text), cx) {
+                Ok(Cow::Owned(owned)) => Some(owned),
+                // A shorter borrow is a cut, e.g. of a `##FASTA` section.
+                Ok(Cow::Borrowed(cut)) if std::mem::size_of_val(cut) != std::mem::size_of_val(&*text) => Some(cut.to_owned()),
+                _ => None,
+            };
+            if let Some(next) = next {
+                text = Cow::Owned(next);
+            }
+        }
+        text
+    }
+}
+
+impl Pipeline<[u8]> {
+    /// The FASTA pipeline `options` describe.
+    pub fn fasta(options: &IndexGenOptions) -> io::Result<Self> {
+        let mut out = Pipeline::default();
+        if options.fasta_transforms.is_empty() {
+            if options.strip_cr {
+                out.push(StripCr);
+            }
+            if options.rewrap_irregular {
+                out.push(Rewrap);
+            }
+        }
+        for spec in &options.fasta_transforms {
+            let (name, args) = split_spec(spec);
+            match name {
+                "strip_cr" => out.push(StripCr),
+                "rewrap" => out.push(Rewrap),
+                "filter_sequences" => out.push(FilterSequences(Selection::parse(spec, args)?)),
+                "split_at_gaps" if out.names().contains(&"split_at_gaps") => {
+                    return Err(io_error(ErrorCode::InvalidOption, "split_at_gaps is listed twice"))
+                }
+                "split_at_gaps" => out.push(SplitAtGaps::parse(spec, args)?),
+                "rename_seqids" => out.push(RenameSeqids::parse(spec, args)?),
+                "hook" => out.push(hook::Hook::fasta(spec, args)?),
+                _ => return Err(unknown("FASTA", spec)),
+            };
+        }
+        if let Some(replicons) = Replicons::from_options(options)? {
+            out.push(replicons);
+        }
+        Ok(out)
+    }
+}
+
+impl Pipeline<str> {
+    /// The GFF3 pipeline `options` describe.
+    pub fn gff(options: &IndexGenOptions) -> io::Result<Self> {
+        let mut out = Pipeline::default();
+        if options.gff_transforms.is_empty() {
+            out.push(GtfToGff3).push(NormalizeColumns).push(NormalizeCircular);
+        }
+        for spec in &options.gff_transforms {
+            let (name, args) = split_spec(spec);
+            match name {
+                "strip_fasta_section" => out.push(StripFastaSection),
+                "gtf_to_gff3" => out.push(GtfToGff3),
+                "normalize_columns" => out.push(NormalizeColumns),
+                "normalize_circular" => out.push(NormalizeCircular),
+                "filter_types" => out.push(FilterTypes(Selection::parse(spec, args)?)),
+                "rename_seqids" => out.push(RenameSeqids::parse(spec, args)?),
+                "unique_ids" => out.push(unique_ids::UniqueIds),
+                "renumber_locus_tags" => out.push(locus_tags::RenumberLocusTags::parse(spec, args)?),
+                "hook" => out.push(hook::Hook::gff(spec, args)?),
+                _ => return Err(unknown("GFF3", spec)),
+            };
+        }
+        if options.fasta_transforms.iter().any(|spec| split_spec(spec).0 == "split_at_gaps") {
+            out.push(RemapContigs);
+        }
+        if let Some(replicons) = Replicons::from_options(options)? {
+            let at = out.steps.iter().position(|s| s.name() == "normalize_circular").unwrap_or(out.steps.len());
+            out.steps.insert(at, Box::new(replicons));
+        }
+        Ok(out)
+    }
+}
+
+/// The FASTA and GFF3 pipelines of a run.
+#[derive(Default)]
+pub struct Pipelines {
+    pub fasta: Pipeline<[u8]>,
+    pub gff: Pipeline<str>,
+}
+
+impl Pipelines {
+    /// The pipelines `options` describe.
+    pub fn from_options(options: &IndexGenOptions) -> io::Result<Self> {
+        Ok(Pipelines { fasta: Pipeline::fasta(options)?, gff: Pipeline::gff(options)? })
+    }
+}
+
+fn split_spec(spec: &str) -> (&str, Option<&str>) {
+    match spec.split_once(':') {
+        Some((name, args)) => (name.trim(), Some(args)),
+        None => (spec.trim(), None),
+    }
+}
+
+fn unknown(input: &str, spec: &str) -> io::Error {
+    io_error(ErrorCode::InvalidOption, format!("unknown {} transform {:?}", input, spec))
+}
+
+fn bad_args(spec: &str, expected: &str) -> io::Error {
+    io_error(ErrorCode::InvalidOption, format!("transform {:?} needs {}", spec, expected))
+}
+
+// ---------------------------------------------------------------------------
+// Built-in steps
+// ---------------------------------------------------------------------------
+
+struct StripCr;
+
+impl Transform<[u8]> for StripCr {
+    fn name(&self) -> &str {
+        "strip_cr"
+    }
+
+    fn apply<'t>(&self, text: Cow<'t, [u8]>, _: &mut TransformContext) -> io::Result<Cow<'t, [u8]>> {
+        chain(text, |fa| Ok(fasta::strip_cr(fa)))
+    }
+}
+
+struct Rewrap;
+
+impl Transform<[u8]> for Rewrap {
+    fn name(&self) -> &str {
+        "rewrap"
+    }
+
+    fn apply<'t>(&self, text: Cow<'t, [u8]>, cx: &mut TransformContext) -> io::Result<Cow<'t, [u8]>> {
+        let Some(line) = fasta::first_irregular_line(&text) else {
+            return Ok(text);
+        };
+        let width = fasta::first_line_width(&text).unwrap_or(fasta::DEFAULT_LINE_WIDTH);
+        cx.warnings.push("fasta", "fasta_rewrapped", Some(line), format!("irregular line length; rewrapped to {} bases per line", width));
+        Ok(Cow::Owned(fasta::rewrap(&text, width)))
+    }
+}
+
+/// Names a `keep=`/`drop=` argument selects.
+struct Selection {
+    keep: bool,
+    names: HashSet<String>,
+}
+
+impl Selection {
+    fn parse(spec: &str, args: Option<&str>) -> io::Result<Self> {
+        let (keep, names) = match args.and_then(|a| a.split_once('=')) {
+            Some(("keep", names)) => (true, names),
+            Some(("drop", names)) => (false, names),
+            _ => return Err(bad_args(spec, "keep=<names> or drop=<names>")),
+        };
+        Ok(Selection { keep, names: names.split(',').map(|n| n.trim().to_owned()).filter(|n| !n.is_empty()).collect() })
+    }
+
+    fn selects(&self, name: &str) -> bool {
+        self.names.contains(name) == self.keep
+    }
+}
+
+/// First word of a FASTA header line, without the `>`.
+fn header_name(line: &[u8]) -> &[u8] {
+    line[1..].split(|b| b.is_ascii_whitespace()).next().unwrap_or_default()
+}
+
+/// `fa` with each header line replaced by what `f` returns for it
+/// (`Some(None)` dropping the record); borrowed when nothing changes.
+fn rewrite_records<'a>(fa: &'a [u8], mut f: impl FnMut(&'a [u8]) -> Option<Option<Vec<u8>>>) -> Cow<'a, [u8]> {
+    let mut out: Option<Vec<u8>> = None;
+    let (mut offset, mut dropping) = (0, false);
+    for line in fa.split_inclusive(|&b| b == b'\n') {
+        let replaced = if line.starts_with(b">") {
+            let new = f(line);
+            dropping = matches!(new, Some(None));
+            new
+        } else {
+            None
+        };
+        match (replaced, &mut out) {
+            (Some(new), out) => {
+                let out = out.get_or_insert_with(|| fa[..offset].to_vec());
+                out.extend_from_slice(new.as_deref().unwrap_or_default());
+            }
+            (None, Some(out)) if !dropping => out.extend_from_slice(line),
+            _ => {}
+        }
+        offset += line.len();
+    }
+    out.map_or(Cow::Borrowed(fa), Cow::Owned)
+}
+
+struct FilterSequences(Selection);
+
+impl Transform<[u8]> for FilterSequences {
+    fn name(&self) -> &str {
+        "filter_sequences"
+    }
+
+    fn apply<'t>(&self, text: Cow<'t, [u8]>, _: &mut TransformContext) -> io::Result<Cow<'t, [u8]>> {
+        chain(text, |fa| {
+            Ok(rewrite_records(fa, |line| {
+                let name = String::from_utf8_lossy(header_name(line));
+                (!self.0.selects(&name)).then_some(None)
+            }))
+        })
+    }
+}
+
+struct SplitAtGaps(u64);
+
+impl SplitAtGaps {
+    fn parse(spec: &str, args: Option<&str>) -> io::Result<Self> {
+        match args.map(|a| a.split_once('=')) {
+            None => Ok(SplitAtGaps(fasta::gaps::DEFAULT_MIN_GAP)),
+            Some(Some(("min_n", n))) => match n.trim().parse() {
+                Ok(n) if n > 0 => Ok(SplitAtGaps(n)),
+                _ => Err(bad_args(spec, "min_n=<n> with n at least 1")),
+            },
+            Some(_) => Err(bad_args(spec, "min_n=<n> or no arguments")),
+        }
+    }
+}
+
+impl Transform<[u8]> for SplitAtGaps {
+    fn name(&self) -> &str {
+        "split_at_gaps"
+    }
+
+    fn apply<'t>(&self, text: Cow<'t, [u8]>, cx: &mut TransformContext) -> io::Result<Cow<'t, [u8]>> {
+        chain(text, |fa| {
+            let (fa, map) = ContigMap::split(fa, self.0, cx.warnings)?;
+            *cx.contigs = map;
+            Ok(fa)
+        })
+    }
+}
+
+struct RemapContigs;
+
+impl Transform<str> for RemapContigs {
+    fn name(&self) -> &str {
+        "remap_contigs"
+    }
+
+    fn apply<'t>(&self, text: Cow<'t, str>, cx: &mut TransformContext) -> io::Result<Cow<'t, str>> {
+        if cx.contigs.min_gap.is_none() {
+            let message = "split_at_gaps needs the FASTA of the same run; the GFF3 cannot be moved onto contigs without it";
+            return Err(io_error(ErrorCode::InvalidOption, message));
+        }
+        chain(text, |gff| cx.contigs.remap_gff(gff, cx.warnings))
+    }
+}
+
+struct FilterTypes(Selection);
+
+impl Transform<str> for FilterTypes {
+    fn name(&self) -> &str {
+        "filter_types"
+    }
+
+    fn apply<'t>(&self, text: Cow<'t, str>, _: &mut TransformContext) -> io::Result<Cow<'t, str>> {
+        chain(text, |gff| {
+            rewrite_lines(gff, |_, line| {
+                let ftype = (!line.starts_with('#')).then(|| line.split('\t').nth(2)).flatten();
+                Ok(ftype.filter(|t| !self.0.selects(t)).map(|_| Cow::Borrowed("")))
+            })
+        })
+    }
+}
+
+struct RenameSeqids(HashMap<String, String>);
+
+impl RenameSeqids {
+    fn parse(spec: &str, args: Option<&str>) -> io::Result<Self> {
+        let mut map = HashMap::new();
+        for pair in args.unwrap_or_default().split(',').filter(|p| !p.trim().is_empty()) {
+            let (old, new) = pair.split_once('=').ok_or_else(|| bad_args(spec, "old=new pairs"))?;
+            map.insert(old.trim().to_owned(), new.trim().to_owned());
+        }
+        if map.is_empty() {
+            return Err(bad_args(spec, "old=new pairs"));
+        }
+        Ok(RenameSeqids(map))
+    }
+}
+
+impl Transform<[u8]> for RenameSeqids {
+    fn name(&self) -> &str {
+        "rename_seqids"
+    }
+
+    fn apply<'t>(&self, text: Cow<'t, [u8]>, _: &mut TransformContext) -> io::Result<Cow<'t, [u8]>> {
+        chain(text, |fa| {
+            Ok(rewrite_records(fa, |line| {
+                let name = header_name(line);
+                let new = self.0.get(std::str::from_utf8(name).ok()?)?;
+                Some(Some([b">", new.as_bytes(), &line[1 + name.len()..]].concat()))
+            }))
+        })
+    }
+}
+
+impl Transform<str> for RenameSeqids {
+    fn name(&self) -> &str {
+        "rename_seqids"
+    }
+
+    fn apply<'t>(&self, text: Cow<'t, str>, _: &mut TransformContext) -> io::Result<Cow<'t, str>> {
+        chain(text, |gff| {
+            rewrite_lines(gff, |_, line| {
+                let (prefix, rest) = match line.strip_prefix("##sequence-region") {
+                    Some(rest) => ("##sequence-region ", rest.trim_start()),
+                    None if line.starts_with('#') => return Ok(None),
+                    None => ("", line),
+                };
+                let end = rest.find(['\t', ' ', '\n', '\r']).unwrap_or(rest.len());
+                Ok(self.0.get(&rest[..end]).map(|new| Cow::Owned(format!("{}{}{}", prefix, new, &rest[end..]))))
+            })
+        })
+    }
+}
+
+struct StripFastaSection;
+
+impl Transform<str> for StripFastaSection {
+    fn name(&self) -> &str {
+        "strip_fasta_section"
+    }
+
+    fn apply<'t>(&self, text: Cow<'t, str>, _: &mut TransformContext) -> io::Result<Cow<'t, str>> {
+        let at = text.match_indices("##FASTA").map(|(i, _)| i).find(|&i| i == 0 || text.as_bytes()[i - 1] == b'\n');
+        Ok(match (at, text) {
+            (None, text) => text,
+            (Some(at), Cow::Borrowed(gff)) => Cow::Borrowed(&gff[..at]),
+            (Some(at), Cow::Owned(mut gff)) => {
+                gff.truncate(at);
+                Cow::Owned(gff)
+            }
+        })
+    }
+}
+
+struct GtfToGff3;
+
+impl Transform<str> for GtfToGff3 {
+    fn name(&self) -> &str {
+        "gtf_to_gff3"
+    }
+
+    fn apply<'t>(&self, text: Cow<'t, str>, cx: &mut TransformContext) -> io::Result<Cow<'t, str>> {
+        if gff::detect_dialect(&text) == gff::GffDialect::Gff3 {
+            return Ok(text);
+        }
+        crate::logw("GFF column 9 uses GTF syntax; converting to GFF3", None);
+        let (converted, added) = gff::gtf_to_gff3(&text);
+        cx.warnings.push("gff", "gtf_converted", None, format!("GTF/GFF2 attributes converted to GFF3; {} gene/transcript records added", added));
+        Ok(Cow::Owned(converted))
+    }
+}
+
+struct NormalizeColumns;
+
+impl Transform<str> for NormalizeColumns {
+    fn name(&self) -> &str {
+        "normalize_columns"
+    }
+
+    fn apply<'t>(&self, text: Cow<'t, str>, cx: &mut TransformContext) -> io::Result<Cow<'t, str>> {
+        chain(text, |gff| gff::normalize_columns(gff, cx.options.malformed_records, cx.warnings))
+    }
+}
+
+struct NormalizeCircular;
+
+impl Transform<str> for NormalizeCircular {
+    fn name(&self) -> &str {
+        "normalize_circular"
+    }
+
+    fn apply<'t>(&self, text: Cow<'t, str>, cx: &mut TransformContext) -> io::Result<Cow<'t, str>> {
+        chain(text, |gff| gff::normalize_circular(gff, cx.options.circular_features, cx.warnings))
+    }
+}
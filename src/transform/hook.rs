@@ -0,0 +1,191 @@
+//! Transform hooks: per-record callbacks registered at runtime, for fixes
+//! too site-specific for a built-in step (say an institution's `locus_tag`
+//! scheme) that should not need a new wasm build.
+//!
+//! A hook is registered under a label, from JS with
+//! [`register_gff_record_hook`] / [`register_fasta_header_hook`] or from
+//! Rust with [`register_gff_hook`] / [`register_fasta_hook`], and placed in
+//! a pipeline with the spec `hook:<label>`.  Hooks live in the calling
+//! thread (the worker that runs the pipeline), and only the label reaches
+//! the options digest: give a hook a new label when what it does changes,
+//! or the output cache may answer with results of the old one.
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use super::{bad_args, chain, rewrite_records, Transform, TransformContext};
+use crate::error::{io_error, js_error, ErrorCode};
+use crate::gff::rewrite_lines;
+
+/// GFF3 hook: a data record (without its line ending) and its line number
+/// to the replacement record, `None` dropping it.  A replacement may hold
+/// several records separated by `\n`.
+pub type GffHookFn = dyn Fn(&str, usize) -> io::Result<Option<String>>;
+
+/// FASTA hook: a header (without `>` and line ending) to the replacement
+/// header, `None` dropping the record.  A replacement with a line break
+/// fails the step with `E_TRANSFORM_HOOK`.
+pub type FastaHookFn = dyn Fn(&str) -> io::Result<Option<String>>;
+
+thread_local! {
+    static GFF_HOOKS: RefCell<HashMap<String, Rc<GffHookFn>>> = RefCell::new(HashMap::new());
+    static FASTA_HOOKS: RefCell<HashMap<String, Rc<FastaHookFn>>> = RefCell::new(HashMap::new());
+}
+
+fn check_label(label: &str) -> io::Result<&str> {
+    match label.trim() {
+        "" => Err(io_error(ErrorCode::InvalidOption, "a transform hook needs a non-empty label")),
+        label => Ok(label),
+    }
+}
+
+/// Register (or replace) the GFF3 hook `label`.
+pub fn register_gff_hook(label: &str, hook: impl Fn(&str, usize) -> io::Result<Option<String>> + 'static) -> io::Result<()> {
+    let label = check_label(label)?;
+    GFF_HOOKS.with(|hooks| hooks.borrow_mut().insert(label.to_owned(), Rc::new(hook)));
+    Ok(())
+}
+
+/// Register (or replace) the FASTA hook `label`.
+pub fn register_fasta_hook(label: &str, hook: impl Fn(&str) -> io::Result<Option<String>> + 'static) -> io::Result<()> {
+    let label = check_label(label)?;
+    FASTA_HOOKS.with(|hooks| hooks.borrow_mut().insert(label.to_owned(), Rc::new(hook)));
+    Ok(())
+}
+
+/// Remove the hooks registered as `label`; whether there were any.
+#[wasm_bindgen]
+pub fn unregister_transform_hook(label: &str) -> bool {
+    let label = label.trim();
+    let gff = GFF_HOOKS.with(|hooks| hooks.borrow_mut().remove(label).is_some());
+    let fasta = FASTA_HOOKS.with(|hooks| hooks.borrow_mut().remove(label).is_some());
+    gff || fasta
+}
+
+/// What a JS hook returned, as a Rust hook's result.
+fn js_outcome(label: &str, result: Result<JsValue, JsValue>) -> io::Result<Option<String>> {
+    match result {
+        Err(thrown) => {
+            let message = match thrown.dyn_ref::<js_sys::Error>() {
+                Some(e) => String::from(e.message()),
+                None => thrown.as_string().unwrap_or_else(|| format!("{:?}", thrown)),
+            };
+            Err(io_error(ErrorCode::TransformHook, format!("transform hook {:?} threw: {}", label, message)))
+        }
+        Ok(value) if value.is_null() || value.is_undefined() => Ok(None),
+        Ok(value) => value.as_string().map(Some).ok_or_else(|| {
+            io_error(ErrorCode::TransformHook, format!("transform hook {:?} returned neither a string nor null", label))
+        }),
+    }
+}
+
+/// Register `callback(record, lineNumber)` as the GFF3 hook `label`.  It
+/// sees each data record without its line ending and returns the record to
+/// write (one or more lines), or `null`/`undefined` to drop it.
+#[wasm_bindgen]
+pub fn register_gff_record_hook(label: &str, callback: js_sys::Function) -> Result<(), JsValue> {
+    let name = label.trim().to_owned();
+    register_gff_hook(label, move |record, line| {
+        js_outcome(&name, callback.call2(&JsValue::NULL, &JsValue::from_str(record), &JsValue::from(line as u32)))
+    })
+    .map_err(js_error)
+}
+
+/// Register `callback(header)` as the FASTA hook `label`.  It sees each
+/// header without the `>` and returns the header to write, or
+/// `null`/`undefined` to drop the record.
+#[wasm_bindgen]
+pub fn register_fasta_header_hook(label: &str, callback: js_sys::Function) -> Result<(), JsValue> {
+    let name = label.trim().to_owned();
+    register_fasta_hook(label, move |header| js_outcome(&name, callback.call1(&JsValue::NULL, &JsValue::from_str(header))))
+        .map_err(js_error)
+}
+
+fn unregistered(spec: &str, input: &str) -> io::Error {
+    io_error(ErrorCode::InvalidOption, format!("transform {:?}: no {} hook registered under that label", spec, input))
+}
+
+/// A registered hook as a pipeline step.
+pub(super) struct Hook<F: ?Sized> {
+    name: String,
+    hook: Rc<F>,
+}
+
+impl Hook<GffHookFn> {
+    pub(super) fn gff(spec: &str, args: Option<&str>) -> io::Result<Self> {
+        let label = check_label(args.unwrap_or_default()).map_err(|_| bad_args(spec, "a label"))?;
+        let hook = GFF_HOOKS.with(|hooks| hooks.borrow().get(label).cloned()).ok_or_else(|| unregistered(spec, "GFF3"))?;
+        Ok(Hook { name: format!("hook:{}", label), hook })
+    }
+}
+
+impl Hook<FastaHookFn> {
+    pub(super) fn fasta(spec: &str, args: Option<&str>) -> io::Result<Self> {
+        let label = check_label(args.unwrap_or_default()).map_err(|_| bad_args(spec, "a label"))?;
+        let hook = FASTA_HOOKS.with(|hooks| hooks.borrow().get(label).cloned()).ok_or_else(|| unregistered(spec, "FASTA"))?;
+        Ok(Hook { name: format!("hook:{}", label), hook })
+    }
+}
+
+impl Transform<str> for Hook<GffHookFn> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn apply<'t>(&self, text: Cow<'t, str>, _: &mut TransformContext) -> io::Result<Cow<'t, str>> {
+        chain(text, |gff| {
+            rewrite_lines(gff, |n, line| {
+                let record = line.trim_end_matches(['\n', '\r']);
+                if record.is_empty() || record.starts_with('#') {
+                    return Ok(None);
+                }
+                Ok(match (self.hook)(record, n)? {
+                    Some(new) if new == record => None,
+                    Some(new) if !new.trim().is_empty() => Some(Cow::Owned(format!("{}\n", new.trim_end_matches('\n')))),
+                    _ => Some(Cow::Borrowed("")),
+                })
+            })
+        })
+    }
+}
+
+impl Transform<[u8]> for Hook<FastaHookFn> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn apply<'t>(&self, text: Cow<'t, [u8]>, _: &mut TransformContext) -> io::Result<Cow<'t, [u8]>> {
+        chain(text, |fa| {
+            let mut failed = None;
+            let out = rewrite_records(fa, |line| {
+                if failed.is_some() {
+                    return None;
+                }
+                let header = String::from_utf8_lossy(&line[1..]);
+                let header = header.trim_end_matches(['\n', '\r']);
+                match (self.hook)(header) {
+                    Ok(Some(new)) if new == header => None,
+                    // A line break would start sequence data, or another record, mid-header.
+                    Ok(Some(new)) if new.trim_end().contains(['\n', '\r']) => {
+                        let message = format!("transform hook {:?} returned a header with a line break: {:?}", &self.name["hook:".len()..], new);
+                        failed = Some(io_error(ErrorCode::TransformHook, message));
+                        None
+                    }
+                    Ok(Some(new)) => Some(Some(format!(">{}\n", new.trim_end()).into_bytes())),
+                    Ok(None) => Some(None),
+                    Err(e) => {
+                        failed = Some(e);
+                        None
+                    }
+                }
+            });
+            failed.map_or(Ok(out), Err)
+        })
+    }
+}
@@ -0,0 +1,168 @@
+//! Locus tags as ENA wants them: `<prefix>_<id>`, the prefix registered
+//! with the study, 3 to 12 letters and digits starting with a letter, and
+//! the id letters and digits.
+//!
+//! Validation checks every `locus_tag` against that format, against the
+//! prefix of the first one, and for numbering that goes down in file order
+//! (see `validate.rs`).  The `renumber_locus_tags:prefix=<P>` GFF3 step
+//! gives the features new tags instead: `<P>_00001`, `<P>_00002`, … in order
+//! of first appearance, optionally `step=<n>` apart and `width=<w>` digits
+//! wide (defaults 1 and 5).  Records sharing an old tag (a gene and its CDS)
+//! share the new one, and `ID` and `Parent` values naming an old tag, such
+//! as `cds-ABC_0042`, are rewritten to match.  A `locus_tags_renumbered`
+//! warning gives the count.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io;
+
+use super::{bad_args, chain, Transform, TransformContext};
+use crate::error::{io_error, ErrorCode};
+use crate::gff::{attribute, rewrite_lines, GffFields};
+
+/// Why `prefix` is not an ENA locus tag prefix, if it is not.
+pub fn prefix_error(prefix: &str) -> Option<&'static str> {
+    if !(3..=12).contains(&prefix.len()) {
+        Some("a prefix has 3 to 12 characters")
+    } else if !prefix.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        Some("a prefix starts with a letter")
+    } else if !prefix.chars().all(|c| c.is_ascii_alphanumeric()) {
+        Some("a prefix has only letters and digits")
+    } else {
+        None
+    }
+}
+
+/// `(prefix, id)` of a locus tag of the ENA format, or why it is not one.
+pub fn split_locus_tag(tag: &str) -> Result<(&str, &str), &'static str> {
+    let (prefix, id) = tag.split_once('_').ok_or("expected <prefix>_<id>")?;
+    if let Some(error) = prefix_error(prefix) {
+        return Err(error);
+    }
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err("an id has only letters and digits");
+    }
+    Ok((prefix, id))
+}
+
+/// The number an id ends with (`00042` in `00042`, `42` in `B42`), if any.
+pub fn trailing_number(id: &str) -> Option<u64> {
+    let digits = id.len() - id.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    id[id.len() - digits..].parse().ok()
+}
+
+pub(super) struct RenumberLocusTags {
+    prefix: String,
+    step: u64,
+    width: usize,
+}
+
+impl RenumberLocusTags {
+    pub(super) fn parse(spec: &str, args: Option<&str>) -> io::Result<Self> {
+        const EXPECTED: &str = "prefix=<prefix>, optionally with step=<n> and width=<w>";
+        let mut out = RenumberLocusTags { prefix: String::new(), step: 1, width: 5 };
+        for pair in args.unwrap_or_default().split(',').filter(|p| !p.trim().is_empty()) {
+            match pair.split_once('=').map(|(k, v)| (k.trim(), v.trim())) {
+                Some(("prefix", prefix)) => out.prefix = prefix.to_owned(),
+                Some(("step", n)) => out.step = n.parse().ok().filter(|&n| n > 0).ok_or_else(|| bad_args(spec, EXPECTED))?,
+                Some(("width", w)) => out.width = w.parse().ok().filter(|&w| w <= 20).ok_or_else(|| bad_args(spec, EXPECTED))?,
+                _ => return Err(bad_args(spec, EXPECTED)),
+            }
+        }
+        match prefix_error(&out.prefix) {
+            Some(_) if out.prefix.is_empty() => Err(bad_args(spec, EXPECTED)),
+            Some(error) => Err(bad_args(spec, &format!("an ENA locus tag prefix ({})", error))),
+            None => Ok(out),
+        }
+    }
+
+    /// New tags of the old ones of `gff`, numbered in order of first
+    /// appearance.  A `step` too large for the number of tags fails with
+    /// `E_INVALID_OPTION`.
+    fn new_tags<'a>(&self, gff: &'a str) -> io::Result<HashMap<&'a str, String>> {
+        let mut tags = HashMap::new();
+        for tag in crate::gff::data_lines(gff).filter_map(GffFields::parse).filter_map(|f| f.attribute("locus_tag")) {
+            if tags.contains_key(tag) {
+                continue;
+            }
+            let number = (tags.len() as u64 + 1).checked_mul(self.step).ok_or_else(|| {
+                let message = format!("renumber_locus_tags: tag {} at step={} is past the largest number a tag can have", tags.len() + 1, self.step);
+                io_error(ErrorCode::InvalidOption, message)
+            })?;
+            tags.insert(tag, format!("{}_{:0width$}", self.prefix, number, width = self.width));
+        }
+        Ok(tags)
+    }
+}
+
+/// `value` with every old tag in `tags` it names replaced, an old tag
+/// being named where it is neither preceded nor followed by a letter or
+/// digit (so `cds-ABC_1` and `ABC_1.t1` name `ABC_1`, `ABC_10` does not).
+fn rename_tags(value: &str, tags: &HashMap<&str, String>) -> Option<String> {
+    let boundary = |c: Option<char>| !c.is_some_and(|c| c.is_ascii_alphanumeric());
+    let mut out = String::new();
+    let mut copied = 0;
+    let mut i = 0;
+    while i < value.len() {
+        let found = boundary(value[..i].chars().next_back())
+            .then(|| {
+                // The longest old tag starting here.
+                value[i..]
+                    .char_indices()
+                    .map(|(j, _)| i + j)
+                    .chain([value.len()])
+                    .skip(1)
+                    .filter(|&end| boundary(value[end..].chars().next()))
+                    .filter_map(|end| tags.get(&value[i..end]).map(|new| (end, new)))
+                    .last()
+            })
+            .flatten();
+        match found {
+            Some((end, new)) => {
+                out.push_str(&value[copied..i]);
+                out.push_str(new);
+                copied = end;
+                i = end;
+            }
+            None => i += value[i..].chars().next().map_or(1, char::len_utf8),
+        }
+    }
+    (copied > 0).then(|| out + &value[copied..])
+}
+
+impl Transform<str> for RenumberLocusTags {
+    fn name(&self) -> &str {
+        "renumber_locus_tags"
+    }
+
+    fn apply<'t>(&self, text: Cow<'t, str>, cx: &mut TransformContext) -> io::Result<Cow<'t, str>> {
+        chain(text, |gff| {
+            let tags = self.new_tags(gff)?;
+            if tags.is_empty() {
+                return Ok(Cow::Borrowed(gff));
+            }
+            cx.warnings.push("gff", "locus_tags_renumbered", None, format!("{} locus tags renumbered with prefix {}", tags.len(), self.prefix));
+            rewrite_lines(gff, |_, line| {
+                let Some(f) = GffFields::parse(line) else {
+                    return Ok(None);
+                };
+                if ["locus_tag", "ID", "Parent"].iter().all(|key| attribute(f.attributes, key).is_none()) {
+                    return Ok(None);
+                }
+                let attributes: Vec<Cow<str>> = f
+                    .attributes
+                    .split(';')
+                    .map(|kv| match kv.split_once('=') {
+                        Some(("locus_tag", old)) => tags.get(old).map_or(Cow::Borrowed(kv), |new| Cow::Owned(format!("locus_tag={}", new))),
+                        Some((key @ ("ID" | "Parent"), value)) => {
+                            rename_tags(value, &tags).map_or(Cow::Borrowed(kv), |value| Cow::Owned(format!("{}={}", key, value)))
+                        }
+                        _ => Cow::Borrowed(kv),
+                    })
+                    .collect();
+                let columns: Vec<&str> = line.splitn(9, '\t').take(8).collect();
+                Ok(Some(Cow::Owned(format!("{}\t{}\n", columns.join("\t"), attributes.join(";")))))
+            })
+        })
+    }
+}
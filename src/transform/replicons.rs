@@ -0,0 +1,202 @@
+//! Replicon metadata (chromosome or plasmid, linear or circular), tagged
+//! onto the outputs for submission.
+//!
+//! `IndexGenOptions::replicon_metadata` is a tab-separated table, one
+//! sequence per line: `contig  replicon type  topology`, the type a single
+//! word (`chromosome`, `plasmid`, …) and the topology `linear` or
+//! `circular`.  Blank lines and `#` comments are skipped, as is a first
+//! line whose topology is neither (a header).  A malformed line or a
+//! contig listed twice is `E_INVALID_OPTION`.
+//!
+//! When it is set, both pipelines get a `tag_replicons` step:
+//!
+//! - FASTA: the header of every listed sequence gets
+//!   `[location=<type>] [topology=<topology>]` source modifiers, as
+//!   table2asn reads them, replacing any it had.  A listed contig the FASTA
+//!   does not have is `E_UNKNOWN_SEQUENCE`, so the table is checked against
+//!   the sequences of the `.fai`.  The step runs last, on the names the
+//!   outputs have.
+//! - GFF3: the `region` records of listed sequences get `genome=<type>`
+//!   and, when circular, `Is_circular=true` (any previous values dropped).
+//!   A sequence without a `region` record gets one after its
+//!   `##sequence-region` pragma, or a `replicon_region_missing` warning
+//!   when it has neither.  The step runs before `normalize_circular`, so
+//!   features crossing the origin of a sequence the table makes circular
+//!   are handled like any other.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io;
+
+use super::{chain, header_name, rewrite_records, Transform, TransformContext};
+use crate::error::{io_error, ErrorCode};
+use crate::gff::circular::IS_CIRCULAR;
+use crate::gff::{rewrite_lines, GffFields};
+use crate::options::IndexGenOptions;
+
+/// GFF3 attribute of the replicon type, as NCBI's GFF3 reader takes it.
+const GENOME: &str = "genome";
+
+/// Type and topology of one sequence.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Replicon {
+    /// Lower case.
+    pub kind: String,
+    pub circular: bool,
+}
+
+impl Replicon {
+    /// FASTA header source modifiers.
+    fn header_tags(&self) -> String {
+        format!("[location={}] [topology={}]", self.kind, if self.circular { "circular" } else { "linear" })
+    }
+}
+
+/// The replicon table, by contig.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Replicons(pub HashMap<String, Replicon>);
+
+fn metadata_error(line: usize, message: impl std::fmt::Display) -> io::Error {
+    io_error(ErrorCode::InvalidOption, format!("replicon_metadata line {}: {}", line, message))
+}
+
+impl Replicons {
+    /// Read the table.
+    pub fn parse(tsv: &str) -> io::Result<Self> {
+        let mut out = HashMap::new();
+        let mut first = true;
+        for (n, line) in tsv.split('\n').enumerate().map(|(n, line)| (n + 1, line.trim_end_matches('\r'))) {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+            let header = std::mem::replace(&mut first, false);
+            let circular = match fields.get(2).map(|t| t.to_ascii_lowercase()).as_deref() {
+                Some("circular") => true,
+                Some("linear") => false,
+                _ if header => continue,
+                Some(other) => return Err(metadata_error(n, format!("topology {:?} is neither linear nor circular", other))),
+                None => return Err(metadata_error(n, "expected `contig<TAB>replicon type<TAB>topology`")),
+            };
+            let kind = fields[1].to_ascii_lowercase();
+            if kind.is_empty() || kind.contains(|c: char| c.is_whitespace() || "[]=;,".contains(c)) {
+                return Err(metadata_error(n, format!("bad replicon type {:?}", fields[1])));
+            }
+            if out.insert(fields[0].to_owned(), Replicon { kind, circular }).is_some() {
+                return Err(metadata_error(n, format!("contig {:?} is listed twice", fields[0])));
+            }
+        }
+        Ok(Replicons(out))
+    }
+
+    /// The table of `options`, if one is set.
+    pub fn from_options(options: &IndexGenOptions) -> io::Result<Option<Self>> {
+        if options.replicon_metadata.trim().is_empty() {
+            return Ok(None);
+        }
+        Self::parse(&options.replicon_metadata).map(Some)
+    }
+
+    /// Column 9 of a `region` record of `replicon`: `attributes` without
+    /// its own type and topology, then ours.
+    fn region_attributes(attributes: &str, replicon: &Replicon) -> String {
+        let mut kept: Vec<&str> = attributes
+            .split(';')
+            .filter(|kv| !kv.trim().is_empty() && *kv != ".")
+            .filter(|kv| !matches!(kv.split_once('=').map(|(k, _)| k.trim()), Some(GENOME | IS_CIRCULAR)))
+            .collect();
+        let genome = format!("{}={}", GENOME, replicon.kind);
+        kept.push(&genome);
+        let circular = format!("{}=true", IS_CIRCULAR);
+        if replicon.circular {
+            kept.push(&circular);
+        }
+        kept.join(";")
+    }
+}
+
+/// `header` (a `>` line, newline included) with the source modifiers of
+/// `replicon` in place of any it had.
+fn tag_header(header: &[u8], replicon: &Replicon) -> Vec<u8> {
+    let text = String::from_utf8_lossy(header);
+    let text = text.trim_end_matches(['\n', '\r']);
+    let mut words: Vec<&str> = Vec::new();
+    let mut rest = text;
+    while let Some(open) = rest.find("[location=").into_iter().chain(rest.find("[topology=")).min() {
+        words.push(&rest[..open]);
+        rest = rest[open..].find(']').map_or("", |close| &rest[open + close + 1..]);
+    }
+    words.push(rest);
+    let kept = words.concat().split_whitespace().collect::<Vec<_>>().join(" ");
+    format!("{} {}\n", kept, replicon.header_tags()).into_bytes()
+}
+
+impl Transform<[u8]> for Replicons {
+    fn name(&self) -> &str {
+        "tag_replicons"
+    }
+
+    fn apply<'t>(&self, text: Cow<'t, [u8]>, _: &mut TransformContext) -> io::Result<Cow<'t, [u8]>> {
+        chain(text, |fa| {
+            let names: HashSet<&[u8]> = fa.split(|&b| b == b'\n').filter(|l| l.starts_with(b">")).map(header_name).collect();
+            let mut unknown: Vec<&str> = self.0.keys().map(String::as_str).filter(|c| !names.contains(c.as_bytes())).collect();
+            if !unknown.is_empty() {
+                unknown.sort_unstable();
+                let message = format!("replicon_metadata lists {:?}, which the FASTA does not have", unknown);
+                return Err(io_error(ErrorCode::UnknownSequence, message));
+            }
+            Ok(rewrite_records(fa, |line| {
+                let replicon = self.0.get(std::str::from_utf8(header_name(line)).ok()?)?;
+                Some(Some(tag_header(line, replicon)))
+            }))
+        })
+    }
+}
+
+impl Transform<str> for Replicons {
+    fn name(&self) -> &str {
+        "tag_replicons"
+    }
+
+    fn apply<'t>(&self, text: Cow<'t, str>, cx: &mut TransformContext) -> io::Result<Cow<'t, str>> {
+        chain(text, |gff| {
+            let mut with_region: HashSet<&str> = HashSet::new();
+            let mut with_pragma: HashSet<&str> = HashSet::new();
+            for line in gff.split('\n').take_while(|l| !l.starts_with("##FASTA")) {
+                if let Some(pragma) = line.strip_prefix("##sequence-region") {
+                    with_pragma.extend(pragma.split_whitespace().next());
+                } else if let Some(f) = GffFields::parse(line).filter(|f| f.ftype == "region") {
+                    with_region.insert(f.seqid);
+                }
+            }
+            let mut missing: Vec<&str> = self.0.keys().map(String::as_str).filter(|c| !with_region.contains(c) && !with_pragma.contains(c)).collect();
+            missing.sort_unstable();
+            for seqid in missing {
+                cx.warnings.push("gff", "replicon_region_missing", None, format!("{}: no region record or ##sequence-region to tag", seqid));
+            }
+            rewrite_lines(gff, |_, line| {
+                if let Some(pragma) = line.strip_prefix("##sequence-region") {
+                    // A pragma of a listed sequence without a region record
+                    // gets one after it.
+                    let [seqid, start, end] = pragma.split_whitespace().collect::<Vec<_>>()[..] else {
+                        return Ok(None);
+                    };
+                    let Some(replicon) = self.0.get(seqid).filter(|_| !with_region.contains(seqid)) else {
+                        return Ok(None);
+                    };
+                    let attributes = Replicons::region_attributes("", replicon);
+                    let pragma = line.trim_end_matches(['\n', '\r']);
+                    return Ok(Some(Cow::Owned(format!("{}\n{}\t.\tregion\t{}\t{}\t.\t+\t.\t{}\n", pragma, seqid, start, end, attributes))));
+                }
+                let Some(f) = GffFields::parse(line).filter(|f| f.ftype == "region") else {
+                    return Ok(None);
+                };
+                let Some(replicon) = self.0.get(f.seqid) else {
+                    return Ok(None);
+                };
+                let columns: Vec<&str> = line.splitn(9, '\t').take(8).collect();
+                Ok(Some(Cow::Owned(format!("{}\t{}\n", columns.join("\t"), Replicons::region_attributes(f.attributes, replicon)))))
+            })
+        })
+    }
+}
@@ -0,0 +1,233 @@
+//! Globally unique `ID`s, which JBrowse needs to tell features apart.
+//!
+//! Records of a multi-part type ([`MULTIPART_TYPES`]) sharing seqid, type,
+//! `ID` and `Parent` are the parts of one feature (see `gff/multipart.rs`)
+//! and keep sharing it; any other record with an `ID` is a feature of its
+//! own.  When an `ID` belongs to several features (different types, as some
+//! tools write for a gene and its mRNA, or repeated genes, as merged
+//! annotations have), the `unique_ids` step keeps it on the first and gives
+//! the others `<ID>_2`, `<ID>_3`, … (skipping suffixes already in use), in
+//! file order.  Each renamed feature gets an `id_renamed` warning.
+//!
+//! `Parent` and `Derives_from` values naming a renamed `ID` are rewritten
+//! to the feature they most likely meant: the last one with that `ID` on
+//! the same sequence before the referring record, else the first one on
+//! that sequence.  References to other sequences are left as they are.
+//! Every rename and rewrite is recorded in [`IdRenames`], which the
+//! warnings, capped per code, cannot hold in full.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io;
+
+use super::{chain, Transform, TransformContext};
+use crate::gff::{rewrite_lines, GffFields};
+
+/// Attributes whose values are `ID`s of other features.
+const REFERENCES: &[&str] = &["Parent", "Derives_from"];
+
+/// Types written as one record per part, the parts sharing `ID` and
+/// `Parent`: spliced CDSs, and alignments as the GFF3 specification writes
+/// them.
+pub const MULTIPART_TYPES: &[&str] =
+    &["CDS", "match", "cDNA_match", "EST_match", "nucleotide_match", "protein_match", "translated_nucleotide_match", "nucleotide_to_protein_match"];
+
+/// A feature the step gave a new `ID`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RenamedFeature {
+    pub seqid: String,
+    pub ftype: String,
+    pub id: String,
+    pub new_id: String,
+    /// 1-based line of its first record.
+    pub line: usize,
+}
+
+/// A `Parent` or `Derives_from` value the step rewrote.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RewrittenReference {
+    /// 1-based line of the referring record.
+    pub line: usize,
+    pub attribute: String,
+    pub id: String,
+    pub new_id: String,
+}
+
+/// Everything `unique_ids` changed, in file order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IdRenames {
+    pub features: Vec<RenamedFeature>,
+    pub references: Vec<RewrittenReference>,
+}
+
+impl IdRenames {
+    /// `{features: [{seqid, type, id, new_id, line}], references: [{line,
+    /// attribute, id, new_id}]}`.
+    pub fn to_json(&self) -> json::JsonValue {
+        let features: Vec<json::JsonValue> = self
+            .features
+            .iter()
+            .map(|f| json::object! { seqid: f.seqid.as_str(), type: f.ftype.as_str(), id: f.id.as_str(), new_id: f.new_id.as_str(), line: f.line })
+            .collect();
+        let references: Vec<json::JsonValue> = self
+            .references
+            .iter()
+            .map(|r| json::object! { line: r.line, attribute: r.attribute.as_str(), id: r.id.as_str(), new_id: r.new_id.as_str() })
+            .collect();
+        json::object! { features: features, references: references }
+    }
+
+    /// The renames [`IdRenames::to_json`] wrote.
+    pub fn from_json(value: &json::JsonValue) -> Self {
+        let text = |v: &json::JsonValue| v.as_str().unwrap_or_default().to_owned();
+        IdRenames {
+            features: value["features"]
+                .members()
+                .map(|f| RenamedFeature {
+                    seqid: text(&f["seqid"]),
+                    ftype: text(&f["type"]),
+                    id: text(&f["id"]),
+                    new_id: text(&f["new_id"]),
+                    line: f["line"].as_usize().unwrap_or(0),
+                })
+                .collect(),
+            references: value["references"]
+                .members()
+                .map(|r| RewrittenReference { line: r["line"].as_usize().unwrap_or(0), attribute: text(&r["attribute"]), id: text(&r["id"]), new_id: text(&r["new_id"]) })
+                .collect(),
+        }
+    }
+}
+
+pub(super) struct UniqueIds;
+
+/// Which feature a record with an `ID` belongs to, besides its seqid, type
+/// and `ID`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Owner<'a> {
+    /// The parts of a multi-part type with this `Parent`.
+    Parent(Option<&'a str>),
+    /// A feature of its own, by its line.
+    Line(usize),
+}
+
+type FeatureKey<'a> = (&'a str, &'a str, &'a str, Owner<'a>);
+
+/// The feature of the record `f` on line `line_no`; `None` for a record
+/// without an `ID`.
+fn feature_key<'a>(line_no: usize, f: &GffFields<'a>) -> Option<FeatureKey<'a>> {
+    let id = f.attribute("ID")?;
+    let owner = if MULTIPART_TYPES.contains(&f.ftype) { Owner::Parent(f.attribute("Parent")) } else { Owner::Line(line_no) };
+    Some((f.seqid, f.ftype, id, owner))
+}
+
+/// Records before any `##FASTA` section with their 1-based line numbers,
+/// as [`rewrite_lines`] numbers them.
+fn numbered_records(gff: &str) -> impl Iterator<Item = (usize, GffFields<'_>)> {
+    gff.split_inclusive('\n')
+        .take_while(|l| !l.starts_with("##FASTA"))
+        .enumerate()
+        .filter_map(|(i, line)| GffFields::parse(line).map(|f| (i + 1, f)))
+}
+
+/// The new `ID` of every feature that gets one.
+fn renames(gff: &str) -> HashMap<FeatureKey<'_>, String> {
+    let records: Vec<(usize, GffFields)> = numbered_records(gff).collect();
+    let mut taken: HashSet<Cow<str>> = records.iter().filter_map(|(_, f)| f.attribute("ID")).map(Cow::Borrowed).collect();
+    let mut owner: HashMap<&str, FeatureKey> = HashMap::new();
+    let mut out = HashMap::new();
+    for key @ (_, _, id, _) in records.iter().filter_map(|(line_no, f)| feature_key(*line_no, f)) {
+        let first = *owner.entry(id).or_insert(key);
+        if first == key || out.contains_key(&key) {
+            continue;
+        }
+        let new = (2..).map(|n| format!("{}_{}", id, n)).find(|new| !taken.contains(new.as_str())).unwrap_or_default();
+        taken.insert(Cow::Owned(new.clone()));
+        out.insert(key, new);
+    }
+    out
+}
+
+impl Transform<str> for UniqueIds {
+    fn name(&self) -> &str {
+        "unique_ids"
+    }
+
+    fn apply<'t>(&self, text: Cow<'t, str>, cx: &mut TransformContext) -> io::Result<Cow<'t, str>> {
+        chain(text, |gff| {
+            let renames = renames(gff);
+            if renames.is_empty() {
+                return Ok(Cow::Borrowed(gff));
+            }
+            let renamed: HashSet<&str> = renames.keys().map(|(_, _, id, _)| *id).collect();
+            // The new ID of the first feature with a renamed ID on each
+            // sequence; `last` follows the records in file order.
+            let mut first: HashMap<(&str, &str), &str> = HashMap::new();
+            for (line_no, f) in numbered_records(gff) {
+                if let Some(key @ (seqid, _, id, _)) = feature_key(line_no, &f).filter(|(_, _, id, _)| renamed.contains(id)) {
+                    first.entry((seqid, id)).or_insert_with(|| renames.get(&key).map_or(id, String::as_str));
+                }
+            }
+            let mut last: HashMap<(&str, &str), &str> = HashMap::new();
+            let mut reported: HashSet<FeatureKey> = HashSet::new();
+            let references_before = cx.id_renames.references.len();
+            let text = rewrite_lines(gff, |line_no, line| {
+                let Some(f) = GffFields::parse(line) else {
+                    return Ok(None);
+                };
+                let mut changed = false;
+                let mut attributes: Vec<Cow<str>> = f
+                    .attributes
+                    .split(';')
+                    .map(|kv| match kv.split_once('=') {
+                        Some((key, values)) if REFERENCES.contains(&key) => {
+                            let values: Vec<&str> = values
+                                .split(',')
+                                .map(|v| match last.get(&(f.seqid, v)).or(first.get(&(f.seqid, v))) {
+                                    Some(&new) if renamed.contains(v) && new != v => {
+                                        changed = true;
+                                        let rewrite = RewrittenReference { line: line_no, attribute: key.to_owned(), id: v.to_owned(), new_id: new.to_owned() };
+                                        cx.id_renames.references.push(rewrite);
+                                        new
+                                    }
+                                    _ => v,
+                                })
+                                .collect();
+                            Cow::Owned(format!("{}={}", key, values.join(",")))
+                        }
+                        _ => Cow::Borrowed(kv),
+                    })
+                    .collect();
+                if let Some(key @ (seqid, ftype, id, _)) = feature_key(line_no, &f) {
+                    let new = renames.get(&key);
+                    if renamed.contains(id) {
+                        last.insert((seqid, id), new.map_or(id, String::as_str));
+                    }
+                    if let Some(new) = new {
+                        for kv in attributes.iter_mut().filter(|kv| kv.split_once('=').is_some_and(|(k, _)| k == "ID")) {
+                            *kv = Cow::Owned(format!("ID={}", new));
+                        }
+                        changed = true;
+                        if reported.insert(key) {
+                            let message = format!("ID {:?} of {} on {} is also used by another feature; renamed to {:?}", id, ftype, seqid, new);
+                            cx.warnings.push("gff", "id_renamed", Some(line_no), message);
+                            let feature = RenamedFeature { seqid: seqid.to_owned(), ftype: ftype.to_owned(), id: id.to_owned(), new_id: new.clone(), line: line_no };
+                            cx.id_renames.features.push(feature);
+                        }
+                    }
+                }
+                if !changed {
+                    return Ok(None);
+                }
+                let columns: Vec<&str> = line.splitn(9, '\t').take(8).collect();
+                Ok(Some(Cow::Owned(format!("{}\t{}\n", columns.join("\t"), attributes.join(";")))))
+            })?;
+            let rewritten = cx.id_renames.references.len() - references_before;
+            if rewritten > 0 {
+                let message = format!("{} Parent/Derives_from values rewritten to follow renamed IDs; id_renames lists them", rewritten);
+                cx.warnings.push("gff", "id_references_rewritten", None, message);
+            }
+            Ok(text)
+        })
+    }
+}
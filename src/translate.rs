@@ -0,0 +1,143 @@
+//! Codon translation.
+
+use std::io;
+
+use crate::error::{io_error, ErrorCode};
+
+const BASES: &[u8; 4] = b"TCAG";
+
+/// NCBI genetic code table used when none is given: bacterial, archaeal
+/// and plant plastid.
+pub const DEFAULT_TABLE: u32 = 11;
+
+/// The NCBI genetic code tables (`gc.prt`): number, amino acids and start
+/// codons (`M`), indexed by codon in TCAG order.  In tables 27, 28 and 31
+/// the stop codons that also code for an amino acid read as that amino
+/// acid.
+#[rustfmt::skip]
+const TABLES: &[(u32, &[u8; 64], &[u8; 64])] = &[
+    // Standard
+    (1, b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", b"---M---------------M---------------M----------------------------"),
+    // Vertebrate Mitochondrial
+    (2, b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSS**VVVVAAAADDEEGGGG", b"--------------------------------MMMM---------------M------------"),
+    // Yeast Mitochondrial
+    (3, b"FFLLSSSSYY**CCWWTTTTPPPPHHQQRRRRIIMMTTTTNNKKSSRRVVVVAAAADDEEGGGG", b"----------------------------------MM----------------------------"),
+    // Mold, Protozoan, Coelenterate Mitochondrial and Mycoplasma/Spiroplasma
+    (4, b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", b"--MM---------------M------------MMMM---------------M------------"),
+    // Invertebrate Mitochondrial
+    (5, b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSSSSVVVVAAAADDEEGGGG", b"---M----------------------------MMMM---------------M------------"),
+    // Ciliate, Dasycladacean and Hexamita Nuclear
+    (6, b"FFLLSSSSYYQQCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", b"-----------------------------------M----------------------------"),
+    // Echinoderm and Flatworm Mitochondrial
+    (9, b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNNKSSSSVVVVAAAADDEEGGGG", b"-----------------------------------M---------------M------------"),
+    // Euplotid Nuclear
+    (10, b"FFLLSSSSYY**CCCWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", b"-----------------------------------M----------------------------"),
+    // Bacterial, Archaeal and Plant Plastid
+    (11, b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", b"---M---------------M------------MMMM---------------M------------"),
+    // Alternative Yeast Nuclear
+    (12, b"FFLLSSSSYY**CC*WLLLSPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", b"-------------------M---------------M----------------------------"),
+    // Ascidian Mitochondrial
+    (13, b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNKKSSGGVVVVAAAADDEEGGGG", b"---M------------------------------MM---------------M------------"),
+    // Alternative Flatworm Mitochondrial
+    (14, b"FFLLSSSSYYY*CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNNKSSSSVVVVAAAADDEEGGGG", b"-----------------------------------M----------------------------"),
+    // Chlorophycean Mitochondrial
+    (16, b"FFLLSSSSYY*LCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", b"-----------------------------------M----------------------------"),
+    // Trematode Mitochondrial
+    (21, b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIMMTTTTNNNKSSSSVVVVAAAADDEEGGGG", b"-----------------------------------M---------------M------------"),
+    // Scenedesmus obliquus Mitochondrial
+    (22, b"FFLLSS*SYY*LCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", b"-----------------------------------M----------------------------"),
+    // Thraustochytrium Mitochondrial
+    (23, b"FF*LSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", b"--------------------------------M--M---------------M------------"),
+    // Rhabdopleuridae Mitochondrial
+    (24, b"FFLLSSSSYY**CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSSKVVVVAAAADDEEGGGG", b"---M---------------M---------------M---------------M------------"),
+    // Candidate Division SR1 and Gracilibacteria
+    (25, b"FFLLSSSSYY**CCGWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", b"---M-------------------------------M---------------M------------"),
+    // Pachysolen tannophilus Nuclear
+    (26, b"FFLLSSSSYY**CC*WLLLAPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", b"-------------------M---------------M----------------------------"),
+    // Karyorelict Nuclear
+    (27, b"FFLLSSSSYYQQCCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", b"-----------------------------------M----------------------------"),
+    // Condylostoma Nuclear
+    (28, b"FFLLSSSSYYQQCCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", b"-----------------------------------M----------------------------"),
+    // Mesodinium Nuclear
+    (29, b"FFLLSSSSYYYYCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", b"-----------------------------------M----------------------------"),
+    // Peritrich Nuclear
+    (30, b"FFLLSSSSYYEECC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", b"-----------------------------------M----------------------------"),
+    // Blastocrithidia Nuclear
+    (31, b"FFLLSSSSYYEECCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", b"-----------------------------------M----------------------------"),
+    // Balanophoraceae Plastid
+    (32, b"FFLLSSSSYY*WCC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG", b"---M---------------M------------MMMM---------------M------------"),
+    // Cephalodiscidae Mitochondrial
+    (33, b"FFLLSSSSYYY*CCWWLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSSKVVVVAAAADDEEGGGG", b"---M---------------M---------------M---------------M------------"),
+];
+
+/// An NCBI genetic code table.
+pub struct GeneticCode {
+    aas: &'static [u8; 64],
+    starts: &'static [u8; 64],
+}
+
+impl GeneticCode {
+    /// Look up a table by its NCBI number.
+    pub fn table(id: u32) -> io::Result<Self> {
+        match TABLES.iter().find(|(n, _, _)| *n == id) {
+            Some(&(_, aas, starts)) => Ok(GeneticCode { aas, starts }),
+            None => Err(io_error(ErrorCode::InvalidOption, format!("unsupported genetic code table {}", id))),
+        }
+    }
+
+    /// Numbers of the tables [`GeneticCode::table`] knows, ascending.
+    pub fn ids() -> impl Iterator<Item = u32> {
+        TABLES.iter().map(|(n, _, _)| *n)
+    }
+
+    fn codon_index(codon: &[u8]) -> Option<usize> {
+        codon.iter().try_fold(0usize, |acc, &b| {
+            let b = match b.to_ascii_uppercase() {
+                b'U' => b'T',
+                other => other,
+            };
+            let i = BASES.iter().position(|&x| x == b)?;
+            Some(acc * 4 + i)
+        })
+    }
+
+    /// Whether `codon` is a start codon of this table.
+    pub fn is_start(&self, codon: &[u8]) -> bool {
+        Self::codon_index(codon).is_some_and(|c| self.starts[c] == b'M')
+    }
+
+    /// Whether `codon` is a stop codon of this table.
+    pub fn is_stop(&self, codon: &[u8]) -> bool {
+        Self::codon_index(codon).is_some_and(|c| self.aas[c] == b'*')
+    }
+
+    /// Translate `seq` codon by codon; a trailing partial codon is ignored and
+    /// ambiguous codons become `X`.  When `is_start` the first codon is read
+    /// as `M` if it is a start codon of this table.
+    pub fn translate(&self, seq: &[u8], is_start: bool) -> String {
+        seq.chunks_exact(3)
+            .enumerate()
+            .map(|(i, codon)| match Self::codon_index(codon) {
+                Some(c) if i == 0 && is_start && self.starts[c] == b'M' => 'M',
+                Some(c) => self.aas[c] as char,
+                None => 'X',
+            })
+            .collect()
+    }
+}
+
+/// Reverse complement of a nucleotide sequence (IUPAC-aware, case-preserving).
+pub fn reverse_complement(seq: &[u8]) -> Vec<u8> {
+    seq.iter()
+        .rev()
+        .map(|&b| {
+            let c = match b.to_ascii_uppercase() {
+                b'A' => b'T', b'T' | b'U' => b'A', b'C' => b'G', b'G' => b'C',
+                b'R' => b'Y', b'Y' => b'R', b'K' => b'M', b'M' => b'K',
+                b'B' => b'V', b'V' => b'B', b'D' => b'H', b'H' => b'D',
+                other => other,
+            };
+            if b.is_ascii_lowercase() { c.to_ascii_lowercase() } else { c }
+        })
+        .collect()
+}
@@ -0,0 +1,352 @@
+//! Chunked uploads of an output.
+//!
+//! MGnify takes large artifacts in fixed-size chunks.  [`chunk_plan`]
+//! reads an output once and records the offset, size and CRC32 of every
+//! chunk, plus the CRC32 of the whole file, so the server can check each
+//! chunk as it arrives and the file once it is assembled:
+//!
+//! ```text
+//! {"artifact":"fasta.bgz","size":20971521,"chunk_size":8388608,"chunk_count":3,"crc32":"…",
+//!  "chunks":[{"index":0,"offset":0,"size":8388608,"crc32":"…"},…]}
+//! ```
+//!
+//! CRC32s are the gzip polynomial, as 8 lower-case hex digits.
+//! [`UploadChunks`] holds the output and hands out chunk `i` as a `Blob`
+//! on request, so an uploader can resume at any chunk and no more than one
+//! chunk is ever copied out of wasm memory at a time (a spilled output is
+//! sliced from its OPFS file without copying at all).
+//!
+//! For S3-style multipart uploads, [`part_plan`] sizes the chunks instead
+//! (as parts) within a service's [`PartLimits`]: every part but the last at
+//! least `min_part` and all at most `max_part` bytes, no more than
+//! `max_parts` of them.  The part size aimed for is the preferred one, or
+//! the smallest the limits allow, raised until the parts fit `max_parts`.
+//! A BGZF output is cut only at block starts, at the first one from that
+//! size on (or the last one before `max_part`), so every part begins with
+//! a block and decompresses on its own, and `block_aligned` is true in the
+//! plan.  Other outputs are cut at the exact size.
+
+use std::io::{self, Read};
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{io_error, js_error, ErrorCode};
+use crate::hash::to_hex;
+use crate::htslib::{plausible_block_header, Crc32};
+use crate::input::InputSource;
+
+/// One chunk of an upload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Chunk {
+    pub offset: u64,
+    pub size: u64,
+    pub crc32: u32,
+}
+
+/// The chunks of one output.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkPlan {
+    pub size: u64,
+    /// The chunk size, or for a [`part_plan`] the part size aimed for.
+    pub chunk_size: u64,
+    pub crc32: u32,
+    pub chunks: Vec<Chunk>,
+    /// Whether every chunk starts with a BGZF block.
+    pub block_aligned: bool,
+}
+
+impl ChunkPlan {
+    /// The plan as JSON, for the output `artifact`.
+    pub fn json(&self, artifact: &str) -> json::JsonValue {
+        let chunks: Vec<json::JsonValue> = self
+            .chunks
+            .iter()
+            .enumerate()
+            .map(|(index, c)| json::object! { index: index, offset: c.offset, size: c.size, crc32: crc_hex(c.crc32) })
+            .collect();
+        json::object! {
+            artifact: artifact,
+            size: self.size,
+            chunk_size: self.chunk_size,
+            chunk_count: self.chunks.len(),
+            crc32: crc_hex(self.crc32),
+            block_aligned: self.block_aligned,
+            chunks: chunks,
+        }
+    }
+}
+
+/// Fails with `E_INVALID_OPTION` for a zero chunk size.
+pub fn check_chunk_size(chunk_size: u64) -> io::Result<()> {
+    if chunk_size == 0 {
+        return Err(io_error(ErrorCode::InvalidOption, "the upload chunk size must be at least 1 byte"));
+    }
+    Ok(())
+}
+
+fn crc_hex(crc: u32) -> String {
+    to_hex(&crc.to_be_bytes())
+}
+
+/// CRC32 of the next `len` bytes of `reader` (fewer at its end) and how
+/// many there were, adding them to `whole`.
+fn checksum_next(reader: &mut impl Read, buf: &mut [u8], len: u64, whole: &mut Crc32) -> io::Result<(u64, u32)> {
+    let mut crc = Crc32::new();
+    let mut size = 0u64;
+    while size < len {
+        let want = buf.len().min((len - size) as usize);
+        let n = match reader.read(&mut buf[..want]) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        crc.update(&buf[..n]);
+        whole.update(&buf[..n]);
+        size += n as u64;
+    }
+    Ok((size, crc.finalize()))
+}
+
+/// Read `reader` to the end in chunks of `chunk_size` bytes (the last one
+/// shorter) and checksum each.  An empty input has no chunks.
+pub fn chunk_plan(mut reader: impl Read, chunk_size: u64) -> io::Result<ChunkPlan> {
+    check_chunk_size(chunk_size)?;
+    let mut whole = Crc32::new();
+    let mut chunks = Vec::new();
+    let mut buf = vec![0u8; chunk_size.min(1 << 20) as usize];
+    let mut offset = 0u64;
+    loop {
+        let (size, crc32) = checksum_next(&mut reader, &mut buf, chunk_size, &mut whole)?;
+        if size == 0 {
+            break;
+        }
+        chunks.push(Chunk { offset, size, crc32 });
+        offset += size;
+        if size < chunk_size {
+            break;
+        }
+    }
+    Ok(ChunkPlan { size: offset, chunk_size, crc32: whole.finalize(), chunks, block_aligned: false })
+}
+
+/// What a multipart upload service allows of its parts.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PartLimits {
+    min_part: u64,
+    max_part: u64,
+    max_parts: u32,
+    preferred_part: Option<u64>,
+}
+
+#[wasm_bindgen]
+impl PartLimits {
+    /// Parts of `min_part` to `max_part` bytes (the last may be smaller),
+    /// at most `max_parts` of them, aiming for `preferred_part` bytes if
+    /// given.  Throws `E_INVALID_OPTION` for limits no upload can meet.
+    #[wasm_bindgen(constructor)]
+    pub fn new(min_part: f64, max_part: f64, max_parts: u32, preferred_part: Option<f64>) -> Result<PartLimits, JsValue> {
+        Self::from_sizes(min_part as u64, max_part as u64, max_parts, preferred_part.map(|p| p as u64)).map_err(js_error)
+    }
+
+    /// Amazon S3's limits: 5 MiB to 5 GiB per part, 10,000 parts.
+    pub fn s3() -> PartLimits {
+        PartLimits { min_part: 5 << 20, max_part: 5 << 30, max_parts: 10_000, preferred_part: None }
+    }
+}
+
+impl PartLimits {
+    /// [`PartLimits::new`] natively.
+    pub fn from_sizes(min_part: u64, max_part: u64, max_parts: u32, preferred_part: Option<u64>) -> io::Result<Self> {
+        if min_part == 0 || min_part > max_part || max_parts == 0 {
+            return Err(io_error(
+                ErrorCode::InvalidOption,
+                format!("parts of {} to {} bytes, at most {} of them, are not upload limits", min_part, max_part, max_parts),
+            ));
+        }
+        Ok(PartLimits { min_part, max_part, max_parts, preferred_part })
+    }
+}
+
+/// Start offsets of the BGZF blocks of `reader`, stepping over each
+/// block by its `BSIZE`; `None` if it is not BGZF throughout.
+pub fn bgzf_block_starts(mut reader: impl Read) -> io::Result<Option<Vec<u64>>> {
+    let mut starts = Vec::new();
+    let mut header = [0u8; 18];
+    let mut pos = 0u64;
+    loop {
+        let mut got = 0;
+        while got < header.len() {
+            match reader.read(&mut header[got..]) {
+                Ok(0) => break,
+                Ok(n) => got += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if got == 0 {
+            break;
+        }
+        if got < 18 || !plausible_block_header(&header) {
+            return Ok(None);
+        }
+        let bsize = u16::from_le_bytes([header[16], header[17]]) as u64 + 1;
+        if bsize < 26 || io::copy(&mut (&mut reader).take(bsize - 18), &mut io::sink())? != bsize - 18 {
+            return Ok(None);
+        }
+        starts.push(pos);
+        pos += bsize;
+    }
+    Ok((!starts.is_empty()).then_some(starts))
+}
+
+/// End offsets of the parts of a `size`-byte output within `limits`,
+/// cutting only at `starts` (block starts) when given, and the part size
+/// aimed for.
+fn part_ends(size: u64, starts: Option<&[u64]>, limits: &PartLimits) -> io::Result<(Vec<u64>, u64)> {
+    let target = limits.preferred_part.unwrap_or(0).max(limits.min_part).max(size.div_ceil(limits.max_parts as u64)).min(limits.max_part);
+    if size.div_ceil(target) > limits.max_parts as u64 {
+        return Err(io_error(
+            ErrorCode::InvalidOption,
+            format!("{} bytes do not fit in {} parts of at most {} bytes", size, limits.max_parts, limits.max_part),
+        ));
+    }
+    // The first cut at or after `at`, and the last one at or before it.
+    let after = |at: u64| starts.map_or(at, |s| s.get(s.partition_point(|&b| b < at)).copied().unwrap_or(size)).min(size);
+    let before = |at: u64| starts.map_or(at, |s| s[..s.partition_point(|&b| b <= at)].last().copied().unwrap_or(0));
+    let mut ends = Vec::new();
+    let mut start = 0u64;
+    while start < size {
+        let mut end = after(start + target);
+        if end - start > limits.max_part {
+            end = before(start + limits.max_part);
+            if end < size && end - start < limits.min_part {
+                return Err(io_error(
+                    ErrorCode::InvalidOption,
+                    format!("no block start between {} and {} bytes after offset {} to end a part at", limits.min_part, limits.max_part, start),
+                ));
+            }
+        }
+        ends.push(end);
+        start = end;
+    }
+    if ends.len() > limits.max_parts as usize {
+        return Err(io_error(ErrorCode::InvalidOption, format!("cutting at block starts takes {} parts, over the limit of {}", ends.len(), limits.max_parts)));
+    }
+    Ok((ends, target))
+}
+
+/// The parts of a `size`-byte output read from `open()` within `limits`,
+/// cut at BGZF block starts if it is BGZF, with a CRC32 each.  Reads the
+/// output twice: once for the block starts and once for the checksums.
+pub fn part_plan<R: Read>(mut open: impl FnMut() -> io::Result<R>, size: u64, limits: &PartLimits) -> io::Result<ChunkPlan> {
+    let starts = bgzf_block_starts(open()?)?;
+    let (ends, target) = part_ends(size, starts.as_deref(), limits)?;
+    let mut reader = open()?;
+    let mut whole = Crc32::new();
+    let mut buf = vec![0u8; 1 << 20];
+    let mut chunks = Vec::with_capacity(ends.len());
+    let mut offset = 0u64;
+    for end in ends {
+        let (read, crc32) = checksum_next(&mut reader, &mut buf, end - offset, &mut whole)?;
+        if read != end - offset {
+            return Err(io_error(ErrorCode::InvalidOption, format!("the output ended at {} bytes, not {}", offset + read, size)));
+        }
+        chunks.push(Chunk { offset, size: read, crc32 });
+        offset = end;
+    }
+    Ok(ChunkPlan { size, chunk_size: target, crc32: whole.finalize(), chunks, block_aligned: starts.is_some() })
+}
+
+/// Where an output is held: in wasm memory, or in the OPFS file it was
+/// spilled to.
+pub enum HeldOutput {
+    Bytes(Vec<u8>),
+    Spilled(web_sys::File),
+}
+
+/// An output cut into upload chunks, from `IndexGen.upload_chunks`.
+#[wasm_bindgen]
+pub struct UploadChunks {
+    artifact: String,
+    output: HeldOutput,
+    plan: ChunkPlan,
+    next: usize,
+}
+
+#[wasm_bindgen]
+impl UploadChunks {
+    /// The upload plan as JSON (see `upload.rs`).
+    pub fn plan_json(&self) -> String {
+        self.plan.json(&self.artifact).dump()
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.plan.chunks.len()
+    }
+
+    /// Chunk `index` as a `Blob`.  Throws `E_INVALID_OPTION` past the last
+    /// chunk.
+    pub fn chunk(&self, index: usize) -> Result<web_sys::Blob, JsValue> {
+        let chunk = self
+            .plan
+            .chunks
+            .get(index)
+            .ok_or_else(|| js_error(io_error(ErrorCode::InvalidOption, format!("no upload chunk {}; there are {}", index, self.plan.chunks.len()))))?;
+        let (start, end) = (chunk.offset, chunk.offset + chunk.size);
+        match &self.output {
+            HeldOutput::Spilled(file) => file.slice_with_f64_and_f64(start as f64, end as f64),
+            HeldOutput::Bytes(bytes) => {
+                let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(&bytes[start as usize..end as usize]));
+                web_sys::Blob::new_with_u8_array_sequence(&parts)
+            }
+        }
+    }
+
+    /// The next chunk, starting from 0 or where [`UploadChunks::seek`]
+    /// put it, and `undefined` after the last.
+    pub fn next_chunk(&mut self) -> Result<Option<web_sys::Blob>, JsValue> {
+        if self.next >= self.plan.chunks.len() {
+            return Ok(None);
+        }
+        self.next += 1;
+        self.chunk(self.next - 1).map(Some)
+    }
+
+    /// Make `next_chunk()` continue at chunk `index`, e.g. the first one
+    /// the server does not have yet.
+    pub fn seek(&mut self, index: usize) {
+        self.next = index;
+    }
+}
+
+impl UploadChunks {
+    /// Cut the output `artifact` into chunks of `chunk_size` bytes.  A
+    /// spilled output is read once, through `FileReaderSync`.
+    pub fn new(artifact: &str, output: HeldOutput, chunk_size: u64) -> io::Result<Self> {
+        let plan = match &output {
+            HeldOutput::Bytes(bytes) => chunk_plan(&bytes[..], chunk_size)?,
+            HeldOutput::Spilled(file) => chunk_plan(InputSource::File(file.clone()).open_raw()?, chunk_size)?,
+        };
+        Ok(UploadChunks { artifact: artifact.to_owned(), output, plan, next: 0 })
+    }
+
+    /// Cut the output `artifact` into parts within `limits` (see
+    /// [`part_plan`]).  A spilled output is read twice, through
+    /// `FileReaderSync`.
+    pub fn parts(artifact: &str, output: HeldOutput, limits: &PartLimits) -> io::Result<Self> {
+        let plan = match &output {
+            HeldOutput::Bytes(bytes) => part_plan(|| Ok(&bytes[..]), bytes.len() as u64, limits)?,
+            HeldOutput::Spilled(file) => {
+                let source = InputSource::File(file.clone());
+                part_plan(|| source.open_raw(), file.size() as u64, limits)?
+            }
+        };
+        Ok(UploadChunks { artifact: artifact.to_owned(), output, plan, next: 0 })
+    }
+
+    pub fn plan(&self) -> &ChunkPlan {
+        &self.plan
+    }
+}
@@ -0,0 +1,337 @@
+//! Input validation: problems in the FASTA / GFF3 pair that would make the
+//! outputs wrong or unusable, reported per line instead of failing on the
+//! first one.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::fasta::{FastaLine, FastaParser, FastaRecord, Sequences};
+use crate::gff::circular::origin_overhang;
+use crate::gff::columns::{GFF3_COLUMNS, MIN_RECORD_COLUMNS};
+use crate::gff::multipart::{group_parts, Feature};
+use crate::gff::{circular_lengths, data_lines, pad_columns, GffFields};
+use crate::options::CircularPolicy;
+use crate::scan;
+use crate::transform::locus_tags::{split_locus_tag, trailing_number};
+use crate::translate::GeneticCode;
+
+/// Issues kept per code; further occurrences are only counted.
+const MAX_ISSUES_PER_CODE: usize = 100;
+
+/// IUPAC nucleotide and amino-acid letters plus gap/stop symbols.
+const SEQUENCE_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz*-.";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// The outputs would be wrong or unindexable.
+    Error,
+    /// Suspicious but processable.
+    Warning,
+}
+
+/// One problem found in an input file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Issue {
+    pub severity: Severity,
+    /// Stable identifier, e.g. `duplicate_name`.
+    pub code: &'static str,
+    /// `"fasta"` or `"gff"`.
+    pub file: &'static str,
+    /// 1-based line number, when the issue belongs to a line.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+/// All issues found, capped at [`MAX_ISSUES_PER_CODE`] per code.
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub issues: Vec<Issue>,
+    /// Occurrences per code, including those past the cap.
+    pub counts: HashMap<&'static str, usize>,
+    /// CDS features translated by the CDS checks.
+    pub cds_checked: usize,
+}
+
+impl ValidationReport {
+    fn push(&mut self, severity: Severity, code: &'static str, file: &'static str, line: Option<usize>, message: String) {
+        let count = self.counts.entry(code).or_default();
+        *count += 1;
+        if *count <= MAX_ISSUES_PER_CODE {
+            self.issues.push(Issue { severity, code, file, line, message });
+        }
+    }
+
+    /// No errors (warnings are allowed).
+    pub fn is_valid(&self) -> bool {
+        self.issues.iter().all(|i| i.severity != Severity::Error)
+    }
+
+    /// `{valid, errors, warnings, counts: {code: n}, cds_checked, issues:
+    /// [{severity, code, file, line, message}]}`.  `errors`/`warnings`
+    /// count the listed issues; `counts` includes the ones past the
+    /// per-code cap.
+    pub fn to_json(&self) -> json::JsonValue {
+        let errors = self.issues.iter().filter(|i| i.severity == Severity::Error).count();
+        let mut counts = json::JsonValue::new_object();
+        for (code, n) in &self.counts {
+            counts[*code] = (*n).into();
+        }
+        json::object! {
+            valid: self.is_valid(),
+            errors: errors,
+            warnings: self.issues.len() - errors,
+            counts: counts,
+            cds_checked: self.cds_checked,
+            issues: self.issues.iter().map(|i| json::object! {
+                severity: if i.severity == Severity::Error { "error" } else { "warning" },
+                code: i.code,
+                file: i.file,
+                line: i.line,
+                message: i.message.as_str(),
+            }).collect::<Vec<_>>(),
+        }
+    }
+}
+
+/// Check a FASTA file; returns the length of every sequence by name (the
+/// first one, for duplicated names).
+fn validate_fasta(fa: &[u8], report: &mut ValidationReport) -> HashMap<String, u64> {
+    fn finish(record: FastaRecord, lengths: &mut HashMap<String, u64>, report: &mut ValidationReport) {
+        if record.length == 0 {
+            report.push(Severity::Warning, "empty_sequence", "fasta", Some(record.line), format!("sequence {:?} has no bases", record.name));
+        }
+        lengths.entry(record.name).or_insert(record.length);
+    }
+
+    let mut lengths: HashMap<String, u64> = HashMap::new();
+    let mut parser = FastaParser::new();
+
+    for line in scan::split_lines(fa) {
+        let line_no = parser.lines_read() + 1;
+        match parser.push(line) {
+            (FastaLine::Header { .. }, ended) => {
+                if let Some(record) = ended {
+                    finish(record, &mut lengths, report);
+                }
+                let name = parser.current().map(|r| r.name.as_str()).unwrap_or_default();
+                if name.is_empty() {
+                    report.push(Severity::Error, "empty_name", "fasta", Some(line_no), "header without a sequence name".into());
+                } else if lengths.contains_key(name) {
+                    report.push(Severity::Error, "duplicate_name", "fasta", Some(line_no), format!("duplicate sequence name {:?}", name));
+                }
+            }
+            (FastaLine::Blank, _) => {}
+            (FastaLine::Sequence(line), _) => match parser.current() {
+                Some(record) => {
+                    if let Some(&bad) = line.iter().find(|b| !SEQUENCE_CHARS.contains(b)) {
+                        report.push(
+                            Severity::Error,
+                            "invalid_base",
+                            "fasta",
+                            Some(line_no),
+                            format!("invalid character {:?} in sequence {:?}", bad as char, record.name),
+                        );
+                    }
+                }
+                None => report.push(Severity::Error, "text_before_header", "fasta", Some(line_no), "sequence data before the first '>' header".into()),
+            },
+        }
+    }
+    if let Some(record) = parser.finish() {
+        finish(record, &mut lengths, report);
+    }
+    if lengths.is_empty() {
+        report.push(Severity::Error, "fasta_empty", "fasta", None, "no sequences found".into());
+    }
+    lengths
+}
+
+/// Check the GFF3 records (up to any `##FASTA` section) against the
+/// sequence lengths of the FASTA.  Features crossing the origin of a
+/// circular sequence are errors only under [`CircularPolicy::Error`].
+fn validate_gff(gff: &str, lengths: &HashMap<String, u64>, circular: CircularPolicy, report: &mut ValidationReport) {
+    let circular_lengths = circular_lengths(gff);
+    let first = gff.lines().find(|l| !l.trim().is_empty());
+    if !first.is_some_and(|l| l.starts_with("##gff-version 3")) {
+        report.push(Severity::Warning, "missing_version_pragma", "gff", Some(1), "file does not start with ##gff-version 3".into());
+    }
+
+    for (i, line) in gff.split('\n').enumerate() {
+        let line_no = Some(i + 1);
+        let line = line.trim_end_matches('\r');
+        if line.starts_with("##FASTA") {
+            break;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let cols: Vec<&str> = line.split('\t').collect();
+        if !(MIN_RECORD_COLUMNS..=GFF3_COLUMNS).contains(&cols.len()) {
+            report.push(Severity::Error, "column_count", "gff", line_no, format!("expected 9 tab-separated columns, found {}", cols.len()));
+            continue;
+        }
+        let padded = pad_columns(line);
+        if padded.is_some() {
+            let message = match cols.len() {
+                GFF3_COLUMNS => "empty columns are set to '.'".to_owned(),
+                n => format!("found {} columns; the missing ones are set to '.'", n),
+            };
+            report.push(Severity::Warning, "column_count", "gff", line_no, message);
+        }
+        let line = padded.as_deref().unwrap_or(line);
+        let Some(f) = GffFields::parse(line) else {
+            report.push(Severity::Error, "bad_coordinate", "gff", line_no, format!("start/end not integers: {:?}, {:?}", cols[3], cols[4]));
+            continue;
+        };
+        let overhang = circular_lengths.get(f.seqid).and_then(|&len| origin_overhang(f.start, f.end, len));
+        if overhang.is_some() {
+            let severity = if circular == CircularPolicy::Error { Severity::Error } else { Severity::Warning };
+            report.push(severity, "crosses_origin", "gff", line_no, format!("{}..{} crosses the origin of circular {:?}", f.start, f.end, f.seqid));
+            continue;
+        }
+        if f.start == 0 {
+            report.push(Severity::Error, "zero_start", "gff", line_no, "start must be 1 or more".into());
+        }
+        if f.start > f.end {
+            report.push(Severity::Error, "start_after_end", "gff", line_no, format!("start {} is after end {}", f.start, f.end));
+        }
+        match lengths.get(f.seqid) {
+            None => report.push(Severity::Warning, "unknown_seqid", "gff", line_no, format!("seqid {:?} is not in the FASTA", f.seqid)),
+            Some(&len) if f.end > len => report.push(
+                Severity::Error,
+                "beyond_sequence_end",
+                "gff",
+                line_no,
+                format!("end {} is beyond the end of {:?} ({} bp)", f.end, f.seqid, len),
+            ),
+            Some(_) => {}
+        }
+        if !matches!(f.strand, "+" | "-" | "." | "?") {
+            report.push(Severity::Error, "bad_strand", "gff", line_no, format!("invalid strand {:?}", f.strand));
+        }
+        if f.ftype == "CDS" && !matches!(f.phase, "0" | "1" | "2") {
+            report.push(Severity::Warning, "bad_phase", "gff", line_no, format!("CDS phase {:?} is not 0, 1 or 2", f.phase));
+        }
+        if let Some(table) = f.attribute("transl_table").filter(|_| f.ftype == "CDS") {
+            if !table.trim_matches('"').parse::<u32>().is_ok_and(|t| GeneticCode::ids().any(|id| id == t)) {
+                report.push(Severity::Warning, "unknown_transl_table", "gff", line_no, format!("transl_table {:?} is not an NCBI genetic code table", table));
+            }
+        }
+    }
+}
+
+/// Check the `locus_tag`s of `gff` for submission to ENA: the
+/// `<prefix>_<id>` format (`locus_tag_format`), the prefix of the first
+/// one (`locus_tag_prefix`), and ids numbered upwards in order of first
+/// appearance (`locus_tag_order`).
+fn validate_locus_tags(gff: &str, report: &mut ValidationReport) {
+    let mut seen: HashSet<&str> = HashSet::new();
+    let mut first_prefix: Option<&str> = None;
+    let mut last: Option<(&str, u64)> = None;
+    for (i, line) in gff.split('\n').enumerate().take_while(|(_, l)| !l.starts_with("##FASTA")) {
+        let line_no = Some(i + 1);
+        let Some(tag) = GffFields::parse(line).and_then(|f| f.attribute("locus_tag")) else {
+            continue;
+        };
+        if !seen.insert(tag) {
+            continue;
+        }
+        let (prefix, id) = match split_locus_tag(tag) {
+            Ok(split) => split,
+            Err(why) => {
+                report.push(Severity::Warning, "locus_tag_format", "gff", line_no, format!("locus_tag {:?} is not an ENA locus tag: {}", tag, why));
+                continue;
+            }
+        };
+        match first_prefix {
+            Some(first) if first != prefix => report.push(
+                Severity::Warning,
+                "locus_tag_prefix",
+                "gff",
+                line_no,
+                format!("locus_tag {:?} has prefix {:?}, the first one {:?}", tag, prefix, first),
+            ),
+            Some(_) => {}
+            None => first_prefix = Some(prefix),
+        }
+        let Some(number) = trailing_number(id) else {
+            continue;
+        };
+        if let Some((previous, _)) = last.filter(|&(_, n)| number <= n) {
+            report.push(Severity::Warning, "locus_tag_order", "gff", line_no, format!("locus_tag {:?} is numbered below {:?}, which comes before it", tag, previous));
+        }
+        last = Some((tag, number));
+    }
+}
+
+/// Whether `feature` is marked as incomplete: `partial`, or GFF3
+/// `start_range` / `end_range`.
+fn is_partial(feature: &Feature) -> bool {
+    feature.parts.iter().any(|p| {
+        p.attribute("partial").is_some_and(|v| v != "false") || p.attribute("start_range").is_some() || p.attribute("end_range").is_some()
+    })
+}
+
+/// Translate every CDS of `gff` from `seqs` and report the ones that would
+/// not make a protein: `cds_length` (coding length not a multiple of 3),
+/// `missing_start`, `missing_stop` and `internal_stop`.  The genetic code
+/// is the `transl_table` of the CDS, else `default_table`.  Pseudogenes
+/// and CDSs outside the FASTA are skipped; partial ones (see
+/// [`is_partial`]) only get the internal stop check, and so do the start
+/// codons of CDSs whose phase is not 0.
+fn validate_cds(gff: &str, seqs: &Sequences, default_table: u32, report: &mut ValidationReport) {
+    let line_starts: Vec<usize> = std::iter::once(0).chain(gff.match_indices('\n').map(|(i, _)| i + 1)).collect();
+    let line_of = |f: &GffFields| Some(line_starts.partition_point(|&s| s <= f.seqid.as_ptr() as usize - gff.as_ptr() as usize));
+    for feature in group_parts(data_lines(gff).filter_map(GffFields::parse).filter(|f| f.ftype == "CDS")) {
+        let first = feature.first();
+        if feature.is_pseudo() {
+            continue;
+        }
+        let table = first.attribute("transl_table").map_or(Some(default_table), |t| t.trim_matches('"').parse().ok());
+        let (Some(nt), Some(code)) = (feature.spliced(seqs), table.and_then(|t| GeneticCode::table(t).ok())) else {
+            continue;
+        };
+        report.cds_checked += 1;
+        let line = line_of(feature.five_prime());
+        let name = first.attribute("ID").unwrap_or("CDS");
+        let phase = feature.five_prime().phase.parse::<usize>().unwrap_or(0).min(nt.len());
+        let coding = &nt[phase..];
+        let partial = is_partial(&feature);
+        let whole = coding.len() - coding.len() % 3;
+        if !partial && coding.len() % 3 != 0 {
+            report.push(Severity::Warning, "cds_length", "gff", line, format!("CDS {:?} is {} bases long, not a multiple of 3", name, coding.len()));
+        }
+        if !partial && phase == 0 && coding.len() >= 3 && !code.is_start(&coding[..3]) {
+            let codon = String::from_utf8_lossy(&coding[..3]).to_ascii_uppercase();
+            report.push(Severity::Warning, "missing_start", "gff", line, format!("CDS {:?} starts with {}, not a start codon", name, codon));
+        }
+        let last_is_stop = whole >= 3 && code.is_stop(&coding[whole - 3..whole]);
+        if !partial && coding.len() % 3 == 0 && !last_is_stop {
+            report.push(Severity::Warning, "missing_stop", "gff", line, format!("CDS {:?} does not end with a stop codon", name));
+        }
+        let protein = code.translate(&coding[..whole - if last_is_stop { 3 } else { 0 }], false);
+        let stops: Vec<usize> = protein.match_indices('*').map(|(i, _)| i + 1).collect();
+        if let Some(first_stop) = stops.first() {
+            let message = format!("CDS {:?} has {} internal stop codon(s), the first at codon {}", name, stops.len(), first_stop);
+            report.push(Severity::Warning, "internal_stop", "gff", line, message);
+        }
+    }
+}
+
+/// Validate a FASTA / GFF3 pair (both uncompressed), with `circular` the
+/// handling of features that cross the origin of a circular sequence and
+/// `genetic_code` the table of CDSs without `transl_table`.
+pub fn validate(fa: &[u8], gff: &[u8], circular: CircularPolicy, genetic_code: u32) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    let lengths = validate_fasta(fa, &mut report);
+    if std::str::from_utf8(gff).is_err() {
+        report.push(Severity::Error, "not_utf8", "gff", None, "GFF is not valid UTF-8".into());
+    }
+    let gff = String::from_utf8_lossy(gff);
+    validate_gff(&gff, &lengths, circular, &mut report);
+    validate_locus_tags(&gff, &mut report);
+    // A FASTA that does not parse already has its errors in the report.
+    if let Ok(seqs) = Sequences::parse(fa) {
+        validate_cds(&gff, &seqs, genetic_code, &mut report);
+    }
+    report
+}
@@ -0,0 +1,192 @@
+//! Non-fatal issues met while processing: the input was accepted, but the
+//! output may not be what the user expects.  Collected per run so a UI can
+//! list them instead of scraping the console.
+
+use std::collections::BTreeMap;
+
+use crate::logw;
+
+/// Warnings kept per code; further occurrences are only counted.
+const MAX_WARNINGS_PER_CODE: usize = 100;
+
+/// One non-fatal issue.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Warning {
+    /// Stable identifier, e.g. `unsorted_input`.
+    pub code: &'static str,
+    /// Pipeline stage that raised it: `"options"`, `"fasta"` or `"gff"`.
+    pub stage: &'static str,
+    /// 1-based input line number, when the warning belongs to a line.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+/// Warnings of one run, capped at [`MAX_WARNINGS_PER_CODE`] per code.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Warnings {
+    pub warnings: Vec<Warning>,
+    /// Occurrences per code, including those past the cap.
+    pub counts: BTreeMap<&'static str, usize>,
+    /// The same per stage and code, so that dropping a stage's warnings
+    /// leaves `counts` exact.
+    pub stage_counts: BTreeMap<(&'static str, &'static str), usize>,
+}
+
+impl Warnings {
+    /// Record a warning (also logged to the console).
+    pub fn push(&mut self, stage: &'static str, code: &'static str, line: Option<usize>, message: String) {
+        *self.stage_counts.entry((stage, code)).or_default() += 1;
+        let count = self.counts.entry(code).or_default();
+        *count += 1;
+        if *count <= MAX_WARNINGS_PER_CODE {
+            logw(&message, Some("warning"));
+            self.warnings.push(Warning { code, stage, line, message });
+        }
+    }
+
+    /// Append the warnings of a later stage, listing no more than
+    /// [`MAX_WARNINGS_PER_CODE`] per code between the two.
+    pub fn extend(&mut self, other: Warnings) {
+        let mut listed: BTreeMap<&str, usize> = BTreeMap::new();
+        for w in &self.warnings {
+            *listed.entry(w.code).or_default() += 1;
+        }
+        for w in other.warnings {
+            let n = listed.entry(w.code).or_default();
+            *n += 1;
+            if *n <= MAX_WARNINGS_PER_CODE {
+                self.warnings.push(w);
+            }
+        }
+        for (code, n) in other.counts {
+            *self.counts.entry(code).or_default() += n;
+        }
+        for (key, n) in other.stage_counts {
+            *self.stage_counts.entry(key).or_default() += n;
+        }
+    }
+
+    /// Drop the warnings of `stage`, e.g. before rerunning it, and take
+    /// all its occurrences, listed or past the cap, off the counts.
+    pub fn remove_stage(&mut self, stage: &str) {
+        self.warnings.retain(|w| w.stage != stage);
+        let (removed, kept) = std::mem::take(&mut self.stage_counts).into_iter().partition(|((s, _), _)| *s == stage);
+        self.stage_counts = kept;
+        for ((_, code), n) in removed {
+            let count = self.counts.entry(code).or_default();
+            *count = count.saturating_sub(n);
+            if *count == 0 {
+                self.counts.remove(code);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Occurrences of `code`, including those past the cap.
+    pub fn count(&self, code: &str) -> usize {
+        self.counts.get(code).copied().unwrap_or(0)
+    }
+
+    /// `{total, counts: {code: n}, stages: {stage: {code: n}}, warnings:
+    /// [{code, stage, line, message}]}`.
+    pub fn to_json(&self) -> json::JsonValue {
+        let mut counts = json::JsonValue::new_object();
+        for (code, n) in &self.counts {
+            counts[*code] = (*n).into();
+        }
+        let mut stages = json::JsonValue::new_object();
+        for ((stage, code), n) in &self.stage_counts {
+            stages[*stage][*code] = (*n).into();
+        }
+        json::object! {
+            total: self.counts.values().sum::<usize>(),
+            counts: counts,
+            stages: stages,
+            warnings: self.warnings.iter().map(|w| json::object! {
+                code: w.code,
+                stage: w.stage,
+                line: w.line,
+                message: w.message.as_str(),
+            }).collect::<Vec<_>>(),
+        }
+    }
+
+    /// Rebuild from [`Warnings::to_json`] output, e.g. a cache entry's meta.
+    /// Codes unknown to this version are dropped.
+    pub fn from_json(value: &json::JsonValue) -> Self {
+        let mut out = Warnings::default();
+        for (code, n) in value["counts"].entries() {
+            if let Some(code) = known_code(code) {
+                out.counts.insert(code, n.as_usize().unwrap_or(0));
+            }
+        }
+        for w in value["warnings"].members() {
+            let (Some(code), Some(stage)) = (w["code"].as_str().and_then(known_code), w["stage"].as_str().and_then(known_stage)) else {
+                continue;
+            };
+            let message = w["message"].as_str().unwrap_or_default().to_owned();
+            out.warnings.push(Warning { code, stage, line: w["line"].as_usize(), message });
+        }
+        for (stage, codes) in value["stages"].entries() {
+            for (code, n) in codes.entries() {
+                if let (Some(stage), Some(code)) = (known_stage(stage), known_code(code)) {
+                    out.stage_counts.insert((stage, code), n.as_usize().unwrap_or(0));
+                }
+            }
+        }
+        if !value.has_key("stages") {
+            // Written before stages were counted: each listed warning counts
+            // for its stage, and the rest for the stage of the last one.
+            for w in &out.warnings {
+                *out.stage_counts.entry((w.stage, w.code)).or_default() += 1;
+            }
+            for (code, n) in &out.counts {
+                let listed = out.warnings.iter().filter(|w| w.code == *code).count();
+                if let Some(last) = out.warnings.iter().rfind(|w| w.code == *code).filter(|_| *n > listed) {
+                    *out.stage_counts.entry((last.stage, code)).or_default() += n - listed;
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Every warning code raised by the pipeline.
+pub const CODES: [&str; 25] = [
+    "already_preprocessed",
+    "columns_padded",
+    "compression_level_clamped",
+    "control_characters",
+    "coordinates_swapped",
+    "csi_depth_increased",
+    "dense_index",
+    "depth_ignored",
+    "duplicate_pragma",
+    "fasta_rewrapped",
+    "feature_spans_gap",
+    "gff_sort_keys_ignored",
+    "gff_transforms_ignored",
+    "gtf_converted",
+    "id_references_rewritten",
+    "id_renamed",
+    "locus_tags_renumbered",
+    "origin_clamped",
+    "origin_split",
+    "preview_truncated",
+    "record_dropped",
+    "replicon_region_missing",
+    "scaffold_split",
+    "start_after_end",
+    "unsorted_input",
+];
+
+fn known_code(code: &str) -> Option<&'static str> {
+    CODES.iter().find(|c| **c == code).copied()
+}
+
+fn known_stage(stage: &str) -> Option<&'static str> {
+    ["options", "fasta", "gff"].into_iter().find(|s| *s == stage)
+}
@@ -0,0 +1,42 @@
+// Answers the region queries in <dir>/queries.json with @gmod/tabix and
+// @gmod/indexedfasta, reading the files tests/compat_test.rs wrote to <dir>,
+// and prints one JSON answer per query, in order:
+//
+//   {"kind": "gff", "index": "csi" | "tbi", "ref", "start", "end"} -> [line, ...]
+//   {"kind": "fasta", "ref", "start", "end"}                       -> "bases"
+//   {"kind": "names", "index": "csi" | "tbi" | "fai"}              -> [name, ...]
+//
+// Coordinates are 0-based, end-exclusive, as both libraries take them.
+import { readFileSync } from "node:fs";
+import { join } from "node:path";
+import { TabixIndexedFile } from "@gmod/tabix";
+import { BgzipIndexedFasta } from "@gmod/indexedfasta";
+
+const dir = process.argv[2];
+const queries = JSON.parse(readFileSync(join(dir, "queries.json"), "utf8"));
+
+const gff = {
+  csi: new TabixIndexedFile({ path: join(dir, "genome.gff3.bgz"), csiPath: join(dir, "genome.gff3.bgz.csi") }),
+  tbi: new TabixIndexedFile({ path: join(dir, "genome.gff3.bgz"), tbiPath: join(dir, "genome.gff3.bgz.tbi") }),
+};
+const fasta = new BgzipIndexedFasta({
+  path: join(dir, "genome.fasta.bgz"),
+  faiPath: join(dir, "genome.fasta.bgz.fai"),
+  gziPath: join(dir, "genome.fasta.bgz.gzi"),
+});
+
+const answers = [];
+for (const q of queries) {
+  if (q.kind === "gff") {
+    const lines = [];
+    await gff[q.index].getLines(q.ref, q.start, q.end, { lineCallback: (line) => lines.push(line) });
+    answers.push(lines);
+  } else if (q.kind === "fasta") {
+    answers.push((await fasta.getSequence(q.ref, q.start, q.end)) ?? "");
+  } else if (q.kind === "names") {
+    answers.push(q.index === "fai" ? await fasta.getSequenceNames() : await gff[q.index].getReferenceSequenceNames());
+  } else {
+    throw new Error(`unknown query kind ${q.kind}`);
+  }
+}
+process.stdout.write(JSON.stringify(answers));
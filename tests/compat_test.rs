@@ -0,0 +1,154 @@
+//! Compatibility tests against the JS readers the front end loads our
+//! outputs with (`cargo test --features compat`).
+//!
+//! Both fixture pairs are compressed and indexed as in `reference_test.rs`,
+//! written to a temporary directory, and queried from Node with
+//! `@gmod/tabix` (through the `.csi` and the `.tbi`) and
+//! `@gmod/indexedfasta` (through the `.fai` and `.gzi`) by
+//! `tests/compat/query.mjs`.  Every answer must equal what a plain scan of
+//! the uncompressed text gives.  Needs `node` on PATH and `npm install` run
+//! once in `tests/compat/`.  igv.js is not covered: its readers only load
+//! inside its browser bundle.
+#![cfg(feature = "compat")]
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::process::Command;
+
+use flate2::read::MultiGzDecoder;
+use mgnify_wasm::gff_preprocess;
+use mgnify_wasm::reference::{bgzf_pair, our_artifacts};
+
+const FIXTURES: [(&str, &str, &str); 2] = [
+    ("test", "tests/fixtures/test.fasta", "tests/fixtures/test.gff3"),
+    ("BU_ATCC8492", "tests/fixtures/BU_ATCC8492VPI0062_NT5002.1.fa.gz", "tests/fixtures/BU_ATCC8492_annotations.gff.gz"),
+];
+
+fn read_maybe_gz(path: &str) -> Vec<u8> {
+    let raw = fs::read(path).unwrap_or_else(|e| panic!("cannot read {}: {}", path, e));
+    if !raw.starts_with(&[0x1F, 0x8B]) {
+        return raw;
+    }
+    let mut out = Vec::new();
+    MultiGzDecoder::new(raw.as_slice()).read_to_end(&mut out).unwrap_or_else(|e| panic!("gzip decode failed for {}: {}", path, e));
+    out
+}
+
+/// `(name, bases)` of every FASTA record.
+fn fasta_records(fasta: &str) -> Vec<(String, String)> {
+    let mut records: Vec<(String, String)> = Vec::new();
+    for line in fasta.lines().map(|l| l.trim_end_matches('\r')) {
+        match line.strip_prefix('>') {
+            Some(header) => records.push((header.split([' ', '\t']).next().unwrap_or("").to_owned(), String::new())),
+            None => records.last_mut().map_or((), |(_, bases)| bases.push_str(line)),
+        }
+    }
+    records
+}
+
+/// `(seqid, beg, end)` of a GFF3 data line, 0-based half-open the way
+/// tabix reads it.
+fn gff_span(line: &str) -> Option<(&str, u64, u64)> {
+    if line.starts_with('#') || line.trim().is_empty() {
+        return None;
+    }
+    let f: Vec<&str> = line.splitn(6, '\t').collect();
+    let beg = f.get(3)?.parse::<u64>().ok()?.saturating_sub(1);
+    let end = f.get(4)?.parse::<u64>().ok()?;
+    Some((f[0], beg, end.max(beg + 1)))
+}
+
+/// Lines of `gff` overlapping `[start, end)` on `seqid`, in file order.
+fn overlapping<'a>(gff: &'a str, seqid: &str, start: u64, end: u64) -> Vec<&'a str> {
+    gff.lines().filter(|l| gff_span(l).is_some_and(|(s, b, e)| s == seqid && b < end && e > start)).collect()
+}
+
+/// Windows worth asking about on a sequence of `len` bases: the first and
+/// last bases, the whole, a middle slice and one across the first BGZF
+/// block boundary of a long sequence.
+fn windows(len: u64) -> Vec<(u64, u64)> {
+    let mut w = vec![(0, 1), (0, len), (len.saturating_sub(10), len), (len / 2, (len / 2 + 5_000).min(len))];
+    if len > 70_000 {
+        w.push((64_000, 70_000));
+    }
+    w
+}
+
+fn node_available() -> bool {
+    Command::new("node").arg("--version").output().is_ok_and(|o| o.status.success())
+}
+
+#[test]
+fn js_readers_answer_region_queries_like_a_scan() {
+    assert!(node_available(), "`node` is not on PATH");
+    assert!(
+        Path::new("tests/compat/node_modules/@gmod/tabix").exists(),
+        "run `npm install` in tests/compat/ first"
+    );
+    for (name, fasta, gff) in FIXTURES {
+        let fasta = read_maybe_gz(fasta);
+        let gff = String::from_utf8(read_maybe_gz(gff)).expect("non-UTF8 GFF fixture");
+        let (fasta_bgz, gff_bgz) = bgzf_pair(&fasta, &gff).unwrap();
+        let ours = our_artifacts(&fasta_bgz, &gff_bgz).unwrap();
+        let gff = gff_preprocess(&gff);
+        let records = fasta_records(std::str::from_utf8(&fasta).expect("non-UTF8 FASTA fixture"));
+
+        let dir = std::env::temp_dir().join(format!("mgnify-compat-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, bytes) in [
+            ("genome.fasta.bgz", &fasta_bgz),
+            ("genome.fasta.bgz.fai", &ours.fai),
+            ("genome.fasta.bgz.gzi", &ours.gzi),
+            ("genome.gff3.bgz", &gff_bgz),
+            ("genome.gff3.bgz.csi", &ours.csi),
+            ("genome.gff3.bgz.tbi", ours.tbi.as_ref().unwrap()),
+        ] {
+            fs::write(dir.join(file), bytes).unwrap();
+        }
+
+        let mut seqids: Vec<&str> = Vec::new();
+        let mut extent: Vec<u64> = Vec::new();
+        for (seqid, _, end) in gff.lines().filter_map(gff_span) {
+            match seqids.iter().position(|s| *s == seqid) {
+                Some(i) => extent[i] = extent[i].max(end),
+                None => {
+                    seqids.push(seqid);
+                    extent.push(end);
+                }
+            }
+        }
+
+        let mut queries = json::JsonValue::new_array();
+        let mut expected = json::JsonValue::new_array();
+        let names: Vec<&str> = records.iter().map(|(n, _)| n.as_str()).collect();
+        for (index, names) in [("fai", &names), ("csi", &seqids), ("tbi", &seqids)] {
+            queries.push(json::object! { kind: "names", index: index }).unwrap();
+            expected.push(names.clone()).unwrap();
+        }
+        for (seq, bases) in &records {
+            for (start, end) in windows(bases.len() as u64) {
+                queries.push(json::object! { kind: "fasta", ref: seq.as_str(), start: start, end: end }).unwrap();
+                expected.push(&bases[start as usize..end as usize]).unwrap();
+            }
+        }
+        for (seqid, len) in seqids.iter().zip(&extent) {
+            for (start, end) in windows(*len) {
+                for index in ["csi", "tbi"] {
+                    queries.push(json::object! { kind: "gff", index: index, ref: *seqid, start: start, end: end }).unwrap();
+                    expected.push(overlapping(&gff, seqid, start, end)).unwrap();
+                }
+            }
+        }
+        fs::write(dir.join("queries.json"), queries.dump()).unwrap();
+
+        let out = Command::new("node").arg("tests/compat/query.mjs").arg(&dir).output().expect("running node failed");
+        let _ = fs::remove_dir_all(&dir);
+        assert!(out.status.success(), "{}: query.mjs failed: {}", name, String::from_utf8_lossy(&out.stderr));
+        let answers = json::parse(&String::from_utf8_lossy(&out.stdout)).expect("query.mjs printed invalid JSON");
+        assert_eq!(answers.len(), expected.len(), "{}: answer count", name);
+        for (i, (got, want)) in answers.members().zip(expected.members()).enumerate() {
+            assert_eq!(got, want, "{}: query {}", name, queries[i].dump());
+        }
+    }
+}
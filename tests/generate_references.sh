@@ -41,7 +41,7 @@ mkdir -p "$REF"
 # Step 1: produce BGZF files using our implementation so that the virtual
 # offsets in the reference indexes match what our code produces.
 echo "Building gen_references example..."
-cargo build --example gen_references --manifest-path "$REPO_ROOT/Cargo.toml"
+cargo build --features reference --example gen_references --manifest-path "$REPO_ROOT/Cargo.toml"
 
 # --- test.fasta + test.gff3 ---
 FASTA_BGZ="$REF/test.fasta.bgz"
@@ -62,6 +62,7 @@ samtools faidx "$FASTA_BGZ"
 
 echo "Running tabix (test.gff3)..."
 tabix -C -p gff "$GFF_BGZ"
+tabix -f -p gff "$GFF_BGZ"
 
 rm -f "$FASTA_BGZ" "$GFF_BGZ"
 
@@ -0,0 +1,42 @@
+//! Panic containment (`mgnify_wasm::health`).
+//!
+//! The health flag is process-wide, so this lives in its own test binary:
+//! a deliberate panic here must not poison the instance under the tests in
+//! `integration_test.rs`.
+
+use std::io;
+
+use mgnify_wasm::error::{error_code, ErrorCode};
+use mgnify_wasm::health::{contain, is_healthy, last_panic, reset};
+use mgnify_wasm::input::InputSource;
+
+/// A panicking entry point fails with `E_PANIC`, later calls are refused
+/// with `E_INSTANCE_POISONED`, and `reset()` brings the instance back.
+#[test]
+fn panic_poisons_until_reset() {
+    let ok = || contain(|| Ok::<_, io::Error>(42));
+    assert_eq!(ok().unwrap(), 42);
+    assert!(is_healthy());
+
+    // Bad input is an error, not a panic: the instance stays healthy.
+    for input in [Vec::new(), b">".to_vec()] {
+        let err = contain(|| InputSource::Bytes(input).read_all()).unwrap_err();
+        assert_eq!(error_code(&err), ErrorCode::InputEmpty);
+        assert!(is_healthy());
+    }
+
+    let err = contain(|| -> io::Result<()> { panic!("index out of range") }).unwrap_err();
+    assert_eq!(error_code(&err), ErrorCode::Panic);
+    assert!(err.to_string().contains("index out of range"), "{}", err);
+    assert!(!is_healthy());
+    assert!(last_panic().unwrap().contains("tests/health_test.rs"));
+
+    let err = ok().unwrap_err();
+    assert_eq!(error_code(&err), ErrorCode::InstancePoisoned);
+    assert!(err.to_string().contains("reset()"), "{}", err);
+
+    reset();
+    assert!(is_healthy());
+    assert_eq!(last_panic(), None);
+    assert_eq!(ok().unwrap(), 42);
+}
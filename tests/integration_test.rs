@@ -8,14 +8,14 @@
 ///   test.fasta, test.gff3
 ///   BU_ATCC8492VPI0062_NT5002.1.fa.gz, BU_ATCC8492_annotations.gff.gz
 /// Reference files required in `tests/fixtures/reference/`:
-///   test.fasta.bgz.fai, test.fasta.bgz.gzi, test.gff3.bgz.csi
+///   test.fasta.bgz.fai, test.fasta.bgz.gzi, test.gff3.bgz.csi, test.gff3.bgz.tbi
 ///   BU_ATCC8492.fasta.bgz.fai, BU_ATCC8492.fasta.bgz.gzi, BU_ATCC8492.gff3.bgz.csi
 /// (Generate them with: tests/generate_references.sh)
 use std::fs;
 use std::io::{Cursor, Read};
 
 use flate2::read::MultiGzDecoder;
-use mgnify_wasm::htslib::{bgzf_compress, csi_index_gff, faidx_index_fasta, BgzfReader};
+use mgnify_wasm::htslib::{bgzf_compress, compare_index, csi_index_gff, faidx_index_fasta, tbi_index_gff, BgzfReader, FaiRecord};
 
 // --- test.fasta / test.gff3 (plain text) ---
 const FASTA_FIXTURE: &str = "tests/fixtures/test.fasta";
@@ -23,6 +23,7 @@ const GFF_FIXTURE:   &str = "tests/fixtures/test.gff3";
 const REF_FAI:       &str = "tests/fixtures/reference/test.fasta.bgz.fai";
 const REF_GZI:       &str = "tests/fixtures/reference/test.fasta.bgz.gzi";
 const REF_CSI:       &str = "tests/fixtures/reference/test.gff3.bgz.csi";
+const REF_TBI:       &str = "tests/fixtures/reference/test.gff3.bgz.tbi";
 
 // --- BU_ATCC8492 (gzip-compressed fixtures) ---
 const BU_FASTA_FIXTURE: &str = "tests/fixtures/BU_ATCC8492VPI0062_NT5002.1.fa.gz";
@@ -86,6 +87,9 @@ fn compress_bu_gff() -> Vec<u8> {
 // CSI normalisation helper
 // ---------------------------------------------------------------------------
 
+/// (bin, loff, chunks) as read from a CSI per-sequence section.
+type CsiBin = (u32, u64, Vec<(u64, u64)>);
+
 /// Parse a raw (decompressed) CSI blob and re-serialise it with bins sorted
 /// ascending by bin number and chunks sorted ascending by start offset within
 /// each bin.  This makes the comparison robust to hash-table iteration order
@@ -108,7 +112,7 @@ fn normalize_csi(csi: &[u8]) -> Vec<u8> {
         let n_bin = i32::from_le_bytes(csi[pos..pos + 4].try_into().unwrap());
         pos += 4;
 
-        let mut bins: Vec<(u32, u64, Vec<(u64, u64)>)> = Vec::new();
+        let mut bins: Vec<CsiBin> = Vec::new();
         for _ in 0..n_bin {
             let bin  = u32::from_le_bytes(csi[pos..pos + 4].try_into().unwrap()); pos += 4;
             let loff = u64::from_le_bytes(csi[pos..pos + 8].try_into().unwrap()); pos += 8;
@@ -141,6 +145,57 @@ fn normalize_csi(csi: &[u8]) -> Vec<u8> {
     out
 }
 
+/// Same as [`normalize_csi`] for a raw TBI blob (no `loff`, but a linear
+/// index after each sequence's bins).
+fn normalize_tbi(tbi: &[u8]) -> Vec<u8> {
+    // magic (4) + n_ref (4) + 7 header fields (28) + names
+    assert!(tbi.len() >= 36, "TBI blob too short for header");
+    let l_nm = u32::from_le_bytes(tbi[32..36].try_into().unwrap()) as usize;
+    let mut pos = 36 + l_nm;
+    let mut out = tbi[0..pos].to_vec();
+    let n_ref = i32::from_le_bytes(tbi[4..8].try_into().unwrap());
+
+    for _ in 0..n_ref {
+        let n_bin = i32::from_le_bytes(tbi[pos..pos + 4].try_into().unwrap());
+        pos += 4;
+
+        let mut bins: Vec<(u32, Vec<(u64, u64)>)> = Vec::new();
+        for _ in 0..n_bin {
+            let bin = u32::from_le_bytes(tbi[pos..pos + 4].try_into().unwrap()); pos += 4;
+            let n_chunk = i32::from_le_bytes(tbi[pos..pos + 4].try_into().unwrap()); pos += 4;
+            let mut chunks: Vec<(u64, u64)> = Vec::new();
+            for _ in 0..n_chunk {
+                let s = u64::from_le_bytes(tbi[pos..pos + 8].try_into().unwrap()); pos += 8;
+                let e = u64::from_le_bytes(tbi[pos..pos + 8].try_into().unwrap()); pos += 8;
+                chunks.push((s, e));
+            }
+            chunks.sort_unstable();
+            bins.push((bin, chunks));
+        }
+        bins.sort_by_key(|&(b, _)| b);
+
+        out.extend_from_slice(&n_bin.to_le_bytes());
+        for (bin, chunks) in bins {
+            out.extend_from_slice(&bin.to_le_bytes());
+            out.extend_from_slice(&(chunks.len() as i32).to_le_bytes());
+            for (s, e) in chunks {
+                out.extend_from_slice(&s.to_le_bytes());
+                out.extend_from_slice(&e.to_le_bytes());
+            }
+        }
+
+        // Linear index is already in window order.
+        let n_intv = i32::from_le_bytes(tbi[pos..pos + 4].try_into().unwrap()) as usize;
+        let lidx_end = pos + 4 + 8 * n_intv;
+        out.extend_from_slice(&tbi[pos..lidx_end]);
+        pos = lidx_end;
+    }
+
+    // n_no_coor (8 bytes)
+    out.extend_from_slice(&tbi[pos..]);
+    out
+}
+
 // ---------------------------------------------------------------------------
 // test.fasta / test.gff3 tests
 // ---------------------------------------------------------------------------
@@ -232,6 +287,24 @@ fn csi_matches_tabix() {
     assert_eq!(normalize_csi(&ref_csi), normalize_csi(&our_csi), ".csi does not match tabix reference");
 }
 
+/// Our `.tbi` index, linear index included, matches `tabix -p gff` output.
+#[test]
+fn tbi_matches_tabix() {
+    let bgzf = compress_gff();
+
+    let mut our_tbi_bgzf = Vec::new();
+    tbi_index_gff(Cursor::new(&bgzf), &mut our_tbi_bgzf).expect("tbi_index_gff failed");
+
+    let mut ref_tbi = Vec::new();
+    BgzfReader::new(Cursor::new(read_fixture(REF_TBI))).read_to_end(&mut ref_tbi)
+        .expect("decompressing reference TBI failed");
+    let mut our_tbi = Vec::new();
+    BgzfReader::new(Cursor::new(&our_tbi_bgzf)).read_to_end(&mut our_tbi)
+        .expect("decompressing our TBI failed");
+
+    assert_eq!(normalize_tbi(&ref_tbi), normalize_tbi(&our_tbi), ".tbi does not match tabix reference");
+}
+
 // ---------------------------------------------------------------------------
 // BU_ATCC8492 tests
 // ---------------------------------------------------------------------------
@@ -282,6 +355,31 @@ fn bu_fai_matches_samtools() {
     );
 }
 
+/// With virtual offsets, each `.fai` offset points at the first sequence
+/// line and translates through the `.gzi` to the samtools offset; the other
+/// columns are unchanged.
+#[test]
+fn bu_fai_virtual_offsets_match_samtools() {
+    use mgnify_wasm::htslib::{faidx_index_fasta_with, parse_fai, parse_gzi, record_at, FaiOffsets};
+
+    let bgzf = compress_bu_fasta();
+    let (mut fai, mut gzi) = (Vec::new(), Vec::new());
+    faidx_index_fasta_with(Cursor::new(&bgzf), &mut fai, &mut gzi, FaiOffsets::Virtual).unwrap();
+    let (fai, gzi) = (parse_fai(&fai).unwrap(), parse_gzi(&gzi).unwrap());
+    let reference = parse_fai(&read_fixture(REF_BU_FAI)).unwrap();
+    let raw = read_maybe_gz(BU_FASTA_FIXTURE);
+    assert_eq!(fai.len(), reference.len());
+
+    for (ours, theirs) in fai.iter().zip(&reference) {
+        let block = gzi.iter().find(|&&(caddr, _)| caddr == ours.offset >> 16).expect("offset not on a .gzi block");
+        assert_eq!(block.1 + (ours.offset & 0xffff), theirs.offset, "{}", ours.name);
+        assert_eq!(FaiRecord { offset: theirs.offset, ..ours.clone() }, *theirs);
+
+        let first_line = raw[theirs.offset as usize..].split(|&b| b == b'\n').next().unwrap();
+        assert_eq!(record_at(&bgzf, ours.offset).unwrap().as_bytes(), first_line);
+    }
+}
+
 /// Our BU `.gzi` index matches the reference produced by `samtools faidx`.
 #[test]
 fn bu_gzi_matches_samtools() {
@@ -320,3 +418,3923 @@ fn bu_csi_matches_tabix() {
 
     assert_eq!(normalize_csi(&ref_csi), normalize_csi(&our_csi), "BU .csi does not match tabix reference");
 }
+
+/// `compare_index` sees through bin order and chunk merging, and names what
+/// actually differs.
+#[test]
+fn compare_index_explains_differences() {
+    let mut our_csi = Vec::new();
+    csi_index_gff(Cursor::new(&compress_bu_gff()), &mut our_csi).unwrap();
+    let diff = compare_index(&our_csi, &read_fixture(REF_BU_CSI)).unwrap();
+    assert_eq!(diff.format, "csi");
+    assert!(diff.is_equivalent(), "{:?}", diff.differences);
+
+    let mut our_tbi = Vec::new();
+    tbi_index_gff(Cursor::new(&compress_gff()), &mut our_tbi).unwrap();
+    assert!(compare_index(&our_tbi, &read_fixture(REF_TBI)).unwrap().is_equivalent());
+
+    // One record fewer: its seqid's chunks or record count change.
+    let gff = mgnify_wasm::gff_preprocess(&fs::read_to_string(GFF_FIXTURE).unwrap());
+    let shorter = &gff[..gff.trim_end().rfind('\n').unwrap() + 1];
+    let mut bgzf = Vec::new();
+    bgzf_compress(Cursor::new(shorter.as_bytes()), &mut bgzf).unwrap();
+    let mut csi = Vec::new();
+    csi_index_gff(Cursor::new(&bgzf), &mut csi).unwrap();
+    let diff = compare_index(&csi, &read_fixture(REF_CSI)).unwrap();
+    assert!(!diff.is_equivalent());
+    assert!(diff.differences.iter().all(|d| d.seq.is_some() && matches!(d.field, "chunks" | "pseudo_bin" | "loff")), "{:?}", diff.differences);
+
+    let fai = String::from_utf8(read_fixture(REF_BU_FAI)).unwrap();
+    let mut fields: Vec<&str> = fai.lines().next().unwrap().split('\t').collect();
+    let shifted = (fields[2].parse::<u64>().unwrap() + 1).to_string();
+    fields[2] = &shifted;
+    let mutated = fai.replacen(fai.lines().next().unwrap(), &fields.join("\t"), 1);
+    let diff = compare_index(mutated.as_bytes(), fai.as_bytes()).unwrap();
+    assert_eq!((diff.format, diff.total), ("fai", 1));
+    assert_eq!((diff.differences[0].seq.as_deref(), diff.differences[0].field), (Some(fields[0]), "offset"));
+
+    assert!(compare_index(fai.as_bytes(), &our_csi).is_err());
+}
+
+/// Corrupt counts and name blocks are parse errors, not panics or huge
+/// allocations.
+#[test]
+fn compare_index_rejects_malformed_indexes() {
+    use mgnify_wasm::error::{error_code, ErrorCode};
+
+    let mut tbi = Vec::new();
+    tbi_index_gff(Cursor::new(&compress_gff()), &mut tbi).unwrap();
+    let mut raw = Vec::new();
+    BgzfReader::new(Cursor::new(&tbi)).read_to_end(&mut raw).unwrap();
+    let n_ref = i32::from_le_bytes(raw[4..8].try_into().unwrap());
+    let l_nm = i32::from_le_bytes(raw[32..36].try_into().unwrap());
+
+    let with = |at: usize, value: i32| {
+        let mut bad = raw.clone();
+        bad[at..at + 4].copy_from_slice(&value.to_le_bytes());
+        bad
+    };
+    for bad in [with(4, -1), with(32, -1), with(32, i32::MAX), with(4, n_ref + 1)] {
+        let err = compare_index(&bad, &raw).unwrap_err();
+        assert_eq!(error_code(&err), ErrorCode::IndexParse, "{}", err);
+    }
+    // One name fewer than sequences.
+    let names = &raw[36..36 + l_nm as usize];
+    let first = names.iter().position(|&b| b == 0).unwrap() + 1;
+    let mut bad = with(32, l_nm - first as i32);
+    bad.drain(36..36 + first);
+    let err = compare_index(&raw, &bad).unwrap_err();
+    assert_eq!(error_code(&err), ErrorCode::IndexParse, "{}", err);
+
+    let mut csi = Vec::new();
+    csi_index_gff(Cursor::new(&compress_gff()), &mut csi).unwrap();
+    let mut raw = Vec::new();
+    BgzfReader::new(Cursor::new(&csi)).read_to_end(&mut raw).unwrap();
+    raw[12..16].copy_from_slice(&(-1i32).to_le_bytes());
+    let err = compare_index(&raw, &raw).unwrap_err();
+    assert_eq!(error_code(&err), ErrorCode::IndexParse, "{}", err);
+}
+
+// ---------------------------------------------------------------------------
+// Region query tests (remote reader planning, served from local bytes)
+// ---------------------------------------------------------------------------
+
+/// Slice `[start, end)` out of `data` the way an HTTP server answers a Range request.
+fn serve_range(data: &[u8], start: u64, end: Option<u64>) -> &[u8] {
+    let end = end.unwrap_or(data.len() as u64).min(data.len() as u64);
+    &data[start as usize..end as usize]
+}
+
+/// A FASTA region spanning several BGZF blocks is reassembled from only the
+/// blocks selected via the `.gzi`.
+#[test]
+fn fasta_region_from_block_range() {
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::htslib::{extract_fasta_region, fai_byte_range, gzi_block_range, parse_fai, parse_gzi};
+
+    let bgzf = compress_bu_fasta();
+    let (mut fai, mut gzi) = (Vec::new(), Vec::new());
+    faidx_index_fasta(Cursor::new(&bgzf), &mut fai, &mut gzi).expect("faidx_index_fasta failed");
+    let fai = parse_fai(&fai).unwrap();
+    let gzi = parse_gzi(&gzi).unwrap();
+    assert!(gzi.len() > 3, "BU fixture should span several blocks");
+
+    // Expected bases straight from the uncompressed FASTA.
+    let raw = read_maybe_gz(BU_FASTA_FIXTURE);
+    let rec = &fai[0];
+    let seq: Vec<u8> = raw[rec.offset as usize..]
+        .split(|&b| b == b'>').next().unwrap()
+        .iter().copied().filter(|b| !b.is_ascii_whitespace()).collect();
+
+    for &(beg, end) in &[(0u64, 10u64), (65_000, 200_000), (rec.length - 5, rec.length)] {
+        let (u0, u1) = fai_byte_range(rec, beg, end).unwrap();
+        let range = gzi_block_range(&gzi, u0, u1);
+        let blocks = serve_range(&bgzf, range.cstart, range.cend);
+        let got = extract_fasta_region(blocks, &range, rec, beg, end).expect("extract failed");
+        assert_eq!(got.as_bytes(), &seq[beg as usize..end as usize], "region {}-{}", beg, end);
+    }
+
+    // No bases per line: an empty record has an empty range, others fail.
+    let empty = parse_fai(b"empty\t0\t7\t0\t0\n").unwrap();
+    assert_eq!(fai_byte_range(&empty[0], 0, 10).unwrap(), (7, 7));
+    let bad = parse_fai(b"bad\t100\t7\t0\t0\nhuge\t100\t7\t60\t18446744073709551615\n").unwrap();
+    for rec in &bad {
+        assert_eq!(error_code(&fai_byte_range(rec, 0, 100).unwrap_err()), ErrorCode::IndexParse, "{}", rec.name);
+    }
+}
+
+/// Every `.fai` offset, located through the `.gzi`, follows its header line;
+/// a shifted offset or a `.gzi` entry off a block boundary is caught.
+#[test]
+fn fai_offsets_verified_against_gzi() {
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::htslib::verify_fasta_index;
+
+    let fa = b">a first\nACGTACGT\nACG\n>b\nAC\n>c\n>d desc\nACGTACGT\n";
+    let mut bgzf = Vec::new();
+    bgzf_compress(&fa[..], &mut bgzf).unwrap();
+    let (mut fai, mut gzi) = (Vec::new(), Vec::new());
+    faidx_index_fasta(Cursor::new(&bgzf), &mut fai, &mut gzi).unwrap();
+    verify_fasta_index(&bgzf, &fai, &gzi).unwrap();
+
+    let bu = compress_bu_fasta();
+    let (mut bu_fai, mut bu_gzi) = (Vec::new(), Vec::new());
+    faidx_index_fasta(Cursor::new(&bu), &mut bu_fai, &mut bu_gzi).unwrap();
+    verify_fasta_index(&bu, &bu_fai, &bu_gzi).unwrap();
+
+    let shifted = String::from_utf8(fai).unwrap().replace("b\t2\t25\t", "b\t2\t26\t");
+    let err = verify_fasta_index(&bgzf, shifted.as_bytes(), &gzi).unwrap_err();
+    assert_eq!(err.to_string(), "sequence \"b\": offset 26 does not follow its header line");
+
+    let mut bad_gzi = bu_gzi.clone();
+    bad_gzi[8] ^= 1;
+    assert!(verify_fasta_index(&bu, &bu_fai, &bad_gzi).is_err());
+
+    // Entries out of order are a malformed .gzi, not an arithmetic failure.
+    let mut unsorted = 2u64.to_le_bytes().to_vec();
+    for (c, u) in [(bu.len() as u64 / 2, 70000u64), (bu.len() as u64 / 4, 10)] {
+        unsorted.extend_from_slice(&c.to_le_bytes());
+        unsorted.extend_from_slice(&u.to_le_bytes());
+    }
+    let err = verify_fasta_index(&bu, &bu_fai, &unsorted).unwrap_err();
+    assert_eq!(error_code(&err), ErrorCode::IndexParse);
+}
+
+/// GFF records fetched through CSI chunks equal a brute-force overlap scan.
+#[test]
+fn gff_region_from_csi_chunks() {
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::htslib::{chunk_block_range, extract_gff_chunk, CsiIndex};
+
+    let bgzf = compress_bu_gff();
+    let mut csi = Vec::new();
+    csi_index_gff(Cursor::new(&bgzf), &mut csi).expect("csi_index_gff failed");
+    let index = CsiIndex::from_bgzf(&csi).expect("CSI parse failed");
+    let seq = index.names[0].clone();
+
+    let raw = String::from_utf8(read_maybe_gz(BU_GFF_FIXTURE)).unwrap();
+    let preprocessed = mgnify_wasm::gff_preprocess(&raw);
+
+    for &(beg, end) in &[(0u64, 5_000u64), (1_000_000, 1_250_000), (0, u32::MAX as u64)] {
+        let expected: Vec<&str> = preprocessed
+            .lines()
+            .filter(|l| !l.starts_with('#'))
+            .filter(|l| {
+                let f: Vec<&str> = l.split('\t').collect();
+                let (s, e): (u64, u64) = (f[3].parse().unwrap(), f[4].parse().unwrap());
+                f[0] == seq && s - 1 < end && e > beg
+            })
+            .collect();
+
+        let mut got = Vec::new();
+        for chunk in index.query(&seq, beg, end) {
+            let (cstart, cend) = chunk_block_range(chunk);
+            let blocks = serve_range(&bgzf, cstart, Some(cend));
+            extract_gff_chunk(blocks, cstart, chunk, &seq, beg, end, &mut got).expect("extract failed");
+        }
+        assert!(!expected.is_empty());
+        assert_eq!(got, expected, "region {}-{}", beg, end);
+    }
+
+    // Past the binnable range the query is clamped, not grown bin by bin.
+    let whole = index.query(&seq, 0, 1 << (index.min_shift + 3 * index.n_lvls));
+    assert_eq!(index.query(&seq, 0, 1 << 45), whole);
+    assert_eq!(index.query(&seq, 0, u64::MAX), whole);
+    assert!(index.query(&seq, u64::MAX, u64::MAX).is_empty());
+    assert!(index.query(&seq, 10, 10).is_empty());
+
+    // A header whose bins would shift past 64 bits is a corrupt index.
+    let mut raw = Vec::new();
+    BgzfReader::new(Cursor::new(&csi)).read_to_end(&mut raw).unwrap();
+    for (min_shift, n_lvls) in [(40u32, 9u32), (14, 10), (u32::MAX, 5)] {
+        let mut bad = raw.clone();
+        bad[4..8].copy_from_slice(&min_shift.to_le_bytes());
+        bad[8..12].copy_from_slice(&n_lvls.to_le_bytes());
+        assert_eq!(error_code(&CsiIndex::parse(&bad).err().unwrap()), ErrorCode::IndexParse);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Size/time estimate
+// ---------------------------------------------------------------------------
+
+/// The sampled estimate lands near the real output sizes, for plain and
+/// gzipped inputs.
+#[test]
+fn estimate_tracks_real_output_sizes() {
+    use mgnify_wasm::estimate::estimate;
+    use mgnify_wasm::input::InputSource;
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let options = IndexGenOptions::default();
+    for (fa, gff) in [(FASTA_FIXTURE, GFF_FIXTURE), (BU_FASTA_FIXTURE, BU_GFF_FIXTURE)] {
+        let est = estimate(InputSource::Bytes(read_fixture(fa)), InputSource::Bytes(read_fixture(gff)), &options)
+            .expect("estimate failed");
+        let (fa_bytes, gff_bytes) = (read_maybe_gz(fa), read_maybe_gz(gff));
+        let out = IndexGen::from_bytes(&fa_bytes, &gff_bytes, &options).expect("pipeline failed");
+
+        for (key, input, actual) in [("fasta", &fa_bytes, out.artifacts()[0].1), ("gff", &gff_bytes, out.artifacts()[3].1)] {
+            let close = |estimated: u64, real: usize, tolerance: f64| {
+                assert!((estimated as f64 / real as f64 - 1.0).abs() < tolerance, "{} {}: estimated {} vs {}", fa, key, estimated, real);
+            };
+            close(est[key]["uncompressed_bytes"].as_u64().unwrap(), input.len(), 0.05);
+            close(est[key]["output_bytes"].as_u64().unwrap(), actual.len(), 0.15);
+        }
+        assert!(est["stages"].members().any(|s| s["stage"] == "gff_index"));
+        assert_eq!(est["output_bytes"].as_u64(), Some(est["fasta"]["output_bytes"].as_u64().unwrap() + est["gff"]["output_bytes"].as_u64().unwrap()));
+    }
+}
+
+/// Samples come from the options' seed: the same seed reads the same
+/// slices and gives the same estimate, another seed reads other slices.
+#[test]
+fn estimate_sampling_follows_the_seed() {
+    use mgnify_wasm::estimate::estimate;
+    use mgnify_wasm::input::InputSource;
+    use mgnify_wasm::seed::{SeededRng, Seedable};
+    use mgnify_wasm::IndexGenOptions;
+
+    // SplitMix64's published first output for seed 0.
+    assert_eq!(SeededRng::new(0).next_u64(), 0xe220_a839_7b1d_cdaf);
+
+    let mut rng = SeededRng::new(1);
+    let mut fasta = b">seq\n".to_vec();
+    for _ in 0..40_000 {
+        fasta.extend((0..60).map(|_| b"ACGT"[rng.below(4) as usize]));
+        fasta.push(b'\n');
+    }
+    let sample = |seed: u32| {
+        let options = IndexGenOptions { seed, ..Default::default() };
+        InputSource::Bytes(fasta.clone()).sample(16, 0xff00, &mut options.rng("estimate")).unwrap().chunks
+    };
+    assert_eq!(sample(7), sample(7));
+    assert_ne!(sample(7), sample(8));
+    assert!(sample(7).iter().all(|c| c.len() == 0xff00));
+
+    let run = |seed: u32| {
+        let options = IndexGenOptions { seed, ..Default::default() };
+        estimate(InputSource::Bytes(fasta.clone()), InputSource::Bytes(read_fixture(GFF_FIXTURE)), &options).unwrap()
+    };
+    let (a, b) = (run(7), run(7));
+    assert_eq!(a["fasta"], b["fasta"]);
+    assert_eq!(a["output_bytes"], b["output_bytes"]);
+}
+
+// ---------------------------------------------------------------------------
+// Output cache keys
+// ---------------------------------------------------------------------------
+
+/// Cache keys are stable for identical inputs/options and change when either does.
+#[test]
+fn cache_key_tracks_inputs_and_options() {
+    use mgnify_wasm::cache::cache_key;
+    use mgnify_wasm::hash::sha256_hex;
+    use mgnify_wasm::IndexGenOptions;
+
+    let fa = sha256_hex(&read_fixture(FASTA_FIXTURE));
+    let gff = sha256_hex(&read_fixture(GFF_FIXTURE));
+    let defaults = IndexGenOptions::default();
+    let best = IndexGenOptions { compression_level: 9, ..Default::default() };
+
+    let key = cache_key(&fa, &gff, &defaults.digest());
+    assert_eq!(key, cache_key(&fa, &gff, &IndexGenOptions::default().digest()));
+    assert_ne!(key, cache_key(&fa, &gff, &best.digest()));
+    assert_ne!(key, cache_key(&gff, &fa, &defaults.digest()));
+    assert_eq!(key.len(), 64);
+
+    // A tag with a comma in it is not the two tags either side of it.
+    let joined = IndexGenOptions { attribute_blacklist: vec!["a,b".into()], ..Default::default() };
+    let split = IndexGenOptions { attribute_blacklist: vec!["a".into(), "b".into()], ..Default::default() };
+    assert_ne!(joined.digest(), split.digest());
+    let joined = IndexGenOptions { search_keys: vec!["a,b".into()], ..Default::default() };
+    let split = IndexGenOptions { search_keys: vec!["a".into(), "b".into()], ..Default::default() };
+    assert_ne!(joined.digest(), split.digest());
+}
+
+/// Cached GFF reindexing reuses FASTA outputs only under the same
+/// `fasta_digest`, which GFF-only options leave alone.
+#[test]
+fn fasta_digest_covers_only_fasta_options() {
+    use mgnify_wasm::options::{OPTION_READERS, RUN_OPTIONS};
+    use mgnify_wasm::IndexGenOptions;
+
+    let defaults = IndexGenOptions::default();
+    let gff_only = IndexGenOptions { genetic_code: 4, gff_transforms: vec!["unique_ids".into()], ..Default::default() };
+    assert_ne!(gff_only.digest(), defaults.digest());
+    assert_eq!(gff_only.fasta_digest(), defaults.fasta_digest());
+    let best = IndexGenOptions { compression_level: 9, ..Default::default() };
+    assert_ne!(best.fasta_digest(), defaults.fasta_digest());
+    let rewrapped = IndexGenOptions { rewrap_irregular: true, ..Default::default() };
+    assert_ne!(rewrapped.fasta_digest(), defaults.fasta_digest());
+
+    // Every output-affecting option is classified once, so the digest and
+    // stage retry agree on what the FASTA branch reads.
+    let options = mgnify_wasm::capabilities::capabilities_json()["options"].clone();
+    let mut keys: Vec<&str> = options.entries().map(|(key, _)| key).filter(|key| !RUN_OPTIONS.contains(key)).collect();
+    let mut classified: Vec<&str> = OPTION_READERS.iter().map(|(key, _)| *key).collect();
+    keys.sort_unstable();
+    classified.sort_unstable();
+    assert_eq!(keys, classified);
+    let tracks = IndexGenOptions { gap_track: mgnify_wasm::TrackFormat::Bed, ..Default::default() };
+    assert_eq!(tracks.fasta_digest(), defaults.fasta_digest());
+    assert_eq!(tracks.differences_read_by(&defaults, "fasta"), ["gap_track"]);
+    assert!(tracks.differences_read_by(&defaults, "gff").is_empty());
+    assert_eq!(best.differences_read_by(&defaults, "gff"), ["compression_level"]);
+}
+
+/// Offloaded hashing falls back to Rust outside a browser, with the same
+/// hashes, and leaves the options digest alone.
+#[test]
+fn offloaded_hashes_match_rust_hashes() {
+    use std::future::Future;
+    use std::task::{Context, Poll, Waker};
+    use mgnify_wasm::hash::{sha256_hex, sha256_hex_all};
+    use mgnify_wasm::IndexGenOptions;
+
+    let (fa, gff) = (read_fixture(FASTA_FIXTURE), read_fixture(GFF_FIXTURE));
+    for offload in [false, true] {
+        let mut hashing = std::pin::pin!(sha256_hex_all([&fa, &gff, b""], offload));
+        let Poll::Ready(hashes) = hashing.as_mut().poll(&mut Context::from_waker(Waker::noop())) else {
+            panic!("native hashing should not wait");
+        };
+        assert_eq!(hashes, [sha256_hex(&fa), sha256_hex(&gff), sha256_hex(b"")]);
+    }
+    let digest = |webcrypto_hashing| IndexGenOptions { webcrypto_hashing, ..Default::default() }.digest();
+    assert_eq!(digest(true), digest(false));
+}
+
+// ---------------------------------------------------------------------------
+// IndexGen pipeline
+// ---------------------------------------------------------------------------
+
+/// Rebuilding only the GFF branch from a previous run's FASTA artifacts gives
+/// the same outputs as a full run.
+#[test]
+fn gff_only_rebuild_matches_full_run() {
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let options = IndexGenOptions::default();
+    let fa = read_fixture(FASTA_FIXTURE);
+    let gff = read_fixture(GFF_FIXTURE);
+    let full = IndexGen::from_bytes(&fa, &gff, &options).expect("full run failed");
+
+    let [(_, bgz), (_, fai), (_, gzi), ..] = full.artifacts();
+    let rebuilt = IndexGen::with_fasta_artifacts(bgz.to_vec(), fai.to_vec(), gzi.to_vec(), &gff, &options)
+        .expect("GFF-only rebuild failed");
+
+    assert_eq!(full.artifacts(), rebuilt.artifacts());
+}
+
+/// Index-only mode over a run's own BGZF outputs gives that run's indexes,
+/// from memory or from files, and refuses plain gzip and unsorted records.
+#[test]
+fn indexes_only_matches_full_run() {
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::input::InputSource;
+    use mgnify_wasm::{IndexFormat, IndexGen, IndexGenOptions};
+
+    let options = IndexGenOptions { index_format: IndexFormat::Both, ..Default::default() }.with_default_search_keys();
+    let full = IndexGen::from_bytes(&read_fixture(FASTA_FIXTURE), &read_fixture(GFF_FIXTURE), &options).unwrap();
+    let [(_, fa_bgz), _, _, (_, gff_bgz), ..] = full.artifacts();
+    let expected: Vec<(&str, &[u8])> = full.artifacts().into_iter().map(|(n, d)| (n, if n.ends_with(".bgz") { &[][..] } else { d })).collect();
+
+    let indexed = IndexGen::indexes_from_sources(InputSource::Bytes(fa_bgz.to_vec()), InputSource::Bytes(gff_bgz.to_vec()), &options).unwrap();
+    assert_eq!(indexed.artifacts().to_vec(), expected);
+    assert!(!indexed.artifacts()[6].1.is_empty());
+
+    let dir = std::env::temp_dir().join(format!("mgnify-indexes-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (fa_path, gff_path) = (dir.join("a.fa.gz"), dir.join("a.gff.gz"));
+    fs::write(&fa_path, fa_bgz).unwrap();
+    fs::write(&gff_path, gff_bgz).unwrap();
+    let from_files = IndexGen::indexes_from_sources(InputSource::Path(fa_path), InputSource::Path(gff_path), &options).unwrap();
+    assert_eq!(from_files.artifacts().to_vec(), expected);
+    let _ = fs::remove_dir_all(&dir);
+
+    let mut plain = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    std::io::Write::write_all(&mut plain, &read_fixture(FASTA_FIXTURE)).unwrap();
+    let plain = plain.finish().unwrap();
+    let err = IndexGen::indexes_from_sources(InputSource::Bytes(plain), InputSource::Bytes(gff_bgz.to_vec()), &options).err().unwrap();
+    assert_eq!(error_code(&err), ErrorCode::BgzfHeader);
+
+    let mut unsorted = Vec::new();
+    bgzf_compress(&b"chr1\t.\tgene\t5\t9\t.\t+\t.\tID=a\nchr1\t.\tgene\t1\t4\t.\t+\t.\tID=b\n"[..], &mut unsorted).unwrap();
+    let err = IndexGen::indexes_from_sources(InputSource::Bytes(fa_bgz.to_vec()), InputSource::Bytes(unsorted), &options).err().unwrap();
+    assert_eq!(error_code(&err), ErrorCode::GffUnsorted);
+    assert!(err.to_string().contains("line 2"), "{}", err);
+}
+
+/// Search index hits point at the GFF lines carrying the looked-up value.
+#[test]
+fn search_index_finds_features_by_name() {
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::htslib::record_at;
+    use mgnify_wasm::search::SearchIndex;
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let options = IndexGenOptions::default().with_default_search_keys();
+    let out = IndexGen::from_bytes(&read_fixture(FASTA_FIXTURE), &read_fixture(GFF_FIXTURE), &options)
+        .expect("pipeline failed");
+    let [.., (_, gff_bgz), _, _, (_, search)] = out.artifacts();
+    let index = SearchIndex::parse(search).expect("search index parse failed");
+
+    let hits = index.find("DNAA");
+    assert!(!hits.is_empty());
+    for hit in &hits {
+        assert_eq!((hit.key.as_str(), hit.value.as_str()), ("gene", "dnaA"));
+        assert!(record_at(gff_bgz, hit.voffset).unwrap().contains("gene=\"dnaA\""));
+    }
+    let tag = index.find("SPN23F00010");
+    assert!(tag.iter().any(|h| record_at(gff_bgz, h.voffset).unwrap().contains("\tCDS\t186\t1547\t")));
+    assert!(index.find("no-such-gene").is_empty());
+
+    let plain = IndexGen::from_bytes(&read_fixture(FASTA_FIXTURE), &read_fixture(GFF_FIXTURE), &IndexGenOptions::default())
+        .expect("pipeline failed");
+    assert!(plain.artifacts()[6].1.is_empty());
+
+    // An entry count the blob has no room for fails before allocating.
+    let mut oversized = b"GSI\x01\x00".to_vec();
+    oversized.extend_from_slice(&u32::MAX.to_le_bytes());
+    let mut truncated = b"GSI\x01\x00".to_vec();
+    truncated.extend_from_slice(&1u32.to_le_bytes());
+    truncated.extend_from_slice(&[0; 10]);
+    for blob in [oversized, truncated] {
+        let err = SearchIndex::parse(&blob).err().unwrap();
+        assert_eq!(error_code(&err), ErrorCode::IndexParse);
+        assert_eq!(err.to_string(), "bad search index entry count");
+    }
+}
+
+/// Blacklisted attributes are gone from the compressed GFF and the saving is
+/// reported; a whitelist keeps only the listed keys.
+#[test]
+fn attribute_projection_shrinks_gff() {
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let fa = read_fixture(FASTA_FIXTURE);
+    let gff = read_fixture(GFF_FIXTURE);
+    let decompressed = |out: &IndexGen| {
+        let mut text = String::new();
+        BgzfReader::new(Cursor::new(out.artifacts()[3].1)).read_to_string(&mut text).unwrap();
+        text
+    };
+    let full = decompressed(&IndexGen::from_bytes(&fa, &gff, &IndexGenOptions::default()).unwrap());
+
+    let options = IndexGenOptions {
+        attribute_blacklist: vec!["translation".into(), "db_xref".into()],
+        ..Default::default()
+    };
+    let out = IndexGen::from_bytes(&fa, &gff, &options).unwrap();
+    let stripped = decompressed(&out);
+    assert!(full.contains("translation=") && !stripped.contains("translation=") && !stripped.contains("db_xref="));
+    assert_eq!(out.attribute_bytes_saved(), (full.len() - stripped.len()) as u64);
+    assert_eq!(full.lines().count(), stripped.lines().count());
+
+    let options = IndexGenOptions { attribute_whitelist: vec!["ID".into()], ..Default::default() };
+    let ids_only = decompressed(&IndexGen::from_bytes(&fa, &gff, &options).unwrap());
+    for line in ids_only.lines().filter(|l| !l.starts_with('#')) {
+        let attributes = line.split('\t').nth(8).unwrap();
+        assert!(attributes.starts_with("ID=") && !attributes.contains(';'), "{}", line);
+    }
+}
+
+/// Streaming the sorted GFF with a projection writes the records sorted,
+/// comments with the header, the FASTA section dropped and the attributes
+/// projected, unsorted and reversed records included, and `output_len`
+/// predicts its size.
+#[test]
+fn sorted_gff_streams_preprocessed_text() {
+    use mgnify_wasm::gff::{gff_preprocess_with_warnings, project_attributes, AttributeFilter, SortedGff};
+    use mgnify_wasm::warnings::Warnings;
+
+    let small = "##gff-version 3\n##sequence-region b 1 500\n\
+        b\tsrc\tgene\t300\t400\t.\t+\t.\tID=g3;locus_tag=L3;Name=x\n\
+        a\tsrc\tCDS\t90\t10\t.\t-\t0\tID=c1;locus_tag=L1\n\
+        # a comment\n\
+        a\tsrc\tgene\t5\t100\t.\t-\t.\tID=g1;locus_tag=L1\n\
+        b\tsrc\tgene\t20\t60\t.\t+\t.\tID=g2;locus_tag=L2\n\
+        ##FASTA\n>a\nACGT\n";
+    for (repair, cds, code) in [(false, "90\t10", "start_after_end"), (true, "10\t90", "coordinates_swapped")] {
+        let whitelist = vec!["ID".to_owned(), "locus_tag".to_owned()];
+        let blacklist = vec!["locus_tag".to_owned()];
+        let filter = AttributeFilter { whitelist: &whitelist, blacklist: &blacklist };
+        let mut warnings = Warnings::default();
+        let mut out = Vec::new();
+        SortedGff::new(small, repair, &mut warnings).write_to(&mut out, filter).unwrap();
+        let expected = format!(
+            "##gff-version 3\n##sequence-region b 1 500\n# a comment\n\
+             a\tsrc\tgene\t5\t100\t.\t-\t.\tID=g1\n\
+             a\tsrc\tCDS\t{}\t.\t-\t0\tID=c1\n\
+             b\tsrc\tgene\t20\t60\t.\t+\t.\tID=g2\n\
+             b\tsrc\tgene\t300\t400\t.\t+\t.\tID=g3\n",
+            cds
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+        let codes: Vec<&str> = warnings.warnings.iter().map(|w| w.code).collect();
+        assert_eq!(codes, [code, "unsorted_input"]);
+    }
+
+    let raw = String::from_utf8(read_maybe_gz(BU_GFF_FIXTURE)).unwrap();
+    let mut lines: Vec<&str> = raw.lines().collect();
+    lines.reverse();
+    let reversed = lines.join("\n").replacen("\t1\t1386\t", "\t1386\t1\t", 1);
+
+    for repair in [false, true] {
+        let whitelist = vec!["ID".to_owned(), "locus_tag".to_owned()];
+        let blacklist = vec!["locus_tag".to_owned()];
+        let filter = AttributeFilter { whitelist: &whitelist, blacklist: &blacklist };
+        let (mut expected_warnings, mut warnings) = (Warnings::default(), Warnings::default());
+        let text = gff_preprocess_with_warnings(&reversed, repair, &mut expected_warnings);
+        let expected = project_attributes(&text, &whitelist, &blacklist);
+
+        let sorted = SortedGff::new(&reversed, repair, &mut warnings);
+        let mut out = Vec::new();
+        let saved = sorted.write_to(&mut out, filter).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+        assert_eq!(saved, (text.len() - expected.len()) as u64);
+        assert_eq!(sorted.output_len(filter), expected.len() as u64);
+        assert_eq!(sorted.output_len(AttributeFilter::default()), text.len() as u64);
+        assert_eq!(warnings, expected_warnings);
+        assert!(warnings.warnings.iter().any(|w| w.code == "unsorted_input"));
+    }
+}
+
+/// A GFF3 already in index order is streamed as it is, with the same
+/// output and warnings as the sorted path; a `#` line after the records
+/// sends it back to the sort.
+#[test]
+fn sorted_gff_streams_input_already_in_order() {
+    use mgnify_wasm::gff::{AttributeFilter, SortedGff};
+    use mgnify_wasm::warnings::Warnings;
+
+    let raw = String::from_utf8(read_maybe_gz(BU_GFF_FIXTURE)).unwrap();
+    let mut warnings = Warnings::default();
+    let in_order = String::from_utf8({
+        let mut out = Vec::new();
+        SortedGff::new(&raw, false, &mut warnings).write_to(&mut out, AttributeFilter::default()).unwrap();
+        out
+    })
+    .unwrap();
+    let with_fasta = format!("{}##FASTA\n>contig\nACGT\n", in_order);
+
+    let sorted = SortedGff::new(&with_fasta, false, &mut Warnings::default());
+    assert!(sorted.was_in_order());
+    let mut out = Vec::new();
+    sorted.write_to(&mut out, AttributeFilter::default()).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), in_order);
+
+    // A reversed record is in order once repaired, and written swapped.
+    let reversed = in_order.replacen("\t1\t1386\t", "\t1386\t1\t", 1);
+    let mut warnings = Warnings::default();
+    let sorted = SortedGff::new(&reversed, true, &mut warnings);
+    assert!(sorted.was_in_order());
+    assert_eq!(sorted.output_len(AttributeFilter::default()), in_order.len() as u64);
+    assert!(warnings.warnings.iter().any(|w| w.code == "coordinates_swapped"));
+    assert!(!warnings.warnings.iter().any(|w| w.code == "unsorted_input"));
+
+    let trailing_comment = format!("{}# trailing comment\n", in_order);
+    let sorted = SortedGff::new(&trailing_comment, false, &mut Warnings::default());
+    assert!(!sorted.was_in_order());
+    let mut out = Vec::new();
+    sorted.write_to(&mut out, AttributeFilter::default()).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(out.len(), trailing_comment.len());
+    assert!(out.lines().take_while(|l| l.starts_with('#')).any(|l| l == "# trailing comment"));
+}
+
+/// `gff_sort_keys` orders records with identical coordinates by type
+/// priority and strand, leaves coordinate order alone, and rejects unknown
+/// keys.
+#[test]
+fn gff_sort_keys_order_records_with_equal_coordinates() {
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::gff::{AttributeFilter, SortedGff, TieBreak};
+    use mgnify_wasm::warnings::Warnings;
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let gff = "##gff-version 3\n\
+        c1\t.\tCDS\t10\t90\t.\t+\t0\tID=cds1\n\
+        c1\t.\tgene\t10\t90\t.\t-\t.\tID=gene2\n\
+        c1\t.\tmRNA\t10\t90\t.\t+\t.\tID=mrna1\n\
+        c1\t.\tgene\t10\t90\t.\t+\t.\tID=gene1\n\
+        c1\t.\tgene\t5\t90\t.\t-\t.\tID=gene0\n";
+    let ids = |keys: &[&str]| {
+        let keys: Vec<String> = keys.iter().map(|k| k.to_string()).collect();
+        let priority = vec!["gene".to_owned(), "mRNA".to_owned()];
+        let ties = TieBreak::parse(&keys, &priority).unwrap();
+        let mut out = Vec::new();
+        let mut warnings = Warnings::default();
+        SortedGff::with_ties(gff, false, &ties, &mut warnings).write_to(&mut out, AttributeFilter::default()).unwrap();
+        let ids: Vec<String> = String::from_utf8(out).unwrap().lines().filter_map(|l| l.split("ID=").nth(1).map(str::to_owned)).collect();
+        (ids, warnings.warnings.iter().filter(|w| w.code == "unsorted_input").count())
+    };
+
+    assert_eq!(ids(&[]), (vec!["gene0", "cds1", "gene2", "mrna1", "gene1"].into_iter().map(String::from).collect(), 1));
+    assert_eq!(ids(&["type"]).0, ["gene0", "gene2", "gene1", "mrna1", "cds1"]);
+    assert_eq!(ids(&["strand"]).0, ["gene0", "cds1", "mrna1", "gene1", "gene2"]);
+    assert_eq!(ids(&["type", "strand"]).0, ["gene0", "gene1", "gene2", "mrna1", "cds1"]);
+
+    // Already in that order: streamed, and no coordinate disorder reported.
+    let ordered = "c1\t.\tgene\t10\t90\t.\t+\t.\tID=a\nc1\t.\tCDS\t10\t90\t.\t+\t0\tID=b\n";
+    let priority = vec!["gene".to_owned()];
+    let ties = TieBreak::parse(&["type".to_owned()], &priority).unwrap();
+    let mut warnings = Warnings::default();
+    assert!(SortedGff::with_ties(ordered, false, &ties, &mut warnings).was_in_order());
+    assert!(warnings.is_empty());
+
+    let fa = read_fixture(FASTA_FIXTURE);
+    let options = IndexGenOptions { gff_sort_keys: vec!["phase".to_owned()], ..Default::default() };
+    let err = IndexGen::from_bytes(&fa, gff.as_bytes(), &options).err().unwrap();
+    assert_eq!(error_code(&err), ErrorCode::InvalidOption);
+    assert_ne!(options.digest(), IndexGenOptions::default().digest());
+}
+
+/// GTF input is detected from its column 9 and indexed as GFF3, with the
+/// genes and transcripts it only implies added and reserved characters
+/// escaped; GFF3 input is left alone.
+#[test]
+fn gtf_input_is_converted_to_gff3() {
+    use mgnify_wasm::gff::{attribute, detect_dialect, GffDialect, GffFields};
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let gtf = "##gff-version 2\n\
+        #!genome-build test\n\
+        FM211187\tsrc\tgene\t100\t900\t.\t+\t.\tgene_id \"g1\"; gene_name \"abc\";\n\
+        FM211187\tsrc\texon\t100\t400\t.\t+\t.\tgene_id \"g1\"; transcript_id \"t1\"; tag \"basic\"; tag \"CCDS\";\n\
+        FM211187\tsrc\tCDS\t150\t900\t.\t+\t0\tgene_id \"g1\"; transcript_id \"t1\"; note \"a=b; c,d\";\n\
+        FM211187\tsrc\texon\t2000\t2500\t.\t-\t.\tgene_id \"g2\"; transcript_id \"t2\";\n";
+    assert_eq!(detect_dialect(gtf), GffDialect::Gtf);
+    assert_eq!(detect_dialect(&String::from_utf8(read_fixture(GFF_FIXTURE)).unwrap()), GffDialect::Gff3);
+
+    let fa = read_fixture(FASTA_FIXTURE);
+    let out = IndexGen::from_bytes(&fa, gtf.as_bytes(), &IndexGenOptions::default()).unwrap();
+    let mut text = String::new();
+    MultiGzDecoder::new(out.artifacts()[3].1).read_to_string(&mut text).unwrap();
+    assert!(text.starts_with("##gff-version 3\n#!genome-build test\n"), "{}", text);
+    assert_eq!(detect_dialect(&text), GffDialect::Gff3);
+
+    let records: Vec<GffFields> = text.lines().filter_map(GffFields::parse).collect();
+    let find = |ftype: &str, id: &str| records.iter().find(|f| f.ftype == ftype && f.attributes.contains(id)).unwrap();
+    assert_eq!(find("gene", "ID=g1").attributes, "ID=g1;gene_id=g1;gene_name=abc");
+    assert_eq!(find("exon", "t1").attributes, "Parent=t1;gene_id=g1;transcript_id=t1;tag=basic,CCDS");
+    assert_eq!(attribute(find("CDS", "t1").attributes, "note"), Some("a%3Db%3B c%2Cd"));
+    let t1 = find("transcript", "ID=t1");
+    assert_eq!((t1.start, t1.end, attribute(t1.attributes, "Parent")), (100, 900, Some("g1")));
+    let g2 = find("gene", "ID=g2");
+    assert_eq!((g2.start, g2.end, g2.strand), (2000, 2500, "-"));
+    assert_eq!(records.len(), 7);
+    assert!(out.warnings().warnings.iter().any(|w| w.code == "gtf_converted"));
+    // Cached results rebuild their warnings from JSON.
+    assert_eq!(mgnify_wasm::warnings::Warnings::from_json(&out.warnings().to_json()), *out.warnings());
+
+    let report = IndexGen::dry_run_bytes(&fa, gtf.as_bytes(), &IndexGenOptions::default());
+    assert_eq!(report["stats"]["gff_dialect"].as_str(), Some("gtf"));
+    assert_eq!(report["stats"]["sorted_gff_bytes"].as_usize(), Some(text.len()));
+}
+
+/// The manifest lists every output produced with its size, SHA-256 and a
+/// passing format check, and flags bytes that are not what their name says.
+#[test]
+fn manifest_lists_outputs_with_checks() {
+    use mgnify_wasm::hash::sha256_hex;
+    use mgnify_wasm::manifest::manifest;
+    use mgnify_wasm::{IndexFormat, IndexGen, IndexGenOptions, OutputContainer};
+
+    let (fa, gff) = (read_fixture(FASTA_FIXTURE), read_fixture(GFF_FIXTURE));
+    let options = IndexGenOptions { index_format: IndexFormat::Both, ..Default::default() }.with_default_search_keys();
+    let out = IndexGen::from_bytes(&fa, &gff, &options).unwrap();
+    let m = out.manifest();
+    assert_eq!(m["producer"]["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(m["producer"]["options_digest"].as_str(), Some(options.digest().as_str()));
+    assert_eq!(m["producer"]["seed"].as_u32(), Some(0));
+    assert!(m["valid"].as_bool().unwrap(), "{}", m.pretty(2));
+    let entries: Vec<_> = m["artifacts"].members().collect();
+    let formats: Vec<&str> = entries.iter().map(|e| e["format"].as_str().unwrap()).collect();
+    assert_eq!(formats, ["bgzf", "fai", "gzi", "bgzf", "csi", "tbi", "gsi"]);
+    for (entry, (name, data)) in entries.iter().zip(out.artifacts()) {
+        assert_eq!(entry["name"], name);
+        assert_eq!(entry["size"].as_usize(), Some(data.len()));
+        assert_eq!(entry["sha256"].as_str(), Some(sha256_hex(data).as_str()));
+    }
+
+    let gzip = IndexGenOptions { gff_output: OutputContainer::Gzip, ..Default::default() };
+    let m = IndexGen::from_bytes(&fa, &gff, &gzip).unwrap().manifest();
+    assert_eq!(m["artifacts"].len(), 4);
+    assert_eq!(m["artifacts"][3]["format"], "gzip");
+    assert!(m["valid"].as_bool().unwrap());
+
+    let [bgz, fai, ..] = out.artifacts();
+    let truncated = &bgz.1[..bgz.1.len() - 28];
+    let m = manifest(&[("fasta.bgz", truncated), ("fasta.fai", fai.1), ("gff.csi", fai.1)], "digest", 0);
+    let checks: Vec<bool> = m["artifacts"].members().map(|e| e["magic_ok"].as_bool().unwrap()).collect();
+    assert_eq!(checks, [false, true, false]);
+    assert!(!m["valid"].as_bool().unwrap());
+}
+
+/// `capabilities()` reports the version, the cargo features built in and
+/// every option with its accepted values.
+#[test]
+fn capabilities_describe_the_build() {
+    use mgnify_wasm::capabilities::capabilities_json;
+    use mgnify_wasm::options::OPTIONS_SCHEMA_VERSION;
+
+    let caps = capabilities_json();
+    assert_eq!(caps["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(caps["options_schema_version"].as_u32(), Some(OPTIONS_SCHEMA_VERSION));
+    assert_eq!(caps["features"]["threads"].as_bool(), Some(cfg!(feature = "threads")));
+    assert!(caps["input_budget"].is_null());
+    assert!(caps["formats"]["indexes"].members().any(|f| f == "csi"));
+    let options = &caps["options"];
+    assert_eq!(options["compression_level"], "0-9");
+    assert_eq!(options["index_format"].len(), 3);
+    for name in ["gff_sort_keys", "gff_type_priority", "attribute_whitelist", "fai_offsets", "verify_written_crc"] {
+        assert!(options.has_key(name), "{} missing from {}", name, options.dump());
+    }
+    assert!(!options.has_key("version"));
+}
+
+/// A dry run over the fixtures is clean and its stats match a real run.
+#[test]
+fn dry_run_reports_stats_without_outputs() {
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let (fa, gff) = (read_fixture(FASTA_FIXTURE), read_fixture(GFF_FIXTURE));
+    let report = IndexGen::dry_run_bytes(&fa, &gff, &IndexGenOptions::default());
+    assert!(report["valid"].as_bool().unwrap(), "{}", report["validation"].pretty(2));
+
+    let stats = &report["stats"];
+    assert_eq!(stats["fasta"]["sequences"].as_usize(), Some(1));
+    assert_eq!(stats["fasta"]["total_length"].as_u64(), Some(2_221_315));
+    assert_eq!(stats["fasta"]["n50"].as_u64(), Some(2_221_315));
+    assert!(stats["gff"]["fasta_section"].as_bool().unwrap());
+    let data_lines = mgnify_wasm::gff::data_lines(std::str::from_utf8(&gff).unwrap()).count();
+    assert_eq!(stats["gff"]["records"].as_usize(), Some(data_lines));
+
+    let out = IndexGen::from_bytes(&fa, &gff, &IndexGenOptions::default()).unwrap();
+    let mut sorted = Vec::new();
+    BgzfReader::new(Cursor::new(out.artifacts()[3].1)).read_to_end(&mut sorted).unwrap();
+    assert_eq!(stats["sorted_gff_bytes"].as_usize(), Some(sorted.len()));
+}
+
+/// Broken inputs yield one issue per problem, with codes and line numbers.
+#[test]
+fn dry_run_reports_line_issues() {
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let fa = b">chr1 first\nACGTNACGTA\n>chr1\nAC!T\n>empty\n";
+    let gff = "##gff-version 3\n\
+        chr1\t.\tgene\t1\t10\t.\t+\t.\tID=a\n\
+        chr1\t.\tgene\t8\t3\t.\t-\t.\tID=b\n\
+        chr1\t.\tgene\t5\t50\t.\t+\t.\tID=c\n\
+        chr1\t.\tgene\t5\n\
+        chrX\t.\tCDS\t1\t3\t.\t*\t.\tID=d\n";
+    let report = IndexGen::dry_run_bytes(fa, gff.as_bytes(), &IndexGenOptions::default());
+    assert!(!report["valid"].as_bool().unwrap());
+    assert_eq!(report["stats"]["sorted_gff_bytes"].as_usize(), Some(0));
+
+    let issues: Vec<(String, String, Option<usize>)> = report["validation"]["issues"]
+        .members()
+        .map(|i| (i["file"].to_string(), i["code"].to_string(), i["line"].as_usize()))
+        .collect();
+    let expected = [
+        ("fasta", "duplicate_name", Some(3)),
+        ("fasta", "invalid_base", Some(4)),
+        ("fasta", "empty_sequence", Some(5)),
+        ("gff", "start_after_end", Some(3)),
+        ("gff", "beyond_sequence_end", Some(4)),
+        ("gff", "column_count", Some(5)),
+        ("gff", "unknown_seqid", Some(6)),
+        ("gff", "bad_strand", Some(6)),
+        ("gff", "bad_phase", Some(6)),
+    ];
+    let expected: Vec<_> = expected.iter().map(|(f, c, l)| (f.to_string(), c.to_string(), *l)).collect();
+    assert_eq!(issues, expected);
+}
+
+/// Plain gzip outputs are single members without BGZF framing that decode
+/// to the same bytes as the BGZF outputs, and come without indexes.
+#[test]
+fn gzip_outputs_are_single_plain_members() {
+    use flate2::read::GzDecoder;
+    use mgnify_wasm::{IndexGen, IndexGenOptions, OutputContainer};
+
+    let (fa, gff) = (read_fixture(FASTA_FIXTURE), read_fixture(GFF_FIXTURE));
+    let bgzf = IndexGen::from_bytes(&fa, &gff, &IndexGenOptions::default().with_default_search_keys()).unwrap();
+    let options = IndexGenOptions {
+        fasta_output: OutputContainer::Gzip,
+        gff_output: OutputContainer::Gzip,
+        ..IndexGenOptions::default().with_default_search_keys()
+    };
+    let gzip = IndexGen::from_bytes(&fa, &gff, &options).unwrap();
+
+    for (i, name) in [(0, "fasta"), (3, "gff")] {
+        let (data, reference) = (gzip.artifacts()[i].1, bgzf.artifacts()[i].1);
+        assert_eq!(data[3], 0, "{}: FLG must not set FEXTRA", name);
+
+        // A single-member decoder must see the whole stream (no EOF block).
+        let (mut plain, mut expected) = (Vec::new(), Vec::new());
+        let mut single = GzDecoder::new(data);
+        single.read_to_end(&mut plain).unwrap();
+        assert!(single.into_inner().is_empty(), "{}: trailing members", name);
+        BgzfReader::new(Cursor::new(reference)).read_to_end(&mut expected).unwrap();
+        assert!(plain == expected, "{}: decoded bytes differ", name);
+    }
+    assert!(gzip.artifacts().iter().filter(|(name, _)| !name.ends_with("bgz")).all(|(_, data)| data.is_empty()));
+}
+
+/// Non-fatal issues end up in the warnings report, not in errors, and
+/// survive the JSON round trip used by the cache.
+#[test]
+fn warnings_collect_non_fatal_issues() {
+    use mgnify_wasm::warnings::Warnings;
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let fa = b">chr1\nACGTACGTACGTACGTACGT\n";
+    let gff = "##gff-version 3\n\
+        ##sequence-region chr1 1 20\n\
+        chr1\t.\tgene\t9\t12\t.\t+\t.\tID=b\n\
+        ##gff-version 3\n\
+        ##sequence-region chr1 1 20\n\
+        chr1\t.\tgene\t1\t4\t.\t+\t.\tID=a\n\
+        ###\n\
+        ###\n";
+    let options = IndexGenOptions { compression_level: 12, ..Default::default() };
+    let out = IndexGen::from_bytes(fa, gff.as_bytes(), &options).unwrap();
+
+    let found: Vec<(&str, Option<usize>)> = out.warnings().warnings.iter().map(|w| (w.code, w.line)).collect();
+    assert_eq!(
+        found,
+        [("compression_level_clamped", None), ("duplicate_pragma", Some(4)), ("duplicate_pragma", Some(5)), ("unsorted_input", Some(6))]
+    );
+    assert_eq!(out.warnings().count("duplicate_pragma"), 2);
+
+    let json = json::parse(&out.warnings_json()).unwrap();
+    assert_eq!(json["total"].as_usize(), Some(4));
+    assert_eq!(Warnings::from_json(&json), *out.warnings());
+
+    let clean = IndexGen::from_bytes(fa, b"##gff-version 3\nchr1\t.\tgene\t1\t4\t.\t+\t.\tID=a\n", &Default::default()).unwrap();
+    assert!(clean.warnings().is_empty());
+}
+
+/// Merging two stages' warnings keeps the per-code cap of one run.
+#[test]
+fn merged_warnings_keep_the_per_code_cap() {
+    use mgnify_wasm::warnings::Warnings;
+
+    let some = |stage, n| {
+        let mut out = Warnings::default();
+        for line in 0..n {
+            out.push(stage, "record_dropped", Some(line + 1), format!("line {}", line + 1));
+        }
+        out
+    };
+    let mut merged = some("fasta", 80);
+    merged.extend(some("gff", 80));
+    assert_eq!(merged.count("record_dropped"), 160);
+    assert_eq!(merged.warnings.len(), 100);
+    assert_eq!(merged.warnings.iter().filter(|w| w.stage == "gff").count(), 20);
+}
+
+/// `repair_coordinates` swaps start > end records, which then sort by their
+/// real start; without it they are kept and flagged.
+#[test]
+fn repair_coordinates_swaps_reversed_records() {
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let fa = b">chr1\nACGTACGTACGTACGTACGT\n";
+    let gff = "chr1\t.\tgene\t2\t6\t.\t+\t.\tID=a\n\
+        chr1\t.\tgene\t12\t3\t.\t-\t.\tID=b\r\n";
+    let gff_text = |out: &IndexGen| {
+        let mut text = String::new();
+        BgzfReader::new(out.artifacts()[3].1).read_to_string(&mut text).unwrap();
+        text
+    };
+
+    let kept = IndexGen::from_bytes(fa, gff.as_bytes(), &IndexGenOptions::default()).unwrap();
+    assert_eq!(gff_text(&kept), gff);
+    assert_eq!(kept.warnings().warnings[0].code, "start_after_end");
+
+    let options = IndexGenOptions { repair_coordinates: true, ..Default::default() };
+    let repaired = IndexGen::from_bytes(fa, gff.as_bytes(), &options).unwrap();
+    assert_eq!(gff_text(&repaired), "chr1\t.\tgene\t2\t6\t.\t+\t.\tID=a\nchr1\t.\tgene\t3\t12\t.\t-\t.\tID=b\r\n");
+    let found: Vec<(&str, Option<usize>)> = repaired.warnings().warnings.iter().map(|w| (w.code, w.line)).collect();
+    assert_eq!(found, [("coordinates_swapped", Some(2))]);
+}
+
+/// Control characters fail the run by default, or are stripped / replaced
+/// (`%XX` in the GFF3, `N` in sequences) with a warning per line.
+#[test]
+fn control_characters_follow_policy() {
+    use mgnify_wasm::{ControlCharPolicy, IndexGen, IndexGenOptions};
+
+    let fa = b">chr1\x01 desc\nACGT\x00ACGT\nACGTACGTA\n";
+    let gff = b"chr1\t.\tgene\t1\t4\t.\t+\t.\tID=a\x1bb\n";
+    let text = |out: &IndexGen, i: usize| {
+        let mut text = Vec::new();
+        BgzfReader::new(out.artifacts()[i].1).read_to_end(&mut text).unwrap();
+        text
+    };
+
+    let err = IndexGen::from_bytes(fa, gff, &IndexGenOptions::default()).err().unwrap();
+    assert_eq!(err.to_string(), "fasta line 2 contains control character 0x00");
+
+    let options = IndexGenOptions { control_chars: ControlCharPolicy::Replace, ..Default::default() };
+    let replaced = IndexGen::from_bytes(fa, gff, &options).unwrap();
+    assert_eq!(text(&replaced, 0), b">chr1\x01 desc\nACGTNACGT\nACGTACGTA\n");
+    assert_eq!(text(&replaced, 3), b"chr1\t.\tgene\t1\t4\t.\t+\t.\tID=a%1Bb\n");
+    assert_eq!(replaced.warnings().count("control_characters"), 2);
+
+    let options = IndexGenOptions { control_chars: ControlCharPolicy::Strip, ..Default::default() };
+    let stripped = IndexGen::from_bytes(fa, gff, &options).unwrap();
+    assert_eq!(text(&stripped, 0), b">chr1\x01 desc\nACGTACGT\nACGTACGTA\n");
+    assert_eq!(text(&stripped, 3), b"chr1\t.\tgene\t1\t4\t.\t+\t.\tID=ab\n");
+}
+
+/// A sequence whose lines change length before its last line cannot be
+/// described by `.fai`: it is rejected, or rewrapped on request.
+#[test]
+fn irregular_fasta_lines_rejected_or_rewrapped() {
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let fa = b">a\nACGT\nACG\nACGT\n>b\nACGT\nAC\n\n>c\nACGT\n\nACGT\n";
+    let gff = b"##gff-version 3\n";
+    let err = IndexGen::from_bytes(fa, gff, &IndexGenOptions::default()).err().unwrap();
+    assert_eq!(err.to_string(), "irregular line length in sequence \"a\" at line 4");
+
+    let options = IndexGenOptions { rewrap_irregular: true, ..Default::default() };
+    let out = IndexGen::from_bytes(fa, gff, &options).unwrap();
+    let mut text = Vec::new();
+    BgzfReader::new(out.artifacts()[0].1).read_to_end(&mut text).unwrap();
+    assert_eq!(text, b">a\nACGT\nACGA\nCGT\n>b\nACGT\nAC\n>c\nACGT\nACGT\n");
+    assert_eq!(String::from_utf8_lossy(out.artifacts()[1].1), "a\t11\t3\t4\t5\nb\t6\t20\t4\t5\nc\t8\t31\t4\t5\n");
+    let found: Vec<(&str, Option<usize>)> = out.warnings().warnings.iter().map(|w| (w.code, w.line)).collect();
+    assert_eq!(found, [("fasta_rewrapped", Some(4))]);
+}
+
+/// A partial run keeps the GFF outputs when the FASTA stage fails, and a
+/// retry with other options fills in the FASTA outputs; a failing retry
+/// empties its stage and is listed again.
+#[test]
+fn partial_run_keeps_outputs_and_retries_a_stage() {
+    use mgnify_wasm::error::ErrorCode;
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let fa = b">a\nACGT\nACG\nACGT\n";
+    let gff = b"##gff-version 3\na\t.\tgene\t1\t8\t.\t+\t.\tID=g1\n";
+    let mut out = IndexGen::from_bytes_partial(fa, gff, &IndexGenOptions::default());
+    let stages: Vec<(&str, ErrorCode)> = out.failures().iter().map(|f| (f.stage, f.error.code)).collect();
+    assert_eq!(stages, [("fasta", ErrorCode::FastaLineLength)]);
+    let sizes = |out: &IndexGen| out.artifacts().map(|(_, data)| data.len());
+    assert!(sizes(&out)[..3].iter().all(|&n| n == 0));
+    assert!(sizes(&out)[3] > 0 && sizes(&out)[4] > 0);
+    let gff_outputs = out.artifacts()[3..].iter().map(|(_, data)| data.to_vec()).collect::<Vec<_>>();
+
+    // A retry may not change what the kept GFF outputs were made with.
+    let level = IndexGenOptions { rewrap_irregular: true, compression_level: 9, ..Default::default() };
+    let err = out.retry_fasta_bytes(fa, &level).unwrap_err();
+    assert_eq!(mgnify_wasm::error::error_code(&err), ErrorCode::InvalidOption);
+    assert!(err.to_string().contains("compression_level"), "{}", err);
+    assert_eq!(out.failures().len(), 1);
+
+    // The GFF outputs were made with the same GFF options, so the result
+    // as a whole is made with the retry's options.
+    let rewrap = IndexGenOptions { rewrap_irregular: true, ..Default::default() };
+    out.retry_fasta_bytes(fa, &rewrap).unwrap();
+    assert!(out.failures().is_empty());
+    assert!(sizes(&out)[..2].iter().all(|&n| n > 0));
+    assert_eq!(out.artifacts()[3..].iter().map(|(_, data)| data.to_vec()).collect::<Vec<_>>(), gff_outputs);
+    assert_eq!(out.warnings().count("fasta_rewrapped"), 1);
+    let fresh = IndexGen::from_bytes(fa, gff, &rewrap).unwrap();
+    assert_eq!(out.artifacts(), fresh.artifacts());
+    assert_eq!(out.manifest()["producer"]["options_digest"].as_str(), Some(rewrap.digest().as_str()));
+
+    let bad_keys = IndexGenOptions { rewrap_irregular: true, gff_sort_keys: vec!["score".into()], ..Default::default() };
+    let err = out.retry_gff_bytes(gff, &bad_keys).unwrap_err();
+    assert_eq!(mgnify_wasm::error::error_code(&err), ErrorCode::InvalidOption);
+    assert_eq!(out.failures().iter().map(|f| f.stage).collect::<Vec<_>>(), ["gff"]);
+    assert!(sizes(&out)[3..].iter().all(|&n| n == 0));
+    assert!(sizes(&out)[0] > 0);
+
+    // Option warnings are those of the retry's options, once.
+    let clamped = IndexGenOptions { compression_level: 12, ..Default::default() };
+    let mut out = IndexGen::from_bytes_partial(fa, gff, &clamped);
+    out.retry_fasta_bytes(fa, &IndexGenOptions { rewrap_irregular: true, ..clamped.clone() }).unwrap();
+    assert_eq!(out.warnings().count("compression_level_clamped"), 1);
+    assert_eq!(out.warnings().count("fasta_rewrapped"), 1);
+}
+
+/// Dropping a stage's warnings takes its occurrences past the cap off the
+/// counts too.
+#[test]
+fn removing_a_stage_keeps_warning_counts_exact() {
+    use mgnify_wasm::warnings::Warnings;
+
+    let mut warnings = Warnings::default();
+    for line in 1..=150 {
+        warnings.push("fasta", "control_characters", Some(line), format!("line {}", line));
+    }
+    for line in 1..=10 {
+        warnings.push("gff", "control_characters", Some(line), format!("line {}", line));
+    }
+    assert_eq!((warnings.count("control_characters"), warnings.warnings.len()), (160, 100));
+    let reloaded = Warnings::from_json(&warnings.to_json());
+    assert_eq!(reloaded, warnings);
+
+    for mut warnings in [warnings, reloaded] {
+        warnings.remove_stage("fasta");
+        assert_eq!(warnings.count("control_characters"), 10);
+        assert!(warnings.warnings.is_empty());
+        warnings.remove_stage("gff");
+        assert!(warnings.is_empty());
+    }
+}
+
+/// Preview runs keep whole lines of the first sequences of each input and
+/// mark the result partial only when something was cut.
+#[test]
+fn preview_covers_the_start_of_each_input() {
+    use std::io::Write;
+    use mgnify_wasm::input::InputSource;
+    use mgnify_wasm::preview::PreviewOptions;
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let fa = b">a\nACGTACGT\n>b\nACGTACGT\n>c\nACGTACGT\n";
+    let gff = b"##gff-version 3\na\t.\tgene\t1\t8\t.\t+\t.\tID=g1\nb\t.\tgene\t1\t8\t.\t+\t.\tID=g2\n\
+                b\t.\tCDS\t2\t7\t.\t+\t0\tID=c2\nc\t.\tgene\t1\t8\t.\t+\t.\tID=g3\n";
+    let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gz.write_all(fa).unwrap();
+    let fa_gz = gz.finish().unwrap();
+    let options = IndexGenOptions::default();
+    let run = |preview: PreviewOptions| {
+        IndexGen::preview_from_sources(InputSource::Bytes(fa_gz.clone()), InputSource::Bytes(gff.to_vec()), &options, &preview).unwrap()
+    };
+
+    let out = run(PreviewOptions { max_sequences: 2, ..PreviewOptions::new() });
+    assert!(out.is_partial());
+    let fai = String::from_utf8(out.artifacts()[1].1.to_vec()).unwrap();
+    assert_eq!(fai.lines().map(|l| l.split('\t').next().unwrap()).collect::<Vec<_>>(), ["a", "b"]);
+    let report = json::parse(&out.preview_json().unwrap()).unwrap();
+    assert_eq!(report["fasta"]["sequences"], 2);
+    assert_eq!(report["gff"]["sequences"], 2);
+    assert_eq!(report["stats"]["gff"]["records"], 3);
+    assert_eq!(out.warnings().count("preview_truncated"), 2);
+    let manifest = out.manifest();
+    assert_eq!(manifest["partial"], true);
+    assert_eq!(manifest["preview"]["gff"]["truncated"], true);
+
+    // A byte limit cuts back to the last whole line, and drops a header
+    // left without sequence.
+    let out = run(PreviewOptions { max_bytes: 20, max_sequences: 0 });
+    let report = json::parse(&out.preview_json().unwrap()).unwrap();
+    assert_eq!(report["fasta"]["bytes"], 12);
+    assert_eq!(report["fasta"]["sequences"], 1);
+    assert_eq!(report["gff"]["bytes"], 16);
+    assert_eq!(report["gff"]["sequences"], 0);
+
+    let out = run(PreviewOptions::new());
+    assert!(!out.is_partial());
+    assert_eq!(out.warnings().count("preview_truncated"), 0);
+    let full = IndexGen::from_bytes(fa, gff, &options).unwrap();
+    assert_eq!(out.artifacts().map(|(_, data)| data.to_vec()), full.artifacts().map(|(_, data)| data.to_vec()));
+    assert_eq!(out.manifest()["partial"], false);
+    assert!(full.manifest()["partial"].is_null());
+}
+
+/// CRLF FASTA: line widths count the `\r` like `samtools faidx`, unless
+/// `strip_cr` converts the line endings first.
+#[test]
+fn crlf_fasta_line_widths() {
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let fa = b">a desc\r\nACGT\r\nAC\r\n>b\r\nACG\r\n";
+    let gff = b"##gff-version 3\n";
+    let fai = |out: &IndexGen| String::from_utf8_lossy(out.artifacts()[1].1).into_owned();
+
+    let kept = IndexGen::from_bytes(fa, gff, &IndexGenOptions::default()).unwrap();
+    assert_eq!(fai(&kept), "a\t6\t9\t4\t6\nb\t3\t23\t3\t5\n");
+
+    let options = IndexGenOptions { strip_cr: true, ..Default::default() };
+    let stripped = IndexGen::from_bytes(fa, gff, &options).unwrap();
+    assert_eq!(fai(&stripped), "a\t6\t8\t4\t5\nb\t3\t19\t3\t4\n");
+    let mut text = Vec::new();
+    BgzfReader::new(stripped.artifacts()[0].1).read_to_end(&mut text).unwrap();
+    assert_eq!(text, b">a desc\nACGT\nAC\n>b\nACG\n");
+}
+
+/// The vectorised scans agree with the byte-at-a-time definitions at every
+/// length and alignment, including bytes above 0x7f.
+#[test]
+fn byte_scans_match_scalar() {
+    use mgnify_wasm::scan::{count_bases, count_graphic, find_byte, split_lines};
+
+    let mut data = read_fixture(FASTA_FIXTURE)[..4096].to_vec();
+    data.extend_from_slice(b"acgtunNRY \t\r\x7f\x80\xc7\xe1\xce\xff-*");
+    data.extend((0..=255u8).cycle().take(512));
+    for start in 0..40 {
+        for end in (start..data.len()).step_by(37) {
+            let bytes = &data[start..end];
+            assert_eq!(find_byte(bytes, b'\n'), bytes.iter().position(|&b| b == b'\n'));
+            assert_eq!(find_byte(bytes, 0xff), bytes.iter().position(|&b| b == 0xff));
+            assert_eq!(count_graphic(bytes), bytes.iter().filter(|b| b.is_ascii_graphic()).count());
+            let counts = count_bases(bytes);
+            let count = |set: &[u8]| bytes.iter().filter(|b| set.contains(&b.to_ascii_uppercase())).count() as u64;
+            assert_eq!((counts.gc, counts.at, counts.n), (count(b"GC"), count(b"ATU"), count(b"N")));
+            assert!(split_lines(bytes).eq(bytes.split(|&b| b == b'\n')));
+        }
+    }
+}
+
+/// The file-path/stdio front-end (also the WASI build) writes the same
+/// artifacts as `IndexGen` and the warnings JSON on stdout.
+#[test]
+fn cli_front_end_matches_index_gen() {
+    use std::process::{Command, Stdio};
+    use mgnify_wasm::{IndexFormat, IndexGen, IndexGenOptions};
+
+    let exe = env!("CARGO_BIN_EXE_mgnify-preprocess");
+    let dir = std::env::temp_dir().join(format!("mgnify-cli-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let prefix = dir.join("test");
+
+    // GFF3 on stdin, FASTA from a path.
+    let mut child = Command::new(exe)
+        .args(["--index-format", "both", FASTA_FIXTURE, "-"])
+        .arg(&prefix)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::io::Write::write_all(&mut child.stdin.take().unwrap(), &read_fixture(GFF_FIXTURE)).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    let options = IndexGenOptions { index_format: IndexFormat::Both, ..Default::default() };
+    let expected = IndexGen::from_bytes(&read_fixture(FASTA_FIXTURE), &read_fixture(GFF_FIXTURE), &options).unwrap();
+    for (name, data) in expected.artifacts() {
+        let path = format!("{}.{}", prefix.display(), name);
+        if data.is_empty() {
+            assert!(!std::path::Path::new(&path).exists(), "{} should not be written", name);
+        } else {
+            assert_eq!(fs::read(&path).unwrap(), data, "{} differs", name);
+        }
+    }
+    let warnings = json::parse(&String::from_utf8(output.stdout).unwrap()).unwrap();
+    assert_eq!(warnings, expected.warnings().to_json());
+
+    let usage = Command::new(exe).args(["--index-format", "bam", "a", "b", "c"]).output().unwrap();
+    assert_eq!(usage.status.code(), Some(2));
+    let missing = Command::new(exe).args(["missing.fa", GFF_FIXTURE]).arg(&prefix).output().unwrap();
+    assert_eq!(missing.status.code(), Some(1));
+    let _ = fs::remove_dir_all(&dir);
+}
+
+/// The Node.js byte entry points run the same pipeline, gzip included, and
+/// hand outputs over by name.
+#[test]
+fn buffer_inputs_and_named_artifacts() {
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let gz = |data: &[u8]| {
+        let mut out = Vec::new();
+        mgnify_wasm::htslib::gzip_compress_level(Cursor::new(data), &mut out, 6).unwrap();
+        out
+    };
+    let (fa, gff) = (read_fixture(FASTA_FIXTURE), read_fixture(GFF_FIXTURE));
+    let expected = IndexGen::from_bytes(&fa, &gff, &IndexGenOptions::default()).unwrap();
+    let mut out = IndexGen::from_buffers(gz(&fa), gff, None).unwrap();
+
+    for (name, data) in expected.artifacts() {
+        assert_eq!(out.take_artifact(name).as_deref(), Some(data), "{} differs", name);
+        assert_eq!(out.take_artifact(name), Some(Vec::new()), "{} taken twice", name);
+    }
+    assert_eq!(out.take_artifact("gff.bam"), None);
+}
+
+/// `dispose()` frees every buffer not taken yet, can be repeated, and
+/// leaves the accessors returning empty data; `memory_usage()` tracks it.
+#[test]
+fn dispose_frees_remaining_artifacts() {
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let mut out = IndexGen::from_bytes(&read_fixture(FASTA_FIXTURE), &read_fixture(GFF_FIXTURE), &IndexGenOptions::default()).unwrap();
+    let usage = json::parse(&out.memory_usage()).unwrap();
+    assert_eq!(usage["retained_bytes"].as_usize(), Some(out.retained_bytes()));
+    assert_eq!(out.retained_bytes(), out.artifacts().iter().map(|(_, data)| data.len()).sum::<usize>());
+    assert!(usage["wasm_memory_bytes"].is_null());
+    let bgz = out.take_artifact("fasta.bgz").unwrap();
+    assert!(bgz.len() <= usage["artifacts"]["fasta.bgz"].as_usize().unwrap());
+    assert_eq!(json::parse(&out.memory_usage()).unwrap()["artifacts"]["fasta.bgz"], 0);
+    assert!(out.retained_bytes() > 0);
+
+    let warnings = out.warnings_json();
+    out.dispose();
+    out.dispose();
+    assert_eq!(out.retained_bytes(), 0);
+    assert!(out.artifacts().iter().all(|(_, data)| data.is_empty()));
+    assert_eq!(out.take_artifact("gff.csi"), Some(Vec::new()));
+    assert_eq!(out.warnings_json(), warnings);
+}
+
+/// Block and line buffers go back to the pool after a run, and a second run
+/// on the same thread is served from it without new allocations.
+#[test]
+fn pipeline_reuses_pooled_buffers() {
+    use mgnify_wasm::htslib::{pool_stats, pooled_bytes};
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let (fa, gff) = (read_fixture(FASTA_FIXTURE), read_fixture(GFF_FIXTURE));
+    let options = IndexGenOptions { index_format: mgnify_wasm::IndexFormat::Both, ..IndexGenOptions::default().with_default_search_keys() };
+    let first = IndexGen::from_bytes(&fa, &gff, &options).unwrap();
+    let after_first = pool_stats();
+    assert!(after_first.reused > 0);
+    assert!(pooled_bytes() >= 2 * 0x10000);
+
+    let second = IndexGen::from_bytes(&fa, &gff, &options).unwrap();
+    let after_second = pool_stats();
+    assert_eq!(after_second.allocated, after_first.allocated);
+    assert!(after_second.reused > after_first.reused);
+    assert_eq!(first.artifacts(), second.artifacts());
+    assert!(json::parse(&second.memory_usage()).unwrap()["pooled_bytes"].as_usize().unwrap() > 0);
+}
+
+/// Builder setters land in the options digest exactly like the struct
+/// fields, and `run_buffers` is the plain pipeline.
+#[test]
+fn builder_sets_options() {
+    use mgnify_wasm::{ControlCharPolicy, FaiOffsets, IndexFormat, IndexGen, IndexGenBuilder, IndexGenOptions};
+
+    let builder = IndexGenBuilder::new()
+        .compression_level(9)
+        .index_format(IndexFormat::Both)
+        .control_chars(ControlCharPolicy::Strip)
+        .fai_offsets(FaiOffsets::Virtual)
+        .default_search_keys();
+    let options = IndexGenOptions {
+        compression_level: 9,
+        index_format: IndexFormat::Both,
+        control_chars: ControlCharPolicy::Strip,
+        fai_offsets: FaiOffsets::Virtual,
+        ..Default::default()
+    }
+    .with_default_search_keys();
+    assert_eq!(builder.options().digest(), options.digest());
+    assert_eq!(IndexGenBuilder::from_options(&options).options().digest(), options.digest());
+    assert_ne!(IndexGenBuilder::new().options().digest(), options.digest());
+
+    let (fa, gff) = (read_fixture(FASTA_FIXTURE), read_fixture(GFF_FIXTURE));
+    let built = builder.run_buffers(fa.clone(), gff.clone()).unwrap();
+    let expected = IndexGen::from_bytes(&fa, &gff, &options).unwrap();
+    assert_eq!(built.artifacts(), expected.artifacts());
+}
+
+/// Failures carry a stable code next to their message, and the codes are
+/// distinct strings.
+#[test]
+fn failures_carry_error_codes() {
+    use std::collections::HashSet;
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::fasta::Sequences;
+    use mgnify_wasm::htslib::parse_fai;
+    use mgnify_wasm::input::InputSource;
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let run = |fa: &[u8], gff: &[u8]| error_code(&IndexGen::from_bytes(fa, gff, &IndexGenOptions::default()).err().unwrap());
+    let gff = b"##gff-version 3\n";
+    assert_eq!(run(b">a\nAC\x00GT\n", gff), ErrorCode::ControlCharacter);
+    assert_eq!(run(b">a\nACGT\nAC\nACGT\n", gff), ErrorCode::FastaLineLength);
+    assert_eq!(run(b">a\nACGT\n", b"chr1\t.\tgene\tx\t4\t.\t+\t.\tID=a\n"), ErrorCode::GffParse);
+    assert_eq!(run(b">a\nACGT\n", b"\xff\n"), ErrorCode::GffEncoding);
+
+    let gz = InputSource::Bytes(b"\x1f\x8b\x08\x00garbage".to_vec()).read_all().unwrap_err();
+    assert_eq!(error_code(&gz), ErrorCode::InputDecompress);
+    assert_eq!(error_code(&Sequences::parse(b">a\nAC\n>a\nGT\n").err().unwrap()), ErrorCode::FastaDupName);
+    assert_eq!(error_code(&parse_fai(b"a\t4\n").unwrap_err()), ErrorCode::IndexParse);
+    let bgzf = compress_fasta();
+    let mut text = Vec::new();
+    let err = BgzfReader::new(&bgzf[..bgzf.len() - 40]).read_to_end(&mut text).unwrap_err();
+    assert_eq!(error_code(&err), ErrorCode::BgzfTruncated);
+    assert_eq!(error_code(&std::io::Error::other("plain")), ErrorCode::Io);
+
+    let codes: HashSet<&str> = ErrorCode::ALL.iter().map(|c| c.as_str()).collect();
+    assert_eq!(codes.len(), ErrorCode::ALL.len());
+}
+
+/// Inputs past the memory budget fail with `E_INPUT_TOO_LARGE` and a hint,
+/// whether they are too large as stored or only once decompressed; an input
+/// exactly on the budget is read, and a `.gzi` declaring more entries than it
+/// holds is rejected before allocating for them.
+#[test]
+fn inputs_over_budget_fail_with_guidance() {
+    use flate2::{write::GzEncoder, Compression};
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::htslib::parse_gzi;
+    use mgnify_wasm::input::InputSource;
+    use mgnify_wasm::limits::{read_to_end_within, Measure};
+    use std::io::Write;
+
+    let fasta = read_fixture(FASTA_FIXTURE);
+    let budget = fasta.len() as u64;
+    let exact = InputSource::Bytes(fasta.clone()).read_all_within(budget, "the FASTA input").unwrap();
+    assert_eq!(exact, fasta);
+
+    let err = InputSource::Path(FASTA_FIXTURE.into()).read_all_within(budget - 1, "the FASTA input").unwrap_err();
+    assert_eq!(error_code(&err), ErrorCode::InputTooLarge);
+    assert!(err.to_string().contains("the FASTA input") && err.to_string().contains("mgnify-preprocess CLI"), "{}", err);
+    assert!(err.to_string().contains("bytes as stored"), "{}", err);
+
+    let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+    gz.write_all(&b"N".repeat(fasta.len() * 2)).unwrap();
+    let gz = gz.finish().unwrap();
+    assert!((gz.len() as u64) < budget);
+    let err = InputSource::Bytes(gz).read_all_within(budget, "the FASTA input").unwrap_err();
+    assert_eq!(error_code(&err), ErrorCode::InputTooLarge);
+    assert!(err.to_string().contains("bytes decompressed"), "{}", err);
+
+    let mut out = Vec::new();
+    let err = read_to_end_within(&fasta[..], &mut out, 10, Measure::Stored, "standard input").unwrap_err();
+    assert_eq!(error_code(&err), ErrorCode::InputTooLarge);
+    assert!(out.len() <= 10);
+
+    let mut gzi = u64::MAX.to_le_bytes().to_vec();
+    gzi.extend_from_slice(&[0; 16]);
+    assert_eq!(error_code(&parse_gzi(&gzi).unwrap_err()), ErrorCode::IndexParse);
+}
+
+/// Range requests are built, read and retried as `fetch_bytes` and
+/// `fetch_range` expect, without a network.
+#[test]
+fn fetch_ranges_are_read_and_retried() {
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::input::{download_complete, range_header, range_reply, retry_delays, FetchOptions, RangeReply};
+
+    assert_eq!(range_header(0, Some(1024)), "bytes=0-1023");
+    assert_eq!(range_header(100, Some(0)), "bytes=100-100");
+    assert_eq!(range_header(100, None), "bytes=100-");
+    assert_eq!(range_header(u64::MAX, Some(8)), format!("bytes={}-{}", u64::MAX, u64::MAX));
+
+    let url = "https://example.org/genome.fa";
+    assert_eq!(range_reply(url, 206, "", Some("bytes 0-1023/5000")).unwrap(), RangeReply::Partial { total: Some(5000) });
+    assert_eq!(range_reply(url, 206, "", Some("bytes 0-1023/*")).unwrap(), RangeReply::Partial { total: None });
+    assert_eq!(range_reply(url, 206, "", None).unwrap(), RangeReply::Partial { total: None });
+    assert_eq!(range_reply(url, 200, "OK", None).unwrap(), RangeReply::Whole);
+    assert_eq!(range_reply(url, 416, "Range Not Satisfiable", Some("bytes */5000")).unwrap(), RangeReply::PastEnd);
+    let err = range_reply(url, 503, "Service Unavailable", None).unwrap_err();
+    assert_eq!(error_code(&err), ErrorCode::Fetch);
+    assert_eq!(err.to_string(), "https://example.org/genome.fa: HTTP 503 Service Unavailable");
+
+    let opts = FetchOptions { chunk_size: 1024, max_retries: 3, retry_delay_ms: 500 };
+    assert_eq!(retry_delays(&opts).collect::<Vec<_>>(), [500, 1000, 2000]);
+    assert_eq!(retry_delays(&FetchOptions { max_retries: 0, ..opts }).count(), 0);
+    assert_eq!(retry_delays(&FetchOptions { retry_delay_ms: u32::MAX, ..opts }).last(), Some(u32::MAX));
+
+    // A short chunk ends a download of unknown size, but not one whose
+    // size is known; an empty one before that size fails.
+    assert!(download_complete(url, 1500, 476, 1024, None).unwrap());
+    assert!(!download_complete(url, 2048, 1024, 1024, None).unwrap());
+    assert!(!download_complete(url, 1500, 476, 1024, Some(5000)).unwrap());
+    assert!(download_complete(url, 5000, 904, 1024, Some(5000)).unwrap());
+    assert_eq!(error_code(&download_complete(url, 1500, 0, 1024, Some(5000)).unwrap_err()), ErrorCode::Fetch);
+}
+
+// ---------------------------------------------------------------------------
+// GFF utilities
+// ---------------------------------------------------------------------------
+
+/// Removing, editing and adding features is reported as such; everything
+/// else is unchanged.
+#[test]
+fn gff_diff_reports_feature_changes() {
+    let old = fs::read_to_string(GFF_FIXTURE).unwrap();
+    let removed = "FM211187\tEMBL\tsequence_feature\t648\t671\t0.000\t+\t.\tID=\"FM211187.4\";inference=\"protein motif:ProSite:PS00017\"\n";
+    let edited_from = "FM211187\tEMBL\tsequence_feature\t1419\t1478\t";
+    let edited_to = "FM211187\tEMBL\tsequence_feature\t1419\t1490\t";
+    let added = "FM211187\tmanual\tgene\t5\t50\t.\t-\t.\tID=new_gene";
+    assert!(old.contains(removed) && old.contains(edited_from));
+
+    // Lines after the embedded ##FASTA section are not features, so insert before it.
+    let new = old.replace(removed, "").replace(edited_from, edited_to)
+        .replacen("##FASTA", &format!("{}\n##FASTA", added), 1);
+    let diff = mgnify_wasm::gff::diff_gff(&old, &new);
+
+    assert_eq!(diff.added, vec![added.to_string()]);
+    assert_eq!(diff.removed, vec![removed.trim_end().to_string()]);
+    assert_eq!(diff.changed.len(), 1);
+    assert_eq!(diff.changed[0].2, vec!["end"]);
+    let n_features = mgnify_wasm::gff::data_lines(&old).count();
+    assert_eq!(diff.unchanged, n_features - 2);
+
+    let report = json::parse(&mgnify_wasm::gff::gff_diff(&old, &old)).unwrap();
+    assert_eq!(report["summary"]["unchanged"], n_features);
+    assert_eq!(report["summary"]["changed"], 0);
+}
+
+/// Interval tree queries agree with a brute-force scan of the features.
+#[test]
+fn feature_tree_matches_linear_scan() {
+    use mgnify_wasm::gff::{data_lines, FeatureTree, GffFields};
+
+    let gff = String::from_utf8(read_maybe_gz(BU_GFF_FIXTURE)).unwrap();
+    let tree = FeatureTree::build(&gff);
+    let features: Vec<GffFields> = data_lines(&gff).filter_map(GffFields::parse).collect();
+    assert_eq!(tree.len(), features.len());
+    let seq = features[0].seqid;
+
+    for &(start, end) in &[(1u64, 1u64), (5_000, 5_000), (1_000_000, 1_250_000), (u32::MAX as u64, u32::MAX as u64)] {
+        let mut got = tree.overlaps(seq, start, end);
+        let mut expected: Vec<String> = data_lines(&gff)
+            .filter(|l| GffFields::parse(l).is_some_and(|f| f.seqid == seq && f.start <= end && f.end >= start))
+            .map(str::to_owned)
+            .collect();
+        got.sort();
+        expected.sort();
+        assert_eq!(got, expected, "overlaps {}-{}", start, end);
+    }
+    assert!(tree.overlaps("no-such-seq", 1, 10).is_empty());
+
+    // Between two features the closer one wins; inside one, it is returned.
+    let on_seq: Vec<&GffFields> = features.iter().filter(|f| f.seqid == seq).collect();
+    let gap = on_seq
+        .windows(2)
+        .find(|w| w[1].start > on_seq.iter().filter(|f| f.start <= w[0].start).map(|f| f.end).max().unwrap() + 10)
+        .expect("fixture has an intergenic gap");
+    let nearest = tree.nearest(seq, gap[1].start - 1);
+    assert!(nearest.iter().all(|l| GffFields::parse(l).unwrap().start == gap[1].start), "{:?}", nearest);
+    assert!(!nearest.is_empty());
+    assert_eq!(tree.nearest(seq, gap[1].start + 1).len(), tree.overlaps(seq, gap[1].start + 1, gap[1].start + 1).len());
+}
+
+/// Short records are padded to nine columns; records without seqid..end or
+/// with a non-numeric coordinate fail the run or, with the "drop" policy,
+/// are removed, each with its line number.
+#[test]
+fn short_gff_records_are_padded_and_malformed_ones_follow_the_policy() {
+    use std::io::Read;
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::gff::normalize_columns;
+    use mgnify_wasm::htslib::BgzfReader;
+    use mgnify_wasm::warnings::Warnings;
+    use mgnify_wasm::{IndexGen, IndexGenOptions, MalformedRecordPolicy};
+
+    let gff = "##gff-version 3\n\
+        chr1\t.\tgene\t20\t30\r\n\
+        chr1\t.\tgene\t1\t10\t\t+\t\tID=a\n\
+        chr1\tgene\t5\n\
+        \t \n\
+        chr1\t.\tgene\t.\t9\t.\t+\t.\tID=b\n\
+        chr1\t.\tgene\t2\t3\t.\t-\t.\tID=c\n\
+        ##FASTA\n>chr1\nACGT\n";
+
+    let mut warnings = Warnings::default();
+    let normalized = normalize_columns(gff, MalformedRecordPolicy::Drop, &mut warnings).unwrap();
+    assert_eq!(
+        normalized,
+        "##gff-version 3\n\
+         chr1\t.\tgene\t20\t30\t.\t.\t.\t.\r\n\
+         chr1\t.\tgene\t1\t10\t.\t+\t.\tID=a\n\
+         chr1\t.\tgene\t2\t3\t.\t-\t.\tID=c\n\
+         ##FASTA\n>chr1\nACGT\n"
+    );
+    let codes: Vec<(&str, Option<usize>)> = warnings.warnings.iter().map(|w| (w.code, w.line)).collect();
+    assert_eq!(codes, [("columns_padded", Some(2)), ("columns_padded", Some(3)), ("record_dropped", Some(4)), ("record_dropped", Some(6))]);
+
+    // Complete input is borrowed as it is.
+    let complete = "chr1\t.\tgene\t2\t3\t.\t-\t.\tID=c\n";
+    assert!(matches!(normalize_columns(complete, MalformedRecordPolicy::Error, &mut Warnings::default()).unwrap(), std::borrow::Cow::Borrowed(_)));
+
+    let err = normalize_columns(gff, MalformedRecordPolicy::Error, &mut Warnings::default()).unwrap_err();
+    assert_eq!(error_code(&err), ErrorCode::GffParse);
+    assert!(err.to_string().contains("line 4"), "{}", err);
+
+    // A full run indexes the padded records and drops the others.
+    let fa = b">chr1\nACGTACGTACGTACGTACGTACGTACGTACGT\n";
+    let options = IndexGenOptions { malformed_records: MalformedRecordPolicy::Drop, ..Default::default() };
+    let built = IndexGen::from_bytes(fa, gff.as_bytes(), &options).unwrap();
+    let mut text = String::new();
+    BgzfReader::new(built.artifacts()[3].1).read_to_string(&mut text).unwrap();
+    let records: Vec<&str> = text.lines().filter(|l| !l.starts_with('#')).map(|l| l.split('\t').nth(3).unwrap()).collect();
+    assert_eq!(records, ["1", "2", "20"]);
+    assert!(text.lines().all(|l| l.starts_with('#') || l.split('\t').count() == 9));
+    assert_eq!(built.warnings().warnings.iter().filter(|w| w.code == "record_dropped").count(), 2);
+    assert_eq!(error_code(&IndexGen::from_bytes(fa, gff.as_bytes(), &IndexGenOptions::default()).err().unwrap()), ErrorCode::GffParse);
+}
+
+/// Features crossing the origin of a circular sequence are split at the
+/// sequence end, clamped to it or rejected; other sequences and features
+/// are left alone.
+#[test]
+fn origin_crossing_features_follow_the_circular_policy() {
+    use std::io::Read;
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::gff::{circular_lengths, normalize_circular};
+    use mgnify_wasm::htslib::BgzfReader;
+    use mgnify_wasm::warnings::Warnings;
+    use mgnify_wasm::{CircularPolicy, IndexGen, IndexGenOptions};
+
+    let gff = "##gff-version 3\n\
+        ##sequence-region p1 1 40\n\
+        p1\t.\tregion\t1\t40\t.\t+\t.\tID=p1;Is_circular=true\n\
+        p1\t.\tgene\t35\t45\t.\t+\t.\tID=a\n\
+        p1\t.\tgene\t38\t3\t.\t-\t.\tID=b\n\
+        p1\t.\tgene\t5\t9\t.\t+\t.\tID=c\n\
+        chr\t.\tgene\t30\t20\t.\t+\t.\tID=d\n";
+    assert_eq!(circular_lengths(gff).into_iter().collect::<Vec<_>>(), [("p1", 40)]);
+
+    let mut warnings = Warnings::default();
+    let split = normalize_circular(gff, CircularPolicy::Split, &mut warnings).unwrap();
+    let records: Vec<(&str, &str, &str)> = split
+        .lines()
+        .filter(|l| !l.starts_with('#'))
+        .map(|l| {
+            let cols: Vec<&str> = l.split('\t').collect();
+            (cols[3], cols[4], cols[8])
+        })
+        .collect();
+    assert_eq!(
+        records,
+        [
+            ("1", "40", "ID=p1;Is_circular=true"),
+            ("35", "40", "ID=a"),
+            ("1", "5", "ID=a"),
+            ("38", "40", "ID=b"),
+            ("1", "3", "ID=b"),
+            ("5", "9", "ID=c"),
+            ("30", "20", "ID=d"),
+        ]
+    );
+    let codes: Vec<(&str, Option<usize>)> = warnings.warnings.iter().map(|w| (w.code, w.line)).collect();
+    assert_eq!(codes, [("origin_split", Some(4)), ("origin_split", Some(5))]);
+
+    let mut warnings = Warnings::default();
+    let clamped = normalize_circular(gff, CircularPolicy::Clamp, &mut warnings).unwrap();
+    assert!(clamped.contains("p1\t.\tgene\t35\t40\t.\t+\t.\tID=a\np1\t.\tgene\t38\t40\t.\t-\t.\tID=b\n"), "{}", clamped);
+    assert_eq!(warnings.count("origin_clamped"), 2);
+
+    let err = normalize_circular(gff, CircularPolicy::Error, &mut Warnings::default()).unwrap_err();
+    assert_eq!(error_code(&err), ErrorCode::GffParse);
+    assert!(err.to_string().contains("line 4"), "{}", err);
+
+    // A full run indexes the split parts, and the whitelist keeps Is_circular.
+    let fa = b">p1\nACGTACGTACGTACGTACGTACGTACGTACGTACGTACGT\n>chr\nACGTACGTACGTACGTACGTACGTACGTACGT\n";
+    let options = IndexGenOptions { attribute_whitelist: vec!["ID".into()], ..Default::default() };
+    let built = IndexGen::from_bytes(fa, gff.as_bytes(), &options).unwrap();
+    let mut text = String::new();
+    BgzfReader::new(built.artifacts()[3].1).read_to_string(&mut text).unwrap();
+    assert!(text.contains("ID=p1;Is_circular=true"), "{}", text);
+    assert_eq!(text.lines().filter(|l| l.ends_with("ID=a")).count(), 2);
+    assert_eq!(built.warnings().count("origin_split"), 2);
+
+    // The dry run reports crossing features as warnings unless they fail.
+    let circular_only = gff.replace("chr\t.\tgene\t30\t20\t.\t+\t.\tID=d\n", "");
+    let report = IndexGen::dry_run_bytes(fa, circular_only.as_bytes(), &IndexGenOptions::default());
+    assert_eq!(report["valid"], true, "{}", report.dump());
+    assert_eq!(report["validation"]["counts"]["crosses_origin"], 2);
+    let options = IndexGenOptions { circular_features: CircularPolicy::Error, ..Default::default() };
+    assert_eq!(IndexGen::dry_run_bytes(fa, circular_only.as_bytes(), &options)["valid"], false);
+}
+
+/// Transform specs replace the default steps in order, bad specs are
+/// option errors, and Rust callers can add their own steps.
+#[test]
+fn transform_pipelines_follow_the_specs() {
+    use std::borrow::Cow;
+    use std::io::Read;
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::htslib::BgzfReader;
+    use mgnify_wasm::transform::{Pipeline, Pipelines, Transform, TransformContext};
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let fa = b">1 chromosome\nACGTACGT\n>2\nACGTACGT\n>p\nACGT\n";
+    let gff = "##gff-version 3\n##sequence-region 1 1 8\n\
+               1\t.\tregion\t1\t8\t.\t+\t.\tID=r1\n\
+               1\t.\tgene\t2\t7\t.\t+\t.\tID=g1\n\
+               2\t.\tgene\t1\t4\t.\t-\t.\tID=g2\n";
+    let gff_text = |out: &IndexGen| {
+        let mut text = String::new();
+        BgzfReader::new(out.artifacts()[3].1).read_to_string(&mut text).unwrap();
+        text
+    };
+    let defaults = IndexGenOptions::default();
+    assert_eq!(Pipeline::gff(&defaults).unwrap().names(), ["gtf_to_gff3", "normalize_columns", "normalize_circular"]);
+    assert!(Pipeline::fasta(&defaults).unwrap().names().is_empty());
+    let rewrap = IndexGenOptions { rewrap_irregular: true, ..Default::default() };
+    assert_eq!(Pipeline::fasta(&rewrap).unwrap().names(), ["rewrap"]);
+
+    let options = IndexGenOptions {
+        fasta_transforms: vec!["filter_sequences:drop=p".into(), "rename_seqids:1=chr1,2=chr2".into()],
+        gff_transforms: vec!["rename_seqids:1=chr1,2=chr2".into(), "filter_types:drop=region".into()],
+        ..Default::default()
+    };
+    assert_ne!(options.digest(), defaults.digest());
+    let out = IndexGen::from_bytes(fa, gff.as_bytes(), &options).unwrap();
+    let fai = String::from_utf8(out.artifacts()[1].1.to_vec()).unwrap();
+    assert_eq!(fai.lines().map(|l| l.split('\t').next().unwrap()).collect::<Vec<_>>(), ["chr1", "chr2"]);
+    let text = gff_text(&out);
+    assert!(text.contains("##sequence-region chr1 1 8\n"));
+    let records: Vec<(&str, &str)> = text.lines().filter(|l| !l.starts_with('#')).map(|l| (l.split('\t').next().unwrap(), l.split('\t').nth(2).unwrap())).collect();
+    assert_eq!(records, [("chr1", "gene"), ("chr2", "gene")]);
+
+    for (fasta, gff_spec) in [(vec!["reverse".into()], vec![]), (vec![], vec!["filter_types:types=gene".into()]), (vec![], vec!["rename_seqids".into()])] {
+        let bad = IndexGenOptions { fasta_transforms: fasta, gff_transforms: gff_spec, ..Default::default() };
+        let err = IndexGen::from_bytes(fa, gff.as_bytes(), &bad).err().unwrap();
+        assert_eq!(error_code(&err), ErrorCode::InvalidOption);
+    }
+    let bad = IndexGenOptions { gff_transforms: vec!["nope".into()], ..Default::default() };
+    let report = IndexGen::dry_run_bytes(fa, gff.as_bytes(), &bad);
+    assert_eq!(report["warnings"]["counts"]["gff_transforms_ignored"], 1);
+
+    struct TagLocus;
+    impl Transform<str> for TagLocus {
+        fn name(&self) -> &str {
+            "tag_locus"
+        }
+        fn apply<'t>(&self, text: Cow<'t, str>, _: &mut TransformContext) -> std::io::Result<Cow<'t, str>> {
+            Ok(Cow::Owned(text.replace("ID=g", "ID=SITE_g")))
+        }
+    }
+    let mut pipelines = Pipelines::from_options(&defaults).unwrap();
+    pipelines.gff.push(TagLocus);
+    let out = IndexGen::from_bytes_with(fa, gff.as_bytes(), &defaults, &pipelines).unwrap();
+    assert_eq!(gff_text(&out).matches("ID=SITE_g").count(), 2);
+}
+
+// ---------------------------------------------------------------------------
+// Flat-file and JSON export
+// ---------------------------------------------------------------------------
+
+/// Features of an EMBL entry as `(key line, qualifier → values)`, with wrapped
+/// continuation lines joined back together.
+fn embl_features(entry: &str) -> Vec<(String, Vec<(String, String)>)> {
+    let mut features: Vec<(String, Vec<(String, String)>)> = Vec::new();
+    for line in entry.lines().filter_map(|l| l.strip_prefix("FT   ")) {
+        if let Some(q) = line.trim_start().strip_prefix('/').filter(|_| line.starts_with(' ')) {
+            let (k, v) = q.split_once('=').unwrap_or((q, ""));
+            features.last_mut().unwrap().1.push((k.to_owned(), v.to_owned()));
+        } else if line.starts_with(' ') {
+            let (key, quals) = features.last_mut().unwrap();
+            let cont = line.trim_start();
+            match quals.last_mut() {
+                // Translations are hard-wrapped, free text is wrapped at spaces.
+                Some((k, v)) if k == "translation" => v.push_str(cont),
+                Some((_, v)) => { v.push(' '); v.push_str(cont) }
+                None => key.push_str(cont),
+            }
+        } else {
+            features.push((line.to_owned(), Vec::new()));
+        }
+    }
+    features
+}
+
+/// Packed sequences decode to exactly the bytes parsed (case, `N` runs and
+/// IUPAC codes included) while holding a fraction of them.
+#[test]
+fn packed_sequences_round_trip() {
+    use mgnify_wasm::fasta::Sequences;
+
+    let fa = b">dna\nACGTNNNNNacgtnnRYacgT\n>iupac\nRYSWKMBDHVNRYSWKMBDHV\n>protein\nMKV*LLA-\n";
+    let seqs = Sequences::parse(fa).unwrap();
+    assert_eq!(seqs.get("dna").unwrap(), b"ACGTNNNNNacgtnnRYacgT");
+    assert_eq!(seqs.get("iupac").unwrap(), b"RYSWKMBDHVNRYSWKMBDHV");
+    assert_eq!(seqs.get("protein").unwrap(), b"MKV*LLA-");
+    assert_eq!(seqs.region("dna", 3, 12).unwrap(), b"GTNNNNNacg");
+    assert_eq!(seqs.region("dna", 21, 21).unwrap(), b"T");
+    assert_eq!(seqs.region("dna", 1, 22), None);
+    assert_eq!(seqs.seq_len("iupac"), Some(21));
+
+    let raw = read_fixture(FASTA_FIXTURE);
+    let seqs = Sequences::parse(&raw).unwrap();
+    let mut bases = 0;
+    for name in seqs.names() {
+        let seq = seqs.get(name).unwrap();
+        bases += seq.len();
+        let mid = seq.len() as u64 / 2;
+        assert_eq!(seqs.region(name, mid, mid + 2).as_deref(), seq.get(mid as usize - 1..mid as usize + 2));
+    }
+    let raw_bases = raw
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.starts_with(b">"))
+        .map(|line| line.iter().filter(|b| b.is_ascii_graphic()).count())
+        .sum::<usize>();
+    assert_eq!(bases, raw_bases);
+    assert!(seqs.resident_bytes() * 3 < bases, "{} bytes for {} bases", seqs.resident_bytes(), bases);
+}
+
+/// Every CDS translation recomputed from the FASTA matches the one the
+/// annotation already carries.
+#[test]
+fn embl_export_translates_cds() {
+    let gff = fs::read_to_string(GFF_FIXTURE).unwrap();
+    let seqs = mgnify_wasm::fasta::Sequences::parse(&read_fixture(FASTA_FIXTURE)).unwrap();
+    let embl = mgnify_wasm::export::embl_feature_table(&gff, &seqs).unwrap();
+
+    let expected: std::collections::HashMap<&str, &str> = mgnify_wasm::gff::data_lines(&gff)
+        .filter_map(mgnify_wasm::gff::GffFields::parse)
+        .filter(|f| f.ftype == "CDS")
+        .filter_map(|f| Some((f.attribute("locus_tag")?.trim_matches('"'), f.attribute("translation")?.trim_matches('"'))))
+        .collect();
+    let mut checked = 0;
+    for (_, quals) in embl_features(&embl).into_iter().filter(|(key, _)| key.starts_with("CDS ")) {
+        let get = |name: &str| quals.iter().find(|(k, _)| k == name).map(|(_, v)| v.trim_matches('"').to_owned());
+        let (Some(tag), Some(aa)) = (get("locus_tag"), get("translation")) else { continue };
+        assert_eq!(expected.get(tag.as_str()).copied(), Some(aa.as_str()), "translation of {}", tag);
+        checked += 1;
+    }
+    assert_eq!(checked, expected.len());
+}
+
+/// Lines sharing an `ID` become one `join(...)`, complemented on the minus
+/// strand and translated 5' to 3'.
+#[test]
+fn embl_export_joins_multi_part_features() {
+    let seqs = mgnify_wasm::fasta::Sequences::parse(b">chr test\nCCCCCTTAAGC\nGGGGCATCC\n").unwrap();
+    let gff = "chr\tx\tCDS\t16\t18\t.\t-\t0\tID=cds1;Name=x;product=two%2C parts;ribosomal_slippage\n\
+               chr\tx\tCDS\t6\t11\t.\t-\t0\tID=cds1;Name=x;product=two%2C parts;ribosomal_slippage\n";
+    let embl = mgnify_wasm::export::embl_feature_table(gff, &seqs).unwrap();
+    let features = embl_features(&embl);
+
+    assert!(embl.starts_with("ID   chr\n") && embl.ends_with("//\n"));
+    assert_eq!(features[0].0, "source          1..20");
+    assert_eq!(features[1].0, "CDS             complement(join(6..11,16..18))");
+    assert_eq!(features[1].1, vec![
+        ("product".to_string(), "\"two, parts\"".to_string()),
+        ("ribosomal_slippage".to_string(), String::new()),
+        ("translation".to_string(), "\"MA\"".to_string()),
+    ]);
+}
+
+/// Parts of a trans-spliced CDS are joined in input order, each on its own
+/// strand; multi-part features count, and are found, once, and collapse
+/// into one NDJSON record spanning their parts.
+#[test]
+fn multi_part_features_are_handled_as_one_feature() {
+    use mgnify_wasm::export::ndjson_records;
+    use mgnify_wasm::search::SearchIndex;
+    use mgnify_wasm::stats::GffStats;
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let fa = b">chr\nATGCCCCCCTTAAAACCCCCTGACCCCCCCCCCCCCCCCATGAAACCCCCCCCCCCCCCC\n";
+    let gff = "chr\tx\tCDS\t21\t23\t.\t+\t0\tID=c1\n\
+               chr\tx\tCDS\t1\t3\t.\t+\t0\tID=c1\n\
+               chr\tx\tCDS\t40\t45\t.\t+\t0\tID=t1;exception=trans-splicing\n\
+               chr\tx\tCDS\t10\t15\t.\t-\t0\tID=t1;exception=trans-splicing\n\
+               chr\tx\tgene\t30\t50\t.\t+\t.\tName=g\n";
+
+    let seqs = mgnify_wasm::fasta::Sequences::parse(fa).unwrap();
+    let embl = mgnify_wasm::export::embl_feature_table(gff, &seqs).unwrap();
+    let features = embl_features(&embl);
+    assert_eq!(features[1].0, "CDS             join(1..3,21..23)");
+    assert_eq!(features[1].1.last().unwrap().1, "\"M\"");
+    assert_eq!(features[2].0, "CDS             join(40..45,complement(10..15))");
+    assert_eq!(features[2].1.last().unwrap().1, "\"MKF\"");
+
+    let stats = GffStats::from_gff(gff);
+    assert_eq!((stats.records, stats.features, stats.multi_part_features), (5, 3, 2));
+
+    let options = IndexGenOptions { search_keys: vec!["ID".into()], ..Default::default() };
+    let built = IndexGen::from_bytes(fa, gff.as_bytes(), &options).unwrap();
+    let index = SearchIndex::parse(built.artifacts()[6].1).unwrap();
+    assert_eq!(index.find("t1").len(), 1);
+    assert_eq!(index.find("c1").len(), 1);
+
+    assert_eq!(ndjson_records(gff, false, false).lines().count(), 5);
+    let collapsed: Vec<json::JsonValue> = ndjson_records(gff, true, true)
+        .lines()
+        .map(|l| json::parse(l.splitn(4, '\t').nth(3).unwrap()).unwrap())
+        .collect();
+    let spans: Vec<(u64, u64, String)> =
+        collapsed.iter().map(|r| (r["start"].as_u64().unwrap(), r["end"].as_u64().unwrap(), r["parts"].dump())).collect();
+    assert_eq!(
+        spans,
+        [(1, 23, "[[1,3],[21,23]]".to_owned()), (10, 45, "[[40,45],[10,15]]".to_owned()), (30, 50, "[[30,50]]".to_owned())]
+    );
+}
+
+/// Coordinate-prefixed NDJSON is indexed on its embedded columns: a CSI query
+/// returns exactly the records overlapping the region, each a JSON feature.
+#[test]
+fn ndjson_export_indexed_on_coordinates() {
+    use mgnify_wasm::export::{ndjson_bgzf, ndjson_records};
+    use mgnify_wasm::htslib::CsiIndex;
+
+    let gff = String::from_utf8(read_maybe_gz(BU_GFF_FIXTURE)).unwrap();
+    let records = ndjson_records(&gff, true, false);
+    assert_eq!(records.lines().count(), mgnify_wasm::gff::data_lines(&gff).count());
+
+    let (bgz, csi) = ndjson_bgzf(&gff, 6, false).expect("NDJSON export failed");
+    let index = CsiIndex::from_bgzf(&csi).expect("CSI parse failed");
+    let seq = index.names[0].clone();
+    let (beg, end) = (1_000_000u64, 1_250_000u64);
+
+    let overlaps = |line: &&str| {
+        let f: Vec<&str> = line.splitn(4, '\t').collect();
+        f[0] == seq && f[1].parse::<u64>().unwrap() - 1 < end && f[2].parse::<u64>().unwrap() > beg
+    };
+    let expected: Vec<&str> = records.lines().filter(overlaps).collect();
+
+    let mut got = Vec::new();
+    for (s, e) in index.query(&seq, beg, end) {
+        let mut reader = BgzfReader::new(Cursor::new(&bgz[(s >> 16) as usize..]));
+        std::io::copy(&mut (&mut reader).take(s & 0xffff), &mut std::io::sink()).unwrap();
+        let mut line = Vec::new();
+        while reader.virtual_offset() + (s >> 16 << 16) < e {
+            line.clear();
+            if reader.read_line(&mut line).unwrap().0 == 0 {
+                break;
+            }
+            got.push(String::from_utf8(line.clone()).unwrap().trim_end().to_owned());
+        }
+    }
+    got.retain(|l| overlaps(&l.as_str()));
+    assert!(!expected.is_empty());
+    assert_eq!(got, expected);
+
+    let first = json::parse(expected[0].splitn(4, '\t').nth(3).unwrap()).unwrap();
+    assert_eq!(first["seqid"], seq.as_str());
+    assert!(first["attributes"]["ID"].is_array());
+}
+
+/// The JBrowse 2 config points at the outputs under the chosen URL, uses
+/// the index the run wrote, aliases GFF3 seqids that spell a FASTA name
+/// differently and carries the GFF3 stats.
+#[test]
+fn jbrowse_config_references_outputs() {
+    use mgnify_wasm::export::jbrowse_config;
+    use mgnify_wasm::{IndexFormat, IndexGen, IndexGenOptions, JBrowseOptions};
+
+    let (fa, gff) = (read_fixture(FASTA_FIXTURE), read_fixture(GFF_FIXTURE));
+    let options = IndexGenOptions { index_format: IndexFormat::Tbi, ..Default::default() };
+    let out = IndexGen::from_bytes(&fa, &gff, &options).unwrap();
+    let jb = JBrowseOptions { base_url: "https://example.org/data/".into(), ..JBrowseOptions::new("spn".into()) };
+    let config = out.jbrowse_config_json(&jb).unwrap();
+
+    let assembly = &config["assemblies"][0];
+    assert_eq!(assembly["name"], "spn");
+    assert_eq!(assembly["sequence"]["adapter"]["gziLocation"]["uri"], "https://example.org/data/spn.fasta.gzi");
+    assert!(assembly["refNameAliases"].is_null());
+    let track = &config["tracks"][0];
+    assert_eq!(track["assemblyNames"][0], "spn");
+    assert_eq!(track["adapter"]["type"], "Gff3TabixAdapter");
+    assert_eq!(track["adapter"]["index"]["indexType"], "TBI");
+    assert_eq!(track["adapter"]["index"]["location"]["uri"], "https://example.org/data/spn.gff.tbi");
+    assert_eq!(track["metadata"]["types"]["CDS"].as_usize(), Some(2232));
+
+    let fai = b"chr1\t100\t6\t60\t61\nNC_000913.3\t100\t200\t60\t61\nplasmid\t10\t400\t60\t61\n";
+    let gff = "1\t.\tgene\t1\t10\t.\t+\t.\tID=a\nNC_000913\t.\tgene\t1\t10\t.\t+\t.\tID=b\nplasmid\t.\tgene\t1\t5\t.\t+\t.\tID=c\n";
+    let config = jbrowse_config(&JBrowseOptions::new("g".into()), fai, Some(gff), None).unwrap();
+    let aliases = &config["assemblies"][0]["refNameAliases"]["adapter"]["features"];
+    assert_eq!(aliases.len(), 2);
+    assert_eq!((aliases[0]["refName"].as_str(), aliases[0]["aliases"].dump()), (Some("chr1"), r#"["1"]"#.to_owned()));
+    assert_eq!((aliases[1]["refName"].as_str(), aliases[1]["aliases"].dump()), (Some("NC_000913.3"), r#"["NC_000913"]"#.to_owned()));
+    assert_eq!(config["tracks"][0]["adapter"]["gffLocation"]["uri"], "g.gff.bgz");
+    assert!(jbrowse_config(&JBrowseOptions::new("g".into()), b"", Some(gff), None).is_err());
+}
+
+/// The igv.js session fills the URL template for every output, uses the
+/// index the run wrote and opens on the first sequence.
+#[test]
+fn igv_session_references_outputs() {
+    use mgnify_wasm::export::igv_session;
+    use mgnify_wasm::{IgvOptions, IndexGen, IndexGenOptions};
+
+    let out = IndexGen::from_bytes(&read_fixture(FASTA_FIXTURE), &read_fixture(GFF_FIXTURE), &IndexGenOptions::default()).unwrap();
+    let igv = IgvOptions { url_template: "/files?id={prefix}&f={artifact}".into(), ..IgvOptions::new("spn".into()) };
+    let session = out.igv_session_json(&igv).unwrap();
+    assert_eq!(session["reference"]["name"], "spn");
+    assert_eq!(session["reference"]["fastaURL"], "/files?id=spn&f=fasta.bgz");
+    assert_eq!(session["reference"]["indexURL"], "/files?id=spn&f=fasta.fai");
+    assert_eq!(session["reference"]["compressedIndexURL"], "/files?id=spn&f=fasta.gzi");
+    assert_eq!(session["locus"], "FM211187");
+    let track = &session["tracks"][0];
+    assert_eq!((track["format"].as_str(), track["indexed"].as_bool()), (Some("gff3"), Some(true)));
+    assert_eq!(track["indexURL"], "/files?id=spn&f=gff.csi");
+
+    let fai = b"c1\t10\t4\t10\t11\n";
+    let plain = igv_session(&IgvOptions::new("g".into()), fai, None).unwrap();
+    assert_eq!(plain["tracks"][0]["url"], "g.gff.bgz");
+    assert!(plain["tracks"][0]["indexURL"].is_null());
+    let fixed = IgvOptions { url_template: "https://example.org/data".into(), ..IgvOptions::new("g".into()) };
+    assert!(igv_session(&fixed, fai, None).is_err());
+}
+
+/// Feature types get their category's colour and priority, sources a
+/// palette colour by frequency, and the result's styles match the text's.
+#[test]
+fn track_styles_follow_types_and_sources() {
+    use mgnify_wasm::export::gff_track_styles;
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let gff = "##gff-version 3\n\
+               c1\tProdigal\tCDS\t1\t90\t.\t+\t0\tID=c1\n\
+               c1\tProdigal\tCDS\t100\t190\t.\t+\t0\tID=c2\n\
+               c1\tAragorn\ttRNA\t200\t275\t.\t-\t.\tID=t1\n\
+               c1\tminced\tCRISPR\t300\t400\t.\t.\t.\tID=cr1\n\
+               c1\tISEScan\tinsertion_sequence\t500\t900\t.\t+\t.\tID=is1\n\
+               c1\tcustom\tweird_thing\t950\t960\t.\t+\t.\tID=w1\n";
+    let styles = json::parse(&gff_track_styles(gff)).unwrap();
+    let style = |ftype: &str| (styles["types"][ftype]["category"].to_string(), styles["types"][ftype]["priority"].as_u32());
+    assert_eq!(style("CDS"), ("coding".into(), Some(1)));
+    assert_eq!(style("tRNA"), ("trna".into(), Some(2)));
+    assert_eq!(style("CRISPR"), ("crispr".into(), Some(2)));
+    assert_eq!(style("insertion_sequence"), ("mobile_element".into(), Some(2)));
+    assert_eq!(style("weird_thing"), ("other".into(), styles["default"]["priority"].as_u32()));
+    assert_eq!(styles["types"]["CDS"]["count"], 2);
+    assert!(styles["types"]["gene"].is_null());
+    assert_eq!(styles["sources"]["Prodigal"]["color"], "#4e79a7");
+    assert_eq!(styles["sources"]["Aragorn"]["color"], "#f28e2b");
+    assert_ne!(styles["types"]["tRNA"]["color"], styles["types"]["CDS"]["color"]);
+
+    let fa = b">c1\n".iter().chain(&[b'A'; 1000]).chain(b"\n").copied().collect::<Vec<u8>>();
+    let out = IndexGen::from_bytes(&fa, gff.as_bytes(), &IndexGenOptions::default()).unwrap();
+    assert_eq!(out.track_styles_json().unwrap(), styles);
+}
+
+// ---------------------------------------------------------------------------
+// Generic tabix indexing
+// ---------------------------------------------------------------------------
+
+/// Index a custom track and return the parsed CSI.
+fn index_track(text: &str, config: &mgnify_wasm::htslib::TabixConfig) -> std::io::Result<mgnify_wasm::htslib::CsiIndex> {
+    let mut bgz = Vec::new();
+    bgzf_compress(Cursor::new(text.as_bytes()), &mut bgz)?;
+    let mut csi = Vec::new();
+    mgnify_wasm::htslib::csi_index(Cursor::new(&bgz), &mut csi, config)?;
+    mgnify_wasm::htslib::CsiIndex::from_bgzf(&csi)
+}
+
+/// A record ending beyond 2^38 gives the CSI a ninth level, with bins
+/// already assigned renumbered to what they are when binned with nine
+/// levels from the start; past 2^41 (2^29 for TBI) indexing fails naming
+/// the record.
+#[test]
+fn csi_grows_a_level_for_coordinates_beyond_2_pow_38() {
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::htslib::{record_at, tbi_index, TabixConfig};
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let big = format!("chr0\t.\tgene\t{}\t{}\t.\t+\t.\tID=big\n", (1u64 << 38) - 10, (1u64 << 38) + 100);
+    let small = "chr1\t.\tgene\t1\t100\t.\t+\t.\tID=a\nchr1\t.\tgene\t20000\t30000\t.\t+\t.\tID=b\nchr1\t.\tgene\t5000000\t5000100\t.\t+\t.\tID=c\n";
+    let deepened = index_track(&format!("{}{}", small, big), &TabixConfig::GFF).unwrap();
+    let fresh = index_track(&format!("{}{}", big, small), &TabixConfig::GFF).unwrap();
+    assert_eq!((deepened.n_lvls, fresh.n_lvls), (9, 9));
+    let bins = |index: &mgnify_wasm::htslib::CsiIndex, seq: usize| {
+        let mut bins: Vec<u32> = index.seqs[seq].keys().copied().collect();
+        bins.sort_unstable();
+        bins
+    };
+    assert_eq!(bins(&deepened, 0), bins(&fresh, 1));
+
+    let gff = format!("##gff-version 3\n{}{}", small, big);
+    let out = IndexGen::from_bytes(b">chr1\nACGT\n", gff.as_bytes(), &IndexGenOptions::default()).unwrap();
+    assert_eq!(out.warnings().count("csi_depth_increased"), 1);
+    let (bgz, csi) = (out.artifacts()[3].1, out.artifacts()[4].1);
+    let index = mgnify_wasm::htslib::CsiIndex::from_bgzf(csi).unwrap();
+    let hits = index.query("chr0", 1 << 38, (1 << 38) + 1);
+    assert!(record_at(bgz, hits[0].0).unwrap().ends_with("ID=big"));
+
+    let mut compressed = Vec::new();
+    bgzf_compress(Cursor::new(big.as_bytes()), &mut compressed).unwrap();
+    let err = tbi_index(Cursor::new(&compressed), &mut Vec::new(), &TabixConfig::GFF).unwrap_err();
+    assert_eq!(error_code(&err), ErrorCode::CoordinateLimit);
+    assert!(err.to_string().starts_with("line 1: chr0:") && err.to_string().contains("TBI"), "{}", err);
+    let huge = format!("chr1\t.\tgene\t1\t100\t.\t+\t.\tID=a\nchr1\t.\tgene\t5\t{}\t.\t+\t.\tID=x\n", (1u64 << 41) + 1);
+    let err = index_track(&huge, &TabixConfig::GFF).err().unwrap();
+    assert_eq!(error_code(&err), ErrorCode::CoordinateLimit);
+    assert!(err.to_string().starts_with("line 2: chr1:5-2199023255553 ends beyond 2199023255552"), "{}", err);
+}
+
+/// A column-header line and `!` comments are skipped when configured, and
+/// both settings are written to the meta block.
+#[test]
+fn csi_index_honours_meta_char_and_line_skip() {
+    use mgnify_wasm::htslib::TabixConfig;
+
+    let track = "seq\tbeg\tend\tname\n!comment\tline\twith\ttabs\nchr1\t100\t200\tx\n!another\nchr1\t150\t400\ty\nchr2\t5\t9\tz\n";
+    let config = TabixConfig { meta_char: b'!', line_skip: 1, ..TabixConfig::BED };
+    let index = index_track(track, &config).expect("custom track should index");
+
+    assert_eq!(index.names, vec!["chr1", "chr2"]);
+    assert_eq!(index.config, Some(config));
+    assert!(!index.query("chr1", 180, 190).is_empty());
+    assert!(!index.query("chr2", 0, 10).is_empty());
+
+    // Without them the header lines are taken for records.
+    assert!(index_track(track, &TabixConfig { line_skip: 0, ..config }).is_err());
+    assert!(index_track(track, &TabixConfig { meta_char: b'#', ..config }).is_err());
+}
+
+/// With the 0-based flag, a BED interval is binned like the equivalent
+/// 1-based record, and the flag is stored in the preset field.
+#[test]
+fn csi_index_zero_based_coordinates() {
+    use mgnify_wasm::htslib::TabixConfig;
+
+    let bins = |index: &mgnify_wasm::htslib::CsiIndex| {
+        let mut b: Vec<u32> = index.seqs[0].keys().copied().collect();
+        b.sort_unstable();
+        b
+    };
+    // 16384..16385 (0-based) is the first base of the second 16 kb window.
+    let bed = "chr1\t16384\t16385\n";
+    let one_based_config = TabixConfig { zero_based: false, ..TabixConfig::BED };
+    let zero = index_track(bed, &TabixConfig::BED).unwrap();
+    let one = index_track("chr1\t16385\t16385\n", &one_based_config).unwrap();
+
+    assert_eq!(bins(&zero), bins(&one));
+    assert_eq!(zero.config, Some(TabixConfig::BED));
+    assert_eq!(one.config, Some(one_based_config));
+    // Read as 1-based, the record would straddle two windows.
+    assert_ne!(bins(&index_track(bed, &one_based_config).unwrap()), bins(&zero));
+}
+
+/// Each `index_format` emits exactly the requested indexes, each matching
+/// its tabix reference; TBI refuses coordinates it cannot bin.
+#[test]
+fn index_format_matrix() {
+    use mgnify_wasm::htslib::{tbi_index, TabixConfig};
+    use mgnify_wasm::{IndexFormat, IndexGen, IndexGenOptions};
+
+    let (fasta, gff) = (read_fixture(FASTA_FIXTURE), read_fixture(GFF_FIXTURE));
+    let decompress = |bgzf: &[u8]| {
+        let mut out = Vec::new();
+        BgzfReader::new(Cursor::new(bgzf)).read_to_end(&mut out).unwrap();
+        out
+    };
+    let ref_csi = normalize_csi(&decompress(&read_fixture(REF_CSI)));
+    let ref_tbi = normalize_tbi(&decompress(&read_fixture(REF_TBI)));
+
+    for (format, want_csi, want_tbi) in [
+        (IndexFormat::Csi, true, false),
+        (IndexFormat::Tbi, false, true),
+        (IndexFormat::Both, true, true),
+    ] {
+        let options = IndexGenOptions { index_format: format, ..Default::default() };
+        let out = IndexGen::from_bytes(&fasta, &gff, &options).expect("pipeline failed");
+        let [.., (_, csi), (_, tbi), _] = out.artifacts();
+        assert_eq!((!csi.is_empty(), !tbi.is_empty()), (want_csi, want_tbi), "{:?}", format);
+        if want_csi {
+            assert_eq!(normalize_csi(&decompress(csi)), ref_csi, "{:?} .csi", format);
+        }
+        if want_tbi {
+            assert_eq!(normalize_tbi(&decompress(tbi)), ref_tbi, "{:?} .tbi", format);
+        }
+    }
+
+    let mut bgz = Vec::new();
+    bgzf_compress(Cursor::new(&b"chr1\t600000000\t600000100\n"[..]), &mut bgz).unwrap();
+    assert!(tbi_index(Cursor::new(&bgz), &mut Vec::new(), &TabixConfig::BED).is_err());
+    assert!(mgnify_wasm::htslib::csi_index(Cursor::new(&bgz), &mut Vec::new(), &TabixConfig::BED).is_ok());
+}
+
+// ---------------------------------------------------------------------------
+// Lenient BGZF reading
+// ---------------------------------------------------------------------------
+
+/// One gzip member with the given FLG-dependent header fields; `bc` adds a
+/// BSIZE subfield after `extra` so the member is also a BGZF block.
+fn gzip_member(data: &[u8], xfl_os: [u8; 2], extra: &[u8], bc: bool, name: Option<&str>) -> Vec<u8> {
+    use flate2::write::DeflateEncoder;
+    use std::io::Write;
+
+    let mut enc = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+    enc.write_all(data).unwrap();
+    let deflated = enc.finish().unwrap();
+
+    let mut extra = extra.to_vec();
+    if bc {
+        extra.extend_from_slice(b"BC\x02\x00\x00\x00");
+    }
+    let flags = if extra.is_empty() { 0 } else { 0x04 } | if name.is_some() { 0x08 } else { 0 };
+    let mut out = vec![0x1f, 0x8b, 0x08, flags, 0, 0, 0, 0, xfl_os[0], xfl_os[1]];
+    if !extra.is_empty() {
+        out.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+        out.extend_from_slice(&extra);
+    }
+    if let Some(name) = name {
+        out.extend_from_slice(name.as_bytes());
+        out.push(0);
+    }
+    out.extend_from_slice(&deflated);
+    out.extend_from_slice(&crc32fast::hash(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    if bc {
+        let bsize = (out.len() - 1) as u16;
+        let at = 12 + extra.len() - 2;
+        out[at..at + 2].copy_from_slice(&bsize.to_le_bytes());
+    }
+    out
+}
+
+/// Inputs a strict BGZF parser trips over, each decoding to `text`.
+fn foreign_gzip_corpus(text: &[u8]) -> Vec<(&'static str, Vec<u8>)> {
+    let (a, b) = text.split_at(text.len() / 2);
+    // BGZF members must stay under 64 KiB; plain members may be any size.
+    let mut ours = Vec::new();
+    bgzf_compress(Cursor::new(text), &mut ours).unwrap();
+    let (mut part_a, mut part_b) = (Vec::new(), Vec::new());
+    bgzf_compress(Cursor::new(a), &mut part_a).unwrap();
+    bgzf_compress(Cursor::new(b), &mut part_b).unwrap();
+
+    vec![
+        ("our bgzf", ours),
+        ("concatenated bgzf files", [part_a, part_b.clone()].concat()),
+        ("single plain gzip member", gzip_member(text, [0, 3], b"", false, Some("test.gff3"))),
+        ("concatenated plain members", [gzip_member(a, [2, 0], b"", false, None), gzip_member(b, [4, 11], b"", false, None)].concat()),
+        ("extra subfield before BC", text.chunks(0xff00).enumerate().flat_map(|(i, chunk)| match i % 2 {
+            0 => gzip_member(chunk, [0, 3], b"XY\x03\x00abc", true, None),
+            _ => gzip_member(chunk, [2, 255], b"", true, Some("x")),
+        }).collect()),
+        ("plain member then bgzf", [gzip_member(a, [0, 255], b"", false, None), part_b].concat()),
+    ]
+}
+
+/// Foreign gzip layouts decode in lenient mode; plain members are counted.
+#[test]
+fn lenient_bgzf_reads_foreign_gzip_layouts() {
+    let gff = read_fixture(GFF_FIXTURE);
+    let text = &gff[..=gff[..300_000].iter().rposition(|&b| b == b'\n').unwrap()];
+    let corpus = foreign_gzip_corpus(text);
+    for (label, data) in &corpus {
+        let mut reader = BgzfReader::lenient(Cursor::new(data));
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap_or_else(|e| panic!("{}: {}", label, e));
+        assert!(out == text, "{}: decoded bytes differ", label);
+        assert_eq!(reader.plain_members() > 0, label.contains("plain"), "{}", label);
+    }
+
+    // Lines come back whole across member boundaries.
+    let mut reader = BgzfReader::lenient(Cursor::new(&corpus[4].1));
+    let (mut lines, mut buf) = (0, Vec::new());
+    while reader.read_line(&mut buf).unwrap().0 > 0 {
+        lines += 1;
+    }
+    assert_eq!(lines, text.iter().filter(|&&b| b == b'\n').count());
+}
+
+/// Both readers return an error, never panic, on a seeded corpus of byte
+/// flips, truncations and insertions of the foreign layouts.
+#[test]
+fn bgzf_readers_survive_mutated_corpus() {
+    let text = &read_fixture(GFF_FIXTURE)[..20_000];
+    let mut seed = 0x9e37_79b9_7f4a_7c15u64;
+    let mut next = move |n: usize| {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        (seed % n as u64) as usize
+    };
+    for (_, data) in foreign_gzip_corpus(text) {
+        for _ in 0..200 {
+            let mut mutated = data.clone();
+            match next(3) {
+                0 => mutated[next(data.len())] ^= 1 << next(8),
+                1 => mutated.truncate(next(data.len())),
+                _ => mutated.insert(next(data.len()), next(256) as u8),
+            }
+            let _ = BgzfReader::new(Cursor::new(&mutated)).read_to_end(&mut Vec::new());
+            let _ = BgzfReader::lenient(Cursor::new(&mutated)).read_to_end(&mut Vec::new());
+        }
+    }
+}
+
+/// A FASTA gzipped per contig: member boundaries line up with the contigs,
+/// each member inflates on its own, and the pipeline reads the file like
+/// the plain text.
+#[test]
+fn gzip_member_boundaries_split_per_contig_files() {
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::htslib::{gzip_members, inflate_member, inflate_members};
+    use mgnify_wasm::input::InputSource;
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let fixture = read_fixture(FASTA_FIXTURE);
+    let lines: Vec<&[u8]> = fixture.split_inclusive(|&b| b == b'\n').skip(1).collect();
+    let contigs: Vec<Vec<u8>> = lines
+        .chunks(lines.len() / 3 + 1)
+        .enumerate()
+        .map(|(i, chunk)| [format!(">contig{}\n", i + 1).into_bytes(), chunk.concat()].concat())
+        .collect();
+    let fa = contigs.concat();
+    let mut gz: Vec<u8> = contigs.iter().map(|c| gzip_member(c, [0, 3], b"", false, None)).collect::<Vec<_>>().concat();
+    gz.extend_from_slice(&gzip_member(b"", [0, 3], b"", false, None));
+
+    let members = gzip_members(&gz).unwrap();
+    assert_eq!(members.len(), 3, "the empty trailing member is skipped");
+    let mut offset = 0;
+    for (member, contig) in members.iter().zip(&contigs) {
+        assert_eq!(member.uncompressed_offset, offset);
+        assert_eq!(inflate_member(member.slice(&gz)).unwrap(), *contig);
+        offset += contig.len() as u64;
+    }
+    assert!(inflate_members(&gz, &members).unwrap() == fa);
+    assert!(InputSource::Bytes(gz.clone()).read_all().unwrap() == fa);
+    let gff = read_fixture(GFF_FIXTURE);
+    let from_gz = IndexGen::from_sources(InputSource::Bytes(gz.clone()), InputSource::Bytes(gff.clone()), &IndexGenOptions::default()).unwrap();
+    let from_plain = IndexGen::from_bytes(&fa, &gff, &IndexGenOptions::default()).unwrap();
+    assert_eq!(from_gz.artifacts(), from_plain.artifacts());
+
+    // BGZF blocks are members too, found from their BC size alone.
+    let bgzf = compress_fasta();
+    let mut reader = BgzfReader::new(Cursor::new(&bgzf));
+    reader.read_to_end(&mut Vec::new()).unwrap();
+    let starts: Vec<(u64, u64)> = gzip_members(&bgzf).unwrap().iter().map(|m| (m.compressed_offset, m.uncompressed_offset)).collect();
+    assert_eq!(starts, [&[(0, 0)][..], reader.gzi_entries()].concat());
+
+    let cut = members[1].compressed_offset as usize + 100;
+    assert_eq!(error_code(&gzip_members(&gz[..cut]).unwrap_err()), ErrorCode::BgzfTruncated);
+    let mut bad_crc = gz.clone();
+    bad_crc[members[1].compressed_offset as usize + members[1].compressed_len as usize - 8] ^= 1;
+    assert_eq!(gzip_members(&bad_crc).unwrap(), members);
+    assert_eq!(error_code(&inflate_member(members[1].slice(&bad_crc)).unwrap_err()), ErrorCode::BgzfCorrupt);
+}
+
+/// Chunked decompression gives the bytes of a sequential decoder for any
+/// worker count: BGZF, one plain member, per-contig members, and a stored
+/// member whose contents look like a valid gzip member (a worker starting
+/// inside it locks onto the fake one and is discarded).
+#[test]
+fn parallel_inflate_matches_sequential() {
+    use flate2::write::GzEncoder;
+    use mgnify_wasm::htslib::inflate_parallel;
+    use std::io::Write;
+
+    let sequential = |data: &[u8]| {
+        let mut out = Vec::new();
+        MultiGzDecoder::new(data).read_to_end(&mut out).unwrap();
+        out
+    };
+    let gff = read_fixture(GFF_FIXTURE);
+    let (a, b) = gff.split_at(gff.len() / 3);
+    let mut stored = GzEncoder::new(Vec::new(), flate2::Compression::none());
+    stored.write_all(&[a, &gzip_member(b"fake member\n", [0, 3], b"", false, None), &b[..1000]].concat()).unwrap();
+    let fake = [gzip_member(a, [0, 3], b"", false, None), stored.finish().unwrap(), gzip_member(b, [0, 3], b"", false, None)].concat();
+
+    let corpus = [
+        ("bgzf", compress_gff()),
+        ("plain member", fs::read(BU_FASTA_FIXTURE).unwrap()),
+        ("per-contig members", [gzip_member(a, [0, 3], b"", false, None), gzip_member(b, [0, 3], b"", false, None)].concat()),
+        ("fake member inside stored data", fake),
+    ];
+    for (label, data) in &corpus {
+        let expected = sequential(data);
+        for workers in [1, 2, 7, 64] {
+            assert!(inflate_parallel(data, workers).unwrap() == expected, "{} with {} workers", label, workers);
+        }
+    }
+    let bgzf = &corpus[0].1;
+    assert!(inflate_parallel(&bgzf[..bgzf.len() - 100], 7).is_err());
+    assert!(inflate_parallel(&[], 4).unwrap().is_empty());
+}
+
+/// A single plain gzip member is split at deflate blocks: the chunks its
+/// workers decode are used, at any compression level, and data that does
+/// not check out is left to the sequential decoder.
+#[test]
+fn speculative_inflate_splits_single_members() {
+    use flate2::write::GzEncoder;
+    use mgnify_wasm::htslib::{inflate_parallel, inflate_speculative};
+    use std::io::Write;
+
+    let text = read_fixture(GFF_FIXTURE);
+    for level in [0, 6] {
+        let mut gz = GzEncoder::new(Vec::new(), flate2::Compression::new(level));
+        gz.write_all(&text).unwrap();
+        let data = gz.finish().unwrap();
+        for workers in [2, 7] {
+            let (out, used) = inflate_speculative(&data, workers).unwrap().unwrap_or_else(|| panic!("level {} with {} workers", level, workers));
+            assert!(out == text, "level {} with {} workers", level, workers);
+            if level > 0 {
+                assert!(used > workers / 2, "level {}: {} of {} chunks used", level, used, workers);
+            }
+        }
+    }
+
+    let data = gzip_member(&text, [0, 3], b"", false, None);
+    let mut bad_crc = data.clone();
+    let at = bad_crc.len() - 8;
+    bad_crc[at] ^= 0xff;
+    assert!(inflate_speculative(&bad_crc, 4).unwrap().is_none());
+    assert!(inflate_speculative(&data[..data.len() - 100], 4).unwrap().is_none());
+    assert!(inflate_parallel(&bad_crc, 4).is_err());
+    assert!(inflate_parallel(&[&data[..], b"junk"].concat(), 4).is_err());
+}
+
+/// A corrupted block and a truncated tail are skipped and reported; every
+/// other block is recovered.
+#[test]
+fn recover_bgzf_skips_damaged_blocks() {
+    use mgnify_wasm::htslib::recover_bgzf;
+
+    let text = read_fixture(GFF_FIXTURE);
+    let text = &text[..300_000];
+    let mut bgzf = Vec::new();
+    bgzf_compress(text, &mut bgzf).unwrap();
+    let mut reader = BgzfReader::new(Cursor::new(&bgzf));
+    reader.read_to_end(&mut Vec::new()).unwrap();
+    let blocks = reader.gzi_entries().to_vec();
+    let ((c1, u1), (c2, u2)) = (blocks[0], blocks[1]);
+    let (c_last, u_last) = blocks[blocks.len() - 1];
+
+    bgzf[c1 as usize + 40] ^= 0xff;
+    bgzf.truncate(c_last as usize + 30);
+    let recovered = recover_bgzf(&bgzf);
+
+    let expected = [&text[..u1 as usize], &text[u2 as usize..u_last as usize]].concat();
+    assert!(recovered.data == expected, "recovered bytes differ");
+    let ranges: Vec<(u64, u64, u64)> = recovered.damaged.iter().map(|d| (d.start, d.end, d.recovered_offset)).collect();
+    assert_eq!(ranges, [(c1, c2, u1), (c_last, bgzf.len() as u64, u_last - (u2 - u1))]);
+}
+
+/// Both CRC32 implementations agree, and a reader told not to verify CRCs
+/// reads blocks whose stored CRC is wrong; the pipeline gives the same
+/// outputs with the re-check of its own output turned off.
+#[test]
+fn crc32_implementations_and_skipped_checks() {
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::htslib::{crc32, crc32_impl, set_crc32_impl, Crc32Impl};
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let text = read_fixture(GFF_FIXTURE);
+    let text = &text[..200_000];
+    let fast = (crc32(b"123456789"), crc32(text));
+    set_crc32_impl(Crc32Impl::Scalar);
+    assert_eq!(crc32_impl(), Crc32Impl::Scalar);
+    let scalar = (crc32(b"123456789"), crc32(text));
+    set_crc32_impl(Crc32Impl::Fast);
+    assert_eq!(fast, scalar);
+    assert_eq!(fast.0, 0xcbf4_3926);
+
+    let mut bgzf = Vec::new();
+    bgzf_compress(text, &mut bgzf).unwrap();
+    let trailer = u16::from_le_bytes([bgzf[16], bgzf[17]]) as usize + 1 - 8;
+    bgzf[trailer] ^= 0xff;
+    let err = BgzfReader::new(Cursor::new(&bgzf)).read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(error_code(&err), ErrorCode::BgzfCorrupt);
+    let mut read = Vec::new();
+    BgzfReader::new(Cursor::new(&bgzf)).verify_crc(false).read_to_end(&mut read).unwrap();
+    assert!(read == text);
+
+    let (fa, gff) = (read_fixture(FASTA_FIXTURE), read_fixture(GFF_FIXTURE));
+    let options = IndexGenOptions { verify_written_crc: false, ..IndexGenOptions::default().with_default_search_keys() };
+    let skipped = IndexGen::from_bytes(&fa, &gff, &options).unwrap();
+    let checked = IndexGen::from_bytes(&fa, &gff, &IndexGenOptions::default().with_default_search_keys()).unwrap();
+    assert_eq!(skipped.artifacts(), checked.artifacts());
+}
+
+#[test]
+fn transform_hooks_modify_or_drop_records() {
+    use std::io::Read;
+    use mgnify_wasm::error::{error_code, io_error, ErrorCode};
+    use mgnify_wasm::htslib::BgzfReader;
+    use mgnify_wasm::transform::hook::{register_fasta_hook, register_gff_hook, unregister_transform_hook};
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let fa = b">1 chromosome\nACGTACGT\n>junk\nNNNN\n";
+    let gff = "##gff-version 3\n\
+               1\t.\tgene\t2\t7\t.\t+\t.\tID=g1;locus_tag=OLD_0001\n\
+               1\t.\tgene\t3\t8\t.\t+\t.\tID=g2;locus_tag=OLD_0002\n\
+               1\t.\tregion\t1\t8\t.\t+\t.\tID=r1\n";
+    register_gff_hook("site_locus_tags", |record, _line| {
+        Ok((!record.contains("\tregion\t")).then(|| record.replace("locus_tag=OLD_", "locus_tag=SITE_")))
+    })
+    .unwrap();
+    register_fasta_hook("site_headers", |header| Ok((header != "junk").then(|| header.replace("chromosome", "chr"))))
+        .unwrap();
+
+    let options = IndexGenOptions {
+        fasta_transforms: vec!["hook:site_headers".into()],
+        gff_transforms: vec!["hook:site_locus_tags".into()],
+        ..Default::default()
+    };
+    let out = IndexGen::from_bytes(fa, gff.as_bytes(), &options).unwrap();
+    let fai = String::from_utf8(out.artifacts()[1].1.to_vec()).unwrap();
+    assert_eq!(fai.lines().count(), 1);
+    let mut fasta = String::new();
+    BgzfReader::new(out.artifacts()[0].1).read_to_string(&mut fasta).unwrap();
+    assert!(fasta.starts_with(">1 chr\n"));
+    let mut text = String::new();
+    BgzfReader::new(out.artifacts()[3].1).read_to_string(&mut text).unwrap();
+    assert_eq!(text.matches("locus_tag=SITE_").count(), 2);
+    assert!(!text.contains("\tregion\t"));
+
+    // A header with a line break in it would turn into sequence.
+    register_fasta_hook("site_headers", |header| Ok(Some(format!("{}\nNNNN", header)))).unwrap();
+    let err = IndexGen::from_bytes(fa, gff.as_bytes(), &options).err().unwrap();
+    assert_eq!(error_code(&err), ErrorCode::TransformHook);
+    assert!(err.to_string().contains("line break"), "{}", err);
+    register_fasta_hook("site_headers", |header| Ok(Some(header.to_owned()))).unwrap();
+
+    register_gff_hook("site_locus_tags", |_, line| Err(io_error(ErrorCode::TransformHook, format!("bad record on line {}", line)))).unwrap();
+    let err = IndexGen::from_bytes(fa, gff.as_bytes(), &options).err().unwrap();
+    assert_eq!(error_code(&err), ErrorCode::TransformHook);
+    assert!(err.to_string().contains("line 2"));
+
+    assert!(unregister_transform_hook("site_locus_tags"));
+    assert!(!unregister_transform_hook("site_locus_tags"));
+    let err = IndexGen::from_bytes(fa, gff.as_bytes(), &options).err().unwrap();
+    assert_eq!(error_code(&err), ErrorCode::InvalidOption);
+}
+
+#[test]
+fn gff_reader_streams_typed_records() {
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::gff::{columns, GffReader, GffRecords};
+
+    assert_eq!(columns("a\tb\tc\td", 3).collect::<Vec<_>>(), ["a", "b", "c\td"]);
+    assert_eq!(columns("a\tb", 9).collect::<Vec<_>>(), ["a", "b"]);
+
+    let gff = "##gff-version 3\n\
+               # comment\n\
+               \n\
+               chr1\tProdigal\tCDS\t10\t99\t12.5\t-\t0\tID=cds1;Dbxref=a,b;Name=x%3By;partial\n\
+               chr1\t.\tgene\t5\t120\t.\t+\t.\t.\n\
+               ##FASTA\n\
+               >chr1\n\
+               ACGT\n";
+    let records: Vec<_> = GffReader::new(Cursor::new(gff)).collect::<std::io::Result<_>>().unwrap();
+    assert_eq!(records.len(), 2);
+    let cds = &records[0];
+    assert_eq!((cds.line, cds.seqid.as_str(), cds.ftype.as_str(), cds.start, cds.end), (4, "chr1", "CDS", 10, 99));
+    assert_eq!((cds.score, cds.strand, cds.phase), (Some(12.5), '-', Some(0)));
+    assert_eq!(cds.attribute("Name"), Some("x;y"));
+    assert_eq!(cds.attributes[1], ("Dbxref".to_owned(), vec!["a".to_owned(), "b".to_owned()]));
+    assert_eq!(cds.attributes[3], ("partial".to_owned(), vec![]));
+    assert_eq!((records[1].score, records[1].phase, records[1].attributes.len()), (None, None, 0));
+    let json = cds.to_json();
+    assert_eq!(json["type"], "CDS");
+    assert_eq!(json["attributes"]["Dbxref"][1], "b");
+
+    let bad = "chr1\t.\tgene\t5\t120\t.\t+\t.\tID=g1\nchr1\t.\tgene\tfive\t120\t.\t+\t.\tID=g2\nchr1\t.\tgene\t9\t12\t.\t+\t.\tID=g3\n";
+    let mut reader = GffReader::new(Cursor::new(bad));
+    assert!(reader.next().unwrap().is_ok());
+    let err = reader.next().unwrap().unwrap_err();
+    assert_eq!(error_code(&err), ErrorCode::GffParse);
+    assert!(err.to_string().contains("line 2"));
+    assert!(reader.next().is_none());
+
+    let mut js = GffRecords::new(gff.to_owned());
+    let first = json::parse(&js.next().unwrap().unwrap()).unwrap();
+    assert_eq!(first["strand"], "-");
+    assert!(js.next().unwrap().is_some());
+    assert!(js.next().unwrap().is_none());
+    assert_eq!(js.lines_read(), 6);
+}
+
+#[test]
+fn fasta_reader_streams_records_and_chunks() {
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::fasta::{FastaReader, FastaRecord, FastaRecords, FastaVisitor};
+
+    let fa = b"junk\n>chr1 Escherichia coli chromosome\r\nACGTAC\r\nGT\r\n\n>plasmid\tcircular\nNNNN\n>empty\n";
+    let records: Vec<FastaRecord> = FastaReader::new(Cursor::new(&fa[..])).collect::<std::io::Result<_>>().unwrap();
+    let summary: Vec<(&str, &str, u64, usize)> = records.iter().map(|r| (r.name.as_str(), r.description.as_str(), r.length, r.line)).collect();
+    assert_eq!(
+        summary,
+        [("chr1", "Escherichia coli chromosome", 8, 2), ("plasmid", "circular", 4, 6), ("empty", "", 0, 8)]
+    );
+
+    #[derive(Default)]
+    struct Collect {
+        events: Vec<String>,
+    }
+    impl FastaVisitor for Collect {
+        fn record_start(&mut self, record: &FastaRecord) -> std::io::Result<()> {
+            self.events.push(format!("start {}", record.name));
+            Ok(())
+        }
+        fn sequence(&mut self, record: &FastaRecord, chunk: &[u8]) -> std::io::Result<()> {
+            self.events.push(format!("{} {} {}", record.name, String::from_utf8_lossy(chunk), record.length));
+            Ok(())
+        }
+        fn record_end(&mut self, record: FastaRecord) -> std::io::Result<()> {
+            self.events.push(format!("end {} {}", record.name, record.length));
+            Ok(())
+        }
+    }
+    let mut visitor = Collect::default();
+    FastaReader::new(Cursor::new(&fa[..])).visit(&mut visitor).unwrap();
+    assert_eq!(
+        visitor.events,
+        ["start chr1", "chr1 ACGTAC 6", "chr1 GT 8", "end chr1 8", "start plasmid", "plasmid NNNN 4", "end plasmid 4", "start empty", "end empty 0"]
+    );
+
+    let err = FastaReader::new(Cursor::new(&b">ok\nAC\n>\xff\nAC\n"[..])).nth(1).unwrap().unwrap_err();
+    assert_eq!(error_code(&err), ErrorCode::FastaParse);
+    assert!(err.to_string().contains("line 3"));
+
+    let mut js = FastaRecords::new(fa.to_vec());
+    let first = json::parse(&js.next().unwrap().unwrap()).unwrap();
+    assert_eq!((first["name"].as_str(), first["length"].as_u64()), (Some("chr1"), Some(8)));
+    assert!(js.next().unwrap().is_some());
+    assert!(js.next().unwrap().is_some());
+    assert!(js.next().unwrap().is_none());
+}
+
+#[test]
+fn parallel_branches_match_sequential_runs() {
+    use mgnify_wasm::coordinator;
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let (a, b) = coordinator::join(true, || 6 * 7, || "gff");
+    assert_eq!((a, b), (42, "gff"));
+
+    let fa = b">chr1\nACGTACGTAC\nACG\n>chr2\nGGGG\n";
+    let gff = "##gff-version 3\n\
+               chr2\t.\tgene\t1\t4\t.\t+\t.\tID=g2\n\
+               chr1\t.\tgene\t8\t2\t.\t+\t.\tID=g1\n";
+    let parallel = IndexGenOptions::default();
+    assert!(parallel.parallel_branches);
+    let sequential = IndexGenOptions { parallel_branches: false, ..Default::default() };
+    assert_eq!(parallel.digest(), sequential.digest());
+    let a = IndexGen::from_bytes(fa, gff.as_bytes(), &parallel).unwrap();
+    let b = IndexGen::from_bytes(fa, gff.as_bytes(), &sequential).unwrap();
+    assert_eq!(a.artifacts(), b.artifacts());
+    assert_eq!(a.warnings().to_json().dump(), b.warnings().to_json().dump());
+    assert_eq!(a.warnings().count("unsorted_input"), 1);
+
+    // With both branches failing, the FASTA error is the one reported.
+    let bad_fa = b">chr1\nACGTACGTAC\nACG\nACGTACGTAC\n";
+    let bad_gff = "chr1\t.\tgene\tone\t4\t.\t+\t.\tID=g1\n";
+    for options in [&parallel, &sequential] {
+        let err = IndexGen::from_bytes(bad_fa, bad_gff.as_bytes(), options).err().unwrap();
+        assert_eq!(error_code(&err), ErrorCode::FastaLineLength);
+        let partial = IndexGen::from_bytes_partial(bad_fa, gff.as_bytes(), options);
+        assert_eq!(partial.failures().iter().map(|f| f.stage).collect::<Vec<_>>(), ["fasta"]);
+        assert!(!partial.artifacts()[3].1.is_empty());
+        assert_eq!(partial.warnings().count("unsorted_input"), 1);
+    }
+}
+
+#[test]
+fn aggressive_bin_rollup_shrinks_indexes() {
+    use mgnify_wasm::htslib::{csi_index_reader_with, BinRollup, CsiIndex, TabixConfig};
+
+    let csi = |bgzf: &[u8], rollup| {
+        let mut out = Vec::new();
+        csi_index_reader_with(BgzfReader::new(Cursor::new(bgzf)), &mut out, &TabixConfig::GFF, rollup).unwrap();
+        CsiIndex::from_bgzf(&out).unwrap()
+    };
+    let bins = |idx: &CsiIndex| idx.seqs.iter().map(|s| s.len()).sum::<usize>();
+    // Sparse features: 50 bp every 20 kb, each in its own finest bin.
+    let mut sparse = String::from("##gff-version 3\n");
+    for i in 0..2000u64 {
+        sparse.push_str(&format!("chr1\t.\tgene\t{}\t{}\t.\t+\t.\tID=g{}\n", i * 20_000 + 1, i * 20_000 + 50, i));
+    }
+    let mut sparse_bgzf = Vec::new();
+    bgzf_compress(Cursor::new(sparse.as_bytes()), &mut sparse_bgzf).unwrap();
+
+    for bgzf in [compress_bu_gff(), sparse_bgzf.clone()] {
+        let (htslib, aggressive) = (csi(&bgzf, BinRollup::Htslib), csi(&bgzf, BinRollup::Aggressive));
+        assert!(bins(&aggressive) <= bins(&htslib));
+        // Every region still reaches every chunk the htslib index gives.
+        for name in &htslib.names {
+            for beg in (0..40_000_000u64).step_by(1_000_003) {
+                let wide = aggressive.query(name, beg, beg + 70_000);
+                for (s, e) in htslib.query(name, beg, beg + 70_000) {
+                    assert!(wide.iter().any(|&(ws, we)| ws <= s && e <= we), "{}:{} chunk {}-{} lost", name, beg, s, e);
+                }
+            }
+        }
+    }
+    // The 2000 finest bins collapse into a handful of coarse ones.
+    assert!(bins(&csi(&sparse_bgzf, BinRollup::Htslib)) > 1000);
+    assert!(bins(&csi(&sparse_bgzf, BinRollup::Aggressive)) < 10);
+}
+
+#[test]
+fn dense_indexes_stay_compact_and_report_density() {
+    use mgnify_wasm::htslib::{index_density, record_at, CsiIndex};
+
+    // 5000 features, one per 16 kb window and so one finest bin each.
+    let mut gff = String::new();
+    for i in 0..5000u64 {
+        gff.push_str(&format!("chr1\t.\tgene\t{}\t{}\t.\t+\t.\tID=g{}\n", i * 16_384 + 1, i * 16_384 + 50, i));
+    }
+    gff.push_str("chr2\t.\tregion\t1\t900000\t.\t+\t.\tID=r\n");
+    for i in 0..300u64 {
+        gff.push_str(&format!("chr2\t.\tgene\t{}\t{}\t.\t+\t.\tID=h{}\n", i * 3000 + 1, i * 3000 + 900, i));
+    }
+    let mut bgzf = Vec::new();
+    bgzf_compress(Cursor::new(gff.as_bytes()), &mut bgzf).unwrap();
+    let mut csi = Vec::new();
+    csi_index_gff(Cursor::new(&bgzf), &mut csi).unwrap();
+    let idx = CsiIndex::from_bgzf(&csi).unwrap();
+
+    let density = idx.density();
+    assert_eq!((density.sequences, density.records), (2, 5301));
+    assert!(density.max_bins_per_sequence >= 5000);
+    assert_eq!(density.chunks, density.bins, "one chunk per bin: {:?}", density);
+    for i in [0u64, 4095, 4096, 4999] {
+        let chunks = idx.query("chr1", i * 16_384, i * 16_384 + 50);
+        let line = record_at(&bgzf, chunks[0].0).unwrap();
+        assert!(line.ends_with(&format!("ID=g{}", i)), "{}", line);
+    }
+
+    let json = json::parse(&index_density(&csi).unwrap()).unwrap();
+    assert_eq!(json["records"].as_u64(), Some(5301));
+    assert_eq!(json["fullest_bin"]["chunks"].as_usize(), Some(1));
+    assert_eq!(json["fullest_bin"]["sequence"].as_str(), Some("chr1"));
+}
+
+#[test]
+fn csi_bins_are_written_in_ascending_order() {
+    use mgnify_wasm::htslib::{csi_index_reader_with, BinRollup, TabixConfig};
+
+    // Features at several levels, long ones between short ones, so bins of
+    // every level interleave in file order.
+    let mut gff = String::new();
+    for i in 0..3000u64 {
+        let len = if i % 7 == 0 { 300_000 } else if i % 3 == 0 { 20_000 } else { 200 };
+        gff.push_str(&format!("chr1\t.\tgene\t{}\t{}\t.\t+\t.\tID=g{}\n", i * 9_000 + 1, i * 9_000 + len, i));
+    }
+    let mut bgzf = Vec::new();
+    bgzf_compress(Cursor::new(gff.as_bytes()), &mut bgzf).unwrap();
+    for rollup in [BinRollup::Htslib, BinRollup::Aggressive] {
+        let mut csi = Vec::new();
+        csi_index_reader_with(BgzfReader::new(Cursor::new(&bgzf)), &mut csi, &TabixConfig::GFF, rollup).unwrap();
+        let mut raw = Vec::new();
+        BgzfReader::new(Cursor::new(&csi)).read_to_end(&mut raw).unwrap();
+        let u32_at = |at: usize| u32::from_le_bytes(raw[at..at + 4].try_into().unwrap());
+        let mut at = 16 + u32_at(12) as usize + 4;
+        let n_bin = u32_at(at) as usize;
+        at += 4;
+        let mut bins = Vec::new();
+        for _ in 0..n_bin {
+            bins.push(u32_at(at));
+            at += 16 + 16 * u32_at(at + 12) as usize;
+        }
+        assert!(bins.windows(2).all(|w| w[0] < w[1]), "{:?}: {:?}", rollup, bins);
+        assert_eq!(*bins.last().unwrap(), 19173962, "pseudo-bin last");
+    }
+}
+
+#[test]
+fn parsed_csi_serializes_back_to_the_same_bytes() {
+    use mgnify_wasm::htslib::{csi_index, CsiIndex, TabixConfig};
+
+    let mut csi = Vec::new();
+    csi_index_gff(Cursor::new(&compress_bu_gff()), &mut csi).unwrap();
+    let index = CsiIndex::from_bgzf(&csi).unwrap();
+    assert_eq!(index.write_bgzf(Vec::new()).unwrap(), csi);
+
+    // Other layouts keep their meta block.
+    let bed = "# header\nchr1\t0\t10\nchr2\t5\t20\n";
+    let mut bgzf = Vec::new();
+    bgzf_compress(Cursor::new(bed.as_bytes()), &mut bgzf).unwrap();
+    let mut csi = Vec::new();
+    csi_index(Cursor::new(&bgzf), &mut csi, &TabixConfig::BED).unwrap();
+    let index = CsiIndex::from_bgzf(&csi).unwrap();
+    assert_eq!(index.config, Some(TabixConfig::BED));
+    assert_eq!(index.write_bgzf(Vec::new()).unwrap(), csi);
+}
+
+#[test]
+fn chunked_bgzf_matches_compressing_the_concatenation() {
+    use mgnify_wasm::htslib::bgzf_compress_chunks;
+
+    let raw = read_fixture(FASTA_FIXTURE);
+    let whole = compress_fasta();
+    // Chunk sizes that straddle block boundaries, plus empty chunks.
+    for size in [7, 4096, 65280, 65281, 200_000] {
+        let chunks = raw.chunks(size).flat_map(|c| [c, &[][..]]);
+        let chunked = bgzf_compress_chunks(chunks, Vec::new(), 6).unwrap();
+        assert_eq!(chunked, whole, "chunk size {}", size);
+    }
+    assert_eq!(bgzf_compress_chunks(std::iter::empty::<&[u8]>(), Vec::new(), 6).unwrap(), mgnify_wasm::htslib::EOF_BLOCK);
+}
+
+#[test]
+fn reheader_keeps_data_blocks_and_patches_indexes() {
+    use mgnify_wasm::htslib::{gff_header, gzip_members, reheader_gff, IndexDifference};
+
+    let bgzf = compress_bu_gff();
+    let mut data = Vec::new();
+    BgzfReader::new(Cursor::new(&bgzf)).read_to_end(&mut data).unwrap();
+    let old_header = gff_header(&bgzf).unwrap();
+    assert!(old_header.starts_with("##gff-version 3") && data.starts_with(old_header.as_bytes()));
+    let body = &data[old_header.len()..];
+    let (mut csi, mut tbi) = (Vec::new(), Vec::new());
+    csi_index_gff(Cursor::new(&bgzf), &mut csi).unwrap();
+    tbi_index_gff(Cursor::new(&bgzf), &mut tbi).unwrap();
+
+    // A short header fits the first block; one of ~100 kB spans several.
+    let long = format!("##gff-version 3.1.26\n{}", "# a long comment line padding the header out\n".repeat(2300));
+    for header in ["##gff-version 3.1.26\n##sequence-region contig 1 100", long.as_str(), ""] {
+        for index in [&csi, &tbi] {
+            let (new_bgzf, new_index) = reheader_gff(&bgzf, index, header).unwrap();
+            let mut new_data = Vec::new();
+            BgzfReader::new(Cursor::new(&new_bgzf)).read_to_end(&mut new_data).unwrap();
+            let expected_header = if header.is_empty() || header.ends_with('\n') { header.to_owned() } else { format!("{}\n", header) };
+            assert_eq!(&new_data[..expected_header.len()], expected_header.as_bytes());
+            assert!(new_data[expected_header.len()..] == *body, "data after the header changed");
+            assert_eq!(gff_header(&new_bgzf).unwrap(), expected_header);
+
+            // Blocks past the first are copied untouched.
+            let members = gzip_members(&bgzf).unwrap();
+            assert!(new_bgzf.ends_with(&bgzf[members[1].compressed_offset as usize..]));
+
+            let mut fresh = Vec::new();
+            if index == &csi {
+                csi_index_gff(Cursor::new(&new_bgzf), &mut fresh).unwrap();
+            } else {
+                tbi_index_gff(Cursor::new(&new_bgzf), &mut fresh).unwrap();
+            }
+            // With no header the first record sits at offset 0, which a
+            // fresh index takes for an unset linear-index entry and skips;
+            // the patched loff stays at that record, a tighter bound.
+            let diff = compare_index(&new_index, &fresh).unwrap();
+            let tighter = |d: &IndexDifference| header.is_empty() && d.field == "loff" && d.ours.parse::<u64>().unwrap() < d.theirs.parse().unwrap();
+            assert!(diff.total == diff.differences.len() && diff.differences.iter().all(tighter), "{}", diff.to_json().pretty(2));
+        }
+    }
+
+    let err = reheader_gff(&bgzf, &csi, "##gff-version 3\nnot a comment\n").unwrap_err();
+    assert_eq!(mgnify_wasm::error::error_code(&err), mgnify_wasm::error::ErrorCode::InvalidOption);
+
+    // A chunk count past the index is a truncated index, whatever its size.
+    let mut raw = Vec::new();
+    BgzfReader::new(Cursor::new(&tbi)).read_to_end(&mut raw).unwrap();
+    let l_nm = u32::from_le_bytes(raw[32..36].try_into().unwrap()) as usize;
+    let n_chunk = 36 + l_nm + 8;
+    raw[n_chunk..n_chunk + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    let mut bad = Vec::new();
+    bgzf_compress(&raw[..], &mut bad).unwrap();
+    let err = reheader_gff(&bgzf, &bad, "##gff-version 3\n").unwrap_err();
+    assert_eq!(mgnify_wasm::error::error_code(&err), mgnify_wasm::error::ErrorCode::IndexParse);
+}
+
+#[test]
+fn appended_records_are_found_through_the_extended_csi() {
+    use mgnify_wasm::htslib::{append_gff, extract_gff_chunk, CsiIndex, EOF_BLOCK};
+
+    let bgzf = compress_bu_gff();
+    let mut data = Vec::new();
+    BgzfReader::new(Cursor::new(&bgzf)).read_to_end(&mut data).unwrap();
+    let text = String::from_utf8(data).unwrap();
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    let seqid = |l: &str| l.split('\t').next().unwrap_or("").to_owned();
+
+    // Mid-sequence, and just before a new sequence starts.
+    let mid = lines.len() * 3 / 5;
+    let boundary = (1..lines.len()).find(|&i| !lines[i].starts_with('#') && !lines[i - 1].starts_with('#') && seqid(lines[i]) != seqid(lines[i - 1])).unwrap();
+    for split in [mid, boundary, lines.len() - 3] {
+        let (head, tail) = (lines[..split].concat(), lines[split..].concat());
+        let mut base = Vec::new();
+        bgzf_compress(Cursor::new(head.as_bytes()), &mut base).unwrap();
+        let mut base_csi = Vec::new();
+        csi_index_gff(Cursor::new(&base), &mut base_csi).unwrap();
+
+        let (out, csi) = append_gff(&base, &base_csi, &tail).unwrap();
+        assert!(out.starts_with(&base[..base.len() - EOF_BLOCK.len()]), "old blocks were rewritten");
+        assert!(out.ends_with(&EOF_BLOCK));
+        let mut out_data = Vec::new();
+        BgzfReader::new(Cursor::new(&out)).read_to_end(&mut out_data).unwrap();
+        assert!(out_data == text.as_bytes(), "split {}: content differs", split);
+
+        // Bins are laid out as indexing the new records alone gives (small
+        // bins rolled into their parents), so compare queries, not bins.
+        let index = CsiIndex::from_bgzf(&csi).unwrap();
+        let mut seqids: Vec<String> = Vec::new();
+        for l in lines.iter().filter(|l| !l.starts_with('#')) {
+            if seqids.last() != Some(&seqid(l)) {
+                seqids.push(seqid(l));
+            }
+        }
+        assert_eq!(index.names, seqids);
+        for seq in &seqids {
+            for beg in (0..6_000_000u64).step_by(250_000) {
+                let end = beg + 250_000;
+                let expected: Vec<&str> = lines
+                    .iter()
+                    .map(|l| l.trim_end())
+                    .filter(|l| {
+                        let f: Vec<&str> = l.split('\t').collect();
+                        !l.starts_with('#') && f[0] == seq && f[3].parse::<u64>().unwrap() - 1 < end && f[4].parse::<u64>().unwrap() > beg
+                    })
+                    .collect();
+                let mut got = Vec::new();
+                for chunk in index.query(seq, beg, end) {
+                    extract_gff_chunk(&out[(chunk.0 >> 16) as usize..], chunk.0 >> 16, chunk, seq, beg, end, &mut got).unwrap();
+                }
+                assert_eq!(got, expected, "split {}: {}:{}-{}", split, seq, beg, end);
+            }
+        }
+    }
+
+    // Records before the last one, or back on an earlier sequence, would
+    // leave the file unsorted.
+    let start = |l: &str| l.split('\t').nth(3).unwrap().parse::<u64>().unwrap();
+    let later = (boundary + 1..lines.len()).find(|&i| start(lines[i]) > start(lines[boundary])).unwrap();
+    let head = lines[..=later].concat();
+    let mut base = Vec::new();
+    bgzf_compress(Cursor::new(head.as_bytes()), &mut base).unwrap();
+    let mut base_csi = Vec::new();
+    csi_index_gff(Cursor::new(&base), &mut base_csi).unwrap();
+    let first_record = lines.iter().find(|l| !l.starts_with('#')).unwrap();
+    for records in [lines[boundary], first_record] {
+        let err = append_gff(&base, &base_csi, records).unwrap_err();
+        assert_eq!(mgnify_wasm::error::error_code(&err), mgnify_wasm::error::ErrorCode::GffUnsorted);
+    }
+}
+
+#[test]
+fn bgzf_cat_joins_files_and_split_compression_matches_whole() {
+    use mgnify_wasm::htslib::{bgzf_cat, bgzf_compress_split, gzip_members, EOF_BLOCK};
+
+    let raw = read_fixture(FASTA_FIXTURE);
+    let whole = compress_fasta();
+    // Splits inside blocks are rounded down, so the pieces start on the
+    // same block boundaries as the whole.
+    for splits in [vec![], vec![1000, 65280], vec![70_000, 300_000, raw.len() + 5]] {
+        assert_eq!(bgzf_compress_split(&raw, &splits, 6).unwrap(), whole, "splits {:?}", splits);
+    }
+
+    // Parts compressed apart, each with its EOF block, read back as one
+    // file with a single EOF block at the end.
+    let parts: Vec<Vec<u8>> = [&raw[..12_345], &raw[12_345..12_345], &raw[12_345..]]
+        .iter()
+        .map(|part| {
+            let mut out = Vec::new();
+            bgzf_compress(Cursor::new(part), &mut out).unwrap();
+            out
+        })
+        .collect();
+    let joined = bgzf_cat(&parts.iter().map(Vec::as_slice).collect::<Vec<_>>());
+    assert_eq!(joined.len(), parts.iter().map(|p| p.len() - EOF_BLOCK.len()).sum::<usize>() + EOF_BLOCK.len());
+    assert!(joined.ends_with(&EOF_BLOCK) && joined.windows(EOF_BLOCK.len()).filter(|w| *w == EOF_BLOCK).count() == 1);
+    let mut data = Vec::new();
+    BgzfReader::new(Cursor::new(&joined)).read_to_end(&mut data).unwrap();
+    assert!(data == raw);
+    assert_eq!(gzip_members(&joined).unwrap().len(), gzip_members(&parts[0]).unwrap().len() + gzip_members(&parts[2]).unwrap().len());
+    assert_eq!(bgzf_cat(&[]), EOF_BLOCK);
+}
+
+#[test]
+fn standalone_gzi_matches_faidx_and_block_boundaries() {
+    use mgnify_wasm::htslib::{gzi_index, gzip_members, parse_gzi};
+
+    let fasta = compress_fasta();
+    let (mut fai, mut gzi) = (Vec::new(), Vec::new());
+    faidx_index_fasta(Cursor::new(&fasta), &mut fai, &mut gzi).unwrap();
+    let mut standalone = Vec::new();
+    gzi_index(Cursor::new(&fasta), &mut standalone).unwrap();
+    assert_eq!(standalone, gzi);
+
+    // Any BGZF text gets one entry per block after the first.
+    let gff = compress_bu_gff();
+    let mut gzi = Vec::new();
+    gzi_index(Cursor::new(&gff), &mut gzi).unwrap();
+    let members = gzip_members(&gff).unwrap();
+    let expected: Vec<(u64, u64)> = members.iter().map(|m| (m.compressed_offset, m.uncompressed_offset)).collect();
+    assert!(members.len() > 1);
+    assert_eq!(parse_gzi(&gzi).unwrap(), expected);
+
+    let err = gzi_index(Cursor::new(b"plain text, not BGZF"), &mut Vec::new()).unwrap_err();
+    assert_eq!(mgnify_wasm::error::error_code(&err), mgnify_wasm::error::ErrorCode::BgzfHeader);
+}
+
+#[test]
+fn voffset_helpers_round_trip_through_the_gzi() {
+    use mgnify_wasm::htslib::{
+        compose_voffset, parse_gzi, record_at, region_bin, region_bins, split_voffset, uncompressed_to_voffset, voffset_to_uncompressed,
+    };
+    use mgnify_wasm::error::{error_code, ErrorCode};
+
+    assert_eq!(compose_voffset(5, 7).unwrap(), 5 << 16 | 7);
+    assert_eq!(split_voffset(5 << 16 | 7), (5, 7));
+    assert_eq!(error_code(&compose_voffset(0, 0x10000).unwrap_err()), ErrorCode::InvalidOption);
+
+    // Every line start, moved to a virtual offset, reads back as that line.
+    let gff = compress_bu_gff();
+    let mut text = Vec::new();
+    BgzfReader::new(Cursor::new(&gff)).read_to_end(&mut text).unwrap();
+    let mut gzi = Vec::new();
+    mgnify_wasm::htslib::gzi_index(Cursor::new(&gff), &mut gzi).unwrap();
+    let gzi = parse_gzi(&gzi).unwrap();
+    let mut start = 0u64;
+    for (i, line) in text.split_inclusive(|&b| b == b'\n').enumerate() {
+        if i % 97 == 0 {
+            let voffset = uncompressed_to_voffset(&gzi, start).unwrap();
+            assert_eq!(record_at(&gff, voffset).unwrap().as_bytes(), line.strip_suffix(b"\n").unwrap());
+            assert_eq!(voffset_to_uncompressed(&gzi, voffset).unwrap(), start);
+        }
+        start += line.len() as u64;
+    }
+    for (i, &(coffset, ustart)) in gzi.iter().enumerate().skip(1) {
+        // Block boundaries land at the start of the later block.
+        assert_eq!(uncompressed_to_voffset(&gzi, ustart).unwrap(), coffset << 16);
+        assert_eq!(voffset_to_uncompressed(&gzi, gzi[i - 1].0 << 16 | (ustart - gzi[i - 1].1)).unwrap(), ustart);
+    }
+    assert_eq!(error_code(&voffset_to_uncompressed(&gzi, 3 << 16).unwrap_err()), ErrorCode::IndexMismatch);
+    // Entries handed over out of order fail instead of underflowing.
+    assert_eq!(error_code(&uncompressed_to_voffset(&[(400, 5000), (0, 6000)], 3000).unwrap_err()), ErrorCode::IndexParse);
+    assert_eq!(error_code(&voffset_to_uncompressed(&[(0, 0), (7, u64::MAX)], 7 << 16 | 1).unwrap_err()), ErrorCode::IndexParse);
+
+    // htslib's values for the tabix scheme and for the deeper CSI default.
+    assert_eq!(region_bin(0, 1, 14, 5).unwrap(), 4681);
+    assert_eq!(region_bin(16_384, 16_385, 14, 5).unwrap(), 4682);
+    assert_eq!(region_bin(0, 1 << 17, 14, 5).unwrap(), 585);
+    assert_eq!(region_bin(0, 1 << 29, 14, 5).unwrap(), 0);
+    assert_eq!(region_bin(0, 1, 14, 6).unwrap(), 37449);
+    assert_eq!(region_bins(0, 1, 14, 5).unwrap(), vec![0, 1, 9, 73, 585, 4681]);
+    let bins = region_bins(100_000, 300_000, 14, 5).unwrap();
+    assert!(bins.contains(&region_bin(100_000, 300_000, 14, 5).unwrap()) && bins.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(error_code(&region_bin(0, (1 << 29) + 1, 14, 5).unwrap_err()), ErrorCode::CoordinateLimit);
+    assert_eq!(error_code(&region_bins(0, 1, 14, 12).unwrap_err()), ErrorCode::InvalidOption);
+}
+
+/// samtools-style names follow the inputs, overrides are checked, and the
+/// manifest, browser configs and CLI use them.
+#[test]
+fn output_names_follow_samtools_and_overrides() {
+    use std::process::Command;
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::{IgvOptions, IndexGen, IndexGenOptions, JBrowseOptions, OutputNames};
+
+    let mut names = OutputNames::from_inputs("dir/genome.fa.gz", "ann.gff3").unwrap();
+    let expected = [
+        ("fasta.bgz", "genome.fa.gz"),
+        ("fasta.fai", "genome.fa.gz.fai"),
+        ("fasta.gzi", "genome.fa.gz.gzi"),
+        ("gff.bgz", "ann.gff3.gz"),
+        ("gff.csi", "ann.gff3.gz.csi"),
+        ("gff.tbi", "ann.gff3.gz.tbi"),
+        ("gff.search", "ann.gff3.gz.search"),
+    ];
+    for (artifact, name) in expected {
+        assert_eq!(names.get(artifact), name);
+    }
+    assert_eq!(OutputNames::new("out").get("gff.csi"), "out.gff.csi");
+    names.rename("gff.csi", "annotations.csi".into()).unwrap();
+    assert_eq!(names.get("gff.csi"), "annotations.csi");
+    for (artifact, name) in [("gff.bai", "x"), ("gff.csi", "a/b.csi"), ("gff.csi", "genome.fa.gz"), ("gff.csi", "..")] {
+        assert_eq!(error_code(&names.clone().rename(artifact, name.into()).unwrap_err()), ErrorCode::InvalidOption, "{} = {}", artifact, name);
+    }
+    assert!(OutputNames::from_inputs("-", "ann.gff3").is_err());
+    assert!(OutputNames::from_inputs("x.bgz", "x").is_err());
+
+    let (fa, gff) = (read_fixture(FASTA_FIXTURE), read_fixture(GFF_FIXTURE));
+    let out = IndexGen::from_bytes(&fa, &gff, &IndexGenOptions::default()).unwrap();
+    let manifest = out.manifest_named(&names);
+    for entry in manifest["artifacts"].members() {
+        assert_eq!(entry["file_name"].as_str(), Some(names.get(entry["name"].as_str().unwrap())));
+    }
+    let jb = JBrowseOptions { base_url: "/d/".into(), names: Some(names.clone()), ..JBrowseOptions::new("spn".into()) };
+    let config = out.jbrowse_config_json(&jb).unwrap();
+    assert_eq!(config["assemblies"][0]["sequence"]["adapter"]["gziLocation"]["uri"], "/d/genome.fa.gz.gzi");
+    assert_eq!(config["tracks"][0]["adapter"]["index"]["location"]["uri"], "/d/annotations.csi");
+    let mut igv = IgvOptions { url_template: "/d/{file}".into(), ..IgvOptions::new("spn".into()) };
+    assert_eq!(out.igv_session_json(&igv).unwrap()["reference"]["fastaURL"], "/d/spn.fasta.bgz");
+    igv.set_names(&names);
+    let session = out.igv_session_json(&igv).unwrap();
+    assert_eq!(session["reference"]["fastaURL"], "/d/genome.fa.gz");
+    assert_eq!(session["tracks"][0]["indexURL"], "/d/annotations.csi");
+
+    // The CLI writes the same names next to its prefix.
+    let dir = std::env::temp_dir().join(format!("mgnify-names-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_mgnify-preprocess"))
+        .args(["--naming", "samtools", "--name", "gff.csi=annotations.csi", FASTA_FIXTURE, GFF_FIXTURE])
+        .arg(dir.join("run"))
+        .output()
+        .unwrap()
+        .status;
+    assert!(status.success());
+    let cli_names = {
+        let mut n = OutputNames::from_inputs(FASTA_FIXTURE, GFF_FIXTURE).unwrap();
+        n.rename("gff.csi", "annotations.csi".into()).unwrap();
+        n
+    };
+    for (artifact, data) in out.artifacts().into_iter().filter(|(_, d)| !d.is_empty()) {
+        assert_eq!(fs::read(dir.join(cli_names.get(artifact))).unwrap(), data, "{}", artifact);
+    }
+    let manifest = json::parse(&fs::read_to_string(dir.join("run.manifest.json")).unwrap()).unwrap();
+    assert_eq!(manifest["artifacts"][4]["file_name"], "annotations.csi");
+    let _ = fs::remove_dir_all(&dir);
+}
+
+/// Seqids sort by the chosen collation whatever the locale, sequences stay
+/// together when the collation ties, and the manifest records it.
+#[test]
+fn gff_collation_orders_seqids_without_locale() {
+    use std::cmp::Ordering;
+    use mgnify_wasm::gff::{seqid_cmp, AttributeFilter, SortedGff, TieBreak};
+    use mgnify_wasm::warnings::Warnings;
+    use mgnify_wasm::{Collation, IndexGen, IndexGenOptions};
+
+    assert_eq!(seqid_cmp("chr10", "chr2", Collation::Bytewise), Ordering::Less);
+    assert_eq!(seqid_cmp("chr10", "chr2", Collation::Natural), Ordering::Greater);
+    assert_eq!(seqid_cmp("chr02", "chr10", Collation::Natural), Ordering::Less);
+    assert_eq!(seqid_cmp("Ctg_b", "ctga", Collation::Bytewise), Ordering::Less);
+    assert_eq!(seqid_cmp("ctg_b", "ctga", Collation::Dictionary), Ordering::Greater);
+    // Ties fall back to bytes: only identical seqids are equal.
+    assert_eq!(seqid_cmp("ctg.1", "ctg1", Collation::Dictionary), Ordering::Less);
+    assert_eq!(seqid_cmp("chr01", "chr1", Collation::Natural), Ordering::Less);
+    assert_eq!(seqid_cmp("chr1", "chr1", Collation::Natural), Ordering::Equal);
+
+    let gff = "##gff-version 3\n\
+        chr10\t.\tgene\t1\t9\t.\t+\t.\tID=a\n\
+        ctg1\t.\tgene\t1\t9\t.\t+\t.\tID=b\n\
+        chr2\t.\tgene\t5\t9\t.\t+\t.\tID=c\n\
+        ctg.1\t.\tgene\t1\t9\t.\t+\t.\tID=d\n\
+        chr2\t.\tgene\t1\t9\t.\t+\t.\tID=e\n\
+        ctg1\t.\tgene\t3\t9\t.\t+\t.\tID=f\n";
+    let order = |collation| {
+        let ties = TieBreak::default().with_collation(collation);
+        let mut out = Vec::new();
+        SortedGff::with_ties(gff, false, &ties, &mut Warnings::default()).write_to(&mut out, AttributeFilter::default()).unwrap();
+        String::from_utf8(out).unwrap().lines().filter_map(|l| l.split("ID=").nth(1).map(str::to_owned)).collect::<Vec<_>>()
+    };
+    assert_eq!(order(Collation::Bytewise), ["a", "e", "c", "d", "b", "f"]);
+    assert_eq!(order(Collation::Dictionary), ["a", "e", "c", "d", "b", "f"]);
+    assert_eq!(order(Collation::Natural), ["e", "c", "a", "d", "b", "f"]);
+
+    let fa = read_fixture(FASTA_FIXTURE);
+    let natural = IndexGenOptions { gff_collation: Collation::Natural, ..Default::default() };
+    let out = IndexGen::from_bytes(&fa, gff.as_bytes(), &natural).unwrap();
+    assert_eq!(out.manifest()["producer"]["collation"], "natural");
+    assert_eq!(IndexGen::from_bytes(&fa, gff.as_bytes(), &IndexGenOptions::default()).unwrap().manifest()["producer"]["collation"], "bytewise");
+    assert_ne!(natural.digest(), IndexGenOptions::default().digest());
+}
+
+/// A run's own BGZF outputs fed back in are kept as they are and only
+/// indexed; anything preprocessing would change takes the normal path.
+#[test]
+fn rerun_on_outputs_only_indexes_them() {
+    use mgnify_wasm::input::InputSource;
+    use mgnify_wasm::{IndexFormat, IndexGen, IndexGenOptions};
+
+    let (fa, gff) = (read_fixture(FASTA_FIXTURE), read_fixture(GFF_FIXTURE));
+    let options = IndexGenOptions { index_format: IndexFormat::Both, ..Default::default() }.with_default_search_keys();
+    let first = IndexGen::from_bytes(&fa, &gff, &options).unwrap();
+    let [fa_bgz, _, _, gff_bgz, ..] = first.artifacts();
+    let (fa_bgz, gff_bgz) = (fa_bgz.1.to_vec(), gff_bgz.1.to_vec());
+    let reused = |w: &IndexGen| {
+        let mut stages: Vec<&str> = w.warnings().warnings.iter().filter(|w| w.code == "already_preprocessed").map(|w| w.stage).collect();
+        stages.sort_unstable();
+        stages
+    };
+
+    // Same outputs, even at another compression level: nothing recompressed.
+    let level = IndexGenOptions { compression_level: 1, ..options.clone() };
+    let again = IndexGen::from_sources(InputSource::Bytes(fa_bgz.clone()), InputSource::Bytes(gff_bgz.clone()), &level).unwrap();
+    assert_eq!(reused(&again), ["fasta", "gff"]);
+    for ((name, ours), (_, theirs)) in again.artifacts().into_iter().zip(first.artifacts()) {
+        assert_eq!(ours, theirs, "{} differs", name);
+    }
+    assert!(again.warnings().warnings.iter().all(|w| w.message.contains("compression_level 1 was not applied")));
+    let same = IndexGen::from_sources(InputSource::Bytes(fa_bgz.clone()), InputSource::Bytes(gff_bgz.clone()), &options).unwrap();
+    assert!(same.warnings().warnings.iter().all(|w| !w.message.contains("compression_level")));
+
+    // A projection changes the GFF3, so it is recompressed; the FASTA is kept.
+    let projected = IndexGenOptions { attribute_blacklist: vec!["translation".to_owned()], ..options.clone() };
+    let out = IndexGen::from_sources(InputSource::Bytes(fa_bgz.clone()), InputSource::Bytes(gff_bgz.clone()), &projected).unwrap();
+    assert_eq!(reused(&out), ["fasta"]);
+    assert_eq!(out.artifacts()[3].1, IndexGen::from_bytes(&fa, &gff, &projected).unwrap().artifacts()[3].1);
+
+    // bgzip of an input that still needs preprocessing (the fixture's
+    // `##FASTA` section, or a truncated BGZF file) is treated as any input.
+    let mut raw_gff = Vec::new();
+    bgzf_compress(Cursor::new(&gff), &mut raw_gff).unwrap();
+    let out = IndexGen::from_sources(InputSource::Bytes(compress_fasta()), InputSource::Bytes(raw_gff), &options).unwrap();
+    assert_eq!(reused(&out), ["fasta"]);
+    assert_eq!(out.artifacts()[3].1, first.artifacts()[3].1);
+    let truncated = fa_bgz[..fa_bgz.len() - 28].to_vec();
+    let out = IndexGen::from_sources(InputSource::Bytes(truncated), InputSource::Bytes(gff_bgz), &options).unwrap();
+    assert_eq!(reused(&out), ["gff"]);
+    assert_eq!(out.artifacts()[0].1, first.artifacts()[0].1);
+}
+
+#[test]
+fn contig_progress_and_smoothed_eta() {
+    use mgnify_wasm::htslib::{faidx_index_reader, FaiOffsets};
+    use mgnify_wasm::progress::{ContigProgress, Eta, Extent};
+
+    // 100 bytes/ms, then a 300 bytes/ms spike only moves the rate by 0.3 of
+    // the difference.
+    let mut eta = Eta::default();
+    assert_eq!(eta.remaining_ms(1000), None);
+    eta.sample(0.0, 0);
+    eta.sample(10.0, 1000);
+    assert_eq!(eta.rate(), Some(100.0));
+    assert_eq!(eta.remaining_ms(11_000), Some(100.0));
+    eta.sample(10.0, 5000);
+    eta.sample(20.0, 4000);
+    assert_eq!(eta.rate(), Some(160.0));
+    assert_eq!(eta.remaining_ms(4000), Some(0.0));
+
+    let extent = Extent { bytes: Some(1000), contigs: Some(2) };
+    let mut progress = ContigProgress::new("faidx", extent);
+    assert!(progress.message()["contig"].is_null());
+    progress.start_contig("contig_1", 0);
+    progress.start_contig("contig_2", 400);
+    progress.advance(650);
+    let msg = progress.message();
+    assert_eq!((msg["stage"].as_str(), msg["done"].as_u64(), msg["total"].as_u64()), (Some("faidx"), Some(650), Some(1000)));
+    assert_eq!(msg["contig"]["index"].as_u64(), Some(2));
+    assert_eq!(msg["contig"]["count"].as_u64(), Some(2));
+    assert_eq!(msg["contig"]["name"].as_str(), Some("contig_2"));
+    assert_eq!(msg["contig"]["done"].as_u64(), Some(250));
+    assert!(ContigProgress::new("csi", Extent::default()).message()["eta_ms"].is_null());
+
+    // Tracking the reader leaves the index as it was.
+    let bgzf = compress_fasta();
+    let (mut fai, mut gzi) = (Vec::new(), Vec::new());
+    let reader = BgzfReader::new(Cursor::new(&bgzf)).track(ContigProgress::new("faidx", Extent::default()));
+    faidx_index_reader(reader, &mut fai, &mut gzi, FaiOffsets::Uncompressed).unwrap();
+    let (mut plain_fai, mut plain_gzi) = (Vec::new(), Vec::new());
+    faidx_index_fasta(Cursor::new(&bgzf), &mut plain_fai, &mut plain_gzi).unwrap();
+    assert_eq!((fai, gzi), (plain_fai, plain_gzi));
+}
+
+#[test]
+fn cooperative_run_matches_one_shot_run() {
+    use mgnify_wasm::{CooperativeRun, IndexFormat, IndexGen, IndexGenOptions, OutputContainer};
+
+    let (fa, gff) = (read_fixture(FASTA_FIXTURE), read_fixture(GFF_FIXTURE));
+    let both = IndexGenOptions { index_format: IndexFormat::Both, ..Default::default() }.with_default_search_keys();
+    let gzip = IndexGenOptions { fasta_output: OutputContainer::Gzip, gff_output: OutputContainer::Gzip, ..Default::default() };
+    for options in [both, gzip] {
+        let expected = IndexGen::from_bytes(&fa, &gff, &options).unwrap();
+        // A slice too short for more than one unit of work per step.
+        let mut run = CooperativeRun::start(fa.clone(), gff.clone(), &options, 1e-9).unwrap();
+        assert!(run.take_result().is_err());
+        let mut steps = 1;
+        while !run.run_slice().unwrap() {
+            steps += 1;
+        }
+        assert!(steps > 10, "{} steps", steps);
+        let out = run.take_result().unwrap();
+        for ((name, ours), (_, theirs)) in out.artifacts().into_iter().zip(expected.artifacts()) {
+            assert_eq!(ours, theirs, "{} differs", name);
+        }
+        assert_eq!(out.warnings().warnings, expected.warnings().warnings);
+        assert!(run.take_result().is_err());
+    }
+
+    // A failing step ends the run.
+    let mut run = CooperativeRun::start(fa.clone(), b"chr1\t.\tgene\t1\t9\t.\t+\t.\tID=\xff\n".to_vec(), &IndexGenOptions::default(), 1e3).unwrap();
+    while run.run_slice().map_err(|e| assert!(e.to_string().contains("UTF-8"), "{}", e)).is_ok_and(|done| !done) {}
+    assert_eq!(run.stage(), "over");
+    assert!(run.run_slice().is_err());
+    assert!(CooperativeRun::start(fa, gff, &IndexGenOptions::default(), 0.0).is_err());
+}
+
+#[test]
+fn upload_chunk_plan_checksums_every_chunk() {
+    use mgnify_wasm::htslib::crc32;
+    use mgnify_wasm::upload::{chunk_plan, HeldOutput, UploadChunks};
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let out = IndexGen::from_bytes(&read_fixture(FASTA_FIXTURE), &read_fixture(GFF_FIXTURE), &IndexGenOptions::default()).unwrap();
+    let bgz = out.artifacts()[0].1;
+    for chunk_size in [1000, bgz.len() as u64, bgz.len() as u64 / 2, 1 << 30] {
+        let plan = chunk_plan(bgz, chunk_size).unwrap();
+        assert_eq!(plan.size, bgz.len() as u64);
+        assert_eq!(plan.crc32, crc32(bgz));
+        assert_eq!(plan.chunks.len() as u64, (bgz.len() as u64).div_ceil(chunk_size));
+        let mut offset = 0;
+        for chunk in &plan.chunks {
+            assert_eq!(chunk.offset, offset);
+            assert!(chunk.size == chunk_size || chunk.offset + chunk.size == plan.size);
+            assert_eq!(chunk.crc32, crc32(&bgz[offset as usize..(offset + chunk.size) as usize]));
+            offset += chunk.size;
+        }
+        assert_eq!(offset, plan.size);
+    }
+
+    let chunks = UploadChunks::new("fasta.bgz", HeldOutput::Bytes(bgz.to_vec()), 1000).unwrap();
+    let json = json::parse(&chunks.plan_json()).unwrap();
+    assert_eq!(json["artifact"], "fasta.bgz");
+    assert_eq!(json["chunk_count"], chunks.chunk_count());
+    assert_eq!(json["crc32"], format!("{:08x}", crc32(bgz)).as_str());
+    assert_eq!(json["chunks"][1]["offset"], 1000);
+
+    assert!(chunk_plan(&b""[..], 10).unwrap().chunks.is_empty());
+    assert!(chunk_plan(bgz, 0).is_err());
+}
+
+#[test]
+fn upload_parts_start_at_bgzf_blocks() {
+    use mgnify_wasm::htslib::{crc32, plausible_block_header};
+    use mgnify_wasm::upload::{part_plan, PartLimits};
+
+    let mut state = 7u32;
+    let seq: Vec<u8> = (0..4 << 20)
+        .map(|_| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            b"ACGT"[(state >> 30) as usize]
+        })
+        .collect();
+    let mut data = Vec::new();
+    bgzf_compress(Cursor::new(&seq), &mut data).unwrap();
+    let size = data.len() as u64;
+
+    for preferred in [None, Some(250_000)] {
+        let limits = PartLimits::from_sizes(100_000, 300_000, 20, preferred).unwrap();
+        let plan = part_plan(|| Ok(&data[..]), size, &limits).unwrap();
+        assert!(plan.block_aligned);
+        assert!(plan.chunks.len() <= 20 && plan.chunks.len() > 1, "{} parts", plan.chunks.len());
+        assert_eq!(plan.crc32, crc32(&data));
+        let mut inflated = Vec::new();
+        for (i, part) in plan.chunks.iter().enumerate() {
+            assert!(part.size <= 300_000);
+            assert!(part.size >= 100_000 || i + 1 == plan.chunks.len());
+            assert!(part.size >= plan.chunk_size || i + 1 == plan.chunks.len());
+            let bytes = &data[part.offset as usize..(part.offset + part.size) as usize];
+            assert!(plausible_block_header(bytes));
+            assert_eq!(part.crc32, crc32(bytes));
+            BgzfReader::new(bytes).read_to_end(&mut inflated).unwrap();
+        }
+        assert_eq!(inflated, seq);
+    }
+
+    // Anything else is cut at the exact part size.
+    let limits = PartLimits::from_sizes(1 << 20, 2 << 20, 10, None).unwrap();
+    let plan = part_plan(|| Ok(&seq[..]), seq.len() as u64, &limits).unwrap();
+    assert!(!plan.block_aligned);
+    assert_eq!(plan.chunks.iter().map(|c| c.size).collect::<Vec<_>>(), vec![1 << 20; 4]);
+
+    assert!(part_plan(|| Ok(&data[..]), size, &PartLimits::from_sizes(10, 20, 2, None).unwrap()).is_err());
+    assert!(PartLimits::from_sizes(10, 5, 1, None).is_err());
+    assert!(PartLimits::from_sizes(0, 5, 1, None).is_err());
+}
+
+#[test]
+fn split_at_gaps_moves_features_onto_contigs() {
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::transform::Pipeline;
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    // scaf1: a 12 N gap at 11..22 and a 2 N run kept; scaf3: a leading gap.
+    let scaf1 = format!("ACGTACGTAC{}GGCCGGCCGGNNTTTT", "N".repeat(12));
+    let fa = format!(">scaf1 chromosome 1\n{}\n{}\n>scaf2\nACGT\n>scaf3\n{}ACGTACGT\n", &scaf1[..20], &scaf1[20..], "N".repeat(12));
+    let gff = "##gff-version 3\n##sequence-region scaf1 1 38\n\
+               scaf1\t.\tgene\t2\t8\t.\t+\t.\tID=a\n\
+               scaf1\t.\tgene\t5\t30\t.\t+\t.\tID=across\n\
+               scaf1\t.\tgene\t12\t20\t.\t+\t.\tID=inside\n\
+               scaf1\t.\tgene\t25\t30\t.\t-\t.\tID=b\n\
+               scaf2\t.\tgene\t1\t4\t.\t+\t.\tID=c\n\
+               scaf3\t.\tgene\t13\t20\t.\t+\t.\tID=d\n";
+    let options = IndexGenOptions { fasta_transforms: vec!["split_at_gaps:min_n=10".into()], ..Default::default() };
+    assert_eq!(Pipeline::gff(&options).unwrap().names().last(), Some(&"remap_contigs"));
+    let out = IndexGen::from_bytes(fa.as_bytes(), gff.as_bytes(), &options).unwrap();
+
+    let fai = String::from_utf8(out.artifacts()[1].1.to_vec()).unwrap();
+    let lengths: Vec<(&str, &str)> = fai.lines().map(|l| (l.split('\t').next().unwrap(), l.split('\t').nth(1).unwrap())).collect();
+    assert_eq!(lengths, [("scaf1_1", "10"), ("scaf1_2", "16"), ("scaf2", "4"), ("scaf3", "8")]);
+    let mut fasta = String::new();
+    BgzfReader::new(out.artifacts()[0].1).read_to_string(&mut fasta).unwrap();
+    assert!(fasta.starts_with(">scaf1_1 chromosome 1\nACGTACGTAC\n>scaf1_2 chromosome 1\nGGCCGGCCGGNNTTTT\n"));
+
+    let mut text = String::new();
+    BgzfReader::new(out.artifacts()[3].1).read_to_string(&mut text).unwrap();
+    assert!(text.contains("##sequence-region scaf1_1 1 10\n##sequence-region scaf1_2 1 16\n"));
+    let records: Vec<Vec<&str>> = text.lines().filter(|l| !l.starts_with('#')).map(|l| l.split('\t').collect()).collect();
+    let placed: Vec<(&str, &str, &str, &str)> = records.iter().map(|r| (r[0], r[3], r[4], r[8])).collect();
+    assert_eq!(placed, [("scaf1_1", "2", "8", "ID=a"), ("scaf1_2", "3", "8", "ID=b"), ("scaf2", "1", "4", "ID=c"), ("scaf3", "1", "8", "ID=d")]);
+    let warnings = out.warnings().to_json();
+    assert_eq!(warnings["counts"]["feature_spans_gap"], 2);
+    assert_eq!(warnings["counts"]["scaffold_split"], 2);
+
+    let map = json::parse(&out.contig_map_json()).unwrap();
+    assert_eq!(map["min_gap"], 10);
+    assert_eq!(map["scaffolds"].len(), 2);
+    assert_eq!(map["scaffolds"][0]["length"], 38);
+    assert_eq!(map["scaffolds"][0]["contigs"][1]["name"], "scaf1_2");
+    assert_eq!((map["scaffolds"][0]["contigs"][1]["start"].as_u64(), map["scaffolds"][0]["contigs"][1]["end"].as_u64()), (Some(23), Some(38)));
+    assert_eq!(map["scaffolds"][1]["contigs"][0]["name"], "scaf3");
+
+    // Without gaps everything is left alone.
+    let plain = IndexGen::from_bytes(b">s\nACGT\n", b"##gff-version 3\ns\t.\tgene\t1\t4\t.\t+\t.\tID=g\n", &options).unwrap();
+    assert!(plain.warnings().to_json()["counts"]["scaffold_split"].is_null());
+
+    let taken = format!("{}>scaf1_2\nACGT\n", fa);
+    let err = IndexGen::from_bytes(taken.as_bytes(), gff.as_bytes(), &options).err().unwrap();
+    assert_eq!(error_code(&err), ErrorCode::FastaDupName);
+    let [fasta_bgz, fasta_fai, fasta_gzi] = [0, 1, 2].map(|i| out.artifacts()[i].1.to_vec());
+    let err = IndexGen::with_fasta_artifacts(fasta_bgz, fasta_fai, fasta_gzi, gff.as_bytes(), &options).err().unwrap();
+    assert_eq!(error_code(&err), ErrorCode::InvalidOption);
+    let twice = IndexGenOptions { fasta_transforms: vec!["split_at_gaps".into(), "split_at_gaps:min_n=5".into()], ..Default::default() };
+    assert!(Pipeline::fasta(&twice).is_err());
+}
+
+#[test]
+fn gap_track_lists_n_runs_with_an_index() {
+    use mgnify_wasm::htslib::CsiIndex;
+    use mgnify_wasm::{IndexGen, IndexGenOptions, TrackFormat};
+
+    let scaf1 = format!("ACGTACGTAC{}GGCCGGCCGG{}TT{}", "N".repeat(12), "n".repeat(5), "N".repeat(30));
+    let fa = format!(">scaf1\n{}\n{}\n>scaf2\nACGTACGT\n>scaf3\n{}ACGT\n", &scaf1[..25], &scaf1[25..], "N".repeat(10));
+    let gff = b"##gff-version 3\nscaf2\t.\tgene\t1\t8\t.\t+\t.\tID=g\n";
+    let track_text = |out: &IndexGen| {
+        let mut text = String::new();
+        BgzfReader::new(out.track("gaps").unwrap().bgz_bytes()).read_to_string(&mut text).unwrap();
+        text
+    };
+
+    assert!(IndexGen::from_bytes(fa.as_bytes(), gff, &IndexGenOptions::default()).unwrap().track("gaps").is_none());
+    let bed = IndexGenOptions { gap_track: TrackFormat::Bed, ..Default::default() };
+    let out = IndexGen::from_bytes(fa.as_bytes(), gff, &bed).unwrap();
+    assert_eq!(track_text(&out), "scaf1\t10\t22\tgap_1\nscaf1\t39\t69\tgap_2\nscaf3\t0\t10\tgap_3\n");
+    let track = out.track("gaps").unwrap();
+    assert_eq!(track.extension(), "bed");
+    let stats = track.stats();
+    assert_eq!([&stats["count"], &stats["total_length"], &stats["max_length"], &stats["sequences"]], [3, 52, 30, 2]);
+    let csi = CsiIndex::from_bgzf(track.csi_bytes()).unwrap();
+    assert_eq!(csi.names, ["scaf1", "scaf3"]);
+    assert!(!csi.query("scaf1", 40, 41).is_empty());
+
+    let gff3 = IndexGenOptions { gap_track: TrackFormat::Gff, gap_min_length: 5, ..Default::default() };
+    let out = IndexGen::from_bytes(fa.as_bytes(), gff, &gff3).unwrap();
+    let text = track_text(&out);
+    assert!(text.starts_with("##gff-version 3\nscaf1\t.\tgap\t11\t22\t.\t.\t.\tID=gap_1;estimated_length=12\nscaf1\t.\tgap\t33\t37\t"));
+    assert_eq!(text.lines().count(), 5);
+    assert_eq!(out.track("gaps").unwrap().extension(), "gff3");
+    assert_ne!(gff3.digest(), bed.digest());
+
+    let report = IndexGen::dry_run_bytes(fa.as_bytes(), gff, &bed);
+    assert_eq!(report["stats"]["fasta"]["gaps"]["count"], 3);
+    assert_eq!(report["stats"]["fasta"]["gaps"]["min_gap"], 10);
+}
+
+#[test]
+fn repeat_track_finds_homopolymers_and_tandem_repeats() {
+    use mgnify_wasm::htslib::CsiIndex;
+    use mgnify_wasm::{IndexGen, IndexGenOptions, TrackFormat};
+
+    // A homopolymer of 10, (AT)x8 across a line break, (CAG)x3 and T x7 too
+    // short, then (ac)x7 after a gap.
+    let seq1 = format!("ACGT{}CG{}GC{}{}GC", "A".repeat(10), "AT".repeat(8), "CAG".repeat(3), "T".repeat(7));
+    let fa = format!(">seq1\n{}\n{}\n>seq2\n{}{}\n", &seq1[..20], &seq1[20..], "N".repeat(20), "ac".repeat(7));
+    let gff = b"##gff-version 3\nseq1\t.\tgene\t1\t8\t.\t+\t.\tID=g\n";
+    let track_text = |out: &IndexGen| {
+        let mut text = String::new();
+        BgzfReader::new(out.track("repeats").unwrap().bgz_bytes()).read_to_string(&mut text).unwrap();
+        text
+    };
+
+    assert!(IndexGen::from_bytes(fa.as_bytes(), gff, &IndexGenOptions::default()).unwrap().track_names().is_empty());
+    let bed = IndexGenOptions { repeat_track: TrackFormat::Bed, gap_track: TrackFormat::Bed, ..Default::default() };
+    let out = IndexGen::from_bytes(fa.as_bytes(), gff, &bed).unwrap();
+    assert_eq!(out.track_names(), ["gaps", "repeats"]);
+    assert_eq!(track_text(&out), "seq1\t4\t14\trepeat_1\nseq1\t16\t32\trepeat_2\nseq2\t20\t34\trepeat_3\n");
+    let track = out.track("repeats").unwrap();
+    let stats = track.stats();
+    assert_eq!([&stats["homopolymers"], &stats["tandem_repeats"], &stats["total_length"], &stats["max_length"]], [1, 2, 40, 16]);
+    let csi = CsiIndex::from_bgzf(track.csi_bytes()).unwrap();
+    assert_eq!(csi.names, ["seq1", "seq2"]);
+    assert!(!csi.query("seq2", 25, 26).is_empty());
+
+    let gff3 = IndexGenOptions { repeat_track: TrackFormat::Gff, homopolymer_min_length: 11, tandem_min_length: 15, ..Default::default() };
+    let out = IndexGen::from_bytes(fa.as_bytes(), gff, &gff3).unwrap();
+    assert_eq!(
+        track_text(&out),
+        "##gff-version 3\nseq1\t.\ttandem_repeat\t17\t32\t.\t.\t.\tID=repeat_1;rpt_type=tandem;rpt_unit_seq=at;copies=8\n"
+    );
+    assert_eq!(out.track("repeats").unwrap().extension(), "gff3");
+    assert_ne!(gff3.digest(), bed.digest());
+}
+
+#[test]
+fn orf_track_lists_six_frame_orfs() {
+    use mgnify_wasm::htslib::CsiIndex;
+    use mgnify_wasm::{IndexGen, IndexGenOptions, TrackFormat};
+
+    // seq1: ATG (GCC)x9 TGA on the forward strand, lower case; seq2: the
+    // reverse complement of ATG (AAA)x10 TAG; seq3: an ORF broken by Ns.
+    let seq1 = format!("cc{}tgacc", "atg".to_owned() + &"gcc".repeat(9));
+    let seq2 = format!("GGCTA{}CATGG", "TTT".repeat(10));
+    let seq3 = format!("ATG{}NNN{}TAA", "GCC".repeat(5), "GCC".repeat(5));
+    let fa = format!(">seq1\n{}\n{}\n>seq2\n{}\n>seq3\n{}\n", &seq1[..20], &seq1[20..], seq2, seq3);
+    let gff = b"##gff-version 3\nseq1\t.\tgene\t1\t8\t.\t+\t.\tID=g\n";
+    let track_text = |out: &IndexGen| {
+        let mut text = String::new();
+        BgzfReader::new(out.track("orfs").unwrap().bgz_bytes()).read_to_string(&mut text).unwrap();
+        text
+    };
+
+    let defaults = IndexGenOptions { orf_track: TrackFormat::Gff, ..Default::default() };
+    assert_eq!(track_text(&IndexGen::from_bytes(fa.as_bytes(), gff, &defaults).unwrap()), "##gff-version 3\n");
+    let gff3 = IndexGenOptions { orf_track: TrackFormat::Gff, orf_min_length: 30, ..Default::default() };
+    let out = IndexGen::from_bytes(fa.as_bytes(), gff, &gff3).unwrap();
+    assert_eq!(
+        track_text(&out),
+        "##gff-version 3\n\
+         seq1\t.\tORF\t3\t35\t.\t+\t.\tID=orf_1;start_codon=ATG;aa_length=10\n\
+         seq2\t.\tORF\t3\t38\t.\t-\t.\tID=orf_2;start_codon=ATG;aa_length=11\n"
+    );
+    let track = out.track("orfs").unwrap();
+    assert_eq!([&track.stats()["count"], &track.stats()["forward"], &track.stats()["reverse"], &track.stats()["max_length"]], [2, 1, 1, 36]);
+    let csi = CsiIndex::from_bgzf(track.csi_bytes()).unwrap();
+    assert_eq!(csi.names, ["seq1", "seq2"]);
+
+    let bed = IndexGenOptions { orf_track: TrackFormat::Bed, ..gff3.clone() };
+    let out = IndexGen::from_bytes(fa.as_bytes(), gff, &bed).unwrap();
+    assert_eq!(track_text(&out), "seq1\t2\t35\torf_1\t0\t+\nseq2\t2\t38\torf_2\t0\t-\n");
+    assert_ne!(gff3.digest(), defaults.digest());
+}
+
+#[test]
+fn rna_track_flags_rrna_anchors_and_trna_cloverleaves() {
+    use mgnify_wasm::htslib::CsiIndex;
+    use mgnify_wasm::{IndexGen, IndexGenOptions, TrackFormat};
+
+    let place = |len: usize, words: &[(usize, &str)]| {
+        let mut seq = "CA".repeat(len / 2).into_bytes();
+        for (at, word) in words {
+            seq[*at..at + word.len()].copy_from_slice(word.as_bytes());
+        }
+        String::from_utf8(seq).unwrap()
+    };
+    let rc = |s: &str| String::from_utf8(mgnify_wasm::translate::reverse_complement(s.as_bytes())).unwrap();
+    // seq1: three 16S anchors of a gene starting at 100; seq2: a lone 16S
+    // anchor and yeast tRNA-Phe at 200; seq3: two 23S anchors of a gene
+    // starting at 50, reverse complemented.
+    let seq1 = place(2000, &[(107, "AGAGTTTGATCATGGCTCAG"), (614, "GTGCCAGCAGCCGCGGTAA"), (1573, "AAGTCGTAACAAGGTAACC")]);
+    let trna = "GCGGATTTAGCTCAGTTGGGAGAGCGCCAGACTGAAGATCTGGAGGTCCTGTGTTCGATCCACAGAATTCGCACCA";
+    let seq2 = place(400, &[(20, "AAACTCAAAGGAATTGACGG"), (200, trna)]);
+    let seq3 = rc(&place(3000, &[(72, "ACGGTGGATGCCTTG"), (2274, "AGTTTAACTGGGGCGGT")]));
+    let fa = format!(">seq1\n{}\n>seq2\n{}\n>seq3\n{}\n", seq1, seq2, seq3);
+    let gff = b"##gff-version 3\nseq1\t.\tgene\t1\t8\t.\t+\t.\tID=g\n";
+    let track_text = |out: &IndexGen| {
+        let mut text = String::new();
+        BgzfReader::new(out.track("rna").unwrap().bgz_bytes()).read_to_string(&mut text).unwrap();
+        text
+    };
+
+    let gff3 = IndexGenOptions { rna_track: TrackFormat::Gff, ..Default::default() };
+    let out = IndexGen::from_bytes(fa.as_bytes(), gff, &gff3).unwrap();
+    assert_eq!(
+        track_text(&out),
+        "##gff-version 3\n\
+         seq1\t.\trRNA\t101\t1642\t.\t+\t.\tID=rna_1;Name=16S_rRNA;anchors=3/8;Note=low-confidence quick scan\n\
+         seq2\t.\ttRNA\t201\t273\t.\t+\t.\tID=rna_2;Name=tRNA;Note=low-confidence quick scan\n\
+         seq3\t.\trRNA\t47\t2950\t.\t-\t.\tID=rna_3;Name=23S_rRNA;anchors=2/3;Note=low-confidence quick scan\n"
+    );
+    let track = out.track("rna").unwrap();
+    assert_eq!([&track.stats()["rrna_16s"], &track.stats()["rrna_23s"], &track.stats()["trna"]], [1, 1, 1]);
+    assert_eq!(CsiIndex::from_bgzf(track.csi_bytes()).unwrap().names, ["seq1", "seq2", "seq3"]);
+
+    let bed = IndexGenOptions { rna_track: TrackFormat::Bed, ..Default::default() };
+    let out = IndexGen::from_bytes(fa.as_bytes(), gff, &bed).unwrap();
+    assert!(track_text(&out).starts_with("seq1\t100\t1642\trna_1\t0\t+\n"));
+    assert_ne!(gff3.digest(), bed.digest());
+}
+
+#[test]
+fn depth_files_become_a_bedgraph_track_and_mean_depths() {
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::htslib::CsiIndex;
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let fa = b">c1\nACGTACGTAC\n>c2\nACGTAC\n>c3\nACGT\n";
+    let gff = b"##gff-version 3\nc1\t.\tgene\t1\t8\t.\t+\t.\tID=g\n";
+    let options = IndexGenOptions::default();
+    let track_text = |out: &IndexGen| {
+        let mut text = String::new();
+        BgzfReader::new(out.track("coverage").unwrap().bgz_bytes()).read_to_string(&mut text).unwrap();
+        text
+    };
+
+    // samtools depth -H, two samples on c2, c1 position 4 left out.
+    let per_base = b"#CHROM\tPOS\ta.bam\nc1\t1\t3\nc1\t2\t3\nc1\t3\t4\nc1\t5\t4\nc2\t1\t1\t1\n";
+    let mut out = IndexGen::from_bytes(fa, gff, &options).unwrap();
+    assert!(out.track("coverage").is_none());
+    out.add_coverage_bytes(per_base, &options).unwrap();
+    assert_eq!(track_text(&out), "c1\t0\t2\t3\nc1\t2\t3\t4\nc1\t4\t5\t4\nc2\t0\t1\t2\n");
+    let track = out.track("coverage").unwrap();
+    assert_eq!(track.extension(), "bedgraph");
+    assert_eq!(CsiIndex::from_bgzf(track.csi_bytes()).unwrap().names, ["c1", "c2"]);
+    let stats = track.stats();
+    assert_eq!((stats["format"].as_str(), stats["mean_depth"].as_f64(), stats["sequences_covered"].as_u64()), (Some("per_base"), Some(0.8), Some(2)));
+    let means = &stats["mean_depth_per_sequence"];
+    assert_eq!([means["c1"].as_f64(), means["c2"].as_f64(), means["c3"].as_f64()], [Some(1.4), Some(0.33), Some(0.0)]);
+
+    let jgi = b"contigName\tcontigLen\ttotalAvgDepth\ta.bam\ta.bam-var\nc1\t10\t12.5\t12.5\t1\nc3\t4\t2\t2\t0\n";
+    out.add_coverage_bytes(jgi, &options).unwrap();
+    assert_eq!(track_text(&out), "c1\t0\t10\t12.5\nc3\t0\t4\t2\n");
+    assert_eq!(out.track("coverage").unwrap().stats()["mean_depth"].as_f64(), Some(6.65));
+
+    for (depth, code) in [
+        (&b"c9\t1\t3\n"[..], ErrorCode::UnknownSequence),
+        (b"c1\t2\t3\nc1\t1\t3\n", ErrorCode::DepthParse),
+        (b"c1\t1\t3\nc2\t1\t3\nc1\t2\t3\n", ErrorCode::DepthParse),
+        (b"c1\t11\t3\n", ErrorCode::DepthParse),
+        (b"c1\t1\tdeep\n", ErrorCode::DepthParse),
+        (b"contigName\tcontigLen\ttotalAvgDepth\nc1\t12\t3\n", ErrorCode::DepthParse),
+    ] {
+        assert_eq!(error_code(&out.add_coverage_bytes(depth, &options).unwrap_err()), code);
+    }
+
+    let report = IndexGen::dry_run_with_depth(fa, gff, Some(per_base), &options);
+    assert_eq!(report["stats"]["coverage"]["mean_depth_per_sequence"]["c1"].as_f64(), Some(1.4));
+    assert!(IndexGen::dry_run_bytes(fa, gff, &options)["stats"]["coverage"].is_null());
+    let report = IndexGen::dry_run_with_depth(fa, gff, Some(b"c9\t1\t3\n"), &options);
+    assert!(report["stats"]["coverage"].is_null());
+    assert_eq!(report["warnings"]["counts"]["depth_ignored"], 1);
+}
+
+#[test]
+fn bins_table_becomes_a_coloured_bed_track_and_bin_stats() {
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let fa = b">c1\nACGTACGTAC\n>c2\nGGGCCC\n>c3\nACGT\n>c4\nAAAA\n";
+    let gff = b"##gff-version 3\nc1\t.\tgene\t1\t8\t.\t+\t.\tID=g\n";
+    let options = IndexGenOptions::default();
+    let mut out = IndexGen::from_bytes(fa, gff, &options).unwrap();
+    assert!(out.track("bins").is_none());
+    out.add_bins_bytes(b"contig\tbin\n# DAS Tool\nc3\tbinB\nc1\tbinA\nc2\tbinA\n", &options).unwrap();
+    let track = out.track("bins").unwrap();
+    let mut text = String::new();
+    BgzfReader::new(track.bgz_bytes()).read_to_string(&mut text).unwrap();
+    assert_eq!(
+        text,
+        "c1\t0\t10\tbinA\t0\t.\t0\t10\t78,121,167\nc2\t0\t6\tbinA\t0\t.\t0\t6\t78,121,167\nc3\t0\t4\tbinB\t0\t.\t0\t4\t242,142,43\n"
+    );
+    let stats = track.stats();
+    let a = &stats["bins"][0];
+    assert_eq!((a["name"].as_str(), a["color"].as_str(), a["contigs"].as_u64()), (Some("binA"), Some("#4e79a7"), Some(2)));
+    assert_eq!((a["size"].as_u64(), a["n50"].as_u64(), a["gc_fraction"].as_f64()), (Some(16), Some(10), Some(0.6875)));
+    assert_eq!((stats["bins"][1]["name"].as_str(), stats["bins"][1]["gc_fraction"].as_f64()), (Some("binB"), Some(0.5)));
+    assert_eq!((stats["unbinned"]["contigs"].as_u64(), stats["unbinned"]["size"].as_u64()), (Some(1), Some(4)));
+
+    for (tsv, code) in [
+        (&b"c1\tbinA\nc9\tbinB\n"[..], ErrorCode::UnknownSequence),
+        (b"c1\tbinA\nc1\tbinB\n", ErrorCode::BinsParse),
+        (b"c1\n", ErrorCode::BinsParse),
+    ] {
+        assert_eq!(error_code(&out.add_bins_bytes(tsv, &options).unwrap_err()), code);
+    }
+}
+
+#[test]
+fn gfa_graphs_are_checked_against_the_fasta() {
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::gfa::Gfa;
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let fa = b">a\nACGTACGTAC\n>b\nACGTAC\n>c\nACGT\n";
+    let gff = b"##gff-version 3\na\t.\tgene\t1\t8\t.\t+\t.\tID=g\n";
+    let out = IndexGen::from_bytes(fa, gff, &IndexGenOptions::default()).unwrap();
+    // The link to b comes before b; x is in no link.
+    let gfa = b"H\tVN:Z:1.0\nL\ta\t+\tb\t-\t0M\nS\ta\tACGTACGTAC\nS\tb\t*\tLN:i:7\nS\tx\tACGT\tRC:i:3\nL\tb\t+\tb\t+\t0M\nP\tp1\ta+,b-\t*\n";
+    let report = out.check_gfa_bytes(gfa).unwrap();
+    assert_eq!((report["version"].as_str(), report["segments"].as_u64(), report["total_length"].as_u64()), (Some("1.0"), Some(3), Some(21)));
+    assert_eq!((report["links"].as_u64(), report["self_loops"].as_u64(), report["paths"].as_u64()), (Some(2), Some(1), Some(1)));
+    assert_eq!((report["dead_ends"].as_u64(), report["isolated_segments"].as_u64(), report["components"].as_u64()), (Some(3), Some(1), Some(2)));
+    let fasta = &report["fasta"];
+    assert_eq!((fasta["consistent"].as_bool(), fasta["matched"].as_u64()), (Some(false), Some(1)));
+    assert_eq!(fasta["missing_from_fasta"], json::array!["x"]);
+    assert_eq!(fasta["missing_from_graph"], json::array!["c"]);
+    assert_eq!(fasta["length_mismatches"], json::array![json::object! { name: "b", gfa: 7, fasta: 6 }]);
+
+    assert_eq!(Gfa::parse(gfa, true).unwrap().segments_fasta(), b">a\nACGTACGTAC\n>x\nACGT\n");
+    assert!(Gfa::parse(gfa, false).unwrap().segments_fasta().is_empty());
+
+    for bad in [
+        &b"S\ta\tACGT\nL\ta\t+\tz\t+\t0M\n"[..],
+        b"S\ta\tACGT\nS\ta\tACGT\n",
+        b"S\ta\t*\n",
+        b"S\ta\tACGT\tLN:i:5\n",
+        b"H\tVN:Z:2.0\n",
+        b"S\ta\tACGT\nL\ta\t?\ta\t+\t0M\n",
+    ] {
+        assert_eq!(error_code(&out.check_gfa_bytes(bad).unwrap_err()), ErrorCode::GfaParse);
+    }
+}
+
+#[test]
+fn replicon_metadata_tags_fasta_headers_and_gff_regions() {
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::{IndexGen, IndexGenBuilder};
+
+    let fa = b">chr1 E. coli [topology=linear]\nACGTACGT\n>p1\nACGT\n>p2\nAC\n";
+    // The gene crosses the origin of chr1 once the table makes it circular.
+    let gff = b"##gff-version 3\n##sequence-region p1 1 4\nchr1\t.\tregion\t1\t8\t.\t+\t.\tID=chr1;Is_circular=false;genome=genomic\nchr1\t.\tgene\t7\t10\t.\t+\t.\tID=g\n";
+    let metadata = "contig\treplicon\ttopology\n# from the assembler\nchr1\tchromosome\tcircular\np1\tPlasmid\tcircular\np2\tplasmid\tlinear\n";
+    let options = IndexGenBuilder::new().replicon_metadata(metadata.to_owned()).options();
+    let out = IndexGen::from_bytes(fa, gff, &options).unwrap();
+
+    let mut fasta = String::new();
+    BgzfReader::new(out.artifacts()[0].1).read_to_string(&mut fasta).unwrap();
+    let headers: Vec<&str> = fasta.lines().filter(|l| l.starts_with('>')).collect();
+    assert_eq!(
+        headers,
+        [
+            ">chr1 E. coli [location=chromosome] [topology=circular]",
+            ">p1 [location=plasmid] [topology=circular]",
+            ">p2 [location=plasmid] [topology=linear]"
+        ]
+    );
+    let mut text = String::new();
+    BgzfReader::new(out.artifacts()[3].1).read_to_string(&mut text).unwrap();
+    let regions: Vec<(&str, &str)> = text.lines().map(|l| l.split('\t').collect::<Vec<_>>()).filter(|r| r.len() == 9 && r[2] == "region").map(|r| (r[0], r[8])).collect();
+    assert_eq!(regions, [("chr1", "ID=chr1;genome=chromosome;Is_circular=true"), ("p1", "genome=plasmid;Is_circular=true")]);
+    let warnings = out.warnings().to_json();
+    assert_eq!(warnings["counts"]["origin_split"], 1);
+    assert_eq!(warnings["counts"]["replicon_region_missing"], 1);
+
+    for (metadata, code) in [
+        ("chr1\tchromosome\tcircular\np9\tplasmid\tlinear\n", ErrorCode::UnknownSequence),
+        ("chr1\tchromosome\tcircular\nchr1\tplasmid\tlinear\n", ErrorCode::InvalidOption),
+        ("chr1\tchromosome\tcircular\np1\tplasmid\tknotted\n", ErrorCode::InvalidOption),
+        ("chr1\tchromosome\tcircular\np1\tbig plasmid\tlinear\n", ErrorCode::InvalidOption),
+    ] {
+        let options = IndexGenBuilder::new().replicon_metadata(metadata.to_owned()).options();
+        assert_eq!(IndexGen::from_bytes(fa, gff, &options).err().map(|e| error_code(&e)), Some(code));
+    }
+}
+
+#[test]
+fn genetic_code_tables_follow_options_and_transl_table() {
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::export::embl_feature_table_with_code;
+    use mgnify_wasm::fasta::Sequences;
+    use mgnify_wasm::translate::GeneticCode;
+    use mgnify_wasm::{IndexGen, IndexGenOptions, TrackFormat};
+
+    // ATG TGA ATA AGA CTG under tables that read them differently.
+    let codons = b"ATGTGAATAAGACTG";
+    for (table, aa) in [(11, "M*IRL"), (4, "MWIRL"), (2, "MWM*L"), (3, "MWMRT"), (12, "M*IRS"), (25, "MGIRL")] {
+        assert_eq!(GeneticCode::table(table).unwrap().translate(codons, true), aa, "table {}", table);
+    }
+    assert!(GeneticCode::ids().any(|id| id == 33));
+    assert_eq!(GeneticCode::table(7).err().map(|e| error_code(&e)), Some(ErrorCode::InvalidOption));
+
+    // transl_table wins over the default, which applies to the rest.
+    let seqs = Sequences::parse(b">chr\nATGTGAGCCTAA\n").unwrap();
+    let translation = |gff: &str, default: u32| {
+        let embl = embl_feature_table_with_code(gff, &seqs, default).unwrap();
+        embl_features(&embl)[1].1.iter().find(|(k, _)| k == "translation").unwrap().1.clone()
+    };
+    assert_eq!(translation("chr\tx\tCDS\t1\t12\t.\t+\t0\tID=c;transl_table=4\n", 11), "\"MWA\"");
+    assert_eq!(translation("chr\tx\tCDS\t1\t12\t.\t+\t0\tID=c\n", 25), "\"MGA\"");
+    assert_eq!(translation("chr\tx\tCDS\t1\t12\t.\t+\t0\tID=c\n", 11), "\"M*A\"");
+
+    // The ORF track reads through TGA under table 4.
+    let fa = format!(">seq1\nATG{}TGA{}TAA\n", "GCC".repeat(4), "GCC".repeat(5));
+    let gff = b"##gff-version 3\nseq1\t.\tgene\t1\t8\t.\t+\t.\tID=g\n";
+    let options = |genetic_code| IndexGenOptions { orf_track: TrackFormat::Bed, orf_min_length: 30, genetic_code, ..Default::default() };
+    assert_eq!(IndexGen::from_bytes(fa.as_bytes(), gff, &options(11)).unwrap().track("orfs").unwrap().stats()["count"], 0);
+    let out = IndexGen::from_bytes(fa.as_bytes(), gff, &options(4)).unwrap();
+    let stats = out.track("orfs").unwrap().stats();
+    assert_eq!((stats["count"].as_u64(), stats["max_length"].as_u64(), stats["genetic_code"].as_u32()), (Some(1), Some(36), Some(4)));
+    assert_eq!(IndexGen::from_bytes(fa.as_bytes(), gff, &options(7)).err().map(|e| error_code(&e)), Some(ErrorCode::InvalidOption));
+
+    let gff = b"##gff-version 3\nseq1\t.\tCDS\t1\t36\t.\t+\t0\tID=c;transl_table=7\nseq1\t.\tCDS\t1\t36\t.\t+\t0\tID=d;transl_table=25\n";
+    let report = IndexGen::dry_run_bytes(fa.as_bytes(), gff, &IndexGenOptions::default());
+    assert_eq!(report["validation"]["counts"]["unknown_transl_table"], 1);
+}
+
+#[test]
+fn cds_qc_reports_stops_starts_and_lengths() {
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let fa = b">chr\nATGGCCTAAATGTAAGCCTAAGCCGCCTAAATGGCCGCCATGGCCGCCTA\n";
+    let gff = "##gff-version 3\n\
+               chr\t.\tCDS\t1\t9\t.\t+\t0\tID=ok\n\
+               chr\t.\tCDS\t10\t21\t.\t+\t0\tID=stop\n\
+               chr\t.\tCDS\t22\t30\t.\t+\t0\tID=start\n\
+               chr\t.\tCDS\t31\t39\t.\t+\t0\tID=end\n\
+               chr\t.\tCDS\t40\t50\t.\t+\t0\tID=length\n\
+               chr\t.\tCDS\t40\t50\t.\t+\t0\tID=partial;partial=true\n\
+               chr\t.\tCDS\t10\t21\t.\t+\t0\tID=pseudo;pseudo=true\n";
+    let report = IndexGen::dry_run_bytes(fa, gff.as_bytes(), &IndexGenOptions::default());
+    let validation = &report["validation"];
+    assert_eq!(validation["valid"], true);
+    assert_eq!(validation["cds_checked"], 6);
+    for code in ["internal_stop", "missing_start", "missing_stop", "cds_length"] {
+        assert_eq!(validation["counts"][code], 1, "{}", code);
+    }
+    let stop = validation["issues"].members().find(|i| i["code"] == "internal_stop").unwrap();
+    assert_eq!((stop["line"].as_usize(), stop["severity"].as_str()), (Some(3), Some("warning")));
+    assert!(stop["message"].as_str().unwrap().contains("\"stop\""));
+
+    // TGA is tryptophan under table 4.
+    let fa = b">chr\nATGTGAGCCTAA\n";
+    let gff = b"chr\t.\tCDS\t1\t12\t.\t+\t0\tID=c\n";
+    let count = |genetic_code| IndexGen::dry_run_bytes(fa, gff, &IndexGenOptions { genetic_code, ..Default::default() })["validation"]["counts"]["internal_stop"].as_usize();
+    assert_eq!((count(11), count(4)), (Some(1), None));
+}
+
+#[test]
+fn conflict_report_finds_overlapping_cds_and_duplicate_genes() {
+    use mgnify_wasm::gff::conflict_report;
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let gff = "##gff-version 3\n\
+               chr\tprokka\tgene\t1\t300\t.\t+\t.\tID=g1\n\
+               chr\tbakta\tgene\t1\t300\t.\t+\t.\tID=g2\n\
+               chr\tbakta\tgene\t1\t300\t.\t-\t.\tID=g3\n\
+               chr\tprokka\tCDS\t1\t300\t.\t+\t0\tID=c1\n\
+               chr\tbakta\tCDS\t91\t300\t.\t+\t0\tID=c2\n\
+               chr\tbakta\tCDS\t200\t500\t.\t+\t0\tID=c3\n\
+               chr\tbakta\tCDS\t1\t300\t.\t-\t0\tID=c4\n\
+               chr\tx\tCDS\t600\t700\t.\t+\t0\tID=s\n\
+               chr\tx\tCDS\t650\t800\t.\t+\t0\tID=s\n";
+    let report = conflict_report(gff);
+    // c1/c2 share all 210 bases of c2; c2/c3 (101 of 210) and c1/c3 (101
+    // of 300) are under half, c4 is on the other strand and the parts of s
+    // are one CDS.
+    let cds = &report["cds_overlaps"];
+    assert_eq!(cds["count"], 1);
+    let pair = &cds["examples"][0];
+    assert_eq!((pair["a"]["id"].as_str(), pair["b"]["id"].as_str(), pair["fraction"].as_f64()), (Some("c1"), Some("c2"), Some(1.0)));
+    let genes = &report["duplicate_genes"];
+    assert_eq!(genes["count"], 1);
+    let sources: Vec<&str> = genes["examples"][0]["features"].members().filter_map(|f| f["source"].as_str()).collect();
+    assert_eq!(sources, ["prokka", "bakta"]);
+
+    let fa = format!(">chr\n{}\n", "A".repeat(1000));
+    let dry = IndexGen::dry_run_bytes(fa.as_bytes(), gff.as_bytes(), &IndexGenOptions::default());
+    assert_eq!(dry["conflicts"], report);
+}
+
+#[test]
+fn locus_tags_are_checked_and_renumbered() {
+    use mgnify_wasm::error::{error_code, ErrorCode};
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let fa = format!(">chr\n{}\n", "A".repeat(1000));
+    let gff = "##gff-version 3\n\
+               chr\t.\tgene\t1\t90\t.\t+\t.\tID=ABC_0010;locus_tag=ABC_0010\n\
+               chr\t.\tCDS\t1\t90\t.\t+\t0\tID=cds-ABC_0010;Parent=ABC_0010;locus_tag=ABC_0010\n\
+               chr\t.\tgene\t100\t190\t.\t+\t.\tID=ABC_0005;locus_tag=ABC_0005\n\
+               chr\t.\tgene\t200\t290\t.\t+\t.\tID=gene-XYZ_0020;locus_tag=XYZ_0020\n\
+               chr\t.\tmRNA\t200\t290\t.\t+\t.\tID=XYZ_0020.t1,XYZ_00200;Parent=gene-XYZ_0020\n\
+               chr\t.\tgene\t300\t390\t.\t+\t.\tID=g5;locus_tag=ab_1\n";
+    let validation = &IndexGen::dry_run_bytes(fa.as_bytes(), gff.as_bytes(), &IndexGenOptions::default())["validation"];
+    for (code, line) in [("locus_tag_order", 4), ("locus_tag_prefix", 5), ("locus_tag_format", 7)] {
+        assert_eq!(validation["counts"][code], 1, "{}", code);
+        assert_eq!(validation["issues"].members().find(|i| i["code"] == code).unwrap()["line"], line, "{}", code);
+    }
+
+    let options = IndexGenOptions { gff_transforms: vec!["renumber_locus_tags:prefix=NEWP,step=5".into()], ..Default::default() };
+    let out = IndexGen::from_bytes(fa.as_bytes(), gff.as_bytes(), &options).unwrap();
+    let mut text = String::new();
+    BgzfReader::new(out.artifacts()[3].1).read_to_string(&mut text).unwrap();
+    let attributes: Vec<&str> = text.lines().filter(|l| !l.starts_with('#')).map(|l| l.split('\t').nth(8).unwrap()).collect();
+    assert_eq!(
+        attributes,
+        [
+            "ID=NEWP_00005;locus_tag=NEWP_00005",
+            "ID=cds-NEWP_00005;Parent=NEWP_00005;locus_tag=NEWP_00005",
+            "ID=NEWP_00010;locus_tag=NEWP_00010",
+            "ID=gene-NEWP_00015;locus_tag=NEWP_00015",
+            "ID=NEWP_00015.t1,XYZ_00200;Parent=gene-NEWP_00015",
+            "ID=g5;locus_tag=NEWP_00020",
+        ]
+    );
+    assert_eq!(out.warnings().to_json()["counts"]["locus_tags_renumbered"], 1);
+    let renumbered = IndexGen::dry_run_bytes(fa.as_bytes(), text.as_bytes(), &IndexGenOptions::default());
+    assert!(renumbered["validation"]["counts"]["locus_tag_order"].is_null());
+
+    for spec in [
+        "renumber_locus_tags",
+        "renumber_locus_tags:prefix=1AB",
+        "renumber_locus_tags:prefix=AB_C",
+        "renumber_locus_tags:prefix=ABC,step=0",
+        // Past u64 at the second tag
+        "renumber_locus_tags:prefix=ABC,step=18446744073709551615",
+    ] {
+        let options = IndexGenOptions { gff_transforms: vec![spec.into()], ..Default::default() };
+        assert_eq!(IndexGen::from_bytes(fa.as_bytes(), gff.as_bytes(), &options).err().map(|e| error_code(&e)), Some(ErrorCode::InvalidOption), "{}", spec);
+    }
+}
+
+#[test]
+fn unique_ids_suffixes_duplicates_and_follows_parents() {
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let fa = format!(">chr1\n{0}\n>chr2\n{0}\n", "A".repeat(500));
+    let gff = "##gff-version 3\n\
+               chr1\t.\tgene\t1\t300\t.\t+\t.\tID=X\n\
+               chr1\t.\tmRNA\t1\t300\t.\t+\t.\tID=X;Parent=X\n\
+               chr1\t.\texon\t1\t100\t.\t+\t.\tParent=X\n\
+               chr1\t.\tCDS\t1\t100\t.\t+\t0\tID=cds1;Parent=X\n\
+               chr1\t.\tCDS\t201\t300\t.\t+\t0\tID=cds1;Parent=X\n\
+               chr1\t.\tgene\t400\t450\t.\t+\t.\tID=Y\n\
+               chr1\t.\tgene\t460\t480\t.\t+\t.\tID=Y_2\n\
+               chr2\t.\tgene\t1\t300\t.\t-\t.\tID=X\n\
+               chr2\t.\tmRNA\t1\t300\t.\t-\t.\tID=m;Parent=X,Y\n\
+               chr2\t.\tgene\t400\t450\t.\t+\t.\tID=Y\n";
+    let options = IndexGenOptions { gff_transforms: vec!["unique_ids".into()], ..Default::default() };
+    let out = IndexGen::from_bytes(fa.as_bytes(), gff.as_bytes(), &options).unwrap();
+    let mut text = String::new();
+    BgzfReader::new(out.artifacts()[3].1).read_to_string(&mut text).unwrap();
+    // Sorting reorders the records; match them by seqid, type and start.
+    let mut attributes: Vec<(&str, &str, &str, &str)> =
+        text.lines().filter(|l| !l.starts_with('#')).map(|l| l.split('\t').collect::<Vec<_>>()).map(|r| (r[0], r[2], r[3], r[8])).collect();
+    attributes.sort();
+    assert_eq!(
+        attributes,
+        [
+            ("chr1", "CDS", "1", "ID=cds1;Parent=X_2"),
+            ("chr1", "CDS", "201", "ID=cds1;Parent=X_2"),
+            ("chr1", "exon", "1", "Parent=X_2"),
+            ("chr1", "gene", "1", "ID=X"),
+            ("chr1", "gene", "400", "ID=Y"),
+            ("chr1", "gene", "460", "ID=Y_2"),
+            ("chr1", "mRNA", "1", "ID=X_2;Parent=X"),
+            // Y on chr2 comes later; the first one on the sequence is meant.
+            ("chr2", "gene", "1", "ID=X_3"),
+            ("chr2", "gene", "400", "ID=Y_3"),
+            ("chr2", "mRNA", "1", "ID=m;Parent=X_3,Y_3"),
+        ]
+    );
+    let warnings = out.warnings().to_json();
+    assert_eq!(warnings["counts"]["id_renamed"], 3);
+    let lines: Vec<Option<usize>> = warnings["warnings"].members().filter(|w| w["code"] == "id_renamed").map(|w| w["line"].as_usize()).collect();
+    assert_eq!(lines, [Some(3), Some(9), Some(11)]);
+    assert_eq!(warnings["counts"]["id_references_rewritten"], 1);
+    let references: Vec<(usize, &str, &str)> = out.id_renames().references.iter().map(|r| (r.line, r.id.as_str(), r.new_id.as_str())).collect();
+    assert_eq!(references, [(4, "X", "X_2"), (5, "X", "X_2"), (6, "X", "X_2"), (10, "X", "X_3"), (10, "Y", "Y_3")]);
+
+    // Repeated genes on one contig are separate features, and so are CDSs
+    // sharing an ID under different parents.
+    let gff = "##gff-version 3\n\
+               chr1\t.\tgene\t1\t100\t.\t+\t.\tID=gene1\n\
+               chr1\t.\tmRNA\t1\t100\t.\t+\t.\tID=m1;Parent=gene1\n\
+               chr1\t.\tCDS\t1\t50\t.\t+\t0\tID=cds;Parent=m1\n\
+               chr1\t.\tgene\t200\t300\t.\t+\t.\tID=gene1\n\
+               chr1\t.\tmRNA\t200\t300\t.\t+\t.\tID=m2;Parent=gene1\n\
+               chr1\t.\tCDS\t200\t250\t.\t+\t0\tID=cds;Parent=m2\n";
+    let out = IndexGen::from_bytes(fa.as_bytes(), gff.as_bytes(), &options).unwrap();
+    let renames = json::parse(&out.id_renames_json()).unwrap();
+    assert_eq!(
+        renames,
+        json::object! {
+            features: [
+                { seqid: "chr1", type: "gene", id: "gene1", new_id: "gene1_2", line: 5 },
+                { seqid: "chr1", type: "CDS", id: "cds", new_id: "cds_2", line: 7 },
+            ],
+            references: [{ line: 6, attribute: "Parent", id: "gene1", new_id: "gene1_2" }],
+        }
+    );
+}
+
+#[test]
+fn trix_index_makes_the_annotation_track_searchable() {
+    use mgnify_wasm::export::{JBrowseOptions, TrixIndex};
+    use mgnify_wasm::{IndexGen, IndexGenOptions};
+
+    let fa = format!(">chr1\n{}\n", "A".repeat(1000));
+    let gff = "##gff-version 3\n\
+               chr1\t.\tgene\t1\t300\t.\t+\t.\tID=g1;Name=dnaA\n\
+               chr1\t.\tCDS\t1\t100\t.\t+\t0\tID=c1;Parent=g1;product=Chromosomal replication initiator,DnaA\n\
+               chr1\t.\tCDS\t201\t300\t.\t+\t0\tID=c1;Parent=g1\n\
+               chr1\t.\texon\t1\t300\t.\t+\t.\tID=e1;Parent=g1\n\
+               chr1\t.\tgene\t400\t500\t.\t-\t.\tNote=unnamed\n";
+    let out = IndexGen::from_bytes(fa.as_bytes(), gff.as_bytes(), &IndexGenOptions::default()).unwrap();
+    let options = JBrowseOptions { base_url: "https://example.org/run/".into(), text_index: true, ..JBrowseOptions::new("genome".into()) };
+    let config = out.jbrowse_config_json(&options).unwrap();
+    let search = &config["tracks"][0]["textSearching"]["textSearchAdapter"];
+    assert_eq!(search["type"], "TrixTextSearchAdapter");
+    assert_eq!(search["ixFilePath"]["uri"], "https://example.org/run/genome.gff.bgz.ix");
+    assert_eq!(search["ixxFilePath"]["uri"], "https://example.org/run/genome.gff.bgz.ixx");
+
+    let mut trix = out.trix_index_files(&options).unwrap();
+    let ix = String::from_utf8(trix.ix()).unwrap();
+    let words: Vec<&str> = ix.lines().map(|l| l.split(' ').next().unwrap()).collect();
+    assert_eq!(words, ["c1", "chromosomal", "dnaa", "g1", "initiator", "replication"]);
+    let cds = r#"["chr1%3A1..300"|"genome-annotations"|"c1"|"Chromosomal%20replication%20initiator"|"DnaA"]"#;
+    let dnaa: Vec<&str> = ix.lines().find_map(|l| l.strip_prefix("dnaa ")).unwrap().split(' ').collect();
+    assert_eq!(dnaa.len(), 2);
+    assert!(dnaa.contains(&r#"["chr1%3A1..300"|"genome-annotations"|"dnaA"|"g1"],1"#));
+    assert!(dnaa.contains(&format!("{},5", cds).as_str()));
+    assert_eq!(String::from_utf8(trix.ixx()).unwrap(), "c10000000000\n");
+
+    // Large indexes get an .ixx entry per 64 KiB bin, at line starts.
+    let gff: String = (1..=3000).map(|i| format!("chr1\t.\tgene\t{0}\t{0}\t.\t+\t.\tID=gene{0:05};product=hypothetical protein {0}\n", i)).collect();
+    let mut trix = TrixIndex::build(&gff, "t");
+    let (ix, ixx) = (trix.ix(), String::from_utf8(trix.ixx()).unwrap());
+    assert!(ixx.lines().count() > 2);
+    for entry in ixx.lines() {
+        let (prefix, offset) = entry.split_at(entry.len() - 10);
+        let offset = usize::from_str_radix(offset, 16).unwrap();
+        assert!(offset == 0 || ix[offset - 1] == b'\n');
+        assert!(ix[offset..].starts_with(prefix.as_bytes()), "{}", entry);
+    }
+}
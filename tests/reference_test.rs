@@ -0,0 +1,68 @@
+//! Differential tests against htslib (`cargo test --features reference`).
+//!
+//! When `samtools` and `tabix` are on PATH, both fixture pairs are indexed
+//! by them on the fly; otherwise the committed goldens in
+//! `tests/fixtures/reference/` stand in.  The divergence report is printed
+//! (visible with `--nocapture`) and any diverged artifact fails the test.
+#![cfg(feature = "reference")]
+
+use std::fs;
+use std::io::Read;
+
+use flate2::read::MultiGzDecoder;
+use mgnify_wasm::reference::{bgzf_pair, htslib_artifacts, our_artifacts, tools_available, Artifacts, Report};
+
+/// (name, fasta, gff3, golden prefix, has golden .tbi)
+const FIXTURES: [(&str, &str, &str, &str, bool); 2] = [
+    ("test", "tests/fixtures/test.fasta", "tests/fixtures/test.gff3", "tests/fixtures/reference/test", true),
+    (
+        "BU_ATCC8492",
+        "tests/fixtures/BU_ATCC8492VPI0062_NT5002.1.fa.gz",
+        "tests/fixtures/BU_ATCC8492_annotations.gff.gz",
+        "tests/fixtures/reference/BU_ATCC8492",
+        false,
+    ),
+];
+
+fn read_maybe_gz(path: &str) -> Vec<u8> {
+    let raw = fs::read(path).unwrap_or_else(|e| panic!("cannot read {}: {}", path, e));
+    if !raw.starts_with(&[0x1F, 0x8B]) {
+        return raw;
+    }
+    let mut out = Vec::new();
+    MultiGzDecoder::new(raw.as_slice()).read_to_end(&mut out).unwrap_or_else(|e| panic!("gzip decode failed for {}: {}", path, e));
+    out
+}
+
+fn goldens(prefix: &str, has_tbi: bool) -> Artifacts {
+    let read = |ext: &str| fs::read(format!("{}{}", prefix, ext)).unwrap_or_else(|e| panic!("cannot read {}{}: {}", prefix, ext, e));
+    Artifacts {
+        fai: read(".fasta.bgz.fai"),
+        gzi: read(".fasta.bgz.gzi"),
+        csi: read(".gff3.bgz.csi"),
+        tbi: has_tbi.then(|| read(".gff3.bgz.tbi")),
+    }
+}
+
+#[test]
+fn indexes_match_htslib() {
+    let live = tools_available();
+    let mut report = Report::default();
+    for (name, fasta, gff, prefix, has_tbi) in FIXTURES {
+        let gff = String::from_utf8(read_maybe_gz(gff)).expect("non-UTF8 GFF fixture");
+        let (fasta_bgz, gff_bgz) = bgzf_pair(&read_maybe_gz(fasta), &gff).unwrap();
+        let ours = our_artifacts(&fasta_bgz, &gff_bgz).unwrap();
+        let theirs = if live {
+            let workdir = std::env::temp_dir().join(format!("mgnify-reference-{}-{}", std::process::id(), name));
+            fs::create_dir_all(&workdir).unwrap();
+            let theirs = htslib_artifacts(&fasta_bgz, &gff_bgz, &workdir);
+            let _ = fs::remove_dir_all(&workdir);
+            theirs.unwrap()
+        } else {
+            goldens(prefix, has_tbi)
+        };
+        report.compare(name, &ours, &theirs);
+    }
+    eprintln!("against {}:\n{}", if live { "samtools/tabix" } else { "committed goldens" }, report);
+    assert!(report.is_compatible(), "indexes diverged from htslib:\n{}", report);
+}
@@ -0,0 +1,173 @@
+//! Browser tests of the JS boundary: `IndexGen` end to end on small bundled
+//! fixtures, built from real `web_sys::File`s and read back through its
+//! Blob accessors, checked against the native pipeline on the same bytes.
+//!
+//! They run in a dedicated worker of a headless browser, since reading a
+//! `File` needs `FileReaderSync`:
+//!
+//!   wasm-pack test --headless --firefox -- --test wasm
+//!
+//! (or `--chrome`).  On other targets this file compiles to nothing, so
+//! `cargo test` skips it.
+#![cfg(target_arch = "wasm32")]
+
+use js_sys::{Array, Reflect, Uint8Array};
+use mgnify_wasm::htslib::{compress_bgzf, compress_bgzf_chunks, compress_gzip};
+use mgnify_wasm::{IndexGen, IndexGenBuilder, IndexGenOptions};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use wasm_bindgen_test::*;
+
+wasm_bindgen_test_configure!(run_in_dedicated_worker);
+
+const FASTA: &[u8] = include_bytes!("fixtures/small.fa");
+const GFF: &[u8] = include_bytes!("fixtures/small.gff3");
+
+/// `bytes` as a browser `File` named `name`, in parts of `part` bytes.
+fn file(bytes: &[u8], name: &str, part: usize) -> web_sys::File {
+    let parts: Array = bytes.chunks(part).map(Uint8Array::from).collect();
+    web_sys::File::new_with_u8_array_sequence(&parts, name).expect("File constructor")
+}
+
+async fn blob_bytes(blob: web_sys::Blob) -> Vec<u8> {
+    let buffer = JsFuture::from(blob.array_buffer()).await.expect("Blob.arrayBuffer()");
+    Uint8Array::new(&buffer).to_vec()
+}
+
+/// The outputs of the native pipeline on the fixtures, in artifact order.
+fn native_outputs(options: &IndexGenOptions) -> Vec<Vec<u8>> {
+    let native = IndexGen::from_bytes(FASTA, GFF, options).expect("native pipeline");
+    native.artifacts().iter().map(|(_, bytes)| bytes.to_vec()).collect()
+}
+
+/// Drain every output of `out` through its Blob accessor.
+async fn blob_outputs(out: &mut IndexGen) -> Vec<Vec<u8>> {
+    vec![
+        blob_bytes(out.fasta_bgz_blob().unwrap()).await,
+        blob_bytes(out.fasta_fai_blob().unwrap()).await,
+        blob_bytes(out.fasta_gzi_blob().unwrap()).await,
+        blob_bytes(out.gff_bgz_blob().unwrap()).await,
+        blob_bytes(out.gff_csi_blob().unwrap()).await,
+        blob_bytes(out.gff_tbi_blob().unwrap()).await,
+        blob_bytes(out.gff_search_blob().unwrap()).await,
+    ]
+}
+
+fn error_code(e: &JsValue) -> String {
+    Reflect::get(e, &JsValue::from_str("code")).ok().and_then(|c| c.as_string()).unwrap_or_default()
+}
+
+#[wasm_bindgen_test]
+async fn files_through_blobs_match_the_native_pipeline() {
+    let mut out = IndexGenBuilder::new().run(file(FASTA, "small.fa", 1000), file(GFF, "small.gff3", 1000)).unwrap();
+    let outputs = blob_outputs(&mut out).await;
+    assert_eq!(outputs, native_outputs(&IndexGenOptions::default()));
+    assert!(!outputs[0].is_empty() && !outputs[4].is_empty());
+
+    // The accessors drain: a second call gives an empty Blob, while the
+    // warnings and manifest stay readable.
+    assert_eq!(out.gff_bgz_blob().unwrap().size(), 0.0);
+    assert!(json::parse(&out.warnings_json()).is_ok());
+    assert!(json::parse(&out.manifest_json()).unwrap().is_object());
+}
+
+#[wasm_bindgen_test]
+async fn gzip_files_and_tbi_output() {
+    let options = IndexGenBuilder::new().index_format(mgnify_wasm::IndexFormat::Both);
+    let fa = compress_gzip(FASTA, None).unwrap();
+    let gff = compress_gzip(GFF, Some(9)).unwrap();
+    let mut out = options.run(file(&fa, "small.fa.gz", 64), file(&gff, "small.gff3.gz", 64)).unwrap();
+    let outputs = blob_outputs(&mut out).await;
+    assert_eq!(outputs, native_outputs(&options.options()));
+    assert!(!outputs[5].is_empty(), "no .tbi");
+
+    assert_eq!(error_code(&compress_gzip(GFF, Some(10)).err().unwrap()), "E_INVALID_OPTION");
+    let chunks: JsValue = Array::of1(&Uint8Array::from(GFF)).into();
+    assert_eq!(error_code(&compress_bgzf_chunks(chunks, Some(10)).await.err().unwrap()), "E_INVALID_OPTION");
+}
+
+#[wasm_bindgen_test]
+async fn buffers_and_streams_match_files() {
+    let mut out = IndexGen::from_buffers(FASTA.to_vec(), GFF.to_vec(), None).unwrap();
+    let from_buffers = blob_outputs(&mut out).await;
+
+    let chunks = |bytes: &[u8]| -> JsValue { bytes.chunks(333).map(Uint8Array::from).collect::<Array>().into() };
+    let mut out = IndexGen::from_streams(chunks(FASTA), chunks(GFF), None).await.unwrap();
+    assert_eq!(blob_outputs(&mut out).await, from_buffers);
+
+    for name in ["fasta.bgz", "gff.csi"] {
+        let mut out = IndexGen::from_buffers(FASTA.to_vec(), GFF.to_vec(), None).unwrap();
+        assert!(!out.take_artifact(name).unwrap().is_empty(), "{}", name);
+        assert_eq!(out.take_artifact(name).unwrap(), Vec::<u8>::new());
+    }
+}
+
+#[wasm_bindgen_test]
+async fn shared_inputs_match_buffers() {
+    let mut out = IndexGen::from_buffers(FASTA.to_vec(), GFF.to_vec(), None).unwrap();
+    let from_buffers = blob_outputs(&mut out).await;
+
+    let mut out = IndexGen::from_shared(Uint8Array::from(FASTA).into(), Uint8Array::from(compress_gzip(GFF, None).unwrap().as_slice()).buffer().into(), None).unwrap();
+    assert_eq!(blob_outputs(&mut out).await, from_buffers);
+
+    if mgnify_wasm::input::shared_memory_available() {
+        let shared = |bytes: &[u8]| -> JsValue {
+            let buffer = js_sys::SharedArrayBuffer::new(bytes.len() as u32);
+            Uint8Array::new(&buffer).copy_from(bytes);
+            buffer.into()
+        };
+        let mut out = IndexGen::from_shared(shared(FASTA), shared(&compress_gzip(GFF, None).unwrap()), None).unwrap();
+        assert_eq!(blob_outputs(&mut out).await, from_buffers);
+    }
+
+    let e = IndexGen::from_shared(JsValue::from_str(">contig\nACGT\n"), Uint8Array::from(GFF).into(), None).err().unwrap();
+    assert_eq!(error_code(&e), "E_INPUT_TYPE");
+}
+
+#[wasm_bindgen_test]
+async fn chunked_compression_matches_whole_input() {
+    let chunks: Array = GFF.chunks(100).map(Uint8Array::from).collect();
+    assert_eq!(compress_bgzf_chunks(chunks.into(), None).await.unwrap(), compress_bgzf(GFF).unwrap());
+}
+
+#[wasm_bindgen_test]
+async fn errors_reach_js_with_their_codes() {
+    let e = IndexGen::from_streams(JsValue::from_str(">contig\nACGT\n"), Uint8Array::from(GFF).into(), None).await.err().unwrap();
+    assert_eq!(error_code(&e), "E_INPUT_TYPE");
+
+    let not_chunks: Array = [JsValue::from(1)].into_iter().collect();
+    let e = compress_bgzf_chunks(not_chunks.into(), None).await.err().unwrap();
+    assert_eq!(error_code(&e), "E_INPUT_TYPE");
+    assert!(e.is_instance_of::<js_sys::Error>());
+}
+
+#[wasm_bindgen_test]
+async fn encrypted_cache_reads_back_transparently() {
+    use mgnify_wasm::cache::{cache_clear, cache_list, cache_set_key};
+    let scope: web_sys::WorkerGlobalScope = js_sys::global().unchecked_into();
+    let subtle = scope.crypto().unwrap().subtle();
+    let algorithm = js_sys::Object::new();
+    Reflect::set(&algorithm, &"name".into(), &"AES-GCM".into()).unwrap();
+    Reflect::set(&algorithm, &"length".into(), &256.into()).unwrap();
+    let usages: Array = ["encrypt", "decrypt"].iter().map(|u| JsValue::from_str(u)).collect();
+    let key = JsFuture::from(subtle.generate_key_with_object(&algorithm, false, &usages).unwrap()).await.unwrap();
+
+    cache_clear().await.unwrap();
+    cache_set_key(Some(key.unchecked_into())).unwrap();
+    let mut stored = IndexGen::cached(file(FASTA, "small.fa", 1000), file(GFF, "small.gff3", 1000), None).await.unwrap();
+    let mut hit = IndexGen::cached(file(FASTA, "small.fa", 1000), file(GFF, "small.gff3", 1000), None).await.unwrap();
+    assert_eq!(hit.cache_key(), stored.cache_key());
+    assert_eq!(blob_outputs(&mut hit).await, blob_outputs(&mut stored).await);
+    let entries = json::parse(&cache_list().await.unwrap()).unwrap();
+    assert_eq!(entries[0]["encrypted"], true);
+    assert!(entries[0]["fasta_sha256"].is_string());
+
+    // Without the key the entry stays listed but cannot be read.
+    cache_set_key(None).unwrap();
+    let entries = json::parse(&cache_list().await.unwrap()).unwrap();
+    assert_eq!(entries[0]["encrypted"], true);
+    assert!(entries[0]["fasta_sha256"].is_null());
+    let e = cache_set_key(Some(web_sys::CryptoKey::from(JsValue::from(js_sys::Object::new())))).err().unwrap();
+    assert_eq!(error_code(&e), "E_INVALID_OPTION");
+    cache_clear().await.unwrap();
+}